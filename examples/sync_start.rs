@@ -0,0 +1,51 @@
+//! A benchmarked program taking part in the `sync-start` protocol: only the part between its
+//! two signals is counted, not starting it or reading its input.
+//!
+//! `sync_start [LOG]` appends what it does to `LOG`, to check the order of the signals.
+
+use std::fs::OpenOptions;
+use std::io::{Read, Write};
+use std::os::fd::FromRawFd;
+use std::os::unix::net::UnixStream;
+
+/// Send `signal` and wait until the benchmarker is done with it. Without a benchmarker on the
+/// other end, the program carries on unsynchronized.
+fn signal(sync: &mut Option<UnixStream>, signal: u8) {
+    if let Some(socket) = sync {
+        if socket.write_all(&[signal]).is_err() || socket.read_exact(&mut [0]).is_err() {
+            *sync = None;
+        }
+    }
+}
+
+fn main() {
+    let mut sync = std::env::var("BENCHMARKER_SYNC_FD")
+        .ok()
+        .and_then(|fd| fd.parse().ok())
+        // SAFETY: the benchmarker passes the socket on in this descriptor, nothing else uses it.
+        .map(|fd| unsafe { UnixStream::from_raw_fd(fd) });
+    let mut log = std::env::args().nth(1).map(|log| {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(log)
+            .unwrap()
+    });
+    let mut log = |line: &str| {
+        if let Some(log) = &mut log {
+            writeln!(log, "{line}").unwrap();
+        }
+    };
+
+    // The setup, which isn't counted.
+    let input = (0..100_000u64).collect::<Vec<_>>();
+    log("ready");
+    signal(&mut sync, b'r');
+
+    let sum = input.iter().fold(0u64, |sum, n| sum.wrapping_add(n * n));
+    std::hint::black_box(sum);
+
+    log("done");
+    signal(&mut sync, b'd');
+    log("exit");
+}
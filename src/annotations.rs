@@ -0,0 +1,511 @@
+//! Pointing GitHub at the config lines of failing comparisons, with `::error` workflow
+//! commands. The config is deserialized from a `Value` without positions, so the lines of the
+//! keys come from a separate scan of the config files.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::compare::Comparisons;
+
+/// Where a comparison row is defined. Without a line, the whole file is meant.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ConfigSpan {
+    pub file: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+}
+
+/// The line of every key in a JSON document, by the path of keys leading to it. Array
+/// elements are keyed by their index, and lines start at 1.
+pub fn key_lines(json: &str) -> Result<HashMap<Vec<String>, usize>, String> {
+    let mut scanner = Scanner {
+        bytes: json.as_bytes(),
+        pos: 0,
+        line: 1,
+        lines: HashMap::new(),
+    };
+    scanner.value(&mut vec![])?;
+    scanner.skip_whitespace();
+    if scanner.pos < scanner.bytes.len() {
+        return Err(scanner.unexpected());
+    }
+    Ok(scanner.lines)
+}
+
+struct Scanner<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    line: usize,
+    lines: HashMap<Vec<String>, usize>,
+}
+
+impl Scanner<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(byte @ (b' ' | b'\t' | b'\r' | b'\n')) = self.peek() {
+            if byte == b'\n' {
+                self.line += 1;
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn unexpected(&self) -> String {
+        match self.bytes.get(self.pos..) {
+            Some([]) | None => "unexpected end of the JSON".to_owned(),
+            Some(rest) => format!(
+                "unexpected `{}` on line {}",
+                String::from_utf8_lossy(&rest[..rest.len().min(1)]),
+                self.line
+            ),
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), String> {
+        self.skip_whitespace();
+        if self.peek() != Some(byte) {
+            return Err(self.unexpected());
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn value(&mut self, path: &mut Vec<String>) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'{') => self.object(path),
+            Some(b'[') => self.array(path),
+            Some(b'"') => self.string().map(drop),
+            Some(_) => {
+                let start = self.pos;
+                while !matches!(
+                    self.peek(),
+                    None | Some(b',' | b'}' | b']' | b' ' | b'\t' | b'\r' | b'\n')
+                ) {
+                    self.pos += 1;
+                }
+                if self.pos == start {
+                    return Err(self.unexpected());
+                }
+                Ok(())
+            }
+            None => Err(self.unexpected()),
+        }
+    }
+
+    fn object(&mut self, path: &mut Vec<String>) -> Result<(), String> {
+        self.pos += 1;
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(());
+        }
+
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some(b'"') {
+                return Err(self.unexpected());
+            }
+            let line = self.line;
+            let key = self.string()?;
+            self.expect(b':')?;
+
+            path.push(key);
+            self.lines.entry(path.clone()).or_insert(line);
+            self.value(path)?;
+            path.pop();
+
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(());
+                }
+                _ => return Err(self.unexpected()),
+            }
+        }
+    }
+
+    fn array(&mut self, path: &mut Vec<String>) -> Result<(), String> {
+        self.pos += 1;
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(());
+        }
+
+        for index in 0.. {
+            self.skip_whitespace();
+            path.push(index.to_string());
+            self.lines.entry(path.clone()).or_insert(self.line);
+            self.value(path)?;
+            path.pop();
+
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    break;
+                }
+                _ => return Err(self.unexpected()),
+            }
+        }
+        Ok(())
+    }
+
+    /// The string at the cursor, with its escapes decoded so keys can be looked up by name.
+    fn string(&mut self) -> Result<String, String> {
+        self.pos += 1;
+        let mut string = vec![];
+        loop {
+            let Some(byte) = self.peek() else {
+                return Err(self.unexpected());
+            };
+            self.pos += 1;
+            match byte {
+                b'"' => break,
+                b'\\' => {
+                    let Some(escaped) = self.peek() else {
+                        return Err(self.unexpected());
+                    };
+                    self.pos += 1;
+                    let decoded = match escaped {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.unicode_escape()?,
+                        _ => {
+                            self.pos -= 1;
+                            return Err(self.unexpected());
+                        }
+                    };
+                    string.extend_from_slice(decoded.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                b'\n' => {
+                    self.line += 1;
+                    string.push(byte);
+                }
+                _ => string.push(byte),
+            }
+        }
+        String::from_utf8(string).map_err(|e| format!("invalid UTF-8 on line {}: {e}", self.line))
+    }
+
+    /// The character of a `\u` escape, which may be a surrogate pair of two escapes.
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let mut units = vec![self.hex4()?];
+        if (0xd800..0xdc00).contains(&units[0]) && self.bytes[self.pos..].starts_with(b"\\u") {
+            self.pos += 2;
+            units.push(self.hex4()?);
+        }
+        char::decode_utf16(units)
+            .next()
+            .and_then(Result::ok)
+            .ok_or_else(|| format!("invalid unicode escape on line {}", self.line))
+    }
+
+    fn hex4(&mut self) -> Result<u16, String> {
+        let hex = self
+            .bytes
+            .get(self.pos..self.pos + 4)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u16::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| format!("invalid unicode escape on line {}", self.line))?;
+        self.pos += 4;
+        Ok(hex)
+    }
+}
+
+/// The key lines of every config file.
+#[derive(Debug, Default)]
+pub struct ConfigSpans {
+    files: Vec<(PathBuf, HashMap<Vec<String>, usize>)>,
+}
+
+impl ConfigSpans {
+    pub fn read(files: &[PathBuf]) -> Result<Self, String> {
+        let files = files
+            .iter()
+            .map(|file| {
+                let json = fs::read_to_string(file)
+                    .map_err(|e| format!("failed to read {}: {e}", file.display()))?;
+                let lines = key_lines(&json)
+                    .map_err(|e| format!("failed to scan {}: {e}", file.display()))?;
+                Ok((file.clone(), lines))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(ConfigSpans { files })
+    }
+
+    /// The row of a table in `section`, e.g. `render-versus-other`. The rows of a
+    /// `render-versus-self` table may be the table itself or its `rows`. When only the table is
+    /// found, the span is its whole file.
+    pub fn row(&self, section: &str, table: &str, row: &str) -> Option<ConfigSpan> {
+        let key = |keys: &[&str]| keys.iter().map(|&key| key.to_owned()).collect::<Vec<_>>();
+
+        let (file, lines) = self
+            .files
+            .iter()
+            .find(|(_, lines)| lines.contains_key(&key(&[section, table])))
+            .or(match &self.files[..] {
+                [single] => Some(single),
+                _ => None,
+            })?;
+        let line = lines
+            .get(&key(&[section, table, "rows", row]))
+            .or_else(|| lines.get(&key(&[section, table, row])))
+            .copied();
+
+        Some(ConfigSpan {
+            file: file.clone(),
+            line,
+        })
+    }
+
+    /// Set the span of every row of the `render-versus-other` and `render-versus-self` tables.
+    pub fn resolve(&self, comparisons: &mut Comparisons) {
+        for (section, tables) in [
+            ("render-versus-other", &mut comparisons.versus_other),
+            ("render-versus-self", &mut comparisons.versus_self),
+        ] {
+            for table in tables {
+                for row in &mut table.rows {
+                    row.config_span = self.row(section, &table.name, &row.name);
+                }
+            }
+        }
+    }
+}
+
+/// Escape the message of a workflow command, so a newline doesn't end the command.
+pub fn escape_data(data: &str) -> String {
+    data.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escape a property of a workflow command, which additionally can't contain the `:` of the
+/// `::` that ends the properties or the `,` between them.
+pub fn escape_property(property: &str) -> String {
+    escape_data(property)
+        .replace(':', "%3A")
+        .replace(',', "%2C")
+}
+
+/// An `::error` workflow command, which GitHub shows as an annotation on the line of the span,
+/// the whole file without a line, or just in the log without a span.
+pub fn error_command(span: Option<&ConfigSpan>, message: &str) -> String {
+    let mut properties = vec![];
+    if let Some(span) = span {
+        properties.push(format!(
+            "file={}",
+            escape_property(&path_string(&span.file))
+        ));
+        if let Some(line) = span.line {
+            properties.push(format!("line={line}"));
+        }
+    }
+
+    if properties.is_empty() {
+        format!("::error::{}", escape_data(message))
+    } else {
+        format!("::error {}::{}", properties.join(","), escape_data(message))
+    }
+}
+
+/// GitHub resolves the files of annotations relative to the workspace, with `/` separators.
+fn path_string(path: &Path) -> String {
+    let path = path.strip_prefix("./").unwrap_or(path);
+    path.to_string_lossy().replace('\\', "/")
+}
+
+#[cfg(test)]
+fn keys(keys: &[&str]) -> Vec<String> {
+    keys.iter().map(|&key| key.to_owned()).collect()
+}
+
+#[test]
+fn scan_key_lines() {
+    let json = r#"{
+  "commands": {
+    "compress": [
+      { "cmd": ["./c", "6"] },
+      {
+        "cmd": ["./c", "9"]
+      }
+    ]
+  },
+  "render-versus-other": {
+    "compress": {
+      "measure": "cycles",
+      "command": "compress",
+      "rows": { "level 6": 0,
+        "level 9": 1 }
+    }
+  }
+}"#;
+    let lines = key_lines(json).unwrap();
+    assert_eq!(lines[&keys(&["commands"])], 2);
+    assert_eq!(lines[&keys(&["commands", "compress", "0"])], 4);
+    assert_eq!(lines[&keys(&["commands", "compress", "0", "cmd"])], 4);
+    assert_eq!(lines[&keys(&["commands", "compress", "0", "cmd", "1"])], 4);
+    assert_eq!(lines[&keys(&["commands", "compress", "1"])], 5);
+    assert_eq!(lines[&keys(&["commands", "compress", "1", "cmd"])], 6);
+    // The same name in another section.
+    assert_eq!(lines[&keys(&["render-versus-other", "compress"])], 11);
+    let rows = ["render-versus-other", "compress", "rows"];
+    assert_eq!(lines[&keys(&[&rows[..], &["level 6"]].concat())], 14);
+    assert_eq!(lines[&keys(&[&rows[..], &["level 9"]].concat())], 15);
+    assert_eq!(lines.len(), 17);
+
+    // Everything on one line, and Windows line endings.
+    let lines = key_lines(r#"{"a":{"b":[1,{"c":null}]},"d":-1.5e3}"#).unwrap();
+    assert_eq!(lines[&keys(&["a", "b", "1", "c"])], 1);
+    assert_eq!(lines[&keys(&["d"])], 1);
+    let lines = key_lines("{\r\n\"a\": true,\r\n\"b\": []\r\n}").unwrap();
+    assert_eq!(lines[&keys(&["a"])], 2);
+    assert_eq!(lines[&keys(&["b"])], 3);
+
+    assert_eq!(key_lines("[]").unwrap().len(), 0);
+    assert_eq!(key_lines(" {} ").unwrap().len(), 0);
+}
+
+#[test]
+fn scan_escaped_keys() {
+    let json = r#"{
+  "quote \" and \\ backslash": 1,
+  "café 🚀": {
+    "colons :: and, commas": "value with \"}\" in it",
+    "/": "\n"
+  }
+}"#;
+    let lines = key_lines(json).unwrap();
+    assert_eq!(lines[&keys(&["quote \" and \\ backslash"])], 2);
+    assert_eq!(lines[&keys(&["café 🚀"])], 3);
+    assert_eq!(lines[&keys(&["café 🚀", "colons :: and, commas"])], 4);
+    assert_eq!(lines[&keys(&["café 🚀", "/"])], 5);
+
+    // A duplicate key keeps its first line, like the first definition serde reports on.
+    let lines = key_lines("{\n\"a\": 1,\n\"a\": 2\n}").unwrap();
+    assert_eq!(lines[&keys(&["a"])], 2);
+}
+
+#[test]
+fn scan_invalid_json() {
+    assert_eq!(
+        key_lines("{\n\"a\": 1\n\"b\": 2}").unwrap_err(),
+        "unexpected `\"` on line 3"
+    );
+    assert_eq!(
+        key_lines("{\"a\": [1, 2}").unwrap_err(),
+        "unexpected `}` on line 1"
+    );
+    assert_eq!(
+        key_lines("{\"a\": ").unwrap_err(),
+        "unexpected end of the JSON"
+    );
+    assert_eq!(key_lines("{\"a").unwrap_err(), "unexpected end of the JSON");
+    assert_eq!(key_lines("{} {}").unwrap_err(), "unexpected `{` on line 1");
+    assert_eq!(
+        key_lines(r#"{"\x": 1}"#).unwrap_err(),
+        "unexpected `x` on line 1"
+    );
+    assert_eq!(
+        key_lines(r#"{"\u12": 1}"#).unwrap_err(),
+        "invalid unicode escape on line 1"
+    );
+    assert_eq!(
+        key_lines(r#"{"\udc00": 1}"#).unwrap_err(),
+        "invalid unicode escape on line 1"
+    );
+}
+
+#[test]
+fn row_spans() {
+    let fixture = |name: &str| {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("testdata/configs")
+            .join(name)
+    };
+    let compress = fixture("compress.json");
+    let decompress = fixture("decompress.json");
+    let spans = ConfigSpans::read(&[compress.clone(), decompress.clone()]).unwrap();
+
+    assert_eq!(
+        spans.row("render-versus-other", "decompress", "level 1"),
+        Some(ConfigSpan {
+            file: decompress.clone(),
+            line: Some(10),
+        })
+    );
+    // The rows of a `render-versus-self` table without display options are the table.
+    assert_eq!(
+        spans.row("render-versus-self", "ng vs rs", "level 1"),
+        Some(ConfigSpan {
+            file: compress.clone(),
+            line: Some(12),
+        })
+    );
+
+    // An unknown row falls back to the file of the table, an unknown table to nothing.
+    assert_eq!(
+        spans.row("render-versus-other", "decompress", "no such row"),
+        Some(ConfigSpan {
+            file: decompress,
+            line: None,
+        })
+    );
+    assert_eq!(
+        spans.row("render-versus-other", "no such table", "level 1"),
+        None
+    );
+
+    // With a single file, that file is meant either way.
+    let spans = ConfigSpans::read(std::slice::from_ref(&compress)).unwrap();
+    assert_eq!(
+        spans.row("render-versus-other", "no such table", "level 1"),
+        Some(ConfigSpan {
+            file: compress,
+            line: None,
+        })
+    );
+}
+
+#[test]
+fn workflow_command_escaping() {
+    assert_eq!(escape_data("100% of\r\nit"), "100%25 of%0D%0Ait");
+    assert_eq!(escape_data("a::b, c"), "a::b, c");
+    assert_eq!(escape_property("a::b, c%\n"), "a%3A%3Ab%2C c%25%0A");
+    // Escaping `%` first keeps the escapes from being escaped again.
+    assert_eq!(escape_data("%0A"), "%250A");
+
+    let span = ConfigSpan {
+        file: PathBuf::from("./benches/bench,config::x.json"),
+        line: Some(12),
+    };
+    assert_eq!(
+        error_command(Some(&span), "compress level 9 regressed +6.30% cycles"),
+        "::error file=benches/bench%2Cconfig%3A%3Ax.json,line=12::compress level 9 regressed +6.30%25 cycles"
+    );
+    assert_eq!(
+        error_command(Some(&ConfigSpan { line: None, ..span }), "two\nlines"),
+        "::error file=benches/bench%2Cconfig%3A%3Ax.json::two%0Alines"
+    );
+    assert_eq!(
+        error_command(None, "::error::nested"),
+        "::error::::error::nested"
+    );
+}
@@ -0,0 +1,562 @@
+//! `--backfill-baseline-counters`: measure the counters the baseline lacks, like those of a perf
+//! event added since, at the commit of the baseline. Otherwise the comparisons show n.a. for
+//! them until the main branch has new results.
+//!
+//! ```json
+//! "backfill": { "build": "cargo build --release" }
+//! ```
+//!
+//! The commit of the baseline is checked out in a worktree in the scratch directory, and built
+//! with the `build` command, if any. Only the commands of the baseline that lack counters this
+//! run measured are measured again, in the worktree and only with the missing perf events. The
+//! counters are merged into the baseline of this run and listed in the `backfilled` of their
+//! benchmark. With `--persist-backfill`, they are also added to the stored baseline in the
+//! previous results.
+//!
+//! Counters of another kind of machine don't compare, so the baseline must come from the same
+//! kind of machine as the run. Composites and the commands with `sync-start` or `measure-child`
+//! are left out, they aren't measured like the others.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde::Deserialize;
+
+use crate::bench::{bench_single_cmd, Backend, BenchCounter, CommandSpec, Perf};
+use crate::compare::find_prev_bench;
+use crate::sanitize::Sanitizer;
+use crate::{baseline, sections, worktree, BenchData, Config};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct BackfillConfig {
+    /// The command that builds the commit of the baseline, run in its worktree with `sh -c`.
+    #[serde(default)]
+    pub build: Option<String>,
+}
+
+/// A command of the baseline, with perf counting only the events it lacks.
+pub struct Need {
+    pub group: String,
+    /// The index of the command in the group of the baseline.
+    pub index: usize,
+    pub cmd: Vec<String>,
+    pub expected_exit_codes: Vec<i32>,
+    pub perf: Perf,
+}
+
+/// The commands of `baseline` that lack counters of perf events that `current` has.
+pub fn needs(
+    config: &Config,
+    current: &BenchData,
+    baseline: &BenchData,
+    scratch: &Path,
+) -> Vec<Need> {
+    let mut needs = vec![];
+    for (group_name, benches) in &current.bench_groups {
+        let (Some(prev_benches), Some(commands)) = (
+            baseline.bench_groups.get(group_name),
+            config.commands.get(group_name),
+        ) else {
+            continue;
+        };
+        let perf = config.perf(group_name, scratch);
+        for (bench, command) in benches.iter().zip(commands) {
+            if command.is_composite() || command.sync_start || command.measure_child.is_some() {
+                continue;
+            }
+            let Some(index) = find_prev_bench(prev_benches, bench).and_then(|prev| {
+                prev_benches
+                    .iter()
+                    .position(|other| std::ptr::eq(other, prev))
+            }) else {
+                continue;
+            };
+            let missing = perf
+                .events()
+                .into_iter()
+                .filter(|event| {
+                    let counter = config.counter_renames.canonical(&event.name());
+                    bench.counters.contains_key(&counter)
+                        && !prev_benches[index].counters.contains_key(&counter)
+                })
+                .collect::<Vec<_>>();
+            if missing.is_empty() {
+                continue;
+            }
+            needs.push(Need {
+                group: group_name.clone(),
+                index,
+                cmd: prev_benches[index].cmd.clone(),
+                expected_exit_codes: command.expected_exit_codes.clone(),
+                perf: Perf {
+                    events: missing,
+                    instruction_mix: false,
+                    required_counters: None,
+                    ..perf.clone()
+                },
+            });
+        }
+    }
+    needs
+}
+
+/// Counters measured for a command of the baseline.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Backfilled {
+    pub group: String,
+    pub index: usize,
+    pub counters: BTreeMap<String, BenchCounter>,
+}
+
+/// Measure what the baseline lacks in the worktree of its commit at `dir`. A command that
+/// fails is left out with a warning.
+fn measure(config: &Config, needs: Vec<Need>, dir: &Path) -> Vec<Backfilled> {
+    let mut backfilled = vec![];
+    for need in needs {
+        let wanted = need
+            .perf
+            .events
+            .iter()
+            .map(|event| config.counter_renames.canonical(&event.name()))
+            .collect::<Vec<_>>();
+        let cmd = CommandSpec {
+            argv: need.cmd,
+            expected_exit_codes: need.expected_exit_codes,
+            wrapper: vec![],
+            current_dir: Some(dir.to_owned()),
+            sync_start: None,
+            measure_child: None,
+            verify_output: None,
+            watchdog: None,
+            preemption: None,
+        };
+        let command_line = cmd.argv.join(" ");
+        let backends: [Box<dyn Backend>; 1] = [Box::new(need.perf)];
+        let mut bench =
+            match bench_single_cmd(cmd, config.repetitions(&need.group), &backends, None) {
+                Ok(bench) => bench,
+                Err(err) => {
+                    eprintln!(
+                        "warning: failed to backfill the counters of `{command_line}`: {err}"
+                    );
+                    continue;
+                }
+            };
+        if let Some(err) = &bench.error {
+            eprintln!("warning: failed to backfill the counters of `{command_line}`: {err}");
+            continue;
+        }
+        for warning in config
+            .counter_renames
+            .canonicalize_bench(&need.group, &mut bench)
+        {
+            eprintln!("warning: {warning}");
+        }
+        bench.counters.retain(|counter, _| wanted.contains(counter));
+        backfilled.push(Backfilled {
+            group: need.group,
+            index: need.index,
+            counters: bench.counters,
+        });
+    }
+    backfilled
+}
+
+/// Add the `backfilled` counters to `baseline`, with the counters derived from them, and list
+/// them in the `backfilled` of their benchmarks. Counters the baseline has are kept.
+pub fn merge(config: &Config, baseline: &mut BenchData, backfilled: Vec<Backfilled>) {
+    let cpu_frequency = baseline.cpu_frequency.clone();
+    for Backfilled {
+        group,
+        index,
+        counters,
+    } in backfilled
+    {
+        let Some(bench) = baseline
+            .bench_groups
+            .get_mut(&group)
+            .and_then(|benches| benches.get_mut(index))
+        else {
+            continue;
+        };
+        let before = bench.counters.keys().cloned().collect::<Vec<_>>();
+        for (counter, value) in counters {
+            bench.counters.entry(counter).or_insert(value);
+        }
+        config.derive_counters(&group, cpu_frequency.as_ref(), &mut bench.counters);
+        for counter in bench.counters.keys() {
+            if !before.contains(counter) && !bench.backfilled.contains(counter) {
+                bench.backfilled.push(counter.clone());
+            }
+        }
+    }
+}
+
+/// A worktree of the repository in the current directory, removed on drop.
+pub struct Worktree {
+    pub dir: PathBuf,
+}
+
+impl Worktree {
+    pub fn add(dir: PathBuf, commit: &str) -> Result<Self, String> {
+        let path = dir.to_string_lossy();
+        worktree::git(
+            Path::new("."),
+            &["worktree", "add", "--detach", "--quiet", &path, commit],
+        )?;
+        Ok(Worktree { dir })
+    }
+}
+
+impl Drop for Worktree {
+    fn drop(&mut self) {
+        let path = self.dir.to_string_lossy();
+        if let Err(err) = worktree::git(Path::new("."), &["worktree", "remove", "--force", &path]) {
+            eprintln!("warning: {err}");
+        }
+    }
+}
+
+/// Measure the counters that `baseline` lacks at its commit and merge them into it, returning
+/// how many were added.
+pub fn run(
+    config: &Config,
+    current: &BenchData,
+    baseline: &mut BenchData,
+    scratch: &Path,
+) -> Result<usize, String> {
+    if !baseline::same_machine(baseline, current) {
+        let machine = |data: &BenchData| {
+            data.machine_class
+                .clone()
+                .unwrap_or_else(|| data.cpu_model.clone())
+        };
+        return Err(format!(
+            "the baseline was measured on another kind of machine, `{}` rather than `{}`",
+            machine(baseline),
+            machine(current)
+        ));
+    }
+
+    let scratch = std::path::absolute(scratch)
+        .map_err(|e| format!("failed to resolve {}: {e}", scratch.display()))?;
+    let needs = needs(config, current, baseline, &scratch);
+    if needs.is_empty() {
+        return Ok(0);
+    }
+    eprintln!(
+        "Backfilling the counters of {} commands of the baseline {}",
+        needs.len(),
+        baseline.commit_hash
+    );
+    let worktree = Worktree::add(scratch.join("backfill"), &baseline.commit_hash)?;
+    if let Some(command) = &config.backfill.build {
+        build(command, &worktree.dir)?;
+    }
+
+    let backfilled = measure(config, needs, &worktree.dir);
+    let before = count(baseline);
+    merge(config, baseline, backfilled);
+    Ok(count(baseline) - before)
+}
+
+/// Run the `build` command in the worktree at `dir`.
+pub fn build(command: &str, dir: &Path) -> Result<(), String> {
+    // The output of the build goes to stderr, stdout is for the results.
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .current_dir(dir)
+        .stdout(std::io::stderr())
+        .status()
+        .map_err(|e| format!("failed to run `{command}`: {e}"))?;
+    if !status.success() {
+        return Err(format!("`{command}` failed with {status}"));
+    }
+    Ok(())
+}
+
+fn count(data: &BenchData) -> usize {
+    data.bench_groups
+        .values()
+        .flatten()
+        .map(|bench| bench.backfilled.len())
+        .sum()
+}
+
+/// Add the backfilled counters of `baseline` to its entry in the results at `path`, which is
+/// otherwise kept as it was stored.
+pub fn persist(path: &Path, baseline: &BenchData, sanitizer: &Sanitizer) -> Result<(), String> {
+    let results = std::fs::read_to_string(path)
+        .map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+    let is_baseline = |line: &str| {
+        serde_json::from_str::<BenchData>(line).is_ok_and(|data| {
+            data.commit_hash == baseline.commit_hash && data.timestamp == baseline.timestamp
+        })
+    };
+    let Some(line) = results.lines().find(|line| is_baseline(line)) else {
+        return Err(format!(
+            "the baseline {} is not in {}",
+            baseline.commit_hash,
+            path.display()
+        ));
+    };
+
+    let mut entry = serde_json::from_str::<serde_json::Value>(line).unwrap();
+    for (group_name, benches) in &baseline.bench_groups {
+        for (index, bench) in benches.iter().enumerate() {
+            if bench.backfilled.is_empty() {
+                continue;
+            }
+            let stored = &mut entry["bench_groups"][&*sanitizer.sanitize(group_name)][index];
+            if !stored.is_object() {
+                continue;
+            }
+            let mut backfilled = sanitizer.to_value(&bench.backfilled);
+            for counter in &bench.backfilled {
+                stored["counters"][&*sanitizer.sanitize(counter)] =
+                    sanitizer.to_value(&bench.counters[counter]);
+            }
+            if let Some(previous) = stored["backfilled"].as_array() {
+                let serde_json::Value::Array(added) = &mut backfilled else {
+                    unreachable!()
+                };
+                added.retain(|counter| !previous.contains(counter));
+                added.splice(0..0, previous.iter().cloned());
+            }
+            stored["backfilled"] = backfilled;
+        }
+    }
+    sections::write_line(path, &entry.to_string(), |existing| {
+        existing == line.as_bytes()
+    })
+}
+
+/// Say which counters of the baseline were backfilled.
+pub fn render_markdown_note(md: &mut String, baseline: Option<&BenchData>) {
+    let Some(baseline) = baseline else {
+        return;
+    };
+    let lines = baseline
+        .bench_groups
+        .iter()
+        .flat_map(|(group_name, benches)| {
+            benches
+                .iter()
+                .filter(|bench| !bench.backfilled.is_empty())
+                .map(move |bench| {
+                    format!(
+                        "> - {group_name} / `{}`: {}\n",
+                        bench.cmd.join(" "),
+                        bench
+                            .backfilled
+                            .iter()
+                            .map(|counter| format!("`{counter}`"))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )
+                })
+        })
+        .collect::<Vec<_>>();
+    if lines.is_empty() {
+        return;
+    }
+
+    writeln!(
+        md,
+        "> [!NOTE]\n> Counters the baseline lacked were measured later, at its commit, with `--backfill-baseline-counters`:\n{}",
+        lines.concat()
+    )
+    .unwrap();
+}
+
+#[cfg(test)]
+fn config_for_test() -> Config {
+    serde_json::from_str(
+        r#"{
+            "commands": {
+                "compress": ["./c 1", "./c 2", { "command": "./c 3", "sync-start": true }],
+                "decompress": ["./d"]
+            },
+            "perf-events-for-group": { "compress": ["cycles", "instructions", "branches"] },
+            "render-versus-self": {},
+            "render-versus-other": {}
+        }"#,
+    )
+    .unwrap()
+}
+
+#[test]
+fn needed_counters() {
+    use crate::testkit::BenchDataBuilder;
+
+    let config = config_for_test();
+    let current = BenchDataBuilder::new("2222222222222222222222222222222222222222")
+        .group("compress", |g| {
+            g.bench(["./c", "1"], |b| {
+                b.counter("cycles", 900.0, 100.0, 20, "")
+                    .counter("instructions", 1800.0, 100.0, 20, "")
+                    .counter("branches", 300.0, 100.0, 20, "")
+            })
+            .bench(["./c", "2"], |b| {
+                b.counter("cycles", 800.0, 100.0, 20, "").counter(
+                    "instructions",
+                    1600.0,
+                    100.0,
+                    20,
+                    "",
+                )
+            })
+            .bench(["./c", "3"], |b| {
+                b.counter("cycles", 800.0, 100.0, 20, "").counter(
+                    "instructions",
+                    1600.0,
+                    100.0,
+                    20,
+                    "",
+                )
+            })
+        })
+        .group("decompress", |g| {
+            g.bench(["./d"], |b| b.counter("instructions", 500.0, 100.0, 20, ""))
+        })
+        .build();
+    let baseline = BenchDataBuilder::new("1111111111111111111111111111111111111111")
+        .group("compress", |g| {
+            // In another order, and without branches, which isn't needed for `./c 2`.
+            g.bench(["./c", "2"], |b| {
+                b.counter("cycles", 800.0, 100.0, 20, "").counter(
+                    "instructions",
+                    1600.0,
+                    100.0,
+                    20,
+                    "",
+                )
+            })
+            .bench(["./c", "1"], |b| b.counter("cycles", 900.0, 100.0, 20, ""))
+            .bench(["./c", "3"], |b| b.counter("cycles", 800.0, 100.0, 20, ""))
+        })
+        .build();
+
+    // Only `./c 1` lacks counters: `./c 3` runs with sync-start, and the baseline has no
+    // `decompress` results to compare with.
+    let needs = needs(&config, &current, &baseline, Path::new("/scratch"));
+    assert_eq!(needs.len(), 1);
+    assert_eq!((needs[0].group.as_str(), needs[0].index), ("compress", 1));
+    assert_eq!(needs[0].cmd, ["./c", "1"]);
+    assert_eq!(
+        needs[0].perf.events,
+        crate::perf_events::PerfEvent::parse_list("instructions,branches")
+    );
+    assert_eq!(needs[0].perf.scratch, Path::new("/scratch"));
+}
+
+#[test]
+fn merge_backfilled_counters() {
+    let config = config_for_test();
+    let mut baseline =
+        crate::testkit::BenchDataBuilder::new("1111111111111111111111111111111111111111")
+            .group("compress", |g| {
+                g.bench(["./c", "1"], |b| b.counter("cycles", 900.0, 100.0, 20, ""))
+            })
+            .build();
+    let counter = |value| BenchCounter {
+        value,
+        variance: 1.0,
+        repetitions: 20,
+        unit: String::new(),
+    };
+
+    merge(
+        &config,
+        &mut baseline,
+        vec![Backfilled {
+            group: "compress".to_owned(),
+            index: 0,
+            counters: [
+                ("instructions".to_owned(), counter(1800.0)),
+                ("cycles".to_owned(), counter(1.0)),
+            ]
+            .into(),
+        }],
+    );
+    let bench = &baseline.bench_groups["compress"][0];
+    assert_eq!(bench.counters["instructions"], counter(1800.0));
+    // What the baseline measured itself is kept.
+    assert_eq!(bench.counters["cycles"].value, 900.0);
+    assert_eq!(bench.backfilled, ["instructions"]);
+
+    let mut md = String::new();
+    render_markdown_note(&mut md, Some(&baseline));
+    assert_eq!(
+        md,
+        "> [!NOTE]\n> Counters the baseline lacked were measured later, at its commit, with `--backfill-baseline-counters`:\n\
+         > - compress / `./c 1`: `instructions`\n\n"
+    );
+}
+
+#[test]
+fn persist_backfilled_counters() {
+    let dir = crate::testkit::test_dir("backfill-persist");
+    let results = dir.join("results.json");
+    let config = config_for_test();
+    let stored = crate::testkit::BenchDataBuilder::new("1111111111111111111111111111111111111111")
+        .group("compress", |g| {
+            g.bench(["./c", "1"], |b| b.counter("cycles", 900.0, 100.0, 20, ""))
+        })
+        .build();
+    let other =
+        crate::testkit::BenchDataBuilder::new("0000000000000000000000000000000000000000").build();
+    let lines = [
+        serde_json::to_string(&other).unwrap(),
+        serde_json::to_string(&stored)
+            .unwrap()
+            .replacen('{', "{\"unknown\":1,", 1),
+    ];
+    std::fs::write(&results, format!("{}\n{}\n", lines[0], lines[1])).unwrap();
+
+    let mut baseline = stored.clone();
+    merge(
+        &config,
+        &mut baseline,
+        vec![Backfilled {
+            group: "compress".to_owned(),
+            index: 0,
+            counters: [(
+                "instructions".to_owned(),
+                BenchCounter {
+                    value: 1800.0,
+                    variance: 1.0,
+                    repetitions: 20,
+                    unit: String::new(),
+                },
+            )]
+            .into(),
+        }],
+    );
+    persist(&results, &baseline, &Sanitizer::default()).unwrap();
+
+    // Only the entry of the baseline changes, and keeps what it had.
+    let written = std::fs::read_to_string(&results).unwrap();
+    let written = written.lines().collect::<Vec<_>>();
+    assert_eq!(written.len(), 2);
+    assert_eq!(written[0], lines[0]);
+    let entry = serde_json::from_str::<serde_json::Value>(written[1]).unwrap();
+    assert_eq!(entry["unknown"], 1);
+    let bench = &entry["bench_groups"]["compress"][0];
+    assert_eq!(bench["counters"]["instructions"]["value"], 1800.0);
+    assert_eq!(bench["counters"]["cycles"]["value"], 900.0);
+    assert_eq!(bench["backfilled"], serde_json::json!(["instructions"]));
+
+    // The results of another commit are not the baseline.
+    let other_baseline = BenchData {
+        commit_hash: "2222222222222222222222222222222222222222".to_owned(),
+        ..baseline
+    };
+    assert!(persist(&results, &other_baseline, &Sanitizer::default())
+        .unwrap_err()
+        .starts_with("the baseline 2222222222222222222222222222222222222222 is not in "));
+}
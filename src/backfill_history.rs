@@ -0,0 +1,763 @@
+//! `benchmarker backfill <config> <results> (--last <n> | <commit>...) [--max-duration <duration>] [-- <args>...]`:
+//! measure past commits one after the other, to populate the results of a new kind of runner
+//! machine, which otherwise has no baseline to compare with until the main branch moves on.
+//!
+//! The commits are measured from the oldest to the newest: the last `n` commits of the first
+//! parent history of `HEAD`, or the given ones in the order of the history. Every commit
+//! is checked out in a worktree of its own, in a scratch directory under `scratch-root` like
+//! that of a run, built with the `build` command of `backfill` in the config, like for
+//! `--backfill-baseline-counters`, and measured by running the benchmarker in the worktree with
+//! `<args>`, which adds the results to `<results>` like `--results-file` does. The runs don't
+//! write a step summary, outputs or comments, and don't notify. A commit that already has
+//! results from this kind of machine is skipped, one that fails to build or to run is left out.
+//!
+//! After every commit the progress is written to `<results>.backfill`, so a backfill that was
+//! interrupted, or stopped by `--max-duration`, continues where it left off when run again with
+//! the same commits, without trying again the commits that failed. The checkpoint is removed
+//! once every commit is done. `--max-duration` is checked between commits: a commit that
+//! started is measured to the end, and the first one is measured whatever the budget.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+use crate::backfill::{self, Worktree};
+use crate::scratch::RunScratch;
+use crate::{history, machine, units, worktree, Config};
+
+/// The environment variables the runs of the commits don't get: the backfill is not about the
+/// event that triggered it, and the results of every commit are stored.
+const REMOVED_ENV: &[&str] = &[
+    "GITHUB_REF",
+    "GITHUB_REF_NAME",
+    "GITHUB_HEAD_REF",
+    "GITHUB_EVENT_PATH",
+    "GITHUB_STEP_SUMMARY",
+    "GITHUB_OUTPUT",
+    "GITHUB_TOKEN",
+    "BENCH_GITHUB_TOKEN",
+    "BENCH_NOTIFY_WEBHOOK_URL",
+];
+
+/// The kind of machine the results are measured on, see [`crate::baseline::same_machine`].
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Machine {
+    pub arch: String,
+    pub os: String,
+    pub cpu_model: String,
+    #[serde(default)]
+    pub machine_class: Option<String>,
+    #[serde(default)]
+    pub machine_id: Option<String>,
+}
+
+impl Machine {
+    /// This machine, as the run of a commit with the `aliases` records it.
+    fn current(aliases: &IndexMap<String, String>) -> Self {
+        let cpu_model = crate::get_cpu_model();
+        let arch = std::env::var("RUNNER_ARCH").unwrap_or_default();
+        Machine {
+            os: std::env::var("RUNNER_OS").unwrap_or_default(),
+            machine_class: machine::detect(&cpu_model),
+            machine_id: Some(machine::detect_id(aliases, &arch, &cpu_model)),
+            arch,
+            cpu_model,
+        }
+    }
+
+    fn key(&self) -> machine::MachineKey<'_> {
+        machine::MachineKey {
+            arch: &self.arch,
+            os: &self.os,
+            cpu_model: &self.cpu_model,
+            machine_class: self.machine_class.as_deref(),
+            machine_id: self.machine_id.as_deref(),
+        }
+    }
+
+    fn is_same(&self, other: &Machine) -> bool {
+        machine::same_machine(self.key(), other.key())
+    }
+}
+
+/// Just enough of an entry of the results to tell whether a commit has been measured.
+#[derive(Deserialize)]
+struct Entry {
+    commit_hash: String,
+    #[serde(default)]
+    dirty: bool,
+    #[serde(flatten)]
+    machine: Machine,
+}
+
+/// Whether the results at `path` have an entry of `commit` from the kind of `machine`.
+/// Results of a dirty working tree don't count.
+pub fn has_results(path: &Path, commit: &str, machine: &Machine) -> Result<bool, String> {
+    if !path.exists() {
+        return Ok(false);
+    }
+    let mut found = false;
+    history::for_each_line(path, |_, line| {
+        found = serde_json::from_slice::<Entry>(line).is_ok_and(|entry| {
+            entry.commit_hash == commit && !entry.dirty && entry.machine.is_same(machine)
+        });
+        if found {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    })?;
+    Ok(found)
+}
+
+/// Which commits to measure.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Commits {
+    /// The last commits of the first parent history of `HEAD`.
+    Last(usize),
+    Given(Vec<String>),
+}
+
+impl Commits {
+    /// The full hashes of the commits of the repository at `dir`, the oldest first.
+    fn resolve(&self, dir: &Path) -> Result<Vec<String>, String> {
+        let text = |output: Vec<u8>| String::from_utf8_lossy(&output).into_owned();
+        match self {
+            Commits::Last(count) => {
+                let output = worktree::git(
+                    dir,
+                    &[
+                        "rev-list",
+                        "--first-parent",
+                        &format!("--max-count={count}"),
+                        "--reverse",
+                        "HEAD",
+                    ],
+                )?;
+                Ok(text(output).lines().map(str::to_owned).collect())
+            }
+            Commits::Given(commits) => {
+                let mut hashes = vec![];
+                for commit in commits {
+                    let output = worktree::git(
+                        dir,
+                        &["rev-parse", "--verify", &format!("{commit}^{{commit}}")],
+                    )
+                    .map_err(|err| format!("unknown commit {commit}: {err}"))?;
+                    hashes.push(text(output).trim().to_owned());
+                }
+                // Commit times can be equal or out of order, the history isn't.
+                let mut args = vec!["rev-list", "--topo-order", "--reverse"];
+                args.extend(hashes.iter().map(String::as_str));
+                let output = text(worktree::git(dir, &args)?);
+                Ok(output
+                    .lines()
+                    .filter(|hash| hashes.iter().any(|given| given == hash))
+                    .map(str::to_owned)
+                    .collect())
+            }
+        }
+    }
+}
+
+/// What became of a commit.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Outcome {
+    Measured,
+    /// The results already had an entry of the commit from this kind of machine.
+    Skipped,
+    BuildFailed(String),
+    /// The commit was built, but the run stored no results.
+    Failed(String),
+}
+
+/// The progress of a backfill, in `<results>.backfill`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// The commits of the backfill, the oldest first.
+    pub commits: Vec<String>,
+    /// The commits done so far, in the order they were done.
+    pub done: IndexMap<String, Outcome>,
+}
+
+impl Checkpoint {
+    pub fn path(results: &Path) -> PathBuf {
+        let mut path = results.as_os_str().to_owned();
+        path.push(".backfill");
+        PathBuf::from(path)
+    }
+
+    /// The checkpoint of a backfill of `commits` into `results`, if there is one.
+    fn read(results: &Path, commits: &[String]) -> Result<Option<Self>, String> {
+        let path = Self::path(results);
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("failed to read {}: {e}", path.display())),
+        };
+        let checkpoint = serde_json::from_str::<Checkpoint>(&text)
+            .map_err(|e| format!("failed to parse {}: {e}", path.display()))?;
+        if checkpoint.commits != commits {
+            eprintln!(
+                "warning: {} is of a backfill of other commits, starting over",
+                path.display()
+            );
+            return Ok(None);
+        }
+        Ok(Some(checkpoint))
+    }
+
+    /// Replace the checkpoint at once, so an interruption leaves either the old or the new one.
+    fn write(&self, results: &Path) -> Result<(), String> {
+        let path = Self::path(results);
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        let temp = PathBuf::from(temp);
+        fs::write(&temp, serde_json::to_string(self).unwrap())
+            .map_err(|e| format!("failed to write {}: {e}", temp.display()))?;
+        fs::rename(&temp, &path).map_err(|e| format!("failed to write {}: {e}", path.display()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    pub config: PathBuf,
+    pub results: PathBuf,
+    pub commits: Commits,
+    pub max_duration: Option<Duration>,
+    /// The arguments of the run of every commit.
+    pub args: Vec<String>,
+}
+
+/// How a backfill went.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary {
+    pub done: IndexMap<String, Outcome>,
+    /// The commits `--max-duration` left for the next backfill.
+    pub remaining: Vec<String>,
+}
+
+/// Backfill the commits in the repository at `dir`, measuring each with `measure`, as long as
+/// `elapsed` is within `--max-duration`.
+pub fn backfill(
+    dir: &Path,
+    options: &Options,
+    machine: &Machine,
+    mut elapsed: impl FnMut() -> Duration,
+    mut measure: impl FnMut(&str) -> Outcome,
+) -> Result<Summary, String> {
+    let commits = options.commits.resolve(dir)?;
+    let mut checkpoint = match Checkpoint::read(&options.results, &commits)? {
+        Some(checkpoint) => {
+            eprintln!(
+                "resuming the backfill after {} of {} commits",
+                checkpoint.done.len(),
+                commits.len()
+            );
+            checkpoint
+        }
+        None => Checkpoint {
+            commits: commits.clone(),
+            done: IndexMap::new(),
+        },
+    };
+
+    let mut remaining = vec![];
+    // Every backfill measures a commit at least, so backfilling again makes progress.
+    let mut started = false;
+    for commit in &commits {
+        if checkpoint.done.contains_key(commit) {
+            continue;
+        }
+        if !remaining.is_empty() {
+            remaining.push(commit.clone());
+            continue;
+        }
+        let outcome = if has_results(&options.results, commit, machine)? {
+            Outcome::Skipped
+        } else if started && options.max_duration.is_some_and(|max| elapsed() >= max) {
+            remaining.push(commit.clone());
+            continue;
+        } else {
+            eprintln!("backfilling {commit}");
+            started = true;
+            measure(commit)
+        };
+        checkpoint.done.insert(commit.clone(), outcome);
+        checkpoint.write(&options.results)?;
+    }
+
+    if remaining.is_empty() {
+        let path = Checkpoint::path(&options.results);
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(format!("failed to remove {}: {e}", path.display()));
+            }
+            _ => {}
+        }
+    }
+    Ok(Summary {
+        done: checkpoint.done,
+        remaining,
+    })
+}
+
+/// Build and measure `commit` in a worktree of the repository in the current directory, in
+/// `scratch`, by running the benchmarker at `exe` in it.
+fn measure(
+    exe: &Path,
+    options: &Options,
+    build: Option<&str>,
+    machine: &Machine,
+    commit: &str,
+    scratch: &Path,
+) -> Outcome {
+    let worktree = match Worktree::add(scratch.join(commit), commit) {
+        Ok(worktree) => worktree,
+        Err(err) => return Outcome::Failed(err),
+    };
+    if let Some(command) = build {
+        if let Err(err) = backfill::build(command, &worktree.dir) {
+            return Outcome::BuildFailed(err);
+        }
+    }
+
+    // The results are only stored, and the output of the run is for the log.
+    let mut command = Command::new(exe);
+    command
+        .arg(commit)
+        .arg(&options.config)
+        .arg(&options.results)
+        .arg("--results-file")
+        .arg(&options.results)
+        .args(&options.args)
+        .current_dir(&worktree.dir)
+        .stdout(Stdio::null());
+    for name in REMOVED_ENV {
+        command.env_remove(name);
+    }
+    let status = match command.status() {
+        Ok(status) => status,
+        Err(e) => return Outcome::Failed(format!("failed to run {}: {e}", exe.display())),
+    };
+    match has_results(&options.results, commit, machine) {
+        Ok(true) => Outcome::Measured,
+        Ok(false) => Outcome::Failed(format!(
+            "the run stored no results, it exited with {status}"
+        )),
+        Err(err) => Outcome::Failed(err),
+    }
+}
+
+fn render_summary(summary: &Summary) -> String {
+    let count = |wanted: fn(&Outcome) -> bool| {
+        summary
+            .done
+            .values()
+            .filter(|outcome| wanted(outcome))
+            .count()
+    };
+    let mut out = format!(
+        "measured {} of {} commits\n",
+        count(|outcome| *outcome == Outcome::Measured),
+        summary.done.len() + summary.remaining.len()
+    );
+    writeln!(
+        out,
+        "  skipped: {}",
+        count(|outcome| *outcome == Outcome::Skipped)
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "  failed to build: {}",
+        count(|outcome| matches!(outcome, Outcome::BuildFailed(_)))
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "  failed: {}",
+        count(|outcome| matches!(outcome, Outcome::Failed(_)))
+    )
+    .unwrap();
+    if !summary.remaining.is_empty() {
+        writeln!(
+            out,
+            "  left for the next backfill: {}",
+            summary.remaining.len()
+        )
+        .unwrap();
+    }
+    for (commit, outcome) in &summary.done {
+        let outcome = match outcome {
+            Outcome::Measured => "measured".to_owned(),
+            Outcome::Skipped => "skipped, already measured on this kind of machine".to_owned(),
+            Outcome::BuildFailed(err) => format!("failed to build: {err}"),
+            Outcome::Failed(err) => format!("failed: {err}"),
+        };
+        writeln!(out, "{commit} {outcome}").unwrap();
+    }
+    for commit in &summary.remaining {
+        writeln!(out, "{commit} left for the next backfill").unwrap();
+    }
+    out
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+    const USAGE: &str = "expected the arguments backfill <config> <results> (--last <n> | <commit>...) [--max-duration <duration>] [-- <args>...]";
+
+    let mut positional = vec![];
+    let mut last = None;
+    let mut max_duration = None;
+    let mut run_args = vec![];
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--last" => {
+                let value = args.next().ok_or("expected a number after --last")?;
+                match value.parse::<usize>() {
+                    Ok(count) if count > 0 => last = Some(count),
+                    _ => {
+                        return Err(format!(
+                            "expected a positive number after --last, got {value}"
+                        ))
+                    }
+                }
+            }
+            "--max-duration" => {
+                let value = args
+                    .next()
+                    .ok_or("expected a duration after --max-duration")?;
+                max_duration = Some(units::parse_duration(&value).map_err(|err| {
+                    format!("invalid duration after --max-duration, {value}: {err}")
+                })?);
+            }
+            "--" => run_args.extend(args.by_ref()),
+            _ => positional.push(arg),
+        }
+    }
+    let (config, results, commits) = match (positional.as_slice(), last) {
+        ([config, results], Some(count)) => (config, results, Commits::Last(count)),
+        ([config, results, commits @ ..], None) if !commits.is_empty() => {
+            (config, results, Commits::Given(commits.to_vec()))
+        }
+        _ => return Err(USAGE.to_owned()),
+    };
+    // The runs are in the worktrees.
+    let absolute = |path: &str| {
+        std::path::absolute(path).map_err(|e| format!("failed to resolve {path}: {e}"))
+    };
+    Ok(Options {
+        config: absolute(config)?,
+        results: absolute(results)?,
+        commits,
+        max_duration,
+        args: run_args,
+    })
+}
+
+pub fn run(args: impl IntoIterator<Item = String>) -> Result<String, String> {
+    let options = parse_args(args)?;
+    let config = Config::load(std::slice::from_ref(&options.config))?;
+    let exe = std::env::current_exe()
+        .map_err(|e| format!("failed to find the benchmarker executable: {e}"))?;
+    let machine = Machine::current(&config.machine_aliases);
+    // Worktrees of an interrupted backfill that are gone.
+    worktree::git(Path::new("."), &["worktree", "prune"])?;
+    let scratch_root = config
+        .scratch_root
+        .clone()
+        .unwrap_or_else(std::env::temp_dir);
+    let scratch = RunScratch::create(&scratch_root, "backfill", false, false)?;
+
+    let start = Instant::now();
+    let summary = backfill(
+        Path::new("."),
+        &options,
+        &machine,
+        || start.elapsed(),
+        |commit| {
+            measure(
+                &exe,
+                &options,
+                config.backfill.build.as_deref(),
+                &machine,
+                commit,
+                scratch.path(),
+            )
+        },
+    )?;
+    Ok(render_summary(&summary))
+}
+
+/// A repository at `dir` with `count` commits a minute apart, returning their hashes.
+#[cfg(test)]
+fn repository_for_test(dir: &Path, count: usize) -> Vec<String> {
+    let git = |args: &[&str], date: u64| {
+        let output = Command::new("git")
+            .args([
+                "-c",
+                "user.name=Bench",
+                "-c",
+                "user.email=bench@example.com",
+            ])
+            .args(["-c", "commit.gpgsign=false"])
+            .args(args)
+            .env("GIT_COMMITTER_DATE", format!("{date} +0000"))
+            .current_dir(dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{output:?}");
+        String::from_utf8(output.stdout).unwrap().trim().to_owned()
+    };
+    git(&["init", "--quiet"], 0);
+    (0..count as u64)
+        .map(|i| {
+            let date = 1_700_000_000 + i * 60;
+            git(
+                &["commit", "--quiet", "--allow-empty", "-m", &format!("{i}")],
+                date,
+            );
+            git(&["rev-parse", "HEAD"], date)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+fn machine_for_test() -> Machine {
+    Machine {
+        arch: "X64".to_owned(),
+        os: "Linux".to_owned(),
+        cpu_model: "cpu".to_owned(),
+        machine_class: Some("amd-epyc-7763/4".to_owned()),
+        machine_id: None,
+    }
+}
+
+/// Add an entry of `commit` from `machine_class` to the results at `path`.
+#[cfg(test)]
+fn store_for_test(path: &Path, commit: &str, machine_class: &str) {
+    use std::io::Write;
+
+    let data = crate::testkit::BenchDataBuilder::new(commit)
+        .machine_class(machine_class)
+        .group("work", |g| {
+            g.bench(["./work"], |b| b.counter("cycles", 1e6, 1e2, 2, ""))
+        })
+        .build();
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .unwrap();
+    writeln!(file, "{}", serde_json::to_string(&data).unwrap()).unwrap();
+}
+
+#[test]
+fn backfill_commits() {
+    let dir = crate::testkit::test_dir("backfill-history-commits");
+    let commits = repository_for_test(&dir, 4);
+
+    assert_eq!(Commits::Last(2).resolve(&dir).unwrap(), commits[2..]);
+    assert_eq!(Commits::Last(10).resolve(&dir).unwrap(), commits);
+    // Oldest first whatever the order they're given in, once each, by any name.
+    let given = Commits::Given(vec![
+        commits[3][..12].to_owned(),
+        "HEAD~3".to_owned(),
+        commits[2].clone(),
+        commits[0].clone(),
+    ]);
+    assert_eq!(
+        given.resolve(&dir).unwrap(),
+        [commits[0].clone(), commits[2].clone(), commits[3].clone()]
+    );
+    let err = Commits::Given(vec!["nope".to_owned()])
+        .resolve(&dir)
+        .unwrap_err();
+    assert!(err.starts_with("unknown commit nope: "), "{err}");
+}
+
+#[test]
+fn skip_measured_commits() {
+    let dir = crate::testkit::test_dir("backfill-history-skip");
+    let commits = repository_for_test(&dir, 3);
+    let results = dir.join("results.json");
+    let machine = machine_for_test();
+    assert!(!has_results(&results, &commits[0], &machine).unwrap());
+
+    store_for_test(&results, &commits[0], "amd-epyc-7763/4");
+    // Another kind of machine doesn't count.
+    store_for_test(&results, &commits[1], "intel-xeon-platinum-8370c/4");
+    assert!(has_results(&results, &commits[0], &machine).unwrap());
+    assert!(!has_results(&results, &commits[1], &machine).unwrap());
+
+    let options = Options {
+        config: dir.join("bench.json"),
+        results: results.clone(),
+        commits: Commits::Last(3),
+        max_duration: None,
+        args: vec![],
+    };
+    let mut measured = vec![];
+    let summary = backfill(&dir, &options, &machine, Duration::default, |commit| {
+        measured.push(commit.to_owned());
+        store_for_test(&results, commit, "amd-epyc-7763/4");
+        Outcome::Measured
+    })
+    .unwrap();
+    assert_eq!(measured, commits[1..]);
+    assert_eq!(
+        summary.done,
+        IndexMap::from([
+            (commits[0].clone(), Outcome::Skipped),
+            (commits[1].clone(), Outcome::Measured),
+            (commits[2].clone(), Outcome::Measured),
+        ])
+    );
+    assert!(summary.remaining.is_empty());
+    assert!(!Checkpoint::path(&results).exists());
+}
+
+#[test]
+fn resume_backfill() {
+    let dir = crate::testkit::test_dir("backfill-history-resume");
+    let commits = repository_for_test(&dir, 4);
+    let results = dir.join("results.json");
+    let machine = machine_for_test();
+    let options = Options {
+        config: dir.join("bench.json"),
+        results: results.clone(),
+        commits: Commits::Last(4),
+        max_duration: Some(Duration::from_secs(60)),
+        args: vec![],
+    };
+
+    // Every commit takes 40 seconds, so the budget runs out after two.
+    let mut measured = vec![];
+    let elapsed = std::cell::Cell::new(Duration::ZERO);
+    let summary = backfill(
+        &dir,
+        &options,
+        &machine,
+        || elapsed.get(),
+        |commit| {
+            measured.push(commit.to_owned());
+            elapsed.set(elapsed.get() + Duration::from_secs(40));
+            if commit == commits[0] {
+                Outcome::BuildFailed("`make` failed with exit status: 2".to_owned())
+            } else {
+                store_for_test(&results, commit, "amd-epyc-7763/4");
+                Outcome::Measured
+            }
+        },
+    )
+    .unwrap();
+    assert_eq!(measured, commits[..2]);
+    assert_eq!(summary.remaining, commits[2..]);
+    let checkpoint = Checkpoint::read(&results, &commits).unwrap().unwrap();
+    assert_eq!(checkpoint.done, summary.done);
+    assert_eq!(
+        render_summary(&summary),
+        format!(
+            "measured 1 of 4 commits\n  skipped: 0\n  failed to build: 1\n  failed: 0\n  left for the next backfill: 2\n\
+             {} failed to build: `make` failed with exit status: 2\n\
+             {} measured\n\
+             {} left for the next backfill\n\
+             {} left for the next backfill\n",
+            commits[0], commits[1], commits[2], commits[3]
+        )
+    );
+
+    // Run again, it continues with the commits left, without trying the one that failed to
+    // build again.
+    let mut measured = vec![];
+    let summary = backfill(&dir, &options, &machine, Duration::default, |commit| {
+        measured.push(commit.to_owned());
+        Outcome::Failed("the run stored no results, it exited with exit status: 101".to_owned())
+    })
+    .unwrap();
+    assert_eq!(measured, commits[2..]);
+    assert_eq!(
+        summary.done.keys().collect::<Vec<_>>(),
+        commits.iter().collect::<Vec<_>>()
+    );
+    assert!(matches!(summary.done[0], Outcome::BuildFailed(_)));
+    assert!(summary.remaining.is_empty());
+    assert!(!Checkpoint::path(&results).exists());
+
+    // A checkpoint of other commits is of another backfill.
+    let other = Checkpoint {
+        commits: commits[..2].to_vec(),
+        done: IndexMap::from([(commits[0].clone(), Outcome::Measured)]),
+    };
+    other.write(&results).unwrap();
+    assert_eq!(Checkpoint::read(&results, &commits).unwrap(), None);
+    assert_eq!(
+        Checkpoint::read(&results, &commits[..2]).unwrap(),
+        Some(other)
+    );
+}
+
+#[test]
+fn backfill_args() {
+    let parse = |args: &[&str]| parse_args(args.iter().map(|arg| arg.to_string()));
+    let options = parse(&[
+        "bench.json",
+        "results.json",
+        "--last",
+        "30",
+        "--max-duration",
+        "2h",
+        "--",
+        "--only-tag",
+        "fast",
+    ])
+    .unwrap();
+    assert!(options.config.is_absolute() && options.config.ends_with("bench.json"));
+    assert!(options.results.ends_with("results.json"));
+    assert_eq!(options.commits, Commits::Last(30));
+    assert_eq!(options.max_duration, Some(Duration::from_secs(7200)));
+    assert_eq!(options.args, ["--only-tag", "fast"]);
+
+    let options = parse(&["bench.json", "results.json", "abc", "def"]).unwrap();
+    assert_eq!(
+        options.commits,
+        Commits::Given(vec!["abc".to_owned(), "def".to_owned()])
+    );
+    assert_eq!((options.max_duration, options.args.len()), (None, 0));
+
+    for args in [
+        &["bench.json", "results.json"][..],
+        &["bench.json", "results.json", "abc", "--last", "2"],
+        &["bench.json", "--last", "2"],
+    ] {
+        assert_eq!(
+            parse(args).unwrap_err(),
+            "expected the arguments backfill <config> <results> (--last <n> | <commit>...) [--max-duration <duration>] [-- <args>...]"
+        );
+    }
+    assert_eq!(
+        parse(&["bench.json", "results.json", "--last", "0"]).unwrap_err(),
+        "expected a positive number after --last, got 0"
+    );
+    let err = parse(&[
+        "bench.json",
+        "results.json",
+        "--last",
+        "2",
+        "--max-duration",
+        "2",
+    ])
+    .unwrap_err();
+    assert!(
+        err.starts_with("invalid duration after --max-duration, 2: "),
+        "{err}"
+    );
+}
@@ -0,0 +1,500 @@
+//! Checking the stored results of the merge base against those of the commits before it. A
+//! baseline measured on a machine that was still hot makes every comparison against it show
+//! phantom changes.
+
+use std::fmt::Write;
+use std::path::Path;
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bench::{BenchCounter, SingleBench};
+use crate::compare::find_prev_bench;
+use crate::{machine, BenchData};
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct BaselineSanityConfig {
+    /// How many older results of the main branch to compare the baseline against.
+    #[serde(default = "default_neighbors")]
+    pub neighbors: usize,
+    /// How many commits before the baseline to look for them.
+    #[serde(default = "default_search_commits")]
+    pub search_commits: usize,
+    /// The largest deviation of the baseline from the median of the older results, in
+    /// percent, that is still fine.
+    #[serde(default = "default_tolerance_percent")]
+    pub tolerance_percent: f64,
+    #[serde(default)]
+    pub action: AnomalyAction,
+}
+
+fn default_neighbors() -> usize {
+    3
+}
+
+fn default_search_commits() -> usize {
+    20
+}
+
+fn default_tolerance_percent() -> f64 {
+    5.0
+}
+
+/// What to do with a baseline that deviates too much.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AnomalyAction {
+    /// Compare against it anyway, but say so above every table.
+    #[default]
+    Annotate,
+    /// Compare against the median of the baseline and the older results instead.
+    Substitute,
+}
+
+/// A baseline that deviates from the older results, recorded in the run report.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BaselineAnomaly {
+    pub commit: String,
+    /// The older results it was checked against, nearest first.
+    pub neighbors: Vec<String>,
+    pub deviations: Vec<Deviation>,
+    pub action: AnomalyAction,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Deviation {
+    pub group: String,
+    pub command: String,
+    pub counter: String,
+    pub baseline: f64,
+    pub median: f64,
+    pub deviation_percent: f64,
+}
+
+/// The first-parent ancestors of `commit` in the repository at `dir`, nearest first and
+/// without `commit` itself. Following only the first parent stays on the main branch.
+pub fn ancestors(dir: &Path, commit: &str, count: usize) -> Result<Vec<String>, String> {
+    let output = Command::new("git")
+        .args(["rev-list", "--first-parent"])
+        .arg(format!("--max-count={}", count + 1))
+        .arg(commit)
+        .current_dir(dir)
+        .output()
+        .map_err(|e| format!("failed to run git rev-list: {e}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "git rev-list {commit} failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .skip(1)
+        .map(|line| line.trim().to_owned())
+        .collect())
+}
+
+/// Whether two results were measured on the same kind of machine, see
+/// [`machine::same_machine`].
+pub fn same_machine(a: &BenchData, b: &BenchData) -> bool {
+    machine::same_machine(a.machine_key(), b.machine_key())
+}
+
+/// The results of up to `count` of the `ancestors` from the same machine as the baseline,
+/// nearest first.
+pub fn neighbors<'a>(
+    baseline: &BenchData,
+    ancestors: &[String],
+    history: &'a [BenchData],
+    count: usize,
+) -> Vec<&'a BenchData> {
+    ancestors
+        .iter()
+        .filter_map(|commit| {
+            history
+                .iter()
+                .find(|entry| &entry.commit_id() == commit && same_machine(baseline, entry))
+        })
+        .take(count)
+        .collect()
+}
+
+pub fn median(mut values: Vec<f64>) -> f64 {
+    values.sort_by(f64::total_cmp);
+    let middle = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[middle - 1] + values[middle]) / 2.0
+    } else {
+        values[middle]
+    }
+}
+
+/// The same counter of the same benchmark in the `neighbors` that have it.
+fn neighbor_counters<'a>(
+    neighbors: &[&'a BenchData],
+    group_name: &str,
+    bench: &SingleBench,
+    counter: &str,
+) -> Vec<&'a BenchCounter> {
+    neighbors
+        .iter()
+        .filter_map(|neighbor| {
+            let group = neighbor.bench_groups.get(group_name)?;
+            find_prev_bench(group, bench)?.counters.get(counter)
+        })
+        .collect()
+}
+
+impl BaselineSanityConfig {
+    /// Compare the `stable_counters` of the baseline against their median in the
+    /// `neighbors`. Only the `control_groups` are compared when there are any, as nothing
+    /// should change in them. `None` when the baseline is fine.
+    pub fn check(
+        &self,
+        stable_counters: &[String],
+        control_groups: &[String],
+        baseline: &BenchData,
+        neighbors: &[&BenchData],
+    ) -> Option<BaselineAnomaly> {
+        let mut deviations = vec![];
+        for (group_name, benches) in &baseline.bench_groups {
+            if !control_groups.is_empty() && !control_groups.contains(group_name) {
+                continue;
+            }
+            for bench in benches {
+                for (counter, value) in &bench.counters {
+                    if !machine::is_machine_stable(stable_counters, counter) {
+                        continue;
+                    }
+                    let values = neighbor_counters(neighbors, group_name, bench, counter)
+                        .into_iter()
+                        .map(|counter| counter.value)
+                        .collect::<Vec<_>>();
+                    if values.is_empty() {
+                        continue;
+                    }
+
+                    let median = median(values);
+                    let deviation_percent = (value.value - median) / median * 100.0;
+                    if deviation_percent.abs() > self.tolerance_percent {
+                        deviations.push(Deviation {
+                            group: group_name.clone(),
+                            command: bench.cmd.join(" "),
+                            counter: counter.clone(),
+                            baseline: value.value,
+                            median,
+                            deviation_percent,
+                        });
+                    }
+                }
+            }
+        }
+
+        if deviations.is_empty() {
+            return None;
+        }
+        Some(BaselineAnomaly {
+            commit: baseline.commit_hash.clone(),
+            neighbors: neighbors
+                .iter()
+                .map(|neighbor| neighbor.commit_hash.clone())
+                .collect(),
+            deviations,
+            action: self.action,
+        })
+    }
+}
+
+/// The baseline with every counter replaced by the median of it and the same counter in the
+/// `neighbors`. The variance is the mean of theirs, so the comparisons don't get more
+/// confident than any single run was.
+pub fn composite(baseline: &BenchData, neighbors: &[&BenchData]) -> BenchData {
+    let mut composite = baseline.clone();
+    for (group_name, benches) in &mut composite.bench_groups {
+        for bench in benches {
+            let pooled = bench
+                .counters
+                .keys()
+                .map(|counter| {
+                    let mut counters = neighbor_counters(neighbors, group_name, bench, counter);
+                    counters.push(&bench.counters[counter]);
+                    let values = counters.iter().map(|counter| counter.value).collect();
+                    let variance = counters.iter().map(|counter| counter.variance).sum::<f64>()
+                        / counters.len() as f64;
+                    (counter.clone(), median(values), variance)
+                })
+                .collect::<Vec<_>>();
+            for (counter, value, variance) in pooled {
+                let counter = bench.counters.get_mut(&counter).unwrap();
+                counter.value = value;
+                counter.variance = variance;
+            }
+        }
+    }
+    composite
+}
+
+/// Explain above the comparisons that the baseline deviates from the results before it.
+pub fn render_markdown_warning(md: &mut String, anomaly: Option<&BaselineAnomaly>) {
+    let Some(anomaly) = anomaly else {
+        return;
+    };
+
+    let neighbors = anomaly
+        .neighbors
+        .iter()
+        .map(|commit| format!("`{}`", &commit[..commit.len().min(7)]))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let outcome = match anomaly.action {
+        AnomalyAction::Annotate => "The comparisons below may show changes that aren't there.",
+        AnomalyAction::Substitute => {
+            "The comparisons below are against the median of it and those results instead."
+        }
+    };
+    writeln!(
+        md,
+        "> [!WARNING]\n> The baseline `{}` looks anomalous compared to the results of {neighbors}. \
+         {outcome}\n>",
+        &anomaly.commit[..anomaly.commit.len().min(7)],
+    )
+    .unwrap();
+    for deviation in &anomaly.deviations {
+        writeln!(
+            md,
+            "> - {} / `{}` {}: {:+.1}% from the median",
+            deviation.group, deviation.command, deviation.counter, deviation.deviation_percent
+        )
+        .unwrap();
+    }
+    writeln!(md).unwrap();
+}
+
+/// The line below the heading of every table compared against an anomalous baseline.
+pub fn render_markdown_table_note(md: &mut String, anomaly: Option<&BaselineAnomaly>) {
+    if anomaly.is_some_and(|anomaly| anomaly.action == AnomalyAction::Annotate) {
+        writeln!(
+            md,
+            "> ⚠️ Compared against an anomalous baseline, see the warning above.\n"
+        )
+        .unwrap();
+    }
+}
+
+#[cfg(test)]
+fn history_for_test() -> Vec<BenchData> {
+    let history =
+        std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/baseline/history.json"))
+            .unwrap();
+    history
+        .split(|&b| b == b'\n')
+        .filter_map(|line| serde_json::from_slice(line).ok())
+        .collect()
+}
+
+#[cfg(test)]
+fn ancestors_for_test() -> Vec<String> {
+    ["2", "3", "4", "5", "6"]
+        .iter()
+        .map(|digit| digit.repeat(40))
+        .collect()
+}
+
+#[test]
+fn ancestors_on_main() {
+    let dir = crate::testkit::test_dir("baseline-ancestors");
+    let git = |args: &[&str]| {
+        let output = Command::new("git")
+            .args([
+                "-c",
+                "user.name=Bench",
+                "-c",
+                "user.email=bench@example.com",
+            ])
+            .args(["-c", "commit.gpgsign=false"])
+            .args(args)
+            .current_dir(&dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{output:?}");
+        String::from_utf8(output.stdout).unwrap().trim().to_owned()
+    };
+
+    git(&["init", "--quiet", "--initial-branch=main"]);
+    let mut main = vec![];
+    for message in ["one", "two"] {
+        git(&["commit", "--quiet", "--allow-empty", "-m", message]);
+        main.push(git(&["rev-parse", "HEAD"]));
+    }
+    git(&["checkout", "--quiet", "-b", "feature"]);
+    git(&["commit", "--quiet", "--allow-empty", "-m", "feature"]);
+    let feature = git(&["rev-parse", "HEAD"]);
+    git(&["checkout", "--quiet", "main"]);
+    git(&["merge", "--quiet", "--no-ff", "-m", "merge", "feature"]);
+    main.push(git(&["rev-parse", "HEAD"]));
+
+    // The merged branch is not on main.
+    let on_main = ancestors(&dir, &main[2], 5).unwrap();
+    assert_eq!(on_main, [main[1].clone(), main[0].clone()]);
+    assert!(!on_main.contains(&feature));
+
+    assert_eq!(ancestors(&dir, &main[2], 1).unwrap(), [main[1].clone()]);
+    assert!(ancestors(&dir, "0000000", 1).is_err());
+}
+
+#[test]
+fn baseline_outlier() {
+    let history = history_for_test();
+    let baseline = &history[0];
+    assert_eq!(baseline.commit_hash, "1".repeat(40));
+
+    // The results of `3333333` are from a different class of machine.
+    let neighbors = neighbors(baseline, &ancestors_for_test(), &history, 3);
+    assert_eq!(
+        neighbors
+            .iter()
+            .map(|neighbor| &neighbor.commit_hash[..1])
+            .collect::<Vec<_>>(),
+        ["2", "4", "5"]
+    );
+
+    let config: BaselineSanityConfig = serde_json::from_str("{}").unwrap();
+    let stable = machine::default_machine_stable_counters();
+    let anomaly = config
+        .check(&stable, &["control".to_owned()], baseline, &neighbors)
+        .unwrap();
+    assert_eq!(anomaly.neighbors.len(), 3);
+    assert_eq!(
+        anomaly.deviations,
+        [Deviation {
+            group: "control".to_owned(),
+            command: "./control".to_owned(),
+            counter: "instructions".to_owned(),
+            baseline: 1.1e9,
+            median: 1.0e9,
+            deviation_percent: 10.0,
+        }]
+    );
+
+    // Within the tolerance, or only compared against the unaffected group.
+    let lenient: BaselineSanityConfig =
+        serde_json::from_str(r#"{ "tolerance-percent": 12 }"#).unwrap();
+    assert_eq!(
+        lenient.check(&stable, &["control".to_owned()], baseline, &neighbors),
+        None
+    );
+    assert_eq!(
+        config.check(&stable, &["compress".to_owned()], baseline, &neighbors),
+        None
+    );
+    // The cycles deviate as well, but aren't stable across machines.
+    assert_eq!(
+        config
+            .check(&["cycles".to_owned()], &[], baseline, &neighbors)
+            .unwrap()
+            .deviations
+            .len(),
+        2
+    );
+
+    // A normal baseline is fine.
+    assert_eq!(
+        config.check(&stable, &[], neighbors[0], &neighbors[1..]),
+        None
+    );
+}
+
+#[test]
+fn median_composite_baseline() {
+    let history = history_for_test();
+    let baseline = &history[0];
+    let neighbors = neighbors(baseline, &ancestors_for_test(), &history, 3);
+
+    let composite = composite(baseline, &neighbors);
+    assert_eq!(composite.commit_hash, baseline.commit_hash);
+
+    let control = &composite.bench_groups["control"][0].counters;
+    // The median of 1.1e9 and the 1.0e9, 0.99e9 and 1.01e9 of the neighbors.
+    assert_eq!(control["instructions"].value, 1.005e9);
+    assert_eq!(control["instructions"].variance, 1.0e12);
+    assert_eq!(control["cycles"].value, 2.01e9);
+
+    // Counters that only the baseline has are kept.
+    let compress = &composite.bench_groups["compress"][0].counters;
+    assert_eq!(compress["task-clock"].value, 300.0);
+}
+
+#[test]
+fn annotate_anomalous_baseline() {
+    let anomaly = BaselineAnomaly {
+        commit: "1".repeat(40),
+        neighbors: vec!["2".repeat(40), "4".repeat(40)],
+        deviations: vec![Deviation {
+            group: "control".to_owned(),
+            command: "./control".to_owned(),
+            counter: "instructions".to_owned(),
+            baseline: 1.1e9,
+            median: 1.0e9,
+            deviation_percent: 10.0,
+        }],
+        action: AnomalyAction::Annotate,
+    };
+
+    let mut md = String::new();
+    render_markdown_warning(&mut md, Some(&anomaly));
+    render_markdown_table_note(&mut md, Some(&anomaly));
+    assert_eq!(
+        md,
+        "> [!WARNING]\n\
+         > The baseline `1111111` looks anomalous compared to the results of `2222222`, `4444444`. \
+         The comparisons below may show changes that aren't there.\n\
+         >\n\
+         > - control / `./control` instructions: +10.0% from the median\n\
+         \n\
+         > ⚠️ Compared against an anomalous baseline, see the warning above.\n\n"
+    );
+
+    // A substituted baseline needs no note on every table.
+    let substituted = BaselineAnomaly {
+        action: AnomalyAction::Substitute,
+        ..anomaly
+    };
+    let mut md = String::new();
+    render_markdown_table_note(&mut md, Some(&substituted));
+    render_markdown_table_note(&mut md, None);
+    assert_eq!(md, "");
+}
+
+#[test]
+fn neighbors_by_machine_id() {
+    use crate::testkit::BenchDataBuilder;
+
+    let entry = |digit: &str, cpu_model: &str, machine_id: Option<&str>| {
+        let builder = BenchDataBuilder::new(&digit.repeat(40)).machine("X64", "Linux", cpu_model);
+        match machine_id {
+            Some(id) => builder.machine_id(id),
+            None => builder,
+        }
+        .build()
+    };
+    let baseline = entry("1", "Intel(R) Xeon(R)  CPU E5-2690 v4 @ 2.60GHz", Some("a"));
+    let history = [
+        // Recorded before the identifiers, with the old spacing.
+        entry("2", "Intel(R) Xeon(R) CPU E5-2690 v4 @ 2.60GHz", None),
+        // Another machine with the same CPU.
+        entry("3", "Intel(R) Xeon(R)  CPU E5-2690 v4 @ 2.60GHz", Some("b")),
+        // The same machine, whatever its model says.
+        entry("4", "Intel(R) Xeon(R) CPU E5-2690 v4 (rev 2)", Some("a")),
+        entry("5", "Intel(R) Xeon(R) CPU E5-2673 v4 @ 2.30GHz", None),
+    ];
+    let neighbors = neighbors(&baseline, &ancestors_for_test(), &history, 5);
+    assert_eq!(
+        neighbors
+            .iter()
+            .map(|neighbor| &neighbor.commit_hash[..1])
+            .collect::<Vec<_>>(),
+        ["2", "4"]
+    );
+}
@@ -1,18 +1,72 @@
 use std::collections::BTreeMap;
-use std::fmt::Write;
-use std::process::Command;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Output};
+use std::rc::Rc;
 
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 
-use crate::HumanReadable;
+use crate::import::ImportedFrom;
+use crate::intervals::IntervalSeries;
+use crate::measure_child::{self, MeasureChild};
+use crate::perf_events::{self, PerfEvent};
+use crate::preemption::{Preempted, PreemptionConfig};
+use crate::sync_start::{self, SyncStart};
+use crate::verify_output::{Hashes, VerifyOutput};
+use crate::watchdog::{self, WatchdogConfig, Watched};
+use crate::{mix, required_counters, rusage, scratch};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SingleBench {
     pub cmd: Vec<String>,
+    /// The stable id of the benchmark, to match it with previous results when the command
+    /// line changes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// The tags of the command and its group.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
     pub counters: BTreeMap<String, BenchCounter>,
+    /// The share of samples per symbol, in percent, for commands with `profile` enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<IndexMap<String, f64>>,
+    /// The course of a counter over a single run, for commands with `interval-ms` set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub intervals: Option<IntervalSeries>,
+    /// The exit code of the command, when a backend ran it directly.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    /// The total size of the `produces` outputs after the last run, in bytes. Informational,
+    /// it isn't compared.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_bytes: Option<u64>,
+    /// Why the command failed although it ran, like perf reporting no data for a required
+    /// counter, see [`crate::required_counters`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// The output of the command differed between its repetitions, see
+    /// [`crate::verify_output`].
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub nondeterministic_output: bool,
+    /// The repetitions left out of the counters because other processes disturbed them, see
+    /// [`crate::preemption`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preempted: Option<Preempted>,
+    /// The counters measured later, at the commit of these results, by
+    /// `--backfill-baseline-counters`, see [`crate::backfill`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub backfilled: Vec<String>,
+    /// The command made no progress and was killed by the watchdog, see [`crate::watchdog`].
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub hung: bool,
+    /// Where the command was measured, when it was imported from another run, see
+    /// [`crate::import`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub imported: Option<ImportedFrom>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct BenchCounter {
     pub value: f64,
     pub variance: f64,
@@ -21,39 +75,74 @@ pub struct BenchCounter {
 }
 
 impl BenchCounter {
-    pub fn render_markdown_row(md: &mut String, name: &str, old: &Self, new: &Self) {
-        let percentage = BenchCounter::improvement_percentage(old, new);
-        let significant = BenchCounter::is_significant(old, new);
+    pub fn improvement_percentage(old: &Self, new: &Self) -> f64 {
+        ((new.value - old.value) / new.value) * 100.0
+    }
 
-        let significant = if significant {
-            if percentage > 0.0 {
-                "💩"
-            } else {
-                "🚀"
-            }
+    /// Perform a t-test with a 95% confidence interval.
+    pub fn is_significant(old: &Self, new: &Self) -> bool {
+        let (t_statistic, df, _) = Self::t_test(old, new);
+
+        // Check if t-statistic exceeds the critical value of a two-tailed distribution
+        t_statistic > t_critical(df as f64, ALPHA_95)
+    }
+
+    /// The two-tailed p-value of the same t-test as [`Self::is_significant`].
+    pub fn p_value(old: &Self, new: &Self) -> f64 {
+        let (t_statistic, df, _) = Self::t_test(old, new);
+        t_p_value(t_statistic, df as f64)
+    }
+
+    /// The standard deviation relative to the mean. `None` for a mean of zero.
+    pub fn coefficient_of_variation(&self) -> Option<f64> {
+        (self.value != 0.0).then(|| self.variance.sqrt() / self.value.abs())
+    }
+
+    /// The change of the coefficient of variation, in percent of the old one. `None` when
+    /// either is unknown, or when only the old one is zero, as there is no relative change
+    /// from zero.
+    pub fn cov_change_percent(old: &Self, new: &Self) -> Option<f64> {
+        let old_cov = old.coefficient_of_variation()?;
+        let new_cov = new.coefficient_of_variation()?;
+        if old_cov == 0.0 {
+            return (new_cov == 0.0).then_some(0.0);
+        }
+        Some((new_cov - old_cov) / old_cov * 100.0)
+    }
+
+    /// Perform a two-tailed F-test of the ratio of the variances with a 95% confidence
+    /// interval.
+    ///
+    /// A variance of zero on one side says more about the resolution of the counter than
+    /// about its spread, so it is never significant.
+    pub fn is_variance_significant(old: &Self, new: &Self) -> bool {
+        if old.repetitions < 2 || new.repetitions < 2 || old.variance == 0.0 || new.variance == 0.0
+        {
+            return false;
+        }
+
+        // The larger variance goes in the numerator, so only the upper critical value is
+        // needed.
+        let (larger, smaller) = if new.variance >= old.variance {
+            (new, old)
         } else {
-            "  "
+            (old, new)
         };
-
-        writeln!(
-            md,
-            "| {name} | `{} ± {}` | `{} ± {}` | `{} {:>+6.2}%` |",
-            HumanReadable(old.value),
-            HumanReadable(old.variance.sqrt().round()),
-            HumanReadable(new.value),
-            HumanReadable(new.variance.sqrt().round()),
-            significant,
-            percentage,
-        )
-        .unwrap();
+        let f_statistic = larger.variance / smaller.variance;
+        f_statistic > get_f_score_95(larger.repetitions - 1, smaller.repetitions - 1)
     }
 
-    pub fn improvement_percentage(old: &Self, new: &Self) -> f64 {
-        ((new.value - old.value) / new.value) * 100.0
+    /// The 95% confidence interval of `new.value - old.value`, from the same t-distribution as
+    /// [`Self::is_significant`].
+    pub fn difference_confidence_interval(old: &Self, new: &Self) -> (f64, f64) {
+        let (_, df, se) = Self::t_test(old, new);
+        let difference = new.value - old.value;
+        let margin = get_stat_score_95(df) * se;
+        (difference - margin, difference + margin)
     }
 
-    /// Perform a t-test with a 95% confidence interval.
-    pub fn is_significant(old: &Self, new: &Self) -> bool {
+    /// The absolute t-statistic, the degrees of freedom and the standard error.
+    fn t_test(old: &Self, new: &Self) -> (f64, u32, f64) {
         // We use short variable names that match how the t-test is often taught.
         let x1_bar = old.value; // mean of old
         let s1_sqr = old.variance; // variance of old
@@ -71,24 +160,934 @@ impl BenchCounter {
         // Compute the t-statistic
         let t_statistic = (x2_bar - x1_bar).abs() / se;
 
-        // Lookup the p-score for a 95% confidence interval of a two-tailed distribution
-        let threshold = get_stat_score_95(df);
+        (t_statistic, df, se)
+    }
+}
 
-        // Check if t-statistic exceeds the p-score threshold
-        t_statistic > threshold
+/// The command to benchmark.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandSpec {
+    pub argv: Vec<String>,
+    /// The exit codes with which a run of the command counts as successful.
+    pub expected_exit_codes: Vec<i32>,
+    /// A command line prefix to run the programs of the backends with, e.g. for isolation.
+    pub wrapper: Vec<String>,
+    /// The directory to run the command in rather than the current one, like the worktree of
+    /// the baseline, see [`crate::backfill`].
+    pub current_dir: Option<PathBuf>,
+    /// How long to wait for the start signal of the `sync-start` protocol, for commands that
+    /// take part in it. See [`crate::sync_start`].
+    pub sync_start: Option<std::time::Duration>,
+    /// The process started by the command to measure instead of the command, with perf. See
+    /// [`crate::measure_child`].
+    pub measure_child: Option<MeasureChild>,
+    /// Check that the output of the command is the same in every repetition, see
+    /// [`crate::verify_output`].
+    pub verify_output: Option<VerifyOutput>,
+    /// Kill the command when it makes no progress, see [`crate::watchdog`].
+    pub watchdog: Option<WatchdogConfig>,
+    /// Leave out the runs disturbed by other processes, see [`crate::preemption`].
+    pub preemption: Option<PreemptionConfig>,
+}
+
+impl CommandSpec {
+    #[cfg(test)]
+    pub fn new(argv: Vec<String>) -> Self {
+        CommandSpec {
+            argv,
+            expected_exit_codes: vec![0],
+            wrapper: vec![],
+            current_dir: None,
+            sync_start: None,
+            measure_child: None,
+            verify_output: None,
+            watchdog: None,
+            preemption: None,
+        }
+    }
+
+    /// A [`Command`] running `program` with the wrapper, if any.
+    ///
+    /// Backends wrap the program they run rather than the benchmarked command, so that e.g.
+    /// perf doesn't count the wrapper.
+    pub fn command(&self, program: impl AsRef<std::ffi::OsStr>) -> Command {
+        let mut command = match self.wrapper.split_first() {
+            Some((wrapper, wrapper_args)) => {
+                let mut command = Command::new(wrapper);
+                command.args(wrapper_args).arg(program);
+                command
+            }
+            None => Command::new(program),
+        };
+        if let Some(dir) = &self.current_dir {
+            command.current_dir(dir);
+        }
+        command
+    }
+
+    /// The exit code of a run, or `None` when it is not one of the expected exit codes. Being
+    /// killed by a signal is never expected.
+    pub fn expected_exit_code(&self, status: ExitStatus) -> Option<i32> {
+        status
+            .code()
+            .filter(|code| self.expected_exit_codes.contains(code))
+    }
+
+    /// Run the command once without measuring it.
+    pub fn run_unmeasured(&self) -> Result<(), String> {
+        let mut command = self.command(&self.argv[0]);
+        command.args(&self.argv[1..]);
+        let output = command
+            .output()
+            .map_err(|e| format!("failed to run `{}`: {e}", self.argv[0]))?;
+        match self.expected_exit_code(output.status) {
+            Some(_) => Ok(()),
+            None => Err(command_failed(&command, &output)),
+        }
     }
 }
 
-pub fn bench_single_cmd(cmd: Vec<String>, repetitions: u32) -> SingleBench {
-    eprintln!("Benchmarking {}", cmd.join(" "));
-    if cfg!(target_os = "linux") {
-        bench_single_cmd_perf(cmd, repetitions)
+/// The counters of a command, as measured by one backend.
+#[derive(Debug, Default)]
+pub struct Measurement {
+    pub counters: BTreeMap<String, BenchCounter>,
+    /// The exit code of the command, for backends that run it directly.
+    pub exit_code: Option<i32>,
+    /// The raw output of `perf stat`, for `keep-perf-output`.
+    pub perf_output: Option<Vec<u8>>,
+    /// What every run used, for the backends that measure the runs one by one.
+    pub runs: Vec<rusage::RunUsage>,
+    /// Why the command failed although it ran, see [`crate::required_counters`].
+    pub error: Option<String>,
+}
+
+/// A source of counters for a benchmarked command.
+pub trait Backend {
+    /// The name used in config files and error messages.
+    fn name(&self) -> &str;
+
+    /// The name with what sets this backend apart from others of its kind, to tell the
+    /// calibrations of the harness overhead apart, see [`crate::overhead`].
+    fn label(&self) -> String {
+        self.name().to_owned()
+    }
+
+    /// Run `cmd` `repetitions` times and aggregate the counters over all runs.
+    fn measure(&self, cmd: &CommandSpec, repetitions: u32) -> Result<Measurement, String>;
+
+    /// The unmeasured runs [`Self::measure`] does before the repetitions.
+    fn warmup_runs(&self) -> u32 {
+        0
+    }
+
+    /// Run `cmd` once, without a warmup run, for interleaving the repetitions of several
+    /// commands, see [`crate::interleave`].
+    fn measure_once(&self, cmd: &CommandSpec) -> Result<Measurement, String> {
+        self.measure(cmd, 1)
+    }
+
+    /// Aggregate the counters of the runs of [`Self::measure_once`] like [`Self::measure`]
+    /// aggregates those of its repetitions: by default the mean and the sample variance.
+    fn aggregate(&self, runs: &[Measurement]) -> BTreeMap<String, BenchCounter> {
+        aggregate_samples(runs, |variance, _| variance)
+    }
+
+    /// The command line that measures `cmd` like [`Self::measure`], to reproduce a
+    /// measurement by hand, see [`crate::repro`]. `None` for backends that measure the command
+    /// themselves rather than with another program.
+    fn repro_command_line(&self, _cmd: &CommandSpec, _repetitions: u32) -> Option<Vec<String>> {
+        None
+    }
+}
+
+/// The mean of every counter over the runs that have it, with the variance given by
+/// `variance` from the sample variance and the number of samples.
+fn aggregate_samples(
+    runs: &[Measurement],
+    variance: fn(f64, usize) -> f64,
+) -> BTreeMap<String, BenchCounter> {
+    let mut samples: BTreeMap<&str, (Vec<f64>, &str)> = BTreeMap::new();
+    for run in runs {
+        for (name, counter) in &run.counters {
+            let (values, _) = samples.entry(name).or_insert((vec![], &counter.unit));
+            values.push(counter.value);
+        }
+    }
+
+    samples
+        .into_iter()
+        .map(|(name, (values, unit))| {
+            let (value, sample_variance) = mean_and_variance(&values);
+            let counter = BenchCounter {
+                value,
+                variance: variance(sample_variance, values.len()),
+                repetitions: values.len() as u32,
+                unit: unit.to_owned(),
+            };
+            (name.to_owned(), counter)
+        })
+        .collect()
+}
+
+/// The error for a command that failed, including its output.
+fn command_failed(command: &Command, output: &Output) -> String {
+    format!(
+        "`{:?}` failed with {:?}:\n=== stdout ===\n{}\n\n=== stderr ===\n{}",
+        command,
+        output.status,
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr),
+    )
+}
+
+/// The backend used when a group doesn't configure any.
+pub fn default_backend(perf: Perf) -> Box<dyn Backend> {
+    default_backend_on(std::env::consts::OS, perf)
+}
+
+/// perf only exists on Linux, elsewhere getrusage measures the commands.
+fn default_backend_on(os: &str, perf: Perf) -> Box<dyn Backend> {
+    if os == "linux" {
+        Box::new(perf)
     } else {
-        bench_single_cmd_getrusage(cmd, repetitions)
+        Box::new(Getrusage)
+    }
+}
+
+/// Runs a program of a backend to completion, so tests can stand in for perf on any OS.
+pub trait CommandRunner {
+    fn run(&self, cmd: &mut Command) -> std::io::Result<Output>;
+}
+
+/// Runs the program for real.
+pub struct SystemRunner;
+
+impl CommandRunner for SystemRunner {
+    fn run(&self, cmd: &mut Command) -> std::io::Result<Output> {
+        cmd.output()
+    }
+}
+
+/// Measure `cmd` with every backend. The raw output of perf is written to `perf_output`, if
+/// given, so the counters can be parsed again by `benchmarker replay`. With `verify-output`,
+/// the output is hashed after an unmeasured run before the backends and after all of them.
+pub fn bench_single_cmd(
+    cmd: CommandSpec,
+    repetitions: u32,
+    backends: &[Box<dyn Backend>],
+    perf_output: Option<&Path>,
+) -> Result<SingleBench, String> {
+    eprintln!("Benchmarking {}", cmd.argv.join(" "));
+
+    let mut hashes = Hashes::default();
+    if let Some(verify) = &cmd.verify_output {
+        cmd.run_unmeasured()?;
+        hashes.record(verify);
+    }
+
+    let mut measured = vec![];
+    let mut exit_code = None;
+    let mut error = None;
+    let mut preempted = None;
+    let mut hung = false;
+    for backend in backends {
+        let watched = watchdog::watch(cmd.watchdog.as_ref(), &cmd.argv, || {
+            backend.measure(&cmd, repetitions)
+        })?;
+        let measurement = match watched {
+            Watched::Finished(measurement) => measurement,
+            // The counters of the other backends aren't comparable to anything either.
+            Watched::Hung(why) => {
+                (measured, exit_code, error, hung) = (vec![], None, Some(why), true);
+                break;
+            }
+        };
+        exit_code = exit_code.or(measurement.exit_code);
+        error = error.or(measurement.error);
+        if let (Some(path), Some(output)) = (perf_output, &measurement.perf_output) {
+            fs::write(path, output)
+                .map_err(|e| format!("failed to write {}: {e}", path.display()))?;
+        }
+        let excluded = cmd
+            .preemption
+            .as_ref()
+            .and_then(|preemption| preemption.exclude(&measurement.runs));
+        match excluded {
+            Some((counters, excluded)) => {
+                preempted = Some(excluded);
+                measured.push((backend.name(), counters));
+            }
+            None => measured.push((backend.name(), measurement.counters)),
+        }
+    }
+
+    let mut bench = SingleBench {
+        counters: merge_counters(measured)?,
+        cmd: cmd.argv,
+        id: None,
+        tags: vec![],
+        profile: None,
+        intervals: None,
+        exit_code,
+        output_bytes: None,
+        error,
+        nondeterministic_output: false,
+        preempted,
+        backfilled: vec![],
+        hung,
+        imported: None,
+    };
+    if let Some(verify) = cmd.verify_output.as_ref().filter(|_| !hung) {
+        hashes.record(verify);
+        hashes.apply(&mut bench);
+    }
+    Ok(bench)
+}
+
+/// Merge the counters measured by several backends for the same command.
+///
+/// Two backends reporting the same counter is a configuration error, as there is no
+/// sensible way to pick one of them.
+pub fn merge_counters<'a>(
+    measured: impl IntoIterator<Item = (&'a str, BTreeMap<String, BenchCounter>)>,
+) -> Result<BTreeMap<String, BenchCounter>, String> {
+    let mut merged = BTreeMap::new();
+    let mut sources = BTreeMap::new();
+
+    for (backend, counters) in measured {
+        for (name, counter) in counters {
+            if let Some(other) = sources.insert(name.clone(), backend) {
+                return Err(format!(
+                    "counter `{name}` is reported by both the `{other}` and the `{backend}` backend"
+                ));
+            }
+            merged.insert(name, counter);
+        }
+    }
+
+    Ok(merged)
+}
+
+/// Measure using `perf stat`.
+#[derive(Clone)]
+pub struct Perf {
+    /// The perf executable.
+    pub program: PathBuf,
+    /// The scratch directory of the run, for the output of perf.
+    pub scratch: PathBuf,
+    /// The events to count, see [`crate::perf_events`].
+    pub events: Vec<PerfEvent>,
+    /// Also count the events of the instruction mix.
+    pub instruction_mix: bool,
+    /// The counters every command must report, instead of those of [`Self::events`], see
+    /// [`crate::required_counters`].
+    pub required_counters: Option<Vec<String>>,
+    /// Runs perf, for the commands measured as a whole. Those with `sync-start` or
+    /// `measure-child` talk to perf while it runs.
+    pub runner: Rc<dyn CommandRunner>,
+}
+
+impl Perf {
+    pub fn new(scratch: &Path) -> Self {
+        Perf {
+            program: PathBuf::from("perf"),
+            scratch: scratch.to_owned(),
+            events: PerfEvent::parse_list(perf_events::DEFAULT_EVENTS),
+            instruction_mix: false,
+            required_counters: None,
+            runner: Rc::new(SystemRunner),
+        }
+    }
+
+    /// The events to count, with those of the instruction mix.
+    pub fn events(&self) -> Vec<PerfEvent> {
+        let mut events = self.events.clone();
+        if self.instruction_mix {
+            events.extend(PerfEvent::parse_list(mix::PERF_EVENTS));
+        }
+        events
+    }
+
+    /// The counters every command must report: by default those of the configured events. The
+    /// CPU may not have the events of the instruction mix.
+    pub fn required_counters(&self) -> Vec<String> {
+        match &self.required_counters {
+            Some(required) => required.clone(),
+            None => self.events.iter().map(PerfEvent::name).collect(),
+        }
     }
 }
 
-fn bench_single_cmd_perf(cmd: Vec<String>, repetitions: u32) -> SingleBench {
+impl Backend for Perf {
+    fn name(&self) -> &str {
+        "perf"
+    }
+
+    fn label(&self) -> String {
+        let mut details = vec![];
+        if self.events != PerfEvent::parse_list(perf_events::DEFAULT_EVENTS) {
+            details.push(
+                self.events
+                    .iter()
+                    .map(PerfEvent::name)
+                    .collect::<Vec<_>>()
+                    .join(","),
+            );
+        }
+        if self.instruction_mix {
+            details.push("instruction mix".to_owned());
+        }
+        if details.is_empty() {
+            "perf".to_owned()
+        } else {
+            format!("perf ({})", details.join(", "))
+        }
+    }
+
+    fn measure(&self, cmd: &CommandSpec, repetitions: u32) -> Result<Measurement, String> {
+        if let Some(child) = &cmd.measure_child {
+            return bench_child_perf(self, cmd, child, repetitions);
+        }
+        bench_single_cmd_perf(
+            &self.program,
+            &*self.runner,
+            &self.scratch,
+            &self.events(),
+            &self.required_counters(),
+            cmd,
+            repetitions,
+        )
+    }
+
+    /// Perf reports the variance of the mean rather than the sample variance.
+    fn aggregate(&self, runs: &[Measurement]) -> BTreeMap<String, BenchCounter> {
+        aggregate_samples(runs, |variance, samples| variance / samples as f64)
+    }
+
+    /// Without the JSON output file, so perf prints the counters.
+    fn repro_command_line(&self, cmd: &CommandSpec, repetitions: u32) -> Option<Vec<String>> {
+        let events = self
+            .events()
+            .iter()
+            .map(PerfEvent::name)
+            .collect::<Vec<_>>()
+            .join(",");
+        let mut argv = vec![
+            "LANG=C".to_owned(),
+            self.program.display().to_string(),
+            "stat".to_owned(),
+            "-e".to_owned(),
+            events,
+            "--repeat".to_owned(),
+            repetitions.to_string(),
+            "--".to_owned(),
+        ];
+        argv.extend(cmd.argv.iter().cloned());
+        Some(argv)
+    }
+}
+
+/// Measure the user, system and wall time and the peak memory of every run of the command, see
+/// [`crate::rusage`]. Works on every unix, and also counts cycles and instructions on Apple
+/// Silicon.
+pub struct Getrusage;
+
+impl Backend for Getrusage {
+    fn name(&self) -> &str {
+        "getrusage"
+    }
+
+    fn measure(&self, cmd: &CommandSpec, repetitions: u32) -> Result<Measurement, String> {
+        bench_single_cmd_getrusage(cmd, self.warmup_runs(), repetitions)
+    }
+
+    fn warmup_runs(&self) -> u32 {
+        1
+    }
+
+    fn measure_once(&self, cmd: &CommandSpec) -> Result<Measurement, String> {
+        bench_single_cmd_getrusage(cmd, 0, 1)
+    }
+
+    /// The runs are aggregated as if they were the repetitions of a single measurement, so
+    /// the `-cold` counters are those of the first run.
+    fn aggregate(&self, runs: &[Measurement]) -> BTreeMap<String, BenchCounter> {
+        let runs = runs
+            .iter()
+            .flat_map(|run| run.runs.iter().cloned())
+            .collect::<Vec<_>>();
+        rusage::counters(&runs)
+    }
+}
+
+/// Measure using an external program.
+///
+/// The program is given as a command template in which `{repetitions}` is replaced by the
+/// number of repetitions and `{cmd}` by the arguments of the benchmarked command. When the
+/// template doesn't contain `{cmd}`, the benchmarked command is appended to it.
+///
+/// The program is expected to run the benchmarked command itself and to print its counters
+/// on stdout as a JSON object. Every counter maps directly onto a `BenchCounter`:
+///
+/// ```json
+/// {
+///     "counters": {
+///         "gpu-utilization": { "value": 93.5, "variance": 1.2, "repetitions": 20, "unit": "%" },
+///         "nvme-read-bytes": { "value": 1048576 }
+///     }
+/// }
+/// ```
+///
+/// `variance` defaults to 0, `repetitions` to the requested number of repetitions and `unit`
+/// to the empty string.
+pub struct External {
+    pub template: String,
+}
+
+impl External {
+    fn command_line(&self, cmd: &CommandSpec, repetitions: u32) -> Vec<String> {
+        let mut argv = vec![];
+        let mut spliced = false;
+
+        for arg in self.template.split(' ').filter(|arg| !arg.is_empty()) {
+            if arg == "{cmd}" {
+                argv.extend(cmd.argv.iter().cloned());
+                spliced = true;
+            } else {
+                argv.push(arg.replace("{repetitions}", &repetitions.to_string()));
+            }
+        }
+
+        if !spliced {
+            argv.extend(cmd.argv.iter().cloned());
+        }
+
+        argv
+    }
+
+    fn parse_output(
+        stdout: &[u8],
+        repetitions: u32,
+    ) -> Result<BTreeMap<String, BenchCounter>, String> {
+        #[derive(Debug, Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct ExternalOutput {
+            counters: BTreeMap<String, ExternalCounter>,
+        }
+
+        #[derive(Debug, Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct ExternalCounter {
+            value: f64,
+            #[serde(default)]
+            variance: f64,
+            repetitions: Option<u32>,
+            #[serde(default)]
+            unit: String,
+        }
+
+        let output: ExternalOutput = serde_json::from_slice(stdout).map_err(|e| {
+            format!(
+                "failed to parse the output of the external backend: {e}\n{}",
+                String::from_utf8_lossy(stdout)
+            )
+        })?;
+
+        Ok(output
+            .counters
+            .into_iter()
+            .map(|(name, counter)| {
+                (
+                    name,
+                    BenchCounter {
+                        value: counter.value,
+                        variance: counter.variance,
+                        repetitions: counter.repetitions.unwrap_or(repetitions),
+                        unit: counter.unit,
+                    },
+                )
+            })
+            .collect())
+    }
+}
+
+impl Backend for External {
+    fn name(&self) -> &str {
+        "external"
+    }
+
+    fn label(&self) -> String {
+        format!("external `{}`", self.template)
+    }
+
+    fn repro_command_line(&self, cmd: &CommandSpec, repetitions: u32) -> Option<Vec<String>> {
+        Some(self.command_line(cmd, repetitions))
+    }
+
+    fn measure(&self, cmd: &CommandSpec, repetitions: u32) -> Result<Measurement, String> {
+        let argv = self.command_line(cmd, repetitions);
+        let Some((program, args)) = argv.split_first() else {
+            return Err("the external backend has an empty command template".to_owned());
+        };
+
+        let mut external_cmd = cmd.command(program);
+        external_cmd.args(args);
+
+        let output = external_cmd
+            .output()
+            .map_err(|e| format!("failed to run `{program}`: {e}"))?;
+        // The external program runs the command itself, so this is the status of the program.
+        if !output.status.success() {
+            return Err(command_failed(&external_cmd, &output));
+        }
+
+        Ok(Measurement {
+            counters: Self::parse_output(&output.stdout, repetitions)?,
+            exit_code: None,
+            perf_output: None,
+            runs: vec![],
+            error: None,
+        })
+    }
+}
+
+#[cfg(test)]
+struct FakeBackend(&'static str, &'static [(&'static str, f64)]);
+
+#[cfg(test)]
+impl Backend for FakeBackend {
+    fn name(&self) -> &str {
+        self.0
+    }
+
+    fn measure(&self, _cmd: &CommandSpec, repetitions: u32) -> Result<Measurement, String> {
+        let counters = self
+            .1
+            .iter()
+            .map(|&(name, value)| {
+                (
+                    name.to_owned(),
+                    BenchCounter {
+                        value,
+                        variance: 0.0,
+                        repetitions,
+                        unit: String::new(),
+                    },
+                )
+            })
+            .collect();
+
+        Ok(Measurement {
+            counters,
+            exit_code: None,
+            perf_output: None,
+            runs: vec![],
+            error: None,
+        })
+    }
+}
+
+#[test]
+fn merge_backend_counters() {
+    let cmd = CommandSpec::new(vec!["./bench".to_owned()]);
+    let backends: Vec<Box<dyn Backend>> = vec![
+        Box::new(FakeBackend("a", &[("cycles", 1.0), ("instructions", 2.0)])),
+        Box::new(FakeBackend("b", &[("gpu", 3.0)])),
+    ];
+
+    let bench = bench_single_cmd(cmd.clone(), 5, &backends, None).unwrap();
+    assert_eq!(bench.cmd, cmd.argv);
+    assert_eq!(
+        bench.counters.keys().collect::<Vec<_>>(),
+        ["cycles", "gpu", "instructions"]
+    );
+    assert_eq!(bench.counters["gpu"].value, 3.0);
+    assert_eq!(bench.counters["gpu"].repetitions, 5);
+
+    let backends: Vec<Box<dyn Backend>> = vec![
+        Box::new(FakeBackend("a", &[("cycles", 1.0)])),
+        Box::new(FakeBackend("b", &[("cycles", 3.0)])),
+    ];
+    assert_eq!(
+        bench_single_cmd(cmd, 5, &backends, None).unwrap_err(),
+        "counter `cycles` is reported by both the `a` and the `b` backend"
+    );
+}
+
+#[test]
+fn parse_external_output() {
+    let counters = External::parse_output(
+        br#"{ "counters": {
+            "gpu": { "value": 93.5, "variance": 1.5, "repetitions": 3, "unit": "%" },
+            "nvme-read-bytes": { "value": 1024 }
+        } }"#,
+        20,
+    )
+    .unwrap();
+
+    assert_eq!(counters["gpu"].value, 93.5);
+    assert_eq!(counters["gpu"].variance, 1.5);
+    assert_eq!(counters["gpu"].repetitions, 3);
+    assert_eq!(counters["gpu"].unit, "%");
+    assert_eq!(counters["nvme-read-bytes"].value, 1024.0);
+    assert_eq!(counters["nvme-read-bytes"].variance, 0.0);
+    assert_eq!(counters["nvme-read-bytes"].repetitions, 20);
+    assert_eq!(counters["nvme-read-bytes"].unit, "");
+
+    assert!(External::parse_output(b"not json", 20).is_err());
+    assert!(External::parse_output(br#"{ "counters": { "gpu": { "val": 1 } } }"#, 20).is_err());
+}
+
+#[test]
+fn external_backend_command_line() {
+    let cmd = CommandSpec::new(vec!["./bench".to_owned(), "6".to_owned()]);
+
+    let external = External {
+        template: "./stats --reps {repetitions} -- {cmd} --verbose".to_owned(),
+    };
+    assert_eq!(
+        external.command_line(&cmd, 20),
+        ["./stats", "--reps", "20", "--", "./bench", "6", "--verbose"]
+    );
+
+    let external = External {
+        template: "./stats {repetitions}".to_owned(),
+    };
+    assert_eq!(
+        external.command_line(&cmd, 3),
+        ["./stats", "3", "./bench", "6"]
+    );
+}
+
+#[test]
+fn external_backend_script() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = crate::testkit::test_dir("external-backend");
+    let script = dir.join("stats.sh");
+    std::fs::write(
+        &script,
+        r#"#!/bin/sh
+reps=$1
+shift
+echo "{ \"counters\": { \"args\": { \"value\": $# }, \"reps\": { \"value\": $reps, \"unit\": \"runs\" } } }"
+"#,
+    )
+    .unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let backend = External {
+        template: format!("{} {{repetitions}} {{cmd}}", script.display()),
+    };
+    let cmd = CommandSpec::new(vec!["./bench".to_owned(), "a".to_owned(), "b".to_owned()]);
+
+    let counters = backend.measure(&cmd, 7).unwrap().counters;
+    assert_eq!(counters["args"].value, 3.0);
+    assert_eq!(counters["reps"].value, 7.0);
+    assert_eq!(counters["reps"].unit, "runs");
+
+    let backend = External {
+        template: format!("{} {{repetitions}} false", script.display()),
+    };
+    std::fs::write(&script, "#!/bin/sh\nexit 3\n").unwrap();
+    assert!(backend
+        .measure(&cmd, 7)
+        .unwrap_err()
+        .contains("failed with"));
+}
+
+fn bench_single_cmd_perf(
+    perf: &Path,
+    runner: &dyn CommandRunner,
+    scratch: &Path,
+    events: &[PerfEvent],
+    required: &[String],
+    cmd: &CommandSpec,
+    repetitions: u32,
+) -> Result<Measurement, String> {
+    // Perf writes its counters to a separate file, so the benchmarked command can write
+    // whatever it likes to stderr without corrupting them.
+    let perf_output = scratch::file_path(scratch, "perf", "json");
+
+    let perf_stat = |extra_args: &[String]| {
+        let mut perf_stat_cmd = cmd.command(perf);
+        perf_stat_cmd
+            // Perf produces broken JSON when the system locale uses decimal comma rather than decimal point.
+            .env("LANG", "C")
+            .arg("stat")
+            .arg("-j")
+            .arg("-e")
+            .arg(
+                events
+                    .iter()
+                    .map(PerfEvent::name)
+                    .collect::<Vec<_>>()
+                    .join(","),
+            )
+            .arg("--repeat")
+            .arg(repetitions.to_string())
+            .arg("-o")
+            .arg(&perf_output)
+            .args(extra_args)
+            .arg("--");
+        perf_stat_cmd.args(&cmd.argv);
+        perf_stat_cmd
+    };
+
+    let mut synchronized = None;
+    if let Some(timeout) = cmd.sync_start {
+        let sync_start =
+            SyncStart::new().map_err(|e| format!("failed to set up sync-start: {e}"))?;
+        let mut perf_stat_cmd = perf_stat(&sync_start.perf_args());
+        match sync_start.run(&mut perf_stat_cmd, repetitions, timeout)? {
+            sync_start::Outcome::Finished(output) => synchronized = Some((perf_stat_cmd, output)),
+            sync_start::Outcome::Unsynchronized(reason) => eprintln!(
+                "warning: {} didn't take part in sync-start ({reason}), measuring it as a whole",
+                cmd.argv.join(" ")
+            ),
+        }
+    }
+    let (perf_stat_cmd, output) = match synchronized {
+        Some((perf_stat_cmd, output)) => (perf_stat_cmd, Ok(output)),
+        None => {
+            let mut perf_stat_cmd = perf_stat(&[]);
+            let output = runner
+                .run(&mut perf_stat_cmd)
+                .map_err(|e| format!("failed to run {}: {e}", perf.display()));
+            (perf_stat_cmd, output)
+        }
+    };
+    let perf_data = fs::read(&perf_output);
+    let _ = fs::remove_file(&perf_output);
+
+    // Failing to start perf is an error, whatever exit codes the command may have.
+    let output = output?;
+
+    // perf stat exits with the exit code of the last run of the command, so a failure of perf
+    // itself can look like an expected exit code. It then doesn't write its output file
+    // though. When the command is killed by a signal, perf exits successfully and only
+    // reports the signal on stderr.
+    let exit_code = cmd
+        .expected_exit_code(output.status)
+        .filter(|_| perf_reported_signal(&output.stderr, &cmd.argv[0]).is_none())
+        .ok_or_else(|| command_failed(&perf_stat_cmd, &output))?;
+
+    let perf_data =
+        perf_data.map_err(|e| format!("failed to read {}: {e}", perf_output.display()))?;
+    let counters = parse_perf_stat_output(&perf_data, repetitions, events)?;
+    let error = required_counters::check(required, &counters, &output.stderr);
+    Ok(Measurement {
+        counters,
+        exit_code: Some(exit_code),
+        perf_output: Some(perf_data),
+        runs: vec![],
+        error,
+    })
+}
+
+/// Measure the process `child` started by every run of `cmd` with `perf stat -p`, see
+/// [`crate::measure_child`]. perf can't repeat an attached measurement, so every repetition is
+/// a measurement of its own, aggregated like [`Perf::aggregate`] does. Their output isn't kept.
+fn bench_child_perf(
+    perf: &Perf,
+    cmd: &CommandSpec,
+    child: &MeasureChild,
+    repetitions: u32,
+) -> Result<Measurement, String> {
+    let events = perf.events();
+    let perf_output = scratch::file_path(&perf.scratch, "perf", "json");
+
+    let mut runs = vec![];
+    let mut exit_code = None;
+    for _ in 0..repetitions {
+        let mut driver = cmd.command(&cmd.argv[0]);
+        driver.args(&cmd.argv[1..]);
+        let mut perf_stat = Command::new(&perf.program);
+        perf_stat
+            .env("LANG", "C")
+            .arg("stat")
+            .arg("-j")
+            .arg("-e")
+            .arg(
+                events
+                    .iter()
+                    .map(PerfEvent::name)
+                    .collect::<Vec<_>>()
+                    .join(","),
+            );
+
+        let outcome = measure_child::run(&mut driver, &mut perf_stat, &perf_output, child);
+        let perf_data = fs::read(&perf_output);
+        let _ = fs::remove_file(&perf_output);
+        let (output, perf_stderr) = match outcome? {
+            measure_child::Outcome::Attached {
+                driver: output,
+                perf_stderr,
+            } => (output, perf_stderr),
+            measure_child::Outcome::Missed(error) => {
+                return Ok(Measurement {
+                    error: Some(error),
+                    ..Measurement::default()
+                })
+            }
+        };
+        exit_code = Some(
+            cmd.expected_exit_code(output.status)
+                .ok_or_else(|| command_failed(&driver, &output))?,
+        );
+
+        let perf_data =
+            perf_data.map_err(|e| format!("failed to read {}: {e}", perf_output.display()))?;
+        let counters = parse_perf_stat_output(&perf_data, 1, &events)?;
+        if let Some(error) =
+            required_counters::check(&perf.required_counters(), &counters, &perf_stderr)
+        {
+            return Ok(Measurement {
+                counters,
+                exit_code,
+                error: Some(error),
+                ..Measurement::default()
+            });
+        }
+        runs.push(Measurement {
+            counters,
+            ..Measurement::default()
+        });
+    }
+
+    Ok(Measurement {
+        counters: perf.aggregate(&runs),
+        exit_code,
+        ..Measurement::default()
+    })
+}
+
+/// The signal that killed the command, as reported by perf using `psignal`.
+fn perf_reported_signal(stderr: &[u8], program: &str) -> Option<i32> {
+    let stderr = String::from_utf8_lossy(stderr);
+    let prefix = format!("{program}: ");
+
+    stderr.lines().find_map(|line| {
+        let message = line.strip_prefix(&prefix)?;
+        // The standard signals; real-time signals have no fixed description.
+        (1..32).find(|&signal| {
+            // SAFETY: strsignal returns a valid C string, we copy it before calling it again.
+            let description = unsafe { std::ffi::CStr::from_ptr(libc::strsignal(signal)) };
+            description.to_bytes() == message.as_bytes()
+        })
+    })
+}
+
+/// Parse the output of `perf stat -j`.
+///
+/// Every counter is a JSON object on its own line. Other lines, like the `# started on`
+/// header perf writes to its output file, are skipped. The counters are named after the
+/// `events` perf was asked to count, see [`PerfEvent::counter_names`].
+pub fn parse_perf_stat_output(
+    output: &[u8],
+    repetitions: u32,
+    events: &[PerfEvent],
+) -> Result<BTreeMap<String, BenchCounter>, String> {
     #[derive(Debug, Deserialize)]
     #[serde(rename_all = "kebab-case")]
     struct PerfData {
@@ -98,148 +1097,1138 @@ fn bench_single_cmd_perf(cmd: Vec<String>, repetitions: u32) -> SingleBench {
         variance: f64,
     }
 
-    let mut perf_stat_cmd = Command::new("perf");
-    perf_stat_cmd
-        // Perf produces broken JSON when the system locale uses decimal comma rather than decimal point.
-        .env("LANG", "C")
-        .arg("stat")
-        .arg("-j")
-        .arg("-e")
-        .arg("task-clock,cycles,instructions")
-        .arg("--repeat")
-        .arg(repetitions.to_string())
-        .arg("--");
-    perf_stat_cmd.args(&cmd);
-
-    let output = perf_stat_cmd.output().unwrap();
-    assert!(
-        output.status.success(),
-        "`{:?}` failed with {:?}:=== stdout ===\n{}\n\n=== stderr ===\n{}",
-        perf_stat_cmd,
-        output.status,
-        String::from_utf8_lossy(&output.stdout),
-        String::from_utf8_lossy(&output.stderr),
-    );
-
-    let counters = String::from_utf8(output.stderr)
-        .unwrap()
-        .lines()
+    let reported = output
+        .split(|&b| b == b'\n')
+        .map(|line| line.trim_ascii())
+        .filter(|line| line.starts_with(b"{"))
         .map(|line| {
-            serde_json::from_str::<PerfData>(line)
-                .unwrap_or_else(|e| panic!("Failed to parse {line:?}: {e}"))
-        })
-        .filter(|counter| counter.counter_value != "<not counted>")
-        .map(|counter| {
-            let value = counter
-                .counter_value
-                .parse::<f64>()
-                .unwrap_or_else(|_| panic!("Failed to parse {}", counter.counter_value));
-            // Perf doesn't put the actual variance in the variance field. Instead it puts the
-            // relative standard deviation expressed as percentage there. We need the actual variance
-            // however, so invert the transformation perf does.
-            let variance = (counter.variance / 100. * value).powi(2);
-            (
-                counter.event,
-                BenchCounter {
-                    value,
-                    variance,
-                    repetitions,
-                    unit: counter.unit,
-                },
-            )
+            serde_json::from_slice::<PerfData>(line)
+                .map_err(|e| format!("Failed to parse {:?}: {e}", String::from_utf8_lossy(line)))
         })
-        .collect::<BTreeMap<_, _>>();
+        .collect::<Result<Vec<_>, _>>()?;
+    // Also the events that weren't counted, so the position of every report is right.
+    let names = PerfEvent::counter_names(
+        events,
+        &reported
+            .iter()
+            .map(|counter| counter.event.as_str())
+            .collect::<Vec<_>>(),
+    );
+
+    let mut counters = BTreeMap::new();
+    for (name, counter) in names.into_iter().zip(reported) {
+        // Events the CPU doesn't have are `<not supported>`. Leaving them out is how
+        // everything downstream knows they weren't counted.
+        if matches!(&*counter.counter_value, "<not counted>" | "<not supported>") {
+            continue;
+        }
+
+        let value = counter
+            .counter_value
+            .parse::<f64>()
+            .map_err(|_| format!("Failed to parse {}", counter.counter_value))?;
+        // Perf doesn't put the actual variance in the variance field. Instead it puts the
+        // relative standard deviation expressed as percentage there. We need the actual variance
+        // however, so invert the transformation perf does.
+        let variance = (counter.variance / 100. * value).powi(2);
+        counters.insert(
+            name,
+            BenchCounter {
+                value,
+                variance,
+                repetitions,
+                unit: counter.unit,
+            },
+        );
+    }
 
-    SingleBench { cmd, counters }
+    Ok(counters)
 }
 
-fn bench_single_cmd_getrusage(cmd: Vec<String>, repetitions: u32) -> SingleBench {
-    use std::mem;
-    use std::time::Duration;
+#[cfg(test)]
+const PERF_STAT_OUTPUT: &[u8] = b"# started on Tue Oct 15 10:00:00 2024
 
-    fn get_cpu_times() -> Duration {
-        use libc::{getrusage, rusage, RUSAGE_CHILDREN};
+{\"counter-value\" : \"254.210000\", \"unit\" : \"msec\", \"event\" : \"task-clock\", \"variance\" : 1.50, \"event-runtime\" : 254210000, \"pcnt-running\" : 100.00, \"metric-value\" : \"0.998000\", \"metric-unit\" : \"CPUs utilized\"}
+{\"counter-value\" : \"1000000000.000000\", \"unit\" : \"\", \"event\" : \"cycles\", \"variance\" : 0.10, \"event-runtime\" : 254210000, \"pcnt-running\" : 100.00, \"metric-value\" : \"3.933000\", \"metric-unit\" : \"GHz\"}
+{\"counter-value\" : \"<not counted>\", \"unit\" : \"\", \"event\" : \"instructions\", \"variance\" : 0.00, \"event-runtime\" : 0, \"pcnt-running\" : 0.00}
+";
 
-        let result: rusage = unsafe {
-            let mut buf = mem::zeroed();
-            let success = getrusage(RUSAGE_CHILDREN, &mut buf);
-            assert_eq!(0, success);
-            buf
-        };
+#[test]
+fn parse_perf_stat() {
+    let events = Perf::new(Path::new("/tmp")).events();
+    let counters = parse_perf_stat_output(PERF_STAT_OUTPUT, 20, &events).unwrap();
 
-        Duration::new(
-            result.ru_utime.tv_sec as _,
-            (result.ru_utime.tv_usec * 1000) as _,
-        )
+    assert_eq!(
+        counters.keys().collect::<Vec<_>>(),
+        ["cycles", "task-clock"]
+    );
+    assert_eq!(counters["task-clock"].value, 254.21);
+    assert_eq!(counters["task-clock"].unit, "msec");
+    assert_eq!(counters["task-clock"].repetitions, 20);
+    assert_eq!(counters["cycles"].value, 1e9);
+    assert_eq!(counters["cycles"].variance, 1e6f64.powi(2));
+
+    // Garbage and invalid UTF-8 surrounding the counters is ignored.
+    let mut garbage = b"\xff\xfe not utf-8 \xc3\x28\nrandom garbage\n".to_vec();
+    garbage.extend_from_slice(PERF_STAT_OUTPUT);
+    garbage.extend_from_slice(b"\n\x80\x81\n");
+    assert_eq!(
+        parse_perf_stat_output(&garbage, 20, &events).unwrap()["cycles"].value,
+        1e9
+    );
+
+    assert!(parse_perf_stat_output(b"{\"counter-value\" : \"1\"}", 20, &events).is_err());
+
+    // The events of the instruction mix the CPU doesn't have.
+    let counters = parse_perf_stat_output(
+        b"{\"counter-value\" : \"<not supported>\", \"unit\" : \"\", \"event\" : \"L1-dcache-stores\", \"variance\" : 0.00, \"event-runtime\" : 0, \"pcnt-running\" : 100.00}\n\
+          {\"counter-value\" : \"2000000.000000\", \"unit\" : \"\", \"event\" : \"branches\", \"variance\" : 0.50, \"event-runtime\" : 254210000, \"pcnt-running\" : 100.00}\n",
+        20,
+        &PerfEvent::parse_list(mix::PERF_EVENTS),
+    )
+    .unwrap();
+    assert_eq!(counters.keys().collect::<Vec<_>>(), ["branches"]);
+}
+
+#[test]
+fn parse_perf_stat_modifiers() {
+    // `perf stat -j -e task-clock,cycles,cycles:u,instructions:u` with `perf_event_paranoid`
+    // at 2, where perf adds `:u` to every event itself.
+    let output = b"{\"counter-value\" : \"3.120000\", \"unit\" : \"msec\", \"event\" : \"task-clock\", \"variance\" : 2.10, \"event-runtime\" : 3120000, \"pcnt-running\" : 100.00, \"metric-value\" : \"0.912000\", \"metric-unit\" : \"CPUs utilized\"}
+{\"counter-value\" : \"9500000.000000\", \"unit\" : \"\", \"event\" : \"cycles:u\", \"variance\" : 0.40, \"event-runtime\" : 3120000, \"pcnt-running\" : 100.00, \"metric-value\" : \"3.044000\", \"metric-unit\" : \"GHz\"}
+{\"counter-value\" : \"9400000.000000\", \"unit\" : \"\", \"event\" : \"cycles:u\", \"variance\" : 0.30, \"event-runtime\" : 3120000, \"pcnt-running\" : 100.00, \"metric-value\" : \"3.012000\", \"metric-unit\" : \"GHz\"}
+{\"counter-value\" : \"21000000.000000\", \"unit\" : \"\", \"event\" : \"instructions:u\", \"variance\" : 0.01, \"event-runtime\" : 3120000, \"pcnt-running\" : 100.00, \"metric-value\" : \"2.234000\", \"metric-unit\" : \"insn per cycle\"}
+";
+    let events = PerfEvent::parse_list("task-clock,cycles,cycles:u,instructions:u");
+    let counters = parse_perf_stat_output(output, 20, &events).unwrap();
+    assert_eq!(
+        counters.keys().collect::<Vec<_>>(),
+        ["cycles", "cycles:u", "instructions:u", "task-clock"]
+    );
+    assert_eq!(counters["cycles"].value, 9.5e6);
+    assert_eq!(counters["cycles:u"].value, 9.4e6);
+
+    // A hybrid CPU, where the atom cores didn't run the command.
+    let output = b"{\"counter-value\" : \"<not counted>\", \"unit\" : \"\", \"event\" : \"cpu_atom/cycles:u/\", \"variance\" : 0.00, \"event-runtime\" : 0, \"pcnt-running\" : 0.00}
+{\"counter-value\" : \"9400000.000000\", \"unit\" : \"\", \"event\" : \"cpu_core/cycles:u/\", \"variance\" : 0.30, \"event-runtime\" : 3120000, \"pcnt-running\" : 100.00}
+{\"counter-value\" : \"<not counted>\", \"unit\" : \"\", \"event\" : \"cpu_atom/instructions:u/\", \"variance\" : 0.00, \"event-runtime\" : 0, \"pcnt-running\" : 0.00}
+{\"counter-value\" : \"21000000.000000\", \"unit\" : \"\", \"event\" : \"cpu_core/instructions:u/\", \"variance\" : 0.01, \"event-runtime\" : 3120000, \"pcnt-running\" : 100.00}
+";
+    let events = PerfEvent::parse_list("cycles:u,instructions");
+    let mut bench = SingleBench {
+        cmd: vec!["./c".to_owned()],
+        id: None,
+        tags: vec![],
+        counters: parse_perf_stat_output(output, 20, &events).unwrap(),
+        profile: None,
+        intervals: None,
+        exit_code: None,
+        output_bytes: None,
+        error: None,
+        nondeterministic_output: false,
+        preempted: None,
+        backfilled: vec![],
+        hung: false,
+        imported: None,
+    };
+    let warnings =
+        crate::counter_names::CounterRenames::default().canonicalize_bench("compress", &mut bench);
+    assert_eq!(warnings, Vec::<String>::new());
+    assert_eq!(
+        bench.counters.keys().collect::<Vec<_>>(),
+        ["cycles:u", "instructions"]
+    );
+}
+
+#[test]
+fn perf_instruction_mix_events() {
+    let names = |perf: &Perf| {
+        perf.events()
+            .iter()
+            .map(PerfEvent::name)
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+    let mut perf = Perf::new(Path::new("/tmp"));
+    assert_eq!(names(&perf), "task-clock,cycles,instructions");
+    assert_eq!(perf.label(), "perf");
+
+    perf.instruction_mix = true;
+    assert_eq!(
+        names(&perf),
+        "task-clock,cycles,instructions,branches,branch-misses,L1-dcache-loads,L1-dcache-stores"
+    );
+    assert_eq!(perf.label(), "perf (instruction mix)");
+
+    perf.events = PerfEvent::parse_list("cycles:u,instructions:u");
+    assert_eq!(
+        names(&perf),
+        "cycles:u,instructions:u,branches,branch-misses,L1-dcache-loads,L1-dcache-stores"
+    );
+    assert_eq!(
+        perf.label(),
+        "perf (cycles:u,instructions:u, instruction mix)"
+    );
+}
+
+/// Write an executable shell script mimicking `perf stat -o <file> -- <cmd>`: it runs the
+/// command, writes [`PERF_STAT_OUTPUT`] to the output file and exits like perf does.
+#[cfg(test)]
+fn fake_perf(dir: &Path) -> PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::write(dir.join("perf-output.json"), PERF_STAT_OUTPUT).unwrap();
+
+    let script = dir.join("perf");
+    fs::write(
+        &script,
+        format!(
+            r#"#!/bin/sh
+while [ "$1" != "--" ]; do
+    if [ "$1" = "-o" ]; then out="$2"; fi
+    shift
+done
+shift
+"$@"
+status=$?
+cp "{}" "$out"
+# Like perf, report a signal on stderr rather than in the exit code.
+if [ $status -eq 139 ]; then
+    echo "$1: Segmentation fault" >&2
+    exit 0
+fi
+exit $status
+"#,
+            dir.join("perf-output.json").display()
+        ),
+    )
+    .unwrap();
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+
+    script
+}
+
+#[test]
+fn perf_ignores_child_stderr() {
+    let dir = crate::testkit::test_dir("perf-child-stderr");
+    let perf = Perf {
+        program: fake_perf(&dir),
+        ..Perf::new(&dir)
+    };
+
+    // A benchmark echoing a file name that is not valid UTF-8, and some garbage.
+    let cmd = CommandSpec::new(vec![
+        "sh".to_owned(),
+        "-c".to_owned(),
+        r#"printf '\377\376 corpus\n{"not": "perf"}\ngarbage\n' >&2"#.to_owned(),
+    ]);
+
+    let counters = perf.measure(&cmd, 3).unwrap().counters;
+    assert_eq!(counters["cycles"].value, 1e9);
+    assert_eq!(counters["cycles"].repetitions, 3);
+
+    let cmd = CommandSpec::new(vec![
+        "sh".to_owned(),
+        "-c".to_owned(),
+        "printf '\\377' >&2; exit 1".to_owned(),
+    ]);
+    assert!(perf.measure(&cmd, 3).unwrap_err().contains("failed with"));
+}
+
+#[test]
+fn keep_perf_output() {
+    let dir = crate::testkit::test_dir("keep-perf-output");
+    let backends: Vec<Box<dyn Backend>> = vec![
+        Box::new(Perf {
+            program: fake_perf(&dir),
+            ..Perf::new(&dir)
+        }),
+        Box::new(FakeBackend("external", &[("gpu-cycles", 5.0)])),
+    ];
+    let kept = dir.join("kept.txt");
+
+    let bench = bench_single_cmd(
+        CommandSpec::new(vec!["true".to_owned()]),
+        3,
+        &backends,
+        Some(&kept),
+    )
+    .unwrap();
+    // The kept output parses to the same counters.
+    let output = fs::read(&kept).unwrap();
+    assert_eq!(output, PERF_STAT_OUTPUT);
+    let mut counters = parse_perf_stat_output(&output, 3, &Perf::new(&dir).events()).unwrap();
+    counters.insert(
+        "gpu-cycles".to_owned(),
+        bench.counters["gpu-cycles"].clone(),
+    );
+    assert_eq!(bench.counters, counters);
+}
+
+#[cfg(test)]
+fn sh_command(script: &str, expected_exit_codes: &[i32]) -> CommandSpec {
+    CommandSpec {
+        expected_exit_codes: expected_exit_codes.to_vec(),
+        ..CommandSpec::new(vec!["sh".to_owned(), "-c".to_owned(), script.to_owned()])
     }
+}
 
-    let mut bench_cmd = Command::new(cmd.get(0).unwrap());
-    bench_cmd.args(&cmd[1..]);
+#[test]
+fn perf_expected_exit_codes() {
+    let dir = crate::testkit::test_dir("perf-exit-codes");
+    let perf = Perf {
+        program: fake_perf(&dir),
+        ..Perf::new(&dir)
+    };
 
-    let mut results = vec![];
+    let measurement = perf.measure(&sh_command("exit 1", &[0, 1]), 3).unwrap();
+    assert_eq!(measurement.exit_code, Some(1));
+    assert_eq!(measurement.counters["cycles"].value, 1e9);
 
-    for i in 0..repetitions + 1 {
-    let start_cpu = get_cpu_times();
-    let output = bench_cmd.output().unwrap();
-    let user_time = get_cpu_times() - start_cpu;
-        if i != 0 { // Ignore first run as warmup
-        results.push(user_time);
-        }
-    assert!(
-        output.status.success(),
-        "`{:?}` failed with {:?}:\n=== stdout ===\n{}\n\n=== stderr ===\n{}",
-        bench_cmd,
-        output.status,
-        String::from_utf8_lossy(&output.stdout),
-        String::from_utf8_lossy(&output.stderr),
+    let err = perf.measure(&sh_command("exit 2", &[0, 1]), 3).unwrap_err();
+    assert!(err.contains("failed with"), "{err}");
+
+    // perf exits successfully when the command segfaults.
+    let err = perf
+        .measure(&sh_command("kill -SEGV $$", &[0, 1]), 3)
+        .unwrap_err();
+    assert!(err.contains("sh: Segmentation fault"), "{err}");
+
+    // perf itself failing to start is an error whatever the command may exit with.
+    let perf = Perf {
+        program: dir.join("does-not-exist"),
+        ..Perf::new(&dir)
+    };
+    let err = perf.measure(&sh_command("exit 0", &[0]), 3).unwrap_err();
+    assert!(err.contains("failed to run"), "{err}");
+}
+
+#[test]
+fn perf_missing_required_counters() {
+    let dir = crate::testkit::test_dir("perf-required-counters");
+    let perf = Perf {
+        program: fake_perf(&dir),
+        events: PerfEvent::parse_list("task-clock,cycles,instuctions"),
+        ..Perf::new(&dir)
+    };
+    // perf and the command share stderr.
+    let cmd = sh_command(r#"echo "event syntax error: 'instuctions'" >&2"#, &[0]);
+    let measurement = perf.measure(&cmd, 3).unwrap();
+    assert_eq!(measurement.counters["cycles"].value, 1e9);
+    assert_eq!(
+        measurement.error.as_deref(),
+        Some(
+            "perf reported no data for events: instuctions — check event names\n\
+             === perf stderr ===\n\
+             event syntax error: 'instuctions'"
+        )
     );
+    let backends: Vec<Box<dyn Backend>> = vec![Box::new(perf.clone())];
+    let bench = bench_single_cmd(cmd.clone(), 3, &backends, None).unwrap();
+    assert_eq!(bench.error, measurement.error);
+
+    // Only the configured counters are required.
+    let perf = Perf {
+        required_counters: Some(vec!["cycles".to_owned()]),
+        ..perf
+    };
+    assert_eq!(perf.measure(&cmd, 3).unwrap().error, None);
+    let perf = Perf {
+        required_counters: Some(vec![]),
+        ..perf
+    };
+    assert_eq!(perf.measure(&cmd, 3).unwrap().error, None);
+}
+
+/// Stands in for perf without running anything: records the command lines, writes `output` to
+/// the file given with `-o`, and exits with `status` and `stderr`.
+#[cfg(test)]
+struct CannedPerf {
+    output: &'static [u8],
+    status: i32,
+    stderr: &'static [u8],
+    calls: std::cell::RefCell<Vec<(Vec<String>, Option<String>)>>,
+}
+
+#[cfg(test)]
+impl CannedPerf {
+    fn new(output: &'static [u8], status: i32, stderr: &'static [u8]) -> Rc<Self> {
+        Rc::new(CannedPerf {
+            output,
+            status,
+            stderr,
+            calls: Default::default(),
+        })
     }
+}
 
-    let avg_time_ms = results
-        .iter()
-        .map(|time| time.as_secs_f64() * 1000.0)
-        .sum::<f64>()
-        / results.len() as f64;
+#[cfg(test)]
+impl CommandRunner for CannedPerf {
+    fn run(&self, cmd: &mut Command) -> std::io::Result<Output> {
+        use std::os::unix::process::ExitStatusExt;
 
-    let variance = results
-        .iter()
-        .map(|time| (time.as_secs_f64() * 1000.0 - avg_time_ms).powi(2))
-        .sum::<f64>()
-        / results.len() as f64;
+        let args = cmd
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        let lang = cmd
+            .get_envs()
+            .find(|(name, _)| *name == "LANG")
+            .and_then(|(_, value)| Some(value?.to_string_lossy().into_owned()));
+        let out = args.iter().position(|arg| arg == "-o").unwrap() + 1;
+        fs::write(&args[out], self.output)?;
+        self.calls.borrow_mut().push((args, lang));
+        Ok(Output {
+            status: std::process::ExitStatus::from_raw(self.status << 8),
+            stdout: vec![],
+            stderr: self.stderr.to_vec(),
+        })
+    }
+}
+
+/// Fails like a missing perf binary.
+#[cfg(test)]
+struct MissingPerf;
+
+#[cfg(test)]
+impl CommandRunner for MissingPerf {
+    fn run(&self, _: &mut Command) -> std::io::Result<Output> {
+        Err(std::io::ErrorKind::NotFound.into())
+    }
+}
+
+#[test]
+fn perf_command_line() {
+    let dir = crate::testkit::test_dir("perf-command-line");
+    let runner = CannedPerf::new(PERF_STAT_OUTPUT, 0, b"");
+    let perf = Perf {
+        runner: runner.clone(),
+        ..Perf::new(&dir)
+    };
+    let backends: Vec<Box<dyn Backend>> = vec![Box::new(perf)];
+
+    let bench = bench_single_cmd(
+        CommandSpec::new(vec!["./bench".to_owned(), "--fast".to_owned()]),
+        7,
+        &backends,
+        None,
+    )
+    .unwrap();
+    let calls = runner.calls.borrow();
+    let (args, lang) = &calls[0];
+    assert_eq!(calls.len(), 1);
+    assert_eq!(
+        args[..6],
+        [
+            "stat",
+            "-j",
+            "-e",
+            "task-clock,cycles,instructions",
+            "--repeat",
+            "7"
+        ]
+    );
+    assert_eq!(args[6], "-o");
+    assert_eq!(args[8..], ["--", "./bench", "--fast"]);
+    assert_eq!(lang.as_deref(), Some("C"));
+    // The output file is removed once parsed.
+    assert!(!Path::new(&args[7]).exists());
+
+    // The canned output has instructions `<not counted>`.
+    assert_eq!(
+        bench.error.as_deref(),
+        Some("perf reported no data for events: instructions — check event names")
+    );
+    assert_eq!(bench.counters["task-clock"].value, 254.21);
+    assert_eq!(bench.counters["task-clock"].unit, "msec");
+    assert_eq!(bench.counters["task-clock"].repetitions, 7);
+    assert_eq!(bench.counters["cycles"].value, 1e9);
+    assert!(!bench.counters.contains_key("instructions"));
+}
 
-    SingleBench {
-        cmd,
-        counters: BTreeMap::from_iter([(
-            "user-time".to_owned(),
+#[test]
+fn perf_runner_failures() {
+    let dir = crate::testkit::test_dir("perf-runner-failures");
+    let perf = |runner: Rc<dyn CommandRunner>| Perf {
+        runner,
+        required_counters: Some(vec!["task-clock".to_owned(), "cycles".to_owned()]),
+        ..Perf::new(&dir)
+    };
+    let cmd = CommandSpec::new(vec!["./bench".to_owned()]);
+
+    let err = perf(CannedPerf::new(PERF_STAT_OUTPUT, 1, b"oops"))
+        .measure(&cmd, 3)
+        .unwrap_err();
+    assert!(err.contains("failed with"), "{err}");
+    assert!(err.contains("oops"), "{err}");
+
+    let err = perf(Rc::new(MissingPerf)).measure(&cmd, 3).unwrap_err();
+    assert!(err.starts_with("failed to run perf: "), "{err}");
+
+    // The output of the command on stderr doesn't matter, perf writes to a file of its own.
+    let measurement = perf(CannedPerf::new(
+        PERF_STAT_OUTPUT,
+        0,
+        b"{\"counter-value\" : \"1\", \"event\" : \"cycles\"}\n\xff garbage\n",
+    ))
+    .measure(&cmd, 3)
+    .unwrap();
+    assert_eq!(measurement.error, None);
+    assert_eq!(measurement.counters["cycles"].value, 1e9);
+
+    let measurement = perf(CannedPerf::new(b"not json\n", 0, b""))
+        .measure(&cmd, 3)
+        .unwrap();
+    assert!(measurement.counters.is_empty());
+    assert_eq!(
+        measurement.error.as_deref(),
+        Some("perf reported no data for events: task-clock, cycles — check event names")
+    );
+
+    let measurement = perf(CannedPerf::new(
+        b"{\"counter-value\" : \"5.000000\", \"unit\" : \"\", \"event\" : \"cycles\", \"variance\" : 0.10}\n",
+        0,
+        b"",
+    ))
+    .measure(&cmd, 3)
+    .unwrap();
+    assert_eq!(measurement.counters["cycles"].value, 5.0);
+    assert_eq!(
+        measurement.error.as_deref(),
+        Some("perf reported no data for events: task-clock — check event names")
+    );
+}
+
+#[test]
+fn default_backend_per_os() {
+    let dir = crate::testkit::test_dir("default-backend");
+    assert_eq!(default_backend_on("linux", Perf::new(&dir)).name(), "perf");
+    assert_eq!(
+        default_backend_on("macos", Perf::new(&dir)).name(),
+        "getrusage"
+    );
+    assert_eq!(
+        default_backend_on("freebsd", Perf::new(&dir)).name(),
+        "getrusage"
+    );
+}
+
+#[test]
+fn getrusage_expected_exit_codes() {
+    let measurement = Getrusage
+        .measure(&sh_command("exit 1", &[0, 1]), 2)
+        .unwrap();
+    assert_eq!(measurement.exit_code, Some(1));
+    assert!(measurement.counters.contains_key("user-time"));
+    assert_eq!(measurement.counters["user-time"].repetitions, 2);
+
+    assert_eq!(
+        Getrusage
+            .measure(&sh_command("exit 0", &[0]), 2)
+            .unwrap()
+            .exit_code,
+        Some(0)
+    );
+
+    let err = Getrusage
+        .measure(&sh_command("exit 2", &[0, 1]), 2)
+        .unwrap_err();
+    assert!(err.contains("failed with"), "{err}");
+
+    // Signals are never expected, not even with exit code 128 + signal.
+    let err = Getrusage
+        .measure(&sh_command("kill -SEGV $$", &[0, 139]), 2)
+        .unwrap_err();
+    assert!(err.contains("failed with"), "{err}");
+}
+
+#[test]
+fn getrusage_repetitions() {
+    let dir = crate::testkit::test_dir("getrusage-repetitions");
+    let log = dir.join("runs");
+    let script = format!("echo run >> {}; sleep 0.01", log.display());
+    let measurement = Getrusage.measure(&sh_command(&script, &[0]), 4).unwrap();
+
+    // One warmup run, then every repetition separately.
+    assert_eq!(std::fs::read_to_string(&log).unwrap().lines().count(), 5);
+    assert_eq!(
+        measurement
+            .counters
+            .keys()
+            .filter(|counter| !rusage::is_cold_or_warm(counter))
+            .collect::<Vec<_>>(),
+        ["max-rss", "system-time", "user-time", "wall-time"]
+    );
+    for (name, counter) in &measurement.counters {
+        let repetitions = match name {
+            name if name.ends_with(rusage::COLD_SUFFIX) => 1,
+            name if name.ends_with(rusage::WARM_SUFFIX) => 3,
+            _ => 4,
+        };
+        assert_eq!(counter.repetitions, repetitions, "{name}");
+    }
+    let wall_time = &measurement.counters["wall-time"];
+    assert!(wall_time.value >= 10.0, "{wall_time:?}");
+    assert!(wall_time.variance > 0.0, "{wall_time:?}");
+    assert!(measurement.counters["max-rss"].value > 0.0);
+}
+
+#[test]
+fn aggregate_single_runs() {
+    let run = |cycles: f64| Measurement {
+        counters: BTreeMap::from([(
+            "cycles".to_owned(),
             BenchCounter {
-                value: avg_time_ms,
-                unit: "msec".to_owned(),
-                repetitions,
-                variance,
+                value: cycles,
+                variance: 0.0,
+                repetitions: 1,
+                unit: String::new(),
             },
         )]),
+        ..Measurement::default()
+    };
+    let runs = [run(10.0), run(14.0), run(12.0), run(16.0)];
+
+    // The sample variance by default, the variance of the mean like perf reports it for perf.
+    let counters = FakeBackend("fake", &[]).aggregate(&runs);
+    assert_eq!(counters["cycles"].value, 13.0);
+    assert_eq!(counters["cycles"].variance, 20.0 / 3.0);
+    assert_eq!(counters["cycles"].repetitions, 4);
+    let counters = Perf::new(Path::new(".")).aggregate(&runs);
+    assert_eq!(counters["cycles"].variance, 20.0 / 3.0 / 4.0);
+
+    // The runs of getrusage are aggregated like those of a single measurement.
+    let dir = crate::testkit::test_dir("getrusage-single-runs");
+    let log = dir.join("runs");
+    let cmd = sh_command(&format!("echo run >> {}", log.display()), &[0]);
+    let runs = (0..3)
+        .map(|_| Getrusage.measure_once(&cmd).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(std::fs::read_to_string(&log).unwrap().lines().count(), 3);
+    let counters = Getrusage.aggregate(&runs);
+    assert_eq!(counters["wall-time"].repetitions, 3);
+    assert_eq!(
+        counters["wall-time-cold"].value,
+        runs[0].counters["wall-time"].value
+    );
+    assert_eq!(counters["wall-time-warm"].repetitions, 2);
+}
+
+fn bench_single_cmd_getrusage(
+    cmd: &CommandSpec,
+    warmup_runs: u32,
+    repetitions: u32,
+) -> Result<Measurement, String> {
+    let Some((program, args)) = cmd.argv.split_first() else {
+        return Err("empty command".to_owned());
+    };
+    // With a wrapper, the resources used by the wrapper count too.
+    let mut bench_cmd = cmd.command(program);
+    bench_cmd.args(args);
+
+    let mut runs = vec![];
+    let mut exit_code = None;
+
+    for i in 0..warmup_runs + repetitions {
+        let (output, usage) =
+            rusage::run(&mut bench_cmd).map_err(|e| format!("failed to run `{program}`: {e}"))?;
+        if i >= warmup_runs {
+            runs.push(usage);
+        }
+        exit_code = Some(
+            cmd.expected_exit_code(output.status)
+                .ok_or_else(|| command_failed(&bench_cmd, &output))?,
+        );
+    }
+
+    Ok(Measurement {
+        counters: rusage::counters(&runs),
+        exit_code,
+        perf_output: None,
+        runs,
+        error: None,
+    })
+}
+
+/// The mean and the sample variance.
+pub fn mean_and_variance(samples: &[f64]) -> (f64, f64) {
+    let n = samples.len() as f64;
+    let mean = samples.iter().sum::<f64>() / n;
+    if samples.len() < 2 {
+        return (mean, 0.0);
     }
+    let variance = samples
+        .iter()
+        .map(|sample| (sample - mean).powi(2))
+        .sum::<f64>()
+        / (n - 1.0);
+    (mean, variance)
 }
 
-// Gets either the T or Z score for 95% confidence for a two-tailed distribution.
+/// The significance level of a two-tailed test at 95% confidence.
+const ALPHA_95: f64 = 0.05;
+
+// Gets the T score for 95% confidence for a two-tailed distribution.
 fn get_stat_score_95(df: u32) -> f64 {
-    let dfv: usize = df as usize;
-    if dfv <= 30 {
-        return T_TABLE95_1TO30[dfv - 1];
-    } else if dfv <= 120 {
-        let idx_10s = dfv / 10;
-        return T_TABLE95_10S_10TO120[idx_10s - 1];
+    t_critical(df as f64, ALPHA_95)
+}
+
+/// The critical value of the F distribution for a two-tailed test at 95% confidence, i.e. its
+/// 97.5th percentile, with `df1` degrees of freedom in the numerator and `df2` in the
+/// denominator.
+///
+/// Degrees of freedom that aren't in the table are rounded down to the nearest one that is,
+/// which gives a larger critical value: fewer changes are significant, never more.
+fn get_f_score_95(df1: u32, df2: u32) -> f64 {
+    let index = |df: u32| {
+        F_TABLE_DFS
+            .iter()
+            .rposition(|&table_df| table_df <= df)
+            .expect("at least one degree of freedom")
+    };
+    F_TABLE975[index(df1)][index(df2)]
+}
+
+/// The probability of a t-statistic at least as large as `t` in either direction, with `df`
+/// degrees of freedom, which may be fractional as with Welch's test. This is `I_x(df/2, 1/2)`
+/// with `x = df / (df + t²)`, where `I` is the regularized incomplete beta function.
+pub fn t_p_value(t: f64, df: f64) -> f64 {
+    if t.is_nan() || df.is_nan() || df <= 0.0 {
+        // No variance and no change, or nothing to estimate the variance from.
+        return 1.0;
+    }
+    regularized_incomplete_beta(df / (df + t * t), df / 2.0, 0.5)
+}
+
+/// The critical value of the t-distribution with `df` degrees of freedom for a two-tailed test
+/// at the significance level `two_tailed_alpha`: the `t` whose [`t_p_value`] is the level.
+///
+/// Without degrees of freedom, e.g. for two `-cold` counters of a single run each, no
+/// difference is significant.
+pub fn t_critical(df: f64, two_tailed_alpha: f64) -> f64 {
+    if df.is_nan() || df <= 0.0 || two_tailed_alpha <= 0.0 {
+        return f64::INFINITY;
+    }
+    if two_tailed_alpha >= 1.0 {
+        return 0.0;
+    }
+
+    // The p-value falls as `t` grows, so bracket the critical value and bisect. Even with a
+    // single degree of freedom and a level of 0.001 the bracket is found in a dozen steps.
+    let (mut low, mut high) = (0.0, 1.0);
+    while t_p_value(high, df) > two_tailed_alpha {
+        low = high;
+        high *= 2.0;
+    }
+    for _ in 0..100 {
+        let middle = (low + high) / 2.0;
+        if middle <= low || middle >= high {
+            break;
+        }
+        if t_p_value(middle, df) > two_tailed_alpha {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    (low + high) / 2.0
+}
+
+fn regularized_incomplete_beta(x: f64, a: f64, b: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+
+    let front =
+        (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+    // The continued fraction converges quickly on this side of the mean, use the symmetry
+    // I_x(a, b) = 1 - I_{1-x}(b, a) on the other.
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_continued_fraction(x, a, b) / a
+    } else {
+        1.0 - front * beta_continued_fraction(1.0 - x, b, a) / b
+    }
+}
+
+/// The continued fraction of the incomplete beta function, evaluated with the modified Lentz
+/// method.
+fn beta_continued_fraction(x: f64, a: f64, b: f64) -> f64 {
+    const TINY: f64 = 1e-300;
+
+    let mut c = 1.0;
+    let mut d = 1.0 - (a + b) * x / (a + 1.0);
+    if d.abs() < TINY {
+        d = TINY;
+    }
+    d = 1.0 / d;
+    let mut h = d;
+
+    for m in 1..=300 {
+        let m = m as f64;
+        let m2 = 2.0 * m;
+
+        // The even step.
+        let aa = m * (b - m) * x / ((a + m2 - 1.0) * (a + m2));
+        d = 1.0 + aa * d;
+        if d.abs() < TINY {
+            d = TINY;
+        }
+        c = 1.0 + aa / c;
+        if c.abs() < TINY {
+            c = TINY;
+        }
+        d = 1.0 / d;
+        h *= d * c;
+
+        // The odd step.
+        let aa = -(a + m) * (a + b + m) * x / ((a + m2) * (a + m2 + 1.0));
+        d = 1.0 + aa * d;
+        if d.abs() < TINY {
+            d = TINY;
+        }
+        c = 1.0 + aa / c;
+        if c.abs() < TINY {
+            c = TINY;
+        }
+        d = 1.0 / d;
+        let delta = d * c;
+        h *= delta;
+
+        if (delta - 1.0).abs() < 1e-15 {
+            break;
+        }
     }
 
-    return 1.96;
+    h
 }
 
-const T_TABLE95_1TO30: [f64; 30] = [
-    12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.16,
-    2.145, 2.131, 2.12, 2.11, 2.101, 2.093, 2.086, 2.08, 2.074, 2.069, 2.064, 2.06, 2.056, 2.052,
-    2.045, 2.048, 2.042,
+/// The natural logarithm of the gamma function, with the Lanczos approximation.
+fn ln_gamma(x: f64) -> f64 {
+    const G: f64 = 7.0;
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+
+    let x = x - 1.0;
+    let mut sum = COEFFICIENTS[0];
+    for (i, coefficient) in COEFFICIENTS.iter().enumerate().skip(1) {
+        sum += coefficient / (x + i as f64);
+    }
+    let t = x + G + 0.5;
+
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+/// The degrees of freedom of the rows and columns of [`F_TABLE975`]: all of them up to ten,
+/// then those of commonly configured repetitions, like 19 for the default of 20.
+const F_TABLE_DFS: [u32; 19] = [
+    1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 12, 15, 19, 24, 29, 39, 49, 99, 120,
 ];
 
-const T_TABLE95_10S_10TO120: [f64; 12] = [
-    2.228, 2.086, 2.042, 2.021, 2.009, 2.0, 1.994, 1.99, 1.987, 1.984, 1.982, 1.98,
+/// The 97.5th percentile of the F distribution, by the degrees of freedom of the numerator
+/// (row) and the denominator (column).
+#[allow(clippy::approx_constant)] // (9, 10) is 3.140, which is not meant to be π
+const F_TABLE975: [[f64; 19]; 19] = [
+    [
+        647.789, 38.506, 17.443, 12.218, 10.007, 8.813, 8.073, 7.571, 7.209, 6.937, 6.554, 6.200,
+        5.922, 5.717, 5.588, 5.435, 5.347, 5.180, 5.152,
+    ],
+    [
+        799.500, 39.000, 16.044, 10.649, 8.434, 7.260, 6.542, 6.059, 5.715, 5.456, 5.096, 4.765,
+        4.508, 4.319, 4.201, 4.061, 3.981, 3.830, 3.805,
+    ],
+    [
+        864.163, 39.165, 15.439, 9.979, 7.764, 6.599, 5.890, 5.416, 5.078, 4.826, 4.474, 4.153,
+        3.903, 3.721, 3.607, 3.473, 3.396, 3.251, 3.227,
+    ],
+    [
+        899.583, 39.248, 15.101, 9.605, 7.388, 6.227, 5.523, 5.053, 4.718, 4.468, 4.121, 3.804,
+        3.559, 3.379, 3.267, 3.135, 3.060, 2.918, 2.894,
+    ],
+    [
+        921.848, 39.298, 14.885, 9.364, 7.146, 5.988, 5.285, 4.817, 4.484, 4.236, 3.891, 3.576,
+        3.333, 3.155, 3.044, 2.913, 2.838, 2.697, 2.674,
+    ],
+    [
+        937.111, 39.331, 14.735, 9.197, 6.978, 5.820, 5.119, 4.652, 4.320, 4.072, 3.728, 3.415,
+        3.172, 2.995, 2.884, 2.754, 2.679, 2.539, 2.515,
+    ],
+    [
+        948.217, 39.355, 14.624, 9.074, 6.853, 5.695, 4.995, 4.529, 4.197, 3.950, 3.607, 3.293,
+        3.051, 2.874, 2.763, 2.633, 2.559, 2.418, 2.395,
+    ],
+    [
+        956.656, 39.373, 14.540, 8.980, 6.757, 5.600, 4.899, 4.433, 4.102, 3.855, 3.512, 3.199,
+        2.956, 2.779, 2.669, 2.538, 2.464, 2.323, 2.299,
+    ],
+    [
+        963.285, 39.387, 14.473, 8.905, 6.681, 5.523, 4.823, 4.357, 4.026, 3.779, 3.436, 3.123,
+        2.880, 2.703, 2.592, 2.461, 2.387, 2.245, 2.222,
+    ],
+    [
+        968.627, 39.398, 14.419, 8.844, 6.619, 5.461, 4.761, 4.295, 3.964, 3.717, 3.374, 3.060,
+        2.817, 2.640, 2.529, 2.397, 2.323, 2.181, 2.157,
+    ],
+    [
+        976.708, 39.415, 14.337, 8.751, 6.525, 5.366, 4.666, 4.200, 3.868, 3.621, 3.277, 2.963,
+        2.720, 2.541, 2.430, 2.298, 2.222, 2.079, 2.055,
+    ],
+    [
+        984.867, 39.431, 14.253, 8.657, 6.428, 5.269, 4.568, 4.101, 3.769, 3.522, 3.177, 2.862,
+        2.617, 2.437, 2.325, 2.191, 2.115, 1.969, 1.945,
+    ],
+    [
+        991.797, 39.445, 14.181, 8.575, 6.344, 5.184, 4.483, 4.016, 3.683, 3.435, 3.090, 2.773,
+        2.526, 2.345, 2.231, 2.096, 2.018, 1.870, 1.845,
+    ],
+    [
+        997.249, 39.456, 14.124, 8.511, 6.278, 5.117, 4.415, 3.947, 3.614, 3.365, 3.019, 2.701,
+        2.452, 2.269, 2.154, 2.017, 1.937, 1.785, 1.760,
+    ],
+    [
+        1000.839, 39.463, 14.087, 8.468, 6.234, 5.072, 4.370, 3.901, 3.568, 3.319, 2.971, 2.652,
+        2.402, 2.217, 2.101, 1.962, 1.881, 1.726, 1.700,
+    ],
+    [
+        1005.276, 39.472, 14.040, 8.415, 6.179, 5.017, 4.313, 3.844, 3.510, 3.260, 2.911, 2.590,
+        2.338, 2.151, 2.033, 1.891, 1.808, 1.648, 1.620,
+    ],
+    [
+        1007.911, 39.477, 14.012, 8.383, 6.146, 4.983, 4.279, 3.809, 3.475, 3.224, 2.874, 2.552,
+        2.298, 2.110, 1.990, 1.846, 1.762, 1.597, 1.569,
+    ],
+    [
+        1013.124, 39.488, 13.957, 8.320, 6.081, 4.916, 4.211, 3.740, 3.404, 3.152, 2.800, 2.475,
+        2.218, 2.025, 1.902, 1.752, 1.664, 1.486, 1.455,
+    ],
+    [
+        1014.020, 39.490, 13.947, 8.309, 6.069, 4.904, 4.199, 3.728, 3.392, 3.140, 2.787, 2.461,
+        2.203, 2.010, 1.886, 1.735, 1.646, 1.465, 1.433,
+    ],
 ];
+
+#[test]
+fn p_values() {
+    let close = |t: f64, df: u32, expected: f64| {
+        let p = t_p_value(t, df as f64);
+        assert!(
+            (p - expected).abs() < 1e-4,
+            "p({t}, {df}) = {p}, expected {expected}"
+        );
+    };
+
+    close(0.0, 10, 1.0);
+    close(f64::INFINITY, 10, 0.0);
+    close(f64::NAN, 10, 1.0);
+    // Critical values from t tables.
+    close(12.706, 1, 0.05);
+    close(2.086, 20, 0.05);
+    close(2.845, 20, 0.01);
+    close(3.850, 20, 0.001);
+    close(1.960, 100_000, 0.05);
+    // The cauchy distribution for a single degree of freedom: 1 - 2 atan(t) / pi.
+    close(1.0, 1, 0.5);
+
+    // The critical values are where the p-value is the level.
+    for df in 1..=30 {
+        let p = t_p_value(get_stat_score_95(df), df as f64);
+        assert!((p - 0.05).abs() < 1e-9, "df {df}: {p}");
+    }
+}
+
+#[test]
+fn t_critical_values() {
+    let close = |df: f64, alpha: f64, expected: f64, tolerance: f64| {
+        let t = t_critical(df, alpha);
+        assert!(
+            (t - expected).abs() < tolerance,
+            "t({df}, {alpha}) = {t}, expected {expected}"
+        );
+    };
+
+    // The lookup tables this replaced, at 95%. They had the values of 28 and 29 degrees of
+    // freedom swapped, 2.045 and 2.048.
+    let table_1_to_30 = [
+        12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.16,
+        2.145, 2.131, 2.12, 2.11, 2.101, 2.093, 2.086, 2.08, 2.074, 2.069, 2.064, 2.06, 2.056,
+        2.052, 2.048, 2.045, 2.042,
+    ];
+    for (df, expected) in (1..).zip(table_1_to_30) {
+        close(df as f64, 0.05, expected, 5e-4);
+    }
+    let table_10s_10_to_120 = [
+        2.228, 2.086, 2.042, 2.021, 2.009, 2.0, 1.994, 1.99, 1.987, 1.984, 1.982, 1.98,
+    ];
+    for (df, expected) in (1..).map(|tens| tens * 10).zip(table_10s_10_to_120) {
+        close(df as f64, 0.05, expected, 5e-4);
+    }
+
+    // To four decimals, from published tables, at other levels and up to 1000 degrees of
+    // freedom.
+    close(1.0, 0.001, 636.6192, 1e-4);
+    close(5.0, 0.001, 6.8688, 1e-4);
+    close(10.0, 0.01, 3.1693, 1e-4);
+    close(19.0, 0.1, 1.7291, 1e-4);
+    close(38.0, 0.05, 2.0244, 1e-4);
+    close(1000.0, 0.1, 1.6464, 1e-4);
+    close(1000.0, 0.05, 1.9623, 1e-4);
+    close(1000.0, 0.001, 3.3003, 1e-4);
+
+    // Closed forms: the Cauchy distribution for one degree of freedom, and
+    // `t = (1 - p) sqrt(2 / (1 - (1 - p)²))` for two.
+    for alpha in [0.1, 0.05, 0.01, 0.005, 0.001] {
+        let cauchy = (std::f64::consts::FRAC_PI_2 * (1.0 - alpha)).tan();
+        close(1.0, alpha, cauchy, cauchy * 1e-9);
+        let two = (1.0 - alpha) * (2.0 / (1.0 - (1.0 - alpha) * (1.0 - alpha))).sqrt();
+        close(2.0, alpha, two, 1e-9);
+    }
+
+    // Fractional degrees of freedom, as of Welch's test, lie in between.
+    let (four, five) = (t_critical(4.0, 0.05), t_critical(5.0, 0.05));
+    let between = t_critical(4.5, 0.05);
+    assert!(five < between && between < four, "{five} {between} {four}");
+    assert!((t_p_value(between, 4.5) - 0.05).abs() < 1e-12);
+
+    assert_eq!(t_critical(0.0, 0.05), f64::INFINITY);
+    assert_eq!(t_p_value(3.0, 0.0), 1.0);
+}
+
+#[test]
+fn single_repetitions() {
+    // Like the `-cold` counters, which have a single run each.
+    let cold = |value| BenchCounter {
+        value,
+        variance: 0.0,
+        repetitions: 1,
+        unit: "msec".to_owned(),
+    };
+    assert!(!BenchCounter::is_significant(&cold(30.0), &cold(40.0)));
+    assert_eq!(BenchCounter::p_value(&cold(30.0), &cold(40.0)), 1.0);
+}
+
+#[test]
+fn sample_statistics() {
+    assert_eq!(mean_and_variance(&[2.0, 4.0, 6.0, 8.0]), (5.0, 20.0 / 3.0));
+    assert_eq!(mean_and_variance(&[3.0, 3.0, 3.0]), (3.0, 0.0));
+    assert_eq!(mean_and_variance(&[7.0]), (7.0, 0.0));
+}
+
+#[test]
+fn f_scores() {
+    // scipy.stats.f.ppf(0.975, df1, df2)
+    assert_eq!(get_f_score_95(1, 1), 647.789);
+    assert_eq!(get_f_score_95(10, 10), 3.717);
+    assert_eq!(get_f_score_95(19, 19), 2.526);
+    assert_eq!(get_f_score_95(9, 19), 2.880);
+    assert_eq!(get_f_score_95(19, 9), 3.683);
+    assert_eq!(get_f_score_95(120, 120), 1.433);
+
+    // Rounded down to the table: 2.464 for (20, 20), and 2.817 for (11, 19).
+    assert_eq!(get_f_score_95(20, 20), 2.526);
+    assert_eq!(get_f_score_95(11, 19), get_f_score_95(10, 19));
+    assert_eq!(get_f_score_95(1000, 500), 1.433);
+}
+
+#[test]
+fn variance_changes() {
+    let counter = |value: f64, variance: f64, repetitions: u32| BenchCounter {
+        value,
+        variance,
+        repetitions,
+        unit: String::new(),
+    };
+
+    assert_eq!(
+        counter(100.0, 25.0, 20).coefficient_of_variation(),
+        Some(0.05)
+    );
+    assert_eq!(counter(0.0, 25.0, 20).coefficient_of_variation(), None);
+
+    // Same mean, four times the variance: twice the spread.
+    let before = counter(100.0, 25.0, 20);
+    let after = counter(100.0, 100.0, 20);
+    assert_eq!(
+        BenchCounter::cov_change_percent(&before, &after),
+        Some(100.0)
+    );
+    assert_eq!(
+        BenchCounter::cov_change_percent(&after, &before),
+        Some(-50.0)
+    );
+    // The same spread around a doubled mean.
+    let doubled = counter(200.0, 100.0, 20);
+    assert_eq!(
+        BenchCounter::cov_change_percent(&before, &doubled),
+        Some(0.0)
+    );
+    // No variance before.
+    let exact = counter(100.0, 0.0, 20);
+    assert_eq!(BenchCounter::cov_change_percent(&exact, &after), None);
+    assert_eq!(BenchCounter::cov_change_percent(&exact, &exact), Some(0.0));
+    assert_eq!(
+        BenchCounter::cov_change_percent(&after, &exact),
+        Some(-100.0)
+    );
+
+    // F = 4 is above 2.526 either way.
+    assert!(BenchCounter::is_variance_significant(&before, &after));
+    assert!(BenchCounter::is_variance_significant(&after, &before));
+    assert!(!BenchCounter::is_variance_significant(
+        &before,
+        &counter(100.0, 50.0, 20)
+    ));
+    assert!(!BenchCounter::is_variance_significant(&exact, &after));
+    assert!(!BenchCounter::is_variance_significant(&exact, &exact));
+    assert!(!BenchCounter::is_variance_significant(
+        &counter(100.0, 25.0, 1),
+        &counter(100.0, 2500.0, 1)
+    ));
+
+    // F = 3 is above 2.880 with (9, 19) degrees of freedom, but not above 3.683 with (19, 9).
+    assert!(BenchCounter::is_variance_significant(
+        &counter(100.0, 1.0, 20),
+        &counter(100.0, 3.0, 10)
+    ));
+    assert!(!BenchCounter::is_variance_significant(
+        &counter(100.0, 1.0, 10),
+        &counter(100.0, 3.0, 20)
+    ));
+}
+
+#[test]
+fn wrapped_command() {
+    let cmd = CommandSpec {
+        // Fails unless the wrapper passes the program and its arguments on.
+        wrapper: vec![
+            "sh".to_owned(),
+            "-c".to_owned(),
+            r#"[ "$1" = "--" ] && shift && exec "$@""#.to_owned(),
+            "sh".to_owned(),
+            "--".to_owned(),
+        ],
+        ..sh_command("exit 3", &[3])
+    };
+
+    assert_eq!(
+        cmd.command("true").get_args().collect::<Vec<_>>(),
+        [
+            "-c",
+            r#"[ "$1" = "--" ] && shift && exec "$@""#,
+            "sh",
+            "--",
+            "true"
+        ]
+    );
+    assert_eq!(Getrusage.measure(&cmd, 1).unwrap().exit_code, Some(3));
+}
@@ -0,0 +1,555 @@
+//! Absolute budgets for a measure of a command, like "decompressing the corpus takes less than
+//! 400 msec on this runner class". Unlike the comparisons, they only look at the current run.
+
+use std::fmt::Write;
+
+use serde::{Deserialize, Serialize};
+
+use crate::command_display::CommandDisplay;
+use crate::units::Quantity;
+use crate::BenchData;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct BudgetConfig {
+    pub group: String,
+    pub command: BudgetCommand,
+    pub measure: String,
+    pub operator: Operator,
+    /// A plain number in `unit`, or a duration or size like `"400ms"` that is converted to the
+    /// unit of the measure.
+    pub limit: Quantity,
+    /// The unit of a plain `limit`, which has to be the unit of the measure. Counts have none.
+    #[serde(default)]
+    pub unit: String,
+    /// Only check the budget on this class of machine, the one it was calibrated on.
+    #[serde(default)]
+    pub machine_class: Option<String>,
+    #[serde(default)]
+    pub severity: Severity,
+}
+
+/// The command of a group a budget is for: its index, or a part of its command line that only
+/// it has.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum BudgetCommand {
+    Index(usize),
+    Match(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Operator {
+    #[serde(rename = "<")]
+    Less,
+    #[serde(rename = "<=")]
+    LessOrEqual,
+    #[serde(rename = ">")]
+    Greater,
+    #[serde(rename = ">=")]
+    GreaterOrEqual,
+}
+
+impl Operator {
+    fn holds(self, value: f64, limit: f64) -> bool {
+        match self {
+            Operator::Less => value < limit,
+            Operator::LessOrEqual => value <= limit,
+            Operator::Greater => value > limit,
+            Operator::GreaterOrEqual => value >= limit,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Operator::Less => "<",
+            Operator::LessOrEqual => "≤",
+            Operator::Greater => ">",
+            Operator::GreaterOrEqual => "≥",
+        }
+    }
+}
+
+/// Whether a broken budget fails the run or is only reported.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Severity {
+    Warn,
+    #[default]
+    Fail,
+}
+
+/// The outcome of a budget, in the run report.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BudgetResult {
+    pub name: String,
+    pub group: String,
+    /// The command line, when the command ran.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    pub measure: String,
+    pub operator: Operator,
+    pub limit: f64,
+    pub unit: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<f64>,
+    pub severity: Severity,
+    pub status: BudgetStatus,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BudgetStatus {
+    Passed,
+    Failed,
+    /// The command ran, but the measure wasn't measured, so the budget can't be checked. This
+    /// counts as a failure.
+    Missing,
+    /// The budget is for another class of machine, or the command didn't run.
+    Skipped,
+}
+
+impl BudgetResult {
+    pub fn is_broken(&self) -> bool {
+        matches!(self.status, BudgetStatus::Failed | BudgetStatus::Missing)
+    }
+
+    /// A broken budget with the `fail` severity.
+    pub fn fails_run(&self) -> bool {
+        self.is_broken() && self.severity == Severity::Fail
+    }
+
+    /// What broke, for the log.
+    pub fn describe(&self) -> String {
+        let command = self.command.as_deref().unwrap_or_default();
+        match (self.status, self.value) {
+            (BudgetStatus::Missing, _) => {
+                format!("{}: `{command}` has no {}", self.name, self.measure)
+            }
+            (_, Some(value)) => format!(
+                "{}: {} of `{command}` is {}, expected {} {}",
+                self.name,
+                self.measure,
+                with_unit(value, &self.unit),
+                self.operator.symbol(),
+                with_unit(self.limit, &self.unit),
+            ),
+            (_, None) => self.name.clone(),
+        }
+    }
+}
+
+impl BudgetConfig {
+    /// Check the budget against the command lines of the commands of the groups, as far as
+    /// the config tells.
+    pub fn validate(&self, name: &str, commands: Option<&[String]>) -> Result<(), String> {
+        let Some(commands) = commands else {
+            return Err(format!(
+                "the budget `{name}` is for the `{}` group, which doesn't exist",
+                self.group
+            ));
+        };
+        match &self.command {
+            BudgetCommand::Index(index) if *index >= commands.len() => {
+                return Err(format!(
+                "the budget `{name}` is for command {index} of the `{}` group, which only has {}",
+                self.group,
+                commands.len()
+            ))
+            }
+            BudgetCommand::Index(_) => {}
+            BudgetCommand::Match(part) => {
+                let matches = commands
+                    .iter()
+                    .filter(|command| command.contains(part.as_str()))
+                    .count();
+                if matches != 1 {
+                    return Err(format!(
+                        "the budget `{name}` is for the command of the `{}` group matching `{part}`, which {matches} commands match",
+                        self.group
+                    ));
+                }
+            }
+        }
+        match self.limit {
+            Quantity::Number(limit) if !limit.is_finite() => {
+                return Err(format!("the limit of the budget `{name}` is not a number"));
+            }
+            Quantity::Duration(_) | Quantity::Bytes(_) if !self.unit.is_empty() => {
+                return Err(format!(
+                    "the limit of the budget `{name}` has a unit, so it can't have a `unit` too"
+                ));
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// The limit and its unit, in `measured_unit` when the measure was measured. Otherwise a
+    /// duration is in `msec` and a size in `KiB`, like the counters of perf and getrusage.
+    fn limit(&self, name: &str, measured_unit: Option<&str>) -> Result<(f64, String), String> {
+        match (self.limit, measured_unit) {
+            (Quantity::Number(_), Some(measured_unit)) if measured_unit != self.unit => {
+                Err(format!(
+                    "the budget `{name}` is in `{}`, but {} is measured in `{measured_unit}`",
+                    self.unit, self.measure
+                ))
+            }
+            (Quantity::Number(limit), _) => Ok((limit, self.unit.clone())),
+            (limit, Some(measured_unit)) => limit
+                .in_unit(measured_unit)
+                .map(|limit| (limit, measured_unit.to_owned()))
+                .ok_or_else(|| {
+                    format!(
+                        "the budget `{name}` is {}, but {} is measured in `{measured_unit}`",
+                        limit.kind(),
+                        self.measure
+                    )
+                }),
+            (limit, None) => {
+                let unit = match limit {
+                    Quantity::Duration(_) => "msec",
+                    _ => "KiB",
+                };
+                Ok((limit.in_unit(unit).unwrap(), unit.to_owned()))
+            }
+        }
+    }
+}
+
+/// Check every budget against the results of the current run. A budget in a different unit
+/// than its measure is a config error.
+pub fn evaluate(
+    budgets: &indexmap::IndexMap<String, BudgetConfig>,
+    data: &BenchData,
+) -> Result<Vec<BudgetResult>, String> {
+    let mut results = vec![];
+    for (name, budget) in budgets {
+        let bench =
+            data.bench_groups
+                .get(&budget.group)
+                .and_then(|benches| match &budget.command {
+                    BudgetCommand::Index(index) => benches.get(*index),
+                    BudgetCommand::Match(part) => benches
+                        .iter()
+                        .find(|bench| bench.cmd.join(" ").contains(part.as_str())),
+                });
+        let other_machine = budget
+            .machine_class
+            .as_ref()
+            .is_some_and(|class| data.machine_class.as_ref() != Some(class));
+        let counter = bench.and_then(|bench| bench.counters.get(&budget.measure));
+
+        let measured_unit = counter
+            .filter(|_| !other_machine)
+            .map(|counter| counter.unit.as_str());
+        let (limit, unit) = budget.limit(name, measured_unit)?;
+
+        let status = match (other_machine, bench, counter) {
+            (true, _, _) | (_, None, _) => BudgetStatus::Skipped,
+            (false, Some(_), None) => BudgetStatus::Missing,
+            (false, Some(_), Some(counter)) if budget.operator.holds(counter.value, limit) => {
+                BudgetStatus::Passed
+            }
+            (false, Some(_), Some(_)) => BudgetStatus::Failed,
+        };
+        results.push(BudgetResult {
+            name: name.clone(),
+            group: budget.group.clone(),
+            command: bench.map(|bench| bench.cmd.join(" ")),
+            measure: budget.measure.clone(),
+            operator: budget.operator,
+            limit,
+            unit,
+            value: counter
+                .filter(|_| status != BudgetStatus::Skipped)
+                .map(|counter| counter.value),
+            severity: budget.severity,
+            status,
+        });
+    }
+    Ok(results)
+}
+
+fn with_unit(value: f64, unit: &str) -> String {
+    if unit.is_empty() {
+        format!("{value}")
+    } else {
+        format!("{value} {unit}")
+    }
+}
+
+/// The pass/fail table of the budgets.
+pub fn render_markdown(md: &mut String, results: &[BudgetResult], commands: CommandDisplay) {
+    if results.is_empty() {
+        return;
+    }
+
+    writeln!(md, "### Budgets\n").unwrap();
+    writeln!(md, "| budget | command | limit | value | |").unwrap();
+    writeln!(md, "| --- | --- | --- | --- | --- |").unwrap();
+    let mut cells = commands.cells();
+    for result in results {
+        let command = match &result.command {
+            Some(command) => cells.cell(command),
+            None => format!("`{}` group", result.group),
+        };
+        let value = match result.value {
+            Some(value) => format!("`{}`", with_unit(value, &result.unit)),
+            None => "n.a.".to_owned(),
+        };
+        let status = match (result.status, result.severity) {
+            (BudgetStatus::Passed, _) => "✅",
+            (BudgetStatus::Failed, Severity::Fail) => "❌",
+            (BudgetStatus::Failed, Severity::Warn) => "⚠️",
+            (BudgetStatus::Missing, Severity::Fail) => "❌ not measured",
+            (BudgetStatus::Missing, Severity::Warn) => "⚠️ not measured",
+            (BudgetStatus::Skipped, _) => "skipped",
+        };
+        writeln!(
+            md,
+            "| {} | {command} | {} `{}` | {value} | {status} |",
+            result.name,
+            result.measure,
+            with_unit_operator(result),
+        )
+        .unwrap();
+    }
+    cells.render_footnotes(md);
+    writeln!(md).unwrap();
+}
+
+fn with_unit_operator(result: &BudgetResult) -> String {
+    format!(
+        "{} {}",
+        result.operator.symbol(),
+        with_unit(result.limit, &result.unit)
+    )
+}
+
+#[cfg(test)]
+fn budgets_for_test(json: &str) -> indexmap::IndexMap<String, BudgetConfig> {
+    serde_json::from_str(json).unwrap()
+}
+
+#[cfg(test)]
+fn data_for_test(machine_class: Option<&str>) -> BenchData {
+    let mut builder = crate::testkit::BenchDataBuilder::new(
+        "1111111111111111111111111111111111111111",
+    )
+    .group("decompress", |g| {
+        g.bench(["./decompress", "small.tar"], |b| {
+            b.counter("task-clock", 120.0, 4.0, 20, "msec").counter(
+                "instructions",
+                5e8,
+                1e6,
+                20,
+                "",
+            )
+        })
+        .bench(["./decompress", "corpus.tar"], |b| {
+            b.counter("task-clock", 412.5, 4.0, 20, "msec")
+        })
+    });
+    if let Some(machine_class) = machine_class {
+        builder = builder.machine_class(machine_class);
+    }
+    builder.build()
+}
+
+#[test]
+fn evaluate_budgets() {
+    let budgets = budgets_for_test(
+        r#"{
+            "small": { "group": "decompress", "command": 0, "measure": "task-clock", "operator": "<", "limit": 150, "unit": "msec" },
+            "corpus": { "group": "decompress", "command": "corpus", "measure": "task-clock", "operator": "<=", "limit": 400, "unit": "msec" },
+            "corpus (warn)": { "group": "decompress", "command": "corpus", "measure": "task-clock", "operator": "<=", "limit": 400, "unit": "msec", "severity": "warn" },
+            "instructions": { "group": "decompress", "command": 0, "measure": "instructions", "operator": ">=", "limit": 1e8 },
+            "not measured": { "group": "decompress", "command": 1, "measure": "instructions", "operator": "<", "limit": 1e9 },
+            "not run": { "group": "compress", "command": 0, "measure": "task-clock", "operator": "<", "limit": 1, "unit": "msec" }
+        }"#,
+    );
+    let results = evaluate(&budgets, &data_for_test(None)).unwrap();
+    let statuses = results
+        .iter()
+        .map(|result| (result.name.as_str(), result.status, result.fails_run()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        statuses,
+        [
+            ("small", BudgetStatus::Passed, false),
+            ("corpus", BudgetStatus::Failed, true),
+            ("corpus (warn)", BudgetStatus::Failed, false),
+            ("instructions", BudgetStatus::Passed, false),
+            ("not measured", BudgetStatus::Missing, true),
+            ("not run", BudgetStatus::Skipped, false),
+        ]
+    );
+    assert_eq!(results[1].value, Some(412.5));
+    assert_eq!(
+        results[1].describe(),
+        "corpus: task-clock of `./decompress corpus.tar` is 412.5 msec, expected ≤ 400 msec"
+    );
+    assert_eq!(
+        results[4].describe(),
+        "not measured: `./decompress corpus.tar` has no instructions"
+    );
+
+    for (operator, value, limit, holds) in [
+        (Operator::Less, 1.0, 1.0, false),
+        (Operator::LessOrEqual, 1.0, 1.0, true),
+        (Operator::Greater, 2.0, 1.0, true),
+        (Operator::GreaterOrEqual, 0.5, 1.0, false),
+    ] {
+        assert_eq!(operator.holds(value, limit), holds, "{operator:?}");
+    }
+
+    let mut md = String::new();
+    render_markdown(&mut md, &results, CommandDisplay::default());
+    assert_eq!(
+        md,
+        "### Budgets\n\n\
+         | budget | command | limit | value | |\n\
+         | --- | --- | --- | --- | --- |\n\
+         | small | `./decompress small.tar` | task-clock `< 150 msec` | `120 msec` | ✅ |\n\
+         | corpus | `./decompress corpus.tar` | task-clock `≤ 400 msec` | `412.5 msec` | ❌ |\n\
+         | corpus (warn) | `./decompress corpus.tar` | task-clock `≤ 400 msec` | `412.5 msec` | ⚠️ |\n\
+         | instructions | `./decompress small.tar` | instructions `≥ 100000000` | `500000000` | ✅ |\n\
+         | not measured | `./decompress corpus.tar` | instructions `< 1000000000` | n.a. | ❌ not measured |\n\
+         | not run | `compress` group | task-clock `< 1 msec` | n.a. | skipped |\n\n"
+    );
+}
+
+#[test]
+fn budget_units() {
+    // The limit of a time without a unit, or in the wrong unit.
+    for unit in ["", r#", "unit": "ms""#] {
+        let budgets = budgets_for_test(&format!(
+            r#"{{ "corpus": {{ "group": "decompress", "command": 1, "measure": "task-clock", "operator": "<", "limit": 400{unit} }} }}"#
+        ));
+        let err = evaluate(&budgets, &data_for_test(None)).unwrap_err();
+        assert!(err.starts_with("the budget `corpus` is in `"), "{err}");
+        assert!(
+            err.ends_with("but task-clock is measured in `msec`"),
+            "{err}"
+        );
+    }
+
+    // A count with a unit.
+    let budgets = budgets_for_test(
+        r#"{ "small": { "group": "decompress", "command": 0, "measure": "instructions", "operator": "<", "limit": 1e9, "unit": "msec" } }"#,
+    );
+    assert_eq!(
+        evaluate(&budgets, &data_for_test(None)).unwrap_err(),
+        "the budget `small` is in `msec`, but instructions is measured in ``"
+    );
+
+    // A limit with a unit is converted to the unit of the measure.
+    let budgets = budgets_for_test(
+        r#"{
+            "small": { "group": "decompress", "command": 0, "measure": "task-clock", "operator": "<", "limit": "0.15s" },
+            "corpus": { "group": "decompress", "command": 1, "measure": "task-clock", "operator": "<", "limit": "400 ms" },
+            "not run": { "group": "compress", "command": 0, "measure": "max-rss", "operator": "<", "limit": "1MiB" }
+        }"#,
+    );
+    let results = evaluate(&budgets, &data_for_test(None)).unwrap();
+    let limits = results
+        .iter()
+        .map(|result| (result.limit, result.unit.as_str(), result.status))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        limits,
+        [
+            (150.0, "msec", BudgetStatus::Passed),
+            (400.0, "msec", BudgetStatus::Failed),
+            (1024.0, "KiB", BudgetStatus::Skipped),
+        ]
+    );
+
+    // But only to a unit of the same kind.
+    for limit in [r#""1GiB""#, r#""2 s""#] {
+        let budgets = budgets_for_test(&format!(
+            r#"{{ "small": {{ "group": "decompress", "command": 0, "measure": "instructions", "operator": "<", "limit": {limit} }} }}"#
+        ));
+        let err = evaluate(&budgets, &data_for_test(None)).unwrap_err();
+        assert!(err.ends_with("but instructions is measured in ``"), "{err}");
+    }
+    let budgets = budgets_for_test(
+        r#"{ "small": { "group": "decompress", "command": 0, "measure": "task-clock", "operator": "<", "limit": "1GiB" } }"#,
+    );
+    assert_eq!(
+        evaluate(&budgets, &data_for_test(None)).unwrap_err(),
+        "the budget `small` is a size, but task-clock is measured in `msec`"
+    );
+}
+
+#[test]
+fn budgets_per_machine_class() {
+    let budgets = budgets_for_test(
+        r#"{
+            "corpus": { "group": "decompress", "command": 1, "measure": "task-clock", "operator": "<", "limit": 400, "unit": "msec", "machine-class": "neoverse-n1/4" },
+            "wrong unit elsewhere": { "group": "decompress", "command": 1, "measure": "task-clock", "operator": "<", "limit": 400, "machine-class": "neoverse-n1/4" }
+        }"#,
+    );
+
+    // Only checked on the class it was calibrated on, where the unit is wrong too.
+    for class in [None, Some("amd-epyc-7763/4")] {
+        let results = evaluate(&budgets, &data_for_test(class)).unwrap();
+        assert!(
+            results
+                .iter()
+                .all(|result| result.status == BudgetStatus::Skipped && result.value.is_none()),
+            "{results:?}"
+        );
+    }
+    assert!(evaluate(&budgets, &data_for_test(Some("neoverse-n1/4"))).is_err());
+
+    let budgets = budgets_for_test(
+        r#"{ "corpus": { "group": "decompress", "command": 1, "measure": "task-clock", "operator": "<", "limit": 400, "unit": "msec", "machine-class": "neoverse-n1/4" } }"#,
+    );
+    let results = evaluate(&budgets, &data_for_test(Some("neoverse-n1/4"))).unwrap();
+    assert_eq!(results[0].status, BudgetStatus::Failed);
+}
+
+#[test]
+fn validate_budgets() {
+    let commands = [
+        "./decompress small.tar".to_owned(),
+        "./decompress corpus.tar".to_owned(),
+    ];
+    let budget = |command: &str| {
+        serde_json::from_str::<BudgetConfig>(&format!(
+            r#"{{ "group": "decompress", "command": {command}, "measure": "task-clock", "operator": "<", "limit": 400, "unit": "msec" }}"#
+        ))
+        .unwrap()
+    };
+
+    assert!(budget("1").validate("b", Some(&commands)).is_ok());
+    let mut with_unit = budget("1");
+    with_unit.limit = Quantity::Duration(std::time::Duration::from_millis(400));
+    assert_eq!(
+        with_unit.validate("b", Some(&commands)).unwrap_err(),
+        "the limit of the budget `b` has a unit, so it can't have a `unit` too"
+    );
+    with_unit.unit.clear();
+    assert!(with_unit.validate("b", Some(&commands)).is_ok());
+    assert!(budget(r#""corpus""#).validate("b", Some(&commands)).is_ok());
+    assert_eq!(
+        budget("2").validate("b", Some(&commands)).unwrap_err(),
+        "the budget `b` is for command 2 of the `decompress` group, which only has 2"
+    );
+    assert_eq!(
+        budget(r#""./decompress""#)
+            .validate("b", Some(&commands))
+            .unwrap_err(),
+        "the budget `b` is for the command of the `decompress` group matching `./decompress`, which 2 commands match"
+    );
+    assert_eq!(
+        budget("0").validate("b", None).unwrap_err(),
+        "the budget `b` is for the `decompress` group, which doesn't exist"
+    );
+    assert!(serde_json::from_str::<BudgetConfig>(
+        r#"{ "group": "g", "command": 0, "measure": "m", "operator": "==", "limit": 1 }"#
+    )
+    .is_err());
+}
@@ -0,0 +1,686 @@
+//! How the benchmarked binaries were built, read from the binaries themselves. A baseline
+//! built by another toolchain, like an older rustc on the runner image before an update, or
+//! with another profile, differs for reasons that have nothing to do with the change.
+//!
+//! Of every configured ELF binary, the `.comment` section tells the compilers that produced it,
+//! like `rustc version 1.78.0 (9b00956e5 2024-04-29)` and the GCC of the C dependencies. The
+//! paths into the standard library, `/rustc/<commit>/library/...`, survive
+//! `--remap-path-prefix` and name the exact rustc commit, also when the `.comment` section was
+//! stripped. Debug assertions and overflow checks are told by the strings and symbols they
+//! bring along: the failed unsafe preconditions that debug assertions check, and the panics on
+//! overflowing arithmetic. These are heuristics: a binary can carry those without the checks
+//! enabled, but then so does its baseline.
+//!
+//! The comparison tables of the commands that run a binary built differently than in the
+//! baseline get a note with the difference. A command runs a binary when one of its arguments
+//! is the path of the binary.
+
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+use crate::bench::SingleBench;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct BuildInfoConfig {
+    /// The binaries to read the build metadata of.
+    pub binaries: Vec<PathBuf>,
+}
+
+impl BuildInfoConfig {
+    /// The build metadata of every binary, by path. Binaries that can't be read are left out
+    /// with a warning.
+    pub fn read(&self) -> IndexMap<String, BuildInfo> {
+        let mut infos = IndexMap::new();
+        for binary in &self.binaries {
+            match read(binary) {
+                Ok(info) => {
+                    infos.insert(binary.display().to_string(), info);
+                }
+                Err(err) => eprintln!(
+                    "warning: not reading the build metadata of {}: {err}",
+                    binary.display()
+                ),
+            }
+        }
+        infos
+    }
+}
+
+/// The build metadata of a binary.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BuildInfo {
+    /// The version of rustc, as in `.comment`, like `1.78.0 (9b00956e5 2024-04-29)`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rustc: Option<String>,
+    /// The commit of rustc, from the paths into its standard library.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rustc_commit: Option<String>,
+    /// The other producers in `.comment`, like `GCC: (GNU) 13.2.0`, sorted.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub compilers: Vec<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub debug_assertions: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub overflow_checks: bool,
+}
+
+/// What only a build with debug assertions has: the messages of the unsafe preconditions of
+/// the standard library, which are checked with debug assertions in the crate that uses them.
+const DEBUG_ASSERTIONS: &[&[u8]] = &[b"unsafe precondition(s) violated"];
+
+/// What only a build with overflow checks has: the panics on overflowing arithmetic, as
+/// symbols since rustc 1.79 and as messages before.
+const OVERFLOW_CHECKS: &[&[u8]] = &[
+    b"panic_const_add_overflow",
+    b"panic_const_sub_overflow",
+    b"panic_const_mul_overflow",
+    b"attempt to add with overflow",
+    b"attempt to subtract with overflow",
+    b"attempt to multiply with overflow",
+];
+
+pub fn read(path: &Path) -> Result<BuildInfo, String> {
+    let bytes = fs::read(path).map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+    parse(&bytes)
+}
+
+/// The build metadata of the ELF file `bytes`.
+pub fn parse(bytes: &[u8]) -> Result<BuildInfo, String> {
+    let elf = Elf::parse(bytes)?;
+
+    let mut info = BuildInfo::default();
+    if let Some(comment) = elf.section(".comment")? {
+        for producer in comment.split(|&byte| byte == 0) {
+            let producer = String::from_utf8_lossy(producer).trim().to_owned();
+            if producer.is_empty() {
+                continue;
+            }
+            match producer.strip_prefix("rustc version ") {
+                Some(version) => info.rustc = Some(version.to_owned()),
+                None if !info.compilers.contains(&producer) => info.compilers.push(producer),
+                None => {}
+            }
+        }
+        info.compilers.sort();
+    }
+    info.rustc_commit = rustc_commit(bytes);
+    info.debug_assertions = DEBUG_ASSERTIONS
+        .iter()
+        .any(|marker| contains(bytes, marker));
+    info.overflow_checks = OVERFLOW_CHECKS.iter().any(|marker| contains(bytes, marker));
+    Ok(info)
+}
+
+fn contains(bytes: &[u8], needle: &[u8]) -> bool {
+    bytes.windows(needle.len()).any(|window| window == needle)
+}
+
+/// The commit of the first path into the standard library, `/rustc/<commit>/`.
+fn rustc_commit(bytes: &[u8]) -> Option<String> {
+    const PREFIX: &[u8] = b"/rustc/";
+    bytes
+        .windows(PREFIX.len() + 41)
+        .filter(|window| window.starts_with(PREFIX) && window[PREFIX.len() + 40] == b'/')
+        .map(|window| &window[PREFIX.len()..PREFIX.len() + 40])
+        .find(|commit| commit.iter().all(u8::is_ascii_hexdigit))
+        .map(|commit| String::from_utf8_lossy(commit).into_owned())
+}
+
+/// Just enough of an ELF file to find its sections by name, of either class and byte order.
+struct Elf<'a> {
+    bytes: &'a [u8],
+    is_64: bool,
+    big_endian: bool,
+}
+
+/// A section header: where the name is in the section header string table, and the data.
+struct SectionHeader {
+    name: usize,
+    kind: u32,
+    offset: usize,
+    size: usize,
+}
+
+/// The type of the sections without data in the file, like `.bss`.
+const SHT_NOBITS: u32 = 8;
+
+impl<'a> Elf<'a> {
+    fn parse(bytes: &'a [u8]) -> Result<Self, String> {
+        if !bytes.starts_with(b"\x7fELF") {
+            return Err("not an ELF file".to_owned());
+        }
+        let is_64 = match bytes.get(4) {
+            Some(1) => false,
+            Some(2) => true,
+            _ => return Err("unknown ELF class".to_owned()),
+        };
+        let big_endian = match bytes.get(5) {
+            Some(1) => false,
+            Some(2) => true,
+            _ => return Err("unknown ELF byte order".to_owned()),
+        };
+        Ok(Elf {
+            bytes,
+            is_64,
+            big_endian,
+        })
+    }
+
+    fn uint(&self, offset: usize, size: usize) -> Result<usize, String> {
+        let bytes = offset
+            .checked_add(size)
+            .and_then(|end| self.bytes.get(offset..end))
+            .ok_or("truncated ELF file")?;
+        let mut value = 0u64;
+        for i in 0..size {
+            let byte = if self.big_endian {
+                bytes[i]
+            } else {
+                bytes[size - 1 - i]
+            };
+            value = value << 8 | u64::from(byte);
+        }
+        usize::try_from(value).map_err(|_| "truncated ELF file".to_owned())
+    }
+
+    /// A word of the class: 4 bytes for 32-bit files, 8 bytes for 64-bit ones.
+    fn word(&self, offset: usize) -> Result<usize, String> {
+        self.uint(offset, if self.is_64 { 8 } else { 4 })
+    }
+
+    fn section_headers(&self) -> Result<Vec<SectionHeader>, String> {
+        let (shoff, shentsize, shnum, shstrndx) = if self.is_64 {
+            (0x28, 0x3a, 0x3c, 0x3e)
+        } else {
+            (0x20, 0x2e, 0x30, 0x32)
+        };
+        let shoff = self.word(shoff)?;
+        let shentsize = self.uint(shentsize, 2)?;
+        if shoff == 0 {
+            return Ok(vec![]);
+        }
+        let header = |index: usize| -> Result<SectionHeader, String> {
+            let start = shoff + index * shentsize;
+            let (offset, size) = if self.is_64 {
+                (0x18, 0x20)
+            } else {
+                (0x10, 0x14)
+            };
+            Ok(SectionHeader {
+                name: self.uint(start, 4)?,
+                kind: self.uint(start + 4, 4)? as u32,
+                offset: self.word(start + offset)?,
+                size: self.word(start + size)?,
+            })
+        };
+        // With too many sections for the header, their number is the size of the first one.
+        let count = match self.uint(shnum, 2)? {
+            0 => header(0)?.size,
+            count => count,
+        };
+        let mut headers = (0..count).map(header).collect::<Result<Vec<_>, _>>()?;
+        // Likewise, the index of the string table is the link of the first one then.
+        let names = match self.uint(shstrndx, 2)? {
+            0xffff => self.uint(shoff + if self.is_64 { 0x28 } else { 0x18 }, 4)?,
+            names => names,
+        };
+        if names >= headers.len() {
+            return Err("truncated ELF file".to_owned());
+        }
+        let names = headers.swap_remove(names);
+        for header in &mut headers {
+            header.name += names.offset;
+        }
+        Ok(headers)
+    }
+
+    /// The data of the first section named `name`.
+    fn section(&self, name: &str) -> Result<Option<&'a [u8]>, String> {
+        for header in self.section_headers()? {
+            let bytes = self.bytes;
+            let name_bytes = bytes.get(header.name..).ok_or("truncated ELF file")?;
+            if name_bytes.split(|&byte| byte == 0).next() != Some(name.as_bytes()) {
+                continue;
+            }
+            if header.kind == SHT_NOBITS {
+                return Ok(Some(&[]));
+            }
+            return header
+                .offset
+                .checked_add(header.size)
+                .and_then(|end| bytes.get(header.offset..end))
+                .map(Some)
+                .ok_or_else(|| "truncated ELF file".to_owned());
+        }
+        Ok(None)
+    }
+}
+
+/// A binary built differently than in the baseline.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BuildDifference {
+    pub binary: String,
+    /// Like `baseline built with rustc 1.76.0, current with rustc 1.78.0`.
+    pub differences: Vec<String>,
+}
+
+/// How `before`, of the baseline, and `after` were built differently.
+pub fn differences(before: &BuildInfo, after: &BuildInfo) -> Vec<String> {
+    let mut differences = vec![];
+    let built =
+        |before: &str, after: &str| format!("baseline built with {before}, current with {after}");
+
+    let version = |rustc: &str| rustc.split(' ').next().unwrap_or(rustc).to_owned();
+    match (
+        &before.rustc,
+        &after.rustc,
+        &before.rustc_commit,
+        &after.rustc_commit,
+    ) {
+        (Some(before), Some(after), _, _) if version(before) != version(after) => {
+            differences.push(built(
+                &format!("rustc {}", version(before)),
+                &format!("rustc {}", version(after)),
+            ))
+        }
+        (Some(before), Some(after), _, _) if before != after => {
+            differences.push(built(&format!("rustc {before}"), &format!("rustc {after}")))
+        }
+        (_, _, Some(before), Some(after)) if before != after => differences.push(built(
+            &format!("rustc {}", &before[..9]),
+            &format!("rustc {}", &after[..9]),
+        )),
+        _ => {}
+    }
+
+    if !before.compilers.is_empty()
+        && !after.compilers.is_empty()
+        && before.compilers != after.compilers
+    {
+        differences.push(built(
+            &before.compilers.join(", "),
+            &after.compilers.join(", "),
+        ));
+    }
+
+    for (name, before, after) in [
+        (
+            "debug assertions",
+            before.debug_assertions,
+            after.debug_assertions,
+        ),
+        (
+            "overflow checks",
+            before.overflow_checks,
+            after.overflow_checks,
+        ),
+    ] {
+        if before != after {
+            let with = |enabled: bool| if enabled { "with" } else { "without" };
+            differences.push(format!(
+                "baseline built {} {name}, current {} them",
+                with(before),
+                with(after)
+            ));
+        }
+    }
+    differences
+}
+
+/// The binaries built differently than in the baseline, of those with build metadata on both
+/// sides.
+pub fn collect(
+    before: &IndexMap<String, BuildInfo>,
+    after: &IndexMap<String, BuildInfo>,
+) -> Vec<BuildDifference> {
+    after
+        .iter()
+        .filter_map(|(binary, after)| {
+            let differences = differences(before.get(binary)?, after);
+            (!differences.is_empty()).then(|| BuildDifference {
+                binary: binary.clone(),
+                differences,
+            })
+        })
+        .collect()
+}
+
+/// Whether `bench` runs `binary`: one of its arguments is its path. Imported commands ran
+/// elsewhere.
+pub fn runs(bench: &SingleBench, binary: &str) -> bool {
+    let normalize = |path: &str| path.trim_start_matches("./").to_owned();
+    bench.imported.is_none()
+        && bench
+            .cmd
+            .iter()
+            .any(|arg| normalize(arg) == normalize(binary))
+}
+
+/// The notes of a table of the commands `benches`: the differences of the binaries they run.
+pub fn notes<'a>(
+    differences: &[BuildDifference],
+    benches: impl IntoIterator<Item = &'a SingleBench> + Clone,
+) -> Vec<String> {
+    differences
+        .iter()
+        .filter(|difference| {
+            benches
+                .clone()
+                .into_iter()
+                .any(|bench| runs(bench, &difference.binary))
+        })
+        .map(|difference| {
+            format!(
+                "`{}`: {}",
+                difference.binary,
+                difference.differences.join("; ")
+            )
+        })
+        .collect()
+}
+
+/// The note above a table whose commands run binaries built differently than in the baseline.
+pub fn render_markdown_note(md: &mut String, notes: &[String]) {
+    if notes.is_empty() {
+        return;
+    }
+    writeln!(
+        md,
+        "> [!NOTE]\n> Built differently than the baseline, which may explain changes:"
+    )
+    .unwrap();
+    for note in notes {
+        writeln!(md, "> - {note}").unwrap();
+    }
+    md.push('\n');
+}
+
+#[cfg(test)]
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("testdata/build-info")
+        .join(name)
+}
+
+/// A minimal ELF file of the class and byte order with the `sections`, with the section
+/// header string table last.
+#[cfg(test)]
+fn elf_for_test(is_64: bool, big_endian: bool, sections: &[(&str, &[u8])]) -> Vec<u8> {
+    let put = |bytes: &mut Vec<u8>, offset: usize, size: usize, value: usize| {
+        let value = (value as u64).to_be_bytes();
+        let value = &value[8 - size..];
+        let range = offset..offset + size;
+        if big_endian {
+            bytes[range].copy_from_slice(value);
+        } else {
+            let mut value = value.to_vec();
+            value.reverse();
+            bytes[range].copy_from_slice(&value);
+        }
+    };
+    let word = if is_64 { 8 } else { 4 };
+    let (header_size, shentsize) = if is_64 { (0x40, 0x40) } else { (0x34, 0x28) };
+
+    let mut names = vec![0u8];
+    let mut data = vec![];
+    let mut headers = vec![(0, 0, 0)];
+    for (name, bytes) in sections {
+        headers.push((names.len(), header_size + data.len(), bytes.len()));
+        names.extend_from_slice(name.as_bytes());
+        names.push(0);
+        data.extend_from_slice(bytes);
+    }
+    headers.push((names.len(), header_size + data.len(), 0));
+    names.extend_from_slice(b".shstrtab\0");
+    let last = headers.len() - 1;
+    headers[last].2 = names.len();
+    data.extend_from_slice(&names);
+
+    let shoff = header_size + data.len();
+    let mut bytes = vec![0; shoff + headers.len() * shentsize];
+    bytes[..4].copy_from_slice(b"\x7fELF");
+    bytes[4] = if is_64 { 2 } else { 1 };
+    bytes[5] = if big_endian { 2 } else { 1 };
+    bytes[header_size..shoff].copy_from_slice(&data);
+    let (e_shoff, e_shentsize) = if is_64 { (0x28, 0x3a) } else { (0x20, 0x2e) };
+    put(&mut bytes, e_shoff, word, shoff);
+    put(&mut bytes, e_shentsize, 2, shentsize);
+    put(&mut bytes, e_shentsize + 2, 2, headers.len());
+    put(&mut bytes, e_shentsize + 4, 2, headers.len() - 1);
+    for (index, (name, offset, size)) in headers.into_iter().enumerate() {
+        let start = shoff + index * shentsize;
+        let (sh_offset, sh_size) = if is_64 { (0x18, 0x20) } else { (0x10, 0x14) };
+        put(&mut bytes, start, 4, name);
+        put(&mut bytes, start + 4, 4, 1);
+        put(&mut bytes, start + sh_offset, word, offset);
+        put(&mut bytes, start + sh_size, word, size);
+    }
+    bytes
+}
+
+#[test]
+fn read_object_files() {
+    let release = read(&fixture("release.o")).unwrap();
+    assert_eq!(
+        release,
+        BuildInfo {
+            rustc: Some("1.95.0 (59807616e 2026-04-14)".to_owned()),
+            rustc_commit: Some("59807616e1fa2540724bfbac14d7976d7e4a3860".to_owned()),
+            compilers: vec![],
+            debug_assertions: false,
+            overflow_checks: false,
+        }
+    );
+    assert_eq!(
+        read(&fixture("debug.o")).unwrap(),
+        BuildInfo {
+            debug_assertions: true,
+            overflow_checks: true,
+            ..release.clone()
+        }
+    );
+    assert_eq!(
+        read(&fixture("release-old.o")).unwrap(),
+        BuildInfo {
+            rustc: Some("1.93.1 (01f6ddf75 2026-02-11)".to_owned()),
+            rustc_commit: Some("01f6ddf7588f42ae2d7eb0a2f21d44e8e96674cf".to_owned()),
+            ..release
+        }
+    );
+    let reference = read(&fixture("ref.o")).unwrap();
+    assert_eq!(reference.rustc, None);
+    assert_eq!(reference.compilers.len(), 1);
+    assert!(reference.compilers[0].starts_with("GCC: "), "{reference:?}");
+
+    assert_eq!(read(&fixture("bench.rs")).unwrap_err(), "not an ELF file");
+    // Cut off in the section headers, which start at byte 1056.
+    let truncated = fs::read(fixture("release.o")).unwrap();
+    assert_eq!(parse(&truncated[..1200]).unwrap_err(), "truncated ELF file");
+}
+
+#[test]
+fn parse_elf_sections() {
+    let comment: &[u8] =
+        b"\0GCC: (GNU) 13.2.0\0rustc version 1.78.0 (9b00956e5 2024-04-29)\0GCC: (GNU) 13.2.0\0";
+    for is_64 in [false, true] {
+        for big_endian in [false, true] {
+            let bytes = elf_for_test(
+                is_64,
+                big_endian,
+                &[
+                    (".text", b"attempt to add with overflow"),
+                    (".comment", comment),
+                ],
+            );
+            let info = parse(&bytes).unwrap();
+            assert_eq!(
+                info,
+                BuildInfo {
+                    rustc: Some("1.78.0 (9b00956e5 2024-04-29)".to_owned()),
+                    rustc_commit: None,
+                    compilers: vec!["GCC: (GNU) 13.2.0".to_owned()],
+                    debug_assertions: false,
+                    overflow_checks: true,
+                },
+                "64-bit: {is_64}, big endian: {big_endian}"
+            );
+            let elf = Elf::parse(&bytes).unwrap();
+            assert_eq!(
+                elf.section(".text").unwrap(),
+                Some(&b"attempt to add with overflow"[..])
+            );
+            assert_eq!(elf.section(".data").unwrap(), None);
+        }
+    }
+
+    // Without a `.comment`, the commit is still in the paths into the standard library.
+    let bytes = elf_for_test(
+        true,
+        false,
+        &[(
+            ".rodata",
+            b"/rustc/9b00956e56009bab2aa15d7bff10916599e3d6d6/library/core/src/str/mod.rs",
+        )],
+    );
+    assert_eq!(
+        parse(&bytes).unwrap(),
+        BuildInfo {
+            rustc_commit: Some("9b00956e56009bab2aa15d7bff10916599e3d6d6".to_owned()),
+            ..BuildInfo::default()
+        }
+    );
+}
+
+#[test]
+fn build_differences() {
+    let release = read(&fixture("release.o")).unwrap();
+    let debug = read(&fixture("debug.o")).unwrap();
+    let old = read(&fixture("release-old.o")).unwrap();
+
+    assert_eq!(differences(&release, &release), Vec::<String>::new());
+    assert_eq!(
+        differences(&old, &release),
+        ["baseline built with rustc 1.93.1, current with rustc 1.95.0"]
+    );
+    assert_eq!(
+        differences(&old, &debug),
+        [
+            "baseline built with rustc 1.93.1, current with rustc 1.95.0",
+            "baseline built without debug assertions, current with them",
+            "baseline built without overflow checks, current with them",
+        ]
+    );
+
+    // Nightlies of the same version, and stripped of `.comment`.
+    let nightly = |rustc: Option<&str>, commit: &str| BuildInfo {
+        rustc: rustc.map(str::to_owned),
+        rustc_commit: Some(commit.to_owned()),
+        ..BuildInfo::default()
+    };
+    assert_eq!(
+        differences(
+            &nightly(Some("1.80.0-nightly (ada5e2c7b 2024-05-31)"), &"a".repeat(40)),
+            &nightly(Some("1.80.0-nightly (c1dba09f2 2024-06-06)"), &"b".repeat(40)),
+        ),
+        ["baseline built with rustc 1.80.0-nightly (ada5e2c7b 2024-05-31), current with rustc 1.80.0-nightly (c1dba09f2 2024-06-06)"]
+    );
+    assert_eq!(
+        differences(
+            &nightly(None, &"a".repeat(40)),
+            &nightly(Some("1.80.0"), &"b".repeat(40))
+        ),
+        ["baseline built with rustc aaaaaaaaa, current with rustc bbbbbbbbb"]
+    );
+
+    // The C compiler of a reference implementation.
+    let gcc = |version: &str| BuildInfo {
+        compilers: vec![format!("GCC: (GNU) {version}")],
+        ..BuildInfo::default()
+    };
+    assert_eq!(
+        differences(&gcc("12.2.0"), &gcc("13.2.0")),
+        ["baseline built with GCC: (GNU) 12.2.0, current with GCC: (GNU) 13.2.0"]
+    );
+    assert_eq!(
+        differences(&BuildInfo::default(), &gcc("13.2.0")),
+        Vec::<String>::new()
+    );
+
+    // Only the binaries with metadata on both sides.
+    let before = IndexMap::from([
+        ("target/release/compress".to_owned(), old.clone()),
+        ("zlib-ng/minigzip".to_owned(), gcc("12.2.0")),
+    ]);
+    let after = IndexMap::from([
+        ("target/release/compress".to_owned(), release.clone()),
+        ("zlib-ng/minigzip".to_owned(), gcc("12.2.0")),
+        ("target/release/decompress".to_owned(), release),
+    ]);
+    assert_eq!(
+        collect(&before, &after),
+        [BuildDifference {
+            binary: "target/release/compress".to_owned(),
+            differences: vec![
+                "baseline built with rustc 1.93.1, current with rustc 1.95.0".to_owned()
+            ],
+        }]
+    );
+}
+
+#[test]
+fn note_affected_tables() {
+    use crate::import::ImportedFrom;
+    use crate::testkit::BenchDataBuilder;
+
+    let data = BenchDataBuilder::new("aaaa")
+        .group("compress", |g| {
+            g.bench(["./zlib-ng/minigzip", "-6"], |b| b)
+                .bench(["./target/release/compress", "6", "silesia.tar"], |b| b)
+                .bench(["hyperfine", "target/release/compress 6"], |b| b)
+                .bench(["target/release/compress", "9"], |b| b)
+        })
+        .build();
+    let benches = &data.bench_groups["compress"];
+    let mut imported = benches[3].clone();
+    imported.imported = Some(ImportedFrom {
+        path: "artifacts/results.json".to_owned(),
+        commit_hash: None,
+        cpu_model: None,
+        machine_class: None,
+    });
+    let differences = [BuildDifference {
+        binary: "target/release/compress".to_owned(),
+        differences: vec![
+            "baseline built with rustc 1.93.1, current with rustc 1.95.0".to_owned(),
+            "baseline built without debug assertions, current with them".to_owned(),
+        ],
+    }];
+
+    let affected = notes(&differences, &benches[..2]);
+    assert_eq!(
+        affected,
+        ["`target/release/compress`: baseline built with rustc 1.93.1, current with rustc 1.95.0; baseline built without debug assertions, current with them"]
+    );
+    assert_eq!(notes(&differences, &benches[3..]), affected);
+    let mut md = String::new();
+    render_markdown_note(&mut md, &affected);
+    assert_eq!(
+        md,
+        "> [!NOTE]\n> Built differently than the baseline, which may explain changes:\n\
+         > - `target/release/compress`: baseline built with rustc 1.93.1, current with rustc 1.95.0; baseline built without debug assertions, current with them\n\n"
+    );
+
+    // Other binaries, the binary in an argument of another one rather than as one, and
+    // imported commands.
+    for unaffected in [&benches[0], &benches[2], &imported] {
+        assert!(notes(&differences, [unaffected]).is_empty());
+    }
+    let mut md = String::new();
+    render_markdown_note(&mut md, &[]);
+    assert_eq!(md, "");
+}
@@ -0,0 +1,287 @@
+//! Hit rates of the caches and the branch predictor, derived from pairs of counters for the
+//! groups with `cache-stats-for-group`. A count of misses means little without the accesses
+//! next to it: more misses of more accesses can be a better hit rate.
+//!
+//! The counters come from the events of the group, like those of `perf-events-for-group`: a
+//! rate is derived for every pair with both counters, and skipped for a command that lacks
+//! either. The rates are counters like any other, with the change in percentage points, so
+//! comparison tables can use them as their `measure`. Unlike the other counters, higher is
+//! better. Under the raw table of the group, a compact table shows the rates of every command.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::bench::BenchCounter;
+use crate::command_display::CommandDisplay;
+use crate::mix;
+
+/// A rate derived from the counters of the accesses and of the misses.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RatePair {
+    /// The name of the derived counter.
+    pub rate: &'static str,
+    /// The counter of the accesses, by the names perf may report it as.
+    pub accesses: &'static [&'static str],
+    pub misses: &'static [&'static str],
+    /// The header of the column of the rate.
+    pub column: &'static str,
+}
+
+pub const PAIRS: &[RatePair] = &[
+    RatePair {
+        rate: "cache-hit-rate",
+        accesses: &["cache-references"],
+        misses: &["cache-misses"],
+        column: "cache hits",
+    },
+    RatePair {
+        rate: "L1-dcache-hit-rate",
+        accesses: &["L1-dcache-loads"],
+        misses: &["L1-dcache-load-misses"],
+        column: "L1d hits",
+    },
+    RatePair {
+        rate: "branch-hit-rate",
+        accesses: &["branches", "branch-instructions"],
+        misses: &["branch-misses"],
+        column: "branch hits",
+    },
+];
+
+/// Whether `measure` is one of the derived hit rates, of which higher is better.
+pub fn is_hit_rate(measure: &str) -> bool {
+    PAIRS.iter().any(|pair| pair.rate == measure)
+}
+
+/// The share of the accesses that hit, in percent. The variance is propagated from both
+/// counters to first order, assuming they vary independently, like for
+/// [`mix::branch_miss_rate`]. Without accesses there is no rate.
+pub fn hit_rate(accesses: &BenchCounter, misses: &BenchCounter) -> Option<BenchCounter> {
+    if accesses.value <= 0.0 {
+        return None;
+    }
+    let miss_rate = misses.value / accesses.value;
+    let relative_variance = if misses.value > 0.0 {
+        misses.variance / misses.value.powi(2)
+    } else {
+        0.0
+    } + accesses.variance / accesses.value.powi(2);
+
+    Some(BenchCounter {
+        value: (1.0 - miss_rate) * 100.0,
+        variance: (miss_rate * 100.0).powi(2) * relative_variance,
+        repetitions: misses.repetitions.min(accesses.repetitions),
+        unit: "%".to_owned(),
+    })
+}
+
+/// The rates of the pairs with both counters, by name.
+pub fn rates(counters: &BTreeMap<String, BenchCounter>) -> Vec<(&'static str, BenchCounter)> {
+    let first = |names: &[&str]| names.iter().find_map(|name| mix::counter(counters, name));
+    PAIRS
+        .iter()
+        .filter_map(|pair| {
+            let rate = hit_rate(first(pair.accesses)?, first(pair.misses)?)?;
+            Some((pair.rate, rate))
+        })
+        .collect()
+}
+
+/// The table of the rates of every command that has any, to go under the raw table of a
+/// group. Only the columns of the rates some command has.
+pub fn render_markdown_table<'a>(
+    md: &mut String,
+    benches: impl IntoIterator<Item = (&'a [String], &'a BTreeMap<String, BenchCounter>)>,
+    commands: CommandDisplay,
+) {
+    let benches = benches
+        .into_iter()
+        .filter(|(_, counters)| PAIRS.iter().any(|pair| counters.contains_key(pair.rate)))
+        .collect::<Vec<_>>();
+    let columns = PAIRS
+        .iter()
+        .filter(|pair| {
+            benches
+                .iter()
+                .any(|(_, counters)| counters.contains_key(pair.rate))
+        })
+        .collect::<Vec<_>>();
+    if columns.is_empty() {
+        return;
+    }
+
+    let mut cells = commands.cells();
+    write!(md, "\n| command |").unwrap();
+    for pair in &columns {
+        write!(md, " {} |", pair.column).unwrap();
+    }
+    write!(md, "\n|---|").unwrap();
+    for _ in &columns {
+        write!(md, "---:|").unwrap();
+    }
+    writeln!(md).unwrap();
+    for (cmd, counters) in benches {
+        write!(md, "|{}|", cells.code(&cmd.join(" "))).unwrap();
+        for pair in &columns {
+            if let Some(rate) = counters.get(pair.rate) {
+                write!(md, "`{:.2}±{:.2}%`", rate.value, rate.variance.sqrt()).unwrap();
+            }
+            md.push('|');
+        }
+        writeln!(md).unwrap();
+    }
+    cells.render_footnotes(md);
+}
+
+#[cfg(test)]
+fn counters_for_test(counters: &[(&str, f64, f64)]) -> BTreeMap<String, BenchCounter> {
+    counters
+        .iter()
+        .map(|&(name, value, variance)| {
+            (
+                name.to_owned(),
+                BenchCounter {
+                    value,
+                    variance,
+                    repetitions: 20,
+                    unit: String::new(),
+                },
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn detect_pairs() {
+    let counters = counters_for_test(&[
+        ("cache-references", 1.0e6, 0.0),
+        ("cache-misses", 1.0e5, 0.0),
+        ("L1-dcache-loads", 4.0e6, 0.0),
+        // Only half of the pair.
+        ("branch-misses", 1.0e3, 0.0),
+    ]);
+    let derived = rates(&counters);
+    assert_eq!(
+        derived
+            .iter()
+            .map(|(name, rate)| (*name, rate.value))
+            .collect::<Vec<_>>(),
+        [("cache-hit-rate", 90.0)]
+    );
+
+    // Every pair, by the other name of the branches and on hybrid CPUs.
+    let counters = counters_for_test(&[
+        ("cpu_core/cache-references/", 1.0e6, 0.0),
+        ("cpu_core/cache-misses/", 5.0e5, 0.0),
+        ("L1-dcache-loads", 4.0e6, 0.0),
+        ("L1-dcache-load-misses", 1.0e5, 0.0),
+        ("branch-instructions", 2.0e6, 0.0),
+        ("branch-misses", 2.0e4, 0.0),
+    ]);
+    assert_eq!(
+        rates(&counters)
+            .iter()
+            .map(|(name, rate)| (*name, rate.value))
+            .collect::<Vec<_>>(),
+        [
+            ("cache-hit-rate", 50.0),
+            ("L1-dcache-hit-rate", 97.5),
+            ("branch-hit-rate", 99.0)
+        ]
+    );
+
+    assert!(is_hit_rate("L1-dcache-hit-rate"));
+    assert!(!is_hit_rate("L1-dcache-load-misses"));
+    assert!(!is_hit_rate(mix::BRANCH_MISS_RATE));
+}
+
+#[test]
+fn compute_hit_rates() {
+    let counters = counters_for_test(&[
+        ("cache-references", 1.0e6, 1.0e8),
+        ("cache-misses", 2.0e4, 4.0e4),
+    ]);
+    let rate = hit_rate(&counters["cache-references"], &counters["cache-misses"]).unwrap();
+    assert_eq!(rate.value, 98.0);
+    // 1% relative deviation of both counters is about 1.41% of the miss rate of 2%.
+    assert!(
+        (rate.variance.sqrt() - 0.02 * 2f64.sqrt()).abs() < 1e-12,
+        "{rate:?}"
+    );
+    assert_eq!(rate.unit, "%");
+    assert_eq!(rate.repetitions, 20);
+
+    // No misses at all.
+    let counters = counters_for_test(&[
+        ("cache-references", 1.0e6, 1.0e8),
+        ("cache-misses", 0.0, 0.0),
+    ]);
+    let rate = hit_rate(&counters["cache-references"], &counters["cache-misses"]).unwrap();
+    assert_eq!((rate.value, rate.variance), (100.0, 0.0));
+
+    // No accesses at all: no rate rather than a division by zero.
+    let counters = counters_for_test(&[("cache-references", 0.0, 0.0), ("cache-misses", 0.0, 0.0)]);
+    assert_eq!(
+        hit_rate(&counters["cache-references"], &counters["cache-misses"]),
+        None
+    );
+    assert!(rates(&counters).is_empty());
+}
+
+#[test]
+fn render_cache_stats() {
+    let with_rates = |counters: &[(&str, f64, f64)]| {
+        let mut counters = counters_for_test(counters);
+        let derived = rates(&counters);
+        counters.extend(
+            derived
+                .into_iter()
+                .map(|(name, rate)| (name.to_owned(), rate)),
+        );
+        counters
+    };
+    let benches = [
+        (
+            vec!["./c".to_owned(), "6".to_owned()],
+            with_rates(&[
+                ("cache-references", 1.0e6, 1.0e8),
+                ("cache-misses", 2.0e4, 4.0e4),
+                ("branches", 1.0e6, 0.0),
+                ("branch-misses", 1.0e4, 0.0),
+            ]),
+        ),
+        (
+            vec!["./c".to_owned(), "1".to_owned()],
+            with_rates(&[("branches", 1.0e6, 0.0), ("branch-misses", 5.0e3, 0.0)]),
+        ),
+        // Without any rate, e.g. as no access was counted.
+        (
+            vec!["./c".to_owned(), "0".to_owned()],
+            with_rates(&[("cache-references", 0.0, 0.0), ("cache-misses", 0.0, 0.0)]),
+        ),
+    ];
+
+    let mut md = String::new();
+    render_markdown_table(
+        &mut md,
+        benches.iter().map(|(cmd, counters)| (&cmd[..], counters)),
+        CommandDisplay::default(),
+    );
+    assert_eq!(
+        md,
+        "\n| command | cache hits | branch hits |\n\
+         |---|---:|---:|\n\
+         |`./c 6`|`98.00±0.03%`|`99.00±0.00%`|\n\
+         |`./c 1`||`99.50±0.00%`|\n"
+    );
+
+    let mut md = String::new();
+    render_markdown_table(
+        &mut md,
+        benches[2..]
+            .iter()
+            .map(|(cmd, counters)| (&cmd[..], counters)),
+        CommandDisplay::default(),
+    );
+    assert_eq!(md, "");
+}
@@ -0,0 +1,251 @@
+//! `--changed-only`: run only the groups whose `paths-for-group` patterns match a file changed
+//! since the merge base. Groups without patterns always run.
+//!
+//! The tag filters select commands first, and `--changed-only` then skips whole groups among
+//! the remaining ones, so a command only runs when both let it. A group removed by the tag
+//! filters isn't recorded as skipped here.
+//!
+//! Patterns are matched against the whole path relative to the repository root: `*` and `?`
+//! match within a path component, and `**` matches any number of components, e.g.
+//! `src/deflate/**` or `**/*.toml`.
+
+use std::path::Path;
+
+use indexmap::IndexMap;
+
+use crate::{fail_fast, worktree};
+
+/// The files changed between `base` and `HEAD` of the repository at `dir`.
+pub fn changed_files(dir: &Path, base: &str) -> Result<Vec<String>, String> {
+    // NUL-separated, so unusual file names aren't quoted.
+    let output = worktree::git(dir, &["diff", "--name-only", "-z", base, "HEAD"])?;
+    Ok(parse_name_only(&output))
+}
+
+fn parse_name_only(output: &[u8]) -> Vec<String> {
+    output
+        .split(|&b| b == 0)
+        .filter(|path| !path.is_empty())
+        .map(|path| String::from_utf8_lossy(path).into_owned())
+        .collect()
+}
+
+/// Whether the glob `pattern` matches the whole of `path`.
+pub fn glob_match(pattern: &str, path: &str) -> bool {
+    fn matches(pattern: &[char], path: &[char]) -> bool {
+        match pattern {
+            [] => path.is_empty(),
+            // Zero or more whole components.
+            ['*', '*', '/', rest @ ..] => (0..=path.len())
+                .filter(|&i| i == 0 || path[i - 1] == '/')
+                .any(|i| matches(rest, &path[i..])),
+            ['*', '*', rest @ ..] => (0..=path.len()).any(|i| matches(rest, &path[i..])),
+            ['*', rest @ ..] => (0..=path.len())
+                .take_while(|&i| i == 0 || path[i - 1] != '/')
+                .any(|i| matches(rest, &path[i..])),
+            ['?', rest @ ..] => {
+                path.first().is_some_and(|&c| c != '/') && matches(rest, &path[1..])
+            }
+            [c, rest @ ..] => path.first() == Some(c) && matches(rest, &path[1..]),
+        }
+    }
+
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let path = path.chars().collect::<Vec<_>>();
+    matches(&pattern, &path)
+}
+
+/// Whether a group with `patterns` runs for the `changed` files, and why.
+#[derive(Debug, PartialEq)]
+pub enum Decision {
+    /// The group has no patterns.
+    Always,
+    /// The first changed file that matched, and the pattern it matched.
+    Matched { file: String, pattern: String },
+    /// Why the group is skipped.
+    Skipped(String),
+}
+
+pub fn decide(patterns: &[String], changed: &[String]) -> Decision {
+    if patterns.is_empty() {
+        return Decision::Always;
+    }
+    for file in changed {
+        if let Some(pattern) = patterns.iter().find(|pattern| glob_match(pattern, file)) {
+            return Decision::Matched {
+                file: file.clone(),
+                pattern: pattern.clone(),
+            };
+        }
+    }
+    Decision::Skipped(format!("no changed files matched {}", patterns.join(", ")))
+}
+
+/// The groups that were skipped with their reason, and the comparison rows that are missing
+/// because of it, by table.
+pub fn render_markdown(
+    md: &mut String,
+    skipped_groups: &IndexMap<String, String>,
+    unmeasured_rows: &IndexMap<String, Vec<String>>,
+) {
+    use std::fmt::Write;
+
+    if skipped_groups.is_empty() {
+        return;
+    }
+
+    writeln!(md, "### Not measured in this run").unwrap();
+    writeln!(md).unwrap();
+    // `--fail-fast` records the groups it didn't run alongside.
+    let (not_run, skipped) = skipped_groups
+        .iter()
+        .partition::<Vec<_>, _>(|(_, reason)| *reason == fail_fast::NOT_RUN);
+    if !skipped.is_empty() {
+        writeln!(md, "Skipped with `--changed-only`:").unwrap();
+        writeln!(md).unwrap();
+        for (group_name, reason) in skipped {
+            writeln!(md, "- `{group_name}`: skipped: {reason}").unwrap();
+        }
+        writeln!(md).unwrap();
+    }
+    if !not_run.is_empty() {
+        writeln!(
+            md,
+            "Not run, `--fail-fast` stopped the run when the gate failed:"
+        )
+        .unwrap();
+        writeln!(md).unwrap();
+        for (group_name, _) in not_run {
+            writeln!(md, "- `{group_name}`").unwrap();
+        }
+        writeln!(md).unwrap();
+    }
+
+    if unmeasured_rows.is_empty() {
+        return;
+    }
+    writeln!(md, "Missing from the comparisons:").unwrap();
+    writeln!(md).unwrap();
+    for (table_name, rows) in unmeasured_rows {
+        let rows = rows
+            .iter()
+            .map(|row| format!("`{row}`"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        writeln!(md, "- {table_name}: {rows}").unwrap();
+    }
+    writeln!(md).unwrap();
+}
+
+#[test]
+fn glob_patterns() {
+    assert!(glob_match("src/deflate/**", "src/deflate/mod.rs"));
+    assert!(glob_match("src/deflate/**", "src/deflate/x/y.rs"));
+    assert!(!glob_match("src/deflate/**", "src/inflate/mod.rs"));
+    assert!(!glob_match("src/deflate/**", "src/deflate"));
+
+    // `*` and `?` stay within a component.
+    assert!(glob_match("src/*.rs", "src/lib.rs"));
+    assert!(!glob_match("src/*.rs", "src/deflate/mod.rs"));
+    assert!(glob_match("src/?ib.rs", "src/lib.rs"));
+    assert!(!glob_match("src?lib.rs", "src/lib.rs"));
+
+    // `**/` also matches no component at all.
+    assert!(glob_match("**/Cargo.toml", "Cargo.toml"));
+    assert!(glob_match("**/Cargo.toml", "crates/zlib/Cargo.toml"));
+    assert!(!glob_match("**/Cargo.toml", "crates/zlib/NotCargo.toml"));
+    assert!(glob_match("src/**/mod.rs", "src/mod.rs"));
+    assert!(glob_match("src/**/mod.rs", "src/a/b/mod.rs"));
+
+    // The whole path has to match.
+    assert!(!glob_match("lib.rs", "src/lib.rs"));
+    assert!(glob_match("src/lib.rs", "src/lib.rs"));
+    assert!(glob_match("src/läuft.rs", "src/läuft.rs"));
+    assert!(glob_match("src/l?uft.rs", "src/läuft.rs"));
+}
+
+#[test]
+fn decisions() {
+    let changed = ["README.md".to_owned(), "src/inflate/window.rs".to_owned()];
+
+    assert_eq!(decide(&[], &changed), Decision::Always);
+    assert_eq!(
+        decide(
+            &["src/deflate/**".to_owned(), "src/inflate/**".to_owned()],
+            &changed
+        ),
+        Decision::Matched {
+            file: "src/inflate/window.rs".to_owned(),
+            pattern: "src/inflate/**".to_owned(),
+        }
+    );
+    assert_eq!(
+        decide(
+            &["src/deflate/**".to_owned(), "benches/*.rs".to_owned()],
+            &changed
+        ),
+        Decision::Skipped("no changed files matched src/deflate/**, benches/*.rs".to_owned())
+    );
+    // Nothing changed at all.
+    assert!(matches!(
+        decide(&["**".to_owned()], &[]),
+        Decision::Skipped(_)
+    ));
+}
+
+#[test]
+fn changed_files_since_base() {
+    use std::process::Command;
+
+    let dir = crate::testkit::test_dir("changed-files");
+    let git = |args: &[&str]| {
+        let output = Command::new("git")
+            .args([
+                "-c",
+                "user.name=Bench",
+                "-c",
+                "user.email=bench@example.com",
+            ])
+            .args(["-c", "commit.gpgsign=false"])
+            .args(args)
+            .current_dir(&dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{output:?}");
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    git(&["init", "--quiet"]);
+    std::fs::create_dir_all(dir.join("src/deflate")).unwrap();
+    std::fs::write(dir.join("src/deflate/mod.rs"), "fn deflate() {}\n").unwrap();
+    std::fs::write(dir.join("src/lib.rs"), "mod deflate;\n").unwrap();
+    git(&["add", "."]);
+    git(&["commit", "--quiet", "-m", "init"]);
+    let base = git(&["rev-parse", "HEAD"]);
+    assert_eq!(
+        changed_files(&dir, base.trim()).unwrap(),
+        Vec::<String>::new()
+    );
+
+    std::fs::write(dir.join("src/deflate/mod.rs"), "fn deflate() { fast() }\n").unwrap();
+    std::fs::write(dir.join("a file with spaces.md"), "\n").unwrap();
+    git(&["add", "."]);
+    git(&["commit", "--quiet", "-m", "faster"]);
+    // Uncommitted changes don't count, only what HEAD changed.
+    std::fs::write(dir.join("src/lib.rs"), "mod inflate;\n").unwrap();
+
+    assert_eq!(
+        changed_files(&dir, base.trim()).unwrap(),
+        ["a file with spaces.md", "src/deflate/mod.rs"]
+    );
+    assert!(changed_files(&dir, "does-not-exist").is_err());
+}
+
+#[test]
+fn parse_git_diff() {
+    assert_eq!(
+        parse_name_only(b"src/lib.rs\0tab\there.rs\0new\nline.rs\0"),
+        ["src/lib.rs", "tab\there.rs", "new\nline.rs"]
+    );
+    assert!(parse_name_only(b"").is_empty());
+}
@@ -0,0 +1,278 @@
+//! Long command lines in the tables. Absolute paths make command lines long enough to push
+//! the Δ columns out of view, so those longer than `max-command-length` characters, 60 by
+//! default, are shortened in the middle, keeping the binary and the last arguments:
+//!
+//! ```text
+//! target/release/compress … /home/runner/corpus/silesia/dickens.txt
+//! ```
+//!
+//! The full command line is in a tooltip, or with `"full-commands": "footnotes"` in a list
+//! under the table, for the markdown renderers that drop HTML. `"max-command-length": null`
+//! never shortens.
+
+use std::borrow::Cow;
+use std::fmt::Write;
+
+use serde::Deserialize;
+
+const ELLIPSIS: &str = "…";
+
+pub fn default_max_command_length() -> Option<usize> {
+    Some(60)
+}
+
+/// Where the full text of a shortened command line goes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FullCommands {
+    /// An HTML `title`, shown when hovering the command.
+    #[default]
+    Tooltip,
+    /// A numbered list under the table.
+    Footnotes,
+}
+
+/// How to show command lines, see [`crate::Config::command_display`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommandDisplay {
+    pub max_length: Option<usize>,
+    pub full_commands: FullCommands,
+}
+
+impl Default for CommandDisplay {
+    fn default() -> Self {
+        CommandDisplay {
+            max_length: default_max_command_length(),
+            full_commands: FullCommands::default(),
+        }
+    }
+}
+
+impl CommandDisplay {
+    /// The command cells of a table, or the command lines of a list.
+    pub fn cells(self) -> CommandCells {
+        CommandCells {
+            display: self,
+            footnotes: vec![],
+        }
+    }
+}
+
+/// Renders the command lines of one table, collecting the footnotes to go under it.
+#[derive(Debug)]
+pub struct CommandCells {
+    display: CommandDisplay,
+    footnotes: Vec<String>,
+}
+
+impl CommandCells {
+    /// `command` as inline code, for a table cell.
+    pub fn cell(&mut self, command: &str) -> String {
+        self.render(command, true)
+    }
+
+    /// `command` as inline code, outside of a table.
+    pub fn code(&mut self, command: &str) -> String {
+        self.render(command, false)
+    }
+
+    fn render(&mut self, command: &str, in_table: bool) -> String {
+        let escape = |text: &str| {
+            if in_table {
+                text.replace('|', "\\|")
+            } else {
+                text.to_owned()
+            }
+        };
+        let shortened = match self.display.max_length {
+            Some(max_length) => shorten(command, max_length),
+            None => Cow::Borrowed(command),
+        };
+        if shortened == command {
+            return format!("`{}`", escape(command));
+        }
+        match self.display.full_commands {
+            FullCommands::Tooltip => format!(
+                "<span title=\"{}\">`{}`</span>",
+                escape(&escape_attribute(command)),
+                escape(&shortened)
+            ),
+            FullCommands::Footnotes => {
+                self.footnotes.push(escape(command));
+                format!("`{}` [{}]", escape(&shortened), self.footnotes.len())
+            }
+        }
+    }
+
+    /// The full command lines of the shortened ones so far, as a list.
+    pub fn render_footnotes(&mut self, md: &mut String) {
+        if self.footnotes.is_empty() {
+            return;
+        }
+        writeln!(md).unwrap();
+        for (index, command) in self.footnotes.drain(..).enumerate() {
+            writeln!(md, "- [{}] `{command}`", index + 1).unwrap();
+        }
+    }
+}
+
+fn escape_attribute(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Whether `c` belongs to the grapheme of the character before it: combining marks, variation
+/// selectors, emoji modifiers and the character after a zero width joiner.
+fn extends_grapheme(c: char, previous: Option<char>) -> bool {
+    matches!(c,
+        '\u{0300}'..='\u{036F}'
+        | '\u{1AB0}'..='\u{1AFF}'
+        | '\u{1DC0}'..='\u{1DFF}'
+        | '\u{200D}'
+        | '\u{20D0}'..='\u{20FF}'
+        | '\u{FE00}'..='\u{FE0F}'
+        | '\u{FE20}'..='\u{FE2F}'
+        | '\u{1F3FB}'..='\u{1F3FF}')
+        || previous == Some('\u{200D}')
+}
+
+/// The length of `text` in graphemes, as far as command lines need: a letter with combining
+/// accents or an emoji sequence counts once.
+pub fn width(text: &str) -> usize {
+    let mut previous = None;
+    let mut width = 0;
+    for c in text.chars() {
+        if !extends_grapheme(c, previous) {
+            width += 1;
+        }
+        previous = Some(c);
+    }
+    width
+}
+
+/// `command` shortened to at most `max_length` graphemes by replacing arguments in the middle
+/// with `…`. Only whole arguments are left out, and the first and the last argument are kept,
+/// the first down to the binary name, even when that is longer. The last arguments are kept
+/// before the first ones, they usually tell the commands of a group apart.
+pub fn shorten(command: &str, max_length: usize) -> Cow<'_, str> {
+    if width(command) <= max_length {
+        return Cow::Borrowed(command);
+    }
+    let args = command.split(' ').collect::<Vec<_>>();
+    let basename = |arg: &'_ str| -> String {
+        match arg.rsplit_once('/') {
+            Some((dir, name)) if !name.is_empty() && width(dir) > 1 => {
+                format!("{ELLIPSIS}/{name}")
+            }
+            _ => arg.to_owned(),
+        }
+    };
+    let [first, .., last] = args[..] else {
+        return Cow::Owned(basename(command));
+    };
+
+    let joined = |head: usize, tail: usize| {
+        format!(
+            "{} {ELLIPSIS} {}",
+            args[..head].join(" "),
+            args[args.len() - tail..].join(" ")
+        )
+    };
+    if width(&joined(1, 1)) > max_length {
+        return Cow::Owned(format!("{} {ELLIPSIS} {last}", basename(first)));
+    }
+    let (mut head, mut tail) = (1, 1);
+    loop {
+        if head + tail + 1 < args.len() && width(&joined(head, tail + 1)) <= max_length {
+            tail += 1;
+        } else if head + tail + 1 < args.len() && width(&joined(head + 1, tail)) <= max_length {
+            head += 1;
+        } else {
+            break;
+        }
+    }
+    Cow::Owned(joined(head, tail))
+}
+
+#[test]
+fn shorten_command_lines() {
+    let command =
+        "target/release/compress --level 6 --threads 4 /home/runner/corpus/silesia/dickens.txt";
+    assert_eq!(shorten(command, 200), command);
+    assert_eq!(
+        shorten(command, 65),
+        "target/release/compress … /home/runner/corpus/silesia/dickens.txt"
+    );
+    assert_eq!(
+        shorten(command, 67),
+        "target/release/compress … 4 /home/runner/corpus/silesia/dickens.txt"
+    );
+    // The first arguments once the last ones no longer fit.
+    assert_eq!(
+        shorten(command, 75),
+        "target/release/compress --level … 4 /home/runner/corpus/silesia/dickens.txt"
+    );
+    assert_eq!(
+        shorten(
+            "./compress --level 6 --threads 4 --dictionary /usr/share/dict/words corpus",
+            51
+        ),
+        "./compress --level 6 … /usr/share/dict/words corpus"
+    );
+
+    // At least the binary name and the last argument, even when longer.
+    assert_eq!(
+        shorten(command, 20),
+        "…/compress … /home/runner/corpus/silesia/dickens.txt"
+    );
+    assert_eq!(shorten("/a/very/long/path/to/compress", 10), "…/compress");
+    assert_eq!(shorten("no-slashes-but-long", 10), "no-slashes-but-long");
+
+    // Accents and emoji sequences are a single grapheme.
+    assert_eq!(width("cafe\u{301}"), 4);
+    assert_eq!(width("👩\u{200D}💻"), 1);
+    assert_eq!(
+        shorten("./c re\u{301}sume\u{301}.txt", 14),
+        "./c re\u{301}sume\u{301}.txt"
+    );
+}
+
+#[test]
+fn full_commands() {
+    let command = "./compress --level 6 --dict \"a|b\" corpus/dickens.txt";
+    let display = CommandDisplay {
+        max_length: Some(30),
+        full_commands: FullCommands::Tooltip,
+    };
+    let mut cells = display.cells();
+    assert_eq!(cells.cell("./c 1"), "`./c 1`");
+    assert_eq!(
+        cells.cell(command),
+        "<span title=\"./compress --level 6 --dict &quot;a\\|b&quot; corpus/dickens.txt\">`./compress … corpus/dickens.txt`</span>"
+    );
+
+    let mut cells = CommandDisplay {
+        full_commands: FullCommands::Footnotes,
+        ..display
+    }
+    .cells();
+    assert_eq!(cells.cell(command), "`./compress … corpus/dickens.txt` [1]");
+    assert_eq!(cells.code(command), "`./compress … corpus/dickens.txt` [2]");
+    let mut md = String::new();
+    cells.render_footnotes(&mut md);
+    assert_eq!(
+        md,
+        "\n- [1] `./compress --level 6 --dict \"a\\|b\" corpus/dickens.txt`\n\
+         - [2] `./compress --level 6 --dict \"a|b\" corpus/dickens.txt`\n"
+    );
+
+    // Never shortened without a maximum.
+    let mut cells = CommandDisplay {
+        max_length: None,
+        ..display
+    }
+    .cells();
+    assert_eq!(cells.code(command), format!("`{command}`"));
+}
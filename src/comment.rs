@@ -0,0 +1,453 @@
+//! Post a short report of a run as a comment on the benchmarked commit, for runs whose step
+//! summary nobody reads, e.g. scheduled runs against main. Opt-in with `comment-target` and a
+//! token in `BENCH_GITHUB_TOKEN`. Like notifications, failing to comment doesn't fail the run.
+
+use std::fmt::Write;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::compare::{self, ComparisonRow, ComparisonTable, Comparisons};
+use crate::gate::GateVerdict;
+use crate::markers::{Marker, Markers};
+use crate::{http, readable, BenchData};
+
+/// The environment variable holding the token to comment with. It needs the `contents: write`
+/// permission.
+pub const TOKEN_ENV: &str = "BENCH_GITHUB_TOKEN";
+
+/// The environment variable with the url of the API, set by GitHub Actions.
+pub const API_URL_ENV: &str = "GITHUB_API_URL";
+
+pub const DEFAULT_API_URL: &str = "https://api.github.com";
+
+/// The longest comment GitHub accepts, in characters. Comments are kept to as many bytes.
+pub const MAX_COMMENT_LEN: usize = 65536;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where to post the report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CommentTarget {
+    /// A comment on the benchmarked commit.
+    Commit,
+}
+
+/// The comment: the verdict, and the rows that moved the most, as many as fit in `max_len`
+/// bytes. A row that doesn't fit is left out, but the smaller moves after it may still fit.
+/// The rows are those of the `render-versus-other` tables, or the raw comparisons when there
+/// are none.
+pub fn build_comment(
+    repository: &str,
+    data: &BenchData,
+    prev_results: Option<&BenchData>,
+    comparisons: &Comparisons,
+    gate: Option<&GateVerdict>,
+    markers: &Markers,
+    max_len: usize,
+) -> String {
+    let mut md = String::new();
+    writeln!(
+        md,
+        "### Benchmarks of [`{}`](https://github.com/{repository}/commit/{})\n",
+        data.short_commit_id(),
+        data.commit_hash,
+    )
+    .unwrap();
+
+    let Some(prev_results) = prev_results else {
+        writeln!(md, "No previous results to compare against.").unwrap();
+        return md;
+    };
+
+    writeln!(
+        md,
+        "Compared {} `{}`{}: {}",
+        data.baseline_relation(),
+        prev_results.short_commit_id(),
+        data.ancestor_label(),
+        verdict(comparisons, gate),
+    )
+    .unwrap();
+
+    let mut rows = verdict_rows(comparisons);
+    if rows.is_empty() {
+        return md;
+    }
+
+    // The biggest moves first, and otherwise in the order of the config.
+    rows.sort_by(|(_, a), (_, b)| b.delta_percent.abs().total_cmp(&a.delta_percent.abs()));
+
+    writeln!(md).unwrap();
+    writeln!(md, "| table | row | measure | before | after | Δ |").unwrap();
+    writeln!(md, "| --- | --- | --- | --- | --- | --- |").unwrap();
+    // Room for the legend, and the note about the rows that didn't fit.
+    let legend_len = markers
+        .legend([Marker::Improvement, Marker::Regression, Marker::Neutral])
+        .map_or(0, |legend| legend.len() + 2);
+    let max_len = max_len.saturating_sub(64 + legend_len);
+    let mut omitted = 0;
+    let mut used = vec![];
+    for (table, row) in rows {
+        let line = render_row(table, row, markers);
+        if md.len() + line.len() > max_len {
+            omitted += 1;
+        } else {
+            md.push_str(&line);
+            used.push(row.marker());
+        }
+    }
+    if let Some(legend) = markers.legend(used) {
+        writeln!(md, "\n{legend}").unwrap();
+    }
+    if omitted > 0 {
+        writeln!(md, "\n_{omitted} more rows didn't fit in a comment._").unwrap();
+    }
+    md
+}
+
+/// The rows the verdict counts: those of the `render-versus-other` tables, or the raw
+/// comparisons when there are none, but for the `informational` ones.
+fn verdict_rows(comparisons: &Comparisons) -> Vec<(&ComparisonTable, &ComparisonRow)> {
+    let tables = if comparisons.versus_other.is_empty() {
+        &comparisons.raw
+    } else {
+        &comparisons.versus_other
+    };
+    compare::counted_rows(tables).collect()
+}
+
+/// The one-line verdict of a comparison, e.g. `1 significant regressions, 0 significant
+/// improvements. The gate failed with 1 failures.`
+pub fn verdict(comparisons: &Comparisons, gate: Option<&GateVerdict>) -> String {
+    let rows = verdict_rows(comparisons);
+    let regressions = rows.iter().filter(|(_, row)| row.is_regression()).count();
+    let improvements = rows.iter().filter(|(_, row)| row.is_improvement()).count();
+    let mut verdict =
+        format!("{regressions} significant regressions, {improvements} significant improvements.");
+    match gate {
+        Some(gate) if gate.passed() => write!(verdict, " The gate passed.").unwrap(),
+        Some(gate) => write!(
+            verdict,
+            " The gate failed with {} failures.",
+            gate.failures.len() + gate.variance_failures.len()
+        )
+        .unwrap(),
+        None => {}
+    }
+    verdict
+}
+
+fn render_row(table: &ComparisonTable, row: &ComparisonRow, markers: &Markers) -> String {
+    let significant = match markers.get(row.marker()) {
+        "" => String::new(),
+        marker => format!("{marker} "),
+    };
+    format!(
+        "| {} | {} | {} | `{}` | `{}` | `{significant}{}` |\n",
+        table.name,
+        row.name,
+        row.measure,
+        readable(row.before.value, &row.before.unit).trim(),
+        readable(row.after.value, &row.after.unit).trim(),
+        row.format_delta(),
+    )
+}
+
+/// Post `body` as a comment on `commit_hash`, explaining the usual errors of the API. The
+/// request goes through a file in `scratch`.
+pub fn post(
+    api_url: &str,
+    repository: &str,
+    commit_hash: &str,
+    token: &str,
+    body: &str,
+    scratch: &Path,
+) -> Result<(), String> {
+    let url = format!(
+        "{}/repos/{repository}/commits/{commit_hash}/comments",
+        api_url.trim_end_matches('/')
+    );
+    let payload = serde_json::json!({ "body": body }).to_string();
+    let response = http::post_github_json(&url, &payload, token, TIMEOUT, scratch)
+        .map_err(|err| format!("failed to comment on the commit: {err}"))?;
+
+    let message = serde_json::from_str::<serde_json::Value>(&response.body)
+        .ok()
+        .and_then(|body| body["message"].as_str().map(str::to_owned))
+        .unwrap_or_else(|| response.body.trim().to_owned());
+    let reason = match response.status {
+        200..=299 => return Ok(()),
+        403 => "the token may not comment on commits, it needs `contents: write`",
+        404 => "the repository or the commit wasn't found, or the token can't see them",
+        422 => "GitHub rejected the comment",
+        _ => "unexpected response",
+    };
+    Err(format!(
+        "failed to comment on the commit: {reason} ({}: {message})",
+        response.status
+    ))
+}
+
+#[cfg(test)]
+fn comparisons_for_test() -> (BenchData, BenchData, Comparisons) {
+    let before = crate::testkit::BenchDataBuilder::cycles(
+        "1111111111111111111111111111111111111111",
+        &[(
+            "compress",
+            &[("./c 1", 1000.0), ("./c 2", 1000.0), ("./c 3", 1000.0)],
+        )],
+    )
+    .build();
+    let after = crate::testkit::BenchDataBuilder::cycles(
+        "2222222222222222222222222222222222222222",
+        &[(
+            "compress",
+            &[("./c 1", 1000.0), ("./c 2", 1200.0), ("./c 3", 900.0)],
+        )],
+    )
+    .build();
+    let config: crate::Config = serde_json::from_str(
+        r#"{
+            "commands": {},
+            "render-versus-self": {},
+            "render-versus-other": {
+                "compression": { "measure": "cycles", "command": "compress", "rows": { "level 1": 0, "level 2": 1, "level 3": 2 } }
+            }
+        }"#,
+    )
+    .unwrap();
+    let comparisons = Comparisons::collect(&config, &after, Some(&before));
+    (before, after, comparisons)
+}
+
+#[test]
+fn comment_with_top_movers() {
+    let (before, after, comparisons) = comparisons_for_test();
+    let gate = crate::gate::GateConfig {
+        max_regression_percent: 5.0,
+        annotations: false,
+        max_variance_increase: None,
+        baseline: Default::default(),
+        exempt_label: None,
+    }
+    .evaluate(&comparisons);
+
+    let comment = build_comment(
+        "owner/repo",
+        &after,
+        Some(&before),
+        &comparisons,
+        Some(&gate),
+        &Markers::default(),
+        MAX_COMMENT_LEN,
+    );
+    assert_eq!(
+        comment,
+        "### Benchmarks of [`2222222`](https://github.com/owner/repo/commit/2222222222222222222222222222222222222222)\n\n\
+         Compared with parent `1111111`: 1 significant regressions, 1 significant improvements. \
+         The gate failed with 1 failures.\n\n\
+         | table | row | measure | before | after | Δ |\n\
+         | --- | --- | --- | --- | --- | --- |\n\
+         | compression | level 2 | cycles | `1.00K` | `1.20K` | `💩 +16.67%` |\n\
+         | compression | level 3 | cycles | `1.00K` | `900` | `🚀 -11.11%` |\n\
+         | compression | level 1 | cycles | `1.00K` | `1.00K` | `+0.00%` |\n\n\
+         🚀 significant improvement · 💩 significant regression\n"
+    );
+
+    // Without `render-versus-other` tables, the raw comparisons, and without a gate.
+    let raw_only = Comparisons {
+        versus_other: vec![],
+        ..comparisons_for_test().2
+    };
+    let comment = build_comment(
+        "owner/repo",
+        &after,
+        Some(&before),
+        &raw_only,
+        None,
+        &Markers::default(),
+        MAX_COMMENT_LEN,
+    );
+    assert!(
+        comment.contains("significant improvements.\n\n| table |"),
+        "{comment}"
+    );
+    assert!(
+        comment.contains("| compress | ./c 2 (cycles) | cycles |"),
+        "{comment}"
+    );
+
+    let comment = build_comment(
+        "owner/repo",
+        &after,
+        None,
+        &comparisons,
+        None,
+        &Markers::default(),
+        1000,
+    );
+    assert!(comment.ends_with(")\n\nNo previous results to compare against.\n"));
+}
+
+#[test]
+fn truncated_comment() {
+    let (before, after, comparisons) = comparisons_for_test();
+    let full = build_comment(
+        "owner/repo",
+        &after,
+        Some(&before),
+        &comparisons,
+        None,
+        &Markers::default(),
+        MAX_COMMENT_LEN,
+    );
+    // The last row comes before the legend.
+    let last_row = format!("{}\n", full.lines().rev().nth(2).unwrap());
+    let last_row_len = last_row.len();
+    assert!(last_row.starts_with("| compression | level 1 |"));
+
+    // Only the last row doesn't fit.
+    let max_len = full.len() - last_row_len + 64;
+    let comment = build_comment(
+        "owner/repo",
+        &after,
+        Some(&before),
+        &comparisons,
+        None,
+        &Markers::default(),
+        max_len,
+    );
+    assert!(comment.len() <= max_len);
+    assert_eq!(
+        comment,
+        format!(
+            "{}\n_1 more rows didn't fit in a comment._\n",
+            full.replace(&last_row, "")
+        )
+    );
+    assert!(comment.contains("level 3"));
+    assert!(!comment.contains("level 1"));
+
+    // Nothing but the verdict fits.
+    let max_len = full.find("| compression").unwrap() + 64;
+    let comment = build_comment(
+        "owner/repo",
+        &after,
+        Some(&before),
+        &comparisons,
+        None,
+        &Markers::default(),
+        max_len,
+    );
+    assert!(comment.len() <= max_len);
+    assert!(comment.ends_with("\n_3 more rows didn't fit in a comment._\n"));
+}
+
+#[test]
+fn post_to_test_server() {
+    let (url, server) = http::serve(vec![
+        http::TestResponse::new(201, br#"{"id":1}"#),
+        http::TestResponse::new(
+            403,
+            br#"{"message":"Resource not accessible by integration"}"#,
+        ),
+        http::TestResponse::new(404, br#"{"message":"Not Found"}"#),
+        http::TestResponse::new(422, br#"{"message":"Validation Failed"}"#),
+        http::TestResponse::new(500, b"oops"),
+    ]);
+    let dir = crate::testkit::test_dir("comment-post");
+    let comment = || {
+        post(
+            &format!("{url}/"),
+            "owner/repo",
+            "2222",
+            "secret",
+            "body",
+            &dir,
+        )
+    };
+
+    comment().unwrap();
+    assert_eq!(
+        comment().unwrap_err(),
+        "failed to comment on the commit: the token may not comment on commits, it needs \
+         `contents: write` (403: Resource not accessible by integration)"
+    );
+    assert_eq!(
+        comment().unwrap_err(),
+        "failed to comment on the commit: the repository or the commit wasn't found, or the \
+         token can't see them (404: Not Found)"
+    );
+    assert_eq!(
+        comment().unwrap_err(),
+        "failed to comment on the commit: GitHub rejected the comment (422: Validation Failed)"
+    );
+    assert_eq!(
+        comment().unwrap_err(),
+        "failed to comment on the commit: unexpected response (500: oops)"
+    );
+
+    // Nothing is left behind in the scratch directory.
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+
+    let requests = server.join().unwrap();
+    assert_eq!(
+        requests[0].request_line,
+        "POST /repos/owner/repo/commits/2222/comments HTTP/1.1"
+    );
+    assert!(requests[0]
+        .headers
+        .contains(&("authorization".to_owned(), "Bearer secret".to_owned())));
+    assert_eq!(requests[0].body, br#"{"body":"body"}"#);
+
+    // Without a server.
+    let err = post_unreachable();
+    assert!(
+        err.starts_with("failed to comment on the commit: POST "),
+        "{err}"
+    );
+}
+
+#[cfg(test)]
+fn post_unreachable() -> String {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    drop(listener);
+    let dir = crate::testkit::test_dir("comment-unreachable");
+    post(&url, "owner/repo", "2222", "secret", "body", &dir).unwrap_err()
+}
+
+#[test]
+fn verdict_without_informational_rows() {
+    let (before, after, _) = comparisons_for_test();
+    let config: crate::Config = serde_json::from_str(
+        r#"{
+            "commands": {},
+            "render-versus-self": {},
+            "render-versus-other": {
+                "compression": { "measure": "cycles", "command": "compress", "rows": { "level 1": 0 } },
+                "reference": { "measure": "cycles", "command": "compress", "rows": { "level 2": 1, "level 3": 2 }, "informational": true }
+            }
+        }"#,
+    )
+    .unwrap();
+    let comparisons = Comparisons::collect(&config, &after, Some(&before));
+    assert!(comparisons.versus_other[1].rows[0].is_regression());
+    assert_eq!(
+        verdict(&comparisons, None),
+        "0 significant regressions, 0 significant improvements."
+    );
+    let comment = build_comment(
+        "owner/repo",
+        &after,
+        Some(&before),
+        &comparisons,
+        None,
+        &Markers::default(),
+        MAX_COMMENT_LEN,
+    );
+    assert!(!comment.contains("level 2"), "{comment}");
+}
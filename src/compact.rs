@@ -0,0 +1,454 @@
+//! `benchmarker compact <results> (<output> | --in-place) [--retain-days <n>] [--keep-every <n>]`:
+//! shrink a results file that grew with every run of the main branch, see [`crate::history`].
+//!
+//! Of the entries of the same commit from the same kind of machine and with the same groups,
+//! only the most recent one is kept, as a retried run replaces the results before it anyway.
+//! The groups are part of it as several suites store their results of a commit side by side.
+//!
+//! With `--retain-days`, the entries measured longer ago are dropped, except for one in
+//! `--keep-every` of them for the trends over the long term. Which ones is decided by their
+//! commit hash rather than their position, so that compacting again keeps the same ones, and
+//! the machines keep the results of the same commits.
+//!
+//! Lines that don't read as results are kept as they were, like by [`crate::migrate`]. The
+//! kept lines are copied as they were, in the order they were. With `--in-place` the results
+//! are replaced, after copying them to `<results>.bak`.
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use indexmap::IndexMap;
+use serde::de::IgnoredAny;
+use serde::Deserialize;
+
+use crate::history::{self, Index};
+use crate::machine;
+use crate::sha256::Sha256;
+
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Drop the entries measured longer ago than this before `now`.
+    pub retain: Option<Duration>,
+    /// Of the dropped entries, keep one in this many.
+    pub keep_every: Option<u64>,
+    pub now: Option<SystemTime>,
+}
+
+/// Just enough of an entry to decide whether to keep it.
+#[derive(Deserialize)]
+struct Entry {
+    commit_hash: String,
+    #[serde(default)]
+    dirty: bool,
+    timestamp: SystemTime,
+    arch: String,
+    os: String,
+    cpu_model: String,
+    #[serde(default)]
+    machine_class: Option<String>,
+    #[serde(default)]
+    machine_id: Option<String>,
+    bench_groups: IndexMap<String, IgnoredAny>,
+}
+
+impl Entry {
+    /// The entries that replace each other: of the same commit, machine and groups.
+    fn key(&self) -> (String, bool, String) {
+        (self.commit_hash.clone(), self.dirty, self.series())
+    }
+
+    /// The entries of the same kind of machine and groups, like
+    /// [`crate::baseline::same_machine`] tells apart.
+    fn series(&self) -> String {
+        let machine = match (&self.machine_id, &self.machine_class) {
+            (Some(id), _) => id.clone(),
+            (None, Some(class)) => class.clone(),
+            (None, None) => machine::normalize_model(&self.cpu_model),
+        };
+        let groups = self.bench_groups.keys().cloned().collect::<Vec<_>>();
+        format!(
+            "{}\n{}\n{machine}\n{}",
+            self.arch,
+            self.os,
+            groups.join("\n")
+        )
+    }
+
+    /// Whether the entry is one of the one in `every` entries kept after the retention
+    /// window, by the hash of its commit.
+    fn kept_for_trends(&self, every: u64) -> bool {
+        let mut sha256 = Sha256::default();
+        sha256.update(self.commit_hash.as_bytes());
+        let hash = u64::from_str_radix(&sha256.finish_hex()[..16], 16).unwrap();
+        hash.is_multiple_of(every)
+    }
+}
+
+/// The outcome of compacting the results.
+#[derive(Debug, Default, PartialEq)]
+pub struct Summary {
+    pub entries: usize,
+    pub kept: usize,
+    /// Entries replaced by a more recent one of the same commit, machine and groups.
+    pub duplicates: usize,
+    /// Entries older than the retention window.
+    pub expired: usize,
+    /// Lines kept as they were, as they don't read as results.
+    pub unreadable: usize,
+}
+
+/// Which lines of the results at `path` to keep, by line number, and the summary.
+fn select(path: &Path, options: &Options) -> Result<(Vec<bool>, Summary), String> {
+    let cutoff = options
+        .retain
+        .map(|retain| options.now.unwrap_or_else(SystemTime::now) - retain);
+
+    let mut summary = Summary::default();
+    let mut keep = vec![];
+    // The line of the most recent entry of every commit, machine and groups, and its time.
+    let mut latest = HashMap::<_, (usize, SystemTime)>::new();
+    history::for_each_line(path, |_, line| {
+        let line_number = keep.len();
+        if line.iter().all(u8::is_ascii_whitespace) {
+            keep.push(false);
+            return ControlFlow::Continue(());
+        }
+        summary.entries += 1;
+        let Ok(entry) = serde_json::from_slice::<Entry>(line) else {
+            summary.unreadable += 1;
+            keep.push(true);
+            return ControlFlow::Continue(());
+        };
+
+        if cutoff.is_some_and(|cutoff| entry.timestamp < cutoff)
+            && !options
+                .keep_every
+                .is_some_and(|every| entry.kept_for_trends(every))
+        {
+            summary.expired += 1;
+            keep.push(false);
+            return ControlFlow::Continue(());
+        }
+
+        keep.push(true);
+        match latest.get_mut(&entry.key()) {
+            // Of the same time, the later line.
+            Some((line, timestamp)) if *timestamp <= entry.timestamp => {
+                keep[*line] = false;
+                (*line, *timestamp) = (line_number, entry.timestamp);
+                summary.duplicates += 1;
+            }
+            Some(_) => {
+                keep[line_number] = false;
+                summary.duplicates += 1;
+            }
+            None => {
+                latest.insert(entry.key(), (line_number, entry.timestamp));
+            }
+        }
+        ControlFlow::Continue(())
+    })?;
+    summary.kept = keep.iter().filter(|&&keep| keep).count();
+    Ok((keep, summary))
+}
+
+/// Compact the results at `results` to `output`, or in place after a backup when `None`.
+pub fn compact(
+    results: &Path,
+    output: Option<&Path>,
+    options: &Options,
+) -> Result<Summary, String> {
+    if let Some(output) = output.filter(|output| output.exists()) {
+        return Err(format!(
+            "{} already exists, compact to a new file or use --in-place",
+            output.display()
+        ));
+    }
+    let (keep, summary) = select(results, options)?;
+
+    let target = match output {
+        Some(output) => output.to_owned(),
+        None => {
+            let mut backup = results.as_os_str().to_owned();
+            backup.push(".bak");
+            let backup = PathBuf::from(backup);
+            if backup.exists() {
+                return Err(format!(
+                    "the backup {} already exists, move it away first",
+                    backup.display()
+                ));
+            }
+            fs::copy(results, &backup)
+                .map_err(|e| format!("failed to back up {}: {e}", results.display()))?;
+            let mut tmp = results.as_os_str().to_owned();
+            tmp.push(format!(".{}.tmp", std::process::id()));
+            PathBuf::from(tmp)
+        }
+    };
+
+    let write_error = |e: std::io::Error| format!("failed to write {}: {e}", target.display());
+    let file = File::create(&target).map_err(write_error)?;
+    let mut writer = BufWriter::new(file);
+    let mut written = Ok(());
+    let mut keep = keep.into_iter();
+    history::for_each_line(results, |_, line| {
+        if keep.next() == Some(true) {
+            written = writer
+                .write_all(line)
+                .and_then(|()| writer.write_all(b"\n"));
+        }
+        if written.is_err() {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    })?;
+    written.and_then(|()| writer.flush()).map_err(write_error)?;
+    drop(writer);
+
+    if output.is_none() {
+        fs::rename(&target, results).map_err(|e| {
+            let _ = fs::remove_file(&target);
+            format!("failed to replace {}: {e}", results.display())
+        })?;
+        // It would be rebuilt anyway, as the results changed.
+        let _ = fs::remove_file(Index::path(results));
+    }
+    Ok(summary)
+}
+
+/// What the compaction did, for the terminal.
+fn render_summary(summary: &Summary) -> String {
+    let mut out = format!("kept {} of {} entries\n", summary.kept, summary.entries);
+    writeln!(out, "  duplicates: {}", summary.duplicates).unwrap();
+    writeln!(out, "  expired: {}", summary.expired).unwrap();
+    if summary.unreadable > 0 {
+        writeln!(
+            out,
+            "kept {} lines that aren't results as they were",
+            summary.unreadable
+        )
+        .unwrap();
+    }
+    out
+}
+
+pub fn run(args: impl IntoIterator<Item = String>) -> Result<String, String> {
+    const USAGE: &str = "expected the arguments compact <results> (<output> | --in-place) [--retain-days <n>] [--keep-every <n>]";
+
+    let mut positional = vec![];
+    let mut in_place = false;
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+    let positive = |flag: &str, value: Option<String>| {
+        let value = value.ok_or_else(|| format!("expected a number after {flag}"))?;
+        match value.parse::<u64>() {
+            Ok(number) if number > 0 => Ok(number),
+            _ => Err(format!(
+                "expected a positive number after {flag}, got {value}"
+            )),
+        }
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--in-place" => in_place = true,
+            "--retain-days" => {
+                let days = positive("--retain-days", args.next())?;
+                options.retain = Some(Duration::from_secs(days * 24 * 60 * 60));
+            }
+            "--keep-every" => options.keep_every = Some(positive("--keep-every", args.next())?),
+            _ => positional.push(PathBuf::from(arg)),
+        }
+    }
+    if options.keep_every.is_some() && options.retain.is_none() {
+        return Err("`--keep-every` requires `--retain-days`".to_owned());
+    }
+    let (results, output) = match (positional.as_slice(), in_place) {
+        ([results], true) => (results, None),
+        ([results, output], false) => (results, Some(output.as_path())),
+        _ => return Err(USAGE.to_owned()),
+    };
+    let summary = compact(results, output, &options)?;
+    Ok(render_summary(&summary))
+}
+
+/// A day after the last entry of [`history::results_for_test`] of `count` entries.
+#[cfg(test)]
+fn now_for_test(count: usize) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(count as u64 * 3600 + 24 * 3600)
+}
+
+/// The results of [`history::results_for_test`], with the entry of every line measured an
+/// hour after the previous one.
+#[cfg(test)]
+fn results_for_test(count: usize) -> String {
+    history::results_for_test(count)
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            let mut entry = serde_json::from_str::<serde_json::Value>(line).unwrap();
+            entry["timestamp"] =
+                serde_json::json!({ "secs_since_epoch": i * 3600, "nanos_since_epoch": 0 });
+            entry.to_string() + "\n"
+        })
+        .collect()
+}
+
+#[test]
+fn compact_duplicates() {
+    let dir = crate::testkit::test_dir("compact-duplicates");
+    let path = dir.join("results.json");
+    let results = results_for_test(600);
+    let lines = results.lines().collect::<Vec<_>>();
+    // Retried runs of the same commit on the same machine, one with other groups, and lines
+    // that aren't results.
+    let retried = lines[10].replace("\"secs_since_epoch\":36000", "\"secs_since_epoch\":3600000");
+    let other_suite = lines[10].replace("\"decompress\"", "\"compress\"");
+    let mut text = results.clone();
+    text.push_str(&format!("{retried}\n{other_suite}\nnot results\n\n"));
+    fs::write(&path, &text).unwrap();
+
+    let output = dir.join("compacted.json");
+    let summary = compact(&path, Some(&output), &Options::default()).unwrap();
+    assert_eq!(
+        summary,
+        Summary {
+            entries: 603,
+            kept: 602,
+            duplicates: 1,
+            expired: 0,
+            unreadable: 1,
+        }
+    );
+    let compacted = fs::read_to_string(&output).unwrap();
+    let kept = compacted.lines().collect::<Vec<_>>();
+    assert_eq!(kept.len(), 602);
+    // The retried run replaces the first one, in its own place.
+    assert!(!kept.contains(&lines[10]));
+    assert_eq!(kept[599..], [&retried, &other_suite, "not results"]);
+    assert_eq!(kept[..10], lines[..10]);
+
+    // Compacting again changes nothing.
+    let again = dir.join("again.json");
+    let summary = compact(&output, Some(&again), &Options::default()).unwrap();
+    assert_eq!((summary.kept, summary.duplicates), (602, 0));
+    assert_eq!(fs::read_to_string(&again).unwrap(), compacted);
+
+    // The output is never overwritten.
+    let err = compact(&path, Some(&output), &Options::default()).unwrap_err();
+    assert!(err.ends_with("compacted.json already exists, compact to a new file or use --in-place"));
+}
+
+#[test]
+fn compact_retention() {
+    let dir = crate::testkit::test_dir("compact-retention");
+    let path = dir.join("results.json");
+    let results = results_for_test(1000);
+    fs::write(&path, &results).unwrap();
+
+    // Ten days of an entry an hour, then a day: only the entries of the last 2 days stay.
+    let two_days = Options {
+        retain: Some(Duration::from_secs(2 * 24 * 3600)),
+        keep_every: None,
+        now: Some(now_for_test(1000)),
+    };
+    let output = dir.join("retained.json");
+    let summary = compact(&path, Some(&output), &two_days).unwrap();
+    assert_eq!((summary.kept, summary.expired), (24, 976));
+    let retained = fs::read_to_string(&output).unwrap();
+    assert!(results.ends_with(&retained));
+
+    // Of the older ones, one in ten by commit, so both machines keep the same commits.
+    let keep_every = Options {
+        keep_every: Some(10),
+        ..two_days.clone()
+    };
+    let output = dir.join("trends.json");
+    let summary = compact(&path, Some(&output), &keep_every).unwrap();
+    let trends = summary.kept - 24;
+    assert!((60..=140).contains(&trends), "{summary:?}");
+    assert_eq!(trends % 2, 0, "{summary:?}");
+    let compacted = fs::read_to_string(&output).unwrap();
+    assert!(compacted.ends_with(&retained));
+
+    // Compacting again keeps the same entries.
+    let again = dir.join("again.json");
+    let summary = compact(&output, Some(&again), &keep_every).unwrap();
+    assert_eq!(summary.expired, 0);
+    assert_eq!(fs::read_to_string(&again).unwrap(), compacted);
+}
+
+#[test]
+fn compact_in_place() {
+    let dir = crate::testkit::test_dir("compact-in-place");
+    let path = dir.join("results.json");
+    let results = results_for_test(100);
+    fs::write(
+        &path,
+        format!("{results}{}", results.lines().last().unwrap()),
+    )
+    .unwrap();
+    Index::refresh(&path).unwrap();
+
+    let output = run([path.display().to_string(), "--in-place".to_owned()]).unwrap();
+    assert_eq!(
+        output,
+        "kept 100 of 101 entries\n  duplicates: 1\n  expired: 0\n"
+    );
+    assert_eq!(fs::read_to_string(&path).unwrap(), results);
+    assert!(fs::read_to_string(dir.join("results.json.bak"))
+        .unwrap()
+        .starts_with(&results));
+    assert!(!Index::path(&path).exists());
+
+    // The backup isn't overwritten.
+    let err = run([path.display().to_string(), "--in-place".to_owned()]).unwrap_err();
+    assert!(err.contains("results.json.bak already exists"), "{err}");
+}
+
+#[test]
+fn compact_args() {
+    for args in [&[][..], &["a.json"], &["a.json", "b.json", "--in-place"]] {
+        assert_eq!(
+            run(args.iter().map(|arg| arg.to_string())).unwrap_err(),
+            "expected the arguments compact <results> (<output> | --in-place) [--retain-days <n>] [--keep-every <n>]"
+        );
+    }
+    assert_eq!(
+        run(["a.json", "b.json", "--retain-days", "0"].map(str::to_owned)).unwrap_err(),
+        "expected a positive number after --retain-days, got 0"
+    );
+    assert_eq!(
+        run(["a.json", "b.json", "--keep-every", "2"].map(str::to_owned)).unwrap_err(),
+        "`--keep-every` requires `--retain-days`"
+    );
+}
+
+#[test]
+fn series_by_machine() {
+    let series = |machine: &str| {
+        let entry = format!(
+            r#"{{ "commit_hash": "abc", "timestamp": {{ "secs_since_epoch": 0, "nanos_since_epoch": 0 }},
+                 "arch": "X64", "os": "Linux", {machine}, "bench_groups": {{ "compress": [] }} }}"#
+        );
+        serde_json::from_str::<Entry>(&entry).unwrap().series()
+    };
+    // The entries from before the identifiers by their normalized models.
+    assert_eq!(
+        series(r#""cpu_model": "Intel(R) Xeon(R) CPU E5-2690 v4 @ 2.60GHz""#),
+        series(r#""cpu_model": "Intel(R) Xeon(R)  CPU E5-2690 v4 @ 2.60GHz""#)
+    );
+    assert_eq!(
+        series(r#""cpu_model": "AMD EPYC 7763 64-Core Processor", "machine_id": "a""#),
+        series(r#""cpu_model": "AMD EPYC 7763  64-Core Processor", "machine_id": "a""#)
+    );
+    assert_ne!(
+        series(r#""cpu_model": "AMD EPYC 7763 64-Core Processor", "machine_id": "a""#),
+        series(r#""cpu_model": "AMD EPYC 7763 64-Core Processor", "machine_id": "b""#)
+    );
+}
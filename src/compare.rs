@@ -0,0 +1,2330 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+use crate::annotations::ConfigSpan;
+use crate::baseline::BaselineAnomaly;
+use crate::bench::{BenchCounter, SingleBench};
+use crate::build_info::{self, BuildDifference};
+use crate::cache_stats;
+use crate::cross_machine::CrossMachine;
+use crate::drift::Drift;
+use crate::import::{self, ImportedCrossClass, MachineClasses};
+use crate::intervals::{self, ShapeChange};
+use crate::limits::{self, Efficiency};
+use crate::machine::{self, CrossClass};
+use crate::markers::{Marker, Markers};
+use crate::measure::MeasureKind;
+use crate::preemption::{self, Preempted};
+use crate::profile::{self, HotFunctionChange};
+use crate::quality::GroupQuality;
+use crate::rolling::RollingChange;
+use crate::sample::{self, SamplingMismatch};
+use crate::{comparison_key, repro, rusage, sentinel};
+use crate::{readable, BenchData, Config, Reference, TableDisplay, VersusOther, VersusSelf};
+
+/// All comparisons of a run.
+#[derive(Debug, Default, Serialize)]
+pub struct Comparisons {
+    /// The `render-versus-other` tables. Empty when there are no previous results.
+    pub versus_other: Vec<ComparisonTable>,
+    /// The `render-versus-self` tables.
+    pub versus_self: Vec<ComparisonTable>,
+    /// The raw comparisons of every group against the previous results, one table per group.
+    /// Without previous results, the tables are empty.
+    pub raw: Vec<ComparisonTable>,
+    /// The tables of `raw` for the `control-groups`.
+    pub control: Vec<ComparisonTable>,
+    /// Symbols whose share of the profile moved. Empty when there are no previous results.
+    pub hot_functions: Vec<HotFunctionChange>,
+    /// Set when the previous results are from a different class of machine. Only the
+    /// `machine-stable-counters` are then compared against them.
+    pub cross_class: Option<CrossClass>,
+    /// The imported commands measured on another class of machine than their baseline. Only
+    /// their `machine-stable-counters` are compared, see [`crate::import`].
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub imported_cross_class: Vec<ImportedCrossClass>,
+    /// Set when the previous results deviate from the results of the commits before them.
+    pub baseline_anomaly: Option<BaselineAnomaly>,
+    /// The fingerprinted binaries under test are the same as those of the previous results.
+    pub identical_binaries: bool,
+    /// The binaries built differently than those of the previous results, when `build-info`
+    /// is configured, see [`crate::build_info`].
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub build_differences: Vec<BuildDifference>,
+    /// The groups that sampled other files of their corpora than the previous results, see
+    /// [`crate::sample`].
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sampling_mismatches: Vec<SamplingMismatch>,
+    /// How close the commands are to their `theoretical-limits`, see [`crate::limits`].
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub efficiencies: Vec<Efficiency>,
+    /// Commands whose course over a run changed shape. Empty when there are no previous
+    /// results.
+    pub shape_changes: Vec<ShapeChange>,
+    /// The results of the same commit on other machines, when `render-cross-machine` is
+    /// configured and there are any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cross_machine: Option<CrossMachine>,
+    /// The measures that rose slowly over the history, when `drift-alarm` is configured, see
+    /// [`crate::drift`].
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub drift: Vec<Drift>,
+    /// How much the measurements of every group vary, when `measurement-quality` is
+    /// configured.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub quality: Vec<GroupQuality>,
+    /// The sentinel group at the end of the run versus the start, when `sentinel-group` is
+    /// configured, see [`crate::sentinel`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stability: Option<ComparisonTable>,
+    /// The command lines to reproduce every command of the raw tables with `repro`, by group,
+    /// see [`crate::repro`].
+    #[serde(skip)]
+    pub raw_repro: IndexMap<String, Vec<(String, String)>>,
+}
+
+impl Comparisons {
+    pub fn collect(config: &Config, data: &BenchData, prev_results: Option<&BenchData>) -> Self {
+        let cross_class = prev_results.and_then(|prev_results| {
+            machine::cross_class(
+                prev_results.machine_class.as_deref(),
+                data.machine_class.as_deref(),
+            )
+        });
+        let stable_counters = cross_class
+            .as_ref()
+            .map(|_| config.machine_stable_counters.as_slice());
+
+        let versus_other = match prev_results {
+            Some(prev_results) => collect_versus_other(
+                &config.render_versus_other,
+                &config.measure_kinds,
+                stable_counters,
+                prev_results,
+                data,
+            ),
+            None => vec![],
+        };
+
+        let mut raw = data
+            .bench_groups
+            .keys()
+            .map(|group_name| {
+                collect_raw_versus_parent(
+                    group_name,
+                    &config.measure_kinds,
+                    &config.machine_stable_counters,
+                    data,
+                    prev_results,
+                )
+            })
+            .collect::<Vec<_>>();
+        if !config.show_cold_warm {
+            for table in &mut raw {
+                table
+                    .rows
+                    .retain(|row| !rusage::is_cold_or_warm(&row.measure));
+            }
+        }
+
+        let shape_changes = match prev_results {
+            Some(prev_results) => {
+                intervals::collect_shape_changes(&config.intervals, data, prev_results)
+            }
+            None => vec![],
+        };
+
+        let hot_functions = match prev_results {
+            Some(prev_results) => profile::collect_changes(&config.profile, data, prev_results),
+            None => vec![],
+        };
+
+        let mut comparisons = Comparisons {
+            versus_other,
+            versus_self: collect_versus_self(
+                &config.render_versus_self,
+                &config.measure_kinds,
+                data,
+            ),
+            raw,
+            control: vec![],
+            hot_functions,
+            shape_changes,
+            cross_class,
+            imported_cross_class: prev_results
+                .map(|prev_results| import::collect_cross_class(data, prev_results))
+                .unwrap_or_default(),
+            baseline_anomaly: None,
+            build_differences: vec![],
+            sampling_mismatches: vec![],
+            efficiencies: vec![],
+            cross_machine: None,
+            drift: vec![],
+            quality: config
+                .measurement_quality
+                .as_ref()
+                .map(|quality| quality.collect(data, prev_results))
+                .unwrap_or_default(),
+            stability: sentinel::collect(config, &config.measure_kinds, data),
+            raw_repro: IndexMap::new(),
+            identical_binaries: config.fingerprint.as_ref().zip(prev_results).is_some_and(
+                |(fingerprint, prev_results)| {
+                    fingerprint.identical(&prev_results.binary_hashes, &data.binary_hashes)
+                },
+            ),
+        };
+        if let Some(prev_results) = prev_results.filter(|_| config.build_info.is_some()) {
+            comparisons.note_build_differences(config, data, prev_results);
+        }
+        if let Some(prev_results) = prev_results {
+            comparisons.note_sampling_mismatches(config, data, prev_results);
+        }
+        comparisons.note_efficiencies(config, data);
+        comparisons.apply_correction(config.correction);
+        comparisons.apply_minimum_effect(config.minimum_effect_percent);
+
+        // After the correction, so the control groups get the corrected verdicts too.
+        comparisons.control = comparisons
+            .raw
+            .iter()
+            .filter(|table| config.control_groups.contains(&table.name))
+            .cloned()
+            .collect();
+
+        comparisons
+    }
+
+    /// Note the binaries built differently than in the previous results on the tables against
+    /// them whose commands run those binaries. The `render-versus-self` tables compare two
+    /// commands of the same build.
+    fn note_build_differences(&mut self, config: &Config, data: &BenchData, prev: &BenchData) {
+        self.build_differences = build_info::collect(&prev.build_info, &data.build_info);
+        if self.build_differences.is_empty() {
+            return;
+        }
+        for table in &mut self.versus_other {
+            let render = &config.render_versus_other[&table.name];
+            let group = &data.bench_groups[&render.command];
+            let benches = render.rows.values().filter_map(|&index| group.get(index));
+            table.build_notes = build_info::notes(&self.build_differences, benches);
+        }
+        for table in &mut self.raw {
+            let benches = &data.bench_groups[&table.name];
+            table.build_notes = build_info::notes(&self.build_differences, benches);
+        }
+    }
+
+    /// Warn on the tables against the previous results of the groups that sampled other files
+    /// of their corpora.
+    fn note_sampling_mismatches(&mut self, config: &Config, data: &BenchData, prev: &BenchData) {
+        self.sampling_mismatches = sample::collect_mismatches(prev, data);
+        for mismatch in &self.sampling_mismatches {
+            let note = sample::note(mismatch);
+            for table in &mut self.versus_other {
+                if config.render_versus_other[&table.name].command == mismatch.group {
+                    table.sampling_note = Some(note.clone());
+                }
+            }
+            for table in &mut self.raw {
+                if table.name == mismatch.group {
+                    table.sampling_note = Some(note.clone());
+                }
+            }
+        }
+    }
+
+    /// Annotate the rows of the commands with a theoretical limit of their measure with how
+    /// close they are to it. The compared command is the one after the change.
+    fn note_efficiencies(&mut self, config: &Config, data: &BenchData) {
+        self.efficiencies = limits::collect(&config.theoretical_limits, data);
+        if self.efficiencies.is_empty() {
+            return;
+        }
+        let efficiencies = &self.efficiencies;
+        let annotation = |group_name: &str, index: usize, measure: &str| {
+            limits::annotations(efficiencies, group_name, index, Some(measure)).next()
+        };
+        for table in &mut self.versus_other {
+            let render = &config.render_versus_other[&table.name];
+            for row in &mut table.rows {
+                row.efficiency = annotation(&render.command, render.rows[&row.name], &row.measure);
+            }
+        }
+        for table in &mut self.versus_self {
+            let render = &config.render_versus_self[&table.name];
+            for row in &mut table.rows {
+                let after = &render.rows[&row.name].after;
+                row.efficiency = annotation(&after.command, after.index, &row.measure);
+            }
+        }
+        for table in &mut self.raw {
+            for efficiency in efficiencies.iter().filter(|e| e.group == table.name) {
+                let name = format!("{} ({})", efficiency.command, efficiency.measure);
+                for row in table.rows.iter_mut().filter(|row| row.name == name) {
+                    row.efficiency = Some(limits::annotation(efficiency));
+                }
+            }
+        }
+    }
+
+    /// Decide which rows are significant, correcting for the number of comparisons in the
+    /// whole report.
+    ///
+    /// The comparisons are the raw and the `render-versus-self` rows, but for those of
+    /// `informational` tables, which only get the verdict. The `render-versus-other` rows
+    /// repeat raw rows, so they don't count again, but get the same verdict.
+    pub fn apply_correction(&mut self, correction: Correction) {
+        // The verdict of the t-test as computed for the row.
+        if let Some(cutoff) = self.cutoff(correction) {
+            self.apply_cutoff(cutoff);
+        }
+    }
+
+    /// Decide which rows are significant: those with a p-value of at most `cutoff`. The run
+    /// stability rows don't count for the cutoff, but get it too.
+    pub fn apply_cutoff(&mut self, cutoff: f64) {
+        for table in self
+            .versus_other
+            .iter_mut()
+            .chain(&mut self.versus_self)
+            .chain(&mut self.raw)
+            .chain(&mut self.stability)
+        {
+            for row in &mut table.rows {
+                row.significant = row.p_value <= cutoff;
+            }
+        }
+    }
+
+    /// The largest p-value that is still significant with the `correction`, or `None` when
+    /// the t-test of every comparison decides on its own.
+    pub fn cutoff(&self, correction: Correction) -> Option<f64> {
+        self.cutoff_with_pending(correction, 0)
+    }
+
+    /// The [`Self::cutoff`] when `pending` comparisons are still to come, as with
+    /// `--fail-fast`. Their p-values are unknown, so the cutoff of Benjamini-Hochberg is
+    /// bounded by that of Bonferroni with all comparisons: a p-value below it is significant
+    /// whatever the p-values to come.
+    pub fn cutoff_with_pending(&self, correction: Correction, pending: usize) -> Option<f64> {
+        let mut p_values = counted_rows(&self.raw)
+            .chain(counted_rows(&self.versus_self))
+            .map(|(_, row)| row.p_value)
+            .collect::<Vec<_>>();
+        let m = (p_values.len() + pending) as f64;
+
+        match correction {
+            Correction::None => None,
+            Correction::Bonferroni => Some(SIGNIFICANCE_LEVEL / m),
+            Correction::BenjaminiHochberg if pending > 0 => Some(SIGNIFICANCE_LEVEL / m),
+            Correction::BenjaminiHochberg => {
+                p_values.sort_by(f64::total_cmp);
+                // The p-value with the largest rank that is below the threshold for its rank.
+                // All smaller p-values are significant, even when above their own threshold.
+                let cutoff = p_values
+                    .iter()
+                    .enumerate()
+                    .rev()
+                    .find(|&(i, &p_value)| p_value <= (i + 1) as f64 / m * SIGNIFICANCE_LEVEL)
+                    .map_or(f64::NEG_INFINITY, |(_, &p_value)| p_value);
+                Some(cutoff)
+            }
+        }
+    }
+
+    /// Set the `minimum-effect-percent` of every row: the one of its table, or else `global`.
+    fn apply_minimum_effect(&mut self, global: Option<f64>) {
+        for table in self
+            .versus_other
+            .iter_mut()
+            .chain(&mut self.versus_self)
+            .chain(&mut self.raw)
+            .chain(&mut self.stability)
+        {
+            let minimum_effect = table.display.minimum_effect_percent.or(global);
+            for row in &mut table.rows {
+                row.minimum_effect = minimum_effect;
+            }
+        }
+    }
+
+    /// Rows of the control groups that changed significantly. The control groups are not
+    /// expected to change between commits, so this indicates a noisy or changed machine.
+    pub fn control_drift(&self) -> impl Iterator<Item = (&ComparisonTable, &ComparisonRow)> {
+        self.control
+            .iter()
+            .flat_map(|table| table.rows.iter().map(move |row| (table, row)))
+            .filter(|(_, row)| row.is_actionable())
+    }
+
+    /// The changes of the `render-versus-other` and `render-versus-self` rows per tag, by tag
+    /// name.
+    pub fn tag_rollups(&self) -> Vec<TagRollup> {
+        let mut rows_by_tag = BTreeMap::<&str, Vec<&ComparisonRow>>::new();
+        for (_, row) in counted_rows(&self.versus_other).chain(counted_rows(&self.versus_self)) {
+            for tag in &row.tags {
+                rows_by_tag.entry(tag).or_default().push(row);
+            }
+        }
+
+        rows_by_tag
+            .into_iter()
+            .map(|(tag, rows)| TagRollup {
+                tag: tag.to_owned(),
+                rows: rows.len(),
+                improvements: rows.iter().filter(|row| row.is_improvement()).count(),
+                regressions: rows.iter().filter(|row| row.is_regression()).count(),
+                geomean_delta_percent: geomean_delta_percent(rows.iter().copied()),
+            })
+            .collect()
+    }
+
+    /// Significant regressions versus the parent commit in the `render-versus-other` tables.
+    pub fn regressions(&self) -> impl Iterator<Item = (&ComparisonTable, &ComparisonRow)> {
+        counted_rows(&self.versus_other).filter(|(_, row)| row.is_regression())
+    }
+
+    /// Significant increases of the spread versus the parent commit in the
+    /// `render-versus-other` tables with `compare-variance`.
+    pub fn variance_regressions(&self) -> impl Iterator<Item = (&ComparisonTable, &ComparisonRow)> {
+        counted_rows(&self.versus_other).filter(|(_, row)| {
+            row.variance
+                .as_ref()
+                .is_some_and(VarianceChange::is_regression)
+        })
+    }
+}
+
+/// The comparison rows of the commands with a tag, taken together.
+#[derive(Debug, PartialEq)]
+pub struct TagRollup {
+    pub tag: String,
+    pub rows: usize,
+    pub improvements: usize,
+    pub regressions: usize,
+    pub geomean_delta_percent: f64,
+}
+
+pub fn render_tag_rollups(md: &mut String, rollups: &[TagRollup], markers: &Markers) {
+    if rollups.is_empty() {
+        return;
+    }
+
+    writeln!(md, "### Tags\n").unwrap();
+    writeln!(md, "| tag | rows | improvements | regressions | Δ |").unwrap();
+    writeln!(md, "| --- | --- | --- | --- | --- |").unwrap();
+    for rollup in rollups {
+        writeln!(
+            md,
+            "| {} | {} | {} | {} | `geomean {:>+6.2}%` |",
+            rollup.tag,
+            rollup.rows,
+            with_marker(&markers.improvement, rollup.improvements),
+            with_marker(&markers.regression, rollup.regressions),
+            rollup.geomean_delta_percent,
+        )
+        .unwrap();
+    }
+
+    let used = rollups.iter().flat_map(|rollup| {
+        [
+            (rollup.improvements > 0).then_some(Marker::Improvement),
+            (rollup.regressions > 0).then_some(Marker::Regression),
+        ]
+    });
+    if let Some(legend) = markers.legend(used.flatten()) {
+        writeln!(md, "\n{legend}").unwrap();
+    }
+    writeln!(md).unwrap();
+}
+
+/// `count`, after the `marker` when there is one.
+fn with_marker(marker: &str, count: usize) -> String {
+    match marker {
+        "" => count.to_string(),
+        marker => format!("{marker} {count}"),
+    }
+}
+
+/// The significance level of the t-tests, before any correction.
+const SIGNIFICANCE_LEVEL: f64 = 0.05;
+
+/// How to correct for the many comparisons in a report: at a 95% confidence level, one in
+/// twenty comparisons of unchanged benchmarks is significant by chance.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Correction {
+    #[default]
+    None,
+    /// Control the probability of any false positive. Strict with many rows.
+    Bonferroni,
+    /// Control the expected fraction of false positives among the significant rows.
+    BenjaminiHochberg,
+}
+
+/// What the two sides of a comparison are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ComparisonKind {
+    /// The same command measured on the parent commit and on the current commit.
+    VersusParent,
+    /// Two different commands measured on the current commit.
+    VersusSelf,
+}
+
+/// A table of comparisons, resolved from the config before anything gets rendered.
+#[derive(Debug, Clone, Serialize)]
+pub struct ComparisonTable {
+    pub name: String,
+    pub kind: ComparisonKind,
+    pub rows: Vec<ComparisonRow>,
+    #[serde(skip)]
+    pub display: TableDisplay,
+    /// How many results the rolling baseline pools, when the rows are compared against it
+    /// too, see [`crate::rolling`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rolling_window: Option<usize>,
+    /// The binaries the commands of the table run that were built differently than in the
+    /// baseline, see [`crate::build_info`].
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub build_notes: Vec<String>,
+    /// Set when the group of the table sampled other files of its corpora than the baseline,
+    /// see [`crate::sample`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sampling_note: Option<String>,
+}
+
+impl ComparisonTable {
+    /// The name of the table, and whether it is `informational`.
+    pub fn heading(&self) -> String {
+        match self.display.informational {
+            true => format!("{} (informational)", self.name),
+            false => self.name.clone(),
+        }
+    }
+
+    /// Split the rows into the ones to show and the ones to omit because of `max-rows`.
+    ///
+    /// The rows with the largest absolute change are shown, with ties broken by row name.
+    /// Significant rows are always shown, unless their change is below the minimum effect. Both
+    /// lists keep the config order.
+    pub fn select_rows(&self) -> (Vec<&ComparisonRow>, Vec<&ComparisonRow>) {
+        let Some(max_rows) = self.display.max_rows else {
+            return (self.rows.iter().collect(), vec![]);
+        };
+
+        let mut by_change = (0..self.rows.len()).collect::<Vec<_>>();
+        by_change.sort_by(|&a, &b| {
+            let (a, b) = (&self.rows[a], &self.rows[b]);
+            b.delta_percent
+                .abs()
+                .total_cmp(&a.delta_percent.abs())
+                .then_with(|| a.name.cmp(&b.name))
+        });
+
+        let mut shown = vec![false; self.rows.len()];
+        for &index in by_change.iter().take(max_rows) {
+            shown[index] = true;
+        }
+
+        let mut selected = vec![];
+        let mut omitted = vec![];
+        for (row, shown) in self.rows.iter().zip(shown) {
+            if shown || row.is_actionable() {
+                selected.push(row);
+            } else {
+                omitted.push(row);
+            }
+        }
+        (selected, omitted)
+    }
+
+    /// Render the rows of the table below the given header lines, followed by the legend of
+    /// the markers of the rows, if any. The rows are rendered in the `order` of the table, with
+    /// a caption above the header when that order needs explaining, below the notes of the
+    /// binaries built differently than in the baseline.
+    pub fn render_markdown(&self, md: &mut String, header: &str, markers: &Markers) {
+        sample::render_markdown_note(md, self.sampling_note.as_deref());
+        build_info::render_markdown_note(md, &self.build_notes);
+        let (mut shown, omitted) = self.select_rows();
+        self.display.order.sort(&mut shown);
+        if let Some(caption) = self.display.order.caption(&shown) {
+            writeln!(md, "{caption}\n").unwrap();
+        }
+        let mut header = header.to_owned();
+        if let Some(window) = self.rolling_window {
+            header = with_column(&header, &format!("vs rolling({window})"));
+        }
+        if self.display.compare_variance {
+            header = with_column(&header, "CoV Δ");
+        }
+        let header = &header;
+
+        md.push_str(header);
+        for row in &shown {
+            row.render_markdown_row(md, self.rolling_window, markers);
+        }
+
+        if !omitted.is_empty() {
+            self.render_markdown_omitted(md, header, &omitted, markers);
+        }
+
+        // Omitted rows are only rendered in the collapsed section.
+        let rendered = if omitted.is_empty() || !self.display.show_all_in_details {
+            shown
+        } else {
+            self.ordered_rows()
+        };
+        let used = rendered
+            .iter()
+            .flat_map(|row| {
+                [
+                    Some(row.marker()),
+                    row.rolling_marker(),
+                    row.unreliable_marker(),
+                ]
+            })
+            .flatten();
+        if let Some(legend) = markers.legend(used) {
+            writeln!(md, "\n{legend}").unwrap();
+        }
+        preemption::render_markdown_notes(
+            md,
+            rendered
+                .iter()
+                .filter_map(|row| Some((row.name.as_str(), row.preempted.as_ref()?))),
+        );
+
+        repro::render_markdown(
+            md,
+            rendered
+                .iter()
+                .filter_map(|row| Some((row.name.as_str(), row.repro.as_deref()?))),
+        );
+    }
+
+    /// The summary of the `omitted` rows, and all rows in a collapsed section when asked for.
+    fn render_markdown_omitted(
+        &self,
+        md: &mut String,
+        header: &str,
+        omitted: &[&ComparisonRow],
+        markers: &Markers,
+    ) {
+        writeln!(
+            md,
+            "| {} more rows | | | `geomean {:>+6.2}%` ({} significant) |{}",
+            omitted.len(),
+            geomean_delta_percent(omitted.iter().copied()),
+            omitted.iter().filter(|row| row.is_actionable()).count(),
+            " |".repeat(
+                usize::from(self.rolling_window.is_some())
+                    + usize::from(self.display.compare_variance)
+            ),
+        )
+        .unwrap();
+
+        if self.display.show_all_in_details {
+            // GitHub only renders markdown inside <details> when surrounded by blank lines.
+            writeln!(md, "\n<details>\n<summary>All rows</summary>\n").unwrap();
+            md.push_str(header);
+            for row in self.ordered_rows() {
+                row.render_markdown_row(md, self.rolling_window, markers);
+            }
+            writeln!(md, "\n</details>\n").unwrap();
+        }
+    }
+
+    /// All rows, in the `order` of the table.
+    fn ordered_rows(&self) -> Vec<&ComparisonRow> {
+        let mut rows = self.rows.iter().collect::<Vec<_>>();
+        self.display.order.sort(&mut rows);
+        rows
+    }
+}
+
+/// The rows of the `tables` that count, with their table: all but those of `informational`
+/// tables. Everything that counts, gates or notifies on the comparisons goes through this, only
+/// the tables themselves render every row.
+pub fn counted_rows(
+    tables: &[ComparisonTable],
+) -> impl Iterator<Item = (&ComparisonTable, &ComparisonRow)> {
+    tables
+        .iter()
+        .flat_map(|table| table.rows.iter().map(move |row| (table, row)))
+        .filter(|(_, row)| !row.informational)
+}
+
+/// The geometric mean of the change of the given rows, expressed the same way as
+/// [`BenchCounter::improvement_percentage`]: relative to the new value.
+pub fn geomean_delta_percent<'a>(rows: impl IntoIterator<Item = &'a ComparisonRow>) -> f64 {
+    let (sum, count) = rows.into_iter().fold((0.0, 0), |(sum, count), row| {
+        (sum + (row.before.value / row.after.value).ln(), count + 1)
+    });
+    if count == 0 {
+        return 0.0;
+    }
+
+    (1.0 - (sum / count as f64).exp()) * 100.0
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ComparisonRow {
+    pub name: String,
+    /// The stable identity of the comparison, see [`crate::comparison_key`]. Set for the rows
+    /// of the tables collected from the results.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    pub measure: String,
+    pub kind: MeasureKind,
+    pub before: BenchCounter,
+    pub after: BenchCounter,
+    pub delta_percent: f64,
+    /// The two-tailed p-value of the t-test.
+    pub p_value: f64,
+    /// Whether the change is significant, after the correction for multiple comparisons.
+    pub significant: bool,
+    /// The tags of the compared commands.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Where the row is defined in the config, set for the `render-versus-other` and
+    /// `render-versus-self` tables.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config_span: Option<ConfigSpan>,
+    /// Set for the rows of tables with `compare-variance`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub variance: Option<VarianceChange>,
+    /// The change versus the rolling baseline, when it has the counter.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rolling: Option<RollingChange>,
+    /// The smallest change that matters, in the unit of [`Self::delta`], see
+    /// `minimum-effect-percent`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub minimum_effect: Option<f64>,
+    /// The command lines to reproduce the row with `repro`, see [`crate::repro`].
+    #[serde(skip)]
+    pub repro: Option<String>,
+    /// The output of a compared command differed between its repetitions, see
+    /// [`crate::verify_output`]. The gate ignores the row.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub nondeterministic_output: bool,
+    /// The repetitions of the command after the change left out because other processes
+    /// disturbed them, see [`crate::preemption`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preempted: Option<Preempted>,
+    /// Too many repetitions of either compared command were disturbed. The gate ignores the
+    /// row.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub too_preempted: bool,
+    /// The row is of an `informational` table, only rendered, see [`counted_rows`].
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub informational: bool,
+    /// How close the command after the change is to its theoretical limit of the measure, see
+    /// [`crate::limits`].
+    #[serde(skip)]
+    pub efficiency: Option<String>,
+}
+
+/// Whether a change is worth acting on: significant, and at least `minimum_effect` in the
+/// unit it is shown in, percent or percentage points. Everything that marks, counts or gates
+/// on changes goes through this.
+pub fn is_actionable(significant: bool, delta: f64, minimum_effect: Option<f64>) -> bool {
+    significant && minimum_effect.is_none_or(|minimum_effect| delta.abs() >= minimum_effect)
+}
+
+/// The change of the spread of a row.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VarianceChange {
+    /// See [`BenchCounter::cov_change_percent`].
+    pub cov_delta_percent: Option<f64>,
+    /// Whether the F-test says the variance changed. Not corrected for multiple comparisons.
+    pub significant: bool,
+}
+
+impl VarianceChange {
+    pub fn new(before: &BenchCounter, after: &BenchCounter) -> Self {
+        VarianceChange {
+            cov_delta_percent: BenchCounter::cov_change_percent(before, after),
+            significant: BenchCounter::is_variance_significant(before, after),
+        }
+    }
+
+    /// A significant increase of the spread.
+    pub fn is_regression(&self) -> bool {
+        self.significant && self.cov_delta_percent.is_some_and(|delta| delta > 0.0)
+    }
+
+    fn render_markdown_cell(&self, md: &mut String) {
+        let significant = match (self.significant, self.cov_delta_percent) {
+            (true, Some(delta)) if delta > 0.0 => "🎲",
+            (true, Some(_)) => "🎯",
+            _ => "  ",
+        };
+        match self.cov_delta_percent {
+            Some(delta) => write!(md, " `{significant} {delta:>+7.2}%` |").unwrap(),
+            None => write!(md, " `n.a.` |").unwrap(),
+        }
+    }
+}
+
+/// Add a column to the header lines of a table, like the one of [`VarianceChange`].
+fn with_column(header: &str, name: &str) -> String {
+    let mut lines = header.lines();
+    let mut with_column = String::new();
+    for (line, cell) in lines.by_ref().zip([name, "---"]) {
+        writeln!(with_column, "{line} {cell} |").unwrap();
+    }
+    for line in lines {
+        writeln!(with_column, "{line}").unwrap();
+    }
+    with_column
+}
+
+impl ComparisonRow {
+    pub fn new(
+        name: String,
+        measure: String,
+        kind: MeasureKind,
+        before: &BenchCounter,
+        after: &BenchCounter,
+    ) -> Self {
+        ComparisonRow {
+            name,
+            key: None,
+            measure,
+            kind,
+            delta_percent: BenchCounter::improvement_percentage(before, after),
+            p_value: BenchCounter::p_value(before, after),
+            significant: BenchCounter::is_significant(before, after),
+            tags: vec![],
+            config_span: None,
+            variance: None,
+            rolling: None,
+            minimum_effect: None,
+            repro: None,
+            nondeterministic_output: false,
+            preempted: None,
+            too_preempted: false,
+            informational: false,
+            efficiency: None,
+            before: before.clone(),
+            after: after.clone(),
+        }
+    }
+
+    /// A significant change of at least the minimum effect, see [`is_actionable`].
+    pub fn is_actionable(&self) -> bool {
+        is_actionable(self.significant, self.delta(), self.minimum_effect)
+    }
+
+    /// Whether the measurements of the compared commands can't be trusted, so the gate
+    /// ignores the row.
+    pub fn is_unreliable(&self) -> bool {
+        self.nondeterministic_output || self.too_preempted
+    }
+
+    /// Why the row is [unreliable](Self::is_unreliable), if it is.
+    fn unreliable_marker(&self) -> Option<Marker> {
+        if self.nondeterministic_output {
+            Some(Marker::NondeterministicOutput)
+        } else {
+            self.too_preempted.then_some(Marker::Preempted)
+        }
+    }
+
+    /// Whether a change by `delta_percent` is for the worse. For all counters we measure,
+    /// higher is worse, but for the derived hit rates, see [`crate::cache_stats`].
+    fn is_worse(&self, delta_percent: f64) -> bool {
+        if cache_stats::is_hit_rate(&self.measure) {
+            delta_percent < 0.0
+        } else {
+            delta_percent > 0.0
+        }
+    }
+
+    /// An actionable change for the worse, an increase for all but the hit rates.
+    pub fn is_regression(&self) -> bool {
+        self.is_actionable() && self.is_worse(self.delta_percent)
+    }
+
+    /// An actionable change for the better.
+    pub fn is_improvement(&self) -> bool {
+        self.is_actionable() && self.is_worse(-self.delta_percent)
+    }
+
+    /// Whether the change versus the rolling baseline is actionable, see [`is_actionable`].
+    pub fn is_rolling_actionable(&self) -> bool {
+        self.rolling.as_ref().is_some_and(|rolling| {
+            is_actionable(
+                rolling.significant,
+                rolling.delta(self.kind, &self.after),
+                self.minimum_effect,
+            )
+        })
+    }
+
+    /// The marker of the change versus the parent commit.
+    pub fn marker(&self) -> Marker {
+        Marker::of_change(self.is_actionable(), self.is_worse(self.delta_percent))
+    }
+
+    /// The marker of the change versus the rolling baseline, if any.
+    pub fn rolling_marker(&self) -> Option<Marker> {
+        let rolling = self.rolling.as_ref()?;
+        Some(Marker::of_change(
+            self.is_rolling_actionable(),
+            self.is_worse(rolling.delta_percent),
+        ))
+    }
+
+    /// An actionable change for the worse versus the rolling baseline.
+    pub fn is_rolling_regression(&self) -> bool {
+        self.is_rolling_actionable()
+            && self
+                .rolling
+                .as_ref()
+                .is_some_and(|rolling| self.is_worse(rolling.delta_percent))
+    }
+
+    /// The change versus the rolling baseline, with how many of the `window` results had the
+    /// counter when some didn't.
+    fn render_rolling_cell(&self, md: &mut String, window: usize, markers: &Markers) {
+        let (Some(rolling), Some(marker)) = (&self.rolling, self.rolling_marker()) else {
+            write!(md, " `n.a.` |").unwrap();
+            return;
+        };
+        let significant = markers.padded(marker);
+        let partial = if rolling.entries < window {
+            format!(" ({} of {window})", rolling.entries)
+        } else {
+            String::new()
+        };
+        write!(
+            md,
+            " `{significant} {:>7}`{partial} |",
+            rolling.format_delta(self.kind, &self.after)
+        )
+        .unwrap();
+    }
+
+    /// The change in the unit it is shown in, see [`MeasureKind::delta`].
+    pub fn delta(&self) -> f64 {
+        self.kind.delta(&self.before, &self.after)
+    }
+
+    pub fn format_delta(&self) -> String {
+        self.kind.format_delta(&self.before, &self.after)
+    }
+
+    /// Render the row, with the change versus the rolling baseline when the table has a
+    /// `rolling_window`.
+    pub fn render_markdown_row(
+        &self,
+        md: &mut String,
+        rolling_window: Option<usize>,
+        markers: &Markers,
+    ) {
+        // An anchor for links to the comparison that survive renaming the table or the row.
+        let anchor = match &self.key {
+            Some(key) => format!(" <a id=\"cmp-{key}\"></a>"),
+            None => String::new(),
+        };
+        let warning = match self.unreliable_marker().map(|marker| markers.get(marker)) {
+            Some(marker) if !marker.is_empty() => format!(" {marker}"),
+            _ => String::new(),
+        };
+        let preempted = match &self.preempted {
+            Some(_) => " †",
+            None => "",
+        };
+        let efficiency = match &self.efficiency {
+            Some(efficiency) => format!(" {efficiency}"),
+            None => String::new(),
+        };
+        write!(
+            md,
+            "| {}{preempted}{efficiency}{warning}{anchor} | `{} ± {}` | `{} ± {}` | `{} {:>7}` |",
+            self.name,
+            readable(self.before.value, &self.before.unit),
+            readable(self.before.variance.sqrt().round(), &self.before.unit),
+            readable(self.after.value, &self.after.unit),
+            readable(self.after.variance.sqrt().round(), &self.after.unit),
+            markers.padded(self.marker()),
+            self.format_delta(),
+        )
+        .unwrap();
+        if let Some(window) = rolling_window {
+            self.render_rolling_cell(md, window, markers);
+        }
+        if let Some(variance) = &self.variance {
+            variance.render_markdown_cell(md);
+        }
+        writeln!(md).unwrap();
+    }
+}
+
+/// Resolve the `render-versus-other` tables: a command compared against itself on the parent
+/// commit.
+///
+/// With `stable_counters`, when the parent ran on a different class of machine, a table of
+/// another measure compares the first of the `stable_counters` instead.
+pub fn collect_versus_other(
+    render: &IndexMap<String, VersusOther>,
+    kinds: &IndexMap<String, MeasureKind>,
+    stable_counters: Option<&[String]>,
+    before: &BenchData,
+    after: &BenchData,
+) -> Vec<ComparisonTable> {
+    render
+        .iter()
+        // Only the groups measured so far, with `--fail-fast`.
+        .filter(|(_, table)| after.bench_groups.contains_key(&table.command))
+        .map(|(table_name, table)| {
+            let measure = match stable_counters {
+                Some(stable) if !machine::is_machine_stable(stable, &table.measure) => {
+                    stable.first()
+                }
+                _ => Some(&table.measure),
+            };
+
+            let mut rows = vec![];
+            for (name, &index) in &table.rows {
+                let Some(measure) = measure else {
+                    break;
+                };
+                let Some(after_bench) = after.bench_groups[&table.command].get(index) else {
+                    continue;
+                };
+                let Some(before_bench) = before
+                    .bench_groups
+                    .get(&table.command)
+                    .and_then(|group| find_prev_bench_at(group, after_bench, index))
+                else {
+                    continue;
+                };
+
+                let Some(before) = before_bench.counters.get(measure) else {
+                    continue;
+                };
+                let Some(after) = after_bench.counters.get(measure) else {
+                    continue;
+                };
+
+                let command = comparison_key::bench_identity(after_bench);
+                rows.push(ComparisonRow {
+                    key: Some(comparison_key::key(
+                        ComparisonKind::VersusParent,
+                        &command,
+                        &command,
+                        measure,
+                    )),
+                    tags: after_bench.tags.clone(),
+                    nondeterministic_output: before_bench.nondeterministic_output
+                        || after_bench.nondeterministic_output,
+                    preempted: after_bench.preempted.clone(),
+                    too_preempted: preemption::is_unreliable(before_bench.preempted.as_ref())
+                        || preemption::is_unreliable(after_bench.preempted.as_ref()),
+                    informational: table.display.informational,
+                    ..ComparisonRow::new(
+                        name.clone(),
+                        measure.clone(),
+                        MeasureKind::of(kinds, measure),
+                        before,
+                        after,
+                    )
+                });
+            }
+
+            if table.display.compare_variance {
+                compare_variance(&mut rows);
+            }
+
+            ComparisonTable {
+                name: table_name.clone(),
+                kind: ComparisonKind::VersusParent,
+                rows,
+                display: table.display.clone(),
+                rolling_window: None,
+                build_notes: vec![],
+                sampling_note: None,
+            }
+        })
+        .collect()
+}
+
+fn compare_variance(rows: &mut [ComparisonRow]) {
+    for row in rows {
+        row.variance = Some(VarianceChange::new(&row.before, &row.after));
+    }
+}
+
+/// Resolve the `render-versus-self` tables: two commands of the current commit compared
+/// against each other.
+pub fn collect_versus_self(
+    render: &IndexMap<String, VersusSelf>,
+    kinds: &IndexMap<String, MeasureKind>,
+    data: &BenchData,
+) -> Vec<ComparisonTable> {
+    render
+        .iter()
+        .map(|(table_name, table)| {
+            let mut rows = vec![];
+            for (name, row) in &table.rows {
+                // Both groups may not have been measured yet, with `--fail-fast`.
+                let bench = |reference: &Reference| {
+                    data.bench_groups
+                        .get(&reference.command)
+                        .and_then(|group| group.get(reference.index))
+                };
+                let (Some(before_bench), Some(after_bench)) =
+                    (bench(&row.before), bench(&row.after))
+                else {
+                    continue;
+                };
+                let Some(before) = before_bench.counters.get(&row.measure) else {
+                    continue;
+                };
+                let Some(after) = after_bench.counters.get(&row.measure) else {
+                    continue;
+                };
+
+                let mut tags = before_bench.tags.clone();
+                for tag in &after_bench.tags {
+                    if !tags.contains(tag) {
+                        tags.push(tag.clone());
+                    }
+                }
+
+                rows.push(ComparisonRow {
+                    key: Some(comparison_key::key(
+                        ComparisonKind::VersusSelf,
+                        &comparison_key::bench_identity(before_bench),
+                        &comparison_key::bench_identity(after_bench),
+                        &row.measure,
+                    )),
+                    tags,
+                    nondeterministic_output: before_bench.nondeterministic_output
+                        || after_bench.nondeterministic_output,
+                    preempted: after_bench.preempted.clone(),
+                    too_preempted: preemption::is_unreliable(before_bench.preempted.as_ref())
+                        || preemption::is_unreliable(after_bench.preempted.as_ref()),
+                    informational: table.display.informational,
+                    ..ComparisonRow::new(
+                        name.clone(),
+                        row.measure.clone(),
+                        MeasureKind::of(kinds, &row.measure),
+                        before,
+                        after,
+                    )
+                });
+            }
+
+            if table.display.compare_variance {
+                compare_variance(&mut rows);
+            }
+
+            ComparisonTable {
+                name: table_name.clone(),
+                kind: ComparisonKind::VersusSelf,
+                rows,
+                display: table.display.clone(),
+                rolling_window: None,
+                build_notes: vec![],
+                sampling_note: None,
+            }
+        })
+        .collect()
+}
+
+/// Compare every counter of every command in a group against the previous results, as shown
+/// in the raw table. Rows are named `<command> (<counter>)`.
+pub fn collect_raw_versus_parent(
+    group_name: &str,
+    kinds: &IndexMap<String, MeasureKind>,
+    stable_counters: &[String],
+    data: &BenchData,
+    prev_results: Option<&BenchData>,
+) -> ComparisonTable {
+    let mut rows = vec![];
+
+    let prev_group_results = prev_results.and_then(|x| x.bench_groups.get(group_name));
+    if let (Some(prev_results), Some(prev_group_results)) = (prev_results, prev_group_results) {
+        let classes = MachineClasses {
+            before: prev_results,
+            after: data,
+            stable_counters,
+        };
+        for bench in &data.bench_groups[group_name] {
+            let Some(prev_bench) = find_prev_bench(prev_group_results, bench) else {
+                continue;
+            };
+
+            let command = comparison_key::bench_identity(bench);
+            for (counter, counter_data) in &bench.counters {
+                if !classes.compared(prev_bench, bench, counter) {
+                    continue;
+                }
+                if let Some(prev_data) = prev_bench.counters.get(counter) {
+                    rows.push(ComparisonRow {
+                        key: Some(comparison_key::key(
+                            ComparisonKind::VersusParent,
+                            &command,
+                            &command,
+                            counter,
+                        )),
+                        tags: bench.tags.clone(),
+                        nondeterministic_output: prev_bench.nondeterministic_output
+                            || bench.nondeterministic_output,
+                        preempted: bench.preempted.clone(),
+                        too_preempted: preemption::is_unreliable(prev_bench.preempted.as_ref())
+                            || preemption::is_unreliable(bench.preempted.as_ref()),
+                        ..ComparisonRow::new(
+                            format!("{} ({counter})", bench.cmd.join(" ")),
+                            counter.clone(),
+                            MeasureKind::of(kinds, counter),
+                            prev_data,
+                            counter_data,
+                        )
+                    });
+                }
+            }
+        }
+    }
+
+    ComparisonTable {
+        name: group_name.to_owned(),
+        kind: ComparisonKind::VersusParent,
+        rows,
+        display: TableDisplay::default(),
+        rolling_window: None,
+        build_notes: vec![],
+        sampling_note: None,
+    }
+}
+
+/// Find the previous result for the same benchmark: the one with the same id, or otherwise
+/// the one without an id with the same command line.
+pub fn find_prev_bench<'a>(
+    prev_group_results: &'a [SingleBench],
+    bench: &SingleBench,
+) -> Option<&'a SingleBench> {
+    find_prev_bench_by_id(prev_group_results, bench).or_else(|| {
+        prev_group_results
+            .iter()
+            .find(|prev_bench| prev_bench.id.is_none() && prev_bench.cmd == bench.cmd)
+    })
+}
+
+/// Like [`find_prev_bench`], but falling back to the previous result at `index` in the group,
+/// as the `render-versus-other` tables refer to commands by index. The command line is checked
+/// first, as the index doesn't match when commands were filtered out by tag.
+pub fn find_prev_bench_at<'a>(
+    prev_group_results: &'a [SingleBench],
+    bench: &SingleBench,
+    index: usize,
+) -> Option<&'a SingleBench> {
+    find_prev_bench(prev_group_results, bench).or_else(|| {
+        prev_group_results
+            .get(index)
+            .filter(|prev_bench| prev_bench.id.is_none())
+    })
+}
+
+fn find_prev_bench_by_id<'a>(
+    prev_group_results: &'a [SingleBench],
+    bench: &SingleBench,
+) -> Option<&'a SingleBench> {
+    let id = bench.id.as_ref()?;
+    prev_group_results
+        .iter()
+        .find(|prev_bench| prev_bench.id.as_ref() == Some(id))
+}
+
+#[cfg(test)]
+fn counter_for_test(value: f64) -> BenchCounter {
+    BenchCounter {
+        value,
+        variance: 100.0,
+        repetitions: 20,
+        unit: String::new(),
+    }
+}
+
+#[test]
+fn regression_requires_significance() {
+    let row = ComparisonRow::new(
+        "row".to_owned(),
+        "cycles".to_owned(),
+        MeasureKind::Count,
+        &counter_for_test(1000.0),
+        &counter_for_test(1100.0),
+    );
+    assert!(row.significant);
+    assert!(row.is_regression());
+
+    let row = ComparisonRow::new(
+        "row".to_owned(),
+        "cycles".to_owned(),
+        MeasureKind::Count,
+        &counter_for_test(1000.0),
+        &counter_for_test(900.0),
+    );
+    assert!(row.significant);
+    assert!(!row.is_regression());
+
+    let row = ComparisonRow::new(
+        "row".to_owned(),
+        "cycles".to_owned(),
+        MeasureKind::Count,
+        &counter_for_test(1000.0),
+        &counter_for_test(1001.0),
+    );
+    assert!(!row.significant);
+    assert!(!row.is_regression());
+}
+
+#[test]
+fn hit_rates_regress_down() {
+    let rate = |value| BenchCounter {
+        value,
+        variance: 0.01,
+        repetitions: 20,
+        unit: "%".to_owned(),
+    };
+    let row = |measure: &str, before, after| {
+        ComparisonRow::new(
+            "row".to_owned(),
+            measure.to_owned(),
+            MeasureKind::of(&IndexMap::new(), measure),
+            &rate(before),
+            &rate(after),
+        )
+    };
+
+    let dropped = row("L1-dcache-hit-rate", 95.0, 90.0);
+    assert_eq!(dropped.kind, MeasureKind::Percentage);
+    assert!(dropped.is_regression());
+    assert!(!dropped.is_improvement());
+    assert_eq!(dropped.marker(), Marker::Regression);
+
+    let rose = row("L1-dcache-hit-rate", 90.0, 95.0);
+    assert!(!rose.is_regression());
+    assert!(rose.is_improvement());
+    assert_eq!(rose.marker(), Marker::Improvement);
+
+    // Unlike a miss rate.
+    let misses = row(crate::mix::BRANCH_MISS_RATE, 5.0, 10.0);
+    assert!(misses.is_regression());
+    assert_eq!(misses.marker(), Marker::Regression);
+}
+
+#[test]
+fn minimum_effect_combinations() {
+    let row = |kind, before: f64, after: f64, minimum_effect| ComparisonRow {
+        minimum_effect,
+        ..ComparisonRow::new(
+            "row".to_owned(),
+            "cycles".to_owned(),
+            kind,
+            &counter_for_test(before),
+            &counter_for_test(after),
+        )
+    };
+
+    // Significant and above the minimum effect.
+    let large = row(MeasureKind::Count, 1000.0, 1100.0, Some(5.0));
+    assert!(large.significant && large.is_actionable() && large.is_regression());
+    // Significant, but below it.
+    let small = row(MeasureKind::Count, 1000.0, 1020.0, Some(5.0));
+    assert!(small.significant);
+    assert!(!small.is_actionable() && !small.is_regression());
+    let mut md = String::new();
+    small.render_markdown_row(&mut md, None, &Markers::default());
+    assert!(md.ends_with("| `    +1.96%` |\n"), "{md}");
+    // Above it, but not significant.
+    assert!(!is_actionable(false, 10.0, Some(5.0)));
+    // Neither.
+    assert!(!is_actionable(false, 1.0, Some(5.0)));
+
+    // Without a minimum effect, any significant change counts, and improvements count the same.
+    assert!(row(MeasureKind::Count, 1000.0, 1020.0, None).is_regression());
+    assert!(row(MeasureKind::Count, 1100.0, 1000.0, Some(5.0)).is_improvement());
+    assert!(!row(MeasureKind::Count, 1020.0, 1000.0, Some(5.0)).is_improvement());
+
+    // Percentages compare in percentage points: 50% to 60% is +10pp, or +16.67%.
+    let pp = |minimum_effect| row(MeasureKind::Percentage, 50.0, 60.0, Some(minimum_effect));
+    assert!(pp(10.0).is_regression());
+    assert!(!pp(15.0).is_regression());
+}
+
+#[test]
+fn minimum_effect_of_tables() {
+    let before = crate::testkit::BenchDataBuilder::cycles(
+        "1111111111111111111111111111111111111111",
+        &[("compress", &[("./c 1", 1000.0)])],
+    )
+    .build();
+    let after = crate::testkit::BenchDataBuilder::cycles(
+        "2222222222222222222222222222222222222222",
+        &[("compress", &[("./c 1", 1200.0)])],
+    )
+    .build();
+    let config: Config = serde_json::from_str(
+        r#"{
+            "commands": {},
+            "minimum-effect-percent": 20,
+            "render-versus-self": {},
+            "render-versus-other": {
+                "global": { "measure": "cycles", "command": "compress", "rows": { "level 1": 0 } },
+                "override": { "measure": "cycles", "command": "compress", "rows": { "level 1": 0 }, "minimum-effect-percent": 5 }
+            }
+        }"#,
+    )
+    .unwrap();
+    let comparisons = Comparisons::collect(&config, &after, Some(&before));
+
+    // +16.67% is below the global minimum, but above the one of the `override` table.
+    let regressions = comparisons
+        .regressions()
+        .map(|(table, _)| table.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(regressions, ["override"]);
+    assert!(comparisons.raw[0]
+        .rows
+        .iter()
+        .all(|row| !row.is_actionable()));
+
+    let mut md = String::new();
+    comparisons.versus_other[0].render_markdown(&mut md, "", &Markers::default());
+    assert!(!md.contains('💩'), "{md}");
+    assert!(md.contains("+16.67%"), "{md}");
+
+    let gate = crate::gate::GateConfig {
+        max_regression_percent: 1.0,
+        annotations: false,
+        max_variance_increase: None,
+        baseline: Default::default(),
+        exempt_label: None,
+    }
+    .evaluate(&comparisons);
+    assert_eq!(gate.failures.len(), 1);
+    assert_eq!(gate.failures[0].table, "override");
+}
+
+#[test]
+fn versus_other_compares_against_parent() {
+    let render: IndexMap<String, VersusOther> = serde_json::from_str(
+        r#"{ "compression": { "measure": "cycles", "command": "compress", "rows": { "level 1": 0, "level 2": 1, "level 3": 2 } } }"#,
+    )
+    .unwrap();
+
+    let before = crate::testkit::BenchDataBuilder::cycles(
+        "1111111111111111111111111111111111111111",
+        &[("compress", &[("./c 1", 1000.0), ("./c 2", 1000.0)])],
+    )
+    .build();
+    let after = crate::testkit::BenchDataBuilder::cycles(
+        "2222222222222222222222222222222222222222",
+        &[(
+            "compress",
+            &[("./c 1", 1200.0), ("./c 2", 1000.0), ("./c 3", 1000.0)],
+        )],
+    )
+    .build();
+
+    let tables = collect_versus_other(&render, &IndexMap::new(), None, &before, &after);
+    assert_eq!(tables.len(), 1);
+    assert_eq!(tables[0].kind, ComparisonKind::VersusParent);
+
+    // level 3 has no previous result
+    let rows = &tables[0].rows;
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0].name, "level 1");
+    assert_eq!(rows[0].before.value, 1000.0);
+    assert_eq!(rows[0].after.value, 1200.0);
+    assert!(rows[0].is_regression());
+    assert!(!rows[1].significant);
+}
+
+#[cfg(test)]
+fn top_movers_table_for_test(display: TableDisplay) -> ComparisonTable {
+    let after = |i| match i {
+        4 => 950.0,
+        11 => 1030.0,
+        16 => 1008.0,
+        2 | 8 => 1050.0,
+        13 => 996.0,
+        _ => 1000.0,
+    };
+    // The ties are large, but noisy enough not to be significant.
+    let variance = |i| if i == 2 || i == 8 { 10000.0 } else { 100.0 };
+
+    ComparisonTable {
+        name: "corpus".to_owned(),
+        kind: ComparisonKind::VersusParent,
+        rows: (0..20)
+            .map(|i| {
+                ComparisonRow::new(
+                    format!("row {i:02}"),
+                    "cycles".to_owned(),
+                    MeasureKind::Count,
+                    &BenchCounter {
+                        variance: variance(i),
+                        ..counter_for_test(1000.0)
+                    },
+                    &BenchCounter {
+                        variance: variance(i),
+                        ..counter_for_test(after(i))
+                    },
+                )
+            })
+            .collect(),
+        display,
+        rolling_window: None,
+        build_notes: vec![],
+        sampling_note: None,
+    }
+}
+
+#[test]
+fn select_top_movers() {
+    let table = top_movers_table_for_test(TableDisplay::default());
+    let (shown, omitted) = table.select_rows();
+    assert_eq!(shown.len(), 20);
+    assert!(omitted.is_empty());
+
+    let table = top_movers_table_for_test(TableDisplay {
+        max_rows: Some(2),
+        show_all_in_details: false,
+        compare_variance: false,
+        minimum_effect_percent: None,
+        order: crate::row_order::TableOrder::Config,
+        informational: false,
+    });
+    let (shown, omitted) = table.select_rows();
+
+    // row 04 moves the most. row 02 and row 08 tie for the last spot, which goes to row 02 by
+    // name. row 11 and row 16 are beyond the cutoff, but significant. Config order is kept.
+    let names = shown
+        .iter()
+        .map(|row| row.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["row 02", "row 04", "row 11", "row 16"]);
+    assert!(shown[3].significant);
+    assert_eq!(omitted.len(), 16);
+    assert_eq!(omitted[0].name, "row 00");
+    assert!(omitted.iter().any(|row| row.name == "row 08"));
+
+    let geomean = geomean_delta_percent(omitted.iter().copied());
+    assert!((geomean - 0.2794970699373289).abs() < 1e-12, "{geomean}");
+}
+
+#[test]
+fn geomean_of_equal_deltas() {
+    let rows = [1100.0, 1100.0, 1100.0].map(|after| {
+        ComparisonRow::new(
+            "row".to_owned(),
+            "cycles".to_owned(),
+            MeasureKind::Count,
+            &counter_for_test(1000.0),
+            &counter_for_test(after),
+        )
+    });
+    let geomean = geomean_delta_percent(&rows);
+    assert!((geomean - rows[0].delta_percent).abs() < 1e-12, "{geomean}");
+    assert_eq!(geomean_delta_percent(&[]), 0.0);
+}
+
+#[test]
+fn render_top_movers() {
+    let header = "| name | before | after | Δ |\n| --- | --- | --- | --- |\n";
+
+    let mut md = String::new();
+    top_movers_table_for_test(TableDisplay {
+        max_rows: Some(2),
+        show_all_in_details: false,
+        compare_variance: false,
+        minimum_effect_percent: None,
+        order: crate::row_order::TableOrder::Config,
+        informational: false,
+    })
+    .render_markdown(&mut md, header, &Markers::default());
+    assert_eq!(md.lines().count(), 2 + 4 + 1 + 2, "{md}");
+    assert!(md.ends_with(
+        "| 16 more rows | | | `geomean  +0.28%` (0 significant) |\n\n\
+         🚀 significant improvement · 💩 significant regression\n"
+    ));
+
+    let mut md = String::new();
+    top_movers_table_for_test(TableDisplay {
+        max_rows: Some(2),
+        show_all_in_details: true,
+        compare_variance: false,
+        minimum_effect_percent: None,
+        order: crate::row_order::TableOrder::Config,
+        informational: false,
+    })
+    .render_markdown(&mut md, header, &Markers::default());
+    let (summary, details) = md.split_once("<details>").unwrap();
+    assert_eq!(summary.lines().count(), 2 + 4 + 1 + 1);
+    assert!(details.starts_with("\n<summary>All rows</summary>\n\n| name |"));
+    assert_eq!(details.matches("| row ").count(), 20);
+    assert!(details
+        .ends_with("\n</details>\n\n\n🚀 significant improvement · 💩 significant regression\n"));
+}
+
+#[test]
+fn render_in_severity_order() {
+    let header = "| name | before | after | Δ |\n| --- | --- | --- | --- |\n";
+    let display = |max_rows, order| TableDisplay {
+        max_rows,
+        show_all_in_details: true,
+        compare_variance: false,
+        minimum_effect_percent: None,
+        order,
+        informational: false,
+    };
+
+    // The rows are selected before they are sorted: of the significant rows, row 11 and row 16
+    // regressed and row 04 improved, row 02 is the largest change left.
+    let mut md = String::new();
+    top_movers_table_for_test(display(Some(2), crate::row_order::TableOrder::Severity))
+        .render_markdown(&mut md, header, &Markers::default());
+    let (summary, details) = md.split_once("<details>").unwrap();
+    assert_eq!(
+        summary,
+        "_Rows sorted by severity: significant regressions first, then significant improvements._\n\n\
+         | name | before | after | Δ |\n\
+         | --- | --- | --- | --- |\n\
+         | row 11 | `  1.00K ±      10` | `  1.03K ±      10` | `💩  +2.91%` |\n\
+         | row 16 | `  1.00K ±      10` | `  1.01K ±      10` | `💩  +0.79%` |\n\
+         | row 04 | `  1.00K ±      10` | `    950 ±      10` | `🚀  -5.26%` |\n\
+         | row 02 | `  1.00K ±     100` | `  1.05K ±     100` | `    +4.76%` |\n\
+         | 16 more rows | | | `geomean  +0.28%` (0 significant) |\n\n"
+    );
+    // All rows in the same order, the insignificant ones in config order.
+    let names = details
+        .lines()
+        .filter_map(|line| line.strip_prefix("| row "))
+        .map(|line| &line[..2])
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        [
+            "11", "16", "04", "00", "01", "02", "03", "05", "06", "07", "08", "09", "10", "12",
+            "13", "14", "15", "17", "18", "19"
+        ]
+    );
+}
+
+#[test]
+fn sort_comparison_rows() {
+    use crate::row_order::TableOrder;
+
+    let header = "| name | before | after | Δ |\n| --- | --- | --- | --- |\n";
+    let row = |name: &str, after| {
+        ComparisonRow::new(
+            name.to_owned(),
+            "cycles".to_owned(),
+            MeasureKind::Count,
+            &counter_for_test(1000.0),
+            &counter_for_test(after),
+        )
+    };
+    let mut table = ComparisonTable {
+        name: "corpus".to_owned(),
+        kind: ComparisonKind::VersusParent,
+        rows: vec![
+            row("row d", 1000.0),
+            row("row c", 1030.0),
+            row("row b", 970.0),
+            row("row a", 1030.0),
+        ],
+        display: TableDisplay::default(),
+        rolling_window: None,
+        build_notes: vec![],
+        sampling_note: None,
+    };
+    let render = |table: &ComparisonTable| {
+        let mut md = String::new();
+        table.render_markdown(&mut md, header, &Markers::default());
+        md
+    };
+
+    assert_eq!(
+        render(&table),
+        "| name | before | after | Δ |\n\
+         | --- | --- | --- | --- |\n\
+         | row d | `  1.00K ±      10` | `  1.00K ±      10` | `    +0.00%` |\n\
+         | row c | `  1.00K ±      10` | `  1.03K ±      10` | `💩  +2.91%` |\n\
+         | row b | `  1.00K ±      10` | `    970 ±      10` | `🚀  -3.09%` |\n\
+         | row a | `  1.00K ±      10` | `  1.03K ±      10` | `💩  +2.91%` |\n\n\
+         🚀 significant improvement · 💩 significant regression\n"
+    );
+
+    // The regressions tie, so they are sorted by name.
+    table.display.order = TableOrder::Severity;
+    assert_eq!(
+        render(&table),
+        "_Rows sorted by severity: significant regressions first, then significant improvements._\n\n\
+         | name | before | after | Δ |\n\
+         | --- | --- | --- | --- |\n\
+         | row a | `  1.00K ±      10` | `  1.03K ±      10` | `💩  +2.91%` |\n\
+         | row c | `  1.00K ±      10` | `  1.03K ±      10` | `💩  +2.91%` |\n\
+         | row b | `  1.00K ±      10` | `    970 ±      10` | `🚀  -3.09%` |\n\
+         | row d | `  1.00K ±      10` | `  1.00K ±      10` | `    +0.00%` |\n\n\
+         🚀 significant improvement · 💩 significant regression\n"
+    );
+
+    table.display.order = TableOrder::Alphabetical;
+    assert_eq!(
+        render(&table),
+        "| name | before | after | Δ |\n\
+         | --- | --- | --- | --- |\n\
+         | row a | `  1.00K ±      10` | `  1.03K ±      10` | `💩  +2.91%` |\n\
+         | row b | `  1.00K ±      10` | `    970 ±      10` | `🚀  -3.09%` |\n\
+         | row c | `  1.00K ±      10` | `  1.03K ±      10` | `💩  +2.91%` |\n\
+         | row d | `  1.00K ±      10` | `  1.00K ±      10` | `    +0.00%` |\n\n\
+         🚀 significant improvement · 💩 significant regression\n"
+    );
+
+    // Without a regression, the order needs no caption.
+    table.display.order = TableOrder::Severity;
+    table.rows.retain(|row| !row.is_regression());
+    assert!(render(&table).starts_with("| name |"));
+
+    // The rows themselves keep the config order.
+    assert_eq!(table.rows[0].name, "row d");
+}
+
+#[test]
+fn render_custom_markers() {
+    let header = "| name | before | after | Δ |\n| --- | --- | --- | --- |\n";
+    let markers: Markers =
+        serde_json::from_str(r#"{ "improvement": "[+]", "regression": "[-]", "neutral": "=" }"#)
+            .unwrap();
+
+    let mut table = top_movers_table_for_test(TableDisplay::default());
+    table
+        .rows
+        .retain(|row| ["row 00", "row 04", "row 11"].contains(&row.name.as_str()));
+    let mut md = String::new();
+    table.render_markdown(&mut md, header, &markers);
+    assert_eq!(
+        md,
+        "| name | before | after | Δ |\n\
+         | --- | --- | --- | --- |\n\
+         | row 00 | `  1.00K ±      10` | `  1.00K ±      10` | `=    +0.00%` |\n\
+         | row 04 | `  1.00K ±      10` | `    950 ±      10` | `[+]  -5.26%` |\n\
+         | row 11 | `  1.00K ±      10` | `  1.03K ±      10` | `[-]  +2.91%` |\n\n\
+         [+] significant improvement · [-] significant regression · = no significant change\n"
+    );
+
+    // Without a marked change, there is nothing to explain.
+    table.rows.retain(|row| row.name == "row 00");
+    for markers in [Markers::default(), markers] {
+        let mut md = String::new();
+        table.render_markdown(&mut md, header, &markers);
+        assert!(md.ends_with("+0.00%` |\n"), "{md}");
+    }
+}
+
+#[test]
+fn compare_variance_column() {
+    let before = crate::testkit::BenchDataBuilder::new("1111111111111111111111111111111111111111")
+        .group("decompress", |g| {
+            g.bench(["./d", "1"], |b| b.counter("cycles", 1000.0, 100.0, 20, ""))
+                .bench(["./d", "2"], |b| b.counter("cycles", 1000.0, 100.0, 20, ""))
+                .bench(["./d", "3"], |b| b.counter("cycles", 1000.0, 0.0, 20, ""))
+        });
+    let mut after = before
+        .clone()
+        .commit_hash("2222222222222222222222222222222222222222")
+        .build();
+    let group = after.bench_groups.get_mut("decompress").unwrap();
+    // Same mean, four times the variance.
+    group[0].counters.get_mut("cycles").unwrap().variance = 400.0;
+    group[1].counters.get_mut("cycles").unwrap().variance = 120.0;
+    group[2].counters.get_mut("cycles").unwrap().variance = 100.0;
+
+    let render: IndexMap<String, VersusOther> = serde_json::from_str(
+        r#"{ "decompression": { "measure": "cycles", "command": "decompress", "rows": { "1": 0, "2": 1, "3": 2 }, "compare-variance": true } }"#,
+    )
+    .unwrap();
+    let tables = collect_versus_other(&render, &IndexMap::new(), None, &before.build(), &after);
+    let rows = &tables[0].rows;
+    assert_eq!(
+        rows[0].variance,
+        Some(VarianceChange {
+            cov_delta_percent: Some(100.0),
+            significant: true,
+        })
+    );
+    assert!(!rows[0].significant);
+    assert!(!rows[1].variance.as_ref().unwrap().significant);
+    // No variance in the baseline.
+    assert_eq!(
+        rows[2].variance,
+        Some(VarianceChange {
+            cov_delta_percent: None,
+            significant: false,
+        })
+    );
+
+    let comparisons = Comparisons {
+        versus_other: tables,
+        ..Comparisons::default()
+    };
+    let regressions = comparisons
+        .variance_regressions()
+        .map(|(_, row)| row.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(regressions, ["1"]);
+
+    let mut md = String::new();
+    comparisons.versus_other[0].render_markdown(
+        &mut md,
+        "| name | before | after | Δ |\n| --- | --- | --- | --- |\n",
+        &Markers::default(),
+    );
+    assert_eq!(
+        md,
+        "| name | before | after | Δ | CoV Δ |\n\
+         | --- | --- | --- | --- | --- |\n\
+         | 1 <a id=\"cmp-96531b1da5303850\"></a> | `  1.00K ±      10` | `  1.00K ±      20` | `    +0.00%` | `🎲 +100.00%` |\n\
+         | 2 <a id=\"cmp-9c62b39cf956a960\"></a> | `  1.00K ±      10` | `  1.00K ±      11` | `    +0.00%` | `     +9.54%` |\n\
+         | 3 <a id=\"cmp-95d0c177caf76cb3\"></a> | `  1.00K ±       0` | `  1.00K ±      10` | `    +0.00%` | `n.a.` |\n"
+    );
+
+    // Without the flag, there is no column.
+    let render: IndexMap<String, VersusOther> = serde_json::from_str(
+        r#"{ "decompression": { "measure": "cycles", "command": "decompress", "rows": { "1": 0 } } }"#,
+    )
+    .unwrap();
+    let tables = collect_versus_other(&render, &IndexMap::new(), None, &after, &after);
+    assert_eq!(tables[0].rows[0].variance, None);
+}
+
+#[test]
+fn match_previous_results_by_id() {
+    let mut before = crate::testkit::BenchDataBuilder::cycles(
+        "1111111111111111111111111111111111111111",
+        &[(
+            "compress",
+            &[("./c 1", 1000.0), ("./c 2", 1000.0), ("./c 3", 1000.0)],
+        )],
+    )
+    .build();
+    let mut after = crate::testkit::BenchDataBuilder::cycles(
+        "2222222222222222222222222222222222222222",
+        &[(
+            "compress",
+            &[
+                ("./c --level 2", 1100.0),
+                ("./c 1", 1000.0),
+                ("./c 3", 1000.0),
+            ],
+        )],
+    )
+    .build();
+    before.bench_groups["compress"][1].id = Some("level-2".to_owned());
+    after.bench_groups["compress"][0].id = Some("level-2".to_owned());
+
+    let prev = &before.bench_groups["compress"];
+    let current = &after.bench_groups["compress"];
+
+    // By id, despite the changed command line and position.
+    assert_eq!(
+        find_prev_bench(prev, &current[0]).unwrap().cmd,
+        ["./c", "2"]
+    );
+    assert_eq!(
+        find_prev_bench_at(prev, &current[0], 0).unwrap().cmd,
+        ["./c", "2"]
+    );
+    // Without ids, by command line or by index.
+    assert_eq!(
+        find_prev_bench(prev, &current[1]).unwrap().cmd,
+        ["./c", "1"]
+    );
+    assert_eq!(
+        find_prev_bench_at(prev, &current[2], 2).unwrap().cmd,
+        ["./c", "3"]
+    );
+    // The command line goes before the index, which shifts when commands are filtered out.
+    assert_eq!(
+        find_prev_bench_at(prev, &current[1], 1).unwrap().cmd,
+        ["./c", "1"]
+    );
+    // A previous result with an id is a different benchmark than one without.
+    let renamed = SingleBench {
+        cmd: vec!["./c".to_owned(), "one".to_owned()],
+        id: None,
+        tags: vec![],
+        counters: Default::default(),
+        profile: None,
+        intervals: None,
+        exit_code: None,
+        output_bytes: None,
+        error: None,
+        nondeterministic_output: false,
+        preempted: None,
+        backfilled: vec![],
+        hung: false,
+        imported: None,
+    };
+    assert!(find_prev_bench_at(prev, &renamed, 1).is_none());
+
+    // The id is used by all comparisons against the parent.
+    let render: IndexMap<String, VersusOther> = serde_json::from_str(
+        r#"{ "compression": { "measure": "cycles", "command": "compress", "rows": { "level 2": 0 } } }"#,
+    )
+    .unwrap();
+    let tables = collect_versus_other(&render, &IndexMap::new(), None, &before, &after);
+    assert_eq!(tables[0].rows[0].before.value, 1000.0);
+    assert_eq!(tables[0].rows[0].after.value, 1100.0);
+
+    let table = collect_raw_versus_parent("compress", &IndexMap::new(), &[], &after, Some(&before));
+    assert_eq!(
+        table
+            .rows
+            .iter()
+            .map(|row| row.name.as_str())
+            .collect::<Vec<_>>(),
+        ["./c --level 2 (cycles)", "./c 1 (cycles)", "./c 3 (cycles)"]
+    );
+    assert!(table.rows[0].is_regression());
+}
+
+#[test]
+fn multiple_comparison_correction() {
+    let table_with_p_values = |p_values: &[f64]| {
+        let rows = p_values
+            .iter()
+            .map(|&p_value| ComparisonRow {
+                p_value,
+                // The uncorrected verdict.
+                significant: p_value < SIGNIFICANCE_LEVEL,
+                ..ComparisonRow::new(
+                    format!("p {p_value}"),
+                    "cycles".to_owned(),
+                    MeasureKind::Count,
+                    &counter_for_test(1000.0),
+                    &counter_for_test(1000.0),
+                )
+            })
+            .collect();
+        Comparisons {
+            raw: vec![ComparisonTable {
+                name: "group".to_owned(),
+                kind: ComparisonKind::VersusParent,
+                rows,
+                display: TableDisplay::default(),
+                rolling_window: None,
+                build_notes: vec![],
+                sampling_note: None,
+            }],
+            ..Comparisons::default()
+        }
+    };
+    let verdicts = |p_values: &[f64], correction| {
+        let mut comparisons = table_with_p_values(p_values);
+        comparisons.apply_correction(correction);
+        comparisons.raw[0]
+            .rows
+            .iter()
+            .map(|row| row.significant)
+            .collect::<Vec<_>>()
+    };
+
+    let p_values = [0.9, 0.013, 0.2, 0.02];
+    assert_eq!(
+        verdicts(&p_values, Correction::None),
+        [false, true, false, true]
+    );
+    // Only below 0.05 / 4.
+    assert_eq!(
+        verdicts(&p_values, Correction::Bonferroni),
+        [false, false, false, false]
+    );
+    // 0.02 is below 2 / 4 * 0.05, which makes the smaller 0.013 significant too.
+    assert_eq!(
+        verdicts(&p_values, Correction::BenjaminiHochberg),
+        [false, true, false, true]
+    );
+
+    let p_values = [
+        0.001, 0.008, 0.039, 0.041, 0.042, 0.06, 0.074, 0.205, 0.212, 0.216,
+    ];
+    let count = |correction| {
+        verdicts(&p_values, correction)
+            .into_iter()
+            .filter(|&significant| significant)
+            .count()
+    };
+    assert_eq!(count(Correction::None), 5);
+    assert_eq!(count(Correction::Bonferroni), 1);
+    assert_eq!(count(Correction::BenjaminiHochberg), 2);
+}
+
+#[test]
+fn corrected_regressions() {
+    // One benchmark of twenty regressed by 1%: significant on its own, but not among 21
+    // comparisons.
+    let commands = (0..20).map(|i| format!("./c {i}")).collect::<Vec<_>>();
+    let before = commands
+        .iter()
+        .map(|cmd| (cmd.as_str(), 1000.0))
+        .collect::<Vec<_>>();
+    let mut after = before.clone();
+    after[0].1 = 1010.0;
+    let before = crate::testkit::BenchDataBuilder::cycles(
+        "1111111111111111111111111111111111111111",
+        &[("compress", &before)],
+    )
+    .build();
+    let after = crate::testkit::BenchDataBuilder::cycles(
+        "2222222222222222222222222222222222222222",
+        &[("compress", &after)],
+    )
+    .build();
+
+    let config = |correction: &str| -> Config {
+        serde_json::from_str(&format!(
+            r#"{{
+                "commands": {{}},
+                "correction": "{correction}",
+                "render-versus-self": {{}},
+                "render-versus-other": {{
+                    "compression": {{ "measure": "cycles", "command": "compress", "rows": {{ "level 0": 0 }} }}
+                }}
+            }}"#
+        ))
+        .unwrap()
+    };
+
+    let comparisons = Comparisons::collect(&config("none"), &after, Some(&before));
+    assert_eq!(comparisons.regressions().count(), 1);
+    assert!(comparisons.versus_other[0].rows[0].p_value < 0.005);
+    let significant = |comparisons: &Comparisons| {
+        comparisons.raw[0]
+            .rows
+            .iter()
+            .filter(|row| row.significant)
+            .count()
+    };
+    assert_eq!(significant(&comparisons), 1);
+
+    for correction in ["bonferroni", "benjamini-hochberg"] {
+        let comparisons = Comparisons::collect(&config(correction), &after, Some(&before));
+        assert_eq!(comparisons.regressions().count(), 0, "{correction}");
+        assert_eq!(significant(&comparisons), 0, "{correction}");
+    }
+}
+
+#[test]
+fn correction_without_informational_rows() {
+    // The regression of 1% is significant on its own, but not among 21 comparisons.
+    let before = crate::testkit::BenchDataBuilder::cycles(
+        "1111111111111111111111111111111111111111",
+        &[("compress", &[("./c 0", 1000.0)])],
+    )
+    .build();
+    let after = crate::testkit::BenchDataBuilder::cycles(
+        "2222222222222222222222222222222222222222",
+        &[("compress", &[("./c 0", 1010.0)])],
+    )
+    .build();
+
+    // Twenty more comparisons, which are no change.
+    let rows = (0..20)
+        .map(|i| {
+            format!(
+                r#""self {i}": {{ "measure": "cycles", "before": {{ "command": "compress", "index": 0 }}, "after": {{ "command": "compress", "index": 0 }} }}"#
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    let config = |informational: bool| -> Config {
+        serde_json::from_str(&format!(
+            r#"{{
+                "commands": {{}},
+                "correction": "bonferroni",
+                "render-versus-self": {{
+                    "reference": {{ "rows": {{ {rows} }}, "informational": {informational} }}
+                }},
+                "render-versus-other": {{
+                    "compression": {{ "measure": "cycles", "command": "compress", "rows": {{ "level 0": 0 }} }}
+                }}
+            }}"#
+        ))
+        .unwrap()
+    };
+
+    let comparisons = Comparisons::collect(&config(false), &after, Some(&before));
+    assert_eq!(comparisons.versus_self[0].rows.len(), 20);
+    assert!(comparisons.versus_other[0].rows[0].p_value > SIGNIFICANCE_LEVEL / 21.0);
+    assert_eq!(comparisons.regressions().count(), 0);
+
+    // As with only the counted table.
+    let comparisons = Comparisons::collect(&config(true), &after, Some(&before));
+    assert_eq!(comparisons.regressions().count(), 1);
+    assert!(comparisons.raw[0].rows[0].significant);
+}
+
+#[test]
+fn tag_rollups() {
+    let before = crate::testkit::BenchDataBuilder::cycles(
+        "1111111111111111111111111111111111111111",
+        &[(
+            "compress",
+            &[("./c 1", 1000.0), ("./c 2", 1000.0), ("./c 3", 1000.0)],
+        )],
+    )
+    .build();
+    let mut after = crate::testkit::BenchDataBuilder::cycles(
+        "2222222222222222222222222222222222222222",
+        &[(
+            "compress",
+            &[("./c 1", 1100.0), ("./c 2", 900.0), ("./c 3", 1000.5)],
+        )],
+    )
+    .build();
+    for (bench, tags) in
+        after.bench_groups["compress"]
+            .iter_mut()
+            .zip([&["fast"][..], &["fast", "slow"], &["slow"]])
+    {
+        bench.tags = tags.iter().map(|tag| tag.to_string()).collect();
+    }
+
+    let config: Config = serde_json::from_str(
+        r#"{
+            "commands": {},
+            "render-versus-self": {},
+            "render-versus-other": {
+                "compression": { "measure": "cycles", "command": "compress", "rows": { "level 1": 0, "level 2": 1, "level 3": 2 } }
+            }
+        }"#,
+    )
+    .unwrap();
+    let comparisons = Comparisons::collect(&config, &after, Some(&before));
+    assert_eq!(comparisons.versus_other[0].rows[1].tags, ["fast", "slow"]);
+
+    let rollups = comparisons.tag_rollups();
+    assert_eq!(
+        rollups
+            .iter()
+            .map(|rollup| (
+                rollup.tag.as_str(),
+                rollup.rows,
+                rollup.improvements,
+                rollup.regressions
+            ))
+            .collect::<Vec<_>>(),
+        [("fast", 2, 1, 1), ("slow", 2, 1, 0)]
+    );
+    let rows = &comparisons.versus_other[0].rows;
+    assert_eq!(
+        rollups[0].geomean_delta_percent,
+        geomean_delta_percent([&rows[0], &rows[1]].into_iter())
+    );
+
+    let mut md = String::new();
+    render_tag_rollups(&mut md, &rollups, &Markers::default());
+    assert_eq!(
+        md,
+        "### Tags\n\n\
+         | tag | rows | improvements | regressions | Δ |\n\
+         | --- | --- | --- | --- | --- |\n\
+         | fast | 2 | 🚀 1 | 💩 1 | `geomean  -0.50%` |\n\
+         | slow | 2 | 🚀 1 | 💩 0 | `geomean  -5.38%` |\n\n\
+         🚀 significant improvement · 💩 significant regression\n\n"
+    );
+
+    let markers: Markers =
+        serde_json::from_str(r#"{ "improvement": "+", "regression": "-" }"#).unwrap();
+    let mut md = String::new();
+    render_tag_rollups(&mut md, &rollups, &markers);
+    assert!(
+        md.ends_with(
+            "| fast | 2 | + 1 | - 1 | `geomean  -0.50%` |\n\
+             | slow | 2 | + 1 | - 0 | `geomean  -5.38%` |\n\n\
+             + significant improvement · - significant regression\n\n"
+        ),
+        "{md}"
+    );
+}
+
+#[test]
+fn cold_and_warm_only_compared_when_shown() {
+    let data = |commit: &str, cold: f64| {
+        let mut data =
+            crate::testkit::BenchDataBuilder::cycles(commit, &[("compress", &[("./c 1", 1000.0)])])
+                .build();
+        data.bench_groups["compress"][0].counters.insert(
+            "cycles-cold".to_owned(),
+            BenchCounter {
+                repetitions: 1,
+                ..counter_for_test(cold)
+            },
+        );
+        data
+    };
+    let before = data("1111111111111111111111111111111111111111", 3000.0);
+    let after = data("2222222222222222222222222222222222222222", 4000.0);
+    let measures = |comparisons: &Comparisons| {
+        comparisons.raw[0]
+            .rows
+            .iter()
+            .map(|row| row.measure.clone())
+            .collect::<Vec<_>>()
+    };
+
+    let config: Config = serde_json::from_str(
+        r#"{ "commands": {}, "render-versus-self": {}, "render-versus-other": {} }"#,
+    )
+    .unwrap();
+    let comparisons = Comparisons::collect(&config, &after, Some(&before));
+    assert_eq!(measures(&comparisons), ["cycles"]);
+
+    let config = Config {
+        show_cold_warm: true,
+        ..config
+    };
+    let comparisons = Comparisons::collect(&config, &after, Some(&before));
+    assert_eq!(measures(&comparisons), ["cycles", "cycles-cold"]);
+    // A single run against a single run is never significant.
+    assert!(!comparisons.raw[0].rows[1].significant);
+
+    // But a pretty table can compare them regardless.
+    let config: Config = serde_json::from_str(
+        r#"{
+            "commands": {},
+            "render-versus-self": {},
+            "render-versus-other": {
+                "startup": { "measure": "cycles-cold", "command": "compress", "rows": { "level 1": 0 } }
+            }
+        }"#,
+    )
+    .unwrap();
+    let comparisons = Comparisons::collect(&config, &after, Some(&before));
+    assert_eq!(comparisons.versus_other[0].rows.len(), 1);
+    assert_eq!(comparisons.versus_other[0].rows[0].measure, "cycles-cold");
+}
+
+#[test]
+fn suppress_machine_dependent_counters_across_classes() {
+    let data = |commit: &str, class: Option<&str>, cycles: f64, instructions: f64| {
+        let mut data =
+            crate::testkit::BenchDataBuilder::cycles(commit, &[("compress", &[("./c 1", cycles)])])
+                .build();
+        data.machine_class = class.map(str::to_owned);
+        data.bench_groups["compress"][0].counters.insert(
+            "instructions".to_owned(),
+            BenchCounter {
+                value: instructions,
+                ..counter_for_test(instructions)
+            },
+        );
+        data
+    };
+    let config: Config = serde_json::from_str(
+        r#"{
+            "commands": {},
+            "render-versus-self": {},
+            "render-versus-other": {
+                "compression": { "measure": "cycles", "command": "compress", "rows": { "level 1": 0 } }
+            }
+        }"#,
+    )
+    .unwrap();
+    let measures = |comparisons: &Comparisons| {
+        let measures = |tables: &[ComparisonTable]| {
+            tables
+                .iter()
+                .flat_map(|table| table.rows.iter().map(|row| row.measure.clone()))
+                .collect::<Vec<_>>()
+        };
+        (
+            measures(&comparisons.versus_other),
+            measures(&comparisons.raw),
+        )
+    };
+
+    let dsv5 = Some("intel-xeon-platinum-8370c/4");
+    let dasv5 = Some("amd-epyc-7763/4");
+    let before = data(
+        "1111111111111111111111111111111111111111",
+        dsv5,
+        1000.0,
+        5000.0,
+    );
+
+    // The same class of machine: everything is compared.
+    let after = data(
+        "2222222222222222222222222222222222222222",
+        dsv5,
+        800.0,
+        5000.0,
+    );
+    let comparisons = Comparisons::collect(&config, &after, Some(&before));
+    assert_eq!(comparisons.cross_class, None);
+    assert_eq!(
+        measures(&comparisons),
+        (
+            vec!["cycles".to_owned()],
+            vec!["cycles".to_owned(), "instructions".to_owned()]
+        )
+    );
+
+    // Another class: the table compares instructions instead, and cycles are left out.
+    let after = data(
+        "2222222222222222222222222222222222222222",
+        dasv5,
+        800.0,
+        5000.0,
+    );
+    let comparisons = Comparisons::collect(&config, &after, Some(&before));
+    assert_eq!(
+        comparisons.cross_class,
+        Some(CrossClass {
+            before: "intel-xeon-platinum-8370c/4".to_owned(),
+            after: "amd-epyc-7763/4".to_owned(),
+        })
+    );
+    assert_eq!(
+        measures(&comparisons),
+        (
+            vec!["instructions".to_owned()],
+            vec!["instructions".to_owned()]
+        )
+    );
+    assert_eq!(comparisons.regressions().count(), 0);
+
+    // Without any machine-stable counters, nothing is compared.
+    let no_stable_counters = Config {
+        machine_stable_counters: vec![],
+        ..config
+    };
+    let comparisons = Comparisons::collect(&no_stable_counters, &after, Some(&before));
+    assert_eq!(measures(&comparisons), (vec![], vec![]));
+    let config = Config {
+        machine_stable_counters: crate::machine::default_machine_stable_counters(),
+        ..no_stable_counters
+    };
+
+    // Results from before the class was recorded are compared like before.
+    let before = data(
+        "1111111111111111111111111111111111111111",
+        None,
+        1000.0,
+        5000.0,
+    );
+    let comparisons = Comparisons::collect(&config, &after, Some(&before));
+    assert_eq!(comparisons.cross_class, None);
+    assert_eq!(measures(&comparisons).0, ["cycles"]);
+}
@@ -0,0 +1,248 @@
+//! A stable identity of every comparison row, to follow the same comparison across runs when
+//! the config is restructured. The key hashes what is compared, never how it is shown: the
+//! commands of both sides, the measure and the kind of comparison, but not the name of the
+//! table or of the row, or where they are in the config.
+//!
+//! A command is identified by its `id` when it has one, as that is what stays the same when
+//! the command line changes, and otherwise by its command line, with any run of whitespace
+//! counting as a single space. The key is in the comparison rows of the run report and of the
+//! webhook payloads, and an anchor `cmp-<key>` in the markdown tables.
+//!
+//! `benchmarker --map-keys <config>...` prints the key of every row of the config, to register
+//! them with other tools before the first run, with the fields separated by tabs:
+//!
+//! ```text
+//! 5f1d9c0b2e7a4d13    versus-other    ng vs rs    level 1    cycles
+//! ```
+
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use crate::bench::SingleBench;
+use crate::compare::ComparisonKind;
+use crate::sha256::Sha256;
+use crate::{CommandConfig, Config};
+
+/// Bumped when the encoding changes, which changes every key.
+const VERSION: &str = "v1";
+
+/// The number of hex digits of a key, 64 bits.
+const KEY_LEN: usize = 16;
+
+/// The identity of a command in a key.
+pub fn command_identity<'a>(id: Option<&str>, argv: impl IntoIterator<Item = &'a str>) -> String {
+    match id.map(str::trim).filter(|id| !id.is_empty()) {
+        Some(id) => format!("id:{id}"),
+        None => format!(
+            "argv:{}",
+            argv.into_iter()
+                .flat_map(str::split_whitespace)
+                .collect::<Vec<_>>()
+                .join(" ")
+        ),
+    }
+}
+
+pub fn bench_identity(bench: &SingleBench) -> String {
+    command_identity(bench.id.as_deref(), bench.cmd.iter().map(String::as_str))
+}
+
+fn config_identity(bench: &CommandConfig) -> String {
+    command_identity(bench.id.as_deref(), [bench.command.as_str()])
+}
+
+/// The key of comparing `measure` of the command identified by `before` with that of `after`.
+pub fn key(kind: ComparisonKind, before: &str, after: &str, measure: &str) -> String {
+    let kind = match kind {
+        ComparisonKind::VersusParent => "versus-parent",
+        ComparisonKind::VersusSelf => "versus-self",
+    };
+    // As a JSON array, so no part can run into the next.
+    let encoded = serde_json::to_string(&[VERSION, kind, before, after, measure.trim()]).unwrap();
+    let mut sha256 = Sha256::default();
+    sha256.update(encoded.as_bytes());
+    let mut key = sha256.finish_hex();
+    key.truncate(KEY_LEN);
+    key
+}
+
+/// The key of every row of the tables of `config`, a line per row with the key, the kind of
+/// table, its name, the name of the row and the measure, separated by tabs. Rows that refer to
+/// a command that doesn't exist are skipped.
+pub fn map_keys(config: &Config) -> String {
+    let command = |group: &str, index: usize| {
+        config
+            .commands
+            .get(group)
+            .and_then(|benches| benches.get(index))
+            .map(config_identity)
+    };
+
+    let mut lines = String::new();
+    for (table_name, table) in &config.render_versus_other {
+        for (row_name, &index) in &table.rows {
+            let Some(command) = command(&table.command, index) else {
+                continue;
+            };
+            let key = key(
+                ComparisonKind::VersusParent,
+                &command,
+                &command,
+                &table.measure,
+            );
+            writeln!(
+                lines,
+                "{key}\tversus-other\t{table_name}\t{row_name}\t{}",
+                table.measure
+            )
+            .unwrap();
+        }
+    }
+    for (table_name, table) in &config.render_versus_self {
+        for (row_name, row) in &table.rows {
+            let (Some(before), Some(after)) = (
+                command(&row.before.command, row.before.index),
+                command(&row.after.command, row.after.index),
+            ) else {
+                continue;
+            };
+            let key = key(ComparisonKind::VersusSelf, &before, &after, &row.measure);
+            writeln!(
+                lines,
+                "{key}\tversus-self\t{table_name}\t{row_name}\t{}",
+                row.measure
+            )
+            .unwrap();
+        }
+    }
+    lines
+}
+
+/// `benchmarker --map-keys <config>...`
+pub fn run(args: impl IntoIterator<Item = String>) -> Result<String, String> {
+    let config_paths = args.into_iter().map(PathBuf::from).collect::<Vec<_>>();
+    if config_paths.is_empty() {
+        return Err("expected the arguments --map-keys <config>...".to_owned());
+    }
+    let config = Config::load(&config_paths)?;
+    config.validate()?;
+    Ok(map_keys(&config))
+}
+
+#[test]
+fn command_identities() {
+    // Any whitespace between the arguments is a single space.
+    assert_eq!(
+        command_identity(None, ["./compress  --level 1\t corpus "]),
+        "argv:./compress --level 1 corpus"
+    );
+    assert_eq!(
+        command_identity(None, ["./compress", "", "--level", "1", "corpus"]),
+        command_identity(None, ["./compress --level 1 corpus"])
+    );
+    // The id wins over the command line, and an empty id is none.
+    assert_eq!(
+        command_identity(Some(" compress-1 "), ["./compress --level 1"]),
+        "id:compress-1"
+    );
+    assert_eq!(
+        command_identity(Some(""), ["./compress"]),
+        "argv:./compress"
+    );
+    // An id never looks like a command line.
+    assert_ne!(
+        command_identity(Some("./compress"), ["./compress"]),
+        command_identity(None, ["./compress"])
+    );
+}
+
+#[test]
+fn stable_keys() {
+    let before = command_identity(None, ["./compress --level 1"]);
+    let after = command_identity(Some("compress-rs-1"), ["./compress-rs --level 1"]);
+    let versus_self = key(ComparisonKind::VersusSelf, &before, &after, "cycles");
+    // The same in every version, or every key tracked elsewhere breaks.
+    assert_eq!(versus_self, "6e0a100d7f3315f0");
+    assert_eq!(
+        key(ComparisonKind::VersusSelf, &before, &after, " cycles "),
+        versus_self
+    );
+
+    // Everything that is compared changes the key.
+    assert_ne!(
+        key(ComparisonKind::VersusSelf, &after, &before, "cycles"),
+        versus_self
+    );
+    assert_ne!(
+        key(ComparisonKind::VersusSelf, &before, &after, "instructions"),
+        versus_self
+    );
+    assert_ne!(
+        key(ComparisonKind::VersusParent, &before, &after, "cycles"),
+        versus_self
+    );
+    assert_eq!(versus_self.len(), KEY_LEN);
+}
+
+#[test]
+fn keys_ignore_names_and_order() {
+    let config = |tables: &str| {
+        serde_json::from_str::<Config>(&format!(
+            r#"{{
+                "commands": {{
+                    "compress": ["./c  1", {{ "command": "./c 9", "id": "c9" }}],
+                    "compress-rs": ["./c-rs 1"]
+                }},
+                {tables}
+            }}"#
+        ))
+        .unwrap()
+    };
+    let keys = |config: &Config| {
+        let mut keys = map_keys(config)
+            .lines()
+            .map(|line| line.split('\t').next().unwrap().to_owned())
+            .collect::<Vec<_>>();
+        keys.sort();
+        keys
+    };
+
+    let original = config(
+        r#""render-versus-other": {
+            "compress": { "measure": "cycles", "command": "compress", "rows": { "level 1": 0, "level 9": 1 } }
+        },
+        "render-versus-self": {
+            "ng vs rs": { "level 1": { "measure": "cycles", "before": { "command": "compress", "index": 0 }, "after": { "command": "compress-rs", "index": 0 } } }
+        }"#,
+    );
+    let lines = map_keys(&original);
+    assert_eq!(
+        lines.lines().next().unwrap().split_once('\t').unwrap().1,
+        "versus-other\tcompress\tlevel 1\tcycles"
+    );
+    assert_eq!(lines.lines().count(), 3);
+
+    // Renamed tables and rows, in another order.
+    let renamed = config(
+        r#""render-versus-self": {
+            "C vs Rust": { "fastest": { "measure": "cycles", "before": { "command": "compress", "index": 0 }, "after": { "command": "compress-rs", "index": 0 } } }
+        },
+        "render-versus-other": {
+            "compression": { "measure": "cycles", "command": "compress", "rows": { "best": 1, "fastest": 0 } }
+        }"#,
+    );
+    assert_eq!(keys(&renamed), keys(&original));
+
+    // Another measure is another comparison.
+    let other_measure = config(
+        r#""render-versus-other": {
+            "compress": { "measure": "instructions", "command": "compress", "rows": { "level 1": 0, "level 9": 1 } }
+        },
+        "render-versus-self": {
+            "ng vs rs": { "level 1": { "measure": "instructions", "before": { "command": "compress", "index": 0 }, "after": { "command": "compress-rs", "index": 0 } } }
+        }"#,
+    );
+    assert!(keys(&other_measure)
+        .iter()
+        .all(|key| !keys(&original).contains(key)));
+}
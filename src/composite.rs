@@ -0,0 +1,277 @@
+//! Composite commands, for scenarios made of several commands, like compressing a file and
+//! decompressing it again:
+//!
+//! ```json
+//! { "composite": "roundtrip level 6", "steps": ["./compress 6 f.tar out.gz", "./decompress out.gz /dev/null"] }
+//! ```
+//!
+//! The steps take the place of the composite in the group, followed by the composite itself,
+//! so a comparison refers to the composite by the index after its steps. The steps are
+//! measured like any command, but one repetition at a time and in order, so the files one step
+//! writes exist for the next. They run in the same working directory. The composite isn't run:
+//! its counters are the sums of those of its steps.
+
+use std::collections::BTreeMap;
+
+use crate::bench::{BenchCounter, SingleBench};
+use crate::{units, CommandConfig};
+
+/// Whether the values of a counter in `unit` add up over consecutive commands: counts, like
+/// cycles, and durations. Ratios don't, and neither do sizes, which are mostly peaks like the
+/// `max-rss` of the `getrusage` backend.
+pub fn is_summable(unit: &str) -> bool {
+    unit.is_empty() || units::is_duration_unit(unit)
+}
+
+/// The sum of the summable counters that every step has in the same unit. The steps are
+/// measured independently, so their variances add up. The repetitions are those of the step
+/// with the fewest.
+pub fn sum_counters(steps: &[&BTreeMap<String, BenchCounter>]) -> BTreeMap<String, BenchCounter> {
+    let Some((first, rest)) = steps.split_first() else {
+        return BTreeMap::new();
+    };
+    first
+        .iter()
+        .filter(|(_, counter)| is_summable(&counter.unit))
+        .filter_map(|(name, counter)| {
+            let mut sum = counter.clone();
+            for step in rest {
+                let counter = step.get(name).filter(|step| step.unit == sum.unit)?;
+                sum.value += counter.value;
+                sum.variance += counter.variance;
+                sum.repetitions = sum.repetitions.min(counter.repetitions);
+            }
+            Some((name.clone(), sum))
+        })
+        .collect()
+}
+
+/// The consecutive steps of every composite of a group, which run interleaved so their
+/// repetitions alternate in order, see [`crate::interleave::schedule`].
+pub fn pairs(benches: &[CommandConfig]) -> Vec<(usize, usize)> {
+    benches
+        .iter()
+        .flat_map(|bench| bench.steps.windows(2).map(|pair| (pair[0], pair[1])))
+        .collect()
+}
+
+/// The results of `composite`, from those of its steps in `group_results`, which are measured
+/// before it.
+pub fn bench(composite: &CommandConfig, group_results: &[Option<SingleBench>]) -> SingleBench {
+    let steps = composite
+        .steps
+        .iter()
+        .map(|&index| {
+            &group_results[index]
+                .as_ref()
+                .expect("the steps are measured before their composite")
+                .counters
+        })
+        .collect::<Vec<_>>();
+    SingleBench {
+        cmd: composite
+            .command
+            .split(' ')
+            .map(|arg| arg.to_owned())
+            .collect(),
+        id: None,
+        tags: vec![],
+        counters: sum_counters(&steps),
+        profile: None,
+        intervals: None,
+        exit_code: None,
+        output_bytes: None,
+        error: None,
+        nondeterministic_output: false,
+        preempted: None,
+        backfilled: vec![],
+        hung: false,
+        imported: None,
+    }
+}
+
+#[cfg(test)]
+fn counter_for_test(value: f64, variance: f64, repetitions: u32, unit: &str) -> BenchCounter {
+    BenchCounter {
+        value,
+        variance,
+        repetitions,
+        unit: unit.to_owned(),
+    }
+}
+
+#[test]
+fn summed_counters() {
+    let compress = BTreeMap::from([
+        ("cycles".to_owned(), counter_for_test(3e6, 100.0, 20, "")),
+        (
+            "task-clock".to_owned(),
+            counter_for_test(2.0, 0.1, 20, "msec"),
+        ),
+        (
+            "max-rss".to_owned(),
+            counter_for_test(8000.0, 1.0, 20, "KiB"),
+        ),
+        (
+            "branch-miss-rate".to_owned(),
+            counter_for_test(1.5, 0.01, 20, "%"),
+        ),
+        (
+            "instructions".to_owned(),
+            counter_for_test(5e6, 10.0, 20, ""),
+        ),
+        (
+            "wall-time".to_owned(),
+            counter_for_test(3.0, 0.1, 20, "msec"),
+        ),
+    ]);
+    let decompress = BTreeMap::from([
+        ("cycles".to_owned(), counter_for_test(1e6, 50.0, 19, "")),
+        (
+            "task-clock".to_owned(),
+            counter_for_test(0.5, 0.2, 20, "msec"),
+        ),
+        (
+            "max-rss".to_owned(),
+            counter_for_test(4000.0, 1.0, 20, "KiB"),
+        ),
+        (
+            "branch-miss-rate".to_owned(),
+            counter_for_test(0.5, 0.01, 20, "%"),
+        ),
+        (
+            "wall-time".to_owned(),
+            counter_for_test(0.001, 0.0, 20, "s"),
+        ),
+    ]);
+
+    let sum = sum_counters(&[&compress, &decompress]);
+    assert_eq!(sum["cycles"], counter_for_test(4e6, 150.0, 19, ""));
+    assert_eq!(sum["task-clock"].value, 2.5);
+    assert!((sum["task-clock"].variance - 0.3).abs() < 1e-12);
+    // Not the sizes or the ratios, the counters a step doesn't have, or those in different
+    // units.
+    assert_eq!(sum.keys().collect::<Vec<_>>(), ["cycles", "task-clock"]);
+
+    // A single step is its own sum, but for what doesn't add up.
+    assert_eq!(
+        sum_counters(&[&compress]).keys().collect::<Vec<_>>(),
+        ["cycles", "instructions", "task-clock", "wall-time"]
+    );
+    assert!(sum_counters(&[]).is_empty());
+}
+
+#[test]
+fn composite_steps_in_config() {
+    let config: crate::Config = serde_json::from_str(
+        r#"{
+            "commands": {
+                "roundtrip": [
+                    "./c 1",
+                    { "composite": "roundtrip level 6", "steps": ["./c 6 f.tar out.gz", "./d out.gz /dev/null"], "id": "rt6", "tags": ["e2e"] },
+                    "./c 9"
+                ]
+            },
+            "render-versus-self": {},
+            "render-versus-other": {}
+        }"#,
+    )
+    .unwrap();
+
+    let benches = &config.commands["roundtrip"];
+    assert_eq!(
+        benches
+            .iter()
+            .map(|bench| bench.command.as_str())
+            .collect::<Vec<_>>(),
+        [
+            "./c 1",
+            "./c 6 f.tar out.gz",
+            "./d out.gz /dev/null",
+            "roundtrip level 6",
+            "./c 9"
+        ]
+    );
+    assert_eq!(benches[3].steps, [1, 2]);
+    assert_eq!(benches[3].id.as_deref(), Some("rt6"));
+    // The steps carry the tags of the composite, so they are selected with it.
+    assert_eq!(benches[1].tags, ["e2e"]);
+    assert!(benches[1].steps.is_empty());
+
+    // The steps run one repetition at a time and in order, before the composite.
+    let steps = crate::interleave::schedule(benches.len(), &pairs(benches));
+    assert_eq!(
+        steps,
+        [
+            crate::interleave::Step::Alone(0),
+            crate::interleave::Step::Interleaved(vec![1, 2]),
+            crate::interleave::Step::Alone(3),
+            crate::interleave::Step::Alone(4),
+        ]
+    );
+
+    let err = serde_json::from_str::<crate::Config>(
+        r#"{
+            "commands": { "roundtrip": [{ "composite": "nothing", "steps": [] }] },
+            "render-versus-self": {},
+            "render-versus-other": {}
+        }"#,
+    )
+    .unwrap_err();
+    assert!(
+        err.to_string()
+            .starts_with("the composite `nothing` has no steps"),
+        "{err}"
+    );
+}
+
+#[test]
+fn steps_run_in_order() {
+    use crate::bench::{Backend, CommandSpec, Getrusage};
+
+    // The second step consumes the file of the first, which fails unless they alternate.
+    let dir = crate::testkit::test_dir("composite-steps");
+    let file = dir.join("out.txt");
+    let script = |name: &str, body: String| {
+        let path = dir.join(name);
+        std::fs::write(&path, format!("#!/bin/sh\nset -e\n{body}\n")).unwrap();
+        std::process::Command::new("chmod")
+            .arg("+x")
+            .arg(&path)
+            .status()
+            .unwrap();
+        CommandSpec::new(vec![path.display().to_string()])
+    };
+    let produce = script("produce", format!("echo data > {}", file.display()));
+    let consume = script(
+        "consume",
+        format!(
+            "test -f {file}\nrm {file}\necho run >> {}",
+            dir.join("runs.txt").display(),
+            file = file.display()
+        ),
+    );
+
+    let backends: Vec<Box<dyn Backend>> = vec![Box::new(Getrusage)];
+    let results =
+        crate::interleave::bench_interleaved(&[produce, consume], 3, &backends, None, None)
+            .unwrap();
+    // The warmup run and the repetitions.
+    let runs = std::fs::read_to_string(dir.join("runs.txt")).unwrap();
+    assert_eq!(runs.lines().count(), 4);
+
+    let composite = CommandConfig {
+        steps: vec![0, 1],
+        ..CommandConfig::new("roundtrip".to_owned())
+    };
+    let results = results.into_iter().map(Some).collect::<Vec<_>>();
+    let bench = bench(&composite, &results);
+    assert_eq!(bench.cmd, ["roundtrip"]);
+    let wall_time =
+        |result: &Option<SingleBench>| result.as_ref().unwrap().counters["wall-time"].value;
+    assert_eq!(
+        bench.counters["wall-time"].value,
+        wall_time(&results[0]) + wall_time(&results[1])
+    );
+    assert!(!bench.counters.contains_key("max-rss"));
+}
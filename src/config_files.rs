@@ -0,0 +1,342 @@
+//! Loading the config from several files, e.g. one per crate of a monorepo. The files are
+//! merged before the config is deserialized, so every file is a config of its own that may
+//! leave out whatever the others define.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use indexmap::IndexMap;
+use serde_json::{Map, Value};
+
+/// Sections with an entry per group or table. Names must be unique across all files.
+const NAMED_SECTIONS: &[&str] = &["commands", "render-versus-self", "render-versus-other"];
+
+/// Sections whose entries are concatenated.
+const LIST_SECTIONS: &[&str] = &["control-groups", "fixtures"];
+
+/// Sections with an entry per measure. Files may repeat a measure with the same value.
+const SHARED_SECTIONS: &[&str] = &["measure-kinds"];
+
+/// The merged config, and the file that defined every group.
+#[derive(Debug)]
+pub struct ConfigFiles {
+    pub config: Value,
+    /// The files, with the directories expanded.
+    pub files: Vec<PathBuf>,
+    pub group_sources: IndexMap<String, PathBuf>,
+}
+
+/// The config files to load: the paths themselves, and the `*.json` files in the directories
+/// among them in sorted order.
+pub fn expand(paths: &[PathBuf]) -> Result<Vec<PathBuf>, String> {
+    let mut files = vec![];
+    for path in paths {
+        if !path.is_dir() {
+            files.push(path.clone());
+            continue;
+        }
+
+        let entries = fs::read_dir(path)
+            .map_err(|e| format!("failed to read the directory {}: {e}", path.display()))?;
+        let mut json_files = vec![];
+        for entry in entries {
+            let entry = entry
+                .map_err(|e| format!("failed to read the directory {}: {e}", path.display()))?;
+            let file = entry.path();
+            if file.extension().is_some_and(|ext| ext == "json") && !file.is_dir() {
+                json_files.push(file);
+            }
+        }
+        if json_files.is_empty() {
+            return Err(format!("no config files in {}", path.display()));
+        }
+        json_files.sort();
+        files.extend(json_files);
+    }
+    Ok(files)
+}
+
+/// Read the config files at `paths`, expanding directories, and merge them.
+pub fn load(paths: &[PathBuf]) -> Result<ConfigFiles, String> {
+    let paths = expand(paths)?;
+    let files = paths
+        .iter()
+        .map(|path| {
+            let bytes =
+                fs::read(path).map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+            let config = serde_json::from_slice(&bytes)
+                .map_err(|e| format!("failed to parse {}: {e}", path.display()))?;
+            Ok((path.clone(), config))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    Ok(ConfigFiles {
+        files: paths,
+        ..merge(files)?
+    })
+}
+
+/// Merge the configs in order. Defining a group or table in more than one file is an error,
+/// as is setting anything else differently.
+pub fn merge(files: Vec<(PathBuf, Value)>) -> Result<ConfigFiles, String> {
+    let mut merged = Map::new();
+    // The file every top-level key and every named entry came from, for the errors.
+    let mut sources: IndexMap<String, PathBuf> = IndexMap::new();
+    let mut group_sources = IndexMap::new();
+
+    for (path, config) in files {
+        let Value::Object(config) = config else {
+            return Err(format!("{} is not a JSON object", path.display()));
+        };
+
+        for (key, value) in config {
+            let Some(existing) = merged.get_mut(&key) else {
+                if key == "commands" {
+                    record_groups(&mut group_sources, &value, &path);
+                }
+                for name in value.as_object().into_iter().flat_map(Map::keys) {
+                    sources.insert(format!("{key}/{name}"), path.clone());
+                }
+                sources.insert(key.clone(), path.clone());
+                merged.insert(key, value);
+                continue;
+            };
+            let other = &sources[&key];
+
+            if is_named_section(&key) {
+                let (Value::Object(existing), Value::Object(value)) = (existing, &value) else {
+                    return Err(format!(
+                        "`{key}` is not an object in {} or {}",
+                        other.display(),
+                        path.display()
+                    ));
+                };
+                for (name, entry) in value {
+                    let entry_key = format!("{key}/{name}");
+                    if let Some(other) = sources.get(&entry_key) {
+                        return Err(format!(
+                            "{} `{name}` is defined in both {} and {}",
+                            describe_entry(&key),
+                            other.display(),
+                            path.display()
+                        ));
+                    }
+                    if key == "commands" {
+                        group_sources.insert(name.clone(), path.clone());
+                    }
+                    sources.insert(entry_key, path.clone());
+                    existing.insert(name.clone(), entry.clone());
+                }
+            } else if LIST_SECTIONS.contains(&key.as_str()) {
+                let (Value::Array(existing), Value::Array(value)) = (existing, value) else {
+                    return Err(format!(
+                        "`{key}` is not a list in {} or {}",
+                        other.display(),
+                        path.display()
+                    ));
+                };
+                existing.extend(value);
+            } else if SHARED_SECTIONS.contains(&key.as_str()) {
+                let (Value::Object(existing), Value::Object(value)) = (existing, value) else {
+                    return Err(format!(
+                        "`{key}` is not an object in {} or {}",
+                        other.display(),
+                        path.display()
+                    ));
+                };
+                for (name, entry) in value {
+                    match existing.get(&name) {
+                        Some(existing_entry) if *existing_entry != entry => {
+                            return Err(format!(
+                                "`{name}` in `{key}` is set differently in {} and {}",
+                                sources[&format!("{key}/{name}")].display(),
+                                path.display()
+                            ));
+                        }
+                        Some(_) => {}
+                        None => {
+                            sources.insert(format!("{key}/{name}"), path.clone());
+                            existing.insert(name, entry);
+                        }
+                    }
+                }
+            } else if *existing != value {
+                return Err(format!(
+                    "`{key}` is set differently in {} and {}",
+                    other.display(),
+                    path.display()
+                ));
+            }
+        }
+    }
+
+    Ok(ConfigFiles {
+        config: Value::Object(merged),
+        files: vec![],
+        group_sources,
+    })
+}
+
+/// The sections with an entry per group, like `repetitions-for-group`, count as named ones.
+fn is_named_section(key: &str) -> bool {
+    NAMED_SECTIONS.contains(&key) || key.ends_with("-for-group")
+}
+
+fn describe_entry(key: &str) -> String {
+    match key {
+        "commands" => "the group".to_owned(),
+        "render-versus-self" | "render-versus-other" => format!("the `{key}` table"),
+        _ => format!("`{key}` of the group"),
+    }
+}
+
+fn record_groups(group_sources: &mut IndexMap<String, PathBuf>, commands: &Value, path: &Path) {
+    for group_name in commands.as_object().into_iter().flat_map(Map::keys) {
+        group_sources.insert(group_name.clone(), path.to_owned());
+    }
+}
+
+#[cfg(test)]
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("testdata/configs")
+        .join(name)
+}
+
+#[test]
+fn merge_config_files() {
+    let files = load(&[fixture("compress.json"), fixture("decompress.json")]).unwrap();
+
+    assert_eq!(
+        files.group_sources,
+        IndexMap::from([
+            ("compress".to_owned(), fixture("compress.json")),
+            ("compress-rs".to_owned(), fixture("compress.json")),
+            ("decompress".to_owned(), fixture("decompress.json")),
+        ])
+    );
+
+    let config = &files.config;
+    assert_eq!(
+        config["commands"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .collect::<Vec<_>>(),
+        ["compress", "compress-rs", "decompress"]
+    );
+    assert_eq!(
+        config["repetitions-for-group"],
+        serde_json::json!({ "compress": 10, "decompress": 5 })
+    );
+    assert_eq!(
+        config["render-versus-self"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .collect::<Vec<_>>(),
+        ["ng vs rs"]
+    );
+    assert_eq!(
+        config["render-versus-other"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .collect::<Vec<_>>(),
+        ["compress", "decompress"]
+    );
+    assert_eq!(
+        config["control-groups"],
+        serde_json::json!(["compress", "decompress"])
+    );
+    // Both set the same kind for the instructions per cycle.
+    assert_eq!(
+        config["measure-kinds"],
+        serde_json::json!({ "ipc": "ratio", "task-clock": "time" })
+    );
+    assert_eq!(config["normalized-time"], serde_json::json!(true));
+}
+
+#[test]
+fn config_directory() {
+    let files = expand(&[fixture("monorepo")]).unwrap();
+    assert_eq!(
+        files,
+        [
+            fixture("monorepo/a-compress.json"),
+            fixture("monorepo/b-decompress.json")
+        ]
+    );
+
+    let files = load(&[fixture("monorepo"), fixture("compress.json")]);
+    assert_eq!(
+        files.unwrap_err(),
+        format!(
+            "the group `compress` is defined in both {} and {}",
+            fixture("monorepo/a-compress.json").display(),
+            fixture("compress.json").display()
+        )
+    );
+
+    let empty = crate::testkit::test_dir("config-files-empty");
+    assert_eq!(
+        expand(std::slice::from_ref(&empty)).unwrap_err(),
+        format!("no config files in {}", empty.display())
+    );
+}
+
+#[test]
+fn conflicting_config_files() {
+    let err = |a, b| load(&[fixture(a), fixture(b)]).unwrap_err();
+    let both = |what: &str, a, b| {
+        format!(
+            "{what} {} and {}",
+            fixture(a).display(),
+            fixture(b).display()
+        )
+    };
+
+    assert_eq!(
+        err("compress.json", "duplicate-group.json"),
+        both(
+            "the group `compress-rs` is defined in both",
+            "compress.json",
+            "duplicate-group.json"
+        )
+    );
+    assert_eq!(
+        err("compress.json", "duplicate-table.json"),
+        both(
+            "the `render-versus-self` table `ng vs rs` is defined in both",
+            "compress.json",
+            "duplicate-table.json"
+        )
+    );
+    assert_eq!(
+        err("compress.json", "duplicate-repetitions.json"),
+        both(
+            "`repetitions-for-group` of the group `compress` is defined in both",
+            "compress.json",
+            "duplicate-repetitions.json"
+        )
+    );
+    assert_eq!(
+        err("compress.json", "different-settings.json"),
+        both(
+            "`normalized-time` is set differently in",
+            "compress.json",
+            "different-settings.json"
+        )
+    );
+    assert_eq!(
+        err("decompress.json", "different-settings.json"),
+        both(
+            "`task-clock` in `measure-kinds` is set differently in",
+            "decompress.json",
+            "different-settings.json"
+        )
+    );
+
+    assert!(merge(vec![(fixture("array.json"), serde_json::json!([]))])
+        .unwrap_err()
+        .ends_with("array.json is not a JSON object"));
+}
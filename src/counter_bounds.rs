@@ -0,0 +1,244 @@
+//! Counter values that can't be right, like 1.8e19 cycles from a counter that wrapped around.
+//! Stored once, such a value would poison the comparisons against the baseline for as long
+//! as it is one, so counters out of bounds are dropped from fresh measurements and from the
+//! previous results alike, with a warning and an entry in the run report:
+//!
+//! - a value or a variance that is negative, or not a number
+//! - more than `max-count` cycles or instructions per run, 1e15 by default
+//! - more than `max-duration` per run for the counters with a duration unit, like task-clock,
+//!   24 hours by default
+//! - a variance larger than the square of the value, for those same counters
+//!
+//! Suites with runs that really are that long raise the bounds:
+//!
+//! ```json
+//! "counter-bounds": { "max-count": 1e17, "max-duration": "3d" }
+//! ```
+//!
+//! The variance of small counts like page faults legitimately exceeds the square of their
+//! mean, so only the cycles, the instructions and the durations are held to it.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bench::{BenchCounter, SingleBench};
+use crate::perf_events;
+use crate::units;
+use crate::BenchData;
+
+/// The events whose counts are bounded by `max-count`.
+const COUNT_EVENTS: &[&str] = &["cycles", "instructions"];
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct CounterBounds {
+    /// The most cycles or instructions a single run can count.
+    #[serde(default = "default_max_count")]
+    pub max_count: f64,
+    /// The longest a single run can take, for the counters with a duration unit.
+    #[serde(default = "default_max_duration", deserialize_with = "units::secs")]
+    pub max_duration: Duration,
+}
+
+fn default_max_count() -> f64 {
+    1e15
+}
+
+fn default_max_duration() -> Duration {
+    Duration::from_secs(24 * 60 * 60)
+}
+
+impl Default for CounterBounds {
+    fn default() -> Self {
+        CounterBounds {
+            max_count: default_max_count(),
+            max_duration: default_max_duration(),
+        }
+    }
+}
+
+/// A counter that was dropped for being out of bounds.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DroppedCounter {
+    /// The commit of the results the counter was dropped from: the current one, or that of
+    /// the baseline.
+    pub commit: String,
+    pub group: String,
+    pub command: String,
+    pub counter: String,
+    pub reason: String,
+}
+
+impl DroppedCounter {
+    pub fn warning(&self) -> String {
+        format!(
+            "dropped `{}` of `{}` in the `{}` group of {}: {}",
+            self.counter, self.command, self.group, self.commit, self.reason
+        )
+    }
+}
+
+/// Why the counter `name` can't be right, or `None` when it is within the `bounds`.
+pub fn check(name: &str, counter: &BenchCounter, bounds: &CounterBounds) -> Option<String> {
+    let value = counter.value;
+    if !value.is_finite() || !counter.variance.is_finite() {
+        return Some(format!(
+            "the value {value} or the variance {} is not a number",
+            counter.variance
+        ));
+    }
+    if value < 0.0 {
+        return Some(format!("the value {value} is negative"));
+    }
+    if counter.variance < 0.0 {
+        return Some(format!("the variance {} is negative", counter.variance));
+    }
+
+    if COUNT_EVENTS.contains(&perf_events::decode(name).event) {
+        if value > bounds.max_count {
+            return Some(format!(
+                "{value:e} per run is more than the {:e} of `max-count`",
+                bounds.max_count
+            ));
+        }
+    } else if let Some(scale) = units::duration_unit_secs(&counter.unit) {
+        let max = bounds.max_duration.as_secs_f64();
+        if value * scale > max {
+            return Some(format!(
+                "{:.0}s per run is more than the {max:.0}s of `max-duration`",
+                value * scale
+            ));
+        }
+    } else {
+        return None;
+    }
+
+    if counter.variance > value * value {
+        return Some(format!(
+            "the variance {:e} is larger than the square of the value {value:e}",
+            counter.variance
+        ));
+    }
+    None
+}
+
+/// Drop the counters of `bench` that are out of `bounds`, returning them.
+pub fn drop_out_of_bounds(
+    bounds: &CounterBounds,
+    commit: &str,
+    group_name: &str,
+    bench: &mut SingleBench,
+) -> Vec<DroppedCounter> {
+    let mut dropped = vec![];
+    bench.counters.retain(|name, counter| {
+        let Some(reason) = check(name, counter, bounds) else {
+            return true;
+        };
+        dropped.push(DroppedCounter {
+            commit: commit.to_owned(),
+            group: group_name.to_owned(),
+            command: bench.cmd.join(" "),
+            counter: name.clone(),
+            reason,
+        });
+        false
+    });
+    dropped
+}
+
+/// Drop the counters of every command of `data` that are out of `bounds`, returning them.
+pub fn drop_from(bounds: &CounterBounds, data: &mut BenchData) -> Vec<DroppedCounter> {
+    let commit = data.short_commit_id();
+    let mut dropped = vec![];
+    for (group_name, benches) in &mut data.bench_groups {
+        for bench in benches {
+            dropped.extend(drop_out_of_bounds(bounds, &commit, group_name, bench));
+        }
+    }
+    dropped
+}
+
+#[test]
+fn counter_bounds() {
+    let bounds = CounterBounds::default();
+    #[rustfmt::skip]
+    let cases: &[(&str, f64, f64, &str, Option<&str>)] = &[
+        // name, value, variance, unit, why it is dropped
+        ("cycles", 1e9, 1e6, "", None),
+        ("cycles", 1.8e19, 1e6, "", Some("1.8e19 per run is more than the 1e15 of `max-count`")),
+        ("cycles:u", 2e15, 0.0, "", Some("2e15 per run is more than the 1e15 of `max-count`")),
+        ("cpu_core/instructions/", 2e15, 0.0, "", Some("2e15 per run is more than the 1e15 of `max-count`")),
+        ("instructions", -1.0, 0.0, "", Some("the value -1 is negative")),
+        ("branch-misses", -5.0, 0.0, "", Some("the value -5 is negative")),
+        ("branch-misses", 5.0, -1.0, "", Some("the variance -1 is negative")),
+        ("instructions", 1000.0, 1e7, "", Some("the variance 1e7 is larger than the square of the value 1e3")),
+        ("task-clock", 250.0, 4.0, "msec", None),
+        ("task-clock", 9e7, 4.0, "msec", Some("90000s per run is more than the 86400s of `max-duration`")),
+        ("wall-time", 90000.0, 4.0, "s", Some("90000s per run is more than the 86400s of `max-duration`")),
+        ("task-clock", 2.0, 5.0, "msec", Some("the variance 5e0 is larger than the square of the value 2e0")),
+        // Small counts vary more than their mean, and have no upper bound.
+        ("major-faults", 0.5, 1.0, "", None),
+        ("output-bytes", 1e18, 0.0, "B", None),
+        ("cycles", f64::NAN, 0.0, "", Some("the value NaN or the variance 0 is not a number")),
+        ("task-clock", 250.0, f64::INFINITY, "msec", Some("the value 250 or the variance inf is not a number")),
+    ];
+    for &(name, value, variance, unit, expected) in cases {
+        let counter = BenchCounter {
+            value,
+            variance,
+            repetitions: 20,
+            unit: unit.to_owned(),
+        };
+        assert_eq!(
+            check(name, &counter, &bounds).as_deref(),
+            expected,
+            "{name} {value} ± {variance} {unit}"
+        );
+    }
+
+    // Long suites raise the bounds.
+    let bounds: CounterBounds =
+        serde_json::from_str(r#"{ "max-count": 1e17, "max-duration": "3d" }"#).unwrap();
+    assert_eq!(bounds.max_duration, Duration::from_secs(3 * 86400));
+    let counter = |value: f64, unit: &str| BenchCounter {
+        value,
+        variance: 0.0,
+        repetitions: 20,
+        unit: unit.to_owned(),
+    };
+    assert_eq!(check("cycles", &counter(2e15, ""), &bounds), None);
+    assert_eq!(check("task-clock", &counter(9e7, "msec"), &bounds), None);
+    assert!(check("cycles", &counter(2e17, ""), &bounds).is_some());
+    assert!(serde_json::from_str::<CounterBounds>(r#"{ "max-cycles": 1 }"#).is_err());
+}
+
+#[test]
+fn drop_counters_out_of_bounds() {
+    let mut data = crate::testkit::BenchDataBuilder::cycles(
+        "1111111111111111111111111111111111111111",
+        &[("compress", &[("./c 1", 1000.0), ("./c 2", 1.8e19)])],
+    )
+    .build();
+    let dropped = drop_from(&CounterBounds::default(), &mut data);
+    assert_eq!(
+        dropped,
+        [DroppedCounter {
+            commit: "1111111".to_owned(),
+            group: "compress".to_owned(),
+            command: "./c 2".to_owned(),
+            counter: "cycles".to_owned(),
+            reason: "1.8e19 per run is more than the 1e15 of `max-count`".to_owned(),
+        }]
+    );
+    assert_eq!(
+        dropped[0].warning(),
+        "dropped `cycles` of `./c 2` in the `compress` group of 1111111: 1.8e19 per run is more than the 1e15 of `max-count`"
+    );
+    assert!(data.bench_groups["compress"][0]
+        .counters
+        .contains_key("cycles"));
+    assert!(!data.bench_groups["compress"][1]
+        .counters
+        .contains_key("cycles"));
+}
@@ -0,0 +1,207 @@
+//! The canonical names of counters. Perf reports the same event under different names
+//! depending on its version and the machine, e.g. `cpu_core/cycles/` or `cpu_core/cycles/u` on
+//! hybrid CPUs, and years of stored results use a mix of them. Fresh results, loaded results
+//! and the measures of the config are all renamed to the canonical names, so they match. The
+//! modifier of an event is part of its canonical name, e.g. `cycles:u`, see
+//! [`crate::perf_events`].
+
+use std::collections::BTreeMap;
+
+use indexmap::IndexMap;
+use serde::Deserialize;
+
+use crate::bench::{BenchCounter, SingleBench};
+use crate::{perf_events, BenchData};
+
+/// Aliases of perf events, by the name they are stored under.
+const BUILTIN_RENAMES: &[(&str, &str)] = &[
+    ("cpu-cycles", "cycles"),
+    ("idle-cycles-frontend", "stalled-cycles-frontend"),
+    ("idle-cycles-backend", "stalled-cycles-backend"),
+];
+
+/// The `counter-renames` of the config, e.g. `{ "cycles:k": "kernel-cycles" }`, in addition
+/// to the built-in renames.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(transparent)]
+pub struct CounterRenames(IndexMap<String, String>);
+
+impl CounterRenames {
+    /// The canonical name of `counter`. In order of precedence:
+    ///
+    /// 1. a configured rename of the name as is,
+    /// 2. a configured rename of the name without the `cpu_core/<event>/` of hybrid CPUs, with
+    ///    the modifier as in `cycles:u`,
+    /// 3. a configured or else built-in rename of the event, followed by the modifier,
+    /// 4. that name.
+    ///
+    /// The result of a configured rename isn't renamed again.
+    pub fn canonical(&self, counter: &str) -> String {
+        if let Some(renamed) = self.0.get(counter) {
+            return renamed.clone();
+        }
+
+        let decoded = perf_events::decode(counter);
+        // The other PMUs of hybrid CPUs, like `cpu_atom`, count something else.
+        if decoded.pmu.is_some_and(|pmu| pmu != "cpu_core") {
+            return counter.to_owned();
+        }
+        let with_modifier = |event: &str| match decoded.modifier {
+            Some(modifier) => format!("{event}:{modifier}"),
+            None => event.to_owned(),
+        };
+        if let Some(renamed) = self.0.get(&with_modifier(decoded.event)) {
+            return renamed.clone();
+        }
+        let event = self.0.get(decoded.event).map_or_else(
+            || {
+                BUILTIN_RENAMES
+                    .iter()
+                    .find(|(alias, _)| *alias == decoded.event)
+                    .map_or(decoded.event, |(_, name)| name)
+            },
+            String::as_str,
+        );
+        with_modifier(event)
+    }
+
+    /// Rename the counters of `bench` to their canonical names. When two counters get the same
+    /// name, the one with more repetitions is kept, or on a tie the one that already had the
+    /// name. Returns a warning for every counter that was dropped.
+    pub fn canonicalize_bench(&self, group_name: &str, bench: &mut SingleBench) -> Vec<String> {
+        let mut warnings = vec![];
+        let mut counters: BTreeMap<String, (String, BenchCounter)> = BTreeMap::new();
+
+        for (name, counter) in std::mem::take(&mut bench.counters) {
+            let canonical = self.canonical(&name);
+            let Some((kept_name, kept)) = counters.get(&canonical) else {
+                counters.insert(canonical, (name, counter));
+                continue;
+            };
+
+            let replace = counter.repetitions > kept.repetitions
+                || (counter.repetitions == kept.repetitions && name == canonical);
+            let (kept_name, dropped_name, repetitions) = if replace {
+                (&name, kept_name, counter.repetitions)
+            } else {
+                (kept_name, &name, kept.repetitions)
+            };
+            warnings.push(format!(
+                "`{dropped_name}` and `{kept_name}` of `{}` in the `{group_name}` group are both `{canonical}`, keeping `{kept_name}` with {repetitions} repetitions",
+                bench.cmd.join(" "),
+            ));
+
+            if replace {
+                counters.insert(canonical, (name, counter));
+            }
+        }
+
+        bench.counters = counters
+            .into_iter()
+            .map(|(canonical, (_, counter))| (canonical, counter))
+            .collect();
+        warnings
+    }
+
+    /// [`Self::canonicalize_bench`] for every benchmark of `data`.
+    pub fn canonicalize(&self, data: &mut BenchData) -> Vec<String> {
+        let mut warnings = vec![];
+        for (group_name, benches) in &mut data.bench_groups {
+            for bench in benches {
+                warnings.extend(self.canonicalize_bench(group_name, bench));
+            }
+        }
+        warnings
+    }
+}
+
+#[test]
+fn canonical_names() {
+    let renames: CounterRenames = serde_json::from_str(
+        r#"{
+            "cpu_core/instructions/": "core-instructions",
+            "cycles:k": "kernel-cycles",
+            "L1-dcache-loads": "loads",
+            "loads": "memory-loads"
+        }"#,
+    )
+    .unwrap();
+
+    for (counter, canonical) in [
+        // Canonical names stay.
+        ("cycles", "cycles"),
+        ("task-clock", "task-clock"),
+        // Hybrid CPUs.
+        ("cpu_core/cycles/", "cycles"),
+        ("cpu_atom/cycles/", "cpu_atom/cycles/"),
+        // Modifiers count something else, and are kept in either form.
+        ("cycles:u", "cycles:u"),
+        ("cpu_core/cycles/u", "cycles:u"),
+        ("cpu_core/cycles:u/", "cycles:u"),
+        ("cycles:k", "kernel-cycles"),
+        ("cpu_core/cycles/k", "kernel-cycles"),
+        ("instructions:k", "instructions:k"),
+        ("cpu_core/instructions/k", "instructions:k"),
+        ("cpu_atom/cycles:u/", "cpu_atom/cycles:u/"),
+        // Built-in aliases, also when decorated.
+        ("cpu-cycles", "cycles"),
+        ("cpu-cycles:u", "cycles:u"),
+        ("idle-cycles-frontend", "stalled-cycles-frontend"),
+        ("cpu_core/idle-cycles-backend/", "stalled-cycles-backend"),
+        // A configured rename of the name as is wins over stripping the decorations.
+        ("cpu_core/instructions/", "core-instructions"),
+        ("instructions:u", "instructions:u"),
+        // Configured renames apply to the stripped name and to the event of a modified one
+        // too, but aren't chained.
+        ("L1-dcache-loads", "loads"),
+        ("cpu_core/L1-dcache-loads/", "loads"),
+        ("L1-dcache-loads:u", "loads:u"),
+        ("loads", "memory-loads"),
+    ] {
+        assert_eq!(renames.canonical(counter), canonical, "{counter}");
+    }
+
+    // Configured renames win over the built-in ones.
+    let renames: CounterRenames =
+        serde_json::from_str(r#"{ "cpu-cycles": "all-cycles" }"#).unwrap();
+    assert_eq!(renames.canonical("cpu-cycles"), "all-cycles");
+    assert_eq!(renames.canonical("cpu_core/cpu-cycles/"), "all-cycles");
+    assert_eq!(CounterRenames::default().canonical("cpu-cycles"), "cycles");
+}
+
+#[test]
+fn canonicalize_colliding_counters() {
+    let mut data =
+        crate::testkit::BenchDataBuilder::new("1111111111111111111111111111111111111111")
+            .group("compress", |g| {
+                g.bench(["./c", "1"], |b| {
+                    b.counter("cpu_core/cycles/", 1000.0, 100.0, 20, "")
+                        .counter("cycles", 900.0, 100.0, 5, "")
+                        .counter("cycles:u", 800.0, 100.0, 5, "")
+                        .counter("cpu_core/cycles/u", 700.0, 100.0, 20, "")
+                        .counter("cpu_core/instructions/", 2000.0, 100.0, 20, "")
+                        .counter("instructions", 2100.0, 100.0, 20, "")
+                        .counter("task-clock", 10.0, 1.0, 20, "msec")
+                })
+            })
+            .build();
+
+    let warnings = CounterRenames::default().canonicalize(&mut data);
+    let counters = &data.bench_groups["compress"][0].counters;
+    assert_eq!(
+        counters.keys().collect::<Vec<_>>(),
+        ["cycles", "cycles:u", "instructions", "task-clock"]
+    );
+    // The most repetitions win, and on a tie the counter that already had the name.
+    assert_eq!(counters["cycles"].value, 1000.0);
+    assert_eq!(counters["cycles:u"].value, 700.0);
+    assert_eq!(counters["instructions"].value, 2100.0);
+    assert_eq!(
+        warnings,
+        [
+            "`cycles` and `cpu_core/cycles/` of `./c 1` in the `compress` group are both `cycles`, keeping `cpu_core/cycles/` with 20 repetitions",
+            "`cycles:u` and `cpu_core/cycles/u` of `./c 1` in the `compress` group are both `cycles:u`, keeping `cpu_core/cycles/u` with 20 repetitions",
+            "`cpu_core/instructions/` and `instructions` of `./c 1` in the `compress` group are both `instructions`, keeping `instructions` with 20 repetitions",
+        ]
+    );
+}
@@ -0,0 +1,101 @@
+//! The order of the counters of a group, the same in the raw tables and the CSV whatever order
+//! they were measured or stored in: first the counters of the `perf-events-for-group` of the
+//! group, in the order of the config, then all others sorted by name. The counters of an event
+//! on the PMUs of a hybrid CPU, like `cpu_core/cycles/` for `cycles`, go with the event.
+//!
+//! The JSON of `diff` has no config, so its counters are always sorted by name.
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::perf_events::PerfEvent;
+use crate::required_counters::is_counter_of;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CounterOrder<'a> {
+    /// The configured events of the groups, none sorts every counter by name.
+    events_for_group: Option<&'a HashMap<String, Vec<PerfEvent>>>,
+}
+
+impl<'a> CounterOrder<'a> {
+    pub fn new(events_for_group: &'a HashMap<String, Vec<PerfEvent>>) -> Self {
+        CounterOrder {
+            events_for_group: Some(events_for_group),
+        }
+    }
+
+    /// The distinct `counters` of the group, in order.
+    pub fn sort<'b>(
+        &self,
+        group_name: &str,
+        counters: impl IntoIterator<Item = &'b String>,
+    ) -> Vec<&'b String> {
+        let mut counters = counters
+            .into_iter()
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+        let Some(events) = self
+            .events_for_group
+            .and_then(|events_for_group| events_for_group.get(group_name))
+        else {
+            return counters;
+        };
+        let names = events.iter().map(PerfEvent::name).collect::<Vec<_>>();
+        counters.sort_by_key(|counter| {
+            names
+                .iter()
+                .position(|name| is_counter_of(counter, name))
+                .unwrap_or(names.len())
+        });
+        counters
+    }
+}
+
+#[test]
+fn configured_counters_first() {
+    let events_for_group = HashMap::from([(
+        "compress".to_owned(),
+        PerfEvent::parse_list("task-clock,instructions,cycles"),
+    )]);
+    let order = CounterOrder::new(&events_for_group);
+    let counters = [
+        "wall-time",
+        "cycles",
+        "cpu_atom/cycles/",
+        "branches",
+        "instructions",
+        "task-clock",
+        "cycles",
+        "cpu_core/cycles/",
+    ]
+    .map(String::from);
+
+    assert_eq!(
+        order.sort("compress", &counters),
+        [
+            "task-clock",
+            "instructions",
+            "cpu_atom/cycles/",
+            "cpu_core/cycles/",
+            "cycles",
+            "branches",
+            "wall-time",
+        ]
+    );
+
+    // Without configured events, and with the counters in any order, they are sorted.
+    let sorted = [
+        "branches",
+        "cpu_atom/cycles/",
+        "cpu_core/cycles/",
+        "cycles",
+        "instructions",
+        "task-clock",
+        "wall-time",
+    ];
+    assert_eq!(order.sort("decompress", &counters), sorted);
+    assert_eq!(
+        CounterOrder::default().sort("compress", counters.iter().rev()),
+        sorted
+    );
+}
@@ -0,0 +1,490 @@
+//! The results of the same commit on several machines side by side, e.g. when every commit on
+//! `main` is benchmarked on both x86_64 and aarch64 runners. Every run appends its own line to
+//! the results file, so the run on the last machine finds those of the others there, and
+//! compares each machine against its own baseline.
+
+use std::fmt::Write;
+
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+use crate::bench::BenchCounter;
+use crate::compare::{find_prev_bench_at, ComparisonRow};
+use crate::machine;
+use crate::markers::Markers;
+use crate::measure::MeasureKind;
+use crate::{readable, BenchData};
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct CrossMachineConfig {
+    /// Short names of machines, by identifier, by CPU model or by architecture, e.g.
+    /// `{ "ARM64": "arm" }`.
+    /// Machines without one are named by their architecture.
+    #[serde(default)]
+    pub aliases: IndexMap<String, String>,
+    /// The tables, like the `render-versus-other` ones.
+    pub tables: IndexMap<String, CrossMachineTableConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct CrossMachineTableConfig {
+    pub measure: String,
+    pub command: String,
+    pub rows: IndexMap<String, usize>,
+}
+
+/// The resolved tables, with a column per machine.
+#[derive(Debug, Serialize)]
+pub struct CrossMachine {
+    pub machines: Vec<Machine>,
+    pub tables: Vec<CrossMachineTable>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Machine {
+    pub label: String,
+    pub arch: String,
+    pub cpu_model: String,
+    /// The commit of the baseline of this machine, if it has one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub baseline: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CrossMachineTable {
+    pub name: String,
+    pub measure: String,
+    pub rows: Vec<CrossMachineRow>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CrossMachineRow {
+    pub name: String,
+    /// One per machine, in the order of [`CrossMachine::machines`].
+    pub cells: Vec<MachineCell>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MachineCell {
+    /// `None` when the machine didn't measure the counter.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<BenchCounter>,
+    /// The change against the baseline of the machine, if it has one with the counter.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub change: Option<ComparisonRow>,
+}
+
+/// The results of a commit on one machine, and of the base commit on the same machine.
+struct MachineResults<'a> {
+    data: &'a BenchData,
+    baseline: Option<&'a BenchData>,
+}
+
+fn same_machine(a: &BenchData, b: &BenchData) -> bool {
+    machine::same_machine(a.machine_key(), b.machine_key())
+}
+
+/// The latest of `entries` for `commit` on the machine of `data`.
+fn latest<'a>(entries: &'a [BenchData], commit: &str, data: &BenchData) -> Option<&'a BenchData> {
+    entries
+        .iter()
+        .filter(|entry| entry.commit_id() == commit && same_machine(entry, data))
+        .max_by_key(|entry| entry.timestamp)
+}
+
+impl CrossMachineConfig {
+    /// The current run and the results of the same commit on other machines from `history`,
+    /// each with the results of `base_commit` on the same machine. `None` when no other machine
+    /// has results for the commit.
+    pub fn collect(
+        &self,
+        kinds: &IndexMap<String, MeasureKind>,
+        data: &BenchData,
+        history: &[BenchData],
+        base_commit: Option<&str>,
+    ) -> Option<CrossMachine> {
+        let commit = data.commit_id();
+        let baseline = |data: &BenchData| base_commit.and_then(|base| latest(history, base, data));
+
+        let mut machines = vec![MachineResults {
+            data,
+            baseline: baseline(data),
+        }];
+        for entry in history.iter().filter(|entry| entry.commit_id() == commit) {
+            if machines
+                .iter()
+                .any(|machine| same_machine(machine.data, entry))
+            {
+                continue;
+            }
+            let data = latest(history, &commit, entry).unwrap();
+            machines.push(MachineResults {
+                data,
+                baseline: baseline(data),
+            });
+        }
+        if machines.len() < 2 {
+            return None;
+        }
+
+        let tables = self
+            .tables
+            .iter()
+            .map(|(name, table)| CrossMachineTable {
+                name: name.clone(),
+                measure: table.measure.clone(),
+                rows: table
+                    .rows
+                    .iter()
+                    .filter_map(|(row_name, &index)| {
+                        // The commands of the other machines are found like previous results.
+                        let bench = data.bench_groups.get(&table.command)?.get(index)?;
+                        let cells = machines
+                            .iter()
+                            .map(|machine| {
+                                let find = |data: &BenchData| {
+                                    let group = data.bench_groups.get(&table.command)?;
+                                    let found = find_prev_bench_at(group, bench, index)?;
+                                    found.counters.get(&table.measure).cloned()
+                                };
+                                let value = find(machine.data);
+                                let change = value
+                                    .as_ref()
+                                    .zip(machine.baseline.and_then(find))
+                                    .map(|(after, before)| {
+                                        ComparisonRow::new(
+                                            row_name.clone(),
+                                            table.measure.clone(),
+                                            MeasureKind::of(kinds, &table.measure),
+                                            &before,
+                                            after,
+                                        )
+                                    });
+                                MachineCell { value, change }
+                            })
+                            .collect();
+                        Some(CrossMachineRow {
+                            name: row_name.clone(),
+                            cells,
+                        })
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        let mut machines = machines
+            .iter()
+            .map(|machine| Machine {
+                label: self.label(machine.data),
+                arch: machine.data.arch.clone(),
+                cpu_model: machine.data.cpu_model.clone(),
+                baseline: machine.baseline.map(|baseline| baseline.commit_id()),
+            })
+            .collect::<Vec<_>>();
+        // Machines of the same architecture without aliases are told apart by their CPU.
+        let labels = machines
+            .iter()
+            .map(|machine| machine.label.clone())
+            .collect::<Vec<_>>();
+        for machine in &mut machines {
+            if labels
+                .iter()
+                .filter(|label| **label == machine.label)
+                .count()
+                > 1
+            {
+                machine.label = format!("{} ({})", machine.label, machine.cpu_model);
+            }
+        }
+
+        Some(CrossMachine { machines, tables })
+    }
+
+    /// The alias of the machine, or of the CPU model, or of the architecture, or the
+    /// architecture.
+    fn label(&self, data: &BenchData) -> String {
+        data.machine_id
+            .as_ref()
+            .and_then(|id| self.aliases.get(id))
+            .or_else(|| self.aliases.get(&data.cpu_model))
+            .or_else(|| self.aliases.get(&data.arch))
+            .unwrap_or(&data.arch)
+            .clone()
+    }
+}
+
+impl CrossMachine {
+    pub fn render_markdown(
+        &self,
+        md: &mut String,
+        repository: &str,
+        data: &BenchData,
+        markers: &Markers,
+    ) {
+        writeln!(
+            md,
+            "## [`{}`](https://github.com/{repository}/commit/{}) on {} machines\n",
+            data.short_commit_id(),
+            data.commit_hash,
+            self.machines.len()
+        )
+        .unwrap();
+        for machine in &self.machines {
+            let baseline = match &machine.baseline {
+                Some(commit) => format!("compared against `{}`", &commit[..commit.len().min(7)]),
+                None => "no baseline".to_owned(),
+            };
+            writeln!(md, "- {}: {}, {baseline}", machine.label, machine.cpu_model).unwrap();
+        }
+        writeln!(md).unwrap();
+
+        let mut header = "| name |".to_owned();
+        let mut separator = "| --- |".to_owned();
+        for machine in &self.machines {
+            write!(header, " {} | Δ |", machine.label).unwrap();
+            separator.push_str(" --- | --- |");
+        }
+
+        for table in &self.tables {
+            writeln!(md, "### {} ({})\n", table.name, table.measure).unwrap();
+            writeln!(md, "{header}\n{separator}").unwrap();
+            for row in &table.rows {
+                write!(md, "| {} |", row.name).unwrap();
+                for cell in &row.cells {
+                    cell.render_markdown(md, markers);
+                }
+                writeln!(md).unwrap();
+            }
+            let used = table
+                .rows
+                .iter()
+                .flat_map(|row| &row.cells)
+                .filter_map(|cell| cell.change.as_ref())
+                .map(ComparisonRow::marker);
+            if let Some(legend) = markers.legend(used) {
+                writeln!(md, "\n{legend}").unwrap();
+            }
+            writeln!(md).unwrap();
+        }
+    }
+}
+
+impl MachineCell {
+    fn render_markdown(&self, md: &mut String, markers: &Markers) {
+        match &self.value {
+            Some(value) => write!(
+                md,
+                " `{} ± {}` |",
+                readable(value.value, &value.unit),
+                readable(value.variance.sqrt().round(), &value.unit)
+            )
+            .unwrap(),
+            None => write!(md, " `n.a.` |").unwrap(),
+        }
+        match &self.change {
+            Some(change) => {
+                let significant = markers.padded(change.marker());
+                write!(md, " `{significant} {:>7}` |", change.format_delta()).unwrap();
+            }
+            None => write!(md, " `n.a.` |").unwrap(),
+        }
+    }
+}
+
+#[cfg(test)]
+fn results_for_test() -> Vec<BenchData> {
+    let results = std::fs::read(
+        std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("testdata/cross-machine/results.json"),
+    )
+    .unwrap();
+    results
+        .split(|&b| b == b'\n')
+        .filter_map(|line| serde_json::from_slice(line).ok())
+        .collect()
+}
+
+#[cfg(test)]
+fn config_for_test() -> CrossMachineConfig {
+    serde_json::from_str(
+        r#"{
+            "aliases": { "Neoverse-N1": "graviton2" },
+            "tables": {
+                "compression": { "measure": "cycles", "command": "compress", "rows": { "level 1": 0, "level 9": 1 } }
+            }
+        }"#,
+    )
+    .unwrap()
+}
+
+#[test]
+fn machines_side_by_side() {
+    let history = results_for_test();
+    // The current run on the x86_64 machine, the aarch64 one already stored its results.
+    let current = history
+        .iter()
+        .find(|entry| entry.commit_hash == "2".repeat(40) && entry.arch == "X64")
+        .unwrap();
+    let history = history
+        .iter()
+        .filter(|entry| !std::ptr::eq(*entry, current))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let cross = config_for_test()
+        .collect(&IndexMap::new(), current, &history, Some(&"1".repeat(40)))
+        .unwrap();
+    assert_eq!(
+        cross
+            .machines
+            .iter()
+            .map(|machine| (machine.label.as_str(), machine.baseline.is_some()))
+            .collect::<Vec<_>>(),
+        [("X64", true), ("graviton2", true)]
+    );
+
+    let mut md = String::new();
+    cross.render_markdown(&mut md, "owner/repo", current, &Markers::default());
+    assert_eq!(
+        md,
+        "## [`2222222`](https://github.com/owner/repo/commit/2222222222222222222222222222222222222222) on 2 machines\n\n\
+         - X64: AMD EPYC 7763 64-Core Processor, compared against `1111111`\n\
+         - graviton2: Neoverse-N1, compared against `1111111`\n\n\
+         ### compression (cycles)\n\n\
+         | name | X64 | Δ | graviton2 | Δ |\n\
+         | --- | --- | --- | --- | --- |\n\
+         | level 1 | `  1.00G ±   1.00M` | `💩 +10.00%` | `  2.00G ±  10.00M` | `    +0.05%` |\n\
+         | level 9 | `  3.00G ±   1.00M` | `🚀  -3.45%` | `n.a.` | `n.a.` |\n\n\
+         🚀 significant improvement · 💩 significant regression\n\n"
+    );
+}
+
+#[test]
+fn machine_without_baseline() {
+    let history = results_for_test();
+    // The current run on the aarch64 machine, which has no results for the base commit.
+    let current = history
+        .iter()
+        .find(|entry| entry.commit_hash == "2".repeat(40) && entry.arch == "ARM64")
+        .unwrap();
+    let history = history
+        .iter()
+        .filter(|entry| !(entry.arch == "ARM64" && entry.commit_hash == "1".repeat(40)))
+        .filter(|entry| !std::ptr::eq(*entry, current))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let cross = config_for_test()
+        .collect(&IndexMap::new(), current, &history, Some(&"1".repeat(40)))
+        .unwrap();
+    assert_eq!(cross.machines[0].label, "graviton2");
+    assert_eq!(cross.machines[0].baseline, None);
+    assert_eq!(cross.machines[1].label, "X64");
+
+    let level_1 = &cross.tables[0].rows[0].cells;
+    assert_eq!(level_1[0].value.as_ref().unwrap().value, 2001000000.0);
+    assert!(level_1[0].change.is_none());
+    assert!(level_1[1].change.is_some());
+
+    let mut md = String::new();
+    cross.render_markdown(&mut md, "owner/repo", current, &Markers::default());
+    assert!(
+        md.contains("- graviton2: Neoverse-N1, no baseline\n"),
+        "{md}"
+    );
+    assert!(
+        md.contains(
+            "| level 1 | `  2.00G ±  10.00M` | `n.a.` | `  1.00G ±   1.00M` | `💩 +10.00%` |\n"
+        ),
+        "{md}"
+    );
+
+    // Only one machine measured the commit.
+    let alone = history
+        .iter()
+        .filter(|entry| entry.arch == "ARM64")
+        .cloned()
+        .collect::<Vec<_>>();
+    assert!(config_for_test()
+        .collect(&IndexMap::new(), current, &alone, Some(&"1".repeat(40)))
+        .is_none());
+}
+
+#[test]
+fn machines_of_the_same_arch() {
+    let history = results_for_test();
+    let current = history
+        .iter()
+        .find(|entry| entry.commit_hash == "2".repeat(40) && entry.arch == "X64")
+        .unwrap();
+    let mut other = current.clone();
+    other.cpu_model = "Intel(R) Xeon(R) Platinum 8370C CPU @ 2.80GHz".to_owned();
+
+    let cross = config_for_test()
+        .collect(&IndexMap::new(), current, &[other], None)
+        .unwrap();
+    assert_eq!(
+        cross
+            .machines
+            .iter()
+            .map(|machine| machine.label.as_str())
+            .collect::<Vec<_>>(),
+        [
+            "X64 (AMD EPYC 7763 64-Core Processor)",
+            "X64 (Intel(R) Xeon(R) Platinum 8370C CPU @ 2.80GHz)"
+        ]
+    );
+}
+
+#[test]
+fn machines_by_identifier() {
+    let history = results_for_test();
+    let current = history
+        .iter()
+        .find(|entry| entry.commit_hash == "2".repeat(40) && entry.arch == "X64")
+        .unwrap();
+    // The current run records the identifier of its machine, and its CPU model changed
+    // cosmetically since the baseline was measured.
+    let mut renamed = current.clone();
+    renamed.cpu_model = "AMD EPYC 7763  64-Core Processor".to_owned();
+    renamed.machine_id = Some("x64-pool".to_owned());
+    let history = history
+        .iter()
+        .filter(|entry| !std::ptr::eq(*entry, current))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let cross = config_for_test()
+        .collect(&IndexMap::new(), &renamed, &history, Some(&"1".repeat(40)))
+        .unwrap();
+    assert_eq!(
+        cross
+            .machines
+            .iter()
+            .map(|machine| (machine.label.as_str(), machine.baseline.is_some()))
+            .collect::<Vec<_>>(),
+        [("X64", true), ("graviton2", true)]
+    );
+
+    // Another machine of the same model with an identifier of its own is told apart, and
+    // named by its alias.
+    let mut other = renamed.clone();
+    other.machine_id = Some("x64-other".to_owned());
+    let config = CrossMachineConfig {
+        aliases: IndexMap::from([("x64-other".to_owned(), "spare".to_owned())]),
+        ..config_for_test()
+    };
+    let cross = config
+        .collect(&IndexMap::new(), &renamed, &[other], None)
+        .unwrap();
+    assert_eq!(
+        cross
+            .machines
+            .iter()
+            .map(|machine| machine.label.as_str())
+            .collect::<Vec<_>>(),
+        ["X64", "spare"]
+    );
+}
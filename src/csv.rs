@@ -0,0 +1,221 @@
+//! A flat CSV of every measurement of a run with `--csv <path>`, for spreadsheets: a row per
+//! counter of every command, with the value of the baseline and the relative change when
+//! there is one. The counters are in the order of the raw tables, see
+//! [`crate::counter_order`].
+//!
+//! The fields follow RFC 4180: a field with a comma, a quote or a line break is quoted, with
+//! its quotes doubled. Numbers are written by Rust, which always uses `.` as the decimal
+//! separator, whatever the locale, rounded to [`DECIMALS`] decimals so the same results give
+//! the same CSV on every platform.
+
+use std::borrow::Cow;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+use crate::compare::find_prev_bench;
+use crate::counter_order::CounterOrder;
+use crate::sanitize::Sanitizer;
+use crate::{format_number, BenchData};
+
+/// The columns, in order. Rows are in the order of the groups, their commands and their
+/// counters. New columns are only ever added at the end.
+pub const COLUMNS: &[&str] = &[
+    "group",
+    "command_index",
+    "command",
+    "counter",
+    "value",
+    "variance",
+    "std_dev",
+    "repetitions",
+    "unit",
+    "commit_hash",
+    "commit_timestamp",
+    "cpu_model",
+    // Empty without a baseline, or when the baseline didn't measure the counter.
+    "baseline_value",
+    // In percent of the baseline value, empty when that is zero.
+    "delta_percent",
+];
+
+/// The decimals of the numbers, well below the noise of any measurement.
+pub const DECIMALS: usize = 6;
+
+/// Quote `field` when it has to be.
+fn field(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+/// The CSV of the measurements of `data`, compared with `prev_results`, with the counters of
+/// every command in the `counter_order`. The names and the command lines are sanitized.
+pub fn render(
+    data: &BenchData,
+    prev_results: Option<&BenchData>,
+    counter_order: &CounterOrder,
+    sanitizer: &Sanitizer,
+) -> String {
+    let mut csv = COLUMNS.join(",");
+    csv.push('\n');
+    for (group_name, benches) in &data.bench_groups {
+        let prev_group = prev_results.and_then(|prev| prev.bench_groups.get(group_name));
+        for (index, bench) in benches.iter().enumerate() {
+            let prev_bench = prev_group.and_then(|group| find_prev_bench(group, bench));
+            let command = bench.cmd.join(" ");
+            for counter_name in counter_order.sort(group_name, bench.counters.keys()) {
+                let counter = &bench.counters[counter_name];
+                let baseline = prev_bench.and_then(|prev| prev.counters.get(counter_name));
+                let baseline_value = baseline
+                    .map(|baseline| format_number(baseline.value, DECIMALS))
+                    .unwrap_or_default();
+                let delta = baseline
+                    .filter(|baseline| baseline.value != 0.0)
+                    .map(|baseline| {
+                        let delta = (counter.value - baseline.value) / baseline.value * 100.0;
+                        format_number(delta, DECIMALS)
+                    })
+                    .unwrap_or_default();
+                writeln!(
+                    csv,
+                    "{},{index},{},{},{},{},{},{},{},{},{},{},{baseline_value},{delta}",
+                    field(&sanitizer.sanitize(group_name)),
+                    field(&sanitizer.sanitize(&command)),
+                    field(counter_name),
+                    format_number(counter.value, DECIMALS),
+                    format_number(counter.variance, DECIMALS),
+                    format_number(counter.variance.sqrt(), DECIMALS),
+                    counter.repetitions,
+                    field(&counter.unit),
+                    field(&data.commit_hash),
+                    data.commit_timestamp,
+                    field(&sanitizer.sanitize(&data.cpu_model)),
+                )
+                .unwrap();
+            }
+        }
+    }
+    csv
+}
+
+pub fn write(
+    path: &Path,
+    data: &BenchData,
+    prev_results: Option<&BenchData>,
+    counter_order: &CounterOrder,
+    sanitizer: &Sanitizer,
+) -> Result<(), String> {
+    fs::write(path, render(data, prev_results, counter_order, sanitizer))
+        .map_err(|e| format!("failed to write the CSV to {}: {e}", path.display()))
+}
+
+/// The records of `csv`, enough of a reader to check what [`render`] writes.
+#[cfg(test)]
+fn parse(csv: &str) -> Vec<Vec<String>> {
+    let mut records = vec![];
+    let mut record = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = csv.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (true, c) => field.push(c),
+            (false, '"') => quoted = true,
+            (false, ',') => record.push(std::mem::take(&mut field)),
+            (false, '\n') => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            (false, c) => field.push(c),
+        }
+    }
+    records
+}
+
+#[test]
+fn csv_round_trip() {
+    use crate::testkit::BenchDataBuilder;
+
+    let data = BenchDataBuilder::new("2222222222222222222222222222222222222222")
+        .machine("X64", "Linux", "AMD EPYC 7763, 64-Core")
+        .commit_timestamp(1700000000)
+        .group("compress", |g| {
+            g.bench(["./c", "--level=1"], |b| {
+                b.counter("cycles", 1.0e9, 4.0e6, 20, "").counter(
+                    "task-clock",
+                    250.5,
+                    1.0,
+                    20,
+                    "msec",
+                )
+            })
+            .bench(["./c", "--dict=\"a,b\""], |b| {
+                b.counter("cycles", 2.0e9, 0.0, 20, "")
+            })
+        })
+        .group("decompress", |g| {
+            g.bench(["./d"], |b| b.counter("cycles", 5.0e8, 0.0, 10, ""))
+        });
+    let prev_results = data
+        .clone()
+        .with_scaled_counters(0.8)
+        .commit_hash("1111111111111111111111111111111111111111")
+        .build();
+    let data = data.build();
+
+    let records = parse(&render(
+        &data,
+        Some(&prev_results),
+        &CounterOrder::default(),
+        &Sanitizer::default(),
+    ));
+    assert_eq!(records[0], COLUMNS);
+    // A row per counter of every command.
+    assert_eq!(records.len(), 1 + 4);
+    assert!(records.iter().all(|record| record.len() == COLUMNS.len()));
+
+    let column = |name: &str| COLUMNS.iter().position(|column| *column == name).unwrap();
+    let first = &records[1];
+    assert_eq!(first[column("group")], "compress");
+    assert_eq!(first[column("command")], "./c --level=1");
+    assert_eq!(first[column("counter")], "cycles");
+    assert_eq!(first[column("value")], "1000000000");
+    assert_eq!(first[column("std_dev")], "2000");
+    assert_eq!(first[column("commit_timestamp")], "1700000000");
+    assert_eq!(first[column("cpu_model")], "AMD EPYC 7763, 64-Core");
+    assert_eq!(first[column("baseline_value")], "800000000");
+    assert_eq!(first[column("delta_percent")], "25");
+    assert_eq!(records[2][column("value")], "250.5");
+    assert_eq!(records[2][column("unit")], "msec");
+
+    // The quotes and the comma of the command survive.
+    let quoted = &records[3];
+    assert_eq!(quoted[column("command_index")], "1");
+    assert_eq!(quoted[column("command")], "./c --dict=\"a,b\"");
+    assert_eq!(records[4][column("group")], "decompress");
+}
+
+#[test]
+fn csv_without_baseline() {
+    use crate::testkit::BenchDataBuilder;
+
+    let data = BenchDataBuilder::new("2222222222222222222222222222222222222222")
+        .group("compress", |g| {
+            g.bench(["./c", "1"], |b| b.counter("cycles", 1.0e9, 0.0, 20, ""))
+        })
+        .build();
+    let csv = render(&data, None, &CounterOrder::default(), &Sanitizer::default());
+    assert_eq!(
+        csv,
+        "group,command_index,command,counter,value,variance,std_dev,repetitions,unit,commit_hash,commit_timestamp,cpu_model,baseline_value,delta_percent\n\
+         compress,0,./c 1,cycles,1000000000,0,0,20,,2222222222222222222222222222222222222222,0,cpu,,\n"
+    );
+}
@@ -0,0 +1,345 @@
+//! Measuring a command that several groups share only once, with `dedupe-commands`, like a
+//! command that is both in the group of the headline table and in one counting context switches:
+//!
+//! ```json
+//! "dedupe-commands": true
+//! ```
+//!
+//! Commands are the same when their command lines, expected exit codes, `sync-start`,
+//! `measure-child`, `verify-output` and `sleeps` are, and their groups have the same backends,
+//! whatever perf events they count. Such a command is measured once, when the first of its
+//! groups runs, with the perf events of all of them, the instruction mix when any of them asks
+//! for it, and the most repetitions of any of them. Every group then gets the counters of the
+//! events it asked for, with the repetitions they were actually measured with, which the
+//! t-tests use. A required counter missing fails the command in all of its groups, it was a
+//! single measurement.
+//!
+//! Only commands measured on their own are shared: not those of groups with
+//! `interleave-for-group`, the steps of composites, the measurements of the `sentinel-group`,
+//! which are measured twice on purpose, commands with `produces`, whose outputs are collected
+//! after every measurement, or imported ones, which aren't measured at all. Profiles and
+//! intervals are still recorded for every group that asks for them.
+
+use std::path::Path;
+
+use crate::bench::{bench_single_cmd, default_backend, CommandSpec, Perf, SingleBench};
+use crate::perf_events::PerfEvent;
+use crate::required_counters::is_counter_of;
+use crate::verify_output::VerifyOutput;
+use crate::{sentinel, BackendConfig, CommandConfig, Config};
+
+/// What makes two commands the same measurement.
+#[derive(Debug, Clone, PartialEq)]
+struct Key {
+    command: String,
+    expected_exit_codes: Vec<i32>,
+    sync_start: bool,
+    measure_child: Option<String>,
+    verify_output: Option<VerifyOutput>,
+    sleeps: bool,
+    backends: Option<Vec<BackendConfig>>,
+}
+
+impl Key {
+    fn new(config: &Config, group_name: &str, bench: &CommandConfig) -> Self {
+        Key {
+            command: bench.command.clone(),
+            expected_exit_codes: bench.expected_exit_codes.clone(),
+            sync_start: bench.sync_start,
+            measure_child: bench.measure_child.clone(),
+            verify_output: bench.verify_output.clone(),
+            sleeps: bench.sleeps,
+            backends: config.backends_for_group.get(group_name).cloned(),
+        }
+    }
+}
+
+/// A command measured once for several groups.
+pub struct Share {
+    key: Key,
+    /// The commands, by group and index, in the order of the config.
+    pub commands: Vec<(String, usize)>,
+    /// The most repetitions of any of the groups.
+    pub repetitions: u32,
+    /// The perf backend with the events of all groups.
+    perf: Perf,
+    /// The measurement, and the group that made it, once the first group ran.
+    measured: Option<(String, SingleBench)>,
+}
+
+/// The commands measured once for several groups.
+#[derive(Default)]
+pub struct Shared {
+    pub shares: Vec<Share>,
+}
+
+impl Shared {
+    /// Find the commands of `config` that several groups share.
+    pub fn plan(config: &Config, scratch: &Path) -> Self {
+        let sentinel = config.sentinel_group.as_deref().map(|group_name| {
+            [
+                sentinel::start_key(group_name),
+                sentinel::end_key(group_name),
+            ]
+        });
+        let mut shares = Vec::<Share>::new();
+        for (group_name, benches) in &config.commands {
+            if config.interleave(group_name)
+                || sentinel
+                    .as_ref()
+                    .is_some_and(|keys| keys.contains(group_name))
+            {
+                continue;
+            }
+            let steps = benches
+                .iter()
+                .flat_map(|bench| bench.steps.iter().copied())
+                .collect::<Vec<_>>();
+            for (index, bench) in benches.iter().enumerate() {
+                if bench.is_composite()
+                    || steps.contains(&index)
+                    || !bench.produces.is_empty()
+                    || bench.import.is_some()
+                {
+                    continue;
+                }
+                let key = Key::new(config, group_name, bench);
+                let perf = config.perf(group_name, scratch);
+                match shares.iter_mut().find(|share| share.key == key) {
+                    Some(share) => {
+                        let required = perf.required_counters();
+                        share.commands.push((group_name.clone(), index));
+                        share.repetitions = share.repetitions.max(config.repetitions(group_name));
+                        for event in perf.events {
+                            if !share.perf.events.contains(&event) {
+                                share.perf.events.push(event);
+                            }
+                        }
+                        share.perf.instruction_mix |= perf.instruction_mix;
+                        let shared_required = share.perf.required_counters.get_or_insert_default();
+                        for counter in required {
+                            if !shared_required.contains(&counter) {
+                                shared_required.push(counter);
+                            }
+                        }
+                    }
+                    None => shares.push(Share {
+                        key,
+                        commands: vec![(group_name.clone(), index)],
+                        repetitions: config.repetitions(group_name),
+                        perf: Perf {
+                            required_counters: Some(perf.required_counters()),
+                            ..perf
+                        },
+                        measured: None,
+                    }),
+                }
+            }
+        }
+        shares.retain(|share| share.commands.len() > 1);
+        Shared { shares }
+    }
+
+    fn find(&mut self, group_name: &str, index: usize) -> Option<&mut Share> {
+        self.shares.iter_mut().find(|share| {
+            share
+                .commands
+                .iter()
+                .any(|(group, i)| group == group_name && *i == index)
+        })
+    }
+
+    /// Whether the command at `index` of the group is measured once for several groups.
+    pub fn contains(&mut self, group_name: &str, index: usize) -> bool {
+        self.find(group_name, index).is_some()
+    }
+
+    /// The measurement of the shared command at `index` of the group, `cmd`: measured with
+    /// the settings of all of its groups the first time, and the counters of the events the
+    /// group asked for.
+    pub fn bench(
+        &mut self,
+        config: &Config,
+        group_name: &str,
+        index: usize,
+        cmd: CommandSpec,
+        perf_output: Option<&Path>,
+    ) -> Result<SingleBench, String> {
+        let share = self
+            .find(group_name, index)
+            .expect("only shared commands are measured once");
+        let bench = match &share.measured {
+            Some((measured_in, bench)) => {
+                eprintln!(
+                    "Reusing the measurement of {} from the `{measured_in}` group",
+                    cmd.argv.join(" ")
+                );
+                bench.clone()
+            }
+            None => {
+                let backends = match &share.key.backends {
+                    Some(backends) => backends
+                        .iter()
+                        .map(|backend| backend.build(&share.perf))
+                        .collect(),
+                    None => vec![default_backend(share.perf.clone())],
+                };
+                let bench = bench_single_cmd(cmd, share.repetitions, &backends, perf_output)?;
+                share.measured = Some((group_name.to_owned(), bench.clone()));
+                bench
+            }
+        };
+        let group_events = config.perf(group_name, Path::new("")).events();
+        Ok(filter(bench, &share.perf.events(), &group_events))
+    }
+}
+
+/// Drop the counters of the `measured` events that aren't among the `requested` ones.
+fn filter(mut bench: SingleBench, measured: &[PerfEvent], requested: &[PerfEvent]) -> SingleBench {
+    let others = measured
+        .iter()
+        .filter(|event| !requested.contains(event))
+        .map(PerfEvent::name)
+        .collect::<Vec<_>>();
+    bench
+        .counters
+        .retain(|counter, _| !others.iter().any(|event| is_counter_of(counter, event)));
+    bench
+}
+
+#[cfg(test)]
+fn config_for_test() -> Config {
+    serde_json::from_str(
+        r#"{
+            "commands": {
+                "headline": ["./compress 6 silesia.tar", "./compress 9 silesia.tar"],
+                "switches": ["./compress 6 silesia.tar", { "command": "./compress 9 silesia.tar", "sleeps": true }],
+                "cache": ["./compress 6 silesia.tar", "./compress 1 silesia.tar"],
+                "pair": ["./compress 6 silesia.tar", "./compress 1 silesia.tar"],
+                "raw": ["./compress 6 silesia.tar"]
+            },
+            "dedupe-commands": true,
+            "repetitions-for-group": { "headline": 5, "switches": 3, "cache": 8 },
+            "perf-events-for-group": {
+                "switches": ["cycles", "context-switches"],
+                "cache": ["cycles", "cache-misses"]
+            },
+            "instruction-mix-for-group": { "cache": true },
+            "interleave-for-group": { "pair": true },
+            "backends-for-group": { "raw": ["getrusage"] },
+            "render-versus-self": {
+                "pair": {
+                    "1 vs 6": { "measure": "cycles", "before": { "command": "pair", "index": 0 }, "after": { "command": "pair", "index": 1 } }
+                }
+            },
+            "render-versus-other": {}
+        }"#,
+    )
+    .unwrap()
+}
+
+#[test]
+fn shared_commands() {
+    let dir = crate::testkit::test_dir("dedupe-plan");
+    let shared = Shared::plan(&config_for_test(), &dir);
+
+    // The same command in three groups, but not in the interleaved one, nor with other
+    // backends or options.
+    assert_eq!(shared.shares.len(), 1);
+    let share = &shared.shares[0];
+    assert_eq!(share.key.command, "./compress 6 silesia.tar");
+    assert_eq!(
+        share.commands,
+        [
+            ("headline".to_owned(), 0),
+            ("switches".to_owned(), 0),
+            ("cache".to_owned(), 0),
+        ]
+    );
+
+    // The union of the events, the most repetitions, and the mix of `cache`.
+    assert_eq!(share.repetitions, 8);
+    assert_eq!(
+        share.perf.events,
+        PerfEvent::parse_list("task-clock,cycles,instructions,context-switches,cache-misses")
+    );
+    assert!(share.perf.instruction_mix);
+    assert_eq!(
+        share.perf.required_counters.as_deref().unwrap(),
+        [
+            "task-clock",
+            "cycles",
+            "instructions",
+            "context-switches",
+            "cache-misses"
+        ]
+    );
+}
+
+#[test]
+fn filter_shared_counters() {
+    let data = crate::testkit::BenchDataBuilder::new("1111111111111111111111111111111111111111")
+        .group("headline", |g| {
+            g.bench(["./compress", "6", "silesia.tar"], |b| {
+                [
+                    "task-clock",
+                    "cycles",
+                    "cpu_core/cycles/",
+                    "instructions",
+                    "context-switches",
+                    "cache-misses",
+                    "branches",
+                    "wall-time",
+                ]
+                .into_iter()
+                .fold(b, |b, name| b.counter(name, 1.0e9, 1.0e6, 8, ""))
+            })
+        })
+        .build();
+    let bench = data.bench_groups["headline"][0].clone();
+    let config = config_for_test();
+    let shared = Shared::plan(&config, Path::new(""));
+    let measured = shared.shares[0].perf.events();
+    let counters = |group_name| {
+        let requested = config.perf(group_name, Path::new("")).events();
+        filter(bench.clone(), &measured, &requested)
+            .counters
+            .into_keys()
+            .collect::<Vec<_>>()
+    };
+
+    // The counters of other backends stay, with the repetitions they were measured with.
+    assert_eq!(
+        counters("headline"),
+        [
+            "cpu_core/cycles/",
+            "cycles",
+            "instructions",
+            "task-clock",
+            "wall-time"
+        ]
+    );
+    assert_eq!(
+        counters("switches"),
+        [
+            "context-switches",
+            "cpu_core/cycles/",
+            "cycles",
+            "wall-time"
+        ]
+    );
+    assert_eq!(
+        counters("cache"),
+        [
+            "branches",
+            "cache-misses",
+            "cpu_core/cycles/",
+            "cycles",
+            "wall-time"
+        ]
+    );
+    assert!(filter(bench.clone(), &measured, &measured)
+        .counters
+        .values()
+        .all(|counter| counter.repetitions == 8));
+}
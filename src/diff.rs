@@ -0,0 +1,545 @@
+//! `benchmarker diff <before> <after> [--format json|markdown]`: what changed between two
+//! stored results, for tooling that shouldn't reimplement the comparison rules.
+//!
+//! Both arguments are files with serialized results, like the lines of the results file in
+//! the bench repo. A file with several entries needs a `<path>@<commit>` to pick one.
+
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+use crate::bench::{BenchCounter, SingleBench};
+use crate::compare::{find_prev_bench, ComparisonKind, ComparisonRow, ComparisonTable};
+use crate::counter_names::CounterRenames;
+use crate::markers::Markers;
+use crate::measure::MeasureKind;
+use crate::{BenchData, TableDisplay};
+
+/// The differences between two results. The schema is stable: fields may be added, but
+/// existing ones keep their name and meaning.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct DiffReport {
+    pub before: Entry,
+    pub after: Entry,
+    /// The properties of the machines that differ, by name (`arch`, `os`, `runner` or
+    /// `cpu_model`). The deltas then include the difference between the machines.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub machine_mismatch: IndexMap<String, Change>,
+    /// Benchmarks that are only in the before entry.
+    pub removed: Vec<Benchmark>,
+    /// Benchmarks that are only in the after entry.
+    pub added: Vec<Benchmark>,
+    /// Benchmarks in both entries, in the order of the after entry.
+    pub common: Vec<BenchmarkDiff>,
+}
+
+/// Which result an entry is.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub commit_hash: String,
+    pub arch: String,
+    pub os: String,
+    pub runner: String,
+    pub cpu_model: String,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Change {
+    pub before: String,
+    pub after: String,
+}
+
+/// A benchmark is identified like previous results are matched: by id, or otherwise by
+/// command line.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Benchmark {
+    pub group: String,
+    pub cmd: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct BenchmarkDiff {
+    #[serde(flatten)]
+    pub benchmark: Benchmark,
+    /// Counters that are only in the after entry.
+    pub counters_added: Vec<String>,
+    /// Counters that are only in the before entry.
+    pub counters_removed: Vec<String>,
+    /// The counters in both entries, by their name in the after entry.
+    pub counters: Vec<CounterDiff>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct CounterDiff {
+    pub counter: String,
+    pub before: BenchCounter,
+    pub after: BenchCounter,
+    /// `after.value - before.value`.
+    pub delta: f64,
+    /// The change relative to the after value, like in the step summary.
+    pub delta_percent: f64,
+    /// The two-tailed p-value of the pooled t-test.
+    pub p_value: f64,
+    /// Whether the change is significant at a 95% confidence level, without any correction
+    /// for multiple comparisons.
+    pub significant: bool,
+    /// Set when the unit changed, in which case the values aren't comparable.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit_change: Option<Change>,
+}
+
+impl Entry {
+    fn of(data: &BenchData) -> Self {
+        Entry {
+            commit_hash: data.commit_hash.clone(),
+            arch: data.arch.clone(),
+            os: data.os.clone(),
+            runner: data.runner.clone(),
+            cpu_model: data.cpu_model.clone(),
+        }
+    }
+}
+
+impl Benchmark {
+    fn of(group: &str, bench: &SingleBench) -> Self {
+        Benchmark {
+            group: group.to_owned(),
+            cmd: bench.cmd.clone(),
+            id: bench.id.clone(),
+        }
+    }
+
+    fn name(&self) -> String {
+        self.id.clone().unwrap_or_else(|| self.cmd.join(" "))
+    }
+}
+
+impl DiffReport {
+    pub fn new(before: &BenchData, after: &BenchData) -> Self {
+        let (before_entry, after_entry) = (Entry::of(before), Entry::of(after));
+        let mut machine_mismatch = IndexMap::new();
+        for (field, before, after) in [
+            ("arch", &before_entry.arch, &after_entry.arch),
+            ("os", &before_entry.os, &after_entry.os),
+            ("runner", &before_entry.runner, &after_entry.runner),
+            ("cpu_model", &before_entry.cpu_model, &after_entry.cpu_model),
+        ] {
+            if before != after {
+                machine_mismatch.insert(
+                    field.to_owned(),
+                    Change {
+                        before: before.clone(),
+                        after: after.clone(),
+                    },
+                );
+            }
+        }
+
+        let mut matched = vec![];
+        let mut added = vec![];
+        let mut common = vec![];
+        for (group_name, benches) in &after.bench_groups {
+            let prev_group_results = before.bench_groups.get(group_name);
+            for bench in benches {
+                let Some(prev_bench) =
+                    prev_group_results.and_then(|prev| find_prev_bench(prev, bench))
+                else {
+                    added.push(Benchmark::of(group_name, bench));
+                    continue;
+                };
+                matched.push(prev_bench);
+                common.push(diff_benchmark(group_name, prev_bench, bench));
+            }
+        }
+
+        let removed = before
+            .bench_groups
+            .iter()
+            .flat_map(|(group_name, benches)| benches.iter().map(move |b| (group_name, b)))
+            .filter(|(_, bench)| !matched.iter().any(|prev| std::ptr::eq(*prev, *bench)))
+            .map(|(group_name, bench)| Benchmark::of(group_name, bench))
+            .collect();
+
+        DiffReport {
+            before: before_entry,
+            after: after_entry,
+            machine_mismatch,
+            removed,
+            added,
+            common,
+        }
+    }
+
+    /// The same information as tables, with a table per group like the raw tables of the step
+    /// summary.
+    pub fn render_markdown(&self, md: &mut String) {
+        let short = |commit: &str| commit.get(..7).unwrap_or(commit).to_owned();
+        writeln!(
+            md,
+            "## `{}` versus `{}`\n",
+            short(&self.after.commit_hash),
+            short(&self.before.commit_hash)
+        )
+        .unwrap();
+
+        if !self.machine_mismatch.is_empty() {
+            writeln!(
+                md,
+                "> [!WARNING]\n> The results were measured on different machines, the changes include the difference between them.\n>"
+            )
+            .unwrap();
+            for (field, change) in &self.machine_mismatch {
+                writeln!(md, "> - {field}: `{}` → `{}`", change.before, change.after).unwrap();
+            }
+            writeln!(md).unwrap();
+        }
+
+        for (title, benchmarks) in [("Added", &self.added), ("Removed", &self.removed)] {
+            if benchmarks.is_empty() {
+                continue;
+            }
+            writeln!(md, "### {title} benchmarks\n").unwrap();
+            for benchmark in benchmarks {
+                writeln!(md, "- {}: `{}`", benchmark.group, benchmark.name()).unwrap();
+            }
+            writeln!(md).unwrap();
+        }
+
+        let mut changes = vec![];
+        for diff in &self.common {
+            let name = diff.benchmark.name();
+            for counter in &diff.counters_added {
+                changes.push(format!("`{name}`: counter `{counter}` added"));
+            }
+            for counter in &diff.counters_removed {
+                changes.push(format!("`{name}`: counter `{counter}` removed"));
+            }
+            for counter in &diff.counters {
+                if let Some(change) = &counter.unit_change {
+                    changes.push(format!(
+                        "`{name}`: unit of `{}` changed from `{}` to `{}`",
+                        counter.counter, change.before, change.after
+                    ));
+                }
+            }
+        }
+        if !changes.is_empty() {
+            writeln!(md, "### Changed counters\n").unwrap();
+            for change in changes {
+                writeln!(md, "- {change}").unwrap();
+            }
+            writeln!(md).unwrap();
+        }
+
+        let header = format!(
+            "| name | before (`{}`) | after (`{}`) | Δ |\n| --- | --- | --- | --- |\n",
+            short(&self.before.commit_hash),
+            short(&self.after.commit_hash)
+        );
+        for table in self.tables() {
+            writeln!(md, "### {}\n", table.name).unwrap();
+            table.render_markdown(md, &header, &Markers::default());
+            writeln!(md).unwrap();
+        }
+    }
+
+    /// The counters of the common benchmarks as comparison tables, one per group.
+    fn tables(&self) -> Vec<ComparisonTable> {
+        let mut tables = IndexMap::<&str, Vec<ComparisonRow>>::new();
+        for diff in &self.common {
+            let rows = tables.entry(&diff.benchmark.group).or_default();
+            for counter in &diff.counters {
+                rows.push(ComparisonRow::new(
+                    format!("{} ({})", diff.benchmark.name(), counter.counter),
+                    counter.counter.clone(),
+                    MeasureKind::default(),
+                    &counter.before,
+                    &counter.after,
+                ));
+            }
+        }
+
+        tables
+            .into_iter()
+            .map(|(name, rows)| ComparisonTable {
+                name: name.to_owned(),
+                kind: ComparisonKind::VersusParent,
+                rows,
+                display: TableDisplay::default(),
+                rolling_window: None,
+                build_notes: vec![],
+                sampling_note: None,
+            })
+            .collect()
+    }
+}
+
+fn diff_benchmark(group_name: &str, before: &SingleBench, after: &SingleBench) -> BenchmarkDiff {
+    let mut counters = vec![];
+    let mut counters_added = vec![];
+    let mut matched = vec![];
+    for (counter, data) in &after.counters {
+        let Some(prev_data) = before.counters.get(counter) else {
+            counters_added.push(counter.clone());
+            continue;
+        };
+        matched.push(counter);
+
+        let row = ComparisonRow::new(
+            counter.clone(),
+            counter.clone(),
+            MeasureKind::default(),
+            prev_data,
+            data,
+        );
+        counters.push(CounterDiff {
+            counter: counter.clone(),
+            before: prev_data.clone(),
+            after: data.clone(),
+            delta: data.value - prev_data.value,
+            delta_percent: row.delta_percent,
+            p_value: row.p_value,
+            significant: row.significant,
+            unit_change: (prev_data.unit != data.unit).then(|| Change {
+                before: prev_data.unit.clone(),
+                after: data.unit.clone(),
+            }),
+        });
+    }
+
+    BenchmarkDiff {
+        benchmark: Benchmark::of(group_name, after),
+        counters_added,
+        counters_removed: before
+            .counters
+            .keys()
+            .filter(|counter| !matched.contains(counter))
+            .cloned()
+            .collect(),
+        counters,
+    }
+}
+
+/// Read the entry `spec` refers to: a file with a single entry, or `<path>@<commit>` for a
+/// file with several, where the commit may be abbreviated.
+fn read_entry(spec: &str) -> Result<BenchData, String> {
+    let (path, commit) = match spec.rsplit_once('@') {
+        Some((path, commit)) if !Path::new(spec).exists() => (path, Some(commit)),
+        _ => (spec, None),
+    };
+
+    let contents = fs::read(path).map_err(|e| format!("failed to read {path}: {e}"))?;
+    let mut entries = contents
+        .split(|&b| b == b'\n')
+        .filter_map(|line| serde_json::from_slice::<BenchData>(line).ok())
+        .filter(|data| commit.is_none_or(|commit| data.commit_hash.starts_with(commit)))
+        .collect::<Vec<_>>();
+
+    match (entries.len(), commit) {
+        (1, _) => {
+            // Without a config, only the built-in renames apply.
+            let mut entry = entries.remove(0);
+            for warning in CounterRenames::default().canonicalize(&mut entry) {
+                eprintln!("warning: {warning}");
+            }
+            Ok(entry)
+        }
+        (0, None) => Err(format!("{path} contains no results")),
+        (0, Some(commit)) => Err(format!("{path} contains no results for {commit}")),
+        (n, None) => Err(format!(
+            "{path} contains {n} results, pick one with `{path}@<commit>`"
+        )),
+        (n, Some(commit)) => Err(format!("{path} contains {n} results for {commit}")),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Json,
+    Markdown,
+}
+
+/// Run the `diff` subcommand with the arguments after `diff`, returning what to print.
+pub fn run(args: impl IntoIterator<Item = String>) -> Result<String, String> {
+    let mut positional = vec![];
+    let mut format = Format::Json;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let value = match arg.strip_prefix("--format") {
+            Some("") => args.next(),
+            Some(value) => value.strip_prefix('=').map(str::to_owned),
+            None => {
+                positional.push(arg);
+                continue;
+            }
+        };
+        format = match value.as_deref() {
+            Some("json") => Format::Json,
+            Some("markdown") => Format::Markdown,
+            _ => return Err("`--format` is either `json` or `markdown`".to_owned()),
+        };
+    }
+
+    let [before, after] = <[String; 2]>::try_from(positional)
+        .map_err(|_| "expected the arguments diff <before> <after>".to_owned())?;
+    let report = DiffReport::new(&read_entry(&before)?, &read_entry(&after)?);
+
+    Ok(match format {
+        Format::Json => serde_json::to_string_pretty(&report).unwrap() + "\n",
+        Format::Markdown => {
+            let mut md = String::new();
+            report.render_markdown(&mut md);
+            md
+        }
+    })
+}
+
+#[cfg(test)]
+fn testdata(name: &str) -> String {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("testdata/diff")
+        .join(name)
+        .display()
+        .to_string()
+}
+
+#[test]
+fn diff_added_and_removed_benchmarks() {
+    let before = read_entry(&testdata("before.json")).unwrap();
+    let after = read_entry(&testdata("after.json")).unwrap();
+    let report = DiffReport::new(&before, &after);
+
+    assert!(report.machine_mismatch.is_empty());
+    assert_eq!(
+        report.removed,
+        [Benchmark {
+            group: "compress".to_owned(),
+            cmd: vec!["./compress".to_owned(), "9".to_owned()],
+            id: None,
+        }]
+    );
+    assert_eq!(
+        report.added,
+        [
+            Benchmark {
+                group: "compress".to_owned(),
+                cmd: vec!["./compress".to_owned(), "6".to_owned()],
+                id: None,
+            },
+            Benchmark {
+                group: "decompress".to_owned(),
+                cmd: vec!["./decompress".to_owned()],
+                id: None,
+            }
+        ]
+    );
+
+    // Matched by id, even though the command line changed.
+    let level_1 = &report.common[0];
+    assert_eq!(level_1.benchmark.id.as_deref(), Some("level-1"));
+    assert_eq!(level_1.benchmark.cmd, ["./compress", "--level", "1"]);
+    assert_eq!(level_1.counters_added, ["instructions"]);
+    assert_eq!(level_1.counters_removed, ["task-clock"]);
+
+    let cycles = &level_1.counters[0];
+    assert_eq!(cycles.counter, "cycles");
+    assert_eq!((cycles.before.value, cycles.after.value), (1000.0, 1250.0));
+    assert_eq!(cycles.delta, 250.0);
+    assert_eq!(cycles.delta_percent, 20.0);
+    assert!(cycles.significant);
+    assert!(cycles.p_value < 0.001);
+    assert_eq!(cycles.unit_change, None);
+
+    // Counters of hybrid CPUs match their old names.
+    let level_3 = &report.common[1];
+    assert_eq!(level_3.counters[0].counter, "cycles");
+    assert!(!level_3.counters[0].significant);
+    assert!(level_3.counters_removed.is_empty());
+
+    // The schema survives a round trip.
+    let json = serde_json::to_string(&report).unwrap();
+    assert_eq!(serde_json::from_str::<DiffReport>(&json).unwrap(), report);
+}
+
+#[test]
+fn diff_unit_changes_and_machine_mismatch() {
+    let before = read_entry(&testdata("before.json")).unwrap();
+    let other_machine = read_entry(&format!("{}@4444444", testdata("history.json"))).unwrap();
+    let report = DiffReport::new(&before, &other_machine);
+
+    assert_eq!(
+        report.machine_mismatch,
+        IndexMap::from([(
+            "cpu_model".to_owned(),
+            Change {
+                before: "AMD Ryzen 9 5950X".to_owned(),
+                after: "Intel Xeon Platinum 8375C".to_owned(),
+            }
+        )])
+    );
+    let task_clock = report.common[0]
+        .counters
+        .iter()
+        .find(|counter| counter.counter == "task-clock")
+        .unwrap();
+    assert_eq!(
+        task_clock.unit_change,
+        Some(Change {
+            before: "msec".to_owned(),
+            after: "usec".to_owned(),
+        })
+    );
+
+    assert!(read_entry(&testdata("history.json"))
+        .unwrap_err()
+        .contains("contains 2 results"));
+    assert!(read_entry(&format!("{}@9999", testdata("history.json"))).is_err());
+}
+
+#[test]
+fn diff_markdown() {
+    let args = |args: &[&str]| run(args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>());
+
+    let md = args(&[
+        &testdata("before.json"),
+        &format!("{}@4444444", testdata("history.json")),
+        "--format",
+        "markdown",
+    ])
+    .unwrap();
+    assert_eq!(
+        md,
+        "## `4444444` versus `1111111`\n\n\
+         > [!WARNING]\n\
+         > The results were measured on different machines, the changes include the difference between them.\n\
+         >\n\
+         > - cpu_model: `AMD Ryzen 9 5950X` → `Intel Xeon Platinum 8375C`\n\n\
+         ### Removed benchmarks\n\n\
+         - compress: `./compress 3`\n\
+         - compress: `./compress 9`\n\n\
+         ### Changed counters\n\n\
+         - `level-1`: unit of `task-clock` changed from `msec` to `usec`\n\n\
+         ### compress\n\n\
+         | name | before (`1111111`) | after (`4444444`) | Δ |\n\
+         | --- | --- | --- | --- |\n\
+         | level-1 (cycles) | `  1.00K ±      10` | `  1.00K ±      10` | `    +0.00%` |\n\
+         | level-1 (task-clock) | `      2 ±       0` | `  2.00K ±     100` | `💩 +99.90%` |\n\n\
+         💩 significant regression\n\n"
+    );
+
+    let json = args(&[&testdata("before.json"), &testdata("after.json")]).unwrap();
+    let report: DiffReport = serde_json::from_str(&json).unwrap();
+    assert_eq!(
+        report.after.commit_hash,
+        "2222222222222222222222222222222222222222"
+    );
+
+    assert!(args(&[&testdata("before.json")]).is_err());
+    assert!(args(&["a", "b", "--format=yaml"]).is_err());
+}
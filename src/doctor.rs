@@ -0,0 +1,927 @@
+//! `benchmarker doctor [<config>...] [--results-file <path>] [--json]`: check that a runner
+//! can take measurements worth comparing, before its first run rather than after a week of
+//! noise. Every check passes, warns or fails:
+//!
+//! - perf is installed and allowed to count hardware events, and `perf_event_paranoid`
+//! - the frequency governor, turbo and ASLR
+//! - the programs the commands, the backends and the isolation of the config run
+//! - the fixtures of the config, the free disk space, and whether the results file and the
+//!   step summary can be written
+//! - the resolution of the clock, and the noise of 50 runs of `true`
+//!
+//! The report is printed as text, or as JSON with `--json`. The exit code is that of the
+//! worst check: 0 when all passed, 1 with warnings and 2 with failures.
+
+use std::env;
+use std::ffi::OsStr;
+use std::fmt::Write;
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::bench::parse_perf_stat_output;
+use crate::outputs::{self, OutputsConfig};
+use crate::perf_events::PerfEvent;
+use crate::quality;
+use crate::{BackendConfig, Config};
+
+/// How many times `true` runs to measure the noise.
+const NOISE_REPETITIONS: u32 = 50;
+
+/// The coarsest clock that still times short commands well.
+const MAX_CLOCK_RESOLUTION: Duration = Duration::from_micros(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Severity {
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// The outcome of a check.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct CheckResult {
+    pub check: String,
+    pub severity: Severity,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    /// The worst severity of the checks.
+    pub severity: Severity,
+    pub checks: Vec<CheckResult>,
+}
+
+impl Severity {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Severity::Pass => 0,
+            Severity::Warn => 1,
+            Severity::Fail => 2,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Severity::Pass => "pass",
+            Severity::Warn => "warn",
+            Severity::Fail => "FAIL",
+        }
+    }
+}
+
+impl CheckResult {
+    fn new(check: impl Into<String>, severity: Severity, message: impl Into<String>) -> Self {
+        CheckResult {
+            check: check.into(),
+            severity,
+            message: message.into(),
+        }
+    }
+}
+
+impl Report {
+    fn new(checks: Vec<CheckResult>) -> Self {
+        let severity = checks
+            .iter()
+            .map(|check| check.severity)
+            .max()
+            .unwrap_or(Severity::Pass);
+        Report { severity, checks }
+    }
+
+    fn render_text(&self) -> String {
+        let mut text = String::new();
+        for check in &self.checks {
+            writeln!(
+                text,
+                "[{}] {}: {}",
+                check.severity.label(),
+                check.check,
+                check.message
+            )
+            .unwrap();
+        }
+        let count = |severity| {
+            self.checks
+                .iter()
+                .filter(|check| check.severity == severity)
+                .count()
+        };
+        writeln!(
+            text,
+            "\n{} passed, {} warnings, {} failures",
+            count(Severity::Pass),
+            count(Severity::Warn),
+            count(Severity::Fail)
+        )
+        .unwrap();
+        text
+    }
+}
+
+/// Run the `doctor` subcommand with the arguments after `doctor`, returning what to print and
+/// the worst severity of the checks.
+pub fn run(args: impl IntoIterator<Item = String>) -> Result<(String, Severity), String> {
+    let mut config_paths = vec![];
+    let mut results_file = None;
+    let mut json = false;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--results-file" => {
+                let path = args.next().ok_or("expected a path after --results-file")?;
+                results_file = Some(PathBuf::from(path));
+            }
+            _ if arg.starts_with("--") => return Err(format!("unknown argument {arg}")),
+            _ => config_paths.push(PathBuf::from(arg)),
+        }
+    }
+
+    let config = if config_paths.is_empty() {
+        None
+    } else {
+        let config = Config::load(&config_paths)?;
+        config
+            .validate()
+            .map_err(|err| format!("invalid config: {err}"))?;
+        Some(config)
+    };
+
+    let report = Report::new(run_checks(config.as_ref(), results_file.as_deref()));
+    let output = if json {
+        format!("{}\n", serde_json::to_string_pretty(&report).unwrap())
+    } else {
+        report.render_text()
+    };
+    Ok((output, report.severity))
+}
+
+/// Run every check on this machine.
+fn run_checks(config: Option<&Config>, results_file: Option<&Path>) -> Vec<CheckResult> {
+    let read = |path: &str| fs::read_to_string(path).map(|value| value.trim().to_owned());
+    let perf = |args: &[&str]| {
+        Command::new("perf")
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .output()
+    };
+
+    let mut checks = vec![
+        perf_access(perf(&[
+            "stat",
+            "-x",
+            ",",
+            "-e",
+            "cycles,instructions",
+            "--",
+            "true",
+        ])),
+        paranoid_level(read("/proc/sys/kernel/perf_event_paranoid")),
+        governor(&read_governors(Path::new("/sys/devices/system/cpu"))),
+        turbo(
+            read("/sys/devices/system/cpu/intel_pstate/no_turbo").ok(),
+            read("/sys/devices/system/cpu/cpufreq/boost").ok(),
+        ),
+        aslr(read("/proc/sys/kernel/randomize_va_space")),
+    ];
+
+    if let Some(config) = config {
+        let path = env::var_os("PATH");
+        for program in needed_programs(config) {
+            let found = find_program(&program, path.as_deref());
+            checks.push(program_check(&program, found.as_deref()));
+        }
+        for fixture in &config.fixtures {
+            checks.push(fixture_check(&fixture.path, &fixture.url));
+        }
+    }
+    checks.push(disk_space(
+        outputs::free_space(Path::new(".")),
+        config.and_then(|config| config.outputs.as_ref()),
+    ));
+    checks.push(writable("results file", results_file));
+    checks.push(writable(
+        "step summary",
+        env::var_os("GITHUB_STEP_SUMMARY").as_deref().map(Path::new),
+    ));
+    checks.push(clock_resolution(monotonic_resolution()));
+
+    let max_cov_percent = config
+        .and_then(|config| config.measurement_quality.as_ref())
+        .map_or_else(quality::default_max_median_cov_percent, |quality| {
+            quality.max_median_cov_percent
+        });
+    let repetitions = NOISE_REPETITIONS.to_string();
+    checks.push(noise(
+        perf(&[
+            "stat",
+            "-j",
+            "-r",
+            &repetitions,
+            "-e",
+            "task-clock",
+            "--",
+            "true",
+        ]),
+        max_cov_percent,
+    ));
+    checks
+}
+
+/// Whether perf can count hardware events, from the output of `perf stat -x , -e
+/// cycles,instructions -- true`.
+fn perf_access(output: io::Result<Output>) -> CheckResult {
+    const CHECK: &str = "perf";
+
+    let output = match output {
+        Ok(output) => output,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            return CheckResult::new(CHECK, Severity::Fail, "perf is not installed");
+        }
+        Err(err) => {
+            return CheckResult::new(CHECK, Severity::Fail, format!("failed to run perf: {err}"));
+        }
+    };
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        // Like `Error:` followed by `Access to performance monitoring and observability
+        // operations is limited.`
+        let reason = stderr
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty() && *line != "Error:")
+            .unwrap_or("no error message");
+        return CheckResult::new(CHECK, Severity::Fail, format!("perf failed: {reason}"));
+    }
+
+    // `<value>,<unit>,<event>,...`, with a value like `<not supported>` for the events it
+    // couldn't count.
+    let mut counted = vec![];
+    let mut not_counted = vec![];
+    for line in stderr.lines() {
+        let fields = line.split(',').collect::<Vec<_>>();
+        let [value, _, event, ..] = fields[..] else {
+            continue;
+        };
+        if value.starts_with('<') {
+            not_counted.push(format!("{event} {value}"));
+        } else if value.parse::<f64>().is_ok() {
+            counted.push(event);
+        }
+    }
+    if !not_counted.is_empty() || counted.is_empty() {
+        let events = if not_counted.is_empty() {
+            "no events reported".to_owned()
+        } else {
+            not_counted.join(", ")
+        };
+        return CheckResult::new(
+            CHECK,
+            Severity::Fail,
+            format!(
+                "perf can't count hardware events ({events}), the CPU may not expose them to a virtual machine"
+            ),
+        );
+    }
+    CheckResult::new(
+        CHECK,
+        Severity::Pass,
+        format!("perf counts {}", counted.join(", ")),
+    )
+}
+
+/// The `perf_event_paranoid` setting: up to 1, perf counts the kernel too, at 2 only user
+/// space, and above, nothing without privileges.
+fn paranoid_level(value: io::Result<String>) -> CheckResult {
+    const CHECK: &str = "perf_event_paranoid";
+
+    let value = match value {
+        Ok(value) => value,
+        Err(err) => {
+            return CheckResult::new(CHECK, Severity::Warn, format!("failed to read it: {err}"))
+        }
+    };
+    match value.parse::<i32>() {
+        Ok(..=1) => CheckResult::new(
+            CHECK,
+            Severity::Pass,
+            format!("{value}, perf counts user space and the kernel"),
+        ),
+        Ok(2) => CheckResult::new(
+            CHECK,
+            Severity::Warn,
+            "2, perf only counts user space; set it to 1 to count the kernel too",
+        ),
+        Ok(level) => CheckResult::new(
+            CHECK,
+            Severity::Fail,
+            format!("{level}, perf can't count without privileges; set it to 1"),
+        ),
+        Err(_) => CheckResult::new(CHECK, Severity::Warn, format!("unknown value `{value}`")),
+    }
+}
+
+/// The scaling governors of the CPUs in `cpus`, like `/sys/devices/system/cpu`, in the order
+/// of the CPUs. Empty without cpufreq.
+fn read_governors(cpus: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(cpus) else {
+        return vec![];
+    };
+    let mut governors = entries
+        .filter_map(|entry| {
+            let name = entry.ok()?.file_name().into_string().ok()?;
+            let index = name.strip_prefix("cpu")?.parse::<usize>().ok()?;
+            let governor =
+                fs::read_to_string(cpus.join(&name).join("cpufreq/scaling_governor")).ok()?;
+            Some((index, governor.trim().to_owned()))
+        })
+        .collect::<Vec<_>>();
+    governors.sort();
+    governors
+        .into_iter()
+        .map(|(_, governor)| governor)
+        .collect()
+}
+
+/// The frequency of the CPUs is only steady with the `performance` governor.
+fn governor(governors: &[String]) -> CheckResult {
+    const CHECK: &str = "governor";
+
+    if governors.is_empty() {
+        return CheckResult::new(
+            CHECK,
+            Severity::Warn,
+            "no cpufreq governor, the frequency of the CPUs can't be checked",
+        );
+    }
+    let mut others = governors
+        .iter()
+        .filter(|governor| *governor != "performance")
+        .collect::<Vec<_>>();
+    if others.is_empty() {
+        return CheckResult::new(CHECK, Severity::Pass, "performance on all CPUs");
+    }
+    let count = others.len();
+    others.sort();
+    others.dedup();
+    CheckResult::new(
+        CHECK,
+        Severity::Warn,
+        format!(
+            "{} on {count} of {} CPUs; set it to performance for a steady frequency",
+            others
+                .iter()
+                .map(|governor| governor.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+            governors.len()
+        ),
+    )
+}
+
+/// Turbo makes the frequency depend on the temperature and the load of the other cores. It is
+/// controlled by `intel_pstate/no_turbo`, or otherwise by `cpufreq/boost`.
+fn turbo(no_turbo: Option<String>, boost: Option<String>) -> CheckResult {
+    const CHECK: &str = "turbo";
+
+    let enabled = match (no_turbo.as_deref(), boost.as_deref()) {
+        (Some("1"), _) | (None, Some("0")) => false,
+        (Some("0"), _) | (None, Some("1")) => true,
+        _ => {
+            return CheckResult::new(
+                CHECK,
+                Severity::Warn,
+                "no turbo control, whether it is enabled can't be checked",
+            )
+        }
+    };
+    if enabled {
+        CheckResult::new(
+            CHECK,
+            Severity::Warn,
+            "enabled; disable it for a frequency that doesn't depend on the temperature",
+        )
+    } else {
+        CheckResult::new(CHECK, Severity::Pass, "disabled")
+    }
+}
+
+/// With address space layout randomization, every run has another layout, and other cache
+/// conflicts.
+fn aslr(value: io::Result<String>) -> CheckResult {
+    const CHECK: &str = "aslr";
+
+    match value.as_deref() {
+        Ok("0") => CheckResult::new(CHECK, Severity::Pass, "disabled"),
+        Ok(value) => CheckResult::new(
+            CHECK,
+            Severity::Warn,
+            format!(
+                "enabled (randomize_va_space is {value}); disable it, or run the commands with `setarch -R`"
+            ),
+        ),
+        Err(err) => CheckResult::new(CHECK, Severity::Warn, format!("failed to read it: {err}")),
+    }
+}
+
+/// The programs that running the config takes from the `PATH`: those of the commands, of the
+/// external backends and of the isolation. Programs with a path are built by the workflow.
+fn needed_programs(config: &Config) -> Vec<String> {
+    let commands = config
+        .commands
+        .values()
+        .flatten()
+        .filter(|command| !command.is_composite())
+        .map(|command| command.command.as_str());
+    let backends =
+        config
+            .backends_for_group
+            .values()
+            .flatten()
+            .filter_map(|backend| match backend {
+                BackendConfig::External(template) => Some(template.as_str()),
+                _ => None,
+            });
+    let isolation = config
+        .isolation
+        .as_ref()
+        .filter(|isolation| isolation.cgroup.is_none())
+        .map(|_| "systemd-run");
+
+    let mut programs = Vec::<String>::new();
+    for command in commands.chain(backends).chain(isolation) {
+        let Some(program) = command.split(' ').find(|arg| !arg.is_empty()) else {
+            continue;
+        };
+        if !program.contains('/') && !programs.iter().any(|other| other == program) {
+            programs.push(program.to_owned());
+        }
+    }
+    programs
+}
+
+/// The executable `program` in the directories of `path`.
+fn find_program(program: &str, path: Option<&OsStr>) -> Option<PathBuf> {
+    env::split_paths(path?)
+        .map(|dir| dir.join(program))
+        .find(|candidate| {
+            fs::metadata(candidate).is_ok_and(|metadata| {
+                metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+            })
+        })
+}
+
+fn program_check(program: &str, found: Option<&Path>) -> CheckResult {
+    let check = format!("program `{program}`");
+    match found {
+        Some(path) => CheckResult::new(check, Severity::Pass, path.display().to_string()),
+        None => CheckResult::new(
+            check,
+            Severity::Fail,
+            "the config runs it, but it is not on the PATH",
+        ),
+    }
+}
+
+/// A missing fixture is downloaded before the first benchmark, but the runner needs the
+/// network for that.
+fn fixture_check(path: &Path, url: &str) -> CheckResult {
+    let check = format!("fixture `{}`", path.display());
+    if path.exists() {
+        CheckResult::new(check, Severity::Pass, "present")
+    } else {
+        CheckResult::new(
+            check,
+            Severity::Warn,
+            format!("missing, every run downloads it from {url}"),
+        )
+    }
+}
+
+/// The free space of the working directory, against the `min-free-space-mb` of `outputs`.
+fn disk_space(free: Result<u64, String>, outputs: Option<&OutputsConfig>) -> CheckResult {
+    const CHECK: &str = "disk space";
+
+    let free = match free {
+        Ok(free) => free,
+        Err(err) => return CheckResult::new(CHECK, Severity::Warn, err),
+    };
+    let min = outputs.and_then(|outputs| outputs.min_free_space);
+    let message = format!(
+        "{} free in the working directory",
+        outputs::format_size(free)
+    );
+    match min {
+        Some(min) if free < min => CheckResult::new(
+            CHECK,
+            Severity::Fail,
+            format!(
+                "{message}, but `min-free-space-mb` requires {}",
+                outputs::format_size(min)
+            ),
+        ),
+        _ => CheckResult::new(CHECK, Severity::Pass, message),
+    }
+}
+
+/// Whether the file at `path` can be appended to, or created. A file created to check is
+/// removed again.
+fn writable(what: &str, path: Option<&Path>) -> CheckResult {
+    let Some(path) = path else {
+        let message = match what {
+            "step summary" => "GITHUB_STEP_SUMMARY is not set, the report is only printed",
+            _ => "not given, the results are only printed",
+        };
+        return CheckResult::new(what, Severity::Warn, message);
+    };
+
+    let existed = path.exists();
+    let result = OpenOptions::new().append(true).create(true).open(path);
+    match result {
+        Ok(_) => {
+            if !existed {
+                let _ = fs::remove_file(path);
+            }
+            CheckResult::new(
+                what,
+                Severity::Pass,
+                format!("{} is writable", path.display()),
+            )
+        }
+        Err(err) => CheckResult::new(
+            what,
+            Severity::Fail,
+            format!("can't write {}: {err}", path.display()),
+        ),
+    }
+}
+
+fn monotonic_resolution() -> io::Result<Duration> {
+    let mut resolution: libc::timespec = unsafe { std::mem::zeroed() };
+    if unsafe { libc::clock_getres(libc::CLOCK_MONOTONIC, &mut resolution) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(Duration::new(
+        resolution.tv_sec as u64,
+        resolution.tv_nsec as u32,
+    ))
+}
+
+fn clock_resolution(resolution: io::Result<Duration>) -> CheckResult {
+    const CHECK: &str = "clock resolution";
+
+    match resolution {
+        Ok(resolution) if resolution <= MAX_CLOCK_RESOLUTION => {
+            CheckResult::new(CHECK, Severity::Pass, format!("{resolution:?}"))
+        }
+        Ok(resolution) => CheckResult::new(
+            CHECK,
+            Severity::Warn,
+            format!("{resolution:?}, too coarse to time short commands"),
+        ),
+        Err(err) => CheckResult::new(CHECK, Severity::Warn, format!("failed to get it: {err}")),
+    }
+}
+
+/// How much the task-clock of `true` varies, from the output of `perf stat -j -r 50 -e
+/// task-clock -- true`, against the `max-median-cov-percent` of the measurement quality:
+/// twice as much fails.
+fn noise(output: io::Result<Output>, max_cov_percent: f64) -> CheckResult {
+    const CHECK: &str = "noise";
+
+    let not_measured =
+        |reason: String| CheckResult::new(CHECK, Severity::Warn, format!("not measured: {reason}"));
+    let output = match output {
+        Ok(output) if output.status.success() => output,
+        Ok(output) => return not_measured(format!("perf failed with {}", output.status)),
+        Err(err) => return not_measured(format!("failed to run perf: {err}")),
+    };
+    let events = [PerfEvent::parse("task-clock").expect("a valid event")];
+    let counters = match parse_perf_stat_output(&output.stderr, NOISE_REPETITIONS, &events) {
+        Ok(counters) => counters,
+        Err(err) => return not_measured(err),
+    };
+    let Some(cov) = counters
+        .get("task-clock")
+        .and_then(|counter| counter.coefficient_of_variation())
+    else {
+        return not_measured("perf reported no task-clock".to_owned());
+    };
+
+    let cov_percent = cov * 100.0;
+    let message = format!(
+        "the task-clock of `true` varies by {cov_percent:.1}% over {NOISE_REPETITIONS} runs"
+    );
+    if cov_percent <= max_cov_percent {
+        CheckResult::new(CHECK, Severity::Pass, message)
+    } else {
+        let severity = if cov_percent <= 2.0 * max_cov_percent {
+            Severity::Warn
+        } else {
+            Severity::Fail
+        };
+        CheckResult::new(
+            CHECK,
+            severity,
+            format!("{message}, more than the {max_cov_percent}% of `max-median-cov-percent`"),
+        )
+    }
+}
+
+#[cfg(test)]
+fn output_for_test(code: i32, stderr: &str) -> io::Result<Output> {
+    use std::os::unix::process::ExitStatusExt;
+
+    Ok(Output {
+        status: std::process::ExitStatus::from_raw(code << 8),
+        stdout: vec![],
+        stderr: stderr.as_bytes().to_vec(),
+    })
+}
+
+#[test]
+fn check_perf_access() {
+    let check = perf_access(output_for_test(
+        0,
+        "1234567,,cycles,500000,100.00,,\n987654,,instructions,500000,100.00,0.80,insn per cycle\n",
+    ));
+    assert_eq!(
+        check,
+        CheckResult::new("perf", Severity::Pass, "perf counts cycles, instructions")
+    );
+
+    // A virtual machine without a PMU.
+    let check = perf_access(output_for_test(
+        0,
+        "<not supported>,,cycles,0,100.00,,\n<not supported>,,instructions,0,100.00,,\n",
+    ));
+    assert_eq!(check.severity, Severity::Fail);
+    assert_eq!(
+        check.message,
+        "perf can't count hardware events (cycles <not supported>, instructions <not supported>), the CPU may not expose them to a virtual machine"
+    );
+
+    let check = perf_access(output_for_test(
+        255,
+        "Error:\nAccess to performance monitoring and observability operations is limited.\n",
+    ));
+    assert_eq!(
+        check.message,
+        "perf failed: Access to performance monitoring and observability operations is limited."
+    );
+    assert_eq!(check.severity, Severity::Fail);
+
+    let check = perf_access(Err(io::ErrorKind::NotFound.into()));
+    assert_eq!(check.message, "perf is not installed");
+}
+
+#[test]
+fn check_kernel_settings() {
+    let ok = |value: &str| Ok(value.to_owned());
+    let severity = |check: CheckResult| check.severity;
+    assert_eq!(severity(paranoid_level(ok("-1"))), Severity::Pass);
+    assert_eq!(severity(paranoid_level(ok("1"))), Severity::Pass);
+    assert_eq!(severity(paranoid_level(ok("2"))), Severity::Warn);
+    assert_eq!(
+        paranoid_level(ok("4")),
+        CheckResult::new(
+            "perf_event_paranoid",
+            Severity::Fail,
+            "4, perf can't count without privileges; set it to 1"
+        )
+    );
+    assert_eq!(
+        severity(paranoid_level(Err(io::ErrorKind::NotFound.into()))),
+        Severity::Warn
+    );
+
+    assert_eq!(severity(aslr(ok("0"))), Severity::Pass);
+    assert_eq!(
+        aslr(ok("2")).message,
+        "enabled (randomize_va_space is 2); disable it, or run the commands with `setarch -R`"
+    );
+
+    let some = |value: &str| Some(value.to_owned());
+    assert_eq!(severity(turbo(some("1"), None)), Severity::Pass);
+    assert_eq!(severity(turbo(some("0"), some("0"))), Severity::Warn);
+    assert_eq!(severity(turbo(None, some("0"))), Severity::Pass);
+    assert_eq!(severity(turbo(None, some("1"))), Severity::Warn);
+    assert_eq!(severity(turbo(None, None)), Severity::Warn);
+
+    assert_eq!(
+        clock_resolution(Ok(Duration::from_nanos(1))).severity,
+        Severity::Pass
+    );
+    assert_eq!(
+        clock_resolution(Ok(Duration::from_millis(4))).message,
+        "4ms, too coarse to time short commands"
+    );
+}
+
+#[test]
+fn check_governors() {
+    let dir = crate::testkit::test_dir("doctor-governors");
+    for (cpu, governor) in [
+        ("cpu0", "performance"),
+        ("cpu1", "powersave"),
+        ("cpu10", "schedutil"),
+    ] {
+        fs::create_dir_all(dir.join(cpu).join("cpufreq")).unwrap();
+        fs::write(
+            dir.join(cpu).join("cpufreq/scaling_governor"),
+            format!("{governor}\n"),
+        )
+        .unwrap();
+    }
+    // Not CPUs, or without cpufreq.
+    fs::create_dir_all(dir.join("cpufreq")).unwrap();
+    fs::create_dir_all(dir.join("cpu2")).unwrap();
+
+    let governors = read_governors(&dir);
+    assert_eq!(governors, ["performance", "powersave", "schedutil"]);
+    assert_eq!(
+        governor(&governors),
+        CheckResult::new(
+            "governor",
+            Severity::Warn,
+            "powersave, schedutil on 2 of 3 CPUs; set it to performance for a steady frequency"
+        )
+    );
+    assert_eq!(
+        governor(&["performance".to_owned()]).severity,
+        Severity::Pass
+    );
+    assert!(read_governors(&dir.join("does-not-exist")).is_empty());
+    assert_eq!(governor(&[]).severity, Severity::Warn);
+}
+
+#[test]
+fn check_programs() {
+    let config: Config = serde_json::from_str(
+        r#"{
+            "commands": {
+                "compress": ["taskset -c 2 ./compress 1", "./compress 6"],
+                "memory": ["valgrind --tool=massif ./compress 1", "taskset -c 3 ./compress 9"]
+            },
+            "backends-for-group": { "memory": ["perf", { "external": "gpu-stats {cmd}" }] },
+            "isolation": { "cpus": "2-3" },
+            "render-versus-self": {},
+            "render-versus-other": {}
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(
+        needed_programs(&config),
+        ["taskset", "valgrind", "gpu-stats", "systemd-run"]
+    );
+
+    let dir = crate::testkit::test_dir("doctor-programs");
+    let program = dir.join("taskset");
+    fs::write(&program, "#!/bin/sh\n").unwrap();
+    fs::set_permissions(&program, fs::Permissions::from_mode(0o755)).unwrap();
+    fs::write(dir.join("valgrind"), "not executable").unwrap();
+    let path = env::join_paths([dir.join("does-not-exist"), dir.clone()]).unwrap();
+    assert_eq!(find_program("taskset", Some(&path)), Some(program.clone()));
+    assert_eq!(find_program("valgrind", Some(&path)), None);
+    assert_eq!(find_program("taskset", None), None);
+
+    assert_eq!(
+        program_check("taskset", Some(&program)).severity,
+        Severity::Pass
+    );
+    assert_eq!(
+        program_check("valgrind", None),
+        CheckResult::new(
+            "program `valgrind`",
+            Severity::Fail,
+            "the config runs it, but it is not on the PATH"
+        )
+    );
+}
+
+#[test]
+fn check_files() {
+    let dir = crate::testkit::test_dir("doctor-files");
+    let existing = dir.join("results.json");
+    fs::write(&existing, "{}\n").unwrap();
+    assert_eq!(
+        writable("results file", Some(&existing)).severity,
+        Severity::Pass
+    );
+    assert_eq!(fs::read_to_string(&existing).unwrap(), "{}\n");
+
+    // A file that doesn't exist yet is created to check, and removed again.
+    let new = dir.join("summary.md");
+    assert_eq!(
+        writable("step summary", Some(&new)).severity,
+        Severity::Pass
+    );
+    assert!(!new.exists());
+
+    let check = writable("results file", Some(&dir.join("missing/results.json")));
+    assert_eq!(check.severity, Severity::Fail);
+    assert!(
+        check.message.starts_with("can't write "),
+        "{}",
+        check.message
+    );
+    assert_eq!(writable("step summary", None).severity, Severity::Warn);
+
+    assert_eq!(
+        fixture_check(&existing, "https://example.com/corpus").severity,
+        Severity::Pass
+    );
+    assert_eq!(
+        fixture_check(&new, "https://example.com/corpus").message,
+        "missing, every run downloads it from https://example.com/corpus"
+    );
+
+    let outputs: OutputsConfig = serde_json::from_str(r#"{ "min-free-space-mb": 1024 }"#).unwrap();
+    assert_eq!(
+        disk_space(Ok(2048 << 20), Some(&outputs)),
+        CheckResult::new(
+            "disk space",
+            Severity::Pass,
+            "2048.0 MiB free in the working directory"
+        )
+    );
+    assert_eq!(
+        disk_space(Ok(512 << 20), Some(&outputs)).message,
+        "512.0 MiB free in the working directory, but `min-free-space-mb` requires 1024.0 MiB"
+    );
+    assert_eq!(disk_space(Ok(512 << 20), None).severity, Severity::Pass);
+}
+
+#[test]
+fn check_noise() {
+    let output = |variance: f64| {
+        output_for_test(
+            0,
+            &format!(
+                "{{\"counter-value\" : \"0.350000\", \"unit\" : \"msec\", \"event\" : \"task-clock\", \"variance\" : {variance:.2}, \"event-runtime\" : 350000, \"pcnt-running\" : 100.00}}\n"
+            ),
+        )
+    };
+    assert_eq!(
+        noise(output(3.0), 5.0),
+        CheckResult::new(
+            "noise",
+            Severity::Pass,
+            "the task-clock of `true` varies by 3.0% over 50 runs"
+        )
+    );
+    assert_eq!(
+        noise(output(8.0), 5.0),
+        CheckResult::new(
+            "noise",
+            Severity::Warn,
+            "the task-clock of `true` varies by 8.0% over 50 runs, more than the 5% of `max-median-cov-percent`"
+        )
+    );
+    assert_eq!(noise(output(12.0), 5.0).severity, Severity::Fail);
+    assert_eq!(
+        noise(output_for_test(1, ""), 5.0).message,
+        "not measured: perf failed with exit status: 1"
+    );
+}
+
+#[test]
+fn doctor_report() {
+    let report = Report::new(vec![
+        CheckResult::new("aslr", Severity::Pass, "disabled"),
+        CheckResult::new("turbo", Severity::Warn, "enabled"),
+    ]);
+    assert_eq!(report.severity, Severity::Warn);
+    assert_eq!(report.severity.exit_code(), 1);
+    assert_eq!(
+        report.render_text(),
+        "[pass] aslr: disabled\n[warn] turbo: enabled\n\n1 passed, 1 warnings, 0 failures\n"
+    );
+    assert_eq!(
+        serde_json::to_value(&report).unwrap(),
+        serde_json::json!({
+            "severity": "warn",
+            "checks": [
+                { "check": "aslr", "severity": "pass", "message": "disabled" },
+                { "check": "turbo", "severity": "warn", "message": "enabled" }
+            ]
+        })
+    );
+    assert_eq!(Report::new(vec![]).severity.exit_code(), 0);
+
+    assert_eq!(
+        run(["--verbose".to_owned()]).unwrap_err(),
+        "unknown argument --verbose"
+    );
+}
@@ -0,0 +1,560 @@
+//! Alarms for slow regressions, which no single change trips the gate with, like cycles that
+//! rose by 6% over two months. For every configured command and measure, a line is fitted
+//! through the values of the last results of the main branch from this kind of machine, by
+//! their commit timestamps:
+//!
+//! ```json
+//! "drift-alarm": {
+//!     "pairs": [{ "group": "decompress", "index": 0, "measure": "cycles" }],
+//!     "entries": 20,
+//!     "max-percent-per-30-days": 2.0,
+//!     "min-correlation": 0.7
+//! }
+//! ```
+//!
+//! Drift is detected when the fitted line rises by more than `max-percent-per-30-days` of the
+//! mean value every 30 days, and the values follow it closely enough, with a correlation of at
+//! least `min-correlation`, so noise that happens to slope doesn't count. Only rises are
+//! alarms, like the gate only fails on regressions. Values further than `outlier-mads` median
+//! absolute deviations from the median, like a run on a noisy runner, are left out of the fit.
+
+use std::fmt::Write;
+use std::fs::OpenOptions;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::baseline::{self, median};
+use crate::{format_number, BenchData};
+
+/// The name of the output in `GITHUB_OUTPUT`.
+pub const OUTPUT: &str = "drift-detected";
+
+/// The fewest values to fit a line through.
+pub const MIN_ENTRIES: usize = 5;
+
+const SECS_PER_DAY: f64 = 24.0 * 60.0 * 60.0;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct DriftAlarmConfig {
+    pub pairs: Vec<DriftPair>,
+    /// How many of the last results to fit, the current one included when it is of the main
+    /// branch.
+    #[serde(default = "default_entries")]
+    pub entries: usize,
+    #[serde(default = "default_max_percent_per_30_days")]
+    pub max_percent_per_30_days: f64,
+    #[serde(default = "default_min_correlation")]
+    pub min_correlation: f64,
+    #[serde(default = "default_outlier_mads")]
+    pub outlier_mads: f64,
+}
+
+/// A measure of the command at `index` of the group.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct DriftPair {
+    pub group: String,
+    pub index: usize,
+    pub measure: String,
+}
+
+fn default_entries() -> usize {
+    20
+}
+
+fn default_max_percent_per_30_days() -> f64 {
+    2.0
+}
+
+fn default_min_correlation() -> f64 {
+    0.7
+}
+
+fn default_outlier_mads() -> f64 {
+    5.0
+}
+
+/// A value of the measure, at the commit it was measured at.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Sample {
+    pub commit: String,
+    /// Seconds since the epoch.
+    pub commit_timestamp: u64,
+    pub value: f64,
+}
+
+/// A line fitted by least squares.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fit {
+    /// The change of the value per day.
+    pub slope: f64,
+    /// The value at the first sample.
+    pub intercept: f64,
+    /// Pearson's correlation of the values with time, 0 when either doesn't vary.
+    pub correlation: f64,
+}
+
+/// A pair that drifted, in the run report.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Drift {
+    pub group: String,
+    pub command: String,
+    pub measure: String,
+    /// The fitted change every 30 days, in percent of the mean value.
+    pub percent_per_30_days: f64,
+    pub correlation: f64,
+    /// How many values were fitted, and how many were left out as outliers.
+    pub entries: usize,
+    pub outliers: usize,
+    pub first: Sample,
+    pub last: Sample,
+}
+
+impl DriftAlarmConfig {
+    pub fn validate(&self, commands: impl Fn(&str) -> Option<usize>) -> Result<(), String> {
+        for pair in &self.pairs {
+            let Some(len) = commands(&pair.group) else {
+                return Err(format!(
+                    "the `drift-alarm` is for the `{}` group, which doesn't exist",
+                    pair.group
+                ));
+            };
+            if pair.index >= len {
+                return Err(format!(
+                    "the `drift-alarm` is for command {} of the `{}` group, which only has {len}",
+                    pair.index, pair.group
+                ));
+            }
+        }
+        if self.entries < MIN_ENTRIES {
+            return Err(format!(
+                "the `drift-alarm` needs at least {MIN_ENTRIES} `entries` to fit, got {}",
+                self.entries
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.min_correlation) {
+            return Err(format!(
+                "the `min-correlation` of the `drift-alarm` must be between 0 and 1, got {}",
+                self.min_correlation
+            ));
+        }
+        Ok(())
+    }
+
+    /// The drift of the `pairs`, whose commands are identified by their `id` or their command
+    /// line, over the results of `history` from the machine of `current`.
+    pub fn collect(
+        &self,
+        current: &BenchData,
+        history: &[&BenchData],
+        commands: impl Fn(&DriftPair) -> (Option<String>, Vec<String>),
+    ) -> Vec<Drift> {
+        let entries = select_entries(current, history, self.entries);
+        self.pairs
+            .iter()
+            .filter_map(|pair| {
+                let (id, argv) = commands(pair);
+                let samples = samples(&entries, &pair.group, id.as_deref(), &argv, &pair.measure);
+                self.check(pair, &argv.join(" "), samples)
+            })
+            .collect()
+    }
+
+    /// The drift of `samples`, when they drifted.
+    pub fn check(&self, pair: &DriftPair, command: &str, samples: Vec<Sample>) -> Option<Drift> {
+        let total = samples.len();
+        let samples = reject_outliers(samples, self.outlier_mads);
+        if samples.len() < MIN_ENTRIES {
+            return None;
+        }
+        let fit = fit(&points(&samples))?;
+        let mean = samples.iter().map(|sample| sample.value).sum::<f64>() / samples.len() as f64;
+        if mean == 0.0 {
+            return None;
+        }
+        let percent_per_30_days = fit.slope * 30.0 / mean.abs() * 100.0;
+        if percent_per_30_days <= self.max_percent_per_30_days
+            || fit.correlation < self.min_correlation
+        {
+            return None;
+        }
+        Some(Drift {
+            group: pair.group.clone(),
+            command: command.to_owned(),
+            measure: pair.measure.clone(),
+            percent_per_30_days,
+            correlation: fit.correlation,
+            entries: samples.len(),
+            outliers: total - samples.len(),
+            first: samples.first()?.clone(),
+            last: samples.last()?.clone(),
+        })
+    }
+}
+
+/// The last `count` results of `history` from the machine of `current`, by commit timestamp,
+/// oldest first. Of the results of the same commit, the last one measured is used.
+pub fn select_entries<'a>(
+    current: &BenchData,
+    history: &[&'a BenchData],
+    count: usize,
+) -> Vec<&'a BenchData> {
+    let mut entries = Vec::<&BenchData>::new();
+    for &entry in history {
+        if !baseline::same_machine(current, entry) {
+            continue;
+        }
+        match entries
+            .iter_mut()
+            .find(|other| other.commit_id() == entry.commit_id())
+        {
+            Some(other) if other.timestamp < entry.timestamp => *other = entry,
+            Some(_) => {}
+            None => entries.push(entry),
+        }
+    }
+    entries.sort_by_key(|entry| (entry.commit_timestamp, entry.timestamp));
+    let skip = entries.len().saturating_sub(count);
+    entries.split_off(skip)
+}
+
+/// The values of `measure` of the command with `id`, or without an id and with the command line
+/// `argv`, in the `entries` that have it and where it didn't fail.
+pub fn samples(
+    entries: &[&BenchData],
+    group_name: &str,
+    id: Option<&str>,
+    argv: &[String],
+    measure: &str,
+) -> Vec<Sample> {
+    entries
+        .iter()
+        .filter_map(|entry| {
+            let bench = entry.bench_groups.get(group_name)?.iter().find(|bench| {
+                match (id, bench.id.as_deref()) {
+                    (Some(id), Some(bench_id)) => id == bench_id,
+                    (None, None) => bench.cmd == argv,
+                    _ => false,
+                }
+            })?;
+            if bench.error.is_some() {
+                return None;
+            }
+            Some(Sample {
+                commit: entry.commit_id(),
+                commit_timestamp: entry.commit_timestamp,
+                value: bench.counters.get(measure)?.value,
+            })
+        })
+        .collect()
+}
+
+/// Leave out the samples further than `mads` median absolute deviations from the median. When
+/// most samples are the same, any other is an outlier.
+pub fn reject_outliers(samples: Vec<Sample>, mads: f64) -> Vec<Sample> {
+    if samples.is_empty() {
+        return samples;
+    }
+    let center = median(samples.iter().map(|sample| sample.value).collect());
+    let mad = median(
+        samples
+            .iter()
+            .map(|sample| (sample.value - center).abs())
+            .collect(),
+    );
+    samples
+        .into_iter()
+        .filter(|sample| (sample.value - center).abs() <= mads * mad)
+        .collect()
+}
+
+/// The samples as days since the first one, and values.
+fn points(samples: &[Sample]) -> Vec<(f64, f64)> {
+    let Some(first) = samples.first() else {
+        return vec![];
+    };
+    samples
+        .iter()
+        .map(|sample| {
+            let days =
+                (sample.commit_timestamp as f64 - first.commit_timestamp as f64) / SECS_PER_DAY;
+            (days, sample.value)
+        })
+        .collect()
+}
+
+/// The line through `points` with the least squared error, `None` for fewer than two points,
+/// or points that are all at the same time.
+pub fn fit(points: &[(f64, f64)]) -> Option<Fit> {
+    if points.len() < 2 {
+        return None;
+    }
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let (mut sxx, mut syy, mut sxy) = (0.0, 0.0, 0.0);
+    for (x, y) in points {
+        sxx += (x - mean_x) * (x - mean_x);
+        syy += (y - mean_y) * (y - mean_y);
+        sxy += (x - mean_x) * (y - mean_y);
+    }
+    if sxx == 0.0 {
+        return None;
+    }
+    let slope = sxy / sxx;
+    Some(Fit {
+        slope,
+        intercept: mean_y - slope * mean_x,
+        correlation: if syy == 0.0 {
+            0.0
+        } else {
+            sxy / (sxx * syy).sqrt()
+        },
+    })
+}
+
+/// The section naming the pairs that drifted.
+pub fn render_markdown(md: &mut String, drifts: &[Drift]) {
+    if drifts.is_empty() {
+        return;
+    }
+
+    writeln!(md, "### Slow drift detected\n").unwrap();
+    writeln!(
+        md,
+        "| command | measure | per 30 days | correlation | first | last | entries |"
+    )
+    .unwrap();
+    writeln!(md, "| --- | --- | --- | --- | --- | --- | --- |").unwrap();
+    for drift in drifts {
+        let outliers = match drift.outliers {
+            0 => String::new(),
+            1 => " (1 outlier left out)".to_owned(),
+            outliers => format!(" ({outliers} outliers left out)"),
+        };
+        writeln!(
+            md,
+            "| `{}` in `{}` | {} | `{:+.2}%` | `{:.2}` | `{}` at {} | `{}` at {} | {}{outliers} |",
+            drift.command,
+            drift.group,
+            drift.measure,
+            drift.percent_per_30_days,
+            drift.correlation,
+            format_number(drift.first.value, 3),
+            short(&drift.first.commit),
+            format_number(drift.last.value, 3),
+            short(&drift.last.commit),
+            drift.entries,
+        )
+        .unwrap();
+    }
+    writeln!(md).unwrap();
+}
+
+fn short(commit: &str) -> &str {
+    &commit[..commit.len().min(7)]
+}
+
+/// Append whether any pair drifted to the `GITHUB_OUTPUT` file of the step.
+pub fn write_github_output(path: &Path, detected: bool) -> Result<(), String> {
+    use std::io::Write;
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("failed to open {}: {e}", path.display()))?;
+    writeln!(file, "{OUTPUT}={detected}")
+        .map_err(|e| format!("failed to write {}: {e}", path.display()))
+}
+
+#[cfg(test)]
+fn config_for_test() -> DriftAlarmConfig {
+    serde_json::from_str(
+        r#"{ "pairs": [{ "group": "decompress", "index": 0, "measure": "cycles" }] }"#,
+    )
+    .unwrap()
+}
+
+/// A history of a value every 3 days, from 1e9, changing by `per_day` with some noise.
+#[cfg(test)]
+fn samples_for_test(count: usize, per_day: f64) -> Vec<Sample> {
+    // Noise of ±0.2%, deterministic.
+    let noise = [0.0, 2.0e6, -1.5e6, 1.0e6, -2.0e6, 0.5e6, -0.5e6];
+    (0..count)
+        .map(|i| Sample {
+            commit: format!("{i:02}{}", "0".repeat(38)),
+            commit_timestamp: 1_700_000_000 + i as u64 * 3 * 86_400,
+            value: 1.0e9 + per_day * 3.0 * i as f64 + noise[i % noise.len()],
+        })
+        .collect()
+}
+
+#[test]
+fn least_squares_fit() {
+    let fit = fit(&[(0.0, 1.0), (1.0, 3.0), (2.0, 5.0)]).unwrap();
+    assert_eq!(fit.slope, 2.0);
+    assert_eq!(fit.intercept, 1.0);
+    assert!((fit.correlation - 1.0).abs() < 1e-12);
+
+    let fit = super::drift::fit(&[(0.0, 5.0), (1.0, 3.0), (2.0, 1.0), (3.0, -1.0)]).unwrap();
+    assert_eq!(fit.slope, -2.0);
+    assert!((fit.correlation + 1.0).abs() < 1e-12);
+
+    // A flat line doesn't correlate.
+    let fit = super::drift::fit(&[(0.0, 2.0), (1.0, 2.0), (2.0, 2.0)]).unwrap();
+    assert_eq!((fit.slope, fit.correlation), (0.0, 0.0));
+
+    assert_eq!(super::drift::fit(&[(0.0, 2.0)]), None);
+    assert_eq!(super::drift::fit(&[(1.0, 2.0), (1.0, 3.0)]), None);
+}
+
+#[test]
+fn detect_drift() {
+    let config = config_for_test();
+    let pair = &config.pairs[0];
+    let check = |samples| config.check(pair, "./decompress", samples);
+
+    // +6% over 60 days is +3% every 30 days.
+    let drift = check(samples_for_test(20, 1.0e6)).unwrap();
+    assert!((drift.percent_per_30_days - 2.9).abs() < 0.1, "{drift:?}");
+    assert!(drift.correlation > 0.99, "{drift:?}");
+    assert_eq!(drift.entries, 20);
+    assert_eq!(drift.outliers, 0);
+    assert_eq!(drift.first.commit, "0".repeat(40));
+    assert_eq!(drift.last.commit, format!("19{}", "0".repeat(38)));
+
+    // Too slow, getting faster, or too few values.
+    assert_eq!(check(samples_for_test(20, 0.2e6)), None);
+    assert_eq!(check(samples_for_test(20, -1.0e6)), None);
+    assert_eq!(check(samples_for_test(4, 1.0e6)), None);
+    // Flat but noisy.
+    assert_eq!(check(samples_for_test(20, 0.0)), None);
+
+    // A slope too weak for the noise doesn't correlate.
+    let mut noisy = samples_for_test(20, 0.8e6);
+    for (i, sample) in noisy.iter_mut().enumerate() {
+        sample.value += if i % 2 == 0 { 3.0e7 } else { -3.0e7 };
+    }
+    assert_eq!(check(noisy), None);
+}
+
+#[test]
+fn drift_with_outliers() {
+    let config = config_for_test();
+    let pair = &config.pairs[0];
+    let check = |samples| config.check(pair, "./decompress", samples);
+
+    // A single run on a noisy runner at the end doesn't make a flat history drift.
+    let mut flat = samples_for_test(20, 0.0);
+    flat[19].value = 1.3e9;
+    assert_eq!(reject_outliers(flat.clone(), 5.0).len(), 19);
+    assert_eq!(check(flat), None);
+
+    // Nor hides a drift.
+    let mut drifting = samples_for_test(20, 1.0e6);
+    drifting[3].value = 0.5e9;
+    let drift = check(drifting).unwrap();
+    assert_eq!(drift.entries, 19);
+    assert_eq!(drift.outliers, 1);
+
+    // Identical values leave out any other.
+    let mut same = samples_for_test(6, 0.0);
+    for sample in &mut same {
+        sample.value = 100.0;
+    }
+    same[5].value = 101.0;
+    assert_eq!(reject_outliers(same, 5.0).len(), 5);
+}
+
+#[test]
+fn select_history_entries() {
+    use crate::testkit::BenchDataBuilder;
+
+    let entry = |commit: usize, timestamp: u64, machine_class: &str, cycles: f64| {
+        let mut data = BenchDataBuilder::new(&format!("{commit:040}"))
+            .machine_class(machine_class)
+            .group("decompress", |g| {
+                g.bench(["./decompress"], |b| {
+                    b.counter("cycles", cycles, 1.0, 20, "")
+                })
+            })
+            .build();
+        data.commit_timestamp = 1_700_000_000 + commit as u64 * 86_400;
+        data.timestamp = std::time::UNIX_EPOCH + std::time::Duration::from_secs(timestamp);
+        data
+    };
+    let dsv5 = "intel-xeon-platinum-8370c/4";
+    let history = [
+        entry(3, 30, dsv5, 3.0),
+        entry(1, 10, dsv5, 1.0),
+        entry(2, 20, "amd-epyc-7763/4", 2.0),
+        entry(2, 21, dsv5, 2.0),
+        // A rerun of the same commit replaces the first run.
+        entry(3, 31, dsv5, 3.5),
+        entry(4, 40, dsv5, 4.0),
+    ];
+    let history = history.iter().collect::<Vec<_>>();
+    let current = entry(5, 50, dsv5, 5.0);
+
+    // By commit timestamp, from the same machine, the last ones.
+    let entries = select_entries(&current, &history, 3);
+    let values = |entries: &[&BenchData]| {
+        samples(
+            entries,
+            "decompress",
+            None,
+            &["./decompress".to_owned()],
+            "cycles",
+        )
+        .into_iter()
+        .map(|sample| sample.value)
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(values(&entries), [2.0, 3.5, 4.0]);
+    assert_eq!(
+        values(&select_entries(&current, &history, 10)),
+        [1.0, 2.0, 3.5, 4.0]
+    );
+
+    // Matched by id, and only where the command didn't fail.
+    let mut with_id = entry(6, 60, dsv5, 6.0);
+    with_id.bench_groups["decompress"][0].id = Some("level-6".to_owned());
+    let mut failed = entry(7, 70, dsv5, 7.0);
+    failed.bench_groups["decompress"][0].id = Some("level-6".to_owned());
+    failed.bench_groups["decompress"][0].error = Some("killed".to_owned());
+    let entries = [&with_id, &failed, history[0]];
+    let by_id = samples(&entries, "decompress", Some("level-6"), &[], "cycles");
+    assert_eq!(by_id.len(), 1);
+    assert_eq!(by_id[0].value, 6.0);
+    assert!(samples(&entries, "decompress", None, &[], "instructions").is_empty());
+}
+
+#[test]
+fn render_drift() {
+    let config = config_for_test();
+    let mut samples = samples_for_test(20, 1.0e6);
+    samples[3].value = 0.5e9;
+    let drift = config
+        .check(&config.pairs[0], "./decompress", samples)
+        .unwrap();
+
+    let mut md = String::new();
+    render_markdown(&mut md, &[drift]);
+    assert_eq!(
+        md,
+        "### Slow drift detected\n\n\
+         | command | measure | per 30 days | correlation | first | last | entries |\n\
+         | --- | --- | --- | --- | --- | --- | --- |\n\
+         | `./decompress` in `decompress` | cycles | `+2.91%` | `1.00` | `1000000000` at 0000000 | `1057500000` at 1900000 | 19 (1 outlier left out) |\n\n"
+    );
+
+    let path = crate::testkit::test_dir("drift-output").join("output");
+    write_github_output(&path, true).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "drift-detected=true\n"
+    );
+}
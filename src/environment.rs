@@ -0,0 +1,466 @@
+//! The environment the benchmarks ran in. A command that is slower than on the baseline for no
+//! reason in the code often ran with a different `LD_LIBRARY_PATH`, glibc or
+//! `MALLOC_ARENA_MAX`, so every run records the variables of an allowlist and the first line
+//! of `ldd --version`, and the step summary lists what changed since the baseline:
+//!
+//! ```json
+//! "environment": { "variables": ["PATH", "LD_LIBRARY_PATH", "MALLOC_*", "RUSTFLAGS", "ZLIB_*"] }
+//! ```
+//!
+//! A trailing `*` matches any suffix, and an empty list records no variables. Variables outside
+//! the allowlist are never recorded, as they may hold secrets. The recorded values are
+//! sanitized right away rather than when the results are written, so that they compare equal
+//! to those of the stored baseline and are never cut short before a rule had a chance to
+//! match.
+//!
+//! The environment is that of the benchmarker itself, which every group inherits, so it is
+//! recorded once per run.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::process::{Command, Output};
+
+use serde::{Deserialize, Serialize};
+
+use crate::sanitize::Sanitizer;
+
+/// How many characters of a value the step summary shows.
+const MAX_VALUE_CHARS: usize = 80;
+
+/// The name the version of the C library is listed under.
+const LDD_VERSION: &str = "ldd --version";
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct EnvironmentConfig {
+    /// The names of the variables to record, with an optional trailing `*`.
+    #[serde(default = "default_variables")]
+    pub variables: Vec<String>,
+    /// Record the first line of `ldd --version`.
+    #[serde(default = "default_ldd")]
+    pub ldd: bool,
+}
+
+fn default_variables() -> Vec<String> {
+    ["PATH", "LD_LIBRARY_PATH", "MALLOC_*", "RUSTFLAGS"]
+        .map(str::to_owned)
+        .to_vec()
+}
+
+fn default_ldd() -> bool {
+    true
+}
+
+impl Default for EnvironmentConfig {
+    fn default() -> Self {
+        EnvironmentConfig {
+            variables: default_variables(),
+            ldd: default_ldd(),
+        }
+    }
+}
+
+impl EnvironmentConfig {
+    /// Whether the variable `name` is on the allowlist.
+    pub fn allows(&self, name: &str) -> bool {
+        self.variables
+            .iter()
+            .any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => name.starts_with(prefix),
+                None => name == pattern,
+            })
+    }
+}
+
+/// The recorded environment of a run.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Environment {
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub variables: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ldd_version: Option<String>,
+}
+
+impl Environment {
+    /// The sanitized variables of `vars` that `config` allows, and the sanitized `ldd_version`.
+    pub fn capture(
+        config: &EnvironmentConfig,
+        vars: impl IntoIterator<Item = (String, String)>,
+        ldd_version: Option<String>,
+        sanitizer: &Sanitizer,
+    ) -> Self {
+        let variables = vars
+            .into_iter()
+            .filter(|(name, _)| config.allows(name))
+            .map(|(name, value)| (name, sanitizer.sanitize(&value).into_owned()))
+            .collect();
+        let ldd_version = ldd_version
+            .filter(|_| config.ldd)
+            .map(|version| sanitizer.sanitize(&version).into_owned());
+        Environment {
+            variables,
+            ldd_version,
+        }
+    }
+
+    /// The environment of the benchmarker.
+    pub fn detect(config: &EnvironmentConfig, sanitizer: &Sanitizer) -> Self {
+        let vars = std::env::vars_os().filter_map(|(name, value)| {
+            Some((
+                name.into_string().ok()?,
+                value.to_string_lossy().into_owned(),
+            ))
+        });
+        let ldd_version = if config.ldd && cfg!(target_os = "linux") {
+            let output = Command::new("ldd")
+                .env("LANG", "C")
+                .arg("--version")
+                .output();
+            output.ok().as_ref().and_then(ldd_version)
+        } else {
+            None
+        };
+        Environment::capture(config, vars, ldd_version, sanitizer)
+    }
+}
+
+/// The first line of the output of `ldd --version`. glibc prints it to stdout, musl to stderr
+/// and exits with a failure.
+pub fn ldd_version(output: &Output) -> Option<String> {
+    [&output.stdout, &output.stderr]
+        .into_iter()
+        .find_map(|stream| {
+            String::from_utf8_lossy(stream)
+                .lines()
+                .map(str::trim)
+                .find(|line| !line.is_empty())
+                .map(str::to_owned)
+        })
+}
+
+/// A variable, or the version of the C library, that differs from the baseline. `before` is
+/// `None` when it was added, `after` when it was removed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub name: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+impl Change {
+    fn kind(&self) -> &'static str {
+        match (&self.before, &self.after) {
+            (None, _) => "added",
+            (_, None) => "removed",
+            _ => "modified",
+        }
+    }
+}
+
+/// What changed from the environment of the baseline to the current one, by name. Variables
+/// of the baseline that `config` no longer allows are left out, as they were not recorded
+/// rather than unset.
+pub fn diff(config: &EnvironmentConfig, before: &Environment, after: &Environment) -> Vec<Change> {
+    let mut names = before
+        .variables
+        .keys()
+        .filter(|name| config.allows(name))
+        .chain(after.variables.keys())
+        .collect::<Vec<_>>();
+    names.sort();
+    names.dedup();
+
+    let mut changes = names
+        .into_iter()
+        .map(|name| Change {
+            name: name.clone(),
+            before: before.variables.get(name).cloned(),
+            after: after.variables.get(name).cloned(),
+        })
+        .collect::<Vec<_>>();
+    changes.push(Change {
+        name: LDD_VERSION.to_owned(),
+        before: before.ldd_version.clone(),
+        after: after.ldd_version.clone(),
+    });
+    changes.retain(|change| change.before != change.after);
+    changes
+}
+
+/// `value` cut to `max_chars` characters, with an ellipsis if it was longer.
+pub fn truncate(value: &str, max_chars: usize) -> String {
+    match value.char_indices().nth(max_chars) {
+        Some((end, _)) => format!("{}…", &value[..end]),
+        None => value.to_owned(),
+    }
+}
+
+/// A value as inline code in a table cell.
+fn code(value: &str) -> String {
+    let value = truncate(value, MAX_VALUE_CHARS).replace('|', "\\|");
+    if value.contains('`') {
+        format!("`` {value} ``")
+    } else {
+        format!("`{value}`")
+    }
+}
+
+/// A collapsed table of the changes since the baseline, if there are any.
+pub fn render_markdown(md: &mut String, changes: &[Change]) {
+    if changes.is_empty() {
+        return;
+    }
+
+    // GitHub only renders markdown inside <details> when surrounded by blank lines.
+    writeln!(
+        md,
+        "<details>\n<summary>Environment: {} changes since the baseline</summary>\n",
+        changes.len()
+    )
+    .unwrap();
+    writeln!(md, "| variable | change | baseline | now |").unwrap();
+    writeln!(md, "| --- | --- | --- | --- |").unwrap();
+    let cell = |value: &Option<String>| value.as_deref().map_or("-".to_owned(), code);
+    for change in changes {
+        writeln!(
+            md,
+            "| `{}` | {} | {} | {} |",
+            change.name,
+            change.kind(),
+            cell(&change.before),
+            cell(&change.after),
+        )
+        .unwrap();
+    }
+    writeln!(md, "\n</details>\n").unwrap();
+}
+
+#[cfg(test)]
+fn synthetic_vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+    vars.iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+#[cfg(test)]
+fn sanitizer_for_test(config: &str) -> Sanitizer {
+    Sanitizer::new(serde_json::from_str(config).unwrap(), Some("runner-7"))
+}
+
+#[test]
+fn capture_allowlisted_variables() {
+    let vars = synthetic_vars(&[
+        ("PATH", "/home/alice/.cargo/bin:/usr/bin"),
+        ("MALLOC_ARENA_MAX", "2"),
+        ("MALLOC_CONF", "background_thread:true"),
+        ("MALLOCX", "1"),
+        ("RUSTFLAGS", "-Ctarget-cpu=native"),
+        ("GITHUB_TOKEN", "ghp_secret"),
+        ("AWS_SECRET_ACCESS_KEY", "secret"),
+        ("HOSTNAME", "runner-7"),
+    ]);
+    let environment = Environment::capture(
+        &EnvironmentConfig::default(),
+        vars.clone(),
+        Some("ldd (GNU libc) 2.39".to_owned()),
+        &sanitizer_for_test("{}"),
+    );
+    assert_eq!(
+        environment.variables,
+        BTreeMap::from([
+            ("MALLOC_ARENA_MAX".to_owned(), "2".to_owned()),
+            (
+                "MALLOC_CONF".to_owned(),
+                "background_thread:true".to_owned()
+            ),
+            (
+                "PATH".to_owned(),
+                "/home/<user>/.cargo/bin:/usr/bin".to_owned()
+            ),
+            ("RUSTFLAGS".to_owned(), "-Ctarget-cpu=native".to_owned()),
+        ])
+    );
+    assert_eq!(
+        environment.ldd_version.as_deref(),
+        Some("ldd (GNU libc) 2.39")
+    );
+    let json = serde_json::to_string(&environment).unwrap();
+    assert!(!json.contains("secret"), "{json}");
+
+    // Nothing but what is asked for.
+    let config: EnvironmentConfig =
+        serde_json::from_str(r#"{ "variables": ["HOSTNAME"], "ldd": false }"#).unwrap();
+    let environment = Environment::capture(
+        &config,
+        vars,
+        Some("ldd (GNU libc) 2.39".to_owned()),
+        &sanitizer_for_test("{}"),
+    );
+    assert_eq!(
+        environment,
+        Environment {
+            variables: BTreeMap::from([("HOSTNAME".to_owned(), "<runner>".to_owned())]),
+            ldd_version: None,
+        }
+    );
+    assert!(serde_json::from_str::<EnvironmentConfig>(r#"{ "vars": [] }"#).is_err());
+}
+
+#[test]
+fn parse_ldd_version() {
+    use std::os::unix::process::ExitStatusExt;
+
+    let output = |stdout: &str, stderr: &str| Output {
+        status: std::process::ExitStatus::from_raw(0),
+        stdout: stdout.as_bytes().to_vec(),
+        stderr: stderr.as_bytes().to_vec(),
+    };
+    assert_eq!(
+        ldd_version(&output(
+            "ldd (Ubuntu GLIBC 2.39-0ubuntu8.4) 2.39\nCopyright (C) 2024 Free Software Foundation, Inc.\n",
+            ""
+        ))
+        .as_deref(),
+        Some("ldd (Ubuntu GLIBC 2.39-0ubuntu8.4) 2.39")
+    );
+    assert_eq!(
+        ldd_version(&output("", "musl libc (x86_64)\nVersion 1.2.5\n")).as_deref(),
+        Some("musl libc (x86_64)")
+    );
+    assert_eq!(ldd_version(&output("", "")), None);
+}
+
+#[test]
+fn diff_environments() {
+    let environment = |vars: &[(&str, &str)], ldd: &str| Environment {
+        variables: synthetic_vars(vars).into_iter().collect(),
+        ldd_version: Some(ldd.to_owned()),
+    };
+    let before = environment(
+        &[
+            ("LD_LIBRARY_PATH", "/opt/zlib/lib"),
+            ("PATH", "/usr/bin"),
+            ("RUSTFLAGS", "-Ctarget-cpu=native"),
+            ("ZLIB_LEVEL", "6"),
+        ],
+        "ldd (GNU libc) 2.35",
+    );
+    let after = environment(
+        &[
+            ("MALLOC_ARENA_MAX", "2"),
+            ("PATH", "/usr/bin"),
+            ("RUSTFLAGS", "-Ctarget-cpu=x86-64-v3"),
+        ],
+        "ldd (GNU libc) 2.39",
+    );
+    let change = |name: &str, before: Option<&str>, after: Option<&str>| Change {
+        name: name.to_owned(),
+        before: before.map(str::to_owned),
+        after: after.map(str::to_owned),
+    };
+
+    // `ZLIB_LEVEL` was recorded with another allowlist.
+    let changes = diff(&EnvironmentConfig::default(), &before, &after);
+    assert_eq!(
+        changes,
+        [
+            change("LD_LIBRARY_PATH", Some("/opt/zlib/lib"), None),
+            change("MALLOC_ARENA_MAX", None, Some("2")),
+            change(
+                "RUSTFLAGS",
+                Some("-Ctarget-cpu=native"),
+                Some("-Ctarget-cpu=x86-64-v3")
+            ),
+            change(
+                LDD_VERSION,
+                Some("ldd (GNU libc) 2.35"),
+                Some("ldd (GNU libc) 2.39")
+            ),
+        ]
+    );
+    assert_eq!(
+        changes.iter().map(Change::kind).collect::<Vec<_>>(),
+        ["removed", "added", "modified", "modified"]
+    );
+
+    assert_eq!(diff(&EnvironmentConfig::default(), &after, &after), []);
+    assert_eq!(
+        diff(
+            &EnvironmentConfig::default(),
+            &Environment::default(),
+            &Environment::default()
+        ),
+        []
+    );
+
+    let mut md = String::new();
+    render_markdown(&mut md, &changes);
+    assert_eq!(
+        md,
+        "<details>
+<summary>Environment: 4 changes since the baseline</summary>
+
+| variable | change | baseline | now |
+| --- | --- | --- | --- |
+| `LD_LIBRARY_PATH` | removed | `/opt/zlib/lib` | - |
+| `MALLOC_ARENA_MAX` | added | - | `2` |
+| `RUSTFLAGS` | modified | `-Ctarget-cpu=native` | `-Ctarget-cpu=x86-64-v3` |
+| `ldd --version` | modified | `ldd (GNU libc) 2.35` | `ldd (GNU libc) 2.39` |
+
+</details>
+
+"
+    );
+
+    let mut md = String::new();
+    render_markdown(&mut md, &[]);
+    assert_eq!(md, "");
+}
+
+#[test]
+fn truncate_sanitized_values() {
+    assert_eq!(truncate("abc", 3), "abc");
+    assert_eq!(truncate("abcd", 3), "abc…");
+    assert_eq!(truncate("äöüß", 2), "äö…");
+
+    // The token straddles the cut: cutting first would leave half of it for the rule that
+    // no longer matches.
+    let token = "0123456789abcdef0123456789abcdef";
+    let value = format!("{}token={token}", "x".repeat(70));
+    let sanitizer = sanitizer_for_test(
+        r#"{ "rules": [{ "pattern": "token=[0-9a-f]{32}", "replacement": "token=<redacted>" }] }"#,
+    );
+    assert_eq!(
+        sanitizer.sanitize(&truncate(&value, MAX_VALUE_CHARS)),
+        format!("{}token=0123…", "x".repeat(70))
+    );
+
+    let config: EnvironmentConfig =
+        serde_json::from_str(r#"{ "variables": ["RUSTFLAGS"] }"#).unwrap();
+    let after = Environment::capture(
+        &config,
+        synthetic_vars(&[("RUSTFLAGS", &value)]),
+        None,
+        &sanitizer,
+    );
+    let changes = diff(&config, &Environment::default(), &after);
+    let mut md = String::new();
+    render_markdown(&mut md, &changes);
+    assert!(!md.contains("0123"), "{md}");
+    assert!(
+        md.contains(&format!("| - | `{}token=<red…` |", "x".repeat(70))),
+        "{md}"
+    );
+
+    // Values can't break out of the table.
+    let mut md = String::new();
+    render_markdown(
+        &mut md,
+        &[Change {
+            name: "MALLOC_CONF".to_owned(),
+            before: None,
+            after: Some("a|`b`".to_owned()),
+        }],
+    );
+    assert!(md.contains("| - | `` a\\|`b` `` |"), "{md}");
+}
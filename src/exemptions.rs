@@ -0,0 +1,499 @@
+//! Regressions that are accepted on purpose, like a correctness fix that costs a few percent.
+//! A trailer in the message of the benchmarked commit, or of the head commit of a pull
+//! request, exempts a row of a `render-versus-other` table, or every row, from the gate:
+//!
+//! ```text
+//! Perf-Exempt: compression/level-1 the bounds check is needed for correctness
+//! Perf-Exempt: `ng vs rs/level 1` the bounds check is needed for correctness
+//! Perf-Exempt: all the new format is slower to parse, and that's fine
+//! ```
+//!
+//! The target is `<table>/<row>`, in backticks or double quotes when the names have spaces,
+//! or `all`, followed by the reason, which is required. The key is case-insensitive, and a
+//! message can have any number of trailers. With
+//!
+//! ```json
+//! "gate": { "max-regression-percent": 5, "exempt-label": "perf-regression-accepted" }
+//! ```
+//!
+//! a pull request with that label exempts every row, with the description of the label as
+//! the reason.
+//!
+//! The failures of exempted rows become warnings, listed with their reason in the step
+//! summary. The exemptions are recorded in the results and the run report, so they can be
+//! audited later.
+
+use std::fmt::Write;
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+
+use crate::compare::Comparisons;
+use crate::gate::{GateFailure, GateVerdict};
+use crate::trigger::GitHubContext;
+
+/// The key of the trailers, compared case-insensitively.
+const TRAILER: &str = "perf-exempt";
+
+/// The target that exempts every row.
+const ALL: &str = "all";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Exemption {
+    /// `<table>/<row>`, or `all`.
+    pub target: String,
+    pub reason: String,
+    pub source: ExemptionSource,
+}
+
+/// Where an exemption comes from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExemptionSource {
+    /// A trailer in the message of the commit, by its short id.
+    Commit(String),
+    /// A label of the pull request.
+    Label(String),
+}
+
+/// A gate failure that an exemption turned into a warning.
+#[derive(Debug, Serialize)]
+pub struct AcceptedFailure {
+    #[serde(flatten)]
+    pub failure: GateFailure,
+    /// A failure of `max-variance-increase`, rather than of `max-regression-percent`.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub variance: bool,
+    pub exemption: Exemption,
+}
+
+impl Exemption {
+    /// Whether the exemption covers the row `row_name` of the table `table`.
+    pub fn covers(&self, table: &str, row_name: &str) -> bool {
+        self.target == ALL
+            || self
+                .target
+                .split_once('/')
+                .is_some_and(|(target_table, target_row)| {
+                    target_table == table && target_row == row_name
+                })
+    }
+}
+
+impl ExemptionSource {
+    pub fn describe(&self) -> String {
+        match self {
+            ExemptionSource::Commit(commit) => format!("commit {commit}"),
+            ExemptionSource::Label(label) => format!("label `{label}`"),
+        }
+    }
+}
+
+/// The value of a `Perf-Exempt` trailer, if `line` is one.
+fn trailer_value(line: &str) -> Option<&str> {
+    let (key, value) = line.split_once(':')?;
+    key.trim()
+        .eq_ignore_ascii_case(TRAILER)
+        .then(|| value.trim())
+}
+
+/// The target and the reason of the value of a trailer.
+fn parse_value(value: &str) -> Result<(String, String), String> {
+    let (target, reason) = match value.chars().next() {
+        Some(quote @ ('`' | '"')) => {
+            let rest = &value[1..];
+            let end = rest
+                .find(quote)
+                .ok_or_else(|| format!("the target has no closing {quote}"))?;
+            (&rest[..end], &rest[end + 1..])
+        }
+        _ => value.split_at(value.find(char::is_whitespace).unwrap_or(value.len())),
+    };
+    let reason = reason.trim();
+    if target.is_empty() {
+        return Err("there is no target".to_owned());
+    }
+    if !target.eq_ignore_ascii_case(ALL) && !target.contains('/') {
+        return Err(format!(
+            "the target `{target}` is neither `all` nor `<table>/<row>`"
+        ));
+    }
+    if reason.is_empty() {
+        return Err(format!("the exemption of `{target}` has no reason"));
+    }
+    let target = if target.eq_ignore_ascii_case(ALL) {
+        ALL
+    } else {
+        target
+    };
+    Ok((target.to_owned(), reason.to_owned()))
+}
+
+/// The exemptions of the `Perf-Exempt` trailers of the commit `message`, and a warning for
+/// every malformed one.
+pub fn parse_trailers(message: &str, commit: &str) -> (Vec<Exemption>, Vec<String>) {
+    let mut exemptions = vec![];
+    let mut warnings = vec![];
+    for line in message.lines() {
+        let Some(value) = trailer_value(line) else {
+            continue;
+        };
+        match parse_value(value) {
+            Ok((target, reason)) => exemptions.push(Exemption {
+                target,
+                reason,
+                source: ExemptionSource::Commit(commit.to_owned()),
+            }),
+            Err(err) => warnings.push(format!(
+                "ignoring the malformed trailer `{}` of commit {commit}: {err}",
+                line.trim()
+            )),
+        }
+    }
+    (exemptions, warnings)
+}
+
+/// The exemption of the `exempt_label`, when the pull request has it.
+pub fn from_labels(github: &GitHubContext, exempt_label: &str) -> Option<Exemption> {
+    let label = github
+        .labels
+        .iter()
+        .find(|label| label.name == exempt_label)?;
+    Some(Exemption {
+        target: ALL.to_owned(),
+        reason: label
+            .description
+            .clone()
+            .filter(|description| !description.trim().is_empty())
+            .unwrap_or_else(|| "the pull request is labelled as such".to_owned()),
+        source: ExemptionSource::Label(label.name.clone()),
+    })
+}
+
+/// The message of `commit`.
+fn commit_message(commit: &str) -> Result<String, String> {
+    let output = Command::new("git")
+        .args(["show", "--no-patch", "--format=%B", commit])
+        .output()
+        .map_err(|e| format!("failed to run git: {e}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "failed to read the message of commit {commit}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// The exemptions of the run of `commit`: the trailers of its message and of the head commit
+/// of the pull request, if different, and the `exempt_label`. With warnings for what couldn't
+/// be read.
+pub fn collect(
+    commit: &str,
+    github: &GitHubContext,
+    exempt_label: Option<&str>,
+) -> (Vec<Exemption>, Vec<String>) {
+    let mut exemptions = vec![];
+    let mut warnings = vec![];
+
+    let mut commits = vec![commit];
+    if let Some(head) = github.pull_request_head.as_deref() {
+        if head != commit {
+            commits.push(head);
+        }
+    }
+    for commit in commits {
+        match commit_message(commit) {
+            Ok(message) => {
+                let short = &commit[..commit.len().min(7)];
+                let (found, malformed) = parse_trailers(&message, short);
+                exemptions.extend(found);
+                warnings.extend(malformed);
+            }
+            Err(err) => warnings.push(err),
+        }
+    }
+
+    if let Some(exempt_label) = exempt_label {
+        exemptions.extend(from_labels(github, exempt_label));
+    }
+    (exemptions, warnings)
+}
+
+/// Warnings for the exemptions of rows that aren't in any `render-versus-other` table, like a
+/// misspelled or renamed one.
+pub fn unknown_targets(exemptions: &[Exemption], comparisons: &Comparisons) -> Vec<String> {
+    exemptions
+        .iter()
+        .filter(|exemption| exemption.target != ALL)
+        .filter(|exemption| {
+            !comparisons.versus_other.iter().any(|table| {
+                table
+                    .rows
+                    .iter()
+                    .any(|row| exemption.covers(&table.name, &row.name))
+            })
+        })
+        .map(|exemption| {
+            format!(
+                "the exemption of `{}` by {} matches no compared row",
+                exemption.target,
+                exemption.source.describe()
+            )
+        })
+        .collect()
+}
+
+/// Move the failures of the `verdict` that an exemption covers to the accepted ones. The
+/// first exemption of a row wins.
+pub fn apply(verdict: &mut GateVerdict, exemptions: &[Exemption]) {
+    if exemptions.is_empty() {
+        return;
+    }
+    for (failures, variance) in [
+        (&mut verdict.failures, false),
+        (&mut verdict.variance_failures, true),
+    ] {
+        let mut kept = vec![];
+        for failure in std::mem::take(failures) {
+            match exemptions
+                .iter()
+                .find(|exemption| exemption.covers(&failure.table, &failure.row.name))
+            {
+                Some(exemption) => verdict.accepted.push(AcceptedFailure {
+                    failure,
+                    variance,
+                    exemption: exemption.clone(),
+                }),
+                None => kept.push(failure),
+            }
+        }
+        *failures = kept;
+    }
+}
+
+impl AcceptedFailure {
+    /// The line of the accepted failure in the log.
+    pub fn warning(&self) -> String {
+        let change = if self.variance {
+            format!("got {} more variable in", self.failure.format_cov_delta())
+        } else {
+            format!("regressed by {}", self.failure.row.format_delta())
+        };
+        format!(
+            "gate failure accepted by {}: {} / {} {change} {} ({})",
+            self.exemption.source.describe(),
+            self.failure.table,
+            self.failure.row.name,
+            self.failure.row.measure,
+            self.exemption.reason
+        )
+    }
+}
+
+/// The section of the accepted regressions, if there are any.
+pub fn render_markdown(md: &mut String, accepted: &[AcceptedFailure]) {
+    if accepted.is_empty() {
+        return;
+    }
+
+    writeln!(md, "### Accepted regressions\n").unwrap();
+    writeln!(md, "| comparison | Δ | reason | accepted by |").unwrap();
+    writeln!(md, "| --- | --- | --- | --- |").unwrap();
+    for accepted in accepted {
+        let failure = &accepted.failure;
+        let change = if accepted.variance {
+            format!(
+                "`{}` coefficient of variation of {}",
+                failure.format_cov_delta(),
+                failure.row.measure
+            )
+        } else {
+            format!("`{}` {}", failure.row.format_delta(), failure.row.measure)
+        };
+        writeln!(
+            md,
+            "| {} / {} | {change} | {} | {} |",
+            failure.table,
+            failure.row.name,
+            accepted.exemption.reason.replace('|', "\\|"),
+            accepted.exemption.source.describe(),
+        )
+        .unwrap();
+    }
+    writeln!(md).unwrap();
+}
+
+#[cfg(test)]
+fn exemption_for_test(target: &str, reason: &str) -> Exemption {
+    Exemption {
+        target: target.to_owned(),
+        reason: reason.to_owned(),
+        source: ExemptionSource::Commit("2222222".to_owned()),
+    }
+}
+
+#[test]
+fn parse_exemption_trailers() {
+    let message = "Check the bounds of the window
+
+The check costs a few percent, but without it a crafted stream reads
+out of bounds.
+
+Perf-Exempt: compression/level-1 the bounds check is needed
+perf-exempt: `ng vs rs/level 1`   the bounds check is needed
+PERF-EXEMPT: \"ng vs rs/level 2\" same
+Perf-Exempt: ALL and everything else too
+Perf-Exempt: compression/level-9
+Perf-Exempt: level-9 missing the table
+Perf-Exempt: `compression/level 9 unterminated
+Perf-Exempt:
+Perf-Exemption: other/row not our trailer
+Signed-off-by: Dev <dev@example.com>
+";
+    let (exemptions, warnings) = parse_trailers(message, "2222222");
+    assert_eq!(
+        exemptions,
+        [
+            exemption_for_test("compression/level-1", "the bounds check is needed"),
+            exemption_for_test("ng vs rs/level 1", "the bounds check is needed"),
+            exemption_for_test("ng vs rs/level 2", "same"),
+            exemption_for_test("all", "and everything else too"),
+        ]
+    );
+    assert_eq!(
+        warnings,
+        [
+            "ignoring the malformed trailer `Perf-Exempt: compression/level-9` of commit 2222222: the exemption of `compression/level-9` has no reason",
+            "ignoring the malformed trailer `Perf-Exempt: level-9 missing the table` of commit 2222222: the target `level-9` is neither `all` nor `<table>/<row>`",
+            "ignoring the malformed trailer `Perf-Exempt: `compression/level 9 unterminated` of commit 2222222: the target has no closing `",
+            "ignoring the malformed trailer `Perf-Exempt:` of commit 2222222: there is no target",
+        ]
+    );
+
+    let (exemptions, warnings) = parse_trailers("Speed up inflate\n", "2222222");
+    assert!(exemptions.is_empty() && warnings.is_empty());
+}
+
+#[test]
+fn exemption_from_label() {
+    let context = crate::trigger::context_for_test(&[
+        ("GITHUB_REF", "refs/pull/43/merge"),
+        ("GITHUB_EVENT_PATH", "pull_request_labeled.json"),
+    ]);
+    assert_eq!(
+        context.pull_request_head.as_deref(),
+        Some("3333333333333333333333333333333333333333")
+    );
+    assert_eq!(
+        from_labels(&context, "perf-regression-accepted"),
+        Some(Exemption {
+            target: "all".to_owned(),
+            reason: "The regression is the price of a correctness fix".to_owned(),
+            source: ExemptionSource::Label("perf-regression-accepted".to_owned()),
+        })
+    );
+    // A label without a description.
+    assert_eq!(
+        from_labels(&context, "benchmark").unwrap().reason,
+        "the pull request is labelled as such"
+    );
+    assert_eq!(from_labels(&context, "accepted"), None);
+    // Pushes have no labels.
+    assert_eq!(
+        from_labels(
+            &crate::trigger::context_for_test(&[("GITHUB_REF", "refs/heads/trunk")]),
+            "perf-regression-accepted"
+        ),
+        None
+    );
+}
+
+#[test]
+fn accept_exempted_failures() {
+    let before = crate::testkit::BenchDataBuilder::cycles(
+        "1111111111111111111111111111111111111111",
+        &[("compress", &[("./c 1", 1000.0), ("./c 2", 1000.0)])],
+    )
+    .build();
+    let after = crate::testkit::BenchDataBuilder::cycles(
+        "2222222222222222222222222222222222222222",
+        &[("compress", &[("./c 1", 1200.0), ("./c 2", 1300.0)])],
+    )
+    .build();
+    let render = serde_json::from_str(
+        r#"{ "compression": { "measure": "cycles", "command": "compress", "rows": { "level 1": 0, "level 2": 1 } } }"#,
+    )
+    .unwrap();
+    let comparisons = Comparisons {
+        versus_other: crate::compare::collect_versus_other(
+            &render,
+            &indexmap::IndexMap::new(),
+            None,
+            &before,
+            &after,
+        ),
+        ..Comparisons::default()
+    };
+    let config: crate::gate::GateConfig =
+        serde_json::from_str(r#"{ "max-regression-percent": 5.0 }"#).unwrap();
+
+    // Only the exempted row is accepted, matched by the resolved table and row names.
+    let exemptions = [
+        exemption_for_test("compression/level 2", "correctness fix"),
+        exemption_for_test("compression/level 3", "renamed since"),
+        exemption_for_test("Compression/level 1", "wrong case"),
+    ];
+    let mut verdict = config.evaluate(&comparisons);
+    apply(&mut verdict, &exemptions);
+    assert!(!verdict.passed());
+    assert_eq!(verdict.failures.len(), 1);
+    assert_eq!(verdict.failures[0].row.name, "level 1");
+    assert_eq!(verdict.accepted.len(), 1);
+    assert_eq!(
+        verdict.accepted[0].warning(),
+        "gate failure accepted by commit 2222222: compression / level 2 regressed by +23.08% cycles (correctness fix)"
+    );
+    assert_eq!(
+        unknown_targets(&exemptions, &comparisons),
+        [
+            "the exemption of `compression/level 3` by commit 2222222 matches no compared row",
+            "the exemption of `Compression/level 1` by commit 2222222 matches no compared row",
+        ]
+    );
+
+    // A blanket exemption passes the gate.
+    let mut verdict = config.evaluate(&comparisons);
+    apply(
+        &mut verdict,
+        &[Exemption {
+            target: "all".to_owned(),
+            reason: "new | format".to_owned(),
+            source: ExemptionSource::Label("perf-regression-accepted".to_owned()),
+        }],
+    );
+    assert!(verdict.passed());
+    assert_eq!(verdict.accepted.len(), 2);
+
+    let mut md = String::new();
+    verdict.render_markdown(&mut md, &config);
+    assert_eq!(
+        md,
+        "### Accepted regressions
+
+| comparison | Δ | reason | accepted by |
+| --- | --- | --- | --- |
+| compression / level 1 | `+16.67%` cycles | new \\| format | label `perf-regression-accepted` |
+| compression / level 2 | `+23.08%` cycles | new \\| format | label `perf-regression-accepted` |
+
+"
+    );
+    assert_eq!(
+        serde_json::to_value(&verdict).unwrap()["accepted"][0]["exemption"],
+        serde_json::json!({
+            "target": "all",
+            "reason": "new | format",
+            "source": { "label": "perf-regression-accepted" }
+        })
+    );
+}
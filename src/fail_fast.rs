@@ -0,0 +1,284 @@
+//! `--fail-fast`: for gating pull requests, stop as soon as the gate fails on the groups
+//! measured so far, rather than after the whole suite. After every group, the gate is
+//! evaluated on the comparisons the results so far decide: the `render-versus-other` tables of
+//! the groups that ran, and the `render-versus-self` rows of which both groups ran. When it
+//! fails, the remaining groups aren't run, the run renders what it has, and exits like any
+//! run with a failed gate.
+//!
+//! The results are then marked as `partial`: they are never written to the results file, and
+//! never used as a baseline.
+//!
+//! With a correction for multiple comparisons, a row only fails early when it would still be
+//! significant with the comparisons of the groups that didn't run yet, see
+//! [`pending_comparisons`].
+
+use indexmap::IndexMap;
+
+use crate::compare::{self, Comparisons};
+use crate::gate::GateVerdict;
+use crate::{BenchData, Config};
+
+/// The reason of the groups that didn't run, in the `skipped_groups` of the results.
+pub const NOT_RUN: &str = "not run (fail-fast)";
+
+/// The verdict of the gate on the comparisons that the groups of `data` decide, before the
+/// other groups of `config` ran. `None` without a gate.
+pub fn early_verdict(
+    config: &Config,
+    data: &BenchData,
+    prev_results: &BenchData,
+    rolling_entries: Option<&[&BenchData]>,
+) -> Option<GateVerdict> {
+    config.gate.as_ref()?;
+
+    let mut comparisons = Comparisons::collect(config, data, Some(prev_results));
+    let pending = pending_comparisons(config, data, &comparisons);
+    let cutoff = comparisons.cutoff_with_pending(config.correction, pending);
+    if let Some(cutoff) = cutoff {
+        comparisons.apply_cutoff(cutoff);
+    }
+    if let (Some(rolling_config), Some(entries)) = (&config.rolling_baseline, rolling_entries) {
+        rolling_config.apply(&mut comparisons, config, data, entries, cutoff);
+    }
+    config.evaluate_gate(&comparisons, &data.exemptions)
+}
+
+/// How many comparisons the groups that didn't run yet will add, at most: a raw row per
+/// counter of each of their commands, and the `render-versus-self` rows that can't be
+/// computed yet, but for those of `informational` tables. The counters of a command are
+/// unknown before it runs, so this assumes none has more than the most of those that ran.
+fn pending_comparisons(config: &Config, data: &BenchData, comparisons: &Comparisons) -> usize {
+    let commands = config
+        .commands
+        .iter()
+        .filter(|(group_name, _)| !data.bench_groups.contains_key(*group_name))
+        .map(|(_, benches)| benches.len())
+        .sum::<usize>();
+    let counters = data
+        .bench_groups
+        .values()
+        .flatten()
+        .map(|bench| bench.counters.len())
+        .max()
+        .unwrap_or_default();
+
+    let versus_self = config
+        .render_versus_self
+        .values()
+        .filter(|table| !table.display.informational)
+        .map(|table| table.rows.len())
+        .sum::<usize>();
+    let computed = compare::counted_rows(&comparisons.versus_self).count();
+
+    commands * counters + versus_self.saturating_sub(computed)
+}
+
+/// Mark `data` as partial, with the groups of `config` that didn't run as skipped, and drop
+/// them from `config`, like the groups `--changed-only` skips. Returns those groups.
+pub fn mark_partial(config: &mut Config, data: &mut BenchData) -> Vec<String> {
+    let not_run = config
+        .commands
+        .keys()
+        .filter(|group_name| !data.bench_groups.contains_key(*group_name))
+        .map(|group_name| (group_name.clone(), NOT_RUN.to_owned()))
+        .collect::<IndexMap<_, _>>();
+
+    data.partial = true;
+    data.skipped_groups.extend(not_run.clone());
+    config.skip_groups(&not_run);
+    not_run.into_keys().collect()
+}
+
+#[cfg(test)]
+fn config_for_test(gate: &str) -> Config {
+    serde_json::from_str(&format!(
+        r#"{{
+            "commands": {{
+                "compress": ["./c 1", "./c 2"],
+                "decompress": ["./d 1", "./d 2"]
+            }},
+            {gate}
+            "render-versus-self": {{
+                "levels": {{
+                    "1 vs 2": {{ "measure": "cycles", "before": {{ "command": "compress", "index": 0 }}, "after": {{ "command": "decompress", "index": 0 }} }}
+                }}
+            }},
+            "render-versus-other": {{
+                "compression": {{ "measure": "cycles", "command": "compress", "rows": {{ "level 1": 0, "level 2": 1 }} }},
+                "decompression": {{ "measure": "cycles", "command": "decompress", "rows": {{ "level 1": 0, "level 2": 1 }} }}
+            }}
+        }}"#
+    ))
+    .unwrap()
+}
+
+#[cfg(test)]
+fn prev_results_for_test() -> BenchData {
+    crate::testkit::BenchDataBuilder::cycles(
+        "1111111111111111111111111111111111111111",
+        &[
+            ("compress", &[("./c 1", 1000.0), ("./c 2", 1000.0)]),
+            ("decompress", &[("./d 1", 1000.0), ("./d 2", 1000.0)]),
+        ],
+    )
+    .build()
+}
+
+#[cfg(test)]
+fn failed_rows(verdict: &GateVerdict) -> Vec<(&str, &str)> {
+    verdict
+        .failures
+        .iter()
+        .map(|failure| (failure.table.as_str(), failure.row.name.as_str()))
+        .collect()
+}
+
+#[test]
+fn gate_on_groups_measured_so_far() {
+    let config = config_for_test(r#""gate": { "max-regression-percent": 5 },"#);
+    let prev_results = prev_results_for_test();
+
+    // Only the first group ran, and regressed.
+    let data = crate::testkit::BenchDataBuilder::cycles(
+        "2222222222222222222222222222222222222222",
+        &[("compress", &[("./c 1", 1200.0), ("./c 2", 1000.0)])],
+    )
+    .build();
+    let verdict = early_verdict(&config, &data, &prev_results, None).unwrap();
+    assert_eq!(failed_rows(&verdict), [("compression", "level 1")]);
+
+    // Without a regression, the gate passes so far.
+    let data = crate::testkit::BenchDataBuilder::cycles(
+        "2222222222222222222222222222222222222222",
+        &[("compress", &[("./c 1", 1000.0), ("./c 2", 1000.0)])],
+    )
+    .build();
+    assert!(early_verdict(&config, &data, &prev_results, None)
+        .unwrap()
+        .passed());
+
+    // Nothing to decide without a gate.
+    let config = config_for_test("");
+    assert!(early_verdict(&config, &data, &prev_results, None).is_none());
+}
+
+#[test]
+fn correct_for_pending_comparisons() {
+    let config = config_for_test(
+        r#""gate": { "max-regression-percent": 0.5 }, "correction": "bonferroni","#,
+    );
+    let prev_results = prev_results_for_test();
+    // +0.8%, with a p-value of about 0.016.
+    let data = crate::testkit::BenchDataBuilder::cycles(
+        "2222222222222222222222222222222222222222",
+        &[("compress", &[("./c 1", 1008.0), ("./c 2", 1000.0)])],
+    )
+    .build();
+    let comparisons = Comparisons::collect(&config, &data, Some(&prev_results));
+    // Two commands with a counter each still to run, and the row of `1 vs 2`.
+    assert_eq!(pending_comparisons(&config, &data, &comparisons), 3);
+    // Significant with the two comparisons so far, but not with all five.
+    assert_eq!(comparisons.cutoff(config.correction), Some(0.025));
+    assert_eq!(
+        comparisons.cutoff_with_pending(config.correction, 3),
+        Some(0.01)
+    );
+    assert!(comparisons.versus_other[0].rows[0].significant);
+    assert!(early_verdict(&config, &data, &prev_results, None)
+        .unwrap()
+        .passed());
+
+    // Benjamini-Hochberg is bounded by Bonferroni while comparisons are pending.
+    let config = config_for_test(
+        r#""gate": { "max-regression-percent": 0.5 }, "correction": "benjamini-hochberg","#,
+    );
+    assert!(early_verdict(&config, &data, &prev_results, None)
+        .unwrap()
+        .passed());
+
+    // A clear regression fails whatever the comparisons to come.
+    let data = crate::testkit::BenchDataBuilder::cycles(
+        "2222222222222222222222222222222222222222",
+        &[("compress", &[("./c 1", 1200.0), ("./c 2", 1000.0)])],
+    )
+    .build();
+    let verdict = early_verdict(&config, &data, &prev_results, None).unwrap();
+    assert_eq!(failed_rows(&verdict), [("compression", "level 1")]);
+}
+
+#[test]
+fn mark_partial_results() {
+    let mut config = config_for_test(r#""gate": { "max-regression-percent": 5 },"#);
+    let prev_results = prev_results_for_test();
+    let mut data = crate::testkit::BenchDataBuilder::cycles(
+        "2222222222222222222222222222222222222222",
+        &[("compress", &[("./c 1", 1200.0), ("./c 2", 1000.0)])],
+    )
+    .build();
+    let json = serde_json::to_value(&data).unwrap();
+    assert!(json.get("partial").is_none());
+
+    assert_eq!(mark_partial(&mut config, &mut data), ["decompress"]);
+    assert!(data.partial);
+    assert_eq!(data.skipped_groups["decompress"], NOT_RUN);
+    let json = serde_json::to_value(&data).unwrap();
+    assert_eq!(json["partial"], true);
+    assert_eq!(
+        json["skipped_groups"],
+        serde_json::json!({ "decompress": "not run (fail-fast)" })
+    );
+
+    // The rows of the groups that didn't run are reported as unmeasured.
+    assert_eq!(
+        config.render_versus_other.keys().collect::<Vec<_>>(),
+        ["compression"]
+    );
+    assert_eq!(
+        config.unmeasured_rows["decompression"],
+        ["level 1", "level 2"]
+    );
+    assert_eq!(config.unmeasured_rows["levels"], ["1 vs 2"]);
+    let mut md = String::new();
+    crate::changed::render_markdown(&mut md, &data.skipped_groups, &config.unmeasured_rows);
+    assert_eq!(
+        md,
+        "### Not measured in this run\n\n\
+         Not run, `--fail-fast` stopped the run when the gate failed:\n\n\
+         - `decompress`\n\n\
+         Missing from the comparisons:\n\n\
+         - decompression: `level 1`, `level 2`\n\
+         - levels: `1 vs 2`\n\n"
+    );
+
+    // The regular gate at the end of the run fails on the same rows as the early one.
+    let comparisons = Comparisons::collect(&config, &data, Some(&prev_results));
+    let verdict = config.evaluate_gate(&comparisons, &[]).unwrap();
+    assert_eq!(failed_rows(&verdict), [("compression", "level 1")]);
+}
+
+#[test]
+fn final_gate_of_complete_run() {
+    // Without `--fail-fast`, the regular gate sees every group, the regressed one included.
+    let config = config_for_test(r#""gate": { "max-regression-percent": 5 },"#);
+    let prev_results = prev_results_for_test();
+    let data = crate::testkit::BenchDataBuilder::cycles(
+        "2222222222222222222222222222222222222222",
+        &[
+            ("compress", &[("./c 1", 1200.0), ("./c 2", 1000.0)]),
+            ("decompress", &[("./d 1", 1000.0), ("./d 2", 1300.0)]),
+        ],
+    )
+    .build();
+    let comparisons = Comparisons::collect(&config, &data, Some(&prev_results));
+    let verdict = config.evaluate_gate(&comparisons, &[]).unwrap();
+    assert_eq!(
+        failed_rows(&verdict),
+        [("compression", "level 1"), ("decompression", "level 2")]
+    );
+    assert_eq!(comparisons.versus_self[0].rows.len(), 1);
+
+    // Once all groups ran, there is nothing pending, and the early verdict is the final one.
+    assert_eq!(pending_comparisons(&config, &data, &comparisons), 0);
+    let early = early_verdict(&config, &data, &prev_results, None).unwrap();
+    assert_eq!(failed_rows(&early), failed_rows(&verdict));
+}
@@ -0,0 +1,205 @@
+//! The hashes of the benchmarked binaries. When they are the same as those of the baseline,
+//! the build step most likely didn't run and both sides benchmarked the same artifacts, which
+//! looks just like a change without any effect.
+
+use std::fmt::Write;
+use std::fs::OpenOptions;
+use std::path::{Path, PathBuf};
+
+use indexmap::IndexMap;
+use serde::Deserialize;
+
+use crate::sha256;
+
+/// The name of the output in `GITHUB_OUTPUT`.
+pub const OUTPUT: &str = "identical-binaries";
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct FingerprintConfig {
+    /// The binaries to hash.
+    pub files: Vec<PathBuf>,
+    /// The binaries among `files` that aren't under test, like a C reference implementation.
+    /// They are expected to stay the same.
+    #[serde(default)]
+    pub control: Vec<PathBuf>,
+}
+
+impl FingerprintConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        for control in &self.control {
+            if !self.files.contains(control) {
+                return Err(format!(
+                    "the control binary {} is not one of the fingerprinted `files`",
+                    control.display()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// The SHA-256 of every file, by path. Files that can't be read are left out with a
+    /// warning.
+    pub fn hash(&self) -> IndexMap<String, String> {
+        let mut hashes = IndexMap::new();
+        for file in &self.files {
+            match sha256::file_hex(file) {
+                Ok(hash) => {
+                    hashes.insert(file.display().to_string(), hash);
+                }
+                Err(err) => eprintln!("warning: not fingerprinting {}: {err}", file.display()),
+            }
+        }
+        hashes
+    }
+
+    /// Whether every binary under test has the same hash in both results. Without hashes of
+    /// all of them on both sides, nothing can be said.
+    pub fn identical(
+        &self,
+        baseline: &IndexMap<String, String>,
+        current: &IndexMap<String, String>,
+    ) -> bool {
+        let mut under_test = self
+            .files
+            .iter()
+            .filter(|file| !self.control.contains(file))
+            .map(|file| file.display().to_string())
+            .peekable();
+        under_test.peek().is_some()
+            && under_test.all(|file| {
+                matches!(
+                    (baseline.get(&file), current.get(&file)),
+                    (Some(before), Some(after)) if before == after
+                )
+            })
+    }
+}
+
+pub fn render_markdown_warning(md: &mut String, identical: bool) {
+    if identical {
+        writeln!(
+            md,
+            "> [!WARNING]\n> ⚠️ benchmark binaries are byte-identical to the baseline — did the build step run?\n"
+        )
+        .unwrap();
+    }
+}
+
+/// Append the flag to the `GITHUB_OUTPUT` file of the step.
+pub fn write_github_output(path: &Path, identical: bool) -> Result<(), String> {
+    use std::io::Write;
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("failed to open {}: {e}", path.display()))?;
+    writeln!(file, "{OUTPUT}={identical}")
+        .map_err(|e| format!("failed to write {}: {e}", path.display()))
+}
+
+#[cfg(test)]
+fn hashes(hashes: &[(&str, &str)]) -> IndexMap<String, String> {
+    hashes
+        .iter()
+        .map(|&(file, hash)| (file.to_owned(), hash.to_owned()))
+        .collect()
+}
+
+#[test]
+fn identical_binaries() {
+    let config: FingerprintConfig = serde_json::from_str(
+        r#"{ "files": ["target/release/compress", "target/release/decompress", "zlib-ng/minigzip"], "control": ["zlib-ng/minigzip"] }"#,
+    )
+    .unwrap();
+    config.validate().unwrap();
+
+    let baseline = hashes(&[
+        ("target/release/compress", "aa"),
+        ("target/release/decompress", "bb"),
+        ("zlib-ng/minigzip", "cc"),
+    ]);
+    assert!(config.identical(&baseline, &baseline));
+
+    // Partially identical: only one binary got rebuilt.
+    let current = hashes(&[
+        ("target/release/compress", "aa"),
+        ("target/release/decompress", "dd"),
+        ("zlib-ng/minigzip", "cc"),
+    ]);
+    assert!(!config.identical(&baseline, &current));
+
+    // The control binary is expected to stay the same, and is all that did.
+    let current = hashes(&[
+        ("target/release/compress", "ee"),
+        ("target/release/decompress", "dd"),
+        ("zlib-ng/minigzip", "cc"),
+    ]);
+    assert!(!config.identical(&baseline, &current));
+    // And doesn't matter when it changed.
+    let current = hashes(&[
+        ("target/release/compress", "aa"),
+        ("target/release/decompress", "bb"),
+        ("zlib-ng/minigzip", "ff"),
+    ]);
+    assert!(config.identical(&baseline, &current));
+
+    // No hashes in the baseline, from before fingerprinting, or a binary missing on one side.
+    assert!(!config.identical(&IndexMap::new(), &baseline));
+    assert!(!config.identical(&baseline, &IndexMap::new()));
+    let partial = hashes(&[("target/release/compress", "aa")]);
+    assert!(!config.identical(&partial, &baseline));
+
+    // Only control binaries.
+    let control_only: FingerprintConfig = serde_json::from_str(
+        r#"{ "files": ["zlib-ng/minigzip"], "control": ["zlib-ng/minigzip"] }"#,
+    )
+    .unwrap();
+    assert!(!control_only.identical(&baseline, &baseline));
+
+    let invalid: FingerprintConfig =
+        serde_json::from_str(r#"{ "files": ["a"], "control": ["b"] }"#).unwrap();
+    assert_eq!(
+        invalid.validate().unwrap_err(),
+        "the control binary b is not one of the fingerprinted `files`"
+    );
+}
+
+#[test]
+fn hash_binaries() {
+    let dir = crate::testkit::test_dir("fingerprint");
+    std::fs::write(dir.join("compress"), "abc").unwrap();
+    let config = FingerprintConfig {
+        files: vec![dir.join("compress"), dir.join("missing")],
+        control: vec![],
+    };
+    assert_eq!(
+        config.hash(),
+        hashes(&[(
+            &dir.join("compress").display().to_string(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        )])
+    );
+}
+
+#[test]
+fn identical_binaries_output() {
+    let mut md = String::new();
+    render_markdown_warning(&mut md, false);
+    assert_eq!(md, "");
+    render_markdown_warning(&mut md, true);
+    assert_eq!(
+        md,
+        "> [!WARNING]\n> ⚠️ benchmark binaries are byte-identical to the baseline — did the build step run?\n\n"
+    );
+
+    let dir = crate::testkit::test_dir("fingerprint-github-output");
+    let path = dir.join("output");
+    std::fs::write(&path, "other=1\n").unwrap();
+    write_github_output(&path, true).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "other=1\nidentical-binaries=true\n"
+    );
+}
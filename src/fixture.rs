@@ -0,0 +1,307 @@
+//! Files the benchmarks need, like a corpus to compress. They are downloaded and verified
+//! before the first benchmark runs, so a changed download can't silently shift the results.
+
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde::Deserialize;
+
+use crate::{http, sha256};
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct FixtureConfig {
+    pub url: String,
+    /// The SHA-256 of the downloaded file, in hex.
+    pub sha256: String,
+    /// Where to store the downloaded file. Archives are extracted into the same directory.
+    pub path: PathBuf,
+    #[serde(default)]
+    pub extract: Option<Extract>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Extract {
+    /// A tarball, optionally compressed.
+    Tar,
+    /// A single gzipped file, extracted to the path without the `.gz` extension.
+    Gz,
+    Zip,
+}
+
+impl FixtureConfig {
+    /// Check what the types of the config can't express.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.sha256.len() != 64 || !self.sha256.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(format!(
+                "the sha256 of the fixture `{}` is not 64 hex digits",
+                self.path.display()
+            ));
+        }
+        if self.extract == Some(Extract::Gz) && self.path.extension() != Some("gz".as_ref()) {
+            return Err(format!(
+                "the fixture `{}` is extracted with gz, but doesn't end in `.gz`",
+                self.path.display()
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn expected_sha256(&self) -> String {
+        self.sha256.to_ascii_lowercase()
+    }
+
+    /// Make sure the fixture is in place with the right hash, downloading and extracting it
+    /// as needed. Returns the verified hash.
+    pub fn ensure(&self) -> Result<String, String> {
+        let expected = self.expected_sha256();
+
+        if self.path.exists() && sha256::file_hex(&self.path)? == expected {
+            eprintln!("fixture {}: up to date", self.path.display());
+        } else {
+            self.download()?;
+        }
+
+        if let Some(extract) = self.extract {
+            // The stamp records what was extracted, so a valid fixture isn't extracted again.
+            let stamp = with_suffix(&self.path, ".extracted");
+            if fs::read_to_string(&stamp).ok().as_deref() != Some(expected.as_str()) {
+                eprintln!("fixture {}: extracting", self.path.display());
+                self.extract(extract)?;
+                fs::write(&stamp, &expected)
+                    .map_err(|e| format!("failed to write {}: {e}", stamp.display()))?;
+            }
+        }
+
+        Ok(expected)
+    }
+
+    /// Download the fixture, retrying once from scratch. It only gets moved into place once
+    /// its hash is verified.
+    fn download(&self) -> Result<(), String> {
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("failed to create {}: {e}", dir.display()))?;
+        }
+
+        // Resumes a download that was interrupted in an earlier run.
+        let partial = with_suffix(&self.path, ".part");
+        eprintln!("fixture {}: downloading {}", self.path.display(), self.url);
+        if let Err(err) = http::download(&self.url, &partial) {
+            eprintln!("warning: {err}, retrying");
+            let _ = fs::remove_file(&partial);
+            http::download(&self.url, &partial)?;
+        }
+
+        let actual = sha256::file_hex(&partial)?;
+        if actual != self.expected_sha256() {
+            let _ = fs::remove_file(&partial);
+            return Err(format!(
+                "refusing to use the fixture `{}`: {} has sha256 {actual}, expected {}",
+                self.path.display(),
+                self.url,
+                self.expected_sha256(),
+            ));
+        }
+
+        fs::rename(&partial, &self.path)
+            .map_err(|e| format!("failed to move {} into place: {e}", partial.display()))
+    }
+
+    fn extract(&self, extract: Extract) -> Result<(), String> {
+        let dir = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+
+        let mut cmd = match extract {
+            Extract::Tar => {
+                let mut cmd = Command::new("tar");
+                cmd.arg("-xf").arg(&self.path).arg("-C").arg(dir);
+                cmd
+            }
+            Extract::Gz => {
+                let output = self.path.with_extension("");
+                let file = File::create(&output)
+                    .map_err(|e| format!("failed to create {}: {e}", output.display()))?;
+                let mut cmd = Command::new("gzip");
+                cmd.arg("-dc").arg(&self.path).stdout(file);
+                cmd
+            }
+            Extract::Zip => {
+                let mut cmd = Command::new("unzip");
+                cmd.arg("-o").arg("-q").arg(&self.path).arg("-d").arg(dir);
+                cmd
+            }
+        };
+
+        let program = cmd.get_program().to_string_lossy().into_owned();
+        let status = cmd
+            .status()
+            .map_err(|e| format!("failed to run {program}: {e}"))?;
+        if !status.success() {
+            return Err(format!(
+                "failed to extract {} with {program}: {status}",
+                self.path.display()
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+#[cfg(test)]
+fn fixture_for_test(url: &str, path: PathBuf, contents: &[u8]) -> FixtureConfig {
+    let mut hasher = sha256::Sha256::default();
+    hasher.update(contents);
+    FixtureConfig {
+        url: url.to_owned(),
+        sha256: hasher.finish_hex(),
+        path,
+        extract: None,
+    }
+}
+
+#[test]
+fn download_verify_and_skip() {
+    use crate::http::{serve, TestResponse};
+
+    let dir = crate::testkit::test_dir("fixture-download");
+    let (url, server) = serve(vec![
+        TestResponse::new(200, b"corpus"),
+        TestResponse::new(200, b"changed corpus"),
+    ]);
+
+    let fixture = fixture_for_test(&format!("{url}/corpus"), dir.join("data/corpus"), b"corpus");
+    fixture.validate().unwrap();
+    assert_eq!(fixture.ensure().unwrap(), fixture.sha256);
+    assert_eq!(fs::read(dir.join("data/corpus")).unwrap(), b"corpus");
+
+    // A changed download is refused.
+    let fixture = FixtureConfig {
+        path: dir.join("other"),
+        ..fixture
+    };
+    let err = fixture.ensure().unwrap_err();
+    assert!(err.contains("refusing"), "{err}");
+    assert!(!dir.join("other").exists());
+    assert!(!dir.join("other.part").exists());
+
+    let requests = server.join().unwrap();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0].request_line, "GET /corpus HTTP/1.1");
+
+    // Valid files aren't downloaded again, the server is gone by now.
+    let fixture = FixtureConfig {
+        path: dir.join("data/corpus"),
+        url: format!("{url}/gone"),
+        ..fixture
+    };
+    assert_eq!(fixture.ensure().unwrap(), fixture.sha256);
+
+    let invalid = FixtureConfig {
+        sha256: "abc".to_owned(),
+        ..fixture
+    };
+    assert!(invalid.validate().is_err());
+}
+
+#[test]
+fn resume_and_retry_download() {
+    use crate::http::{serve, TestResponse};
+
+    let dir = crate::testkit::test_dir("fixture-resume");
+    let (url, server) = serve(vec![
+        TestResponse {
+            status: 206,
+            headers: vec![("Content-Range", "bytes 5-9/10".to_owned())],
+            body: b"56789".to_vec(),
+        },
+        TestResponse::new(500, b"oops"),
+        TestResponse::new(200, b"0123456789"),
+    ]);
+
+    // An earlier download stopped halfway.
+    let fixture = fixture_for_test(&url, dir.join("digits"), b"0123456789");
+    fs::write(dir.join("digits.part"), b"01234").unwrap();
+    fixture.ensure().unwrap();
+    assert_eq!(fs::read(dir.join("digits")).unwrap(), b"0123456789");
+
+    let fixture = FixtureConfig {
+        path: dir.join("retried"),
+        ..fixture
+    };
+    fixture.ensure().unwrap();
+    assert_eq!(fs::read(dir.join("retried")).unwrap(), b"0123456789");
+
+    let requests = server.join().unwrap();
+    assert!(requests[0]
+        .headers
+        .contains(&("range".to_owned(), "bytes=5-".to_owned())));
+    assert!(!requests[2].headers.iter().any(|(name, _)| name == "range"));
+}
+
+#[test]
+fn extract_fixtures() {
+    use crate::http::{serve, TestResponse};
+
+    let dir = crate::testkit::test_dir("fixture-extract");
+    let src = dir.join("src");
+    fs::create_dir_all(&src).unwrap();
+    fs::write(src.join("a.txt"), "from the archive").unwrap();
+
+    let run = |cmd: &mut Command| assert!(cmd.current_dir(&src).status().unwrap().success());
+    run(Command::new("tar").args(["-czf", "../a.tar.gz", "a.txt"]));
+    run(Command::new("zip").args(["-q", "../a.zip", "a.txt"]));
+    run(Command::new("gzip").args(["-k", "a.txt"]));
+
+    let archives = [
+        ("a.tar.gz", Extract::Tar, "tar/a.txt"),
+        ("a.zip", Extract::Zip, "zip/a.txt"),
+        ("src/a.txt.gz", Extract::Gz, "gz/a.txt"),
+    ];
+    let (url, server) = serve(
+        archives
+            .iter()
+            .map(|(archive, ..)| TestResponse::new(200, &fs::read(dir.join(archive)).unwrap()))
+            .collect(),
+    );
+
+    for (archive, extract, extracted) in archives {
+        let file_name = Path::new(archive).file_name().unwrap();
+        let target = dir.join(extracted).with_file_name(file_name);
+        let fixture = FixtureConfig {
+            extract: Some(extract),
+            ..fixture_for_test(&url, target, &fs::read(dir.join(archive)).unwrap())
+        };
+        fixture.validate().unwrap();
+        fixture.ensure().unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join(extracted)).unwrap(),
+            "from the archive"
+        );
+
+        // Extracted once per hash.
+        fs::write(dir.join(extracted), "edited").unwrap();
+        fixture.ensure().unwrap();
+        assert_eq!(fs::read_to_string(dir.join(extracted)).unwrap(), "edited");
+    }
+
+    assert_eq!(server.join().unwrap().len(), 3);
+
+    let fixture = FixtureConfig {
+        extract: Some(Extract::Gz),
+        ..fixture_for_test(&url, dir.join("a.tar"), b"")
+    };
+    assert!(fixture.validate().is_err());
+}
@@ -0,0 +1,297 @@
+//! Flushing the CPU caches between the runs of interleaved commands, with
+//! `flush-between-for-group`. When the compared commands share library code, whichever runs
+//! second finds the caches and the branch predictor warmed up by the first, which biases the
+//! comparison. An unmeasured step between the runs of different commands disrupts them:
+//! touching a buffer larger than the last level cache, or a command of your own.
+//!
+//! ```json
+//! "interleave-for-group": { "compress": true },
+//! "flush-between-for-group": { "compress": {}, "decompress": { "command": "./thrash-caches" } }
+//! ```
+//!
+//! The buffer is twice the size of the last level cache, as detected with `lscpu` or sysfs,
+//! unless `buffer-mb` sets it. Groups that aren't interleaved are never flushed. The time spent
+//! flushing is recorded in the run report, apart from the measurements.
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::units;
+
+/// The stride of the buffer touch, the cache line size of every CPU we run on.
+const CACHE_LINE: usize = 64;
+
+/// The buffer size when the size of the last level cache is unknown.
+const FALLBACK_BUFFER: u64 = 64 << 20;
+
+/// Where the sizes of the caches of the first CPU are in sysfs.
+const SYSFS_CACHES: &str = "/sys/devices/system/cpu/cpu0/cache";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct FlushConfig {
+    /// The size of the buffer to touch, by default twice the last level cache.
+    #[serde(
+        rename = "buffer-mb",
+        default,
+        deserialize_with = "units::option_mebibytes"
+    )]
+    pub buffer: Option<u64>,
+    /// A command to run instead of touching a buffer.
+    #[serde(default)]
+    pub command: Option<String>,
+}
+
+impl FlushConfig {
+    pub fn validate(&self, group_name: &str) -> Result<(), String> {
+        match (&self.buffer, &self.command) {
+            (Some(_), Some(_)) => Err(format!(
+                "`flush-between-for-group.{group_name}` has both `buffer-mb` and `command`, a flush either touches a buffer or runs a command"
+            )),
+            (None, Some(command)) if command.trim().is_empty() => Err(format!(
+                "`flush-between-for-group.{group_name}.command` is empty"
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The time spent flushing the caches during a group, recorded in the run report.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct FlushTime {
+    pub flushes: u32,
+    pub secs: f64,
+}
+
+/// What disrupts the caches.
+#[derive(Debug)]
+enum Disruption {
+    Buffer(Vec<u8>),
+    Command(Vec<String>),
+}
+
+#[derive(Debug)]
+pub struct Flusher {
+    disruption: Disruption,
+    pub flushes: u32,
+    pub spent: Duration,
+}
+
+impl Flusher {
+    pub fn new(config: &FlushConfig) -> Self {
+        let disruption = match &config.command {
+            Some(command) => {
+                Disruption::Command(command.split_whitespace().map(str::to_owned).collect())
+            }
+            None => {
+                let size = config.buffer.unwrap_or_else(|| match detect_llc_size() {
+                    Some(llc) => llc * 2,
+                    None => {
+                        eprintln!(
+                            "warning: the size of the last level cache is unknown, flushing with a buffer of {} MiB",
+                            FALLBACK_BUFFER >> 20
+                        );
+                        FALLBACK_BUFFER
+                    }
+                });
+                Disruption::Buffer(vec![0; size as usize])
+            }
+        };
+        Flusher {
+            disruption,
+            flushes: 0,
+            spent: Duration::ZERO,
+        }
+    }
+
+    /// Disrupt the caches, never measured.
+    pub fn flush(&mut self) -> Result<(), String> {
+        let start = Instant::now();
+        match &mut self.disruption {
+            Disruption::Buffer(buffer) => touch(buffer),
+            Disruption::Command(argv) => {
+                let status = Command::new(&argv[0])
+                    .args(&argv[1..])
+                    .status()
+                    .map_err(|e| format!("failed to run the flush command `{}`: {e}", argv[0]))?;
+                if !status.success() {
+                    return Err(format!(
+                        "the flush command `{}` failed with {status}",
+                        argv.join(" ")
+                    ));
+                }
+            }
+        }
+        self.spent += start.elapsed();
+        self.flushes += 1;
+        Ok(())
+    }
+
+    pub fn time(&self) -> FlushTime {
+        FlushTime {
+            flushes: self.flushes,
+            secs: self.spent.as_secs_f64(),
+        }
+    }
+}
+
+/// Write a byte of every cache line of `buffer`, so the caches hold its lines rather than
+/// those of the commands. Writing rather than reading also evicts the lines from the caches of
+/// the other cores.
+fn touch(buffer: &mut [u8]) {
+    for byte in buffer.iter_mut().step_by(CACHE_LINE) {
+        *byte = byte.wrapping_add(1);
+    }
+    std::hint::black_box(buffer);
+}
+
+/// The size of the last level cache of the current machine, from `lscpu`, falling back to
+/// sysfs.
+pub fn detect_llc_size() -> Option<u64> {
+    if !cfg!(target_os = "linux") {
+        return None;
+    }
+    Command::new("lscpu")
+        .env("LANG", "C")
+        .args(["-C", "-J", "-B"])
+        .output()
+        .ok()
+        .and_then(|output| llc_size_from_lscpu(&output.stdout))
+        .or_else(|| llc_size_from_sysfs(Path::new(SYSFS_CACHES)))
+}
+
+/// A cache size like `49152` with `-B`, or `32K` or `1.5M` without it.
+fn parse_cache_size(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let (number, scale) = match size.char_indices().last()? {
+        (at, 'K') => (&size[..at], 1u64 << 10),
+        (at, 'M') => (&size[..at], 1 << 20),
+        (at, 'G') => (&size[..at], 1 << 30),
+        _ => (size, 1),
+    };
+    let number = number.parse::<f64>().ok()?;
+    (number > 0.0).then_some((number * scale as f64) as u64)
+}
+
+/// The size of the highest level data or unified cache in the output of `lscpu -C -J`, of a
+/// single instance of it.
+pub fn llc_size_from_lscpu(json: &[u8]) -> Option<u64> {
+    let json = serde_json::from_slice::<serde_json::Value>(json).ok()?;
+    json["caches"]
+        .as_array()?
+        .iter()
+        .filter(|cache| cache["type"].as_str() != Some("Instruction"))
+        .filter_map(|cache| {
+            let level = cache["level"].as_u64()?;
+            let size = parse_cache_size(cache["one-size"].as_str()?)?;
+            Some((level, size))
+        })
+        .max()
+        .map(|(_, size)| size)
+}
+
+/// The size of the highest level data or unified cache in a directory like
+/// `/sys/devices/system/cpu/cpu0/cache`.
+pub fn llc_size_from_sysfs(dir: &Path) -> Option<u64> {
+    let read = |path: &Path| Some(fs::read_to_string(path).ok()?.trim().to_owned());
+    fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with("index"))
+        .filter_map(|entry| {
+            let path = entry.path();
+            if read(&path.join("type"))? == "Instruction" {
+                return None;
+            }
+            let level = read(&path.join("level"))?.parse::<u64>().ok()?;
+            let size = parse_cache_size(&read(&path.join("size"))?)?;
+            Some((level, size))
+        })
+        .max()
+        .map(|(_, size)| size)
+}
+
+#[cfg(test)]
+fn cache_fixture(name: &str) -> std::path::PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("testdata/cache")
+        .join(name)
+}
+
+#[test]
+fn llc_size_from_lscpu_caches() {
+    let llc = |name: &str| llc_size_from_lscpu(&fs::read(cache_fixture(name)).unwrap());
+    // With `-B`, in bytes.
+    assert_eq!(llc("intel-xeon-vm.json"), Some(300 << 20));
+    // Without, as by versions of lscpu that ignore it.
+    assert_eq!(llc("amd-zen3.json"), Some(32 << 20));
+    // Without an L3, the L2 is the last level.
+    assert_eq!(llc("arm-neoverse-n1.json"), Some(1 << 20));
+
+    assert_eq!(llc_size_from_lscpu(b"garbage"), None);
+    assert_eq!(llc_size_from_lscpu(br#"{"caches": []}"#), None);
+
+    assert_eq!(parse_cache_size("1.5M"), Some(3 << 19));
+    assert_eq!(parse_cache_size("0K"), None);
+    assert_eq!(parse_cache_size("lots"), None);
+}
+
+#[test]
+fn llc_size_from_sysfs_caches() {
+    assert_eq!(
+        llc_size_from_sysfs(&cache_fixture("sysfs-amd-zen3")),
+        Some(32 << 20)
+    );
+    // A cache without a size is skipped.
+    assert_eq!(llc_size_from_sysfs(&cache_fixture("sysfs-no-size")), None);
+    assert_eq!(llc_size_from_sysfs(&cache_fixture("does-not-exist")), None);
+}
+
+#[test]
+fn touch_every_cache_line() {
+    let mut flusher = Flusher::new(&FlushConfig {
+        buffer: Some(1 << 10),
+        command: None,
+    });
+    flusher.flush().unwrap();
+    flusher.flush().unwrap();
+    let Disruption::Buffer(buffer) = &flusher.disruption else {
+        unreachable!()
+    };
+    assert_eq!(buffer.len(), 1 << 10);
+    assert!(buffer
+        .iter()
+        .enumerate()
+        .all(|(at, &byte)| byte == if at % CACHE_LINE == 0 { 2 } else { 0 }));
+    assert_eq!(flusher.time().flushes, 2);
+
+    // A failing command fails the flush, and isn't counted.
+    let mut flusher = Flusher::new(&FlushConfig {
+        buffer: None,
+        command: Some("false".to_owned()),
+    });
+    assert_eq!(
+        flusher.flush().unwrap_err(),
+        "the flush command `false` failed with exit status: 1"
+    );
+    assert_eq!(flusher.flushes, 0);
+}
+
+#[test]
+fn flush_config() {
+    let config = serde_json::from_str::<FlushConfig>(r#"{ "buffer-mb": "16MiB" }"#).unwrap();
+    assert_eq!(config.buffer, Some(16 << 20));
+    assert!(config.validate("compress").is_ok());
+
+    let config =
+        serde_json::from_str::<FlushConfig>(r#"{ "buffer-mb": 16, "command": "./thrash" }"#)
+            .unwrap();
+    assert_eq!(
+        config.validate("compress").unwrap_err(),
+        "`flush-between-for-group.compress` has both `buffer-mb` and `command`, a flush either touches a buffer or runs a command"
+    );
+}
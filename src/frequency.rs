@@ -0,0 +1,294 @@
+//! The clock frequency of the CPU. Cycle counts are comparable between machines of the same
+//! microarchitecture, but times are not when the machines run at different frequencies.
+
+use std::collections::BTreeMap;
+use std::fmt::{Display, Write};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bench::BenchCounter;
+
+/// The name of the derived counter with the cycles converted to time at the nominal frequency.
+pub const NORMALIZED_TIME: &str = "normalized-time";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CpuFrequency {
+    /// The nominal (base) frequency in MHz.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nominal_mhz: Option<f64>,
+    /// The maximum (boost) frequency in MHz.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_mhz: Option<f64>,
+}
+
+impl CpuFrequency {
+    /// Detect the frequency of the current machine from `lscpu`, falling back to cpufreq in
+    /// sysfs. `None` when neither knows anything about it.
+    pub fn detect() -> Option<Self> {
+        if !cfg!(target_os = "linux") {
+            return None;
+        }
+
+        let lscpu = Command::new("lscpu")
+            .env("LANG", "C")
+            .arg("-J")
+            .output()
+            .map(|output| Self::from_lscpu(&output.stdout))
+            .unwrap_or_default();
+        let sysfs = Self::from_sysfs(Path::new("/sys/devices/system/cpu/cpu0/cpufreq"));
+
+        let frequency = CpuFrequency {
+            nominal_mhz: lscpu.nominal_mhz.or(sysfs.nominal_mhz),
+            max_mhz: lscpu.max_mhz.or(sysfs.max_mhz),
+        };
+        (frequency != CpuFrequency::default()).then_some(frequency)
+    }
+
+    /// Parse the output of `lscpu -J`.
+    pub fn from_lscpu(json: &[u8]) -> Self {
+        let fields = lscpu_fields(json);
+        CpuFrequency {
+            nominal_mhz: fields
+                .get("Model name")
+                .and_then(|model| frequency_from_model_name(model)),
+            max_mhz: fields.get("CPU max MHz").and_then(|mhz| mhz.parse().ok()),
+        }
+    }
+
+    /// Read the frequencies from a cpufreq directory, which reports them in kHz. Only some
+    /// drivers, like intel_pstate, report the base frequency.
+    fn from_sysfs(cpufreq: &Path) -> Self {
+        let read_khz = |name| {
+            fs::read_to_string(cpufreq.join(name))
+                .ok()?
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .map(|khz| khz / 1000.0)
+        };
+
+        CpuFrequency {
+            nominal_mhz: read_khz("base_frequency"),
+            max_mhz: read_khz("cpuinfo_max_freq"),
+        }
+    }
+}
+
+/// The fields of the output of `lscpu -J` by name, without the trailing colon. Newer versions
+/// of lscpu nest the fields, older ones don't.
+pub fn lscpu_fields(json: &[u8]) -> BTreeMap<String, String> {
+    fn collect_fields(entries: &serde_json::Value, fields: &mut BTreeMap<String, String>) {
+        for entry in entries.as_array().into_iter().flatten() {
+            if let (Some(field), Some(data)) = (entry["field"].as_str(), entry["data"].as_str()) {
+                fields.insert(field.trim_end_matches(':').to_owned(), data.to_owned());
+            }
+            collect_fields(&entry["children"], fields);
+        }
+    }
+
+    let mut fields = BTreeMap::new();
+    if let Ok(json) = serde_json::from_slice::<serde_json::Value>(json) {
+        collect_fields(&json["lscpu"], &mut fields);
+    }
+    fields
+}
+
+impl Display for CpuFrequency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mhz = |mhz: Option<f64>| match mhz {
+            Some(mhz) => format!("{mhz:.0} MHz"),
+            None => "unknown".to_owned(),
+        };
+        write!(
+            f,
+            "{} nominal, {} max",
+            mhz(self.nominal_mhz),
+            mhz(self.max_mhz)
+        )
+    }
+}
+
+/// The frequency many model names end with, e.g. `Intel(R) Core(TM) i7-8700 CPU @ 3.20GHz`.
+pub fn frequency_from_model_name(model: &str) -> Option<f64> {
+    let (_, frequency) = model.rsplit_once('@')?;
+    let frequency = frequency.trim();
+
+    if let Some(ghz) = frequency.strip_suffix("GHz") {
+        ghz.trim().parse::<f64>().ok().map(|ghz| ghz * 1000.0)
+    } else if let Some(mhz) = frequency.strip_suffix("MHz") {
+        mhz.trim().parse::<f64>().ok()
+    } else {
+        None
+    }
+}
+
+/// The cycles converted to milliseconds at the nominal frequency: an estimate of the time that
+/// is comparable between machines of the same microarchitecture running at different
+/// frequencies. Only meaningful for CPU bound benchmarks.
+pub fn normalized_time(
+    counters: &BTreeMap<String, BenchCounter>,
+    frequency: &CpuFrequency,
+) -> Option<BenchCounter> {
+    let cycles = counters
+        .get("cycles")
+        .or_else(|| counters.get("cpu_core/cycles/"))?;
+    let cycles_per_msec = frequency.nominal_mhz? * 1000.0;
+
+    Some(BenchCounter {
+        value: cycles.value / cycles_per_msec,
+        variance: cycles.variance / cycles_per_msec.powi(2),
+        repetitions: cycles.repetitions,
+        unit: "msec".to_owned(),
+    })
+}
+
+/// Note that the compared results were measured at different frequencies.
+pub fn render_markdown_note(
+    md: &mut String,
+    before: Option<&CpuFrequency>,
+    after: Option<&CpuFrequency>,
+) {
+    // Older results don't have a frequency, so there is nothing to compare against.
+    let (Some(before), Some(after)) = (before, after) else {
+        return;
+    };
+    if before == after {
+        return;
+    }
+
+    writeln!(
+        md,
+        "> [!NOTE]\n> The parent was measured at {before}, this commit at {after}. \
+         Times are not directly comparable, cycles and `{NORMALIZED_TIME}` are.\n"
+    )
+    .unwrap();
+}
+
+#[cfg(test)]
+pub fn lscpu_fixture(name: &str) -> Vec<u8> {
+    fs::read(
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("testdata/lscpu")
+            .join(name),
+    )
+    .unwrap()
+}
+
+#[test]
+fn frequency_from_lscpu() {
+    // The model name embeds the nominal frequency.
+    assert_eq!(
+        CpuFrequency::from_lscpu(&lscpu_fixture("intel-coffee-lake.json")),
+        CpuFrequency {
+            nominal_mhz: Some(3200.0),
+            max_mhz: Some(4600.0),
+        }
+    );
+    // Virtual machines usually don't report the max frequency.
+    assert_eq!(
+        CpuFrequency::from_lscpu(&lscpu_fixture("intel-xeon-vm.json")),
+        CpuFrequency {
+            nominal_mhz: Some(2900.0),
+            max_mhz: None,
+        }
+    );
+    // The model name doesn't embed the frequency.
+    assert_eq!(
+        CpuFrequency::from_lscpu(&lscpu_fixture("amd-zen3.json")),
+        CpuFrequency {
+            nominal_mhz: None,
+            max_mhz: Some(5083.3979),
+        }
+    );
+    assert_eq!(
+        CpuFrequency::from_lscpu(&lscpu_fixture("arm-neoverse-n1.json")),
+        CpuFrequency::default()
+    );
+    assert_eq!(
+        CpuFrequency::from_lscpu(b"garbage"),
+        CpuFrequency::default()
+    );
+}
+
+#[test]
+fn frequency_from_sysfs() {
+    let dir = crate::testkit::test_dir("frequency-sysfs");
+    assert_eq!(CpuFrequency::from_sysfs(&dir), CpuFrequency::default());
+
+    fs::write(dir.join("base_frequency"), "3800000\n").unwrap();
+    fs::write(dir.join("cpuinfo_max_freq"), "5100000\n").unwrap();
+    assert_eq!(
+        CpuFrequency::from_sysfs(&dir),
+        CpuFrequency {
+            nominal_mhz: Some(3800.0),
+            max_mhz: Some(5100.0),
+        }
+    );
+}
+
+#[test]
+fn parse_model_name_frequency() {
+    assert_eq!(
+        frequency_from_model_name("Intel(R) Xeon(R) CPU E5-2680 v4 @ 2.40GHz"),
+        Some(2400.0)
+    );
+    assert_eq!(frequency_from_model_name("Some CPU @ 800MHz"), Some(800.0));
+    assert_eq!(
+        frequency_from_model_name("AMD EPYC 7763 64-Core Processor"),
+        None
+    );
+    assert_eq!(frequency_from_model_name("Weird @ fast"), None);
+}
+
+#[test]
+fn normalize_cycles() {
+    let frequency = CpuFrequency {
+        nominal_mhz: Some(3200.0),
+        max_mhz: None,
+    };
+    let cycles = BenchCounter {
+        value: 3.2e9,
+        variance: 3.2e6f64.powi(2),
+        repetitions: 20,
+        unit: String::new(),
+    };
+
+    let counters = BTreeMap::from([("cpu_core/cycles/".to_owned(), cycles.clone())]);
+    let normalized = normalized_time(&counters, &frequency).unwrap();
+    assert_eq!(normalized.value, 1000.0);
+    assert_eq!(normalized.variance, 1.0);
+    assert_eq!(normalized.repetitions, 20);
+    assert_eq!(normalized.unit, "msec");
+
+    // Both the cycles and the frequency are needed.
+    assert!(normalized_time(&BTreeMap::new(), &frequency).is_none());
+    let counters = BTreeMap::from([("cycles".to_owned(), cycles)]);
+    assert!(normalized_time(&counters, &CpuFrequency::default()).is_none());
+}
+
+#[test]
+fn frequency_note() {
+    let slow = CpuFrequency {
+        nominal_mhz: Some(3200.0),
+        max_mhz: Some(4600.0),
+    };
+    let fast = CpuFrequency {
+        nominal_mhz: Some(3800.0),
+        max_mhz: None,
+    };
+
+    let mut md = String::new();
+    render_markdown_note(&mut md, Some(&slow), Some(&slow));
+    render_markdown_note(&mut md, None, Some(&fast));
+    assert_eq!(md, "");
+
+    render_markdown_note(&mut md, Some(&slow), Some(&fast));
+    assert_eq!(
+        md,
+        "> [!NOTE]\n> The parent was measured at 3200 MHz nominal, 4600 MHz max, this commit at 3800 MHz nominal, unknown max. \
+         Times are not directly comparable, cycles and `normalized-time` are.\n\n"
+    );
+}
@@ -0,0 +1,852 @@
+use std::fmt::Write;
+use std::fs::OpenOptions;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::annotations;
+use crate::compare::{counted_rows, ComparisonRow, Comparisons};
+use crate::exemptions::{self, AcceptedFailure};
+
+/// Fail the run when a comparison against the parent commit regressed too much.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct GateConfig {
+    /// The largest significant regression, in percent, that is still accepted.
+    pub max_regression_percent: f64,
+    /// Also emit an `::error` workflow command for every failure, which GitHub shows as an
+    /// annotation on the config line of the row.
+    #[serde(default)]
+    pub annotations: bool,
+    /// The largest significant increase of the coefficient of variation, in percent, that is
+    /// still accepted. Only the rows of tables with `compare-variance` are checked.
+    #[serde(default)]
+    pub max_variance_increase: Option<f64>,
+    /// What the regressions are measured against, with `rolling-baseline`.
+    #[serde(default)]
+    pub baseline: GateBaseline,
+    /// The label of pull requests whose regressions are all accepted, see
+    /// [`crate::exemptions`].
+    #[serde(default)]
+    pub exempt_label: Option<String>,
+}
+
+/// The baseline of the gate, see [`crate::rolling`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GateBaseline {
+    /// The parent commit.
+    #[default]
+    Parent,
+    /// The rolling baseline, or the parent commit for the rows without one.
+    Rolling,
+    /// Both: a row only fails when it regressed versus the parent commit and the rolling
+    /// baseline, or versus the parent commit for the rows without one.
+    Both,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct GateVerdict {
+    pub failures: Vec<GateFailure>,
+    /// The comparisons whose spread grew by more than `max-variance-increase`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub variance_failures: Vec<GateFailure>,
+    /// The failures of groups whose measurements vary too much, with `measurement-quality`, or
+    /// that sampled other files than the baseline, see [`crate::sample`]. They don't fail the
+    /// gate.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub suppressed: Vec<GateFailure>,
+    /// The failures of the rows that a `Perf-Exempt` trailer or label exempts. They don't fail
+    /// the gate, see [`crate::exemptions`].
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub accepted: Vec<AcceptedFailure>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GateFailure {
+    pub table: String,
+    pub row: ComparisonRow,
+}
+
+/// The verdicts of the `gate` and the `shadow-gate`, when they are configured.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Verdicts<'a> {
+    pub gate: Option<&'a GateVerdict>,
+    pub shadow: Option<&'a GateVerdict>,
+}
+
+/// What a gate made of a row of the `render-versus-other` tables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RowOutcome {
+    Passed,
+    Failed,
+    /// It failed, but its group's measurements vary too much, or it sampled other files.
+    Suppressed,
+    /// It failed, but an exemption accepts it.
+    Accepted,
+}
+
+/// The outcomes of a row under the `gate` and the `shadow-gate`, side by side. Either is
+/// `None` when that gate isn't configured.
+#[derive(Debug, Serialize)]
+pub struct RowVerdicts {
+    pub table: String,
+    pub row: String,
+    pub measure: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    pub gate: Option<RowOutcome>,
+    pub shadow_gate: Option<RowOutcome>,
+}
+
+/// The outcomes of every row of the `render-versus-other` tables but the `informational`
+/// ones under both gates, when a `shadow-gate` is configured.
+pub fn row_verdicts(
+    comparisons: &Comparisons,
+    gate: Option<&GateVerdict>,
+    shadow: Option<&GateVerdict>,
+) -> Vec<RowVerdicts> {
+    if shadow.is_none() {
+        return vec![];
+    }
+    counted_rows(&comparisons.versus_other)
+        .map(|(table, row)| RowVerdicts {
+            table: table.name.clone(),
+            row: row.name.clone(),
+            measure: row.measure.clone(),
+            key: row.key.clone(),
+            gate: gate.map(|verdict| verdict.outcome(&table.name, row)),
+            shadow_gate: shadow.map(|verdict| verdict.outcome(&table.name, row)),
+        })
+        .collect()
+}
+
+impl GateConfig {
+    /// Whether `row` regressed by more than `max-regression-percent` versus the configured
+    /// baseline. A regression of a hit rate is a decrease.
+    fn regressed(&self, row: &ComparisonRow) -> bool {
+        let versus_parent = row.is_regression() && row.delta().abs() > self.max_regression_percent;
+        let versus_rolling = row.rolling.as_ref().map(|rolling| {
+            row.is_rolling_regression()
+                && rolling.delta(row.kind, &row.after).abs() > self.max_regression_percent
+        });
+        match (self.baseline, versus_rolling) {
+            (GateBaseline::Parent, _) | (_, None) => versus_parent,
+            (GateBaseline::Rolling, Some(versus_rolling)) => versus_rolling,
+            (GateBaseline::Both, Some(versus_rolling)) => versus_parent && versus_rolling,
+        }
+    }
+
+    /// The failures of the rows of `comparisons`, but for those whose output differed between
+    /// repetitions, see [`crate::verify_output`], and those of `informational` tables.
+    pub fn evaluate(&self, comparisons: &Comparisons) -> GateVerdict {
+        GateVerdict {
+            failures: counted_rows(&comparisons.versus_other)
+                .filter(|(_, row)| !row.is_unreliable() && self.regressed(row))
+                .map(|(table, row)| GateFailure {
+                    table: table.name.clone(),
+                    row: row.clone(),
+                })
+                .collect(),
+            variance_failures: self
+                .max_variance_increase
+                .map(|max_variance_increase| {
+                    comparisons
+                        .variance_regressions()
+                        .filter(|(_, row)| {
+                            !row.is_unreliable()
+                                && row
+                                    .variance
+                                    .as_ref()
+                                    .and_then(|variance| variance.cov_delta_percent)
+                                    .is_some_and(|delta| delta > max_variance_increase)
+                        })
+                        .map(|(table, row)| GateFailure {
+                            table: table.name.clone(),
+                            row: row.clone(),
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
+            suppressed: vec![],
+            accepted: vec![],
+        }
+    }
+}
+
+impl GateFailure {
+    /// The workflow command for the annotation on the config line of the row, see
+    /// [`annotations::error_command`].
+    pub fn error_command(&self) -> String {
+        annotations::error_command(
+            self.row.config_span.as_ref(),
+            &format!(
+                "{} {} regressed {} {}",
+                self.table,
+                self.row.name,
+                self.row.format_delta(),
+                self.row.measure
+            ),
+        )
+    }
+
+    /// Like [`Self::error_command`], for a failure of `max-variance-increase`.
+    pub fn variance_error_command(&self) -> String {
+        annotations::error_command(
+            self.row.config_span.as_ref(),
+            &format!(
+                "{} {} got {} more variable in {}",
+                self.table,
+                self.row.name,
+                self.format_cov_delta(),
+                self.row.measure
+            ),
+        )
+    }
+
+    /// The change of the coefficient of variation, for failures of `max-variance-increase`.
+    pub fn format_cov_delta(&self) -> String {
+        match self
+            .row
+            .variance
+            .as_ref()
+            .and_then(|variance| variance.cov_delta_percent)
+        {
+            Some(delta) => format!("{delta:+.2}%"),
+            None => "n.a.".to_owned(),
+        }
+    }
+}
+
+impl GateVerdict {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty() && self.variance_failures.is_empty()
+    }
+
+    /// What the gate made of the `row` of `table`.
+    pub fn outcome(&self, table: &str, row: &ComparisonRow) -> RowOutcome {
+        let is_row = |failure: &GateFailure| {
+            failure.table == table
+                && failure.row.name == row.name
+                && failure.row.measure == row.measure
+        };
+        if self
+            .failures
+            .iter()
+            .chain(&self.variance_failures)
+            .any(is_row)
+        {
+            RowOutcome::Failed
+        } else if self.suppressed.iter().any(is_row) {
+            RowOutcome::Suppressed
+        } else if self
+            .accepted
+            .iter()
+            .any(|accepted| is_row(&accepted.failure))
+        {
+            RowOutcome::Accepted
+        } else {
+            RowOutcome::Passed
+        }
+    }
+
+    /// The verdict of the `shadow-gate` in a collapsed section: what would have failed the
+    /// run, had it been the gate.
+    pub fn render_markdown_shadow(&self, md: &mut String, config: &GateConfig) {
+        let failed = self.failures.len() + self.variance_failures.len();
+        let summary = match failed {
+            0 => "Shadow gate: would pass".to_owned(),
+            _ => format!("Shadow gate: {failed} comparisons would fail"),
+        };
+        // GitHub only renders markdown inside <details> when surrounded by blank lines.
+        writeln!(md, "<details>\n<summary>{summary}</summary>\n").unwrap();
+        let mut verdict = String::new();
+        self.render_markdown(&mut verdict, config);
+        if verdict.is_empty() {
+            writeln!(
+                md,
+                "No comparison regressed by more than {}%.\n",
+                config.max_regression_percent
+            )
+            .unwrap();
+        } else {
+            md.push_str(&verdict);
+        }
+        writeln!(md, "</details>\n").unwrap();
+    }
+
+    pub fn render_markdown(&self, md: &mut String, config: &GateConfig) {
+        if !self.failures.is_empty() {
+            writeln!(
+                md,
+                "> [!CAUTION]\n> {} comparisons regressed by more than {}%:",
+                self.failures.len(),
+                config.max_regression_percent,
+            )
+            .unwrap();
+            for failure in &self.failures {
+                writeln!(
+                    md,
+                    "> - {} / {}: `{}` {}",
+                    failure.table,
+                    failure.row.name,
+                    failure.row.format_delta(),
+                    failure.row.measure,
+                )
+                .unwrap();
+            }
+            writeln!(md).unwrap();
+        }
+
+        if let (false, Some(max_variance_increase)) = (
+            self.variance_failures.is_empty(),
+            config.max_variance_increase,
+        ) {
+            writeln!(
+                md,
+                "> [!CAUTION]\n> {} comparisons got more variable by more than {}%:",
+                self.variance_failures.len(),
+                max_variance_increase,
+            )
+            .unwrap();
+            for failure in &self.variance_failures {
+                writeln!(
+                    md,
+                    "> - {} / {}: `{}` coefficient of variation of {}",
+                    failure.table,
+                    failure.row.name,
+                    failure.format_cov_delta(),
+                    failure.row.measure,
+                )
+                .unwrap();
+            }
+            writeln!(md).unwrap();
+        }
+
+        if !self.suppressed.is_empty() {
+            writeln!(
+                md,
+                "> [!NOTE]\n> {} failures of the gate were ignored, the measurements of their groups vary too much or they sampled other files than the baseline:",
+                self.suppressed.len(),
+            )
+            .unwrap();
+            for failure in &self.suppressed {
+                writeln!(
+                    md,
+                    "> - {} / {}: `{}` {}",
+                    failure.table,
+                    failure.row.name,
+                    failure.row.format_delta(),
+                    failure.row.measure,
+                )
+                .unwrap();
+            }
+            writeln!(md).unwrap();
+        }
+
+        exemptions::render_markdown(md, &self.accepted);
+    }
+}
+
+/// The name of the output with the number of failures of the `shadow-gate`.
+pub const SHADOW_OUTPUT: &str = "shadow_regressions";
+
+/// Append the number of failures of the `shadow-gate` to the `GITHUB_OUTPUT` file at `path`.
+pub fn write_shadow_github_output(path: &Path, verdict: &GateVerdict) -> Result<(), String> {
+    use std::io::Write;
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("failed to open {}: {e}", path.display()))?;
+    writeln!(
+        file,
+        "{SHADOW_OUTPUT}={}",
+        verdict.failures.len() + verdict.variance_failures.len()
+    )
+    .map_err(|e| format!("failed to write {}: {e}", path.display()))
+}
+
+#[test]
+fn gate_threshold() {
+    let config = GateConfig {
+        max_regression_percent: 5.0,
+        annotations: false,
+        max_variance_increase: None,
+        baseline: Default::default(),
+        exempt_label: None,
+    };
+
+    let before = crate::testkit::BenchDataBuilder::cycles(
+        "1111111111111111111111111111111111111111",
+        &[(
+            "compress",
+            &[("./c 1", 1000.0), ("./c 2", 1000.0), ("./c 3", 1000.0)],
+        )],
+    )
+    .build();
+    let after = crate::testkit::BenchDataBuilder::cycles(
+        "2222222222222222222222222222222222222222",
+        &[(
+            "compress",
+            &[("./c 1", 1200.0), ("./c 2", 1030.0), ("./c 3", 800.0)],
+        )],
+    )
+    .build();
+    let render = serde_json::from_str(
+        r#"{ "compression": { "measure": "cycles", "command": "compress", "rows": { "level 1": 0, "level 2": 1, "level 3": 2 } } }"#,
+    )
+    .unwrap();
+    let comparisons = Comparisons {
+        versus_other: crate::compare::collect_versus_other(
+            &render,
+            &indexmap::IndexMap::new(),
+            None,
+            &before,
+            &after,
+        ),
+        ..Comparisons::default()
+    };
+
+    // level 2 regressed significantly, but by less than the threshold
+    let verdict = config.evaluate(&comparisons);
+    assert!(!verdict.passed());
+    assert_eq!(verdict.failures.len(), 1);
+    assert_eq!(verdict.failures[0].table, "compression");
+    assert_eq!(verdict.failures[0].row.name, "level 1");
+
+    let mut md = String::new();
+    verdict.render_markdown(&mut md, &config);
+    assert_eq!(
+        md,
+        "> [!CAUTION]\n> 1 comparisons regressed by more than 5%:\n> - compression / level 1: `+16.67%` cycles\n\n"
+    );
+}
+
+#[test]
+fn gate_ignores_nondeterministic_output() {
+    let config = GateConfig {
+        max_regression_percent: 5.0,
+        annotations: false,
+        max_variance_increase: None,
+        baseline: Default::default(),
+        exempt_label: None,
+    };
+
+    let before = crate::testkit::BenchDataBuilder::cycles(
+        "1111111111111111111111111111111111111111",
+        &[("compress", &[("./c 1", 1000.0), ("./c 2", 1000.0)])],
+    )
+    .build();
+    let mut after = crate::testkit::BenchDataBuilder::cycles(
+        "2222222222222222222222222222222222222222",
+        &[("compress", &[("./c 1", 1200.0), ("./c 2", 1200.0)])],
+    )
+    .build();
+    after.bench_groups["compress"][1].nondeterministic_output = true;
+    let render = serde_json::from_str(
+        r#"{ "compression": { "measure": "cycles", "command": "compress", "rows": { "level 1": 0, "level 2": 1 } } }"#,
+    )
+    .unwrap();
+    let comparisons = Comparisons {
+        versus_other: crate::compare::collect_versus_other(
+            &render,
+            &indexmap::IndexMap::new(),
+            None,
+            &before,
+            &after,
+        ),
+        ..Comparisons::default()
+    };
+    assert!(comparisons.versus_other[0].rows[1].nondeterministic_output);
+
+    // Both regressed, but the output of level 2 differed between its repetitions.
+    let verdict = config.evaluate(&comparisons);
+    assert_eq!(verdict.failures.len(), 1);
+    assert_eq!(verdict.failures[0].row.name, "level 1");
+
+    let mut md = String::new();
+    comparisons.versus_other[0].render_markdown(
+        &mut md,
+        "| name | before | after | Δ |\n| --- | --- | --- | --- |\n",
+        &crate::markers::Markers::default(),
+    );
+    assert!(
+        md.contains("| level 2 ⚠️ <a id=") && md.contains("⚠️ output differed between repetitions"),
+        "{md}"
+    );
+}
+
+#[test]
+fn gate_ignores_too_preempted() {
+    use crate::preemption::Preempted;
+
+    let config = GateConfig {
+        max_regression_percent: 5.0,
+        annotations: false,
+        max_variance_increase: None,
+        baseline: Default::default(),
+        exempt_label: None,
+    };
+
+    let mut before = crate::testkit::BenchDataBuilder::cycles(
+        "1111111111111111111111111111111111111111",
+        &[("compress", &[("./c 1", 1000.0), ("./c 2", 1000.0)])],
+    )
+    .build();
+    let mut after = crate::testkit::BenchDataBuilder::cycles(
+        "2222222222222222222222222222222222222222",
+        &[("compress", &[("./c 1", 1200.0), ("./c 2", 1200.0)])],
+    )
+    .build();
+    // A few disturbed repetitions after the change, too many of them before it.
+    after.bench_groups["compress"][0].preempted = Some(Preempted {
+        excluded: 2,
+        repetitions: 20,
+        unreliable: false,
+    });
+    before.bench_groups["compress"][1].preempted = Some(Preempted {
+        excluded: 8,
+        repetitions: 20,
+        unreliable: true,
+    });
+    let render = serde_json::from_str(
+        r#"{ "compression": { "measure": "cycles", "command": "compress", "rows": { "level 1": 0, "level 2": 1 } } }"#,
+    )
+    .unwrap();
+    let comparisons = Comparisons {
+        versus_other: crate::compare::collect_versus_other(
+            &render,
+            &indexmap::IndexMap::new(),
+            None,
+            &before,
+            &after,
+        ),
+        ..Comparisons::default()
+    };
+    let rows = &comparisons.versus_other[0].rows;
+    assert!(!rows[0].too_preempted && rows[0].preempted.is_some());
+    assert!(rows[1].too_preempted && rows[1].preempted.is_none());
+
+    let verdict = config.evaluate(&comparisons);
+    assert_eq!(verdict.failures.len(), 1);
+    assert_eq!(verdict.failures[0].row.name, "level 1");
+
+    let mut md = String::new();
+    comparisons.versus_other[0].render_markdown(
+        &mut md,
+        "| name | before | after | Δ |\n| --- | --- | --- | --- |\n",
+        &crate::markers::Markers::default(),
+    );
+    assert!(md.contains("| level 1 † <a id="), "{md}");
+    assert!(md.contains("| level 2 ⚠️ <a id="), "{md}");
+    assert!(
+        md.contains(
+            "⚠️ too many repetitions preempted\n\n† level 1: 2 of 20 repetitions excluded due to external preemption  \n"
+        ),
+        "{md}"
+    );
+}
+
+#[test]
+fn gate_threshold_in_percentage_points() {
+    let config = GateConfig {
+        max_regression_percent: 12.0,
+        annotations: false,
+        max_variance_increase: None,
+        baseline: Default::default(),
+        exempt_label: None,
+    };
+
+    // As if the cycles were a miss rate in percent.
+    let before = crate::testkit::BenchDataBuilder::cycles(
+        "1111111111111111111111111111111111111111",
+        &[("cache", &[("./c 1", 10.0), ("./c 2", 10.0)])],
+    )
+    .build();
+    let after = crate::testkit::BenchDataBuilder::cycles(
+        "2222222222222222222222222222222222222222",
+        &[("cache", &[("./c 1", 20.0), ("./c 2", 25.0)])],
+    )
+    .build();
+    let render = serde_json::from_str(
+        r#"{ "misses": { "measure": "cycles", "command": "cache", "rows": { "small": 0, "large": 1 } } }"#,
+    )
+    .unwrap();
+    let kinds = serde_json::from_str(r#"{ "cycles": "percentage" }"#).unwrap();
+    let comparisons = Comparisons {
+        versus_other: crate::compare::collect_versus_other(&render, &kinds, None, &before, &after),
+        ..Comparisons::default()
+    };
+
+    // Both doubled or more, but only large moved by more than 12 percentage points.
+    let verdict = config.evaluate(&comparisons);
+    assert_eq!(verdict.failures.len(), 1);
+    assert_eq!(verdict.failures[0].row.name, "large");
+
+    let mut md = String::new();
+    verdict.render_markdown(&mut md, &config);
+    assert_eq!(
+        md,
+        "> [!CAUTION]\n> 1 comparisons regressed by more than 12%:\n> - misses / large: `+15.0 pp` cycles\n\n"
+    );
+}
+
+#[test]
+fn gate_hit_rate_drops() {
+    use crate::testkit::BenchDataBuilder;
+
+    let config = GateConfig {
+        max_regression_percent: 12.0,
+        annotations: false,
+        max_variance_increase: None,
+        baseline: Default::default(),
+        exempt_label: None,
+    };
+    let results = |commit: &str, rates: [f64; 3]| {
+        BenchDataBuilder::new(commit)
+            .group("cache", |mut g| {
+                for (level, rate) in rates.into_iter().enumerate() {
+                    g = g.bench(["./c".to_owned(), level.to_string()], |b| {
+                        b.counter("cache-hit-rate", rate, 0.01, 20, "%")
+                    });
+                }
+                g
+            })
+            .build()
+    };
+    let before = results(
+        "1111111111111111111111111111111111111111",
+        [95.0, 95.0, 80.0],
+    );
+    let after = results(
+        "2222222222222222222222222222222222222222",
+        [90.0, 80.0, 95.0],
+    );
+    let render = serde_json::from_str(
+        r#"{ "hits": { "measure": "cache-hit-rate", "command": "cache", "rows": { "small": 0, "large": 1, "better": 2 } } }"#,
+    )
+    .unwrap();
+    let comparisons = Comparisons {
+        versus_other: crate::compare::collect_versus_other(
+            &render,
+            &Default::default(),
+            None,
+            &before,
+            &after,
+        ),
+        ..Comparisons::default()
+    };
+
+    // Only the drop by more than 12 percentage points, not the rise.
+    let verdict = config.evaluate(&comparisons);
+    assert_eq!(verdict.failures.len(), 1);
+    assert_eq!(verdict.failures[0].row.name, "large");
+}
+
+#[test]
+fn gate_annotations() {
+    let config: GateConfig =
+        serde_json::from_str(r#"{ "max-regression-percent": 5.0, "annotations": true }"#).unwrap();
+    assert!(config.annotations);
+
+    let before = crate::testkit::BenchDataBuilder::cycles(
+        "1111111111111111111111111111111111111111",
+        &[("compress", &[("./c 1", 1000.0), ("./c 9", 1000.0)])],
+    )
+    .build();
+    let after = crate::testkit::BenchDataBuilder::cycles(
+        "2222222222222222222222222222222222222222",
+        &[("compress", &[("./c 1", 1200.0), ("./c 9", 1300.0)])],
+    )
+    .build();
+    let json = r#"{
+    "render-versus-other": {
+        "compression": {
+            "measure": "cycles",
+            "command": "compress",
+            "rows": {
+                "level 1": 0,
+                "level 9": 1
+            }
+        }
+    }
+}"#;
+    let path = crate::testkit::test_dir("gate-annotations").join("bench-config.json");
+    std::fs::write(&path, json).unwrap();
+
+    let render = serde_json::from_value(
+        serde_json::from_str::<serde_json::Value>(json).unwrap()["render-versus-other"].clone(),
+    )
+    .unwrap();
+    let mut comparisons = Comparisons {
+        versus_other: crate::compare::collect_versus_other(
+            &render,
+            &indexmap::IndexMap::new(),
+            None,
+            &before,
+            &after,
+        ),
+        ..Comparisons::default()
+    };
+    // As if the row was renamed since the config was read.
+    comparisons.versus_other[0].rows[1].name = "level 10".to_owned();
+    annotations::ConfigSpans::read(std::slice::from_ref(&path))
+        .unwrap()
+        .resolve(&mut comparisons);
+
+    let verdict = config.evaluate(&comparisons);
+    let commands = verdict
+        .failures
+        .iter()
+        .map(GateFailure::error_command)
+        .collect::<Vec<_>>();
+    let file = path.display();
+    assert_eq!(
+        commands,
+        [
+            format!("::error file={file},line=7::compression level 1 regressed +16.67%25 cycles"),
+            format!("::error file={file}::compression level 10 regressed +23.08%25 cycles"),
+        ]
+    );
+}
+
+#[test]
+fn gate_variance_increase() {
+    let before = crate::testkit::BenchDataBuilder::new("1111111111111111111111111111111111111111")
+        .group("decompress", |g| {
+            g.bench(["./d", "1"], |b| b.counter("cycles", 1000.0, 100.0, 20, ""))
+                .bench(["./d", "2"], |b| b.counter("cycles", 1000.0, 100.0, 20, ""))
+        });
+    let mut after = before
+        .clone()
+        .commit_hash("2222222222222222222222222222222222222222")
+        .build();
+    let group = after.bench_groups.get_mut("decompress").unwrap();
+    group[0].counters.get_mut("cycles").unwrap().variance = 400.0;
+    group[1].counters.get_mut("cycles").unwrap().variance = 300.0;
+    let render = serde_json::from_str(
+        r#"{ "decompression": { "measure": "cycles", "command": "decompress", "rows": { "level 1": 0, "level 2": 1 }, "compare-variance": true } }"#,
+    )
+    .unwrap();
+    let comparisons = Comparisons {
+        versus_other: crate::compare::collect_versus_other(
+            &render,
+            &indexmap::IndexMap::new(),
+            None,
+            &before.build(),
+            &after,
+        ),
+        ..Comparisons::default()
+    };
+
+    // Both got significantly more variable, without a change of the mean.
+    let config: GateConfig = serde_json::from_str(r#"{ "max-regression-percent": 5.0 }"#).unwrap();
+    assert!(config.evaluate(&comparisons).passed());
+
+    // level 2 by 73%, which is accepted.
+    let config: GateConfig =
+        serde_json::from_str(r#"{ "max-regression-percent": 5.0, "max-variance-increase": 80 }"#)
+            .unwrap();
+    let verdict = config.evaluate(&comparisons);
+    assert!(!verdict.passed());
+    assert!(verdict.failures.is_empty());
+    assert_eq!(verdict.variance_failures.len(), 1);
+    assert_eq!(verdict.variance_failures[0].row.name, "level 1");
+
+    let mut md = String::new();
+    verdict.render_markdown(&mut md, &config);
+    assert_eq!(
+        md,
+        "> [!CAUTION]\n> 1 comparisons got more variable by more than 80%:\n> - decompression / level 1: `+100.00%` coefficient of variation of cycles\n\n"
+    );
+    assert_eq!(
+        verdict.variance_failures[0].variance_error_command(),
+        "::error::decompression level 1 got +100.00%25 more variable in cycles"
+    );
+    assert_eq!(
+        serde_json::to_value(&verdict).unwrap()["variance_failures"][0]["row"]["variance"],
+        serde_json::json!({ "cov_delta_percent": 100.0, "significant": true })
+    );
+}
+
+#[test]
+fn row_outcomes_of_both_gates() {
+    let gate = |max_regression_percent| GateConfig {
+        max_regression_percent,
+        annotations: false,
+        max_variance_increase: None,
+        baseline: Default::default(),
+        exempt_label: None,
+    };
+    let before = crate::testkit::BenchDataBuilder::cycles(
+        "1111111111111111111111111111111111111111",
+        &[("compress", &[("./c 1", 1000.0), ("./c 2", 1000.0)])],
+    )
+    .build();
+    let after = crate::testkit::BenchDataBuilder::cycles(
+        "2222222222222222222222222222222222222222",
+        &[("compress", &[("./c 1", 1200.0), ("./c 2", 1030.0)])],
+    )
+    .build();
+    let render = serde_json::from_str(
+        r#"{ "compression": { "measure": "cycles", "command": "compress", "rows": { "level 1": 0, "level 2": 1 } } }"#,
+    )
+    .unwrap();
+    let comparisons = Comparisons {
+        versus_other: crate::compare::collect_versus_other(
+            &render,
+            &indexmap::IndexMap::new(),
+            None,
+            &before,
+            &after,
+        ),
+        ..Comparisons::default()
+    };
+
+    // The same engine, with different thresholds.
+    let verdict = gate(5.0).evaluate(&comparisons);
+    let mut shadow = gate(1.0).evaluate(&comparisons);
+    assert_eq!(verdict.failures.len(), 1);
+    assert_eq!(shadow.failures.len(), 2);
+    let failure = shadow.failures.remove(0);
+    shadow.accepted.push(AcceptedFailure {
+        failure,
+        variance: false,
+        exemption: exemptions::Exemption {
+            target: "compression/level 1".to_owned(),
+            reason: "expected".to_owned(),
+            source: exemptions::ExemptionSource::Commit("2222222".to_owned()),
+        },
+    });
+
+    let outcomes = |verdicts: &[RowVerdicts]| {
+        verdicts
+            .iter()
+            .map(|row| (row.row.clone(), row.gate, row.shadow_gate))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        outcomes(&row_verdicts(&comparisons, Some(&verdict), Some(&shadow))),
+        [
+            (
+                "level 1".to_owned(),
+                Some(RowOutcome::Failed),
+                Some(RowOutcome::Accepted)
+            ),
+            (
+                "level 2".to_owned(),
+                Some(RowOutcome::Passed),
+                Some(RowOutcome::Failed)
+            ),
+        ]
+    );
+    assert_eq!(
+        outcomes(&row_verdicts(&comparisons, None, Some(&verdict)))[0],
+        ("level 1".to_owned(), None, Some(RowOutcome::Failed))
+    );
+    // Without a shadow gate, there is nothing to put side by side.
+    assert!(row_verdicts(&comparisons, Some(&verdict), None).is_empty());
+}
@@ -0,0 +1,487 @@
+//! Reading the previous results, which grow by an entry for every run of the main branch and
+//! are never trimmed unless [`crate::compact`] does so. The file is streamed line by line
+//! rather than read whole, and when only the baseline is needed the reading stops at its entry.
+//!
+//! With `--results-index`, a sidecar file next to the results, `<results>.index`, maps every
+//! commit to the byte offsets of its entries, so the baseline is read by seeking to it
+//! directly. The index records how much of the results it covers, with a hash of the first
+//! and of the last bytes of that part:
+//!
+//! - when the results grew since, like after `cat new.json >> results.json`, only the new
+//!   lines are indexed;
+//! - when the covered part changed, like after `git pull --rebase` or a compaction, the index
+//!   is rebuilt.
+//!
+//! Every entry read through the index is checked to be of the commit it was looked up for,
+//! and the whole file is streamed whenever the index has no entry of that commit, so a stale
+//! index costs time, never results.
+
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, ErrorKind, Read, Seek, SeekFrom};
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+
+use indexmap::IndexMap;
+use serde::de::IgnoredAny;
+use serde::Deserialize;
+
+use crate::sha256::Sha256;
+use crate::BenchData;
+
+/// The first line of an index, before the covered length and the hashes.
+const INDEX_HEADER: &str = "benchmarker-index 1";
+
+/// How many bytes at either end of the covered part of the results are hashed.
+const FINGERPRINT_LEN: u64 = 4096;
+
+/// Call `f` with every line of the file at `path` and the offset it starts at, without the
+/// line break, until it breaks. A last line without a line break is included.
+pub fn for_each_line(
+    path: &Path,
+    f: impl FnMut(u64, &[u8]) -> ControlFlow<()>,
+) -> Result<(), String> {
+    let file = File::open(path).map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+    for_each_line_from(path, file, 0, f).map(|_| ())
+}
+
+/// [`for_each_line`] from `offset` of `file`, returning the offset after the last complete
+/// line.
+fn for_each_line_from(
+    path: &Path,
+    mut file: File,
+    offset: u64,
+    mut f: impl FnMut(u64, &[u8]) -> ControlFlow<()>,
+) -> Result<u64, String> {
+    let read_error = |e: std::io::Error| format!("failed to read {}: {e}", path.display());
+    file.seek(SeekFrom::Start(offset)).map_err(read_error)?;
+    let mut reader = BufReader::new(file);
+    let mut line = vec![];
+    let mut offset = offset;
+    loop {
+        line.clear();
+        let len = reader.read_until(b'\n', &mut line).map_err(read_error)?;
+        if len == 0 {
+            return Ok(offset);
+        }
+        let complete = line.ends_with(b"\n");
+        let start = offset;
+        if complete {
+            offset += len as u64;
+        }
+        let text = line.strip_suffix(b"\n").unwrap_or(&line);
+        if f(start, text.strip_suffix(b"\r").unwrap_or(text)).is_break() || !complete {
+            return Ok(offset);
+        }
+    }
+}
+
+/// The entries of the results at `path` that `load` reads, in file order. With `until`, the
+/// reading stops at the first entry of that commit, which comes from the index with
+/// `use_index`. Failing to read or update the index is only a warning.
+pub fn read(
+    path: &Path,
+    until: Option<&str>,
+    use_index: bool,
+    mut load: impl FnMut(&[u8]) -> Option<BenchData>,
+) -> Result<Vec<BenchData>, String> {
+    if let (Some(commit), true) = (until, use_index) {
+        match Index::refresh(path) {
+            Ok((index, _)) => {
+                for offset in index.offsets(commit) {
+                    let line = match read_line_at(path, offset) {
+                        Ok(line) => line,
+                        Err(err) => {
+                            eprintln!("warning: {err}");
+                            break;
+                        }
+                    };
+                    if let Some(data) = load(&line).filter(|data| data.commit_id() == commit) {
+                        return Ok(vec![data]);
+                    }
+                }
+            }
+            Err(err) => eprintln!("warning: not using the index of the results: {err}"),
+        }
+    }
+
+    let mut entries = vec![];
+    for_each_line(path, |_, line| {
+        let Some(data) = load(line) else {
+            return ControlFlow::Continue(());
+        };
+        let found = until.is_some_and(|commit| data.commit_id() == commit);
+        entries.push(data);
+        if found {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    })?;
+    Ok(entries)
+}
+
+/// The line of the file at `path` that starts at `offset`, without the line break.
+pub fn read_line_at(path: &Path, offset: u64) -> Result<Vec<u8>, String> {
+    let mut line = None;
+    let file = File::open(path).map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+    for_each_line_from(path, file, offset, |_, text| {
+        line = Some(text.to_vec());
+        ControlFlow::Break(())
+    })?;
+    line.ok_or_else(|| format!("{} has no line at {offset}", path.display()))
+}
+
+/// Just enough of an entry to index it by.
+#[derive(Deserialize)]
+struct IndexKey {
+    commit_hash: String,
+    #[serde(default)]
+    dirty: bool,
+}
+
+impl IndexKey {
+    /// The same as [`BenchData::commit_id`].
+    fn commit_id(self) -> String {
+        if self.dirty {
+            format!("{}-dirty", self.commit_hash)
+        } else {
+            self.commit_hash
+        }
+    }
+}
+
+/// Just enough of an entry to tell which suite of which commit it has the results of, see
+/// [`crate::sections::write_line`].
+#[derive(Deserialize)]
+pub struct SuiteKey {
+    commit_hash: String,
+    #[serde(default)]
+    dirty: bool,
+    #[serde(default)]
+    bench_groups: IndexMap<String, IgnoredAny>,
+}
+
+impl SuiteKey {
+    pub fn parse(line: &[u8]) -> Option<Self> {
+        serde_json::from_slice(line).ok()
+    }
+
+    /// The same as [`BenchData::commit_id`].
+    pub fn commit_id(&self) -> String {
+        if self.dirty {
+            format!("{}-dirty", self.commit_hash)
+        } else {
+            self.commit_hash.clone()
+        }
+    }
+
+    pub fn group_names(&self) -> impl Iterator<Item = &str> {
+        self.bench_groups.keys().map(String::as_str)
+    }
+}
+
+/// What [`Index::refresh`] had to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Refreshed {
+    /// The index covered all of the results.
+    Fresh,
+    /// The results grew, the new lines got indexed.
+    CaughtUp,
+    /// There was no index, or it didn't match the results.
+    Rebuilt,
+}
+
+/// The offsets of the entries of the results, by commit.
+#[derive(Debug, Default, PartialEq)]
+pub struct Index {
+    /// How many bytes of the results are indexed, up to the end of a complete line.
+    covered: u64,
+    /// The SHA-256 of the first and the last [`FINGERPRINT_LEN`] bytes of the covered part.
+    head: String,
+    tail: String,
+    /// The commit and the offset of every entry, in file order.
+    entries: Vec<(String, u64)>,
+}
+
+impl Index {
+    /// The index of the results at `path`.
+    pub fn path(results: &Path) -> PathBuf {
+        let mut path = results.as_os_str().to_owned();
+        path.push(".index");
+        PathBuf::from(path)
+    }
+
+    /// The offsets of the entries of `commit`, in file order.
+    pub fn offsets<'a>(&'a self, commit: &'a str) -> impl Iterator<Item = u64> + 'a {
+        self.entries
+            .iter()
+            .filter(move |(entry_commit, _)| entry_commit == commit)
+            .map(|&(_, offset)| offset)
+    }
+
+    /// Bring the index of the results at `path` up to date with them, and write it when it
+    /// changed.
+    pub fn refresh(path: &Path) -> Result<(Index, Refreshed), String> {
+        let index_path = Index::path(path);
+        let stored = match fs::read_to_string(&index_path) {
+            Ok(text) => Index::parse(&text),
+            Err(e) if e.kind() == ErrorKind::NotFound => None,
+            Err(e) => return Err(format!("failed to read {}: {e}", index_path.display())),
+        };
+        let len = fs::metadata(path)
+            .map_err(|e| format!("failed to read {}: {e}", path.display()))?
+            .len();
+
+        let (mut index, refreshed) = match stored {
+            Some(index) if index.covered <= len && index.fingerprint(path)? == index.hashes() => {
+                let refreshed = if index.covered == len {
+                    Refreshed::Fresh
+                } else {
+                    Refreshed::CaughtUp
+                };
+                (index, refreshed)
+            }
+            _ => (Index::default(), Refreshed::Rebuilt),
+        };
+        if refreshed == Refreshed::Fresh {
+            return Ok((index, refreshed));
+        }
+
+        let file =
+            File::open(path).map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+        let mut entries = vec![];
+        index.covered = for_each_line_from(path, file, index.covered, |offset, line| {
+            if let Ok(key) = serde_json::from_slice::<IndexKey>(line) {
+                entries.push((key.commit_id(), offset));
+            }
+            ControlFlow::Continue(())
+        })?;
+        // An unfinished last line is indexed once it is complete.
+        if let Some(&(_, last)) = entries.last() {
+            if last >= index.covered {
+                entries.pop();
+            }
+        }
+        index.entries.extend(entries);
+        (index.head, index.tail) = index.fingerprint(path)?;
+
+        let mut tmp = index_path.as_os_str().to_owned();
+        tmp.push(format!(".{}.tmp", std::process::id()));
+        let tmp = PathBuf::from(tmp);
+        fs::write(&tmp, index.to_text())
+            .map_err(|e| format!("failed to write {}: {e}", tmp.display()))?;
+        fs::rename(&tmp, &index_path).map_err(|e| {
+            let _ = fs::remove_file(&tmp);
+            format!("failed to replace {}: {e}", index_path.display())
+        })?;
+        Ok((index, refreshed))
+    }
+
+    fn hashes(&self) -> (String, String) {
+        (self.head.clone(), self.tail.clone())
+    }
+
+    /// The hashes of the first and the last bytes of the covered part of the results at
+    /// `path`.
+    fn fingerprint(&self, path: &Path) -> Result<(String, String), String> {
+        let read_error = |e: std::io::Error| format!("failed to read {}: {e}", path.display());
+        let mut file = File::open(path).map_err(read_error)?;
+        let mut hash = |start: u64| {
+            let mut bytes = vec![];
+            file.seek(SeekFrom::Start(start)).map_err(read_error)?;
+            (&mut file)
+                .take(FINGERPRINT_LEN.min(self.covered))
+                .read_to_end(&mut bytes)
+                .map_err(read_error)?;
+            let mut sha256 = Sha256::default();
+            sha256.update(&bytes);
+            Ok::<_, String>(sha256.finish_hex())
+        };
+        let head = hash(0)?;
+        let tail = hash(self.covered.saturating_sub(FINGERPRINT_LEN))?;
+        Ok((head, tail))
+    }
+
+    fn parse(text: &str) -> Option<Index> {
+        let mut lines = text.lines();
+        let header = lines.next()?.strip_prefix(INDEX_HEADER)?;
+        let mut header = header.split_whitespace();
+        let mut index = Index {
+            covered: header.next()?.parse().ok()?,
+            head: header.next()?.to_owned(),
+            tail: header.next()?.to_owned(),
+            entries: vec![],
+        };
+        for line in lines {
+            let (offset, commit) = line.split_once(' ')?;
+            index
+                .entries
+                .push((commit.to_owned(), offset.parse().ok()?));
+        }
+        Some(index)
+    }
+
+    fn to_text(&self) -> String {
+        let mut text = format!(
+            "{INDEX_HEADER} {} {} {}\n",
+            self.covered, self.head, self.tail
+        );
+        for (commit, offset) in &self.entries {
+            text.push_str(&format!("{offset} {commit}\n"));
+        }
+        text
+    }
+}
+
+/// A results file of `count` entries of alternating machines, with a commit per pair, and
+/// counters large enough to make it a few megabytes.
+#[cfg(test)]
+pub fn results_for_test(count: usize) -> String {
+    use crate::testkit::BenchDataBuilder;
+
+    let mut results = String::new();
+    for i in 0..count {
+        let machine_class = if i % 2 == 0 {
+            "amd-epyc-7763/4"
+        } else {
+            "intel-xeon-platinum-8370c/4"
+        };
+        let data = BenchDataBuilder::new(&format!("{:040x}", i / 2))
+            .commit_timestamp(1_700_000_000 + (i / 2) as u64 * 3600)
+            .machine_class(machine_class)
+            .group("decompress", |mut g| {
+                for level in 0..10 {
+                    g = g.bench(["./decompress".to_owned(), level.to_string()], |b| {
+                        b.counter("cycles", 1e9 + i as f64, 1e6, 20, "")
+                            .counter("instructions", 2e9, 1e6, 20, "")
+                            .counter("task-clock", 300.0, 1.0, 20, "msec")
+                    });
+                }
+                g
+            })
+            .build();
+        results.push_str(&serde_json::to_string(&data).unwrap());
+        results.push('\n');
+    }
+    results
+}
+
+#[cfg(test)]
+fn load_for_test(line: &[u8]) -> Option<BenchData> {
+    serde_json::from_slice(line).ok()
+}
+
+#[test]
+fn stream_lines() {
+    let dir = crate::testkit::test_dir("history-stream");
+    let path = dir.join("results.json");
+    let mut results = results_for_test(400);
+    assert!(results.len() > 1_000_000, "{}", results.len());
+    // Something that isn't results, and a last line that isn't finished.
+    results.insert_str(0, "not json\r\n\n");
+    results.push_str("{\"unfinished\"");
+    fs::write(&path, &results).unwrap();
+
+    let mut lines = vec![];
+    for_each_line(&path, |offset, line| {
+        assert!(results.as_bytes()[offset as usize..].starts_with(line));
+        lines.push(String::from_utf8(line.to_vec()).unwrap());
+        ControlFlow::Continue(())
+    })
+    .unwrap();
+    let expected = results.lines().collect::<Vec<_>>();
+    assert_eq!(lines, expected);
+
+    // Every entry, or up to the first of a commit.
+    let all = read(&path, None, false, load_for_test).unwrap();
+    assert_eq!(all.len(), 400);
+    let commit = format!("{:040x}", 100);
+    let until = read(&path, Some(&commit), false, load_for_test).unwrap();
+    assert_eq!(until.len(), 201);
+    assert_eq!(until[200].commit_hash, commit);
+    assert_eq!(until[200].machine_class.as_deref(), Some("amd-epyc-7763/4"));
+
+    let err = read(&dir.join("missing.json"), None, false, load_for_test).unwrap_err();
+    assert!(err.starts_with("failed to read "), "{err}");
+}
+
+#[test]
+fn maintain_index() {
+    use std::io::Write;
+
+    let dir = crate::testkit::test_dir("history-index");
+    let path = dir.join("results.json");
+    let results = results_for_test(300);
+    fs::write(&path, &results).unwrap();
+
+    let (index, refreshed) = Index::refresh(&path).unwrap();
+    assert_eq!(refreshed, Refreshed::Rebuilt);
+    assert_eq!(index.entries.len(), 300);
+    assert_eq!(index.covered, results.len() as u64);
+    let commit = format!("{:040x}", 42);
+    let offsets = index.offsets(&commit).collect::<Vec<_>>();
+    assert_eq!(offsets.len(), 2);
+    for offset in offsets {
+        let entry = load_for_test(&read_line_at(&path, offset).unwrap()).unwrap();
+        assert_eq!(entry.commit_hash, commit);
+    }
+    assert_eq!(Index::refresh(&path).unwrap(), (index, Refreshed::Fresh));
+
+    // Appended results get indexed, unfinished lines once they are finished.
+    let results = results_for_test(302);
+    let new = results.lines().skip(300).collect::<Vec<_>>();
+    let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+    write!(file, "{}\n{}", new[0], &new[1][..100]).unwrap();
+    let (index, refreshed) = Index::refresh(&path).unwrap();
+    assert_eq!(refreshed, Refreshed::CaughtUp);
+    assert_eq!(index.entries.len(), 301);
+    writeln!(file, "{}", &new[1][100..]).unwrap();
+    let (index, refreshed) = Index::refresh(&path).unwrap();
+    assert_eq!(refreshed, Refreshed::CaughtUp);
+    assert_eq!(index.entries.len(), 302);
+    let commit = format!("{:040x}", 150);
+    assert_eq!(index.offsets(&commit).count(), 2);
+
+    // The baseline is read through the index.
+    let found = read(&path, Some(&commit), true, load_for_test).unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].commit_hash, commit);
+}
+
+#[test]
+fn invalidate_index() {
+    let dir = crate::testkit::test_dir("history-invalidate");
+    let path = dir.join("results.json");
+    let results = results_for_test(200);
+    fs::write(&path, &results).unwrap();
+    Index::refresh(&path).unwrap();
+
+    // Rewritten externally: the same entries in another order, of the same length.
+    let mut lines = results.lines().collect::<Vec<_>>();
+    lines.reverse();
+    fs::write(&path, lines.join("\n") + "\n").unwrap();
+    let (index, refreshed) = Index::refresh(&path).unwrap();
+    assert_eq!(refreshed, Refreshed::Rebuilt);
+    let commit = format!("{:040x}", 99);
+    assert_eq!(
+        index.offsets(&commit).collect::<Vec<_>>(),
+        [0, lines[0].len() as u64 + 1]
+    );
+
+    // Shorter than the index covers, or an index that doesn't parse.
+    fs::write(&path, &results[..results.len() / 2]).unwrap();
+    assert_eq!(Index::refresh(&path).unwrap().1, Refreshed::Rebuilt);
+    fs::write(Index::path(&path), "benchmarker-index 1 many\n").unwrap();
+    assert_eq!(Index::refresh(&path).unwrap().1, Refreshed::Rebuilt);
+
+    // An index that is wrong anyway isn't trusted: the entry is checked, and the file read.
+    fs::write(&path, &results).unwrap();
+    let (mut index, _) = Index::refresh(&path).unwrap();
+    for (_, offset) in &mut index.entries {
+        *offset = 0;
+    }
+    fs::write(Index::path(&path), index.to_text()).unwrap();
+    let commit = format!("{:040x}", 50);
+    let found = read(&path, Some(&commit), true, load_for_test).unwrap();
+    assert_eq!(found.last().unwrap().commit_hash, commit);
+}
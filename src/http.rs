@@ -0,0 +1,307 @@
+//! A minimal HTTP client. We shell out to curl, which is available on every runner and
+//! handles TLS for us.
+
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+/// POST a JSON body to `url`, returning the response body.
+///
+/// A webhook URL is the secret, so it goes to curl in a config on stdin, along with the body,
+/// to keep it out of the process list. The errors leave it out too, as they end up in the log.
+pub fn post_json(url: &str, body: &str, timeout: Duration) -> Result<String, String> {
+    let mut curl = Command::new("curl");
+    curl.arg("--silent")
+        .arg("--show-error")
+        .arg("--fail")
+        .arg("--max-time")
+        .arg(timeout.as_secs_f64().to_string())
+        .arg("--header")
+        .arg("Content-Type: application/json")
+        .arg("--config")
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    let mut child = curl
+        .spawn()
+        .map_err(|e| format!("failed to run curl: {e}"))?;
+    let config = format!(
+        "url = {}\ndata-raw = {}\n",
+        config_string(url),
+        config_string(body)
+    );
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(config.as_bytes())
+        .map_err(|e| format!("failed to write the request: {e}"))?;
+
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("failed to run curl: {e}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "POST failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// `value` as a quoted string of a curl config.
+fn config_string(value: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// The status and the body of a response.
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub body: String,
+}
+
+/// POST a JSON body to the GitHub API at `url`, authorized with `token`. Unlike
+/// [`post_json`], a response with an error status is returned, so the caller can tell the
+/// errors of the API apart.
+pub fn post_github_json(
+    url: &str,
+    body: &str,
+    token: &str,
+    timeout: Duration,
+    scratch: &Path,
+) -> Result<Response, String> {
+    // The token goes through stdin to keep it out of the process list, so the body, which may
+    // be too long for an argument, goes through a file in the scratch directory of the run.
+    let body_path = scratch.join("github-request.json");
+    std::fs::write(&body_path, body)
+        .map_err(|e| format!("failed to write {}: {e}", body_path.display()))?;
+
+    let mut curl = Command::new("curl");
+    curl.arg("--silent")
+        .arg("--show-error")
+        .arg("--max-time")
+        .arg(timeout.as_secs_f64().to_string())
+        .arg("--header")
+        .arg("Content-Type: application/json")
+        .arg("--header")
+        .arg("Accept: application/vnd.github+json")
+        .arg("--header")
+        .arg("@-")
+        .arg("--data-binary")
+        .arg(format!("@{}", body_path.display()))
+        .arg("--write-out")
+        .arg("\\n%{http_code}")
+        .arg(url)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    let output = curl
+        .spawn()
+        .map_err(|e| format!("failed to run curl: {e}"))
+        .and_then(|mut child| {
+            child
+                .stdin
+                .take()
+                .unwrap()
+                .write_all(format!("Authorization: Bearer {token}\n").as_bytes())
+                .map_err(|e| format!("failed to write the request headers: {e}"))?;
+            child
+                .wait_with_output()
+                .map_err(|e| format!("failed to run curl: {e}"))
+        });
+    let _ = std::fs::remove_file(&body_path);
+    let output = output?;
+    if !output.status.success() {
+        return Err(format!(
+            "POST {url} failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let (body, status) = stdout.rsplit_once('\n').unwrap_or(("", &stdout));
+    Ok(Response {
+        status: status
+            .parse()
+            .map_err(|_| format!("POST {url} failed: no status code in {stdout:?}"))?,
+        body: body.to_owned(),
+    })
+}
+
+/// Download `url` to `dest`. When `dest` already exists, for example after a dropped
+/// connection, the download continues where it stopped.
+pub fn download(url: &str, dest: &Path) -> Result<(), String> {
+    let mut curl = Command::new("curl");
+    curl.arg("--fail")
+        .arg("--location")
+        .arg("--show-error")
+        // Progress goes to stderr, stdout is reserved for the results.
+        .arg("--progress-bar")
+        .arg("--continue-at")
+        .arg("-")
+        .arg("--output")
+        .arg(dest)
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null());
+
+    let status = curl
+        .status()
+        .map_err(|e| format!("failed to run curl: {e}"))?;
+    if !status.success() {
+        return Err(format!("GET {url} failed with {status}"));
+    }
+
+    Ok(())
+}
+
+/// A response for [`serve`] to send.
+#[cfg(test)]
+pub struct TestResponse {
+    pub status: u16,
+    pub headers: Vec<(&'static str, String)>,
+    pub body: Vec<u8>,
+}
+
+#[cfg(test)]
+impl TestResponse {
+    pub fn new(status: u16, body: &[u8]) -> Self {
+        TestResponse {
+            status,
+            headers: vec![],
+            body: body.to_vec(),
+        }
+    }
+}
+
+/// A request as received by [`test_server`].
+#[cfg(test)]
+#[derive(Debug)]
+pub struct TestRequest {
+    pub request_line: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+/// Serve one request per given status code on localhost, returning the base url and a handle
+/// resolving to the received requests.
+#[cfg(test)]
+pub fn test_server(statuses: Vec<u16>) -> (String, std::thread::JoinHandle<Vec<TestRequest>>) {
+    serve(
+        statuses
+            .into_iter()
+            .map(|status| TestResponse::new(status, b"ok"))
+            .collect(),
+    )
+}
+
+/// Like [`test_server`], with full control over the responses.
+#[cfg(test)]
+pub fn serve(responses: Vec<TestResponse>) -> (String, std::thread::JoinHandle<Vec<TestRequest>>) {
+    use std::io::{BufRead, BufReader, Read};
+    use std::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    let handle = std::thread::spawn(move || {
+        let mut requests = vec![];
+        for response in responses {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+
+            let mut headers = vec![];
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let line = line.trim_end();
+                if line.is_empty() {
+                    break;
+                }
+                let (name, value) = line.split_once(':').unwrap();
+                headers.push((name.trim().to_ascii_lowercase(), value.trim().to_owned()));
+            }
+
+            let content_length = headers
+                .iter()
+                .find(|(name, _)| name == "content-length")
+                .map_or(0, |(_, value)| value.parse().unwrap());
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+
+            let mut stream = reader.into_inner();
+            write!(stream, "HTTP/1.1 {} Status\r\n", response.status).unwrap();
+            for (name, value) in &response.headers {
+                write!(stream, "{name}: {value}\r\n").unwrap();
+            }
+            write!(
+                stream,
+                "Content-Length: {}\r\nConnection: close\r\n\r\n",
+                response.body.len()
+            )
+            .unwrap();
+            stream.write_all(&response.body).unwrap();
+
+            requests.push(TestRequest {
+                request_line: request_line.trim_end().to_owned(),
+                headers,
+                body,
+            });
+        }
+        requests
+    });
+
+    (url, handle)
+}
+
+#[test]
+fn post_json_to_test_server() {
+    let (url, server) = test_server(vec![200, 500]);
+
+    // Quotes, backslashes and line breaks survive the config.
+    let response = post_json(
+        &format!("{url}/hook"),
+        concat!(r#"{"a":"\"1\\"}"#, "\n"),
+        Duration::from_secs(10),
+    );
+    assert_eq!(response.unwrap(), "ok");
+
+    let response = post_json(
+        &format!("{url}/hook"),
+        r#"{"a":2}"#,
+        Duration::from_secs(10),
+    );
+    assert!(response.unwrap_err().contains("500"));
+
+    let requests = server.join().unwrap();
+    assert_eq!(requests[0].request_line, "POST /hook HTTP/1.1");
+    assert!(requests[0]
+        .headers
+        .contains(&("content-type".to_owned(), "application/json".to_owned())));
+    assert_eq!(
+        requests[0].body,
+        concat!(r#"{"a":"\"1\\"}"#, "\n").as_bytes()
+    );
+    assert_eq!(requests[1].body, br#"{"a":2}"#);
+}
@@ -0,0 +1,447 @@
+//! Results measured by another invocation of the benchmarker, like GPU benchmarks that run in
+//! another job on another machine, shown in the same summary as the commands of this run. An
+//! entry of a group imports them instead of running anything:
+//!
+//! ```json
+//! { "import-results": "artifacts/gpu-bench.json", "select": "gpu-group/2" }
+//! ```
+//!
+//! With `select`, `<group>/<index>`, the file holds the results of a run, or a results file
+//! with one run per line of which the last is used, and the command at that index of the
+//! group is imported. Without it, the file holds the results of a single command. The path is
+//! relative to the working directory. A missing file, group or index fails the command, not
+//! the run, like a command that failed.
+//!
+//! An imported command keeps its command line, its id unless the entry sets one, and its tags
+//! after those of the entry, so it is compared with its baseline like any other. It is tagged with where it came
+//! from, including the class of machine it was measured on, see [`ImportedFrom`]: the
+//! comparisons of a command measured on another class of machine than its baseline, either
+//! way, only compare the `machine-stable-counters`, like those of a whole run.
+
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bench::SingleBench;
+use crate::compare::find_prev_bench;
+use crate::machine::{self, CrossClass};
+use crate::BenchData;
+
+/// An entry of a group that imports a command measured elsewhere.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportResults {
+    pub path: PathBuf,
+    pub select: Option<Selector>,
+}
+
+/// The command at `index` of the group `group`, from `<group>/<index>`. Group names may have a
+/// `/`, the index is after the last one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selector {
+    pub group: String,
+    pub index: usize,
+}
+
+impl Selector {
+    pub fn parse(select: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid `select` `{select}`, expected `<group>/<index>`");
+        let (group, index) = select.rsplit_once('/').ok_or_else(invalid)?;
+        if group.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Selector {
+            group: group.to_owned(),
+            index: index.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// Where an imported command was measured, recorded with its results.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImportedFrom {
+    /// The file it was imported from.
+    pub path: String,
+    /// The commit of the results it was selected from, unknown for a single command.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit_hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub machine_class: Option<String>,
+}
+
+impl ImportResults {
+    /// The command the entry shows as when its import fails.
+    pub fn command(&self) -> String {
+        match &self.select {
+            Some(select) => format!(
+                "import-results {} {}/{}",
+                self.path.display(),
+                select.group,
+                select.index
+            ),
+            None => format!("import-results {}", self.path.display()),
+        }
+    }
+
+    /// The imported command, or a failed one with why it couldn't be imported.
+    pub fn bench(&self) -> SingleBench {
+        self.load().unwrap_or_else(|err| SingleBench {
+            cmd: self.command().split(' ').map(str::to_owned).collect(),
+            id: None,
+            tags: vec![],
+            counters: Default::default(),
+            profile: None,
+            intervals: None,
+            exit_code: None,
+            output_bytes: None,
+            error: Some(err),
+            nondeterministic_output: false,
+            preempted: None,
+            backfilled: vec![],
+            hung: false,
+            imported: None,
+        })
+    }
+
+    /// The imported command, tagged with where it was measured.
+    pub fn load(&self) -> Result<SingleBench, String> {
+        let path = self.path.display().to_string();
+        let json = fs::read_to_string(&self.path)
+            .map_err(|e| format!("failed to read the results to import from {path}: {e}"))?;
+        let Some(select) = &self.select else {
+            let mut bench = serde_json::from_str::<SingleBench>(&json)
+                .map_err(|e| format!("invalid results of a command in {path}: {e}"))?;
+            // Imported again, it is still from where it was first measured.
+            bench.imported.get_or_insert(ImportedFrom {
+                path,
+                commit_hash: None,
+                cpu_model: None,
+                machine_class: None,
+            });
+            return Ok(bench);
+        };
+
+        let data = serde_json::from_str::<BenchData>(&json)
+            .ok()
+            .or_else(|| {
+                json.lines()
+                    .rev()
+                    .find_map(|line| serde_json::from_str::<BenchData>(line).ok())
+            })
+            .ok_or_else(|| format!("no results in {path}"))?;
+        let group_results = data.bench_groups.get(&select.group).ok_or_else(|| {
+            format!(
+                "the results in {path} have no `{}` group, they have {}",
+                select.group,
+                data.bench_groups
+                    .keys()
+                    .map(|group_name| format!("`{group_name}`"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        })?;
+        let mut bench = group_results.get(select.index).cloned().ok_or_else(|| {
+            format!(
+                "the `{}` group of the results in {path} has no command {}, it has {}",
+                select.group,
+                select.index,
+                group_results.len()
+            )
+        })?;
+        bench.imported.get_or_insert(ImportedFrom {
+            path,
+            commit_hash: Some(data.commit_hash.clone()),
+            cpu_model: Some(data.cpu_model.clone()),
+            machine_class: data.machine_class.clone(),
+        });
+        Ok(bench)
+    }
+}
+
+/// The class of machine `bench` of `data` was measured on.
+pub fn machine_class<'a>(data: &'a BenchData, bench: &'a SingleBench) -> Option<&'a str> {
+    match &bench.imported {
+        Some(imported) => imported.machine_class.as_deref(),
+        None => data.machine_class.as_deref(),
+    }
+}
+
+/// The classes of machine of the results being compared, to decide which counters of every
+/// command are compared.
+#[derive(Debug, Clone, Copy)]
+pub struct MachineClasses<'a> {
+    pub before: &'a BenchData,
+    pub after: &'a BenchData,
+    pub stable_counters: &'a [String],
+}
+
+impl MachineClasses<'_> {
+    /// Whether `counter` of `after` is compared with that of `before`: always when both were
+    /// measured on the same class of machine, otherwise only for the stable counters.
+    pub fn compared(&self, before: &SingleBench, after: &SingleBench, counter: &str) -> bool {
+        machine::cross_class(
+            machine_class(self.before, before),
+            machine_class(self.after, after),
+        )
+        .is_none()
+            || machine::is_machine_stable(self.stable_counters, counter)
+    }
+}
+
+/// An imported command measured on another class of machine than its baseline, or compared
+/// with an imported baseline from another class.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ImportedCrossClass {
+    pub group: String,
+    pub command: String,
+    #[serde(flatten)]
+    pub cross_class: CrossClass,
+}
+
+/// The commands of `data` that were imported, or whose baseline was, from another class of
+/// machine than the other side of their comparison.
+pub fn collect_cross_class(data: &BenchData, prev_results: &BenchData) -> Vec<ImportedCrossClass> {
+    let mut commands = vec![];
+    for (group_name, benches) in &data.bench_groups {
+        let Some(prev_group_results) = prev_results.bench_groups.get(group_name) else {
+            continue;
+        };
+        for bench in benches {
+            let Some(prev_bench) = find_prev_bench(prev_group_results, bench) else {
+                continue;
+            };
+            if bench.imported.is_none() && prev_bench.imported.is_none() {
+                continue;
+            }
+            if let Some(cross_class) = machine::cross_class(
+                machine_class(prev_results, prev_bench),
+                machine_class(data, bench),
+            ) {
+                commands.push(ImportedCrossClass {
+                    group: group_name.clone(),
+                    command: bench.cmd.join(" "),
+                    cross_class,
+                });
+            }
+        }
+    }
+    commands
+}
+
+/// Explain why only some counters of the imported commands are compared.
+pub fn render_markdown_note(
+    md: &mut String,
+    commands: &[ImportedCrossClass],
+    stable_counters: &[String],
+) {
+    if commands.is_empty() {
+        return;
+    }
+
+    let counters = stable_counters
+        .iter()
+        .map(|counter| format!("`{counter}`"))
+        .collect::<Vec<_>>();
+    let compared = if counters.is_empty() {
+        "nothing is compared for them".to_owned()
+    } else {
+        format!("only {} are compared for them", counters.join(", "))
+    };
+    writeln!(
+        md,
+        "> [!NOTE]\n> Imported results were measured on a different class of machine than their baseline. \
+         Cycles and times depend on the machine, so {compared}:"
+    )
+    .unwrap();
+    for command in commands {
+        writeln!(
+            md,
+            "> - `{}` in `{}`: `{}` rather than `{}`",
+            command.command, command.group, command.cross_class.before, command.cross_class.after
+        )
+        .unwrap();
+    }
+    writeln!(md).unwrap();
+}
+
+#[cfg(test)]
+fn fixture(name: &str) -> PathBuf {
+    std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("testdata/import")
+        .join(name)
+}
+
+#[test]
+fn parse_selectors() {
+    assert_eq!(
+        Selector::parse("gpu-group/2"),
+        Ok(Selector {
+            group: "gpu-group".to_owned(),
+            index: 2
+        })
+    );
+    assert_eq!(
+        Selector::parse("gpu/fp16/0"),
+        Ok(Selector {
+            group: "gpu/fp16".to_owned(),
+            index: 0
+        })
+    );
+    for invalid in [
+        "gpu-group",
+        "gpu-group/",
+        "/2",
+        "gpu-group/-1",
+        "gpu-group/two",
+    ] {
+        assert_eq!(
+            Selector::parse(invalid),
+            Err(format!(
+                "invalid `select` `{invalid}`, expected `<group>/<index>`"
+            ))
+        );
+    }
+}
+
+#[test]
+fn load_imported_results() {
+    let import = |path: PathBuf, select: Option<&str>| ImportResults {
+        path,
+        select: select.map(|select| Selector::parse(select).unwrap()),
+    };
+
+    // The last results in the file, tagged with the machine they were measured on.
+    let bench = import(fixture("gpu-results.json"), Some("gpu-group/2"))
+        .load()
+        .unwrap();
+    assert_eq!(bench.cmd, ["./gpu-bench", "--kernel", "matmul"]);
+    assert_eq!(bench.id.as_deref(), Some("matmul"));
+    assert_eq!(bench.counters["gpu-time"].value, 41.0);
+    let imported = bench.imported.unwrap();
+    assert_eq!(
+        imported.path,
+        fixture("gpu-results.json").display().to_string()
+    );
+    assert_eq!(
+        imported.commit_hash.as_deref(),
+        Some("2222222222222222222222222222222222222222")
+    );
+    assert_eq!(
+        imported.cpu_model.as_deref(),
+        Some("AMD EPYC 7V13 64-Core Processor")
+    );
+    assert_eq!(imported.machine_class.as_deref(), Some("amd-epyc-7v13/24"));
+
+    // A failed command stays failed.
+    let bench = import(fixture("gpu-results.json"), Some("gpu-group/1"))
+        .load()
+        .unwrap();
+    assert_eq!(bench.error.as_deref(), Some("no data for `gpu-time`"));
+
+    // A single command, from an unknown machine.
+    let bench = import(fixture("single-bench.json"), None).load().unwrap();
+    assert_eq!(bench.cmd, ["./gpu-bench", "--kernel", "fft"]);
+    assert_eq!(bench.imported.unwrap().machine_class, None);
+
+    let err = |path, select| import(path, select).load().unwrap_err();
+    let failed = import(fixture("missing.json"), Some("gpu-group/0")).bench();
+    assert_eq!(
+        failed.cmd.join(" "),
+        format!(
+            "import-results {} gpu-group/0",
+            fixture("missing.json").display()
+        )
+    );
+    assert!(failed.error.is_some());
+    assert!(err(fixture("missing.json"), None)
+        .starts_with("failed to read the results to import from "));
+    assert!(err(fixture("single-bench.json"), Some("gpu-group/0")).starts_with("no results in "));
+    assert!(err(fixture("gpu-results.json"), None).starts_with("invalid results of a command in "));
+    assert!(err(fixture("gpu-results.json"), Some("cpu-group/0"))
+        .ends_with(" have no `cpu-group` group, they have `gpu-group`"));
+    assert!(err(fixture("gpu-results.json"), Some("gpu-group/3"))
+        .ends_with(" has no command 3, it has 3"));
+}
+
+#[test]
+fn imported_machine_classes() {
+    use crate::testkit::BenchDataBuilder;
+
+    let load = |select| {
+        ImportResults {
+            path: fixture("gpu-results.json"),
+            select: Some(Selector::parse(select).unwrap()),
+        }
+        .load()
+        .unwrap()
+    };
+    // This run is on a Dsv5, the GPU commands on their own machine, and the baseline of the
+    // FFT on yet another.
+    let data = |commit_hash, machine_class| {
+        BenchDataBuilder::new(commit_hash)
+            .machine_class(machine_class)
+            .group("cpu", |g| {
+                g.bench(["./compress", "6"], |b| {
+                    b.counter("cycles", 1.0e9, 1.0e6, 20, "")
+                })
+            })
+            .build()
+    };
+    let mut prev = data(
+        "1111111111111111111111111111111111111111",
+        "intel-xeon-platinum-8370c/4",
+    );
+    let mut fft = load("gpu-group/0");
+    fft.imported.as_mut().unwrap().machine_class = Some("amd-epyc-7763/4".to_owned());
+    prev.bench_groups
+        .insert("gpu".to_owned(), vec![load("gpu-group/2"), fft]);
+    let mut data = data(
+        "2222222222222222222222222222222222222222",
+        "intel-xeon-platinum-8370c/4",
+    );
+    data.bench_groups.insert(
+        "gpu".to_owned(),
+        vec![load("gpu-group/2"), load("gpu-group/0")],
+    );
+
+    let commands = collect_cross_class(&data, &prev);
+    assert_eq!(
+        commands,
+        [ImportedCrossClass {
+            group: "gpu".to_owned(),
+            command: "./gpu-bench --kernel fft".to_owned(),
+            cross_class: CrossClass {
+                before: "amd-epyc-7763/4".to_owned(),
+                after: "amd-epyc-7v13/24".to_owned(),
+            },
+        }]
+    );
+
+    // Only the command measured on another class is restricted to the stable counters.
+    let stable_counters = machine::default_machine_stable_counters();
+    let classes = MachineClasses {
+        before: &prev,
+        after: &data,
+        stable_counters: &stable_counters,
+    };
+    let compared = |group: &str, index: usize, counter| {
+        let bench = &data.bench_groups[group][index];
+        let prev_bench = find_prev_bench(&prev.bench_groups[group], bench).unwrap();
+        classes.compared(prev_bench, bench, counter)
+    };
+    assert!(compared("cpu", 0, "cycles"));
+    assert!(compared("gpu", 0, "cycles"));
+    assert!(!compared("gpu", 1, "gpu-time"));
+
+    let mut md = String::new();
+    render_markdown_note(&mut md, &commands, &stable_counters);
+    assert_eq!(
+        md,
+        "> [!NOTE]\n> Imported results were measured on a different class of machine than their baseline. \
+         Cycles and times depend on the machine, so only `instructions` are compared for them:\n\
+         > - `./gpu-bench --kernel fft` in `gpu`: `amd-epyc-7763/4` rather than `amd-epyc-7v13/24`\n\n"
+    );
+}
@@ -0,0 +1,480 @@
+//! Interleaved repetitions for the groups with `interleave-for-group`. Running all repetitions
+//! of one command and then all of the next lets slow drift, like the CPU heating up or caches
+//! filling, bias the `render-versus-self` comparison between them. The commands of a group
+//! that are compared with each other are instead run in alternating single repetitions, A, B,
+//! A, B, ..., and the runs of every command are aggregated as usual.
+
+use crate::bench::{merge_counters, Backend, CommandSpec, Measurement, SingleBench};
+use crate::flush::Flusher;
+use crate::seed::Rng;
+use crate::sentinel;
+use crate::verify_output::Hashes;
+use crate::watchdog::{self, Watched};
+use crate::Config;
+
+/// A part of the schedule of a group.
+#[derive(Debug, PartialEq)]
+pub enum Step {
+    /// A command that isn't compared within the group, measured as usual.
+    Alone(usize),
+    /// Commands that are compared with each other, directly or through another command, run
+    /// one repetition at a time in this order.
+    Interleaved(Vec<usize>),
+}
+
+impl Step {
+    pub fn commands(&self) -> &[usize] {
+        match self {
+            Step::Alone(index) => std::slice::from_ref(index),
+            Step::Interleaved(indices) => indices,
+        }
+    }
+}
+
+/// The pairs of commands of the group that a `render-versus-self` row compares. Rows
+/// comparing against another group can't be interleaved, as the groups run one after the
+/// other. Both measurements of the sentinel group are interleaved like the one the rows refer
+/// to, see [`crate::sentinel`].
+pub fn pairs(config: &Config, group_name: &str) -> Vec<(usize, usize)> {
+    let group_name = sentinel::referenced_group(config, group_name);
+    config
+        .render_versus_self
+        .values()
+        .flat_map(|table| table.rows.values())
+        .filter(|row| row.before.command == group_name && row.after.command == group_name)
+        .map(|row| (row.before.index, row.after.index))
+        .collect()
+}
+
+/// The schedule of a group of `commands` commands: the commands connected by `pairs` are
+/// interleaved together, so a command in several pairs is interleaved with all commands it is
+/// compared with. The steps are in the order of their first command, and the commands of an
+/// interleaved step in config order. Pairs with a command outside of the group are ignored.
+pub fn schedule(commands: usize, pairs: &[(usize, usize)]) -> Vec<Step> {
+    fn root(component: &mut [usize], mut index: usize) -> usize {
+        while component[index] != index {
+            component[index] = component[component[index]];
+            index = component[index];
+        }
+        index
+    }
+
+    // Every command points towards the lowest index of the commands connected to it.
+    let mut component = (0..commands).collect::<Vec<_>>();
+    for &(a, b) in pairs {
+        if a >= commands || b >= commands {
+            continue;
+        }
+        let (a, b) = (root(&mut component, a), root(&mut component, b));
+        component[a.max(b)] = a.min(b);
+    }
+
+    let mut steps: Vec<Step> = vec![];
+    for index in 0..commands {
+        let first = root(&mut component, index);
+        if first == index {
+            steps.push(Step::Alone(index));
+            continue;
+        }
+        let step = steps
+            .iter_mut()
+            .find(|step| step.commands()[0] == first)
+            .unwrap();
+        match step {
+            Step::Alone(first) => *step = Step::Interleaved(vec![*first, index]),
+            Step::Interleaved(indices) => indices.push(index),
+        }
+    }
+    steps
+}
+
+/// Measure `cmds` with every backend in alternating single repetitions. Backends that warm up
+/// before measuring get the same number of unmeasured runs of every command first, also
+/// interleaved. With a `flusher`, the caches are flushed before every run of a command that
+/// follows a run of another one, see [`crate::flush`]. The output of the commands with
+/// `verify-output` is hashed after every measured run, see [`crate::verify_output`]. With an
+/// `rng`, the commands run in a random order in every round, see [`crate::seed`].
+pub fn bench_interleaved(
+    cmds: &[CommandSpec],
+    repetitions: u32,
+    backends: &[Box<dyn Backend>],
+    mut flusher: Option<&mut Flusher>,
+    mut rng: Option<&mut Rng>,
+) -> Result<Vec<SingleBench>, String> {
+    eprintln!(
+        "Benchmarking {} interleaved",
+        cmds.iter()
+            .map(|cmd| cmd.argv.join(" "))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    let warmup_runs = backends
+        .iter()
+        .map(|backend| backend.warmup_runs())
+        .max()
+        .unwrap_or(0);
+    // The runs of every command, by backend.
+    let mut runs = cmds
+        .iter()
+        .map(|_| backends.iter().map(|_| vec![]).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let mut hashes = cmds.iter().map(|_| Hashes::default()).collect::<Vec<_>>();
+    // Why the commands the watchdog killed hung, they sit out the rest of the rounds.
+    let mut hung = cmds.iter().map(|_| None).collect::<Vec<_>>();
+    let mut previous = None;
+    let mut order = (0..cmds.len()).collect::<Vec<_>>();
+    for round in 0..warmup_runs + repetitions {
+        if let Some(rng) = rng.as_deref_mut() {
+            rng.shuffle(&mut order);
+        }
+        for &index in &order {
+            let (cmd, hashes) = (&cmds[index], &mut hashes[index]);
+            for (backend, runs) in backends.iter().zip(&mut runs[index]) {
+                if round + backend.warmup_runs() < warmup_runs || hung[index].is_some() {
+                    continue;
+                }
+                if let Some(flusher) = flusher.as_deref_mut() {
+                    if previous.is_some_and(|previous| previous != index) {
+                        flusher.flush()?;
+                    }
+                }
+                previous = Some(index);
+                hung[index] = run_once(cmd, backend.as_ref(), round < warmup_runs, runs, hashes)?;
+            }
+        }
+    }
+
+    cmds.iter()
+        .zip(runs)
+        .zip(hashes)
+        .zip(hung)
+        .map(|(((cmd, runs), hashes), hung)| aggregate(cmd, backends, runs, hashes, hung))
+        .collect()
+}
+
+/// Run `cmd` once with the `backend`, watched by its watchdog. Unless it's a `warmup` run, the
+/// measurement goes to the `runs` and the output is hashed. Why the watchdog killed it, if it
+/// did.
+pub fn run_once(
+    cmd: &CommandSpec,
+    backend: &dyn Backend,
+    warmup: bool,
+    runs: &mut Vec<Measurement>,
+    hashes: &mut Hashes,
+) -> Result<Option<String>, String> {
+    let watched = watchdog::watch(cmd.watchdog.as_ref(), &cmd.argv, || {
+        backend.measure_once(cmd)
+    })?;
+    let measurement = match watched {
+        Watched::Finished(measurement) => measurement,
+        Watched::Hung(why) => return Ok(Some(why)),
+    };
+    if !warmup {
+        runs.push(measurement);
+        if let Some(verify) = &cmd.verify_output {
+            hashes.record(verify);
+        }
+    }
+    Ok(None)
+}
+
+/// The result of `cmd` from its single `runs` by each of the `backends`, like that of
+/// measuring all repetitions at once. A command the watchdog killed, and why, has no counters.
+pub fn aggregate(
+    cmd: &CommandSpec,
+    backends: &[Box<dyn Backend>],
+    runs: Vec<Vec<Measurement>>,
+    hashes: Hashes,
+    hung: Option<String>,
+) -> Result<SingleBench, String> {
+    if let Some(why) = hung {
+        return Ok(SingleBench {
+            counters: Default::default(),
+            cmd: cmd.argv.clone(),
+            id: None,
+            tags: vec![],
+            profile: None,
+            intervals: None,
+            exit_code: None,
+            output_bytes: None,
+            error: Some(why),
+            nondeterministic_output: false,
+            preempted: None,
+            backfilled: vec![],
+            hung: true,
+            imported: None,
+        });
+    }
+    let exit_code = runs.iter().filter_map(|runs| runs.last()?.exit_code).next();
+    let error = runs.iter().flatten().find_map(|run| run.error.clone());
+    let mut preempted = None;
+    let mut measured = vec![];
+    for (backend, runs) in backends.iter().zip(&runs) {
+        let usage = runs
+            .iter()
+            .flat_map(|run| run.runs.iter().cloned())
+            .collect::<Vec<_>>();
+        let excluded = cmd
+            .preemption
+            .as_ref()
+            .and_then(|preemption| preemption.exclude(&usage));
+        match excluded {
+            Some((counters, excluded)) => {
+                preempted = Some(excluded);
+                measured.push((backend.name(), counters));
+            }
+            None => measured.push((backend.name(), backend.aggregate(runs))),
+        }
+    }
+    let mut bench = SingleBench {
+        counters: merge_counters(measured)?,
+        cmd: cmd.argv.clone(),
+        id: None,
+        tags: vec![],
+        profile: None,
+        intervals: None,
+        exit_code,
+        output_bytes: None,
+        error,
+        nondeterministic_output: false,
+        preempted,
+        backfilled: vec![],
+        hung: false,
+        imported: None,
+    };
+    hashes.apply(&mut bench);
+    Ok(bench)
+}
+
+#[test]
+fn schedule_from_pairs() {
+    let commands = |steps: &[Step]| {
+        steps
+            .iter()
+            .map(|step| match step {
+                Step::Alone(index) => format!("{index}"),
+                Step::Interleaved(indices) => format!("{indices:?}"),
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // Without pairs, every command is measured as usual.
+    assert_eq!(commands(&schedule(3, &[])), ["0", "1", "2"]);
+
+    // Commands compared with each other are interleaved, in config order, and keep the place
+    // of their first command.
+    assert_eq!(commands(&schedule(5, &[(3, 1)])), ["0", "[1, 3]", "2", "4"]);
+    assert_eq!(
+        commands(&schedule(4, &[(0, 2), (1, 3)])),
+        ["[0, 2]", "[1, 3]"]
+    );
+
+    // A command in several pairs is interleaved with every command it is compared with,
+    // also through another command.
+    assert_eq!(
+        commands(&schedule(4, &[(0, 1), (0, 2)])),
+        ["[0, 1, 2]", "3"]
+    );
+    assert_eq!(
+        commands(&schedule(5, &[(3, 4), (1, 2), (2, 3)])),
+        ["0", "[1, 2, 3, 4]"]
+    );
+    assert_eq!(
+        commands(&schedule(4, &[(2, 3), (0, 1), (1, 3), (3, 0)])),
+        ["[0, 1, 2, 3]"]
+    );
+
+    // Duplicated pairs, a command compared with itself and commands the group doesn't have.
+    assert_eq!(
+        commands(&schedule(3, &[(0, 1), (1, 0), (2, 2), (1, 7)])),
+        ["[0, 1]", "2"]
+    );
+}
+
+#[test]
+fn pairs_within_the_group() {
+    let config: Config = serde_json::from_str(
+        r#"{
+            "commands": {
+                "compress": ["./c ng 1", "./c rs 1", "./c ng 9", "./c rs 9"],
+                "decompress": ["./d ng", "./d rs"]
+            },
+            "render-versus-self": {
+                "ng vs rs": {
+                    "level 1": { "measure": "cycles", "before": { "command": "compress", "index": 0 }, "after": { "command": "compress", "index": 1 } },
+                    "level 9": { "measure": "cycles", "before": { "command": "compress", "index": 2 }, "after": { "command": "compress", "index": 3 } },
+                    "decompress": { "measure": "cycles", "before": { "command": "decompress", "index": 0 }, "after": { "command": "decompress", "index": 1 } }
+                },
+                "levels": {
+                    "rs": { "measure": "instructions", "before": { "command": "compress", "index": 1 }, "after": { "command": "compress", "index": 3 } },
+                    "across groups": { "measure": "cycles", "before": { "command": "compress", "index": 0 }, "after": { "command": "decompress", "index": 0 } }
+                }
+            },
+            "render-versus-other": {}
+        }"#,
+    )
+    .unwrap();
+
+    let compress = pairs(&config, "compress");
+    assert_eq!(compress, [(0, 1), (2, 3), (1, 3)]);
+    assert_eq!(
+        schedule(4, &compress),
+        [Step::Interleaved(vec![0, 1, 2, 3])]
+    );
+    assert_eq!(pairs(&config, "decompress"), [(0, 1)]);
+    assert!(pairs(&config, "other").is_empty());
+}
+
+/// Logs the runs of all backends in order, and counts every run with its number in the log.
+#[cfg(test)]
+pub struct RecordingBackend {
+    pub name: &'static str,
+    pub warmup_runs: u32,
+    pub log: std::rc::Rc<std::cell::RefCell<Vec<String>>>,
+}
+
+#[cfg(test)]
+impl Backend for RecordingBackend {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn measure(
+        &self,
+        _cmd: &CommandSpec,
+        _repetitions: u32,
+    ) -> Result<crate::bench::Measurement, String> {
+        unreachable!("interleaved commands are measured one run at a time")
+    }
+
+    fn warmup_runs(&self) -> u32 {
+        self.warmup_runs
+    }
+
+    fn measure_once(&self, cmd: &CommandSpec) -> Result<crate::bench::Measurement, String> {
+        let mut log = self.log.borrow_mut();
+        log.push(format!("{} {}", self.name, cmd.argv.join(" ")));
+        let counter = crate::bench::BenchCounter {
+            value: log.len() as f64,
+            variance: 0.0,
+            repetitions: 1,
+            unit: String::new(),
+        };
+        Ok(crate::bench::Measurement {
+            counters: [(format!("{}-runs", self.name), counter)].into(),
+            exit_code: Some(0),
+            perf_output: None,
+            runs: vec![],
+            error: None,
+        })
+    }
+}
+
+#[test]
+fn interleaved_runs() {
+    let log = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+    let backends: Vec<Box<dyn Backend>> = vec![
+        Box::new(RecordingBackend {
+            name: "a",
+            warmup_runs: 0,
+            log: log.clone(),
+        }),
+        Box::new(RecordingBackend {
+            name: "b",
+            warmup_runs: 1,
+            log: log.clone(),
+        }),
+    ];
+    let cmds = [
+        CommandSpec::new(vec!["./ng".to_owned()]),
+        CommandSpec::new(vec!["./rs".to_owned()]),
+    ];
+
+    let results = bench_interleaved(&cmds, 2, &backends, None, None).unwrap();
+    // Only `b` warms up, and every backend measures every command in every round.
+    assert_eq!(
+        *log.borrow(),
+        [
+            "b ./ng", "b ./rs", //
+            "a ./ng", "b ./ng", "a ./rs", "b ./rs", //
+            "a ./ng", "b ./ng", "a ./rs", "b ./rs",
+        ]
+    );
+
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].cmd, ["./ng"]);
+    assert_eq!(results[0].exit_code, Some(0));
+    // The mean and the sample variance of the runs, without the warmup ones.
+    let counters = &results[0].counters;
+    assert_eq!(counters.keys().collect::<Vec<_>>(), ["a-runs", "b-runs"]);
+    assert_eq!(counters["a-runs"].value, 5.0);
+    assert_eq!(counters["a-runs"].variance, 8.0);
+    assert_eq!(counters["a-runs"].repetitions, 2);
+    assert_eq!(counters["b-runs"].value, 6.0);
+    assert_eq!(results[1].counters["a-runs"].value, 7.0);
+}
+
+#[test]
+fn shuffled_rounds() {
+    let log = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+    let backends: Vec<Box<dyn Backend>> = vec![Box::new(RecordingBackend {
+        name: "a",
+        warmup_runs: 0,
+        log: log.clone(),
+    })];
+    let cmds = ["./a", "./b", "./c", "./d"].map(|cmd| CommandSpec::new(vec![cmd.to_owned()]));
+    let run = |seed| {
+        log.borrow_mut().clear();
+        let results =
+            bench_interleaved(&cmds, 5, &backends, None, Some(&mut Rng::new(seed))).unwrap();
+        // The results are still in the order of the commands.
+        assert_eq!(results[3].cmd, ["./d"]);
+        log.borrow().clone()
+    };
+
+    // Every round runs every command once, in an order that only depends on the seed.
+    let shuffled = run(42);
+    for round in shuffled.chunks(4) {
+        let mut round = round.to_vec();
+        round.sort();
+        assert_eq!(round, ["a ./a", "a ./b", "a ./c", "a ./d"]);
+    }
+    assert_eq!(run(42), shuffled);
+    assert_ne!(run(43), shuffled);
+}
+
+#[test]
+fn flush_between_commands() {
+    let log = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+    let backends: Vec<Box<dyn Backend>> = vec![
+        Box::new(RecordingBackend {
+            name: "a",
+            warmup_runs: 0,
+            log: log.clone(),
+        }),
+        Box::new(RecordingBackend {
+            name: "b",
+            warmup_runs: 1,
+            log: log.clone(),
+        }),
+    ];
+    let cmds = [
+        CommandSpec::new(vec!["./ng".to_owned()]),
+        CommandSpec::new(vec!["./rs".to_owned()]),
+    ];
+    let mut flusher = Flusher::new(&crate::flush::FlushConfig {
+        buffer: Some(1 << 10),
+        command: None,
+    });
+
+    // Between every run of a command and the next one of the other, also after the warmup
+    // round, but not between the backends measuring the same command.
+    bench_interleaved(&cmds, 2, &backends, Some(&mut flusher), None).unwrap();
+    assert_eq!(log.borrow().len(), 10);
+    assert_eq!(flusher.flushes, 5);
+
+    // A single command is never flushed.
+    bench_interleaved(&cmds[..1], 2, &backends, Some(&mut flusher), None).unwrap();
+    assert_eq!(flusher.flushes, 5);
+}
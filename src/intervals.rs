@@ -0,0 +1,439 @@
+//! The course of a counter over a single run, recorded with `perf stat -I`, for commands with
+//! phases. A regression in a short setup phase hardly moves the total, but changes the shape.
+
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use std::process::Stdio;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bench::CommandSpec;
+use crate::command_display::CommandDisplay;
+use crate::compare::find_prev_bench;
+use crate::{scratch, BenchData};
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct IntervalConfig {
+    /// The counter to record the course of.
+    #[serde(default = "default_counter")]
+    pub counter: String,
+    /// The most points to keep per command, longer series are downsampled.
+    #[serde(default = "default_max_points")]
+    pub max_points: usize,
+    /// Flag commands whose series correlates less than this with that of the baseline.
+    #[serde(default = "default_min_correlation")]
+    pub min_correlation: f64,
+}
+
+fn default_counter() -> String {
+    "cycles".to_owned()
+}
+
+fn default_max_points() -> usize {
+    32
+}
+
+fn default_min_correlation() -> f64 {
+    0.5
+}
+
+impl Default for IntervalConfig {
+    fn default() -> Self {
+        IntervalConfig {
+            counter: default_counter(),
+            max_points: default_max_points(),
+            min_correlation: default_min_correlation(),
+        }
+    }
+}
+
+/// The counter per interval of a single run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IntervalSeries {
+    pub counter: String,
+    /// The length of an interval before downsampling.
+    pub interval_ms: u32,
+    pub values: Vec<f64>,
+}
+
+/// Record the series of a single run of the command, after the measurements.
+///
+/// Like profiles, the series is only informational, so this returns `None` rather than an
+/// error when perf fails. The output of perf goes into the scratch directory.
+pub fn record(
+    perf: &Path,
+    scratch: &Path,
+    cmd: &CommandSpec,
+    interval_ms: u32,
+    config: &IntervalConfig,
+) -> Option<IntervalSeries> {
+    let perf_output = scratch::file_path(scratch, "intervals", "json");
+    let status = cmd
+        .command(perf)
+        // Perf produces broken JSON when the system locale uses decimal comma rather than decimal point.
+        .env("LANG", "C")
+        .arg("stat")
+        .arg("-I")
+        .arg(interval_ms.to_string())
+        .arg("-j")
+        .arg("-e")
+        .arg(&config.counter)
+        .arg("-o")
+        .arg(&perf_output)
+        .arg("--")
+        .args(&cmd.argv)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    let output = fs::read(&perf_output);
+    let _ = fs::remove_file(&perf_output);
+    cmd.expected_exit_code(status.ok()?)?;
+
+    let values = parse_interval_output(&output.ok()?, &config.counter).ok()?;
+    Some(IntervalSeries {
+        counter: config.counter.clone(),
+        interval_ms,
+        values: downsample(&values, config.max_points),
+    })
+}
+
+/// Parse the output of `perf stat -I <ms> -j` into the values of `counter` per interval.
+///
+/// Every interval has a line per event, with the end of the interval in seconds. Intervals in
+/// which the counter wasn't counted are 0.
+pub fn parse_interval_output(output: &[u8], counter: &str) -> Result<Vec<f64>, String> {
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "kebab-case")]
+    struct IntervalData {
+        interval: f64,
+        event: String,
+        counter_value: String,
+    }
+
+    let hybrid = format!("cpu_core/{counter}/");
+    let mut values = vec![];
+    let mut last_interval = f64::NEG_INFINITY;
+    for line in output.split(|&b| b == b'\n') {
+        let line = line.trim_ascii();
+        if !line.starts_with(b"{") {
+            continue;
+        }
+
+        let data = serde_json::from_slice::<IntervalData>(line)
+            .map_err(|e| format!("Failed to parse {:?}: {e}", String::from_utf8_lossy(line)))?;
+        if data.event != counter && data.event != hybrid {
+            continue;
+        }
+        if data.interval <= last_interval {
+            return Err(format!(
+                "the intervals of `{counter}` are out of order at {}",
+                data.interval
+            ));
+        }
+        last_interval = data.interval;
+
+        values.push(match &*data.counter_value {
+            "<not counted>" | "<not supported>" => 0.0,
+            value => value
+                .parse::<f64>()
+                .map_err(|_| format!("Failed to parse {value}"))?,
+        });
+    }
+
+    Ok(values)
+}
+
+/// At most `max_points` values, each the mean of a run of consecutive values.
+pub fn downsample(values: &[f64], max_points: usize) -> Vec<f64> {
+    if values.len() <= max_points || max_points == 0 {
+        return values.to_vec();
+    }
+
+    (0..max_points)
+        .map(|point| {
+            let bucket =
+                &values[point * values.len() / max_points..(point + 1) * values.len() / max_points];
+            bucket.iter().sum::<f64>() / bucket.len() as f64
+        })
+        .collect()
+}
+
+/// The series as a line of block characters, from its minimum to its maximum.
+pub fn sparkline(values: &[f64]) -> String {
+    const BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    values
+        .iter()
+        .map(|&value| {
+            if max > min {
+                BLOCKS[((value - min) / (max - min) * 7.0).round() as usize]
+            } else {
+                BLOCKS[0]
+            }
+        })
+        .collect()
+}
+
+/// The Pearson correlation of the two series, after bringing the longer one down to the
+/// length of the shorter one. `None` when there is nothing to correlate: fewer than 3 points,
+/// or a flat series.
+pub fn correlation(a: &[f64], b: &[f64]) -> Option<f64> {
+    let len = a.len().min(b.len());
+    if len < 3 {
+        return None;
+    }
+    let (a, b) = (downsample(a, len), downsample(b, len));
+
+    let mean = |values: &[f64]| values.iter().sum::<f64>() / values.len() as f64;
+    let (mean_a, mean_b) = (mean(&a), mean(&b));
+    let (mut covariance, mut variance_a, mut variance_b) = (0.0, 0.0, 0.0);
+    for (a, b) in a.iter().zip(&b) {
+        covariance += (a - mean_a) * (b - mean_b);
+        variance_a += (a - mean_a).powi(2);
+        variance_b += (b - mean_b).powi(2);
+    }
+    if variance_a == 0.0 || variance_b == 0.0 {
+        return None;
+    }
+
+    Some(covariance / (variance_a * variance_b).sqrt())
+}
+
+/// A command whose series changed shape compared to the baseline.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ShapeChange {
+    pub group: String,
+    pub command: String,
+    pub counter: String,
+    pub correlation: f64,
+}
+
+/// The commands whose series correlates less than `min_correlation` with the series of the
+/// same counter in the previous results.
+pub fn collect_shape_changes(
+    config: &IntervalConfig,
+    data: &BenchData,
+    prev_results: &BenchData,
+) -> Vec<ShapeChange> {
+    let mut changes = vec![];
+    for (group_name, benches) in &data.bench_groups {
+        let Some(prev_benches) = prev_results.bench_groups.get(group_name) else {
+            continue;
+        };
+        for bench in benches {
+            let Some(series) = &bench.intervals else {
+                continue;
+            };
+            let Some(prev_series) = find_prev_bench(prev_benches, bench)
+                .and_then(|prev_bench| prev_bench.intervals.as_ref())
+                .filter(|prev_series| prev_series.counter == series.counter)
+            else {
+                continue;
+            };
+
+            if let Some(correlation) = correlation(&prev_series.values, &series.values)
+                .filter(|&correlation| correlation < config.min_correlation)
+            {
+                changes.push(ShapeChange {
+                    group: group_name.clone(),
+                    command: bench.cmd.join(" "),
+                    counter: series.counter.clone(),
+                    correlation,
+                });
+            }
+        }
+    }
+    changes
+}
+
+/// The shape changes of a group, to go under its raw table.
+pub fn render_markdown_shape_changes(
+    md: &mut String,
+    group_name: &str,
+    changes: &[ShapeChange],
+    commands: CommandDisplay,
+) {
+    let changes = changes
+        .iter()
+        .filter(|change| change.group == group_name)
+        .collect::<Vec<_>>();
+    if changes.is_empty() {
+        return;
+    }
+
+    writeln!(md).unwrap();
+    let mut cells = commands.cells();
+    for change in changes {
+        writeln!(
+            md,
+            "- ⚠️ {}: the course of `{}` over the run changed shape (correlation {:.2} with the baseline)",
+            cells.code(&change.command), change.counter, change.correlation
+        )
+        .unwrap();
+    }
+    cells.render_footnotes(md);
+}
+
+#[cfg(test)]
+const PERF_STAT_INTERVAL_OUTPUT: &[u8] = include_bytes!("../testdata/perf/stat-interval.txt");
+
+#[test]
+fn parse_intervals() {
+    let cycles = parse_interval_output(PERF_STAT_INTERVAL_OUTPUT, "cycles").unwrap();
+    assert_eq!(cycles.len(), 12);
+    assert_eq!(cycles[0], 3.1e8);
+    assert_eq!(cycles[3], 0.9e8);
+    assert_eq!(cycles[11], 0.4e8);
+
+    let instructions = parse_interval_output(PERF_STAT_INTERVAL_OUTPUT, "instructions").unwrap();
+    assert_eq!(instructions[0], 9.0e8);
+    assert!(parse_interval_output(PERF_STAT_INTERVAL_OUTPUT, "branches")
+        .unwrap()
+        .is_empty());
+
+    // Hybrid CPUs, an interval in which nothing was counted, and garbage around the lines.
+    let output = b"garbage\n\
+        {\"interval\" : 0.100100100, \"counter-value\" : \"12.000000\", \"unit\" : \"\", \"event\" : \"cpu_core/cycles/\", \"event-runtime\" : 100, \"pcnt-running\" : 100.00}\n\
+        {\"interval\" : 0.100100100, \"counter-value\" : \"5.000000\", \"unit\" : \"\", \"event\" : \"cpu_atom/cycles/\", \"event-runtime\" : 100, \"pcnt-running\" : 100.00}\n\
+        {\"interval\" : 0.200200200, \"counter-value\" : \"<not counted>\", \"unit\" : \"\", \"event\" : \"cpu_core/cycles/\", \"event-runtime\" : 0, \"pcnt-running\" : 0.00}\n\
+        \xff\xfe\n";
+    assert_eq!(
+        parse_interval_output(output, "cycles").unwrap(),
+        [12.0, 0.0]
+    );
+
+    assert!(parse_interval_output(b"{\"interval\" : 0.1}", "cycles").is_err());
+    let out_of_order = b"{\"interval\" : 0.2, \"counter-value\" : \"1\", \"event\" : \"cycles\"}\n\
+        {\"interval\" : 0.1, \"counter-value\" : \"1\", \"event\" : \"cycles\"}\n";
+    assert_eq!(
+        parse_interval_output(out_of_order, "cycles").unwrap_err(),
+        "the intervals of `cycles` are out of order at 0.1"
+    );
+}
+
+#[test]
+fn downsample_series() {
+    let values = (1..=10).map(f64::from).collect::<Vec<_>>();
+    assert_eq!(downsample(&values, 20), values);
+    assert_eq!(downsample(&values, 10), values);
+    assert_eq!(downsample(&values, 5), [1.5, 3.5, 5.5, 7.5, 9.5]);
+    // Uneven buckets still cover every value exactly once.
+    assert_eq!(downsample(&values, 3), [2.0, 5.0, 8.5]);
+    assert_eq!(downsample(&values, 1), [5.5]);
+    assert_eq!(downsample(&[], 3), [] as [f64; 0]);
+
+    let cycles = parse_interval_output(PERF_STAT_INTERVAL_OUTPUT, "cycles").unwrap();
+    let downsampled = downsample(&cycles, 4);
+    assert_eq!(downsampled.len(), 4);
+    assert!((downsampled[0] - 3.1e8).abs() < 1.0);
+    assert_eq!(sparkline(&downsampled), "█▁▂▁");
+}
+
+#[test]
+fn render_sparkline() {
+    assert_eq!(
+        sparkline(&[0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0]),
+        "▁▂▃▄▅▆▇█"
+    );
+    assert_eq!(sparkline(&[3.0, 3.0]), "▁▁");
+    assert_eq!(sparkline(&[]), "");
+
+    let cycles = parse_interval_output(PERF_STAT_INTERVAL_OUTPUT, "cycles").unwrap();
+    assert_eq!(sparkline(&cycles), "███▂▂▂▂▂▂▂▂▁");
+}
+
+#[test]
+fn shape_correlation() {
+    let cycles = parse_interval_output(PERF_STAT_INTERVAL_OUTPUT, "cycles").unwrap();
+    // Scaling doesn't change the shape.
+    let faster = cycles.iter().map(|value| value * 0.8).collect::<Vec<_>>();
+    assert!((correlation(&cycles, &faster).unwrap() - 1.0).abs() < 1e-12);
+
+    // A slower setup phase: the first phase takes twice as long.
+    let mut slow_setup = cycles[..3].to_vec();
+    slow_setup.extend_from_slice(&cycles);
+    let correlation_slow_setup = correlation(&cycles, &slow_setup).unwrap();
+    assert!(
+        (0.6..0.7).contains(&correlation_slow_setup),
+        "{correlation_slow_setup}"
+    );
+
+    // A series twice as long is brought down to the shorter one first.
+    let twice = cycles
+        .iter()
+        .flat_map(|&value| [value, value])
+        .collect::<Vec<_>>();
+    assert!(correlation(&cycles, &twice).unwrap() > 0.999);
+
+    // Reversed.
+    let reversed = cycles.iter().rev().copied().collect::<Vec<_>>();
+    assert!(correlation(&cycles, &reversed).unwrap() < 0.0);
+
+    assert_eq!(correlation(&cycles, &[1.0, 2.0]), None);
+    assert_eq!(correlation(&cycles, &[1.0; 12]), None);
+}
+
+#[test]
+fn flag_shape_changes() {
+    let cycles = parse_interval_output(PERF_STAT_INTERVAL_OUTPUT, "cycles").unwrap();
+    let series = |values: &[f64]| IntervalSeries {
+        counter: "cycles".to_owned(),
+        interval_ms: 100,
+        values: values.to_vec(),
+    };
+    let mut slow_setup = cycles[..3].to_vec();
+    slow_setup.extend_from_slice(&cycles);
+
+    let build = |commit: &str, a: &[f64], b: &[f64]| {
+        let mut data = crate::testkit::BenchDataBuilder::new(commit)
+            .group("compress", |g| {
+                g.bench(["./c", "1"], |b| b.counter("cycles", 1e9, 0.0, 20, ""))
+                    .bench(["./c", "6"], |b| b.counter("cycles", 1e9, 0.0, 20, ""))
+            })
+            .build();
+        let benches = data.bench_groups.get_mut("compress").unwrap();
+        benches[0].intervals = Some(series(a));
+        benches[1].intervals = Some(series(b));
+        data
+    };
+    let prev = build("1111111111111111111111111111111111111111", &cycles, &cycles);
+    let data = build(
+        "2222222222222222222222222222222222222222",
+        &cycles,
+        &slow_setup,
+    );
+
+    let config = IntervalConfig {
+        min_correlation: 0.8,
+        ..IntervalConfig::default()
+    };
+    let changes = collect_shape_changes(&config, &data, &prev);
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].command, "./c 6");
+    assert!(changes[0].correlation < 0.8);
+    // The default threshold is for drastic changes only.
+    assert!(collect_shape_changes(&IntervalConfig::default(), &data, &prev).is_empty());
+
+    // Another counter in the baseline isn't compared.
+    let mut other_counter = prev.clone();
+    for bench in other_counter.bench_groups.get_mut("compress").unwrap() {
+        bench.intervals.as_mut().unwrap().counter = "instructions".to_owned();
+    }
+    assert!(collect_shape_changes(&config, &data, &other_counter).is_empty());
+
+    let mut md = String::new();
+    render_markdown_shape_changes(&mut md, "decompress", &changes, CommandDisplay::default());
+    assert_eq!(md, "");
+    render_markdown_shape_changes(&mut md, "compress", &changes, CommandDisplay::default());
+    assert_eq!(
+        md,
+        format!(
+            "\n- ⚠️ `./c 6`: the course of `cycles` over the run changed shape (correlation {:.2} with the baseline)\n",
+            changes[0].correlation
+        )
+    );
+}
@@ -0,0 +1,201 @@
+//! The I/O of the commands of the groups with `io-counters-for-group`, from an unmeasured run
+//! after the measurements. A change that reads the input twice hardly moves the cycles when the
+//! file is in the page cache, but doubles the bytes read.
+//!
+//! On Linux, the counters come from `/proc/<pid>/io`, read when the command exited but before
+//! it is reaped. The kernel adds the I/O of every child a process waited for to that of the
+//! process, so the counters are those of the whole tree of processes the command waited for,
+//! like a shell script running several programs. Children left running aren't counted.
+//!
+//! | counter | from | unit |
+//! | --- | --- | --- |
+//! | `io-read-bytes` | `rchar`, read by any read syscall, from the page cache too | bytes |
+//! | `io-write-bytes` | `wchar` | bytes |
+//! | `io-storage-read-bytes` | `read_bytes`, fetched from the storage | bytes |
+//! | `io-storage-write-bytes` | `write_bytes` | bytes |
+//! | `io-syscalls-read` | `syscr` | |
+//! | `io-syscalls-write` | `syscw` | |
+//!
+//! Elsewhere, the block input and output operations of `getrusage` are all there is, as
+//! `io-blocks-read` and `io-blocks-written`.
+//!
+//! Every counter is of a single run, with no variance, and is compared like any other.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::process::Stdio;
+
+use crate::bench::{BenchCounter, CommandSpec};
+use crate::rusage;
+
+/// The unit of the counters in bytes, shown with binary prefixes, see
+/// [`crate::BinaryReadable`].
+pub const BYTES: &str = "bytes";
+
+/// The fields of `/proc/<pid>/io` and the counters they become, with their units.
+const PROC_FIELDS: &[(&str, &str, &str)] = &[
+    ("rchar", "io-read-bytes", BYTES),
+    ("wchar", "io-write-bytes", BYTES),
+    ("read_bytes", "io-storage-read-bytes", BYTES),
+    ("write_bytes", "io-storage-write-bytes", BYTES),
+    ("syscr", "io-syscalls-read", ""),
+    ("syscw", "io-syscalls-write", ""),
+];
+
+/// Run the command once and return its I/O counters.
+pub fn observe(cmd: &CommandSpec) -> Result<BTreeMap<String, BenchCounter>, String> {
+    let mut command = cmd.command(&cmd.argv[0]);
+    command
+        .args(&cmd.argv[1..])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    let failed = |e: io::Error| format!("failed to observe the I/O of `{}`: {e}", cmd.argv[0]);
+
+    let child = command.spawn().map_err(failed)?;
+    let pid = child.id() as libc::pid_t;
+    rusage::wait_exited(pid).map_err(failed)?;
+    // The process is a zombie until it is reaped, so the pid still refers to it.
+    let proc_io = if cfg!(target_os = "linux") {
+        Some(std::fs::read_to_string(format!("/proc/{pid}/io")).map_err(failed)?)
+    } else {
+        None
+    };
+    let (status, usage) = rusage::wait4(pid).map_err(failed)?;
+    if cmd.expected_exit_code(status).is_none() {
+        return Err(format!(
+            "`{}` failed while observing its I/O: {status}",
+            cmd.argv.join(" ")
+        ));
+    }
+
+    match proc_io {
+        Some(proc_io) => from_proc_io(&proc_io),
+        None => Ok(from_rusage(&usage)),
+    }
+}
+
+/// The counters of the contents of `/proc/<pid>/io`.
+pub fn from_proc_io(text: &str) -> Result<BTreeMap<String, BenchCounter>, String> {
+    let fields = text
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim(), value.trim()))
+        .collect::<BTreeMap<_, _>>();
+    PROC_FIELDS
+        .iter()
+        .map(|&(field, counter, unit)| {
+            let value = fields
+                .get(field)
+                .ok_or_else(|| format!("no `{field}` in /proc/<pid>/io"))?;
+            let value = value
+                .parse::<u64>()
+                .map_err(|e| format!("invalid `{field}` in /proc/<pid>/io: {e}"))?;
+            Ok((counter.to_owned(), single(value, unit)))
+        })
+        .collect()
+}
+
+fn from_rusage(usage: &libc::rusage) -> BTreeMap<String, BenchCounter> {
+    BTreeMap::from([
+        (
+            "io-blocks-read".to_owned(),
+            single(usage.ru_inblock as u64, ""),
+        ),
+        (
+            "io-blocks-written".to_owned(),
+            single(usage.ru_oublock as u64, ""),
+        ),
+    ])
+}
+
+fn single(value: u64, unit: &str) -> BenchCounter {
+    BenchCounter {
+        value: value as f64,
+        variance: 0.0,
+        repetitions: 1,
+        unit: unit.to_owned(),
+    }
+}
+
+#[test]
+fn parse_proc_io() {
+    let counters = from_proc_io(
+        "rchar: 2097152\nwchar: 1024\nsyscr: 17\nsyscw: 3\nread_bytes: 4096\n\
+         write_bytes: 0\ncancelled_write_bytes: 0\n",
+    )
+    .unwrap();
+    assert_eq!(
+        counters.keys().collect::<Vec<_>>(),
+        [
+            "io-read-bytes",
+            "io-storage-read-bytes",
+            "io-storage-write-bytes",
+            "io-syscalls-read",
+            "io-syscalls-write",
+            "io-write-bytes",
+        ]
+    );
+    assert_eq!(
+        counters["io-read-bytes"],
+        BenchCounter {
+            value: 2097152.0,
+            variance: 0.0,
+            repetitions: 1,
+            unit: "bytes".to_owned(),
+        }
+    );
+    assert_eq!(counters["io-syscalls-read"].value, 17.0);
+    assert_eq!(counters["io-syscalls-read"].unit, "");
+
+    assert_eq!(
+        from_proc_io("rchar: 1\n").unwrap_err(),
+        "no `wchar` in /proc/<pid>/io"
+    );
+    assert!(from_proc_io("rchar: x\nwchar: 1\n")
+        .unwrap_err()
+        .starts_with("invalid `rchar` in /proc/<pid>/io"));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn observe_process_tree() {
+    let dir = crate::testkit::test_dir("io-counters");
+    let input = dir.join("input");
+    std::fs::write(&input, vec![0u8; 1 << 20]).unwrap();
+    let sh = |script: String| {
+        observe(&CommandSpec::new(vec![
+            "sh".to_owned(),
+            "-c".to_owned(),
+            script,
+        ]))
+    };
+
+    // The shell waits for both `cat`s, so their reads count as its own.
+    let once = sh(format!("cat {} > /dev/null", input.display())).unwrap();
+    let twice = sh(format!(
+        "cat {0} > /dev/null; cat {0} > /dev/null",
+        input.display()
+    ))
+    .unwrap();
+    let read = |counters: &BTreeMap<String, BenchCounter>| counters["io-read-bytes"].value;
+    assert!(read(&once) >= (1 << 20) as f64, "{once:?}");
+    assert!(read(&twice) >= (2 << 20) as f64, "{twice:?}");
+    assert!(read(&twice) < (3 << 20) as f64, "{twice:?}");
+    assert!(twice["io-syscalls-read"].value > once["io-syscalls-read"].value);
+
+    let output = dir.join("output");
+    let written = sh(format!(
+        "head -c 524288 {} > {}",
+        input.display(),
+        output.display()
+    ))
+    .unwrap();
+    assert!(written["io-write-bytes"].value >= (512 << 10) as f64);
+    assert!(written["io-write-bytes"].value < (1 << 20) as f64);
+
+    assert_eq!(
+        sh("exit 3".to_owned()).unwrap_err(),
+        "`sh -c exit 3` failed while observing its I/O: exit status: 3"
+    );
+}
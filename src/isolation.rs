@@ -0,0 +1,323 @@
+//! Running the benchmarks in a cgroup with dedicated CPUs, so that other processes on a shared
+//! runner don't perturb the measurements.
+
+use std::fmt::{Display, Write};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct IsolationConfig {
+    /// The CPUs to run the benchmarks on, in cpuset syntax, e.g. `"2-3"`.
+    pub cpus: String,
+    /// The memory limit, e.g. `"4G"`.
+    #[serde(default)]
+    pub memory_max: Option<String>,
+    /// A cgroup delegated to the user running the benchmarks. Without it, a transient scope is
+    /// created for every command with `systemd-run`.
+    #[serde(default)]
+    pub cgroup: Option<PathBuf>,
+}
+
+/// The isolation that was applied, recorded with the results.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IsolationSettings {
+    pub method: IsolationMethod,
+    pub cpus: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_max: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IsolationMethod {
+    Systemd,
+    Cgroup,
+}
+
+/// Isolation that was set up successfully.
+#[derive(Debug)]
+pub struct Isolation {
+    /// The command line prefix that runs a program isolated.
+    pub wrapper: Vec<String>,
+    pub settings: IsolationSettings,
+}
+
+impl IsolationConfig {
+    /// Set up the isolation and check that it works by running `true` with it.
+    pub fn set_up(&self, systemd_run: &Path) -> Result<Isolation, String> {
+        if !cfg!(target_os = "linux") {
+            return Err("isolation is only supported on Linux".to_owned());
+        }
+
+        let method = match &self.cgroup {
+            Some(cgroup) => {
+                let write = |file: &str, value: &str| {
+                    fs::write(cgroup.join(file), value).map_err(|e| {
+                        format!("failed to write {}: {e}", cgroup.join(file).display())
+                    })
+                };
+                write("cpuset.cpus", &self.cpus)?;
+                if let Some(memory_max) = &self.memory_max {
+                    write("memory.max", memory_max)?;
+                }
+                IsolationMethod::Cgroup
+            }
+            None => IsolationMethod::Systemd,
+        };
+
+        let wrapper = self.wrapper(systemd_run);
+        let output = Command::new(&wrapper[0])
+            .args(&wrapper[1..])
+            .arg("true")
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| format!("failed to run {}: {e}", wrapper[0]))?;
+        if !output.status.success() {
+            return Err(format!(
+                "`{}` failed with {:?}: {}",
+                wrapper.join(" "),
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim(),
+            ));
+        }
+
+        Ok(Isolation {
+            wrapper,
+            settings: IsolationSettings {
+                method,
+                cpus: self.cpus.clone(),
+                memory_max: self.memory_max.clone(),
+            },
+        })
+    }
+
+    /// The command line prefix that runs a program isolated.
+    ///
+    /// With `systemd-run --scope` the program is executed in place once the scope exists. For
+    /// a delegated cgroup, a shell moves itself into the cgroup and then executes the program.
+    fn wrapper(&self, systemd_run: &Path) -> Vec<String> {
+        match &self.cgroup {
+            Some(cgroup) => vec![
+                "sh".to_owned(),
+                "-c".to_owned(),
+                r#"echo $$ > "$0/cgroup.procs" && exec "$@""#.to_owned(),
+                cgroup.display().to_string(),
+            ],
+            None => {
+                let mut wrapper = vec![
+                    systemd_run.display().to_string(),
+                    "--user".to_owned(),
+                    "--scope".to_owned(),
+                    "--quiet".to_owned(),
+                    "--collect".to_owned(),
+                    "-p".to_owned(),
+                    format!("AllowedCPUs={}", self.cpus),
+                ];
+                if let Some(memory_max) = &self.memory_max {
+                    wrapper.push("-p".to_owned());
+                    wrapper.push(format!("MemoryMax={memory_max}"));
+                }
+                wrapper.push("--".to_owned());
+                wrapper
+            }
+        }
+    }
+}
+
+impl Display for IsolationSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let method = match self.method {
+            IsolationMethod::Systemd => "a systemd scope",
+            IsolationMethod::Cgroup => "a delegated cgroup",
+        };
+        write!(f, "{method} on CPUs {}", self.cpus)?;
+        if let Some(memory_max) = &self.memory_max {
+            write!(f, " with at most {memory_max} of memory")?;
+        }
+        Ok(())
+    }
+}
+
+fn describe(settings: Option<&IsolationSettings>) -> String {
+    match settings {
+        Some(settings) => settings.to_string(),
+        None => "no isolation".to_owned(),
+    }
+}
+
+/// The warning for results compared against results measured with different isolation.
+pub fn mismatch_warning(
+    before: Option<&IsolationSettings>,
+    after: Option<&IsolationSettings>,
+) -> Option<String> {
+    (before != after).then(|| {
+        format!(
+            "the parent was measured with {}, this commit with {}",
+            describe(before),
+            describe(after)
+        )
+    })
+}
+
+pub fn render_markdown_warning(
+    md: &mut String,
+    before: Option<&IsolationSettings>,
+    after: Option<&IsolationSettings>,
+) {
+    if let Some(warning) = mismatch_warning(before, after) {
+        writeln!(
+            md,
+            "> [!WARNING]\n> The isolation differs: {warning}. The results may not be comparable.\n"
+        )
+        .unwrap();
+    }
+}
+
+#[test]
+fn isolation_wrapper() {
+    let config: IsolationConfig =
+        serde_json::from_str(r#"{ "cpus": "2-3", "memory-max": "4G" }"#).unwrap();
+    assert_eq!(
+        config.wrapper(Path::new("systemd-run")),
+        [
+            "systemd-run",
+            "--user",
+            "--scope",
+            "--quiet",
+            "--collect",
+            "-p",
+            "AllowedCPUs=2-3",
+            "-p",
+            "MemoryMax=4G",
+            "--"
+        ]
+    );
+
+    let config: IsolationConfig =
+        serde_json::from_str(r#"{ "cpus": "2", "cgroup": "/sys/fs/cgroup/bench" }"#).unwrap();
+    assert_eq!(
+        config.wrapper(Path::new("systemd-run")),
+        [
+            "sh",
+            "-c",
+            r#"echo $$ > "$0/cgroup.procs" && exec "$@""#,
+            "/sys/fs/cgroup/bench"
+        ]
+    );
+}
+
+#[test]
+fn set_up_systemd_isolation() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = crate::testkit::test_dir("isolation-systemd");
+    // Runs the command after `--`, like `systemd-run --scope` does.
+    let systemd_run = dir.join("systemd-run");
+    fs::write(
+        &systemd_run,
+        "#!/bin/sh\nwhile [ \"$1\" != \"--\" ]; do shift; done\nshift\nexec \"$@\"\n",
+    )
+    .unwrap();
+    fs::set_permissions(&systemd_run, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let config: IsolationConfig = serde_json::from_str(r#"{ "cpus": "0" }"#).unwrap();
+    let isolation = config.set_up(&systemd_run).unwrap();
+    assert_eq!(isolation.wrapper[0], systemd_run.display().to_string());
+    assert_eq!(
+        isolation.settings,
+        IsolationSettings {
+            method: IsolationMethod::Systemd,
+            cpus: "0".to_owned(),
+            memory_max: None,
+        }
+    );
+
+    // No systemd, or no user session.
+    let err = config.set_up(&dir.join("does-not-exist")).unwrap_err();
+    assert!(err.contains("failed to run"), "{err}");
+    fs::write(
+        &systemd_run,
+        "#!/bin/sh\necho 'Failed to connect to bus' >&2\nexit 1\n",
+    )
+    .unwrap();
+    let err = config.set_up(&systemd_run).unwrap_err();
+    assert!(err.contains("Failed to connect to bus"), "{err}");
+}
+
+#[test]
+fn set_up_cgroup_isolation() {
+    // A plain directory stands in for the delegated cgroup.
+    let dir = crate::testkit::test_dir("isolation-cgroup");
+    let config = IsolationConfig {
+        cpus: "1,3".to_owned(),
+        memory_max: Some("1G".to_owned()),
+        cgroup: Some(dir.clone()),
+    };
+
+    let isolation = config.set_up(Path::new("systemd-run")).unwrap();
+    assert_eq!(isolation.settings.method, IsolationMethod::Cgroup);
+    assert_eq!(fs::read_to_string(dir.join("cpuset.cpus")).unwrap(), "1,3");
+    assert_eq!(fs::read_to_string(dir.join("memory.max")).unwrap(), "1G");
+    assert!(!fs::read_to_string(dir.join("cgroup.procs"))
+        .unwrap()
+        .is_empty());
+
+    // Not delegated.
+    let config = IsolationConfig {
+        cgroup: Some(dir.join("does-not-exist")),
+        ..config
+    };
+    assert!(config.set_up(Path::new("systemd-run")).is_err());
+}
+
+#[test]
+fn isolation_mismatch() {
+    let pinned = IsolationSettings {
+        method: IsolationMethod::Systemd,
+        cpus: "2-3".to_owned(),
+        memory_max: None,
+    };
+
+    assert_eq!(mismatch_warning(None, None), None);
+    assert_eq!(mismatch_warning(Some(&pinned), Some(&pinned)), None);
+
+    let mut md = String::new();
+    render_markdown_warning(&mut md, None, Some(&pinned));
+    assert_eq!(
+        md,
+        "> [!WARNING]\n> The isolation differs: the parent was measured with no isolation, this commit with a systemd scope on CPUs 2-3. The results may not be comparable.\n\n"
+    );
+
+    let capped = IsolationSettings {
+        memory_max: Some("4G".to_owned()),
+        ..pinned.clone()
+    };
+    assert_eq!(
+        mismatch_warning(Some(&pinned), Some(&capped)).unwrap(),
+        "the parent was measured with a systemd scope on CPUs 2-3, this commit with a systemd scope on CPUs 2-3 with at most 4G of memory"
+    );
+}
+
+/// Needs a systemd user session that is allowed to set `AllowedCPUs`, which CI runners usually
+/// don't have.
+#[cfg(feature = "cgroup-tests")]
+#[test]
+fn systemd_scope() {
+    let config: IsolationConfig = serde_json::from_str(r#"{ "cpus": "0" }"#).unwrap();
+    let isolation = config.set_up(Path::new("systemd-run")).unwrap();
+
+    let output = Command::new(&isolation.wrapper[0])
+        .args(&isolation.wrapper[1..])
+        .args(["cat", "/proc/self/status"])
+        .output()
+        .unwrap();
+    let status = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        status.lines().any(|line| line == "Cpus_allowed_list:\t0"),
+        "{status}"
+    );
+}
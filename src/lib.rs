@@ -0,0 +1,6414 @@
+use indexmap::IndexMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::env;
+use std::fmt::Display;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+mod annotations;
+mod backfill;
+mod backfill_history;
+mod baseline;
+mod bench;
+mod budget;
+mod build_info;
+mod cache_stats;
+mod changed;
+mod command_display;
+mod comment;
+mod compact;
+mod compare;
+mod comparison_key;
+mod composite;
+mod config_files;
+mod counter_bounds;
+mod counter_names;
+mod counter_order;
+mod cross_machine;
+mod csv;
+mod dedupe;
+mod diff;
+mod doctor;
+mod drift;
+mod environment;
+mod exemptions;
+mod fail_fast;
+mod fingerprint;
+mod fixture;
+mod flush;
+mod frequency;
+mod gate;
+mod history;
+mod http;
+mod import;
+mod interleave;
+mod intervals;
+mod io_counters;
+mod isolation;
+mod limits;
+mod machine;
+mod manifest;
+mod markers;
+mod matrix;
+mod measure;
+mod measure_child;
+mod migrate;
+mod mix;
+mod notify;
+mod outputs;
+mod overhead;
+mod pattern;
+mod perf_events;
+mod preemption;
+mod preflight;
+mod priority;
+mod profile;
+mod quality;
+mod render_history;
+mod replay;
+mod report;
+mod repro;
+mod required_counters;
+mod rolling;
+mod round_robin;
+mod row_order;
+mod rusage;
+mod sample;
+mod sanitize;
+mod scratch;
+mod sections;
+mod seed;
+mod sentinel;
+mod sha256;
+mod staleness;
+mod stamp;
+mod stat;
+mod sync_start;
+#[cfg(any(test, feature = "testkit"))]
+pub mod testkit;
+mod thermal;
+mod totals;
+mod trigger;
+mod units;
+mod verify_output;
+mod verify_report;
+mod watchdog;
+mod worktree;
+
+use annotations::ConfigSpans;
+use backfill::BackfillConfig;
+use baseline::{BaselineAnomaly, BaselineSanityConfig};
+use bench::*;
+use budget::{BudgetCommand, BudgetConfig, BudgetResult};
+use build_info::{BuildInfo, BuildInfoConfig};
+use command_display::{CommandCells, CommandDisplay, FullCommands};
+use comment::CommentTarget;
+use compare::*;
+use counter_bounds::CounterBounds;
+use counter_names::CounterRenames;
+use counter_order::CounterOrder;
+use cross_machine::CrossMachineConfig;
+use drift::{Drift, DriftAlarmConfig};
+use environment::{Environment, EnvironmentConfig};
+use exemptions::Exemption;
+use fingerprint::FingerprintConfig;
+use fixture::FixtureConfig;
+use flush::{FlushConfig, Flusher};
+use frequency::CpuFrequency;
+use gate::{GateConfig, GateVerdict, Verdicts};
+use import::{ImportResults, MachineClasses, Selector};
+use intervals::IntervalConfig;
+use isolation::{IsolationConfig, IsolationSettings};
+use limits::LimitConfig;
+use markers::Markers;
+use matrix::Matrix;
+use measure::MeasureKind;
+use notify::NotifyConfig;
+use outputs::OutputsConfig;
+use overhead::{CalibrationKey, HarnessOverhead, OverheadConfig};
+use perf_events::PerfEvent;
+use preemption::PreemptionConfig;
+use preflight::{Preflight, PreflightConfig};
+use profile::ProfileConfig;
+use quality::QualityConfig;
+use report::{Baseline, CommandError, GroupReport, GroupStatus, RunReport};
+use rolling::RollingBaselineConfig;
+use row_order::{RowOrder, RowSort, TableOrder};
+use sample::{CorpusCommand, CorpusSample, Sample};
+use sanitize::{SanitizeConfig, Sanitizer};
+use scratch::RunScratch;
+use seed::Rng;
+use sentinel::SentinelMeasurement;
+use staleness::{Staleness, StalenessConfig};
+use stamp::Stamp;
+use thermal::{Thermal, ThermalConfig};
+use totals::{SuiteTotal, SuiteTotalsConfig};
+use trigger::{GitHubContext, Trigger};
+use verify_output::VerifyOutput;
+use watchdog::WatchdogConfig;
+
+/// The exit code when the gate failed, or a budget with the `fail` severity broke.
+const EXIT_GATE_FAILURE: i32 = 1;
+/// The exit code when `--require-quiet` is passed and the system never settled.
+const EXIT_NOT_QUIET: i32 = 2;
+/// The exit code when the working tree has uncommitted changes and `--allow-dirty` isn't
+/// passed. The results are still printed, but must not be stored.
+const EXIT_DIRTY: i32 = 3;
+/// The exit code when commands ran, but perf didn't report their required counters, see
+/// [`required_counters`].
+const EXIT_MISSING_COUNTERS: i32 = 4;
+/// The exit code of a panic, like a failing command or a broken config.
+const EXIT_PANIC: i32 = 101;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Config {
+    #[serde(default)]
+    repetitions_for_group: IndexMap<String, u32>,
+    #[serde(default)]
+    backends_for_group: HashMap<String, Vec<BackendConfig>>,
+    /// Also count the branches, loads and stores of the commands in a group, and derive the
+    /// `branch-miss-rate`.
+    #[serde(default)]
+    instruction_mix_for_group: HashMap<String, bool>,
+    /// Derive the hit rates of the caches and the branch predictor of the commands in a group
+    /// from their counters, see [`cache_stats`].
+    #[serde(default)]
+    cache_stats_for_group: HashMap<String, bool>,
+    /// Observe the bytes read and written and the I/O syscalls of the commands in a group in
+    /// a run after the measurements, see [`io_counters`].
+    #[serde(default)]
+    io_counters_for_group: HashMap<String, bool>,
+    /// The events perf counts for the commands of a group instead of task-clock, cycles and
+    /// instructions, e.g. `"cycles:u"` or `{ "event": "cycles", "modifier": "u" }` to only count
+    /// user space, see [`perf_events`].
+    #[serde(default)]
+    perf_events_for_group: HashMap<String, Vec<PerfEvent>>,
+    /// The counters perf must report for every command of a group instead of those of its
+    /// events, see [`required_counters`].
+    #[serde(default)]
+    required_counters_for_group: HashMap<String, Vec<String>>,
+    /// Run the commands of a group that a `render-versus-self` row compares with each other in
+    /// alternating single repetitions, see [`interleave`].
+    #[serde(default)]
+    interleave_for_group: HashMap<String, bool>,
+    /// Run the commands of a group in a random order, and the commands interleaved with each
+    /// other in a random order in every repetition, from the seed of the run, see [`seed`].
+    #[serde(default)]
+    shuffle_for_group: HashMap<String, bool>,
+    /// Run a single repetition of every command of the suite at a time, so the repetitions
+    /// of every command are spread over the whole run, see [`round_robin`].
+    #[serde(default)]
+    round_robin: bool,
+    /// The order in which the groups run, lower first, 100 by default, see [`priority`].
+    #[serde(default)]
+    priority_for_group: HashMap<String, i64>,
+    /// Run the groups of the comparison tables before the others, see [`priority`].
+    #[serde(default)]
+    prioritize_rendered_groups: bool,
+    /// Measure a command that several groups share only once, see [`dedupe`].
+    #[serde(default)]
+    dedupe_commands: bool,
+    /// Flush the CPU caches between the runs of the interleaved commands of a group, see
+    /// [`flush`].
+    #[serde(default)]
+    flush_between_for_group: HashMap<String, FlushConfig>,
+    /// Tags of all commands in a group, in addition to their own.
+    #[serde(default)]
+    tags_for_group: HashMap<String, Vec<String>>,
+    /// Glob patterns of the repository paths a group depends on. With `--changed-only`, only
+    /// the groups with a changed file, or without patterns, run. See [`changed`].
+    #[serde(default)]
+    paths_for_group: HashMap<String, Vec<String>>,
+    #[serde(deserialize_with = "deserialize_commands")]
+    commands: IndexMap<String, Vec<CommandConfig>>,
+    /// Groups that are not expected to change between commits, like a reference
+    /// implementation. A significant change in them means the measurements are off.
+    #[serde(default)]
+    control_groups: Vec<String>,
+    /// A group to measure at the start and at the end of the run, to detect drift within the
+    /// run, see [`sentinel`].
+    sentinel_group: Option<String>,
+    /// Which measurement of the `sentinel-group` everything that refers to it compares.
+    #[serde(default)]
+    sentinel_measurement: SentinelMeasurement,
+    /// How to correct the significance of the comparisons for their number.
+    #[serde(default)]
+    correction: Correction,
+    /// The smallest change that matters, in percent, or in percentage points for measures of
+    /// the `percentage` kind. Significant changes below it are shown without a marker, aren't
+    /// counted as regressions or improvements and don't fail the gate. Tables can override it.
+    minimum_effect_percent: Option<f64>,
+    /// The markers of the changes in the tables, see [`markers`].
+    #[serde(default)]
+    markers: Markers,
+    /// Files to download and verify before running any benchmark.
+    #[serde(default)]
+    fixtures: Vec<FixtureConfig>,
+    /// Hash the benchmarked binaries, to warn when they are the same as those of the baseline.
+    fingerprint: Option<FingerprintConfig>,
+    /// Read how the benchmarked binaries were built, to note on the comparisons when the
+    /// baseline was built differently, see [`build_info`].
+    build_info: Option<BuildInfoConfig>,
+    gate: Option<GateConfig>,
+    /// A second gate that is evaluated like the `gate`, but only reported and never fails the
+    /// run, to see how often stricter options would fail it before enforcing them.
+    shadow_gate: Option<GateConfig>,
+    /// Warn about the groups whose measurements vary too much to compare them.
+    measurement_quality: Option<QualityConfig>,
+    /// Absolute limits on measures of the current run, by name, checked regardless of the
+    /// baseline.
+    #[serde(default)]
+    budgets: IndexMap<String, BudgetConfig>,
+    /// The best possible values of measures of commands by class of machine, to show how far
+    /// the commands are from them, see [`limits`].
+    #[serde(default)]
+    theoretical_limits: IndexMap<String, LimitConfig>,
+    /// Sum counters over every command of the suite, see [`totals`].
+    #[serde(default)]
+    suite_totals: Option<SuiteTotalsConfig>,
+    /// Alarm when measures rise slowly over the history, see [`drift`].
+    drift_alarm: Option<DriftAlarmConfig>,
+    notify: Option<NotifyConfig>,
+    /// Also post a short report as a comment, with the token in `BENCH_GITHUB_TOKEN`.
+    comment_target: Option<CommentTarget>,
+    /// Options for the commands with `profile` enabled.
+    #[serde(default)]
+    profile: ProfileConfig,
+    /// How to build the commit of the baseline for `--backfill-baseline-counters`, see
+    /// [`backfill`], and the commits of `benchmarker backfill`, see [`backfill_history`].
+    #[serde(default)]
+    backfill: BackfillConfig,
+    /// Options for the commands with `interval-ms` set.
+    #[serde(default)]
+    intervals: IntervalConfig,
+    /// How long perf waits for a command with `sync-start` to signal the start of every run,
+    /// before measuring it as a whole instead.
+    #[serde(
+        rename = "sync-start-timeout-ms",
+        default = "default_sync_start_timeout",
+        deserialize_with = "units::millis"
+    )]
+    sync_start_timeout: Duration,
+    /// How long a command with `measure-child` has to start the process to measure.
+    #[serde(
+        rename = "measure-child-timeout-ms",
+        default = "default_measure_child_timeout",
+        deserialize_with = "units::millis"
+    )]
+    measure_child_timeout: Duration,
+    /// How many commits before the merge base to look for a baseline, when the merge base has
+    /// no results, e.g. because its run failed.
+    #[serde(default = "default_baseline_ancestor_depth")]
+    baseline_ancestor_depth: usize,
+    /// The branches whose results are appended to the results file and are baselines, by
+    /// default the default branch of the repository, see [`trigger`].
+    persistent_branches: Option<Vec<String>>,
+    /// Check the stored results of the merge base against those of the commits before it.
+    baseline_sanity_check: Option<BaselineSanityConfig>,
+    /// Also compare against the pooled results of the last commits of the main branch, see
+    /// [`rolling`].
+    rolling_baseline: Option<RollingBaselineConfig>,
+    /// Wait for the system to be quiet before measuring anything (Linux only).
+    preflight: Option<PreflightConfig>,
+    /// Sample the temperature and the frequency of the CPU while the benchmarks run (Linux
+    /// only).
+    thermal: Option<ThermalConfig>,
+    /// Kill the commands that make no progress, and go on with the next (Linux only), see
+    /// [`watchdog`].
+    watchdog: Option<WatchdogConfig>,
+    /// Leave out the repetitions disturbed by other processes, for the backends that measure
+    /// every run on its own, see [`preemption`].
+    preemption: Option<PreemptionConfig>,
+    /// Run the benchmarks with dedicated CPUs (Linux only).
+    isolation: Option<IsolationConfig>,
+    /// Check the free disk space before running anything, and look for large files the
+    /// commands didn't declare in `produces`, see [`outputs`].
+    outputs: Option<OutputsConfig>,
+    /// How to compare and show the change of a measure. Measures that aren't listed are
+    /// counts.
+    #[serde(default)]
+    measure_kinds: IndexMap<String, MeasureKind>,
+    /// Renames of counters to their canonical names, in addition to the built-in ones, applied
+    /// to fresh and previous results and to the measures of this config.
+    #[serde(default)]
+    counter_renames: CounterRenames,
+    /// The values beyond which counters of fresh and previous results are dropped as broken,
+    /// see [`counter_bounds`].
+    #[serde(default)]
+    counter_bounds: CounterBounds,
+    /// The environment variables recorded with the results, see [`environment`].
+    #[serde(default)]
+    environment: EnvironmentConfig,
+    /// Derive a `normalized-time` counter from the cycles and the nominal frequency of the CPU.
+    #[serde(default)]
+    normalized_time: bool,
+    /// The counters that are still compared when the parent ran on a different class of
+    /// machine.
+    #[serde(default = "machine::default_machine_stable_counters")]
+    machine_stable_counters: Vec<String>,
+    /// The identifiers of machines by the identifier or the raw CPU model of the machines
+    /// that should have them, see [`machine`].
+    #[serde(default)]
+    machine_aliases: IndexMap<String, String>,
+    /// Show the derived `-cold` and `-warm` counters of the `getrusage` backend in the raw
+    /// tables, and compare them there. They can be compared in the pretty tables regardless.
+    #[serde(default)]
+    show_cold_warm: bool,
+    /// Split the raw tables wider than this many columns into several, each repeating the
+    /// command column.
+    max_table_width: Option<usize>,
+    /// Shorten the command lines longer than this in the middle, see [`command_display`].
+    #[serde(default = "command_display::default_max_command_length")]
+    max_command_length: Option<usize>,
+    /// Where the full text of the shortened command lines goes: `tooltip` or `footnotes`.
+    #[serde(default)]
+    full_commands: FullCommands,
+    /// Show how old the baseline is, and warn when it is stale, see [`staleness`].
+    baseline_staleness: Option<StalenessConfig>,
+    /// Replace paths and names in everything the run publishes, see [`sanitize`].
+    sanitize: Option<SanitizeConfig>,
+    /// Measure the overhead of the wrapper and the backends with an empty program, see
+    /// [`overhead`].
+    harness_overhead: Option<OverheadConfig>,
+    /// The order of the rows of the raw tables: `config-order`, `delta-desc` or `value-desc`,
+    /// see [`row_order`].
+    #[serde(default)]
+    sort_raw_rows: RowOrder,
+    #[serde(default)]
+    sort_raw_rows_for_group: HashMap<String, RowOrder>,
+    /// The counter to sort the rows of the raw tables by, task-clock by default.
+    sort_raw_rows_counter: Option<String>,
+    /// The manifest to read the version of the benchmarked package from.
+    #[serde(default = "default_version_manifest")]
+    version_manifest: PathBuf,
+    /// Where to create the scratch directory of the run. Defaults to the temp directory.
+    scratch_root: Option<PathBuf>,
+    /// Keep the scratch directory of a failed run, to look into what went wrong.
+    #[serde(default)]
+    keep_scratch_on_failure: bool,
+    /// Keep the raw output of perf in this directory, with everything `benchmarker replay`
+    /// needs to render the report again.
+    keep_perf_output: Option<PathBuf>,
+    /// With several config files, start the raw results of the groups of every file with a
+    /// heading naming the file.
+    #[serde(default)]
+    config_headings: bool,
+    /// Follow every table with the command lines to reproduce its rows, see [`repro`].
+    #[serde(default)]
+    repro: bool,
+    /// The config file that defined each group, when there are several.
+    #[serde(skip)]
+    group_sources: IndexMap<String, PathBuf>,
+    /// The config files, with the directories expanded.
+    #[serde(skip)]
+    files: Vec<PathBuf>,
+    /// The hash of the merged config, see [`stamp`].
+    #[serde(skip)]
+    sha256: String,
+    /// `--strict-sampling`: gate the tables of the groups sampled differently than the
+    /// baseline too, see [`sample`].
+    #[serde(skip)]
+    strict_sampling: bool,
+    /// The comparison rows dropped with the groups skipped by `--changed-only`, by table.
+    #[serde(skip)]
+    unmeasured_rows: IndexMap<String, Vec<String>>,
+    render_versus_self: IndexMap<String, VersusSelf>,
+    render_versus_other: IndexMap<String, VersusOther>,
+    /// Tables of the results of the same commit on other machines, next to those of this run.
+    render_cross_machine: Option<CrossMachineConfig>,
+}
+
+fn default_version_manifest() -> PathBuf {
+    PathBuf::from("Cargo.toml")
+}
+
+fn default_baseline_ancestor_depth() -> usize {
+    5
+}
+
+fn default_sync_start_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+
+fn default_measure_child_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+
+/// How to render the raw tables, see [`Config::raw_table_options`].
+#[derive(Debug, Default, Clone, Copy)]
+struct RawTableOptions<'a> {
+    stable_counters: &'a [String],
+    show_cold_warm: bool,
+    max_table_width: Option<usize>,
+    row_sort: RowSort<'a>,
+    commands: CommandDisplay,
+    counter_order: CounterOrder<'a>,
+    limits: Option<&'a IndexMap<String, LimitConfig>>,
+}
+
+impl Config {
+    /// Load and merge the config files, see [`config_files`].
+    fn load(paths: &[PathBuf]) -> Result<Self, String> {
+        let files = config_files::load(paths)?;
+        let mut config: Config = serde_json::from_value(files.config.clone()).map_err(|e| {
+            format!(
+                "invalid config: {}",
+                units::locate_error(&files.config, e.to_string())
+            )
+        })?;
+
+        config.files = files.files;
+        config.sha256 = stamp::sha256(&files.config);
+        config.canonicalize_measures();
+
+        let mut sources = files.group_sources.values().collect::<Vec<_>>();
+        sources.dedup();
+        if sources.len() > 1 {
+            config.group_sources = files.group_sources;
+        }
+        sentinel::expand(&mut config)?;
+        Ok(config)
+    }
+
+    /// Rename the measures of the tables and the `measure-kinds` to the canonical counter
+    /// names, like the counters of the results.
+    fn canonicalize_measures(&mut self) {
+        let renames = &self.counter_renames;
+        for table in self.render_versus_other.values_mut() {
+            table.measure = renames.canonical(&table.measure);
+        }
+        for row in self
+            .render_versus_self
+            .values_mut()
+            .flat_map(|table| table.rows.values_mut())
+        {
+            row.measure = renames.canonical(&row.measure);
+        }
+        self.measure_kinds = std::mem::take(&mut self.measure_kinds)
+            .into_iter()
+            .map(|(measure, kind)| (renames.canonical(&measure), kind))
+            .collect();
+        for table in self
+            .render_cross_machine
+            .iter_mut()
+            .flat_map(|cross_machine| cross_machine.tables.values_mut())
+        {
+            table.measure = renames.canonical(&table.measure);
+        }
+        for budget in self.budgets.values_mut() {
+            budget.measure = renames.canonical(&budget.measure);
+        }
+        for limit in self.theoretical_limits.values_mut() {
+            limit.measure = renames.canonical(&limit.measure);
+        }
+        for pair in self
+            .drift_alarm
+            .iter_mut()
+            .flat_map(|drift_alarm| &mut drift_alarm.pairs)
+        {
+            pair.measure = renames.canonical(&pair.measure);
+        }
+    }
+
+    fn repetitions(&self, group_name: &str) -> u32 {
+        self.repetitions_for_group
+            .get(group_name)
+            .copied()
+            .unwrap_or(20)
+    }
+
+    /// The backends to measure the commands of a group with.
+    fn backends(&self, group_name: &str, scratch: &Path) -> Vec<Box<dyn Backend>> {
+        let perf = self.perf(group_name, scratch);
+        match self.backends_for_group.get(group_name) {
+            Some(backends) => backends
+                .iter()
+                .map(|backend| backend.build(&perf))
+                .collect(),
+            None => vec![default_backend(perf)],
+        }
+    }
+
+    /// The perf backend for the commands of a group.
+    fn perf(&self, group_name: &str, scratch: &Path) -> Perf {
+        let mut perf = Perf {
+            instruction_mix: self.instruction_mix(group_name),
+            ..Perf::new(scratch)
+        };
+        if let Some(events) = self.perf_events_for_group.get(group_name) {
+            perf.events = events.clone();
+        }
+        perf.required_counters = self.required_counters_for_group.get(group_name).cloned();
+        perf
+    }
+
+    fn instruction_mix(&self, group_name: &str) -> bool {
+        self.instruction_mix_for_group
+            .get(group_name)
+            .copied()
+            .unwrap_or(false)
+    }
+
+    fn cache_stats(&self, group_name: &str) -> bool {
+        self.cache_stats_for_group
+            .get(group_name)
+            .copied()
+            .unwrap_or(false)
+    }
+
+    fn io_counters(&self, group_name: &str) -> bool {
+        self.io_counters_for_group
+            .get(group_name)
+            .copied()
+            .unwrap_or(false)
+    }
+
+    fn interleave(&self, group_name: &str) -> bool {
+        self.interleave_for_group
+            .get(group_name)
+            .copied()
+            .unwrap_or(false)
+    }
+
+    fn shuffle(&self, group_name: &str) -> bool {
+        self.shuffle_for_group
+            .get(group_name)
+            .copied()
+            .unwrap_or(false)
+    }
+
+    /// The flusher of a group with `flush-between-for-group`, when the `schedule` of the group
+    /// interleaves commands that a `render-versus-self` row compares. The steps of composites
+    /// are interleaved too, but as a pipeline, and never flushed.
+    fn flusher(&self, group_name: &str, schedule: &[interleave::Step]) -> Option<Flusher> {
+        let flush = self.flush_between_for_group.get(group_name)?;
+        let flushed = self.interleave(group_name)
+            && schedule
+                .iter()
+                .any(|step| self.compares_step(group_name, step));
+        if !flushed {
+            eprintln!("warning: the `{group_name}` group has no interleaved commands, its caches aren't flushed between them");
+            return None;
+        }
+        Some(Flusher::new(flush))
+    }
+
+    /// Whether a step of a group's schedule interleaves commands compared with each other,
+    /// rather than the steps of a composite. Only those are flushed between and shuffled.
+    fn compares_step(&self, group_name: &str, step: &interleave::Step) -> bool {
+        let interleave::Step::Interleaved(indices) = step else {
+            return false;
+        };
+        let benches = &self.commands[group_name];
+        !benches
+            .iter()
+            .flat_map(|bench| &bench.steps)
+            .any(|index| indices.contains(index))
+    }
+
+    /// How to show the command lines in the tables.
+    fn command_display(&self) -> CommandDisplay {
+        CommandDisplay {
+            max_length: self.max_command_length,
+            full_commands: self.full_commands,
+        }
+    }
+
+    /// The order of the counters in the raw tables and the CSV.
+    fn counter_order(&self) -> CounterOrder<'_> {
+        CounterOrder::new(&self.perf_events_for_group)
+    }
+
+    /// The totals of the suite, over the commands measured in their own right: not the
+    /// measurements of the `sentinel-group`, which is measured twice, nor composites, which
+    /// are the sums of their steps.
+    fn suite_totals(&self, data: &BenchData, prev_results: Option<&BenchData>) -> Vec<SuiteTotal> {
+        let Some(totals_config) = &self.suite_totals else {
+            return vec![];
+        };
+        let sentinel_keys = self.sentinel_group.as_deref().map(|group_name| {
+            [
+                sentinel::start_key(group_name),
+                sentinel::end_key(group_name),
+            ]
+        });
+        totals_config.collect(data, prev_results, |group_name, bench| {
+            let sentinel = sentinel_keys
+                .as_ref()
+                .is_some_and(|keys| keys.iter().any(|key| key == group_name));
+            let composite = self.commands.get(group_name).is_some_and(|benches| {
+                benches.iter().any(|command| {
+                    command.is_composite() && command.command.split(' ').eq(&bench.cmd)
+                })
+            });
+            !sentinel && !composite
+        })
+    }
+
+    /// The measures that rose slowly over the `history`, and the current results when they are
+    /// stored. A command is identified as in the current results, where an imported command has
+    /// the command line it was measured with, otherwise as configured.
+    fn drift(&self, data: &BenchData, history: &[&BenchData]) -> Vec<Drift> {
+        let Some(drift_alarm) = &self.drift_alarm else {
+            return vec![];
+        };
+        drift_alarm.collect(data, history, |pair| {
+            let measured = data
+                .bench_groups
+                .get(&pair.group)
+                .and_then(|benches| benches.get(pair.index));
+            match (measured, self.commands.get(&pair.group)) {
+                (Some(bench), _) => (bench.id.clone(), bench.cmd.clone()),
+                (None, Some(benches)) => {
+                    let command = &benches[pair.index];
+                    let argv = command.command.split(' ').map(str::to_owned).collect();
+                    (command.id.clone(), argv)
+                }
+                (None, None) => (None, vec![]),
+            }
+        })
+    }
+
+    /// Whether anything looks at more of the previous results than the baseline.
+    fn needs_history(&self) -> bool {
+        self.baseline_sanity_check.is_some()
+            || self.rolling_baseline.is_some()
+            || self.render_cross_machine.is_some()
+            || self.drift_alarm.is_some()
+    }
+
+    /// How to render the raw table of a group.
+    fn raw_table_options(&self, group_name: &str) -> RawTableOptions<'_> {
+        RawTableOptions {
+            stable_counters: &self.machine_stable_counters,
+            show_cold_warm: self.show_cold_warm,
+            max_table_width: self.max_table_width,
+            commands: self.command_display(),
+            counter_order: self.counter_order(),
+            limits: Some(&self.theoretical_limits),
+            row_sort: RowSort {
+                order: self
+                    .sort_raw_rows_for_group
+                    .get(group_name)
+                    .copied()
+                    .unwrap_or(self.sort_raw_rows),
+                counter: self.sort_raw_rows_counter.as_deref(),
+            },
+        }
+    }
+
+    /// Add the counters derived from the measured ones, like the normalized time.
+    fn derive_counters(
+        &self,
+        group_name: &str,
+        cpu_frequency: Option<&CpuFrequency>,
+        counters: &mut BTreeMap<String, BenchCounter>,
+    ) {
+        if self.normalized_time {
+            if let Some(normalized) =
+                cpu_frequency.and_then(|frequency| frequency::normalized_time(counters, frequency))
+            {
+                counters.insert(frequency::NORMALIZED_TIME.to_owned(), normalized);
+            }
+        }
+
+        if self.instruction_mix(group_name) {
+            if let Some(rate) = mix::branch_miss_rate(counters) {
+                counters.insert(mix::BRANCH_MISS_RATE.to_owned(), rate);
+            }
+        }
+
+        if self.cache_stats(group_name) {
+            for (name, rate) in cache_stats::rates(counters) {
+                counters.insert(name.to_owned(), rate);
+            }
+        }
+    }
+
+    /// The group for messages, with the config file that defined it when there are several.
+    fn describe_group(&self, group_name: &str) -> String {
+        match self.group_sources.get(group_name) {
+            Some(source) => format!("`{group_name}` group (from {})", source.display()),
+            None => format!("`{group_name}` group"),
+        }
+    }
+
+    /// Check what the types of the config can't express.
+    fn validate(&self) -> Result<(), String> {
+        let mut ids = HashMap::new();
+        for (group_name, benches) in &self.commands {
+            for bench in benches {
+                let Some(id) = &bench.id else {
+                    continue;
+                };
+                if let Some(other_group) = ids.insert(id, group_name) {
+                    if other_group == group_name {
+                        return Err(format!(
+                            "the id `{id}` is used more than once in the {}",
+                            self.describe_group(group_name)
+                        ));
+                    }
+                    if self.group_sources.is_empty() {
+                        return Err(format!(
+                            "the id `{id}` is used more than once, in the `{other_group}` and the `{group_name}` group"
+                        ));
+                    }
+                    return Err(format!(
+                        "the id `{id}` is used more than once, in the {} and the {}",
+                        self.describe_group(other_group),
+                        self.describe_group(group_name)
+                    ));
+                }
+            }
+        }
+
+        for (table_name, table) in &self.render_versus_other {
+            if !self.commands.contains_key(&table.command) {
+                return Err(format!(
+                    "the `{table_name}` table compares the `{}` group, which doesn't exist",
+                    table.command
+                ));
+            }
+        }
+
+        let minimum_effects = self
+            .render_versus_other
+            .values()
+            .map(|table| &table.display)
+            .chain(self.render_versus_self.values().map(|table| &table.display))
+            .filter_map(|display| display.minimum_effect_percent);
+        for minimum_effect in self
+            .minimum_effect_percent
+            .into_iter()
+            .chain(minimum_effects)
+        {
+            if minimum_effect < 0.0 {
+                return Err(format!(
+                    "`minimum-effect-percent` must not be negative, got {minimum_effect}"
+                ));
+            }
+        }
+        self.markers.validate()?;
+
+        for fixture in &self.fixtures {
+            fixture.validate()?;
+        }
+        for pattern in self
+            .commands
+            .values()
+            .flatten()
+            .flat_map(|bench| &bench.produces)
+        {
+            outputs::validate_pattern(pattern)?;
+        }
+        for bench in self.commands.values().flatten() {
+            if let Some(verify) = &bench.verify_output {
+                verify
+                    .validate()
+                    .map_err(|e| format!("invalid `verify-output` of `{}`: {e}", bench.command))?;
+            }
+        }
+        if let Some(fingerprint) = &self.fingerprint {
+            fingerprint.validate()?;
+        }
+        if let Some(preemption) = &self.preemption {
+            preemption
+                .validate()
+                .map_err(|e| format!("invalid `preemption`: {e}"))?;
+        }
+        for (group_name, flush) in &self.flush_between_for_group {
+            flush.validate(group_name)?;
+        }
+        for (group_name, benches) in &self.commands {
+            for bench in benches {
+                let Some(process) = &bench.measure_child else {
+                    continue;
+                };
+                measure_child::validate(process)?;
+                if bench.sync_start {
+                    return Err(format!(
+                        "`{}` has both `sync-start` and `measure-child`, perf either runs the command or attaches to its process",
+                        bench.command
+                    ));
+                }
+                let other_backend = self
+                    .backends_for_group
+                    .get(group_name)
+                    .into_iter()
+                    .flatten()
+                    .find_map(|backend| match backend {
+                        BackendConfig::Perf => None,
+                        BackendConfig::Getrusage => Some("getrusage"),
+                        BackendConfig::External(_) => Some("external"),
+                    });
+                if let Some(backend) = other_backend {
+                    return Err(format!(
+                        "`{}` has `measure-child`, which only the perf backend measures, but the `{group_name}` group also uses the `{backend}` backend",
+                        bench.command
+                    ));
+                }
+            }
+        }
+
+        let group_tags = self.tags_for_group.values().flatten();
+        let command_tags = self
+            .commands
+            .values()
+            .flatten()
+            .flat_map(|bench| &bench.tags);
+        for tag in group_tags.chain(command_tags) {
+            validate_tag(tag)?;
+        }
+
+        for (name, budget) in &self.budgets {
+            let commands = self.commands.get(&budget.group).map(|benches| {
+                benches
+                    .iter()
+                    .map(|bench| bench.command.clone())
+                    .collect::<Vec<_>>()
+            });
+            budget.validate(name, commands.as_deref())?;
+        }
+        for (name, limit) in &self.theoretical_limits {
+            limit.validate(name)?;
+        }
+        if let Some(drift_alarm) = &self.drift_alarm {
+            drift_alarm.validate(|group_name| self.commands.get(group_name).map(Vec::len))?;
+        }
+
+        Ok(())
+    }
+
+    /// The tags of a command: those of its group, followed by its own.
+    fn tags(&self, group_name: &str, bench: &CommandConfig) -> Vec<String> {
+        let mut tags = self
+            .tags_for_group
+            .get(group_name)
+            .cloned()
+            .unwrap_or_default();
+        for tag in &bench.tags {
+            if !tags.contains(tag) {
+                tags.push(tag.clone());
+            }
+        }
+        tags
+    }
+
+    /// Only keep the commands that carry all of `only_tags` and none of `skip_tags`, so every
+    /// filter narrows the selection further. Groups without any commands left are dropped.
+    ///
+    /// The rows of the render tables and the budgets that refer to a dropped command are
+    /// dropped too, and the indices of the others are updated to the remaining commands.
+    fn retain_tagged(&mut self, only_tags: &[String], skip_tags: &[String]) {
+        if only_tags.is_empty() && skip_tags.is_empty() {
+            return;
+        }
+
+        let selected = self
+            .commands
+            .iter()
+            .map(|(group_name, benches)| {
+                let selected = benches
+                    .iter()
+                    .map(|bench| {
+                        let tags = self.tags(group_name, bench);
+                        only_tags.iter().all(|tag| tags.contains(tag))
+                            && !skip_tags.iter().any(|tag| tags.contains(tag))
+                    })
+                    .collect();
+                (group_name.clone(), selected)
+            })
+            .collect();
+        self.retain_commands(&selected);
+    }
+
+    /// Only keep the chosen files of every corpus with a `sample`, returning what was chosen by
+    /// group, see [`sample`].
+    fn sample_corpora(&mut self, rng: &mut Rng) -> IndexMap<String, Vec<CorpusSample>> {
+        self.retain_corpora(|_, _, sample, files| sample.choose(files, rng))
+    }
+
+    /// Only keep the files of the corpora that were chosen in `samples`, like those recorded
+    /// with the results of a run.
+    fn retain_samples(&mut self, samples: &IndexMap<String, Vec<CorpusSample>>) {
+        self.retain_corpora(|group_name, glob, _, files| {
+            let chosen = samples
+                .get(group_name)
+                .into_iter()
+                .flatten()
+                .find(|recorded| recorded.files == glob)
+                .map(|recorded| recorded.chosen.as_slice())
+                .unwrap_or_default();
+            (0..files.len())
+                .filter(|&index| chosen.iter().any(|file| file.path == files[index].path))
+                .collect()
+        });
+    }
+
+    /// Only keep the files of every corpus with a `sample` that `choose` returns the indices
+    /// of, returning the chosen ones by group.
+    fn retain_corpora(
+        &mut self,
+        mut choose: impl FnMut(&str, &str, &Sample, &[sample::CorpusFile]) -> Vec<usize>,
+    ) -> IndexMap<String, Vec<CorpusSample>> {
+        let mut samples = IndexMap::new();
+        let mut selected = HashMap::new();
+        for (group_name, benches) in &self.commands {
+            let mut keep = vec![true; benches.len()];
+            let mut start = 0;
+            while start < benches.len() {
+                let Some(corpus) = &benches[start].corpus else {
+                    start += 1;
+                    continue;
+                };
+                let end = start
+                    + benches[start..]
+                        .iter()
+                        .take_while(|bench| {
+                            bench
+                                .corpus
+                                .as_ref()
+                                .is_some_and(|other| other.entry == corpus.entry)
+                        })
+                        .count();
+                if let Some(sample) = &corpus.sample {
+                    let files = benches[start..end]
+                        .iter()
+                        .map(|bench| bench.corpus.as_ref().unwrap().file.clone())
+                        .collect::<Vec<_>>();
+                    let chosen = choose(group_name, &corpus.files, sample, &files);
+                    for (index, keep) in keep[start..end].iter_mut().enumerate() {
+                        *keep = chosen.contains(&index);
+                    }
+                    samples
+                        .entry(group_name.clone())
+                        .or_insert_with(Vec::new)
+                        .push(CorpusSample {
+                            files: corpus.files.clone(),
+                            strategy: sample.strategy,
+                            count: sample.count,
+                            chosen: chosen
+                                .into_iter()
+                                .map(|index| files[index].clone())
+                                .collect(),
+                        });
+                }
+                start = end;
+            }
+            selected.insert(group_name.clone(), keep);
+        }
+        if !samples.is_empty() {
+            self.retain_commands(&selected);
+        }
+        samples
+    }
+
+    /// The verdict of the gate on `comparisons`, see [`Self::evaluate_gate_with`].
+    fn evaluate_gate(
+        &self,
+        comparisons: &Comparisons,
+        exemptions: &[Exemption],
+    ) -> Option<GateVerdict> {
+        Some(self.evaluate_gate_with(self.gate.as_ref()?, comparisons, exemptions))
+    }
+
+    /// The verdict of the `shadow-gate` on `comparisons`, see [`Self::evaluate_gate_with`].
+    fn evaluate_shadow_gate(
+        &self,
+        comparisons: &Comparisons,
+        exemptions: &[Exemption],
+    ) -> Option<GateVerdict> {
+        Some(self.evaluate_gate_with(self.shadow_gate.as_ref()?, comparisons, exemptions))
+    }
+
+    /// The verdict of `gate` on `comparisons`, without the failures of the groups whose
+    /// measurements are unreliable, and with the failures that the `exemptions` cover
+    /// accepted.
+    fn evaluate_gate_with(
+        &self,
+        gate: &GateConfig,
+        comparisons: &Comparisons,
+        exemptions: &[Exemption],
+    ) -> GateVerdict {
+        let mut verdict = gate.evaluate(comparisons);
+        if let Some(quality) = &self.measurement_quality {
+            quality.suppress_gate(
+                &mut verdict,
+                &self.render_versus_other,
+                &comparisons.quality,
+            );
+        }
+        if !self.strict_sampling {
+            sample::suppress_gate(
+                &mut verdict,
+                &self.render_versus_other,
+                &comparisons.sampling_mismatches,
+            );
+        }
+        exemptions::apply(&mut verdict, exemptions);
+        verdict
+    }
+
+    /// Drop the groups skipped by `--changed-only`, remembering the comparison rows that go
+    /// with them.
+    fn skip_groups(&mut self, skipped_groups: &IndexMap<String, String>) {
+        if skipped_groups.is_empty() {
+            return;
+        }
+
+        let skipped = |group_name: &str| skipped_groups.contains_key(group_name);
+        for (table_name, table) in &self.render_versus_other {
+            if skipped(&table.command) {
+                self.unmeasured_rows
+                    .entry(table_name.clone())
+                    .or_default()
+                    .extend(table.rows.keys().cloned());
+            }
+        }
+        for (table_name, table) in &self.render_versus_self {
+            for (row_name, row) in &table.rows {
+                if skipped(&row.before.command) || skipped(&row.after.command) {
+                    self.unmeasured_rows
+                        .entry(table_name.clone())
+                        .or_default()
+                        .push(row_name.clone());
+                }
+            }
+        }
+
+        let selected = self
+            .commands
+            .iter()
+            .map(|(group_name, benches)| {
+                (
+                    group_name.clone(),
+                    vec![!skipped(group_name); benches.len()],
+                )
+            })
+            .collect();
+        self.retain_commands(&selected);
+    }
+
+    /// Keep only the `selected` commands, by group, and the comparisons, budgets and tables of
+    /// the commands that are left, with their indices updated.
+    fn retain_commands(&mut self, selected: &HashMap<String, Vec<bool>>) {
+        // The new index of every command by group, `None` when it was dropped.
+        let mut new_indices = HashMap::new();
+        let mut commands = std::mem::take(&mut self.commands);
+        for (group_name, benches) in &mut commands {
+            // A composite keeps its steps.
+            let mut selected = selected[group_name].clone();
+            for (index, bench) in benches.iter().enumerate() {
+                if selected[index] {
+                    for &step in &bench.steps {
+                        selected[step] = true;
+                    }
+                }
+            }
+
+            let mut indices = vec![];
+            let mut kept = 0;
+            for &selected in &selected {
+                indices.push(selected.then_some(kept));
+                kept += usize::from(selected);
+            }
+            let mut keep = selected.iter();
+            benches.retain(|_| *keep.next().unwrap());
+            for bench in benches.iter_mut() {
+                for step in &mut bench.steps {
+                    *step = indices[*step].unwrap();
+                }
+            }
+            new_indices.insert(group_name.clone(), indices);
+        }
+        commands.retain(|_, benches| !benches.is_empty());
+        self.commands = commands;
+
+        let new_index = |command: &str, index: usize| {
+            new_indices
+                .get(command)
+                .and_then(|indices| indices.get(index).copied().flatten())
+        };
+
+        self.render_versus_other
+            .retain(|_, table| self.commands.contains_key(&table.command));
+        for table in self.render_versus_other.values_mut() {
+            table.rows = std::mem::take(&mut table.rows)
+                .into_iter()
+                .filter_map(|(name, index)| Some((name, new_index(&table.command, index)?)))
+                .collect();
+        }
+
+        for table in self.render_versus_self.values_mut() {
+            table.rows.retain(|_, row| {
+                match (
+                    new_index(&row.before.command, row.before.index),
+                    new_index(&row.after.command, row.after.index),
+                ) {
+                    (Some(before), Some(after)) => {
+                        row.before.index = before;
+                        row.after.index = after;
+                        true
+                    }
+                    _ => false,
+                }
+            });
+        }
+
+        if let Some(cross_machine) = &mut self.render_cross_machine {
+            cross_machine
+                .tables
+                .retain(|_, table| self.commands.contains_key(&table.command));
+            for table in cross_machine.tables.values_mut() {
+                table.rows = std::mem::take(&mut table.rows)
+                    .into_iter()
+                    .filter_map(|(name, index)| Some((name, new_index(&table.command, index)?)))
+                    .collect();
+            }
+        }
+
+        self.budgets.retain(|_, budget| match &mut budget.command {
+            BudgetCommand::Index(index) => match new_index(&budget.group, *index) {
+                Some(new) => {
+                    *index = new;
+                    true
+                }
+                None => false,
+            },
+            BudgetCommand::Match(_) => self.commands.contains_key(&budget.group),
+        });
+
+        if let Some(drift_alarm) = &mut self.drift_alarm {
+            drift_alarm
+                .pairs
+                .retain_mut(|pair| match new_index(&pair.group, pair.index) {
+                    Some(new) => {
+                        pair.index = new;
+                        true
+                    }
+                    None => false,
+                });
+        }
+    }
+}
+
+/// Tags end up in markdown tables and on the command line, so they can't contain whitespace or
+/// pipes.
+fn validate_tag(tag: &str) -> Result<(), String> {
+    if tag.is_empty() || tag.contains(|c: char| c.is_whitespace() || c == '|') {
+        return Err(format!(
+            "the tag `{tag}` is empty or contains whitespace or a `|`"
+        ));
+    }
+    Ok(())
+}
+
+/// The command line arguments.
+#[derive(Debug, PartialEq)]
+struct Args {
+    commit_hash: String,
+    /// Config files, or directories with config files, to merge.
+    config_paths: Vec<PathBuf>,
+    previous_results_path: String,
+    /// `--remap-id <command line>=<id>`: match a previous result recorded without an id by its
+    /// command line, e.g. after adding an id and changing the command line at the same time.
+    remap_ids: Vec<(String, String)>,
+    /// `--stream`: print every result as soon as it is measured, not just all of them at the
+    /// end.
+    stream: bool,
+    /// `--require-isolation`: fail rather than warn when the configured isolation can't be set
+    /// up.
+    require_isolation: bool,
+    /// `--require-quiet`: fail rather than warn when the system is still busy after the
+    /// preflight checks.
+    require_quiet: bool,
+    /// `--only-tag <tag>`: only run the commands with this tag. Can be given more than once,
+    /// to run the commands with all of the tags.
+    only_tags: Vec<String>,
+    /// `--skip-tag <tag>`: don't run the commands with this tag. Can be given more than once.
+    skip_tags: Vec<String>,
+    /// `--changed-only`: skip the groups with `paths-for-group` patterns that no file changed
+    /// since the merge base matches. Applies to the commands left by the tag filters.
+    changed_only: bool,
+    /// `--run-report <path>`: where to write the machine-readable summary of the run.
+    run_report: Option<PathBuf>,
+    /// `--keep-scratch`: don't remove the scratch directory at the end of the run.
+    keep_scratch: bool,
+    /// `--results-file <path>`: also write the results to this file, see [`sections`].
+    results_file: Option<PathBuf>,
+    /// `--csv <path>`: also write every measurement to this file as CSV, see [`csv`].
+    csv: Option<PathBuf>,
+    /// `--allow-dirty`: benchmark a working tree with uncommitted changes, marking the results
+    /// as dirty, rather than exiting with [`EXIT_DIRTY`].
+    allow_dirty: bool,
+    /// `--fail-fast`: stop running groups as soon as the gate fails on those measured so far,
+    /// see [`fail_fast`].
+    fail_fast: bool,
+    /// `--seed <seed>`: the seed of the random choices of the run, see [`seed`].
+    seed: Option<u64>,
+    /// `--replay-seed-from <path>`: take the seed from the last stored results with one.
+    replay_seed_from: Option<PathBuf>,
+    /// `--backfill-baseline-counters`: measure the counters the baseline lacks at its commit,
+    /// see [`backfill`].
+    backfill_baseline_counters: bool,
+    /// `--persist-backfill`: also add the backfilled counters to the stored baseline.
+    persist_backfill: bool,
+    /// `--results-index`: look the baseline up in, and keep up to date, the index of the
+    /// previous results and of the results file, see [`history`].
+    results_index: bool,
+    /// `--strict-sampling`: gate the tables of the groups that sampled other files than the
+    /// baseline too, see [`sample`].
+    strict_sampling: bool,
+}
+
+impl Args {
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut positional = vec![];
+        let mut remap_ids = vec![];
+        let mut stream = false;
+        let mut require_isolation = false;
+        let mut require_quiet = false;
+        let mut only_tags = vec![];
+        let mut skip_tags = vec![];
+        let mut changed_only = false;
+        let mut run_report = None;
+        let mut keep_scratch = false;
+        let mut allow_dirty = false;
+        let mut fail_fast = false;
+        let mut results_file = None;
+        let mut csv = None;
+        let mut seed = None;
+        let mut replay_seed_from = None;
+        let mut backfill_baseline_counters = false;
+        let mut persist_backfill = false;
+        let mut results_index = false;
+        let mut strict_sampling = false;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if let Some(flag) = arg.strip_prefix("--") {
+                let (flag, inline_value) = match flag.split_once('=') {
+                    Some((flag, value)) => (flag, Some(value.to_owned())),
+                    None => (flag, None),
+                };
+                let mut value = || {
+                    inline_value
+                        .clone()
+                        .or_else(|| args.next())
+                        .ok_or_else(|| format!("`--{flag}` requires a value"))
+                };
+
+                match flag {
+                    "stream" if inline_value.is_none() => stream = true,
+                    "require-isolation" if inline_value.is_none() => require_isolation = true,
+                    "require-quiet" if inline_value.is_none() => require_quiet = true,
+                    "keep-scratch" if inline_value.is_none() => keep_scratch = true,
+                    "allow-dirty" if inline_value.is_none() => allow_dirty = true,
+                    "changed-only" if inline_value.is_none() => changed_only = true,
+                    "fail-fast" if inline_value.is_none() => fail_fast = true,
+                    "backfill-baseline-counters" if inline_value.is_none() => {
+                        backfill_baseline_counters = true
+                    }
+                    "persist-backfill" if inline_value.is_none() => persist_backfill = true,
+                    "results-index" if inline_value.is_none() => results_index = true,
+                    "strict-sampling" if inline_value.is_none() => strict_sampling = true,
+                    "run-report" => run_report = Some(PathBuf::from(value()?)),
+                    "results-file" => results_file = Some(PathBuf::from(value()?)),
+                    "csv" => csv = Some(PathBuf::from(value()?)),
+                    "seed" => {
+                        let value = value()?;
+                        seed = Some(value.parse().map_err(|_| {
+                            format!("`--seed {value}` is not a non-negative integer")
+                        })?);
+                    }
+                    "replay-seed-from" => replay_seed_from = Some(PathBuf::from(value()?)),
+                    "only-tag" => only_tags.push(value()?),
+                    "skip-tag" => skip_tags.push(value()?),
+                    "remap-id" => {
+                        let value = value()?;
+                        let Some((command, id)) = value.rsplit_once('=') else {
+                            return Err(format!(
+                                "`--remap-id {value}` is not of the form `<command line>=<id>`"
+                            ));
+                        };
+                        remap_ids.push((command.to_owned(), id.to_owned()));
+                    }
+                    _ => return Err(format!("unknown flag `--{flag}`")),
+                }
+            } else {
+                positional.push(arg);
+            }
+        }
+
+        if positional.len() < 3 {
+            return Err(
+                "expected the arguments <commit> <config>... <previous results>".to_owned(),
+            );
+        }
+        if seed.is_some() && replay_seed_from.is_some() {
+            return Err("`--seed` and `--replay-seed-from` can't be given together".to_owned());
+        }
+        if persist_backfill && !backfill_baseline_counters {
+            return Err("`--persist-backfill` requires `--backfill-baseline-counters`".to_owned());
+        }
+        let previous_results_path = positional.pop().unwrap();
+        let commit_hash = positional.remove(0);
+        let config_paths = positional.into_iter().map(PathBuf::from).collect();
+
+        Ok(Args {
+            commit_hash,
+            config_paths,
+            previous_results_path,
+            remap_ids,
+            stream,
+            require_isolation,
+            require_quiet,
+            only_tags,
+            skip_tags,
+            changed_only,
+            run_report,
+            keep_scratch,
+            results_file,
+            csv,
+            allow_dirty,
+            fail_fast,
+            seed,
+            replay_seed_from,
+            backfill_baseline_counters,
+            persist_backfill,
+            results_index,
+            strict_sampling,
+        })
+    }
+}
+
+/// A line of the NDJSON printed on stdout.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+enum OutputLine<'a> {
+    /// A single result, printed as soon as it is measured with `--stream`.
+    Bench {
+        sequence: usize,
+        commit_hash: &'a str,
+        group: &'a str,
+        bench: &'a SingleBench,
+    },
+    /// All results of the run. This is the line that is stored as the previous results.
+    Final(&'a BenchData),
+}
+
+impl OutputLine<'_> {
+    fn print(&self, sanitizer: &Sanitizer) {
+        use std::io::Write;
+
+        // Lock stdout so a line is never interleaved with anything else.
+        let mut stdout = std::io::stdout().lock();
+        writeln!(stdout, "{}", self.to_json(sanitizer)).unwrap();
+        stdout.flush().unwrap();
+    }
+
+    fn to_json(&self, sanitizer: &Sanitizer) -> String {
+        sanitizer.to_json(self)
+    }
+}
+
+/// A command to benchmark: either just the command line, or an object with the command line
+/// and options. A composite in the config becomes its steps followed by itself.
+#[derive(Debug, Clone)]
+struct CommandConfig {
+    command: String,
+    /// A stable id, to keep matching the command with previous results when its command line
+    /// changes.
+    id: Option<String>,
+    /// Record a profile of a single run after the measurements.
+    profile: bool,
+    /// Record the course of a counter over a single run after the measurements, in intervals
+    /// of this length.
+    interval: Option<Duration>,
+    /// The exit codes with which the command counts as successful, e.g. for benchmarks of
+    /// error paths.
+    expected_exit_codes: Vec<i32>,
+    tags: Vec<String>,
+    /// Only count the part of every run between the signals of the command, with perf. See
+    /// [`sync_start`].
+    sync_start: bool,
+    /// The process started by the command to measure instead, with perf. See
+    /// [`measure_child`].
+    measure_child: Option<String>,
+    /// For a composite, the indices of its steps in the group. A composite isn't run itself,
+    /// see [`composite`].
+    steps: Vec<usize>,
+    /// Globs of the files the command writes, deleted after its repetitions, see [`outputs`].
+    produces: Vec<String>,
+    /// Check that the output is the same in every repetition, see [`verify_output`].
+    verify_output: Option<VerifyOutput>,
+    /// The command legitimately sleeps, the watchdog leaves it alone.
+    sleeps: bool,
+    /// Import the results of the command from another run rather than running it, see
+    /// [`import`].
+    import: Option<ImportResults>,
+    /// The file of a corpus the command runs on, see [`sample`].
+    corpus: Option<CorpusCommand>,
+}
+
+impl CommandConfig {
+    fn new(command: String) -> Self {
+        CommandConfig {
+            command,
+            id: None,
+            profile: false,
+            interval: None,
+            expected_exit_codes: default_expected_exit_codes(),
+            tags: vec![],
+            sync_start: false,
+            measure_child: None,
+            steps: vec![],
+            produces: vec![],
+            verify_output: None,
+            sleeps: false,
+            import: None,
+            corpus: None,
+        }
+    }
+
+    fn is_composite(&self) -> bool {
+        !self.steps.is_empty()
+    }
+}
+
+enum CommandConfigRepr {
+    Command(String),
+    Options(CommandOptions),
+    Composite(CompositeOptions),
+    Import(ImportOptions),
+    Matrix(MatrixOptions),
+    Files(FilesOptions),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct CommandOptions {
+    command: String,
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    profile: bool,
+    #[serde(
+        default,
+        rename = "interval-ms",
+        deserialize_with = "units::option_millis"
+    )]
+    interval: Option<Duration>,
+    #[serde(default = "default_expected_exit_codes")]
+    expected_exit_codes: Vec<i32>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    sync_start: bool,
+    #[serde(default)]
+    measure_child: Option<String>,
+    #[serde(default)]
+    produces: Vec<String>,
+    #[serde(default)]
+    verify_output: Option<VerifyOutput>,
+    #[serde(default)]
+    sleeps: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct CompositeOptions {
+    composite: String,
+    steps: Vec<String>,
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct ImportOptions {
+    import_results: PathBuf,
+    #[serde(default)]
+    select: Option<String>,
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct MatrixOptions {
+    template: String,
+    matrix: IndexMap<String, Vec<serde_json::Value>>,
+    #[serde(default)]
+    exclude: Vec<IndexMap<String, serde_json::Value>>,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct FilesOptions {
+    template: String,
+    files: String,
+    #[serde(default)]
+    sample: Option<Sample>,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+// Not `untagged`, which would replace why the options are invalid, e.g. a duration with an
+// unknown unit, with not matching any variant.
+impl<'de> Deserialize<'de> for CommandConfigRepr {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match serde_json::Value::deserialize(deserializer)? {
+            serde_json::Value::String(command) => Ok(CommandConfigRepr::Command(command)),
+            options if options.get("composite").is_some() => CompositeOptions::deserialize(options)
+                .map(CommandConfigRepr::Composite)
+                .map_err(serde::de::Error::custom),
+            options if options.get("import-results").is_some() => {
+                ImportOptions::deserialize(options)
+                    .map(CommandConfigRepr::Import)
+                    .map_err(serde::de::Error::custom)
+            }
+            options if options.get("matrix").is_some() => MatrixOptions::deserialize(options)
+                .map(CommandConfigRepr::Matrix)
+                .map_err(serde::de::Error::custom),
+            options if options.get("files").is_some() => FilesOptions::deserialize(options)
+                .map(CommandConfigRepr::Files)
+                .map_err(serde::de::Error::custom),
+            options => CommandOptions::deserialize(options)
+                .map(CommandConfigRepr::Options)
+                .map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+/// The commands of every group, with the steps of every composite in its place, followed by
+/// the composite.
+fn deserialize_commands<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<IndexMap<String, Vec<CommandConfig>>, D::Error> {
+    IndexMap::<String, Vec<CommandConfigRepr>>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(group_name, benches)| {
+            let mut expanded = vec![];
+            for bench in benches {
+                bench
+                    .expand_into(&mut expanded)
+                    .map_err(serde::de::Error::custom)?;
+            }
+            Ok((group_name, expanded))
+        })
+        .collect()
+}
+
+fn default_expected_exit_codes() -> Vec<i32> {
+    vec![0]
+}
+
+impl CommandConfigRepr {
+    /// Add the command to the `benches` of its group, or the steps of a composite and then
+    /// the composite, or the commands of a matrix or of the files of a corpus. The steps carry
+    /// the tags of the composite, so they are selected with it.
+    fn expand_into(self, benches: &mut Vec<CommandConfig>) -> Result<(), String> {
+        match self {
+            CommandConfigRepr::Command(command) => benches.push(CommandConfig::new(command)),
+            CommandConfigRepr::Options(CommandOptions {
+                command,
+                id,
+                profile,
+                interval,
+                expected_exit_codes,
+                tags,
+                sync_start,
+                measure_child,
+                produces,
+                verify_output,
+                sleeps,
+            }) => benches.push(CommandConfig {
+                command,
+                id,
+                profile,
+                interval,
+                expected_exit_codes,
+                tags,
+                sync_start,
+                measure_child,
+                steps: vec![],
+                produces,
+                verify_output,
+                sleeps,
+                import: None,
+                corpus: None,
+            }),
+            CommandConfigRepr::Composite(CompositeOptions {
+                composite,
+                steps,
+                id,
+                tags,
+            }) => {
+                if steps.is_empty() {
+                    return Err(format!("the composite `{composite}` has no steps"));
+                }
+                let first = benches.len();
+                for step in steps {
+                    benches.push(CommandConfig {
+                        tags: tags.clone(),
+                        ..CommandConfig::new(step)
+                    });
+                }
+                benches.push(CommandConfig {
+                    id,
+                    tags,
+                    steps: (first..benches.len()).collect(),
+                    ..CommandConfig::new(composite)
+                });
+            }
+            CommandConfigRepr::Import(ImportOptions {
+                import_results,
+                select,
+                id,
+                tags,
+            }) => {
+                let import = ImportResults {
+                    path: import_results,
+                    select: select.as_deref().map(Selector::parse).transpose()?,
+                };
+                let command = import.command();
+                benches.push(CommandConfig {
+                    id,
+                    tags,
+                    import: Some(import),
+                    ..CommandConfig::new(command)
+                });
+            }
+            CommandConfigRepr::Matrix(MatrixOptions {
+                template,
+                matrix,
+                exclude,
+                tags,
+            }) => {
+                let matrix = Matrix {
+                    template,
+                    parameters: matrix,
+                    exclude,
+                };
+                for cell in matrix.expand()? {
+                    benches.push(CommandConfig {
+                        id: Some(cell.id),
+                        tags: tags.clone(),
+                        ..CommandConfig::new(cell.command)
+                    });
+                }
+            }
+            CommandConfigRepr::Files(FilesOptions {
+                template,
+                files,
+                sample,
+                tags,
+            }) => {
+                if !template.contains("{file}") {
+                    return Err(format!(
+                        "the template `{template}` of `{files}` has no `{{file}}`"
+                    ));
+                }
+                if sample.as_ref().is_some_and(|sample| sample.count == 0) {
+                    return Err(format!(
+                        "the sample of `{files}` must have a count of at least 1"
+                    ));
+                }
+                let entry = benches.len();
+                for file in sample::expand_glob(Path::new("."), &files)? {
+                    benches.push(CommandConfig {
+                        id: Some(file.path.clone()),
+                        tags: tags.clone(),
+                        corpus: Some(CorpusCommand {
+                            entry,
+                            files: files.clone(),
+                            sample: sample.clone(),
+                            file: file.clone(),
+                        }),
+                        ..CommandConfig::new(template.replace("{file}", &file.path))
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A measurement backend, e.g. `"perf"` or `{ "external": "./gpu-stats {repetitions} {cmd}" }`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum BackendConfig {
+    Perf,
+    Getrusage,
+    External(String),
+}
+
+impl BackendConfig {
+    fn build(&self, perf: &Perf) -> Box<dyn Backend> {
+        match self {
+            BackendConfig::Perf => Box::new(perf.clone()),
+            BackendConfig::Getrusage => Box::new(Getrusage),
+            BackendConfig::External(template) => Box::new(External {
+                template: template.clone(),
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct VersusOther {
+    measure: String,
+    command: String,
+    rows: IndexMap<String, usize>,
+    #[serde(flatten)]
+    display: TableDisplay,
+}
+
+/// A `render-versus-self` table: either just the rows, or an object with the rows and
+/// display options.
+#[derive(Debug, Deserialize)]
+#[serde(from = "VersusSelfRepr")]
+struct VersusSelf {
+    rows: IndexMap<String, Compare>,
+    display: TableDisplay,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum VersusSelfRepr {
+    #[serde(rename_all = "kebab-case")]
+    Table {
+        rows: IndexMap<String, Compare>,
+        #[serde(flatten)]
+        display: TableDisplay,
+    },
+    Rows(IndexMap<String, Compare>),
+}
+
+impl From<VersusSelfRepr> for VersusSelf {
+    fn from(repr: VersusSelfRepr) -> Self {
+        match repr {
+            VersusSelfRepr::Table { rows, display } => VersusSelf { rows, display },
+            VersusSelfRepr::Rows(rows) => VersusSelf {
+                rows,
+                display: TableDisplay::default(),
+            },
+        }
+    }
+}
+
+/// How to display a comparison table.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TableDisplay {
+    /// Only show this many rows with the largest change, plus all significant rows.
+    #[serde(default)]
+    max_rows: Option<usize>,
+    /// When rows are omitted because of `max-rows`, also show the full table, collapsed.
+    #[serde(default)]
+    show_all_in_details: bool,
+    /// Also compare the spread of the rows, in a column with the change of the coefficient of
+    /// variation.
+    #[serde(default)]
+    compare_variance: bool,
+    /// Overrides the global `minimum-effect-percent` for the rows of this table.
+    #[serde(default)]
+    minimum_effect_percent: Option<f64>,
+    /// The order to render the rows in, see [`TableOrder`].
+    #[serde(default)]
+    order: TableOrder,
+    /// Only rendered, for curiosity, like a comparison with a deliberately slow reference: the
+    /// rows are left out of everything that counts, gates or notifies on the comparisons, see
+    /// [`compare::counted_rows`].
+    #[serde(default)]
+    informational: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Compare {
+    measure: String,
+    before: Reference,
+    after: Reference,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Reference {
+    command: String,
+    index: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchData {
+    // The version of the format of the results, older results are upgraded by `benchmarker
+    // migrate`, see [`migrate`]
+    #[serde(default)]
+    schema_version: u32,
+
+    // What and when are we benchmarking
+    commit_hash: String,
+    commit_timestamp: u64,
+
+    // timestamp when the benchmark was started
+    timestamp: SystemTime,
+
+    // Where are we benchmarking it on
+    arch: String,
+    os: String,
+    runner: String,
+    cpu_model: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cpu_frequency: Option<CpuFrequency>,
+    // The CPU model and the number of CPUs, to tell the machines of a runner pool apart
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    machine_class: Option<String>,
+    // The stable identifier of the machine, which survives cosmetic changes of the CPU model
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    machine_id: Option<String>,
+    // How the benchmarks were isolated from other processes, if at all
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    isolation: Option<IsolationSettings>,
+    // How busy the system was right before the benchmarks started
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    preflight: Option<Preflight>,
+    // The temperature and the throttling of the CPU while the benchmarks ran
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    thermal: Option<Thermal>,
+    // The allowlisted environment variables and the version of the C library
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    environment: Option<Environment>,
+
+    // The version of the benchmarked package, if known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+    // The verified SHA-256 of every fixture, by path
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    fixtures: IndexMap<String, String>,
+    // The SHA-256 of every fingerprinted binary, by path
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    binary_hashes: IndexMap<String, String>,
+    // How every configured binary was built, by path, see [`build_info`]
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    build_info: IndexMap<String, BuildInfo>,
+    // The ref, branch and pull request that triggered the run in GitHub Actions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    trigger: Option<Trigger>,
+    // The regressions accepted by `Perf-Exempt` trailers or the label of the pull request
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    exemptions: Vec<Exemption>,
+    // Whether the working tree had uncommitted changes, and the SHA-256 of `git diff HEAD`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    dirty: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    diff_sha256: Option<String>,
+    // The groups that `--changed-only` skipped, or that `--fail-fast` didn't run, with the
+    // reason
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    skipped_groups: IndexMap<String, String>,
+    // Whether `--fail-fast` stopped the run before all groups ran. Partial results are never
+    // stored, nor used as a baseline
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    partial: bool,
+    // Only set on a baseline: how many commits it is before the merge base, when the merge
+    // base itself has no results
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ancestor_distance: Option<usize>,
+    // Only set on a baseline: how old it is, with `baseline-staleness`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    staleness: Option<Staleness>,
+    // The overhead of measuring an empty program, for every wrapper and backends used
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    harness_overhead: Vec<HarnessOverhead>,
+    // The seed of the random choices of the run, see [`seed`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    // The files chosen from the corpora with a `sample`, by group, see [`sample`]
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    samples: IndexMap<String, Vec<CorpusSample>>,
+
+    // The actual results for benchmarks
+    bench_groups: IndexMap<String, Vec<SingleBench>>,
+}
+
+pub(crate) struct HumanReadable(f64);
+
+impl Display for HumanReadable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            1_000_000_000.0.. => write!(f, "{:6.2}G", self.0 / 1_000_000_000.0),
+            1_000_000.0.. => write!(f, "{:6.2}M", self.0 / 1_000_000.0),
+            1_000.0.. => write!(f, "{:6.2}K", self.0 / 1_000.0),
+            _ => write!(f, "{:7.0}", self.0),
+        }
+    }
+}
+
+/// Like [`HumanReadable`] with binary prefixes, for the counters in bytes.
+pub(crate) struct BinaryReadable(f64);
+
+impl Display for BinaryReadable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        const KI: f64 = 1024.0;
+        match self.0 {
+            v if v >= KI * KI * KI => write!(f, "{:6.2}Gi", v / (KI * KI * KI)),
+            v if v >= KI * KI => write!(f, "{:6.2}Mi", v / (KI * KI)),
+            v if v >= KI => write!(f, "{:6.2}Ki", v / KI),
+            v => write!(f, "{v:8.0}"),
+        }
+    }
+}
+
+/// A value of a counter in `unit` for the comparison tables: with binary prefixes for bytes,
+/// see [`BinaryReadable`], and decimal ones otherwise.
+pub(crate) fn readable(value: f64, unit: &str) -> String {
+    if unit == io_counters::BYTES {
+        BinaryReadable(value).to_string()
+    } else {
+        HumanReadable(value).to_string()
+    }
+}
+
+/// `value` rounded to `decimals` decimals, without trailing zeros, so that values that only
+/// differ in their last bits, like the same mean computed on x86_64 and on aarch64, render the
+/// same. `-0` renders as `0`.
+pub(crate) fn format_number(value: f64, decimals: usize) -> String {
+    let rounded = format!("{value:.decimals$}");
+    let trimmed = match rounded.contains('.') {
+        true => rounded.trim_end_matches('0').trim_end_matches('.'),
+        false => &rounded,
+    };
+    match trimmed {
+        "-0" => "0".to_owned(),
+        trimmed => trimmed.to_owned(),
+    }
+}
+
+/// The value of a counter in the raw table: durations in milliseconds with three decimals, like
+/// perf shows them, everything else with up to three.
+fn format_raw_value(counter: &BenchCounter) -> String {
+    if counter.unit == "msec" {
+        // Adding zero turns `-0` into `0`.
+        format!("{:.3}", counter.value + 0.0)
+    } else {
+        format_number(counter.value, 3)
+    }
+}
+
+#[test]
+fn format_numbers() {
+    assert_eq!(format_number(1_000_000_000.0, 3), "1000000000");
+    assert_eq!(format_number(250.5, 3), "250.5");
+    assert_eq!(format_number(1.2345678, 3), "1.235");
+    assert_eq!(format_number(2.0000000000000004, 3), "2");
+    assert_eq!(format_number(1999.9999999999998, 0), "2000");
+    assert_eq!(format_number(-0.0001, 3), "0");
+    assert_eq!(format_number(-1.5, 0), "-2");
+    assert_eq!(format_number(16.666666666666664, 6), "16.666667");
+
+    let counter = |value: f64, unit: &str| BenchCounter {
+        value,
+        variance: 0.0,
+        repetitions: 20,
+        unit: unit.to_owned(),
+    };
+    assert_eq!(format_raw_value(&counter(254.21, "msec")), "254.210");
+    assert_eq!(format_raw_value(&counter(-0.0, "msec")), "0.000");
+    assert_eq!(format_raw_value(&counter(0.1 + 0.2, "")), "0.3");
+}
+
+#[test]
+fn deterministic_rendering() {
+    let config: Config = serde_json::from_str(
+        r#"{
+            "commands": { "compress": ["./compress 6"], "decompress": ["./decompress"] },
+            "perf-events-for-group": { "compress": ["task-clock", "instructions", "cycles"] },
+            "render-versus-self": {},
+            "render-versus-other": {}
+        }"#,
+    )
+    .unwrap();
+    let counters = [
+        ("cycles", 1.0e9 / 3.0, 1.0e12 / 7.0, "", 20),
+        ("task-clock", 0.1 + 0.2, 0.01 / 3.0, "msec", 20),
+        ("branches", 2.0 / 3.0 * 1.0e8, 0.0, "", 20),
+        ("instructions", 2.0000000000000004e9, 1.0e6, "", 20),
+    ];
+    let build = |commit_hash, scale: f64, reverse: bool| {
+        let mut counters = counters.to_vec();
+        if reverse {
+            counters.reverse();
+        }
+        let bench = |b| {
+            counters.iter().fold(
+                b,
+                |b: testkit::BenchBuilder, (name, value, variance, unit, reps)| {
+                    b.counter(name, value * scale, *variance, *reps, unit)
+                },
+            )
+        };
+        testkit::BenchDataBuilder::new(commit_hash)
+            .group("compress", |g| g.bench(["./compress", "6"], bench))
+            .group("decompress", |g| g.bench(["./decompress"], bench))
+            .build()
+    };
+    let render = |reverse: bool| {
+        let prev = build("1111111111111111111111111111111111111111", 1.0, reverse);
+        let data = build("2222222222222222222222222222222222222222", 1.1, !reverse);
+        let mut md = String::new();
+        data.render_markdown_raw(&mut md, "owner/repo", Some(&prev), &config);
+        let csv = csv::render(
+            &data,
+            Some(&prev),
+            &config.counter_order(),
+            &Sanitizer::default(),
+        );
+        let diff = diff::DiffReport::new(&prev, &data);
+        let mut diff_md = String::new();
+        diff.render_markdown(&mut diff_md);
+        let diff_json = serde_json::to_string_pretty(&diff).unwrap();
+        [md, csv, diff_md, diff_json]
+    };
+
+    // The same output every time, whatever order the counters were stored in.
+    let first = render(false);
+    assert_eq!(first, render(false));
+    assert_eq!(first, render(true));
+
+    // The configured counters first, in the order of the config, then the others sorted.
+    let [md, csv, _, _] = &first;
+    let header = md
+        .lines()
+        .find(|line| line.starts_with("|command|"))
+        .unwrap();
+    let columns = |line: &str| {
+        ["task-clock", "instructions", "cycles", "branches"]
+            .map(|counter| line.find(&format!("|{counter}|")).unwrap())
+    };
+    assert!(columns(header).is_sorted(), "{header}");
+    assert!(md.contains("|`0.330±0` msec |"), "{md}");
+    let rows = csv
+        .lines()
+        .filter(|line| line.starts_with("compress,"))
+        .collect::<Vec<_>>();
+    assert!(rows[0].contains(",task-clock,0.33,"), "{csv}");
+    assert!(rows[1].contains(",instructions,2200000000,"), "{csv}");
+    assert!(rows[2].contains(",cycles,366666666.666667,"), "{csv}");
+    assert!(rows[3].contains(",branches,73333333.333333,"), "{csv}");
+}
+
+#[test]
+fn human_readable() {
+    assert_eq!(format!("{}", HumanReadable(12.0)), "     12");
+    assert_eq!(format!("{}", HumanReadable(123.0)), "    123");
+    assert_eq!(format!("{}", HumanReadable(1234.0)), "  1.23K");
+    assert_eq!(format!("{}", HumanReadable(12345.0)), " 12.35K");
+    assert_eq!(format!("{}", HumanReadable(123456.0)), "123.46K");
+    assert_eq!(format!("{}", HumanReadable(1234567.0)), "  1.23M");
+    assert_eq!(format!("{}", HumanReadable(1_000_000_000.0)), "  1.00G");
+
+    assert_eq!(format!("{}", BinaryReadable(512.0)), "     512");
+    assert_eq!(format!("{}", BinaryReadable(1024.0)), "  1.00Ki");
+    assert_eq!(format!("{}", BinaryReadable(1_048_576.0)), "  1.00Mi");
+    assert_eq!(
+        format!("{}", BinaryReadable(3.5 * 1_073_741_824.0)),
+        "  3.50Gi"
+    );
+    assert_eq!(readable(2_097_152.0, "bytes"), "  2.00Mi");
+    assert_eq!(readable(2_097_152.0, ""), "  2.10M");
+}
+
+impl BenchData {
+    /// Give the results recorded without an id with the given command lines that id.
+    fn remap_ids(&mut self, remap_ids: &[(String, String)]) {
+        for bench in self.bench_groups.values_mut().flatten() {
+            if bench.id.is_some() {
+                continue;
+            }
+            let command = bench.cmd.join(" ");
+            if let Some((_, id)) = remap_ids.iter().find(|(old, _)| *old == command) {
+                bench.id = Some(id.clone());
+            }
+        }
+    }
+
+    /// What tells apart the machine the results were measured on from others.
+    fn machine_key(&self) -> machine::MachineKey<'_> {
+        machine::MachineKey {
+            arch: &self.arch,
+            os: &self.os,
+            cpu_model: &self.cpu_model,
+            machine_class: self.machine_class.as_deref(),
+            machine_id: self.machine_id.as_deref(),
+        }
+    }
+
+    /// The commit the results are of, with a `-dirty` suffix when the working tree had
+    /// uncommitted changes. A dirty result never matches the commit it was based on.
+    fn commit_id(&self) -> String {
+        if self.dirty {
+            format!("{}-dirty", self.commit_hash)
+        } else {
+            self.commit_hash.clone()
+        }
+    }
+
+    /// [`Self::commit_id`] with the commit hash shortened, for headers.
+    fn short_commit_id(&self) -> String {
+        let short = &self.commit_hash[..self.commit_hash.len().min(7)];
+        if self.dirty {
+            format!("{short}-dirty")
+        } else {
+            short.to_owned()
+        }
+    }
+
+    /// The package version to show next to the commit hash in headers, e.g. ` (v0.4.1)`, or
+    /// ` (v0.4.0 → v0.4.1)` when the version changed since `prev`.
+    fn version_label(&self, prev: Option<&Self>) -> String {
+        match (
+            prev.and_then(|prev| prev.version.as_deref()),
+            self.version.as_deref(),
+        ) {
+            (Some(old), Some(new)) if old != new => format!(" (v{old} → v{new})"),
+            (_, Some(new)) => format!(" (v{new})"),
+            (_, None) => String::new(),
+        }
+    }
+
+    /// How headers introduce these results as the baseline, followed by its commit.
+    fn baseline_relation(&self) -> &'static str {
+        match self.ancestor_distance {
+            Some(_) => "versus ancestor",
+            None => "with parent",
+        }
+    }
+
+    /// Shown after the commit of the baseline in headers, e.g. ` (2 commits before merge-base)`.
+    fn ancestor_label(&self) -> String {
+        match self.ancestor_distance {
+            Some(1) => " (1 commit before merge-base)".to_owned(),
+            Some(distance) => format!(" ({distance} commits before merge-base)"),
+            None => String::new(),
+        }
+    }
+
+    /// Shown after the commit of the baseline in headers, e.g. ` (baseline is 2 days / 3
+    /// commits old)`.
+    fn staleness_label(&self) -> String {
+        match &self.staleness {
+            Some(staleness) => format!(" ({})", staleness.label()),
+            None => String::new(),
+        }
+    }
+
+    /// The CPU to show in headers, with the machine classes when the previous results are
+    /// from a different class of machine.
+    fn machine_label(&self, prev: Option<&Self>) -> String {
+        let cross_class = prev.and_then(|prev| {
+            machine::cross_class(prev.machine_class.as_deref(), self.machine_class.as_deref())
+        });
+        match cross_class {
+            Some(cross_class) => format!(
+                "{}, machine class `{}` → `{}`",
+                self.cpu_model, cross_class.before, cross_class.after
+            ),
+            None => self.cpu_model.clone(),
+        }
+    }
+
+    /// The raw numbers for the commands. Good to have, but not the easiest to interpret
+    fn render_markdown_raw(
+        &self,
+        md: &mut String,
+        repository: &str,
+        prev_results: Option<&Self>,
+        config: &Config,
+    ) {
+        self.render_markdown_raw_header(md, repository, prev_results);
+        overhead::render_markdown(md, &self.harness_overhead);
+
+        for group_name in self.bench_groups.keys() {
+            use std::fmt::Write;
+
+            writeln!(md, "### {}", group_name).unwrap();
+            writeln!(md).unwrap();
+
+            self.render_markdown_raw_group(
+                md,
+                group_name,
+                prev_results,
+                config.raw_table_options(group_name),
+            );
+        }
+    }
+
+    fn render_markdown_raw_header(
+        &self,
+        md: &mut String,
+        repository: &str,
+        prev_results: Option<&Self>,
+    ) {
+        use std::fmt::Write;
+
+        // Runner pools mix machines, those differences are shown with the machine class.
+        if let Some(prev_results) = prev_results {
+            assert_eq!(self.arch, prev_results.arch);
+            assert_eq!(self.os, prev_results.os);
+        }
+
+        if let Some(prev_results) = prev_results {
+            writeln!(
+                md,
+                "## [`{commit_id}`](https://github.com/{repository}/commit/{commit}) {relation} [`{commit_old_id}`](https://github.com/{repository}/commit/{commit_old})\
+                    {ancestor}{staleness}{version} (on {cpu})",
+                commit_id = self.commit_id(),
+                commit = self.commit_hash,
+                relation = prev_results.baseline_relation(),
+                commit_old_id = prev_results.commit_id(),
+                commit_old = prev_results.commit_hash,
+                ancestor = prev_results.ancestor_label(),
+                staleness = prev_results.staleness_label(),
+                version = self.version_label(Some(prev_results)),
+                cpu = self.machine_label(Some(prev_results))
+            )
+                .unwrap();
+        } else {
+            writeln!(
+                md,
+                "## [`{commit_id}`](https://github.com/{repository}/commit/{commit})\
+                 {version} (on {cpu})",
+                commit_id = self.commit_id(),
+                commit = self.commit_hash,
+                version = self.version_label(None),
+                cpu = self.cpu_model
+            )
+            .unwrap();
+        }
+        writeln!(md).unwrap();
+        // The random choices of the run can be reproduced with `--seed`.
+        if let Some(seed) = self.seed {
+            writeln!(md, "Seed: `{seed}`\n").unwrap();
+        }
+    }
+
+    /// The raw table for a single benchmark group, without a heading. Only the
+    /// `stable_counters` are compared against results from a different class of machine, and
+    /// the `-cold` and `-warm` counters are only shown with `show_cold_warm`. A table wider
+    /// than `max_table_width` columns is split into several, each with a part of the counters.
+    fn render_markdown_raw_group(
+        &self,
+        md: &mut String,
+        group_name: &str,
+        prev_results: Option<&Self>,
+        options: RawTableOptions,
+    ) {
+        use std::fmt::Write;
+
+        let RawTableOptions {
+            stable_counters,
+            show_cold_warm,
+            max_table_width,
+            row_sort,
+            commands,
+            counter_order,
+            limits,
+        } = options;
+
+        let group_results = &self.bench_groups[group_name];
+        let prev_group_results = prev_results.and_then(|x| x.bench_groups.get(group_name));
+        let cross_class = prev_results.is_some_and(|prev_results| {
+            machine::cross_class(
+                prev_results.machine_class.as_deref(),
+                self.machine_class.as_deref(),
+            )
+            .is_some()
+        });
+
+        let efficiencies = limits
+            .map(|limits| limits::collect(limits, self))
+            .unwrap_or_default();
+
+        // Sorted by the changes only where they are shown.
+        let rows = row_sort
+            .permutation(
+                group_results,
+                prev_group_results
+                    .filter(|_| {
+                        !cross_class
+                            || row_sort.counter(group_results).is_some_and(|counter| {
+                                machine::is_machine_stable(stable_counters, counter)
+                            })
+                    })
+                    .map(Vec::as_slice),
+            )
+            .into_iter()
+            .map(|index| {
+                let annotations = limits::annotations(&efficiencies, group_name, index, None);
+                (&group_results[index], annotations.collect())
+            })
+            .collect::<Vec<_>>();
+
+        let available_counters = counter_order.sort(
+            group_name,
+            group_results
+                .iter()
+                .flat_map(|bench| bench.counters.keys())
+                .filter(|counter| show_cold_warm || !rusage::is_cold_or_warm(counter)),
+        );
+
+        // Every table repeats the command column, and has a value and a Δ column per counter.
+        let per_table = max_table_width.map_or(available_counters.len(), |width| {
+            (width.saturating_sub(1) / 2).max(1)
+        });
+        let mut chunks = available_counters
+            .chunks(per_table.max(1))
+            .collect::<Vec<_>>();
+        if chunks.is_empty() {
+            chunks.push(&[]);
+        }
+        for (index, counters) in chunks.iter().enumerate() {
+            if chunks.len() > 1 {
+                if index > 0 {
+                    writeln!(md).unwrap();
+                }
+                let first = index * per_table + 1;
+                let last = first + counters.len() - 1;
+                let total = available_counters.len();
+                if first == last {
+                    writeln!(md, "_(counter {first} of {total})_").unwrap();
+                } else {
+                    writeln!(md, "_(counters {first}–{last} of {total})_").unwrap();
+                }
+                writeln!(md).unwrap();
+            }
+            let mut cells = commands.cells();
+            self.render_markdown_raw_table(
+                md,
+                &rows,
+                prev_group_results,
+                counters,
+                prev_results.map(|prev_results| MachineClasses {
+                    before: prev_results,
+                    after: self,
+                    stable_counters,
+                }),
+                &mut cells,
+            );
+            cells.render_footnotes(md);
+        }
+
+        mix::render_markdown_lines(
+            md,
+            group_results
+                .iter()
+                .map(|bench| (&bench.cmd[..], &bench.counters)),
+            commands,
+        );
+        cache_stats::render_markdown_table(
+            md,
+            group_results
+                .iter()
+                .map(|bench| (&bench.cmd[..], &bench.counters)),
+            commands,
+        );
+    }
+
+    /// A raw table with the value and the Δ of the `counters` of every command in `rows`. When
+    /// a command was measured on another class of machine than its previous results, only the
+    /// stable counters of the `classes` are compared. Every command is followed by its
+    /// annotations, like how close it is to its theoretical limits.
+    fn render_markdown_raw_table(
+        &self,
+        md: &mut String,
+        rows: &[(&SingleBench, Vec<String>)],
+        prev_group_results: Option<&Vec<SingleBench>>,
+        counters: &[&String],
+        classes: Option<MachineClasses>,
+        cells: &mut CommandCells,
+    ) {
+        use std::fmt::Write;
+
+        write!(md, "|command|").unwrap();
+        for counter in counters {
+            write!(md, "{counter}|{counter} Δ|").unwrap();
+        }
+        writeln!(md).unwrap();
+        write!(md, "|---|").unwrap();
+        for _ in counters {
+            write!(md, "---|---|").unwrap();
+        }
+        writeln!(md).unwrap();
+
+        for (bench, annotations) in rows {
+            let bench = *bench;
+            let prev_bench = prev_group_results.and_then(|x| find_prev_bench(x, bench));
+
+            write!(md, "|{}", cells.cell(&bench.cmd.join(" "))).unwrap();
+            if let Some(series) = &bench.intervals {
+                write!(md, " {}", intervals::sparkline(&series.values)).unwrap();
+            }
+            for annotation in annotations {
+                write!(md, " {annotation}").unwrap();
+            }
+            write!(md, "|").unwrap();
+
+            for &counter in counters {
+                if let Some(data) = bench.counters.get(counter) {
+                    if let Some(prev_data) = prev_bench
+                        .filter(|prev_bench| {
+                            classes
+                                .is_none_or(|classes| classes.compared(prev_bench, bench, counter))
+                        })
+                        .and_then(|prev_bench| prev_bench.counters.get(counter))
+                    {
+                        let diff = if data.value > prev_data.value {
+                            format!(
+                                "+{:.1}%",
+                                (data.value - prev_data.value) / prev_data.value * 100.
+                            )
+                        } else {
+                            format!(
+                                "-{:.1}%",
+                                (prev_data.value - data.value) / prev_data.value * 100.
+                            )
+                        };
+
+                        write!(
+                            md,
+                            "`{}±{}` {} | `{diff}` |",
+                            format_raw_value(data),
+                            format_number(data.variance.sqrt(), 0),
+                            data.unit,
+                        )
+                        .unwrap();
+                    } else {
+                        write!(
+                            md,
+                            "`{}±{}` {} | `n.a.` |",
+                            format_raw_value(data),
+                            format_number(data.variance.sqrt(), 0),
+                            data.unit,
+                        )
+                        .unwrap();
+                    }
+                } else {
+                    write!(md, "|").unwrap();
+                }
+            }
+            writeln!(md).unwrap();
+        }
+    }
+
+    fn render_markdown_diff_pretty(
+        md: &mut String,
+        repository: &str,
+        tables: &[ComparisonTable],
+        before: &Self,
+        after: &Self,
+        baseline_anomaly: Option<&BaselineAnomaly>,
+        markers: &Markers,
+    ) {
+        use std::fmt::Write;
+
+        assert_eq!(before.arch, after.arch);
+        assert_eq!(before.os, after.os);
+
+        writeln!(
+            md,
+            concat!(
+                "## ",
+                "[`{commit_new_short}`](https://github.com/{repository}/commit/{commit_new})",
+                " {relation} ",
+                "[`{commit_old_short}`](https://github.com/{repository}/commit/{commit_old})",
+                "{ancestor}{staleness}{version} (on {cpu})"
+            ),
+            repository = repository,
+            relation = before.baseline_relation(),
+            ancestor = before.ancestor_label(),
+            staleness = before.staleness_label(),
+            version = after.version_label(Some(before)),
+            commit_new = after.commit_hash,
+            commit_old = before.commit_hash,
+            commit_new_short = after.short_commit_id(),
+            commit_old_short = before.short_commit_id(),
+            cpu = after.machine_label(Some(before))
+        )
+        .unwrap();
+
+        let header = format!(
+            "| name | [before](https://github.com/{repository}/commit/{commit_before}) | [after](https://github.com/{repository}/commit/{commit_after}) | Δ |\n| --- | --- | --- | --- |\n",
+            commit_before = before.commit_hash,
+            commit_after = after.commit_hash,
+        );
+
+        for table in tables {
+            writeln!(md, "### {}", table.heading()).unwrap();
+            writeln!(md).unwrap();
+            baseline::render_markdown_table_note(md, baseline_anomaly);
+
+            table.render_markdown(md, &header, markers);
+        }
+    }
+
+    fn render_markdown_self_diff_pretty(
+        md: &mut String,
+        repository: &str,
+        tables: &[ComparisonTable],
+        data: &Self,
+        markers: &Markers,
+    ) {
+        use std::fmt::Write;
+
+        writeln!(
+            md,
+            concat!(
+                "## ",
+                "[`{commit_new_short}`](https://github.com/{repository}/commit/{commit_new})",
+                "{version} (on {cpu})"
+            ),
+            repository = repository,
+            version = data.version_label(None),
+            commit_new = data.commit_hash,
+            commit_new_short = data.short_commit_id(),
+            cpu = data.cpu_model
+        )
+        .unwrap();
+
+        for table in tables {
+            writeln!(md, "### {}", table.heading()).unwrap();
+            writeln!(md).unwrap();
+
+            table.render_markdown(
+                md,
+                "| name | before | after | Δ |\n| --- | --- | --- | --- |\n",
+                markers,
+            );
+        }
+    }
+}
+
+fn get_cpu_model() -> String {
+    if cfg!(target_os = "linux") {
+        serde_json::from_slice::<serde_json::Value>(
+            &Command::new("lscpu")
+                .env("LANG", "C")
+                .arg("-J")
+                .output()
+                .unwrap()
+                .stdout,
+        )
+        .unwrap()["lscpu"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|entry| entry["field"] == "Model name:")
+            .unwrap()["data"]
+            .as_str()
+            .unwrap()
+            .to_owned()
+    } else if cfg!(target_os = "macos") {
+        String::from_utf8_lossy(
+            &Command::new("sysctl")
+                .arg("-n")
+                .arg("machdep.cpu.brand_string")
+                .output()
+                .unwrap()
+                .stdout,
+        )
+        .trim()
+        .to_owned()
+    } else {
+        "unknown".to_owned()
+    }
+}
+
+/// The `benchmarker` command line, with its subcommands.
+pub fn main() {
+    if env::args().nth(1).as_deref() == Some("diff") {
+        let output = diff::run(env::args().skip(2)).unwrap_or_else(|err| panic!("{err}"));
+        print!("{output}");
+        return;
+    }
+    if env::args().nth(1).as_deref() == Some("replay") {
+        let output = replay::run(env::args().skip(2)).unwrap_or_else(|err| panic!("{err}"));
+        print!("{output}");
+        return;
+    }
+    if env::args().nth(1).as_deref() == Some("doctor") {
+        let (output, severity) =
+            doctor::run(env::args().skip(2)).unwrap_or_else(|err| panic!("{err}"));
+        print!("{output}");
+        std::process::exit(severity.exit_code());
+    }
+    if env::args().nth(1).as_deref() == Some("render-history") {
+        let output = render_history::run(env::args().skip(2)).unwrap_or_else(|err| panic!("{err}"));
+        print!("{output}");
+        return;
+    }
+    if env::args().nth(1).as_deref() == Some("--map-keys") {
+        let output = comparison_key::run(env::args().skip(2)).unwrap_or_else(|err| panic!("{err}"));
+        print!("{output}");
+        return;
+    }
+    if env::args().nth(1).as_deref() == Some("migrate") {
+        let output = migrate::run(env::args().skip(2)).unwrap_or_else(|err| panic!("{err}"));
+        print!("{output}");
+        return;
+    }
+    if env::args().nth(1).as_deref() == Some("backfill") {
+        let output =
+            backfill_history::run(env::args().skip(2)).unwrap_or_else(|err| panic!("{err}"));
+        print!("{output}");
+        return;
+    }
+    if env::args().nth(1).as_deref() == Some("verify-report") {
+        let (output, matched) =
+            verify_report::run(env::args().skip(2)).unwrap_or_else(|err| panic!("{err}"));
+        print!("{output}");
+        std::process::exit(if matched { 0 } else { 1 });
+    }
+    if env::args().nth(1).as_deref() == Some("compact") {
+        let output = compact::run(env::args().skip(2)).unwrap_or_else(|err| panic!("{err}"));
+        print!("{output}");
+        return;
+    }
+    if env::args().nth(1).as_deref() == Some("stat") {
+        let output = stat::run(env::args().skip(2), std::io::stdin().lock())
+            .unwrap_or_else(|err| panic!("{err}"));
+        print!("{output}");
+        return;
+    }
+
+    let args = Args::parse(env::args().skip(1)).unwrap_or_else(|err| panic!("{err}"));
+    let run_report_path = args.run_report.clone();
+
+    // The report is written however the run ends, including when it panics.
+    let mut report = RunReport::default();
+    let mut scratch: Option<RunScratch> = None;
+    let mut sanitizer = Sanitizer::default();
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        run(args, &mut report, &mut scratch, &mut sanitizer)
+    }));
+    report.exit_code = match result {
+        Ok(exit_code) => exit_code,
+        Err(_) => EXIT_PANIC,
+    };
+
+    // Exiting the process doesn't drop anything, so the scratch directory is removed here.
+    if let Some(kept) = scratch.and_then(|scratch| scratch.finish(report.exit_code != 0)) {
+        report.artifacts.insert("scratch".to_owned(), kept);
+    }
+
+    if let Some(path) = &run_report_path {
+        if let Err(err) = report.write(path, &sanitizer) {
+            eprintln!("warning: {err}");
+        }
+    }
+
+    match result {
+        Ok(exit_code) => std::process::exit(exit_code),
+        Err(payload) => panic::resume_unwind(payload),
+    }
+}
+
+/// Run the benchmarks and everything that follows, returning the exit code. The scratch
+/// directory and the sanitizer for the run report are handed back through `scratch` and
+/// `sanitizer`, so they outlive a panic.
+fn run(
+    args: Args,
+    report: &mut RunReport,
+    scratch: &mut Option<RunScratch>,
+    sanitizer: &mut Sanitizer,
+) -> i32 {
+    let Args {
+        commit_hash,
+        config_paths,
+        previous_results_path,
+        remap_ids,
+        stream,
+        require_isolation,
+        require_quiet,
+        only_tags,
+        skip_tags,
+        changed_only,
+        run_report: _,
+        keep_scratch,
+        results_file,
+        csv: csv_path,
+        allow_dirty,
+        fail_fast,
+        seed,
+        replay_seed_from,
+        backfill_baseline_counters,
+        persist_backfill,
+        results_index,
+        strict_sampling,
+    } = args;
+    eprintln!("current commit: {}", commit_hash);
+
+    let commit_timestamp = {
+        // git show 27b31a568651dd725488e422e854095639d75af6 --no-patch --pretty=format:"%ct"
+        let output = Command::new("git")
+            .args([
+                "show",
+                &commit_hash,
+                "--no-patch",
+                "--pretty=format:\"%ct\"",
+            ])
+            .output()
+            .unwrap();
+
+        String::from_utf8_lossy(&output.stdout)
+            .trim()
+            .trim_matches('"')
+            .parse::<u64>()
+            .unwrap()
+    };
+
+    let github = GitHubContext::from_env(|name| env::var(name).ok());
+    report.stamp = Stamp::from_env(|name| env::var(name).ok());
+    let cpu_model = get_cpu_model();
+    let mut bench_data = BenchData {
+        schema_version: migrate::SCHEMA_VERSION,
+        commit_hash,
+        commit_timestamp,
+        timestamp: SystemTime::now(),
+
+        arch: env::var("RUNNER_ARCH").unwrap_or_default(),
+        os: env::var("RUNNER_OS").unwrap_or_default(),
+        runner: env::var("RUNNER_NAME").unwrap_or_else(|_| "<local bench>".to_owned()),
+        machine_class: machine::detect(&cpu_model),
+        machine_id: None,
+        cpu_model,
+        cpu_frequency: CpuFrequency::detect(),
+        isolation: None,
+        preflight: None,
+        thermal: None,
+        environment: None,
+
+        version: None,
+        fixtures: IndexMap::new(),
+        binary_hashes: IndexMap::new(),
+        build_info: IndexMap::new(),
+        trigger: github.trigger.clone(),
+        exemptions: vec![],
+        dirty: false,
+        diff_sha256: None,
+        skipped_groups: IndexMap::new(),
+        partial: false,
+        ancestor_distance: None,
+        staleness: None,
+        harness_overhead: vec![],
+        seed: None,
+        samples: IndexMap::new(),
+
+        bench_groups: IndexMap::new(),
+    };
+
+    let seed = match (seed, replay_seed_from) {
+        (Some(seed), _) => seed,
+        (None, Some(path)) => seed::from_results(&path).unwrap_or_else(|err| panic!("{err}")),
+        (None, None) => seed::generate(),
+    };
+    eprintln!("seed: {seed}");
+    bench_data.seed = Some(seed);
+    let mut rng = Rng::new(seed);
+
+    match worktree::diff_sha256(Path::new(".")) {
+        Ok(None) => {}
+        Ok(Some(diff_sha256)) => {
+            if allow_dirty {
+                eprintln!("warning: the working tree has uncommitted changes, the results are marked as dirty");
+            } else {
+                eprintln!("warning: the working tree has uncommitted changes, the results are marked as dirty and the run exits with {EXIT_DIRTY} so they are not stored; pass --allow-dirty to benchmark it anyway");
+            }
+            bench_data.dirty = true;
+            bench_data.diff_sha256 = Some(diff_sha256);
+            report.dirty = true;
+        }
+        Err(err) => eprintln!("warning: failed to check the working tree for changes: {err}"),
+    }
+
+    let mut config = Config::load(&config_paths).unwrap_or_else(|err| panic!("{err}"));
+    config
+        .validate()
+        .unwrap_or_else(|err| panic!("invalid config: {err}"));
+    bench_data.machine_id = Some(machine::detect_id(
+        &config.machine_aliases,
+        &bench_data.arch,
+        &bench_data.cpu_model,
+    ));
+    // The first random choice of the run, so the same seed chooses the same files.
+    bench_data.samples = config.sample_corpora(&mut rng);
+    for (group_name, samples) in &bench_data.samples {
+        for sample in samples {
+            eprintln!(
+                "{group_name}: sampled {} of the files of `{}`",
+                sample.chosen.len(),
+                sample.files
+            );
+        }
+    }
+    config.strict_sampling = strict_sampling;
+    config.retain_tagged(&only_tags, &skip_tags);
+    report.stamp.config_sha256 = Some(config.sha256.clone());
+    report.stamp.only_tags = only_tags.clone();
+    report.stamp.skip_tags = skip_tags.clone();
+    report.stamp.strict_sampling = strict_sampling;
+    if let Some(sanitize) = config.sanitize.take() {
+        *sanitizer = Sanitizer::new(sanitize, env::var("RUNNER_NAME").ok().as_deref());
+    }
+    let sanitizer = &*sanitizer;
+    if let Some(gate) = config.gate.as_ref().or(config.shadow_gate.as_ref()) {
+        let (exemptions, warnings) = exemptions::collect(
+            &bench_data.commit_hash,
+            &github,
+            gate.exempt_label.as_deref(),
+        );
+        for warning in warnings {
+            eprintln!("warning: {warning}");
+        }
+        bench_data.exemptions = exemptions;
+    }
+    report.groups = config
+        .commands
+        .iter()
+        .map(|(group_name, benches)| {
+            let mut group = GroupReport::skipped(benches.len());
+            group.config = config.group_sources.get(group_name).cloned();
+            (group_name.clone(), group)
+        })
+        .collect();
+
+    let scratch_root = config.scratch_root.clone().unwrap_or_else(env::temp_dir);
+    let scratch_dir = scratch
+        .insert(
+            RunScratch::create(
+                &scratch_root,
+                &bench_data.commit_hash,
+                keep_scratch,
+                config.keep_scratch_on_failure,
+            )
+            .unwrap_or_else(|err| panic!("{err}")),
+        )
+        .path();
+
+    bench_data.version = manifest::package_version(&config.version_manifest);
+    eprintln!(
+        "package version: {}",
+        bench_data.version.as_deref().unwrap_or("unknown")
+    );
+
+    if let Some(fingerprint) = &config.fingerprint {
+        bench_data.binary_hashes = fingerprint.hash();
+    }
+    if let Some(build_info) = &config.build_info {
+        bench_data.build_info = build_info.read();
+    }
+
+    // Before any benchmark, so downloading doesn't disturb the measurements.
+    for fixture in &config.fixtures {
+        let sha256 = fixture.ensure().unwrap_or_else(|err| panic!("{err}"));
+        bench_data
+            .fixtures
+            .insert(fixture.path.display().to_string(), sha256);
+    }
+    if let Some(min_free_space) = config
+        .outputs
+        .as_ref()
+        .and_then(|outputs| outputs.min_free_space)
+    {
+        outputs::check_free_space(Path::new("."), min_free_space)
+            .unwrap_or_else(|err| panic!("{err}"));
+    }
+
+    let persistent_branches = github.persistent_branches(config.persistent_branches.as_deref());
+
+    // Every entry of the previous results, for the baseline sanity check and the results of
+    // other machines.
+    let mut history = vec![];
+    // The counters dropped for being out of bounds, by entry.
+    let mut history_dropped = HashMap::new();
+    let mut base_commit = None;
+    let mut prev_results = (|| {
+        // we have two scenarios:
+        //
+        // - we benchmark on a PR merge into `main`
+        // - we benchmark a commit versus current `main`
+        let merge_base = String::from_utf8_lossy(
+            &Command::new("git")
+                .arg("merge-base")
+                .arg("origin/main")
+                // Using HEAD~ rather than HEAD to get the parent commit if we are benchmarking for
+                // the main branch.
+                .arg("HEAD~")
+                .output()
+                .unwrap()
+                .stdout,
+        )
+        .trim()
+        .to_owned();
+        if merge_base.is_empty() {
+            return Err("no merge base with origin/main".to_owned());
+        }
+        let base_commit = &*base_commit.insert(merge_base);
+
+        // Without any feature that looks at the rest of the history, the reading stops at the
+        // baseline.
+        let until = (!config.needs_history()).then_some(base_commit.as_str());
+        let load = |line: &[u8]| {
+            let Ok(mut data) = serde_json::from_slice::<BenchData>(line) else {
+                return None; // Data format likely changed
+            };
+            if data.partial {
+                return None; // Stopped by `--fail-fast`, the groups that didn't run are missing
+            }
+            if !trigger::is_persistent(data.trigger.as_ref(), &persistent_branches) {
+                return None; // A pull request or a feature branch, never a baseline
+            }
+            sanitizer.restore(&mut data, &config.commands);
+            data.remap_ids(&remap_ids);
+            let warnings = config.counter_renames.canonicalize(&mut data);
+            // Only for the baseline, the rest of the history isn't compared directly.
+            if data.commit_id() == *base_commit {
+                for warning in warnings {
+                    eprintln!("warning: {warning}");
+                }
+            }
+            let dropped = counter_bounds::drop_from(&config.counter_bounds, &mut data);
+            if !dropped.is_empty() {
+                history_dropped.insert((data.commit_id(), data.timestamp), dropped);
+            }
+            Some(data)
+        };
+        history = history::read(
+            Path::new(&previous_results_path),
+            until,
+            results_index,
+            load,
+        )?;
+
+        if let Some(data) = history.iter().find(|data| data.commit_id() == *base_commit) {
+            return Ok(data.clone());
+        }
+
+        // Runs on main fail now and then, fall back to the nearest ancestor with results from
+        // this kind of machine.
+        let no_results = format!("no previous results for {base_commit}");
+        let ancestors =
+            baseline::ancestors(Path::new("."), base_commit, config.baseline_ancestor_depth)
+                .map_err(|err| format!("{no_results}, and no ancestors: {err}"))?;
+        for (distance, ancestor) in (1..).zip(&ancestors) {
+            if let Some(data) = history.iter().find(|data| {
+                data.commit_id() == *ancestor && baseline::same_machine(data, &bench_data)
+            }) {
+                let data = BenchData {
+                    ancestor_distance: Some(distance),
+                    ..data.clone()
+                };
+                eprintln!(
+                    "warning: {no_results}, comparing against {ancestor}{}",
+                    data.ancestor_label()
+                );
+                return Ok(data);
+            }
+        }
+        Err(no_results)
+    })();
+    if let Ok(prev_results) = &prev_results {
+        let key = (prev_results.commit_id(), prev_results.timestamp);
+        for dropped in history_dropped.remove(&key).unwrap_or_default() {
+            eprintln!("warning: {}", dropped.warning());
+            report.dropped_counters.push(dropped);
+        }
+    }
+
+    if changed_only {
+        let changed = match &base_commit {
+            Some(base_commit) => changed::changed_files(Path::new("."), base_commit),
+            None => Err("no merge base with origin/main".to_owned()),
+        };
+        match changed {
+            Ok(changed) => {
+                for group_name in config.commands.keys() {
+                    let patterns = config
+                        .paths_for_group
+                        .get(group_name)
+                        .map_or(&[][..], Vec::as_slice);
+                    match changed::decide(patterns, &changed) {
+                        changed::Decision::Always => {}
+                        changed::Decision::Matched { file, pattern } => {
+                            eprintln!("running `{group_name}`: {file} matched {pattern}");
+                        }
+                        changed::Decision::Skipped(reason) => {
+                            eprintln!("skipping `{group_name}`: {reason}");
+                            report.groups[group_name].skip_reason = Some(reason.clone());
+                            bench_data.skipped_groups.insert(group_name.clone(), reason);
+                        }
+                    }
+                }
+                config.skip_groups(&bench_data.skipped_groups);
+            }
+            Err(err) => {
+                eprintln!("warning: running all groups, failed to find the changed files: {err}")
+            }
+        }
+    }
+
+    let mut baseline_anomaly = None;
+    if let (Some(sanity_check), Ok(prev_results)) =
+        (&config.baseline_sanity_check, &mut prev_results)
+    {
+        match baseline::ancestors(
+            Path::new("."),
+            &prev_results.commit_hash,
+            sanity_check.search_commits,
+        ) {
+            Ok(ancestors) => {
+                let neighbors =
+                    baseline::neighbors(prev_results, &ancestors, &history, sanity_check.neighbors);
+                if neighbors.len() < 2 {
+                    eprintln!(
+                        "warning: skipping the baseline sanity check, only {} older results from the same machine",
+                        neighbors.len()
+                    );
+                } else if let Some(anomaly) = sanity_check.check(
+                    &config.machine_stable_counters,
+                    &config.control_groups,
+                    prev_results,
+                    &neighbors,
+                ) {
+                    eprintln!(
+                        "warning: the baseline {} deviates from the {} results before it",
+                        prev_results.commit_hash,
+                        neighbors.len()
+                    );
+                    if anomaly.action == baseline::AnomalyAction::Substitute {
+                        *prev_results = baseline::composite(prev_results, &neighbors);
+                        report.stamp.baseline_changed = true;
+                    }
+                    baseline_anomaly = Some(anomaly);
+                }
+            }
+            Err(err) => eprintln!("warning: skipping the baseline sanity check: {err}"),
+        }
+    }
+
+    if let (Some(staleness_config), Ok(prev_results)) =
+        (&config.baseline_staleness, &mut prev_results)
+    {
+        let staleness = staleness_config.evaluate(
+            staleness::age(prev_results.timestamp, bench_data.timestamp),
+            staleness::commit_distance(Path::new("."), &prev_results.commit_hash),
+        );
+        if staleness.stale {
+            eprintln!("warning: the {}", staleness.label());
+        }
+        prev_results.staleness = Some(staleness);
+    }
+
+    report.baseline = match &prev_results {
+        Ok(prev_data) => {
+            eprintln!("base commit: {}", prev_data.commit_hash);
+            Baseline {
+                commit: Some(prev_data.commit_hash.clone()),
+                ancestor_distance: prev_data.ancestor_distance,
+                reason: None,
+                anomaly: baseline_anomaly.clone(),
+                staleness: prev_data.staleness.clone(),
+            }
+        }
+        Err(reason) => {
+            eprintln!("base commit: none ({reason})");
+            Baseline {
+                commit: None,
+                ancestor_distance: None,
+                reason: Some(reason.clone()),
+                anomaly: None,
+                staleness: None,
+            }
+        }
+    };
+    let prev_results = prev_results.ok();
+
+    // The results of the main branch to pool for the rolling baseline, nearest first.
+    let rolling_entries = match (&config.rolling_baseline, &base_commit) {
+        (Some(rolling_config), Some(base_commit)) => {
+            match baseline::ancestors(Path::new("."), base_commit, rolling_config.search_commits) {
+                Ok(ancestors) => {
+                    let commits = std::iter::once(base_commit.clone())
+                        .chain(ancestors)
+                        .collect::<Vec<_>>();
+                    let entries = baseline::neighbors(
+                        &bench_data,
+                        &commits,
+                        &history,
+                        rolling_config.entries,
+                    );
+                    if entries.is_empty() {
+                        eprintln!("warning: no results of the main branch from this machine for the rolling baseline");
+                    }
+                    Some(entries)
+                }
+                Err(err) => {
+                    eprintln!("warning: skipping the rolling baseline: {err}");
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
+
+    let isolation = config.isolation.as_ref().and_then(|isolation| {
+        match isolation.set_up(Path::new("systemd-run")) {
+            Ok(isolation) => Some(isolation),
+            Err(err) if require_isolation => panic!("failed to set up isolation: {err}"),
+            Err(err) => {
+                eprintln!("warning: failed to set up isolation, running without: {err}");
+                None
+            }
+        }
+    });
+    bench_data.isolation = isolation
+        .as_ref()
+        .map(|isolation| isolation.settings.clone());
+    if let Some(prev_results) = &prev_results {
+        if let Some(warning) = isolation::mismatch_warning(
+            prev_results.isolation.as_ref(),
+            bench_data.isolation.as_ref(),
+        ) {
+            eprintln!("warning: {warning}");
+        }
+    }
+
+    if let Some(preflight_config) = &config.preflight {
+        let sample_interval = preflight_config.sample;
+        let preflight = if cfg!(target_os = "linux") {
+            preflight_config.check(
+                || preflight::sample(Path::new("/proc"), sample_interval),
+                std::thread::sleep,
+            )
+        } else {
+            Err("the preflight check is only supported on Linux".to_owned())
+        };
+
+        match preflight {
+            Ok(preflight) if preflight.is_noisy() => {
+                let violations = preflight.violations.join(", ");
+                if require_quiet {
+                    eprintln!("error: the system never settled: {violations}");
+                    return EXIT_NOT_QUIET;
+                }
+                eprintln!("warning: noisy environment, the system never settled: {violations}");
+                bench_data.preflight = Some(preflight);
+            }
+            Ok(preflight) => bench_data.preflight = Some(preflight),
+            Err(err) => eprintln!("warning: failed to check whether the system is quiet: {err}"),
+        }
+    }
+
+    let wrapper = isolation
+        .as_ref()
+        .map(|isolation| isolation.wrapper.clone())
+        .unwrap_or_default();
+    if let Some(overhead_config) = &config.harness_overhead {
+        for group_name in config.commands.keys() {
+            let backends = config.backends(group_name, scratch_dir);
+            let key = CalibrationKey::new(&wrapper, &backends);
+            let calibrated =
+                overhead::calibrate(&mut bench_data.harness_overhead, key, group_name, || {
+                    let mut result =
+                        overhead::measure(&wrapper, &backends, overhead_config.repetitions)?;
+                    config
+                        .counter_renames
+                        .canonicalize_bench(group_name, &mut result);
+                    config.derive_counters(
+                        group_name,
+                        bench_data.cpu_frequency.as_ref(),
+                        &mut result.counters,
+                    );
+                    Ok(result.counters)
+                });
+            if let Err(err) = calibrated {
+                eprintln!("warning: failed to measure the harness overhead of the `{group_name}` group: {err}");
+            }
+        }
+    }
+
+    bench_data.environment = Some(Environment::detect(&config.environment, sanitizer));
+
+    let thermal_sampler = config.thermal.as_ref().and_then(|thermal_config| {
+        if !cfg!(target_os = "linux") {
+            eprintln!("warning: thermal monitoring is only supported on Linux");
+            return None;
+        }
+        Some(thermal::Sampler::start(
+            Path::new("/sys"),
+            thermal_config.interval,
+        ))
+    });
+    let mut group_windows = vec![];
+    if config.watchdog.is_some() && !cfg!(target_os = "linux") {
+        eprintln!("warning: the watchdog is only supported on Linux");
+    }
+
+    if fail_fast && config.gate.is_none() {
+        eprintln!("warning: `--fail-fast` has no effect without a `gate`");
+    }
+    let mut stopped = false;
+    let mut sequence = 0;
+    let order = priority::execution_order(&config);
+    let mut shared = if config.dedupe_commands {
+        dedupe::Shared::plan(&config, scratch_dir)
+    } else {
+        dedupe::Shared::default()
+    };
+    for share in &shared.shares {
+        let (group_name, index) = &share.commands[0];
+        eprintln!(
+            "measuring `{}` once for the {} groups, with {} repetitions",
+            config.commands[group_name][*index].command,
+            share
+                .commands
+                .iter()
+                .map(|(group_name, _)| format!("`{group_name}`"))
+                .collect::<Vec<_>>()
+                .join(", "),
+            share.repetitions
+        );
+    }
+    if config.keep_perf_output.is_some() && !shared.shares.is_empty() {
+        eprintln!("warning: the perf output of the commands measured once for several groups is only kept for the first of them");
+    }
+    if let Some(order) = priority::describe(&config, &order) {
+        eprintln!("running the groups by priority: {order}");
+    }
+
+    let cmd = |bench: &CommandConfig| CommandSpec {
+        argv: bench.command.split(" ").map(|arg| arg.to_owned()).collect(),
+        expected_exit_codes: bench.expected_exit_codes.clone(),
+        wrapper: wrapper.clone(),
+        current_dir: None,
+        sync_start: bench.sync_start.then_some(config.sync_start_timeout),
+        measure_child: bench
+            .measure_child
+            .as_ref()
+            .map(|process| measure_child::MeasureChild {
+                process: process.clone(),
+                timeout: config.measure_child_timeout,
+            }),
+        verify_output: bench.verify_output.clone(),
+        watchdog: config.watchdog.clone().filter(|_| !bench.sleeps),
+        preemption: config.preemption.clone(),
+    };
+    let round_robin = if config.round_robin {
+        round_robin::plan(
+            &config,
+            order.iter().map(|(group_name, _)| group_name.as_str()),
+            |group_name, index| shared.contains(group_name, index),
+        )
+    } else {
+        vec![]
+    };
+    if config.keep_perf_output.is_some() && !round_robin.is_empty() {
+        eprintln!("warning: the perf output of the commands measured round-robin isn't kept, they can't be replayed");
+    }
+    if fail_fast && !round_robin.is_empty() {
+        eprintln!("warning: `--fail-fast` can't skip the commands measured round-robin, they all run before the first group is done");
+    }
+    // Measured when the first group with commands measured round-robin runs, after those
+    // before it, like the start of the `sentinel-group`.
+    let mut round_robin_measured: Option<round_robin::Measured> = None;
+    for (group_name, _) in &order {
+        let benches = &config.commands[group_name];
+        let group_start = thermal_sampler.as_ref().map(thermal::Sampler::elapsed);
+        let backends = config.backends(group_name, scratch_dir);
+        let net_overhead = config
+            .harness_overhead
+            .as_ref()
+            .filter(|overhead_config| overhead_config.subtract)
+            .and_then(|_| {
+                overhead::find(
+                    &bench_data.harness_overhead,
+                    &CalibrationKey::new(&wrapper, &backends),
+                )
+            })
+            .map(|overhead| overhead.counters.clone());
+        report.groups[group_name].backends = backends
+            .iter()
+            .map(|backend| backend.name().to_owned())
+            .collect();
+
+        let mut pairs = if config.interleave(group_name) {
+            interleave::pairs(&config, group_name)
+        } else {
+            vec![]
+        };
+        // The composites aren't run, but their steps are, alternating in order. Imported
+        // commands aren't run at all.
+        pairs.retain(|&(a, b)| {
+            ![a, b].iter().any(|&index| {
+                benches
+                    .get(index)
+                    .is_some_and(|bench| bench.is_composite() || bench.import.is_some())
+            })
+        });
+        pairs.extend(composite::pairs(benches));
+        let mut schedule = interleave::schedule(benches.len(), &pairs);
+        if config.shuffle(group_name) {
+            rng.shuffle(&mut schedule);
+            // The composites are measured from their steps, so they still run after them.
+            schedule.sort_by_key(|step| {
+                matches!(step, interleave::Step::Alone(index) if benches[*index].is_composite())
+            });
+        }
+        if config.keep_perf_output.is_some()
+            && schedule
+                .iter()
+                .any(|step| matches!(step, interleave::Step::Interleaved(_)))
+        {
+            eprintln!("warning: the perf output of the interleaved commands of the `{group_name}` group isn't kept, they can't be replayed");
+        }
+        if config.keep_perf_output.is_some()
+            && benches.iter().any(|bench| bench.measure_child.is_some())
+        {
+            eprintln!("warning: the perf output of the commands with `measure-child` of the `{group_name}` group isn't kept, they can't be replayed");
+        }
+        let mut flusher = config.flusher(group_name, &schedule);
+        let keep_outputs = config
+            .outputs
+            .as_ref()
+            .is_some_and(|outputs| outputs.keep_outputs);
+        let files_before = config
+            .outputs
+            .as_ref()
+            .map(|_| outputs::scan(Path::new(".")));
+        if round_robin_measured.is_none()
+            && round_robin.iter().any(|(group, _)| group == group_name)
+        {
+            let backends = round_robin
+                .iter()
+                .map(|(group, _)| (group.clone(), config.backends(group, scratch_dir)))
+                .collect::<IndexMap<_, _>>();
+            let cmds = round_robin
+                .iter()
+                .map(|(group, index)| round_robin::RoundRobinCommand {
+                    group: group.clone(),
+                    index: *index,
+                    spec: cmd(&config.commands[group][*index]),
+                    repetitions: config.repetitions(group),
+                })
+                .collect::<Vec<_>>();
+            let clock = thermal_sampler
+                .as_ref()
+                .map(|sampler| move || sampler.elapsed());
+            let measured = round_robin::bench(
+                &cmds,
+                &backends,
+                clock.as_ref().map(|clock| clock as &dyn Fn() -> Duration),
+            )
+            .unwrap_or_else(|err| {
+                report.groups[group_name].failed += 1;
+                report.groups[group_name].status = GroupStatus::Failed;
+                panic!("{err}")
+            });
+            group_windows.extend(measured.windows.iter().cloned());
+            round_robin_measured = Some(measured);
+        }
+        if flusher.is_some()
+            && round_robin_measured.as_ref().is_some_and(|measured| {
+                schedule.iter().any(|step| {
+                    config.compares_step(group_name, step)
+                        && measured.covers(group_name, step.commands())
+                })
+            })
+        {
+            eprintln!("warning: the caches of the `{group_name}` group aren't flushed between its commands measured round-robin");
+        }
+
+        let mut group_results = benches.iter().map(|_| None).collect::<Vec<_>>();
+        for step in &schedule {
+            let measured = match step {
+                step if round_robin_measured
+                    .as_ref()
+                    .is_some_and(|measured| measured.covers(group_name, step.commands())) =>
+                {
+                    Ok(round_robin_measured
+                        .as_mut()
+                        .unwrap()
+                        .take(group_name, step.commands()))
+                }
+                interleave::Step::Alone(index) if benches[*index].is_composite() => {
+                    Ok(vec![composite::bench(&benches[*index], &group_results)])
+                }
+                interleave::Step::Alone(index) if benches[*index].import.is_some() => {
+                    let result = benches[*index].import.as_ref().unwrap().bench();
+                    if let Some(commit_hash) = result
+                        .imported
+                        .as_ref()
+                        .and_then(|imported| imported.commit_hash.as_ref())
+                        .filter(|&commit_hash| *commit_hash != bench_data.commit_hash)
+                    {
+                        eprintln!(
+                            "warning: `{}` is imported from the results of {commit_hash}, not of the benchmarked commit",
+                            result.cmd.join(" ")
+                        );
+                    }
+                    Ok(vec![result])
+                }
+                interleave::Step::Alone(index) => {
+                    let perf_output = config.keep_perf_output.as_ref().map(|dir| {
+                        replay::perf_output_path(dir, group_name, *index)
+                            .unwrap_or_else(|err| panic!("{err}"))
+                    });
+                    if shared.contains(group_name, *index) {
+                        shared.bench(
+                            &config,
+                            group_name,
+                            *index,
+                            cmd(&benches[*index]),
+                            perf_output.as_deref(),
+                        )
+                    } else {
+                        bench_single_cmd(
+                            cmd(&benches[*index]),
+                            config.repetitions(group_name),
+                            &backends,
+                            perf_output.as_deref(),
+                        )
+                    }
+                    .map(|result| vec![result])
+                }
+                interleave::Step::Interleaved(indices) => interleave::bench_interleaved(
+                    &indices
+                        .iter()
+                        .map(|&index| cmd(&benches[index]))
+                        .collect::<Vec<_>>(),
+                    config.repetitions(group_name),
+                    &backends,
+                    flusher
+                        .as_mut()
+                        .filter(|_| config.compares_step(group_name, step)),
+                    Some(&mut rng).filter(|_| {
+                        config.shuffle(group_name) && config.compares_step(group_name, step)
+                    }),
+                ),
+            };
+            let measured = measured.unwrap_or_else(|err| {
+                report.groups[group_name].failed += 1;
+                report.groups[group_name].status = GroupStatus::Failed;
+                match config.group_sources.get(group_name) {
+                    Some(source) => panic!(
+                        "{err}\n(in the `{group_name}` group from {})",
+                        source.display()
+                    ),
+                    None => panic!("{err}"),
+                }
+            });
+
+            for (&index, mut result) in step.commands().iter().zip(measured) {
+                let bench = &benches[index];
+                let cmd = cmd(bench);
+                match &result.error {
+                    Some(error) => {
+                        eprintln!("error: `{}` failed: {error}", cmd.argv.join(" "));
+                        report.groups[group_name].failed += 1;
+                        report.groups[group_name].errors.push(CommandError {
+                            command: cmd.argv.join(" "),
+                            error: error.clone(),
+                        });
+                    }
+                    None => report.groups[group_name].completed += 1,
+                }
+                if result.nondeterministic_output {
+                    eprintln!(
+                        "warning: the output of `{}` differed between its repetitions, its comparisons are left out of the gate",
+                        cmd.argv.join(" ")
+                    );
+                }
+                if let Some(preempted) = &result.preempted {
+                    eprintln!(
+                        "warning: `{}`: {}{}",
+                        cmd.argv.join(" "),
+                        preempted.note(),
+                        if preempted.unreliable {
+                            ", its comparisons are left out of the gate"
+                        } else {
+                            ""
+                        }
+                    );
+                }
+
+                if bench.import.is_none() || bench.id.is_some() {
+                    result.id = bench.id.clone();
+                }
+                if config.io_counters(group_name)
+                    && bench.import.is_none()
+                    && !bench.is_composite()
+                    && result.error.is_none()
+                {
+                    match io_counters::observe(&cmd) {
+                        Ok(counters) => result.counters.extend(counters),
+                        Err(err) => eprintln!("warning: {err}"),
+                    }
+                }
+
+                let imported_tags = std::mem::take(&mut result.tags);
+                result.tags = config.tags(group_name, bench);
+                for tag in imported_tags.into_iter().filter(|_| bench.import.is_some()) {
+                    if !result.tags.contains(&tag) {
+                        result.tags.push(tag);
+                    }
+                }
+
+                for warning in config
+                    .counter_renames
+                    .canonicalize_bench(group_name, &mut result)
+                {
+                    eprintln!("warning: {warning}");
+                }
+                for dropped in counter_bounds::drop_out_of_bounds(
+                    &config.counter_bounds,
+                    &bench_data.short_commit_id(),
+                    group_name,
+                    &mut result,
+                ) {
+                    eprintln!("warning: {}", dropped.warning());
+                    report.dropped_counters.push(dropped);
+                }
+
+                // Imported commands got their derived counters where they were measured.
+                if bench.import.is_none() {
+                    config.derive_counters(
+                        group_name,
+                        bench_data.cpu_frequency.as_ref(),
+                        &mut result.counters,
+                    );
+                }
+                // The `-net` counters of a composite are the sums of those of its steps.
+                if let Some(overhead) = net_overhead
+                    .as_ref()
+                    .filter(|_| !bench.is_composite() && bench.import.is_none())
+                {
+                    overhead::add_net_counters(&mut result.counters, overhead);
+                }
+
+                if bench.profile {
+                    result.profile = profile::record(
+                        &Perf::new(scratch_dir).program,
+                        scratch_dir,
+                        &cmd,
+                        config.profile.top_symbols,
+                    );
+                }
+
+                if let Some(interval) = bench.interval {
+                    result.intervals = intervals::record(
+                        &Perf::new(scratch_dir).program,
+                        scratch_dir,
+                        &cmd,
+                        interval.as_millis() as u32,
+                        &config.intervals,
+                    );
+                }
+
+                // After the profile and the intervals, which run the command again.
+                if !bench.produces.is_empty() {
+                    match outputs::collect(Path::new("."), &bench.produces, keep_outputs) {
+                        Ok(bytes) => result.output_bytes = Some(bytes),
+                        Err(err) => eprintln!("warning: {err}"),
+                    }
+                }
+
+                if stream {
+                    OutputLine::Bench {
+                        sequence,
+                        commit_hash: &bench_data.commit_hash,
+                        group: group_name,
+                        bench: &result,
+                    }
+                    .print(sanitizer);
+                    sequence += 1;
+                }
+
+                group_results[index] = Some(result);
+            }
+        }
+        bench_data.bench_groups.insert(
+            group_name.clone(),
+            group_results.into_iter().map(Option::unwrap).collect(),
+        );
+        report.groups[group_name].status = if report.groups[group_name].failed > 0 {
+            GroupStatus::Failed
+        } else {
+            GroupStatus::Completed
+        };
+
+        if let (Some(outputs_config), Some(files_before)) = (&config.outputs, &files_before) {
+            let produces = benches
+                .iter()
+                .flat_map(|bench| bench.produces.iter().cloned())
+                .collect::<Vec<_>>();
+            for (path, size) in outputs::undeclared_large_files(
+                files_before,
+                &outputs::scan(Path::new(".")),
+                &produces,
+                outputs_config.large_file,
+            ) {
+                eprintln!(
+                    "warning: {}",
+                    outputs::undeclared_warning(group_name, &path, size)
+                );
+            }
+        }
+
+        if let Some(flusher) = &flusher {
+            report.groups[group_name].flush = Some(flusher.time());
+        }
+
+        if let (Some(sampler), Some(start)) = (&thermal_sampler, group_start) {
+            group_windows.push(thermal::GroupWindow {
+                group: group_name.clone(),
+                start,
+                end: sampler.elapsed(),
+            });
+        }
+
+        if let (true, Some(prev_results)) = (fail_fast, &prev_results) {
+            let remaining = config.commands.len() - bench_data.bench_groups.len();
+            let verdict = fail_fast::early_verdict(
+                &config,
+                &bench_data,
+                prev_results,
+                rolling_entries.as_deref(),
+            );
+            if remaining > 0 && verdict.is_some_and(|verdict| !verdict.passed()) {
+                eprintln!("the gate failed after the `{group_name}` group, not running the {remaining} remaining groups (--fail-fast)");
+                stopped = true;
+                break;
+            }
+        }
+    }
+
+    // The results keep the order of the config, whatever the order the groups ran in.
+    bench_data
+        .bench_groups
+        .sort_by_cached_key(|group_name, _| config.commands.get_index_of(group_name));
+
+    if stopped {
+        for group_name in fail_fast::mark_partial(&mut config, &mut bench_data) {
+            report.groups[&group_name].skip_reason = Some(fail_fast::NOT_RUN.to_owned());
+        }
+    }
+
+    if let (Some(sampler), Some(thermal_config)) = (thermal_sampler, &config.thermal) {
+        bench_data.thermal = Thermal::summarize(
+            &sampler.stop(),
+            &group_windows,
+            thermal_config.interval,
+            thermal_config.throttle_percent,
+        );
+        if let Some(thermal) = bench_data
+            .thermal
+            .as_ref()
+            .filter(|thermal| thermal.is_throttled())
+        {
+            eprintln!(
+                "warning: the CPU was throttled in {:.0}% of the samples",
+                thermal.throttled_percent.unwrap_or_default()
+            );
+        }
+    }
+
+    let mut prev_results = prev_results;
+    match (backfill_baseline_counters, prev_results.as_mut()) {
+        (false, _) => {}
+        (true, None) => eprintln!("warning: there is no baseline to backfill the counters of"),
+        (true, Some(prev_results)) => {
+            match backfill::run(&config, &bench_data, prev_results, scratch_dir) {
+                Ok(0) => eprintln!("the baseline has all counters of the run"),
+                Ok(backfilled) => {
+                    eprintln!("backfilled {backfilled} counters of the baseline");
+                    let path = Path::new(&previous_results_path);
+                    if !persist_backfill {
+                        report.stamp.baseline_changed = true;
+                    } else if let Err(err) = backfill::persist(path, prev_results, sanitizer) {
+                        eprintln!("warning: failed to store the backfilled counters: {err}");
+                        report.stamp.baseline_changed = true;
+                    }
+                }
+                Err(err) => {
+                    eprintln!("warning: not backfilling the counters of the baseline: {err}")
+                }
+            }
+        }
+    }
+
+    // After the backfill, which may have stored the baseline again.
+    if let Some(prev_results) = &prev_results {
+        match stamp::stored_entry_sha256(
+            Path::new(&previous_results_path),
+            &prev_results.commit_id(),
+            prev_results.timestamp,
+        ) {
+            Ok(sha256) => report.stamp.baseline_sha256 = sha256,
+            Err(err) => eprintln!("warning: failed to hash the baseline: {err}"),
+        }
+    }
+
+    let final_line = OutputLine::Final(&bench_data);
+    final_line.print(sanitizer);
+    report.stamp.commit = Some(bench_data.commit_id());
+    report.stamp.results_sha256 = Some(stamp::sha256(
+        &serde_json::from_str(&final_line.to_json(sanitizer)).unwrap(),
+    ));
+    if let (Some(path), true) = (&results_file, bench_data.partial) {
+        eprintln!(
+            "warning: not writing the partial results of `--fail-fast` to {}",
+            path.display()
+        );
+    } else if let (Some(path), true) = (&results_file, bench_data.dirty && !allow_dirty) {
+        eprintln!(
+            "warning: not writing the results of a dirty working tree to {}, pass --allow-dirty to store them anyway",
+            path.display()
+        );
+    } else if let (Some(path), Some(trigger)) = (
+        &results_file,
+        bench_data
+            .trigger
+            .as_ref()
+            .filter(|trigger| !trigger.is_persistent(&persistent_branches)),
+    ) {
+        eprintln!(
+            "not writing the results of {} to {}, only those of {} are stored",
+            trigger.describe(),
+            path.display(),
+            persistent_branches
+                .iter()
+                .map(|branch| format!("`{branch}`"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    } else if let Some(path) = &results_file {
+        // A retried step replaces its results of the same commit with the same groups, the
+        // results of other suites are kept. The stored group names are sanitized.
+        let same_suite = |line: &[u8]| {
+            history::SuiteKey::parse(line).is_some_and(|key| {
+                key.commit_id() == bench_data.commit_id()
+                    && key.group_names().eq(bench_data
+                        .bench_groups
+                        .keys()
+                        .map(|group_name| sanitizer.sanitize(group_name)))
+            })
+        };
+        match sections::write_line(path, &final_line.to_json(sanitizer), same_suite) {
+            Ok(()) => {
+                report.artifacts.insert("results".to_owned(), path.clone());
+                if results_index {
+                    if let Err(err) = history::Index::refresh(path) {
+                        eprintln!("warning: failed to update the index of the results: {err}");
+                    }
+                }
+            }
+            Err(err) => eprintln!("warning: {err}"),
+        }
+    }
+
+    if let Some(path) = &csv_path {
+        match csv::write(
+            path,
+            &bench_data,
+            prev_results.as_ref(),
+            &config.counter_order(),
+            sanitizer,
+        ) {
+            Ok(()) => {
+                report.artifacts.insert("csv".to_owned(), path.clone());
+            }
+            Err(err) => eprintln!("warning: {err}"),
+        }
+    }
+
+    if let Some(dir) = &config.keep_perf_output {
+        let repository = env::var("GITHUB_REPOSITORY").unwrap_or_default();
+        match replay::write_manifest(
+            dir,
+            &repository,
+            &bench_data,
+            prev_results.as_ref(),
+            baseline_anomaly.as_ref(),
+        ) {
+            Ok(path) => {
+                report.artifacts.insert("perf-output".to_owned(), path);
+            }
+            Err(err) => eprintln!("warning: {err}"),
+        }
+    }
+
+    {
+        let mut buf = String::new();
+        // e.g. trifectatechfoundation/zlib-rs
+        let repository = env::var("GITHUB_REPOSITORY").unwrap();
+
+        bench_data.render_markdown_raw(&mut buf, &repository, prev_results.as_ref(), &config);
+        eprintln!("{}", sanitizer.sanitize(&buf));
+    }
+
+    let mut comparisons = Comparisons::collect(&config, &bench_data, prev_results.as_ref());
+    comparisons.baseline_anomaly = baseline_anomaly;
+    if let (Some(rolling_config), Some(entries)) = (&config.rolling_baseline, &rolling_entries) {
+        let cutoff = comparisons.cutoff(config.correction);
+        rolling_config.apply(&mut comparisons, &config, &bench_data, entries, cutoff);
+    }
+    comparisons.cross_machine = config
+        .render_cross_machine
+        .as_ref()
+        .and_then(|cross_machine| {
+            cross_machine.collect(
+                &config.measure_kinds,
+                &bench_data,
+                &history,
+                base_commit.as_deref(),
+            )
+        });
+    if config.drift_alarm.is_some() {
+        let mut entries = history.iter().collect::<Vec<_>>();
+        if trigger::is_persistent(bench_data.trigger.as_ref(), &persistent_branches) {
+            entries.push(&bench_data);
+        }
+        comparisons.drift = config.drift(&bench_data, &entries);
+    }
+
+    repro::attach(&mut comparisons, &config, &bench_data, sanitizer);
+    report.identical_binaries = comparisons.identical_binaries;
+    if comparisons.identical_binaries {
+        eprintln!("warning: the benchmarked binaries are byte-identical to the baseline");
+    }
+    if let (Some(_), Ok(path)) = (&config.fingerprint, env::var("GITHUB_OUTPUT")) {
+        if let Err(err) =
+            fingerprint::write_github_output(Path::new(&path), comparisons.identical_binaries)
+        {
+            eprintln!("warning: {err}");
+        }
+    }
+    report.build_differences = comparisons.build_differences.clone();
+    for difference in &report.build_differences {
+        eprintln!(
+            "warning: {} was built differently than in the baseline: {}",
+            difference.binary,
+            difference.differences.join("; ")
+        );
+    }
+    report.sampling_mismatches = comparisons.sampling_mismatches.clone();
+    for mismatch in &report.sampling_mismatches {
+        let gate = if strict_sampling {
+            "gates them anyway with --strict-sampling"
+        } else {
+            "ignores its tables"
+        };
+        eprintln!(
+            "warning: {} sampled other files than the baseline, the gate {gate}: {}",
+            mismatch.group,
+            mismatch.differences.join("; ")
+        );
+    }
+    match ConfigSpans::read(&config.files) {
+        Ok(spans) => spans.resolve(&mut comparisons),
+        Err(err) => eprintln!("warning: the config lines of the comparisons are unknown: {err}"),
+    }
+
+    for warning in exemptions::unknown_targets(&bench_data.exemptions, &comparisons) {
+        eprintln!("warning: {warning}");
+    }
+    report.gate = config.evaluate_gate(&comparisons, &bench_data.exemptions);
+    report.shadow_gate = config.evaluate_shadow_gate(&comparisons, &bench_data.exemptions);
+    report.gate_rows = gate::row_verdicts(
+        &comparisons,
+        report.gate.as_ref(),
+        report.shadow_gate.as_ref(),
+    );
+    if let (Some(shadow_gate), Ok(path)) = (&report.shadow_gate, env::var("GITHUB_OUTPUT")) {
+        if let Err(err) = gate::write_shadow_github_output(Path::new(&path), shadow_gate) {
+            eprintln!("warning: {err}");
+        }
+    }
+    report.budgets = budget::evaluate(&config.budgets, &bench_data)
+        .unwrap_or_else(|err| panic!("invalid config: {err}"));
+    report.suite_totals = config.suite_totals(&bench_data, prev_results.as_ref());
+    if let (false, Ok(path)) = (report.suite_totals.is_empty(), env::var("GITHUB_OUTPUT")) {
+        if let Err(err) = totals::write_github_output(Path::new(&path), &report.suite_totals) {
+            eprintln!("warning: {err}");
+        }
+    }
+    if config.drift_alarm.is_some() {
+        report.drift = comparisons.drift.clone();
+        report.drift_detected = !report.drift.is_empty();
+        for drift in &report.drift {
+            eprintln!(
+                "warning: slow drift detected: {} of `{}` in `{}` rose by {:+.2}% every 30 days",
+                drift.measure, drift.command, drift.group, drift.percent_per_30_days
+            );
+        }
+        if let Ok(path) = env::var("GITHUB_OUTPUT") {
+            if let Err(err) = drift::write_github_output(Path::new(&path), report.drift_detected) {
+                eprintln!("warning: {err}");
+            }
+        }
+    }
+    for result in report.budgets.iter().filter(|result| result.is_broken()) {
+        match result.severity {
+            budget::Severity::Fail => eprintln!("budget failure: {}", result.describe()),
+            budget::Severity::Warn => eprintln!("warning: budget broken: {}", result.describe()),
+        }
+    }
+    if let Some(gate) = &report.gate {
+        for failure in &gate.failures {
+            eprintln!(
+                "gate failure: {} / {} regressed by {} {}",
+                failure.table,
+                failure.row.name,
+                failure.row.format_delta(),
+                failure.row.measure
+            );
+        }
+        for failure in &gate.variance_failures {
+            eprintln!(
+                "gate failure: {} / {} got {} more variable in {}",
+                failure.table,
+                failure.row.name,
+                failure.format_cov_delta(),
+                failure.row.measure
+            );
+        }
+        for failure in &gate.suppressed {
+            let sampled = config
+                .render_versus_other
+                .get(&failure.table)
+                .is_some_and(|table| {
+                    comparisons
+                        .sampling_mismatches
+                        .iter()
+                        .any(|mismatch| mismatch.group == table.command)
+                });
+            let reason = if sampled {
+                "its group sampled other files than the baseline"
+            } else {
+                "its group's measurements vary too much"
+            };
+            eprintln!(
+                "warning: gate failure ignored, {reason}: {} / {} regressed by {} {}",
+                failure.table,
+                failure.row.name,
+                failure.row.format_delta(),
+                failure.row.measure
+            );
+        }
+        for accepted in &gate.accepted {
+            eprintln!("warning: {}", accepted.warning());
+        }
+        if config.gate.as_ref().is_some_and(|gate| gate.annotations) {
+            for failure in &gate.failures {
+                eprintln!("{}", sanitizer.sanitize(&failure.error_command()));
+            }
+            for failure in &gate.variance_failures {
+                eprintln!("{}", sanitizer.sanitize(&failure.variance_error_command()));
+            }
+        }
+    }
+
+    if let Some(shadow_gate) = &report.shadow_gate {
+        for failure in &shadow_gate.failures {
+            eprintln!(
+                "shadow gate failure: {} / {} regressed by {} {}",
+                failure.table,
+                failure.row.name,
+                failure.row.format_delta(),
+                failure.row.measure
+            );
+        }
+        for failure in &shadow_gate.variance_failures {
+            eprintln!(
+                "shadow gate failure: {} / {} got {} more variable in {}",
+                failure.table,
+                failure.row.name,
+                failure.format_cov_delta(),
+                failure.row.measure
+            );
+        }
+    }
+
+    if let Ok(path) = env::var("GITHUB_STEP_SUMMARY") {
+        // e.g. trifectatechfoundation/zlib-rs
+        let repository = env::var("GITHUB_REPOSITORY").unwrap();
+
+        let mut buf = render_step_summary(
+            &config,
+            &repository,
+            &bench_data,
+            prev_results.as_ref(),
+            &comparisons,
+            Verdicts {
+                gate: report.gate.as_ref(),
+                shadow: report.shadow_gate.as_ref(),
+            },
+            &report.budgets,
+        );
+        report.stamp.render_markdown(&mut buf);
+
+        let marker = sections::marker(&bench_data.commit_id(), &config_paths);
+        // A re-run job shows the verdict of its own attempt, but the earlier one may be what
+        // got uploaded.
+        match sections::read_section(Path::new(&path), &marker) {
+            Ok(Some(section)) => match Stamp::from_markdown(&section) {
+                Some(earlier) if earlier != report.stamp => eprintln!(
+                    "warning: the step summary already has the report of run {} for this commit, replacing it with that of run {}",
+                    earlier.attempt(),
+                    report.stamp.attempt()
+                ),
+                _ => {}
+            },
+            Ok(None) => {}
+            Err(err) => eprintln!("warning: {err}"),
+        }
+        sections::write_section(Path::new(&path), &marker, &sanitizer.sanitize(&buf))
+            .unwrap_or_else(|err| panic!("{err}"));
+        report
+            .artifacts
+            .insert("step-summary".to_owned(), PathBuf::from(path));
+    }
+
+    if let (Some(notify_config), Ok(url)) = (&config.notify, env::var(notify::WEBHOOK_URL_ENV)) {
+        if !url.is_empty() {
+            let repository = env::var("GITHUB_REPOSITORY").unwrap_or_default();
+            if repository.is_empty() {
+                eprintln!(
+                    "warning: GITHUB_REPOSITORY is not set, the notification names no repository"
+                );
+            }
+            if let Some(payload) = notify::build_payload(
+                notify_config,
+                &repository,
+                &bench_data,
+                &comparisons,
+                report.gate.as_ref(),
+            ) {
+                notify::send(&url, notify_config, &sanitizer.to_json(&payload));
+            }
+        }
+    }
+
+    if let (Some(CommentTarget::Commit), Ok(token)) =
+        (config.comment_target, env::var(comment::TOKEN_ENV))
+    {
+        let repository = env::var("GITHUB_REPOSITORY").unwrap_or_default();
+        if !token.is_empty() && repository.is_empty() {
+            eprintln!("warning: GITHUB_REPOSITORY is not set, not commenting on the commit");
+        } else if !token.is_empty() {
+            let api_url = env::var(comment::API_URL_ENV)
+                .unwrap_or_else(|_| comment::DEFAULT_API_URL.to_owned());
+            let body = comment::build_comment(
+                &repository,
+                &bench_data,
+                prev_results.as_ref(),
+                &comparisons,
+                report.gate.as_ref(),
+                &config.markers,
+                comment::MAX_COMMENT_LEN,
+            );
+            if let Err(err) = comment::post(
+                &api_url,
+                &repository,
+                &bench_data.commit_hash,
+                &token,
+                &sanitizer.sanitize(&body),
+                scratch_dir,
+            ) {
+                eprintln!("warning: {err}");
+            }
+        }
+    }
+
+    if let Ok(path) = env::var("GITHUB_OUTPUT") {
+        if let Err(err) = report.stamp.write_github_output(Path::new(&path)) {
+            eprintln!("warning: {err}");
+        }
+    }
+    eprintln!("{}", report.stamp.one_line());
+
+    if bench_data.dirty && !allow_dirty {
+        EXIT_DIRTY
+    } else if !required_counters::failures(&bench_data).is_empty() {
+        EXIT_MISSING_COUNTERS
+    } else if report.gate.as_ref().is_some_and(|gate| !gate.passed())
+        || bench_data.partial
+        || report.budgets.iter().any(BudgetResult::fails_run)
+    {
+        EXIT_GATE_FAILURE
+    } else {
+        0
+    }
+}
+
+/// Render the markdown for the GitHub step summary.
+///
+/// The pretty tables come first. The raw results follow, one block per group; groups that
+/// are already shown in a pretty table are collapsed, as their raw numbers are mostly
+/// interesting for digging into the details.
+fn render_step_summary(
+    config: &Config,
+    repository: &str,
+    bench_data: &BenchData,
+    prev_results: Option<&BenchData>,
+    comparisons: &Comparisons,
+    verdicts: Verdicts,
+    budgets: &[BudgetResult],
+) -> String {
+    use std::fmt::Write;
+
+    let mut buf = String::new();
+    let mut rendered_groups = BTreeSet::new();
+
+    totals::render_markdown(&mut buf, &config.suite_totals(bench_data, prev_results));
+    required_counters::render_markdown_warning(&mut buf, bench_data);
+    fingerprint::render_markdown_warning(&mut buf, comparisons.identical_binaries);
+
+    if let (Some(gate_config), Some(gate)) = (&config.gate, verdicts.gate) {
+        gate.render_markdown(&mut buf, gate_config);
+    }
+    if let (Some(shadow_config), Some(shadow)) = (&config.shadow_gate, verdicts.shadow) {
+        shadow.render_markdown_shadow(&mut buf, shadow_config);
+    }
+    budget::render_markdown(&mut buf, budgets, config.command_display());
+    drift::render_markdown(&mut buf, &comparisons.drift);
+    limits::render_markdown(
+        &mut buf,
+        &comparisons.efficiencies,
+        config.command_display(),
+    );
+
+    if let Some(prev_results) = prev_results {
+        isolation::render_markdown_warning(
+            &mut buf,
+            prev_results.isolation.as_ref(),
+            bench_data.isolation.as_ref(),
+        );
+    }
+
+    preflight::render_markdown_warning(&mut buf, bench_data.preflight.as_ref());
+    thermal::render_markdown_warning(&mut buf, bench_data.thermal.as_ref());
+    if let Some(quality_config) = &config.measurement_quality {
+        quality::render_markdown_warning(&mut buf, quality_config, &comparisons.quality);
+    }
+    sentinel::render_markdown_warning(&mut buf, comparisons.stability.as_ref());
+
+    frequency::render_markdown_note(
+        &mut buf,
+        prev_results.and_then(|prev_results| prev_results.cpu_frequency.as_ref()),
+        bench_data.cpu_frequency.as_ref(),
+    );
+
+    machine::render_markdown_note(
+        &mut buf,
+        comparisons.cross_class.as_ref(),
+        &config.machine_stable_counters,
+    );
+    import::render_markdown_note(
+        &mut buf,
+        &comparisons.imported_cross_class,
+        &config.machine_stable_counters,
+    );
+    backfill::render_markdown_note(&mut buf, prev_results);
+
+    if let Some(staleness_config) = &config.baseline_staleness {
+        staleness::render_markdown_warning(
+            &mut buf,
+            staleness_config,
+            prev_results.and_then(|prev_results| prev_results.staleness.as_ref()),
+        );
+    }
+    baseline::render_markdown_warning(&mut buf, comparisons.baseline_anomaly.as_ref());
+
+    if let Some(prev_results) = prev_results {
+        if !comparisons.versus_other.is_empty() {
+            BenchData::render_markdown_diff_pretty(
+                &mut buf,
+                repository,
+                &comparisons.versus_other,
+                prev_results,
+                bench_data,
+                comparisons.baseline_anomaly.as_ref(),
+                &config.markers,
+            );
+
+            for rows in config.render_versus_other.values() {
+                rendered_groups.insert(rows.command.as_str());
+            }
+        }
+    }
+
+    if !comparisons.versus_self.is_empty() {
+        BenchData::render_markdown_self_diff_pretty(
+            &mut buf,
+            repository,
+            &comparisons.versus_self,
+            bench_data,
+            &config.markers,
+        );
+
+        for row in config
+            .render_versus_self
+            .values()
+            .flat_map(|table| table.rows.values())
+        {
+            rendered_groups.insert(row.before.command.as_str());
+            rendered_groups.insert(row.after.command.as_str());
+        }
+    }
+
+    changed::render_markdown(
+        &mut buf,
+        &bench_data.skipped_groups,
+        &config.unmeasured_rows,
+    );
+
+    if let Some(cross_machine) = &comparisons.cross_machine {
+        cross_machine.render_markdown(&mut buf, repository, bench_data, &config.markers);
+    }
+
+    render_tag_rollups(&mut buf, &comparisons.tag_rollups(), &config.markers);
+
+    profile::render_markdown(
+        &mut buf,
+        &comparisons.hot_functions,
+        config.command_display(),
+    );
+
+    if let (Some(before), Some(after)) = (
+        prev_results.and_then(|prev_results| prev_results.environment.as_ref()),
+        &bench_data.environment,
+    ) {
+        let changes = environment::diff(&config.environment, before, after);
+        environment::render_markdown(&mut buf, &changes);
+    }
+
+    if !buf.is_empty() {
+        writeln!(buf).unwrap();
+    }
+
+    bench_data.render_markdown_raw_header(&mut buf, repository, prev_results);
+
+    let mut current_source = None;
+    for (group_name, group_results) in &bench_data.bench_groups {
+        let source = config.group_sources.get(group_name);
+        if let Some(path) = source.filter(|_| config.config_headings && source != current_source) {
+            writeln!(buf, "# {}\n", path.display()).unwrap();
+            current_source = source;
+        }
+
+        if rendered_groups.contains(group_name.as_str()) {
+            writeln!(
+                buf,
+                "<details>\n<summary>Raw results: {group_name} ({} commands, {} significant)</summary>\n",
+                group_results.len(),
+                comparisons
+                    .raw
+                    .iter()
+                    .find(|table| &table.name == group_name)
+                    .map_or(0, |table| table.rows.iter().filter(|row| row.is_actionable()).count()),
+            )
+            .unwrap();
+            // GitHub only renders markdown inside <details> when surrounded by blank lines.
+            render_raw_group(
+                &mut buf,
+                config,
+                bench_data,
+                prev_results,
+                comparisons,
+                group_name,
+            );
+
+            writeln!(buf, "\n</details>\n").unwrap();
+        } else {
+            writeln!(buf, "### {group_name}").unwrap();
+            writeln!(buf).unwrap();
+            render_raw_group(
+                &mut buf,
+                config,
+                bench_data,
+                prev_results,
+                comparisons,
+                group_name,
+            );
+
+            writeln!(buf).unwrap();
+        }
+    }
+
+    quality::render_markdown(
+        &mut buf,
+        &comparisons.quality,
+        &config.markers,
+        config.command_display(),
+    );
+    sentinel::render_markdown(&mut buf, comparisons.stability.as_ref(), &config.markers);
+
+    buf
+}
+
+/// Render the raw results of `group_name`, with the notes that go with them, as the body of
+/// its block in the step summary.
+fn render_raw_group(
+    buf: &mut String,
+    config: &Config,
+    bench_data: &BenchData,
+    prev_results: Option<&BenchData>,
+    comparisons: &Comparisons,
+    group_name: &str,
+) {
+    baseline::render_markdown_table_note(buf, comparisons.baseline_anomaly.as_ref());
+    bench_data.render_markdown_raw_group(
+        buf,
+        group_name,
+        prev_results,
+        config.raw_table_options(group_name),
+    );
+    intervals::render_markdown_shape_changes(
+        buf,
+        group_name,
+        &comparisons.shape_changes,
+        config.command_display(),
+    );
+    repro::render_markdown(
+        buf,
+        comparisons
+            .raw_repro
+            .get(group_name)
+            .into_iter()
+            .flatten()
+            .map(|(name, snippet)| (name.as_str(), snippet.as_str())),
+    );
+}
+
+#[test]
+fn step_summary_collapses_rendered_groups() {
+    let config: Config = serde_json::from_str(
+        r#"{
+            "commands": {},
+            "render-versus-self": {
+                "ng vs rs": {
+                    "level 1": { "measure": "cycles", "before": { "command": "compress-ng", "index": 0 }, "after": { "command": "compress-rs", "index": 0 } }
+                }
+            },
+            "render-versus-other": {}
+        }"#,
+    )
+    .unwrap();
+
+    let cycles =
+        |cycles| move |b: testkit::BenchBuilder| b.counter("cycles", cycles, 100.0, 20, "");
+    let prev = testkit::BenchDataBuilder::new("1111111111111111111111111111111111111111")
+        .group("compress-ng", |g| g.bench(["./ng", "1"], cycles(1000.0)))
+        .group("compress-rs", |g| {
+            g.bench(["./rs", "1"], cycles(1000.0))
+                .bench(["./rs", "2"], cycles(1000.0))
+        })
+        .group("other", |g| g.bench(["./other"], cycles(1000.0)))
+        .build();
+    let data = testkit::BenchDataBuilder::new("2222222222222222222222222222222222222222")
+        .group("compress-ng", |g| g.bench(["./ng", "1"], cycles(1000.0)))
+        .group("compress-rs", |g| {
+            g.bench(["./rs", "1"], cycles(900.0))
+                .bench(["./rs", "2"], cycles(1000.0))
+        })
+        .group("other", |g| g.bench(["./other"], cycles(2000.0)))
+        .build();
+
+    let comparisons = Comparisons::collect(&config, &data, Some(&prev));
+    let md = render_step_summary(
+        &config,
+        "owner/repo",
+        &data,
+        Some(&prev),
+        &comparisons,
+        Verdicts::default(),
+        &[],
+    );
+
+    assert_eq!(
+        md,
+        r#"## [`2222222`](https://github.com/owner/repo/commit/2222222222222222222222222222222222222222) (on cpu)
+### ng vs rs
+
+| name | before | after | Δ |
+| --- | --- | --- | --- |
+| level 1 <a id="cmp-20d4d31770731ab9"></a> | `  1.00K ±      10` | `    900 ±      10` | `🚀 -11.11%` |
+
+🚀 significant improvement
+
+## [`2222222222222222222222222222222222222222`](https://github.com/owner/repo/commit/2222222222222222222222222222222222222222) with parent [`1111111111111111111111111111111111111111`](https://github.com/owner/repo/commit/1111111111111111111111111111111111111111) (on cpu)
+
+<details>
+<summary>Raw results: compress-ng (1 commands, 0 significant)</summary>
+
+|command|cycles|cycles Δ|
+|---|---|---|
+|`./ng 1`|`1000±10`  | `-0.0%` |
+
+</details>
+
+<details>
+<summary>Raw results: compress-rs (2 commands, 1 significant)</summary>
+
+|command|cycles|cycles Δ|
+|---|---|---|
+|`./rs 1`|`900±10`  | `-10.0%` |
+|`./rs 2`|`1000±10`  | `-0.0%` |
+
+</details>
+
+### other
+
+|command|cycles|cycles Δ|
+|---|---|---|
+|`./other`|`2000±10`  | `+100.0%` |
+
+"#
+    );
+}
+
+#[test]
+fn step_summary_without_pretty_tables() {
+    let config: Config = serde_json::from_str(
+        r#"{ "commands": {}, "render-versus-self": {}, "render-versus-other": {} }"#,
+    )
+    .unwrap();
+
+    let data = testkit::BenchDataBuilder::new("2222222222222222222222222222222222222222")
+        .group("group", |g| {
+            g.bench(["./cmd"], |b| b.counter("cycles", 1000.0, 100.0, 20, ""))
+        })
+        .build();
+
+    let comparisons = Comparisons::collect(&config, &data, None);
+    let md = render_step_summary(
+        &config,
+        "owner/repo",
+        &data,
+        None,
+        &comparisons,
+        Verdicts::default(),
+        &[],
+    );
+
+    assert_eq!(
+        md,
+        r#"## [`2222222222222222222222222222222222222222`](https://github.com/owner/repo/commit/2222222222222222222222222222222222222222) (on cpu)
+
+### group
+
+|command|cycles|cycles Δ|
+|---|---|---|
+|`./cmd`|`1000±10`  | `n.a.` |
+
+"#
+    );
+}
+
+#[test]
+fn instruction_mix_under_raw_table() {
+    let data = testkit::BenchDataBuilder::new("2222222222222222222222222222222222222222")
+        .group("compress", |g| {
+            g.bench(["./c", "6"], |b| {
+                b.counter("instructions", 1000.0, 0.0, 20, "")
+                    .counter("branches", 250.0, 0.0, 20, "")
+                    .counter("branch-miss-rate", 1.5, 0.0, 20, "%")
+            })
+            .bench(["./c", "1"], |b| {
+                b.counter("instructions", 500.0, 0.0, 20, "")
+            })
+        })
+        .build();
+
+    let mut md = String::new();
+    data.render_markdown_raw_group(&mut md, "compress", None, RawTableOptions::default());
+    assert!(
+        md.ends_with(
+            "|`./c 1`|||`500±0`  | `n.a.` |\n\n- `./c 6`: branches 25% ▓▓▓▓▓ | other 75%\n"
+        ),
+        "{md}"
+    );
+}
+
+#[test]
+fn cache_stats_under_raw_table() {
+    let config: Config = serde_json::from_str(
+        r#"{
+            "commands": { "compress": ["./c 6", "./c 1"], "decompress": ["./d"] },
+            "cache-stats-for-group": { "compress": true },
+            "render-versus-self": {},
+            "render-versus-other": {}
+        }"#,
+    )
+    .unwrap();
+    let counters = |misses: f64| {
+        [
+            ("cache-references", 1.0e6),
+            ("cache-misses", misses),
+            ("instructions", 1.0e9),
+        ]
+        .into_iter()
+        .map(|(name, value)| {
+            let counter = BenchCounter {
+                value,
+                variance: 0.0,
+                repetitions: 20,
+                unit: String::new(),
+            };
+            (name.to_owned(), counter)
+        })
+        .collect::<BTreeMap<_, _>>()
+    };
+
+    let mut compress = counters(5.0e4);
+    config.derive_counters("compress", None, &mut compress);
+    assert_eq!(compress["cache-hit-rate"].value, 95.0);
+    let mut decompress = counters(5.0e4);
+    config.derive_counters("decompress", None, &mut decompress);
+    assert!(!decompress.contains_key("cache-hit-rate"));
+
+    let data = testkit::BenchDataBuilder::new("2222222222222222222222222222222222222222")
+        .group("compress", |g| {
+            g.bench(["./c", "6"], |b| {
+                b.counter("instructions", 1000.0, 0.0, 20, "").counter(
+                    "cache-hit-rate",
+                    95.0,
+                    0.0,
+                    20,
+                    "%",
+                )
+            })
+            .bench(["./c", "1"], |b| {
+                b.counter("instructions", 500.0, 0.0, 20, "")
+            })
+        })
+        .build();
+    let mut md = String::new();
+    data.render_markdown_raw_group(&mut md, "compress", None, RawTableOptions::default());
+    assert!(
+        md.ends_with("\n| command | cache hits |\n|---|---:|\n|`./c 6`|`95.00±0.00%`|\n"),
+        "{md}"
+    );
+}
+
+#[test]
+fn merged_config_files() {
+    let fixture = |name| {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("testdata/configs")
+            .join(name)
+    };
+
+    let config = Config::load(&[fixture("compress.json")]).unwrap();
+    assert!(config.group_sources.is_empty());
+
+    let config = Config::load(&[fixture("compress.json"), fixture("decompress.json")]).unwrap();
+    assert_eq!(config.repetitions_for_group["decompress"], 5);
+    assert_eq!(config.control_groups, ["compress", "decompress"]);
+    assert_eq!(
+        config.group_sources["decompress"],
+        fixture("decompress.json")
+    );
+    config.validate().unwrap();
+
+    let mut config =
+        Config::load(&[fixture("compress.json"), fixture("duplicate-id.json")]).unwrap();
+    assert_eq!(
+        config.validate().unwrap_err(),
+        format!(
+            "the id `compress-1` is used more than once, in the `compress` group (from {}) \
+             and the `other` group (from {})",
+            fixture("compress.json").display(),
+            fixture("duplicate-id.json").display()
+        )
+    );
+
+    // A heading per config file, when asked for.
+    config.config_headings = true;
+    let data = testkit::BenchDataBuilder::new("2222222222222222222222222222222222222222")
+        .group("compress", |g| g.bench(["./compress", "1"], |b| b))
+        .group("compress-rs", |g| g.bench(["./compress-rs", "1"], |b| b))
+        .group("other", |g| g.bench(["./other"], |b| b))
+        .build();
+    let comparisons = Comparisons::collect(&config, &data, None);
+    let md = render_step_summary(
+        &config,
+        "owner/repo",
+        &data,
+        None,
+        &comparisons,
+        Verdicts::default(),
+        &[],
+    );
+    let headings = md
+        .lines()
+        .filter(|line| {
+            ["# ", "### ", "<summary>"]
+                .iter()
+                .any(|p| line.starts_with(p))
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        headings,
+        [
+            "### ng vs rs".to_owned(),
+            format!("# {}", fixture("compress.json").display()),
+            "<summary>Raw results: compress (1 commands, 0 significant)</summary>".to_owned(),
+            "<summary>Raw results: compress-rs (1 commands, 0 significant)</summary>".to_owned(),
+            format!("# {}", fixture("duplicate-id.json").display()),
+            "### other".to_owned(),
+        ]
+    );
+}
+
+#[test]
+fn config_values_with_units() {
+    let dir = testkit::test_dir("config-units");
+    let load = |json: &str| {
+        let path = dir.join("config.json");
+        std::fs::write(&path, json).unwrap();
+        Config::load(&[path])
+    };
+
+    let config = load(
+        r#"{
+            "commands": { "compress": [{ "command": "./compress", "interval-ms": "0.1s" }] },
+            "render-versus-self": {}, "render-versus-other": {},
+            "sync-start-timeout-ms": "1.5min",
+            "notify": { "events": ["regression"], "timeout-secs": "500ms" },
+            "preflight": { "min-available-memory-mb": "2GiB", "retry-delay-secs": 5 },
+            "thermal": { "interval-ms": 250 }
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(
+        config.commands["compress"][0].interval,
+        Some(Duration::from_millis(100))
+    );
+    assert_eq!(config.sync_start_timeout, Duration::from_secs(90));
+    assert_eq!(config.notify.unwrap().timeout, Duration::from_millis(500));
+    let preflight = config.preflight.unwrap();
+    assert_eq!(preflight.min_available_memory, Some(2 << 30));
+    assert_eq!(preflight.retry_delay, Duration::from_secs(5));
+    assert_eq!(preflight.sample, Duration::from_secs(2));
+    assert_eq!(config.thermal.unwrap().interval, Duration::from_millis(250));
+
+    // The error points at the field.
+    let err = load(
+        r#"{
+            "commands": { "compress": [{ "command": "./compress", "interval-ms": "100 msecs" }] },
+            "render-versus-self": {}, "render-versus-other": {}
+        }"#,
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        "invalid config: commands.compress[0].interval-ms: invalid duration \"100 msecs\": \
+         unknown unit `msecs`, expected one of ns, us, µs, ms, s, min, h, d"
+    );
+    let err = load(
+        r#"{
+            "commands": {}, "render-versus-self": {}, "render-versus-other": {},
+            "preflight": { "sample-secs": "2m" }
+        }"#,
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        "invalid config: preflight.sample-secs: invalid duration \"2m\": ambiguous unit `m`, use min or ms"
+    );
+}
+
+#[test]
+fn version_in_headers() {
+    let prev = testkit::BenchDataBuilder::new("1111111111111111111111111111111111111111");
+    let data = testkit::BenchDataBuilder::new("2222222222222222222222222222222222222222");
+
+    assert_eq!(
+        data.clone()
+            .build()
+            .version_label(Some(&prev.clone().build())),
+        ""
+    );
+
+    let data = data.version("0.4.1").build();
+    assert_eq!(data.version_label(None), " (v0.4.1)");
+    assert_eq!(data.version_label(Some(&prev.clone().build())), " (v0.4.1)");
+    assert_eq!(
+        data.version_label(Some(&prev.clone().version("0.4.1").build())),
+        " (v0.4.1)"
+    );
+
+    let prev = prev.version("0.4.0").build();
+    assert_eq!(data.version_label(Some(&prev)), " (v0.4.0 → v0.4.1)");
+
+    let mut md = String::new();
+    data.render_markdown_raw_header(&mut md, "owner/repo", Some(&prev));
+    assert_eq!(
+        md,
+        "## [`2222222222222222222222222222222222222222`](https://github.com/owner/repo/commit/2222222222222222222222222222222222222222) \
+         with parent [`1111111111111111111111111111111111111111`](https://github.com/owner/repo/commit/1111111111111111111111111111111111111111) \
+         (v0.4.0 → v0.4.1) (on cpu)\n\n"
+    );
+
+    // Entries from before the version was recorded still load.
+    let mut value = serde_json::to_value(&prev).unwrap();
+    value.as_object_mut().unwrap().remove("version");
+    let old: BenchData = serde_json::from_value(value).unwrap();
+    assert_eq!(old.version, None);
+}
+
+#[test]
+fn dirty_in_headers() {
+    let clean = testkit::BenchDataBuilder::new("1111111111111111111111111111111111111111").build();
+    let dirty = testkit::BenchDataBuilder::new("2222222222222222222222222222222222222222")
+        .dirty("abcd")
+        .build();
+    assert_eq!(
+        clean.commit_id(),
+        "1111111111111111111111111111111111111111"
+    );
+    assert_eq!(
+        dirty.commit_id(),
+        "2222222222222222222222222222222222222222-dirty"
+    );
+    assert_eq!(dirty.short_commit_id(), "2222222-dirty");
+
+    // The links still go to the commit the changes were made on.
+    let mut md = String::new();
+    dirty.render_markdown_raw_header(&mut md, "owner/repo", Some(&clean));
+    assert_eq!(
+        md,
+        "## [`2222222222222222222222222222222222222222-dirty`](https://github.com/owner/repo/commit/2222222222222222222222222222222222222222) \
+         with parent [`1111111111111111111111111111111111111111`](https://github.com/owner/repo/commit/1111111111111111111111111111111111111111) \
+         (on cpu)\n\n"
+    );
+    let mut md = String::new();
+    BenchData::render_markdown_self_diff_pretty(
+        &mut md,
+        "owner/repo",
+        &[],
+        &dirty,
+        &Markers::default(),
+    );
+    assert_eq!(
+        md,
+        "## [`2222222-dirty`](https://github.com/owner/repo/commit/2222222222222222222222222222222222222222) (on cpu)\n"
+    );
+
+    // Clean results don't mention it.
+    let value = serde_json::to_value(&clean).unwrap();
+    assert!(value.get("dirty").is_none() && value.get("diff_sha256").is_none());
+    let value = serde_json::to_value(&dirty).unwrap();
+    assert_eq!(value["dirty"], true);
+    assert_eq!(value["diff_sha256"], "abcd");
+}
+
+#[test]
+fn ancestor_in_headers() {
+    let data = testkit::BenchDataBuilder::new("2222222222222222222222222222222222222222").build();
+    let ancestor = BenchData {
+        ancestor_distance: Some(1),
+        ..testkit::BenchDataBuilder::new("1111111111111111111111111111111111111111").build()
+    };
+
+    let mut md = String::new();
+    data.render_markdown_raw_header(&mut md, "owner/repo", Some(&ancestor));
+    assert_eq!(
+        md,
+        "## [`2222222222222222222222222222222222222222`](https://github.com/owner/repo/commit/2222222222222222222222222222222222222222) \
+         versus ancestor [`1111111111111111111111111111111111111111`](https://github.com/owner/repo/commit/1111111111111111111111111111111111111111) \
+         (1 commit before merge-base) (on cpu)\n\n"
+    );
+    let mut md = String::new();
+    BenchData::render_markdown_diff_pretty(
+        &mut md,
+        "owner/repo",
+        &[],
+        &ancestor,
+        &data,
+        None,
+        &Markers::default(),
+    );
+    assert!(
+        md.starts_with(
+            "## [`2222222`](https://github.com/owner/repo/commit/2222222222222222222222222222222222222222) \
+             versus ancestor [`1111111`](https://github.com/owner/repo/commit/1111111111111111111111111111111111111111) \
+             (1 commit before merge-base) (on cpu)\n"
+        ),
+        "{md}"
+    );
+}
+
+#[test]
+fn machine_class_in_headers() {
+    let data = testkit::BenchDataBuilder::new("2222222222222222222222222222222222222222")
+        .group("compress", |g| {
+            g.bench(["./c", "1"], |b| b.counter("cycles", 800.0, 100.0, 20, ""))
+        });
+    let prev = data
+        .with_scaled_counters(1.25)
+        .commit_hash("1111111111111111111111111111111111111111");
+    let data = data.machine_class("amd-epyc-7763/4").build();
+    assert_eq!(data.machine_label(Some(&prev.clone().build())), "cpu");
+
+    let prev = prev.machine_class("intel-xeon-platinum-8370c/4").build();
+    assert_eq!(
+        data.machine_label(Some(&prev)),
+        "cpu, machine class `intel-xeon-platinum-8370c/4` → `amd-epyc-7763/4`"
+    );
+
+    // The cycles aren't compared in the raw table either.
+    let mut md = String::new();
+    data.render_markdown_raw_group(
+        &mut md,
+        "compress",
+        Some(&prev),
+        RawTableOptions {
+            stable_counters: &machine::default_machine_stable_counters(),
+            ..RawTableOptions::default()
+        },
+    );
+    assert!(md.contains("| `n.a.` |"), "{md}");
+
+    let mut md = String::new();
+    data.render_markdown_raw_group(
+        &mut md,
+        "compress",
+        Some(&prev),
+        RawTableOptions {
+            stable_counters: &["cycles".to_owned()],
+            ..RawTableOptions::default()
+        },
+    );
+    assert!(md.contains("| `-20.0%` |"), "{md}");
+}
+
+#[test]
+fn sparkline_in_raw_table() {
+    let mut data = testkit::BenchDataBuilder::new("2222222222222222222222222222222222222222")
+        .group("compress", |g| {
+            g.bench(["./c", "1"], |b| b.counter("cycles", 800.0, 100.0, 20, ""))
+                .bench(["./c", "9"], |b| b.counter("cycles", 900.0, 100.0, 20, ""))
+        })
+        .build();
+    data.bench_groups["compress"][0].intervals = Some(intervals::IntervalSeries {
+        counter: "cycles".to_owned(),
+        interval_ms: 100,
+        values: vec![3.0, 3.0, 1.0, 1.0, 2.0],
+    });
+
+    let mut md = String::new();
+    data.render_markdown_raw_group(&mut md, "compress", None, RawTableOptions::default());
+    assert!(md.contains("\n|`./c 1` ██▁▁▅|`800±10`"), "{md}");
+    assert!(md.contains("\n|`./c 9`|`900±10`"), "{md}");
+}
+
+#[test]
+fn long_commands_in_raw_table() {
+    let data = testkit::BenchDataBuilder::new("2222222222222222222222222222222222222222")
+        .group("compress", |g| {
+            g.bench(
+                [
+                    "target/release/compress",
+                    "--level",
+                    "1",
+                    "/home/runner/work/corpus/silesia/dickens.txt",
+                ],
+                |b| b.counter("cycles", 800.0, 100.0, 20, ""),
+            )
+            .bench(["./c", "9"], |b| b.counter("cycles", 900.0, 100.0, 20, ""))
+        })
+        .build();
+
+    let mut md = String::new();
+    data.render_markdown_raw_group(&mut md, "compress", None, RawTableOptions::default());
+    assert!(
+        md.contains("\n|<span title=\"target/release/compress --level 1 /home/runner/work/corpus/silesia/dickens.txt\">`…/compress … /home/runner/work/corpus/silesia/dickens.txt`</span>|`800±10`"),
+        "{md}"
+    );
+
+    let mut md = String::new();
+    data.render_markdown_raw_group(
+        &mut md,
+        "compress",
+        None,
+        RawTableOptions {
+            commands: CommandDisplay {
+                max_length: Some(40),
+                full_commands: FullCommands::Footnotes,
+            },
+            ..RawTableOptions::default()
+        },
+    );
+    assert!(
+        md.contains("\n|`…/compress … /home/runner/work/corpus/silesia/dickens.txt` [1]|`800±10`"),
+        "{md}"
+    );
+    assert!(md.contains("\n|`./c 9`|`900±10`"), "{md}");
+    assert!(
+        md.ends_with("\n\n- [1] `target/release/compress --level 1 /home/runner/work/corpus/silesia/dickens.txt`\n"),
+        "{md}"
+    );
+}
+
+#[test]
+fn cold_and_warm_in_raw_table() {
+    let data = testkit::BenchDataBuilder::new("2222222222222222222222222222222222222222")
+        .group("compress", |g| {
+            g.bench(["./c", "1"], |b| {
+                b.counter("wall-time", 12.0, 4.0, 4, "msec")
+                    .counter("wall-time-cold", 30.0, 0.0, 1, "msec")
+                    .counter("wall-time-warm", 11.0, 1.0, 3, "msec")
+            })
+        })
+        .build();
+
+    let mut md = String::new();
+    data.render_markdown_raw_group(&mut md, "compress", None, RawTableOptions::default());
+    assert!(md.starts_with("|command|wall-time|wall-time Δ|\n"), "{md}");
+
+    let mut md = String::new();
+    data.render_markdown_raw_group(
+        &mut md,
+        "compress",
+        None,
+        RawTableOptions {
+            show_cold_warm: true,
+            ..RawTableOptions::default()
+        },
+    );
+    assert!(
+        md.starts_with(
+            "|command|wall-time|wall-time Δ|wall-time-cold|wall-time-cold Δ|wall-time-warm|wall-time-warm Δ|\n"
+        ),
+        "{md}"
+    );
+}
+
+#[cfg(test)]
+fn wide_raw_table() -> BenchData {
+    testkit::BenchDataBuilder::new("2222222222222222222222222222222222222222")
+        .group("compress", |g| {
+            g.bench(["./c", "1"], |b| {
+                ["a", "b", "c", "d", "e"]
+                    .into_iter()
+                    .fold(b, |b, counter| b.counter(counter, 100.0, 4.0, 20, ""))
+            })
+            .bench(["./c", "9"], |b| b.counter("c", 200.0, 9.0, 20, ""))
+        })
+        .build()
+}
+
+#[test]
+fn raw_table_within_max_width() {
+    let data = wide_raw_table();
+
+    let mut md = String::new();
+    data.render_markdown_raw_group(
+        &mut md,
+        "compress",
+        None,
+        RawTableOptions {
+            max_table_width: Some(11),
+            ..RawTableOptions::default()
+        },
+    );
+    assert_eq!(
+        md,
+        "\
+|command|a|a Δ|b|b Δ|c|c Δ|d|d Δ|e|e Δ|
+|---|---|---|---|---|---|---|---|---|---|---|
+|`./c 1`|`100±2`  | `n.a.` |`100±2`  | `n.a.` |`100±2`  | `n.a.` |`100±2`  | `n.a.` |`100±2`  | `n.a.` |
+|`./c 9`|||`200±3`  | `n.a.` |||
+"
+    );
+
+    let mut unlimited = String::new();
+    data.render_markdown_raw_group(&mut unlimited, "compress", None, RawTableOptions::default());
+    assert_eq!(unlimited, md);
+}
+
+#[test]
+fn raw_table_split_into_chunks() {
+    let data = wide_raw_table();
+
+    // Counter and Δ columns stay together, so 8 columns only fit 3 counters.
+    let mut md = String::new();
+    data.render_markdown_raw_group(
+        &mut md,
+        "compress",
+        None,
+        RawTableOptions {
+            max_table_width: Some(8),
+            ..RawTableOptions::default()
+        },
+    );
+    assert_eq!(
+        md,
+        "\
+_(counters 1–3 of 5)_
+
+|command|a|a Δ|b|b Δ|c|c Δ|
+|---|---|---|---|---|---|---|
+|`./c 1`|`100±2`  | `n.a.` |`100±2`  | `n.a.` |`100±2`  | `n.a.` |
+|`./c 9`|||`200±3`  | `n.a.` |
+
+_(counters 4–5 of 5)_
+
+|command|d|d Δ|e|e Δ|
+|---|---|---|---|---|
+|`./c 1`|`100±2`  | `n.a.` |`100±2`  | `n.a.` |
+|`./c 9`|||
+"
+    );
+
+    // Too narrow for even one counter, which then gets a table of its own.
+    let mut md = String::new();
+    data.render_markdown_raw_group(
+        &mut md,
+        "compress",
+        None,
+        RawTableOptions {
+            max_table_width: Some(2),
+            ..RawTableOptions::default()
+        },
+    );
+    assert_eq!(md.matches("|command|").count(), 5);
+    assert!(
+        md.contains("_(counter 5 of 5)_\n\n|command|e|e Δ|\n"),
+        "{md}"
+    );
+}
+
+#[test]
+fn raw_rows_sorted() {
+    let before = testkit::BenchDataBuilder::cycles(
+        "1111111111111111111111111111111111111111",
+        &[
+            ("compress", &[("./c 1", 100.0), ("./c 2", 100.0)]),
+            ("decompress", &[("./d 1", 100.0), ("./d 2", 100.0)]),
+        ],
+    )
+    .build();
+    let after = testkit::BenchDataBuilder::cycles(
+        "2222222222222222222222222222222222222222",
+        &[
+            (
+                "compress",
+                &[("./c 1", 101.0), ("./c 2", 80.0), ("./c 3", 1.0)],
+            ),
+            ("decompress", &[("./d 1", 101.0), ("./d 2", 80.0)]),
+        ],
+    )
+    .build();
+    let config: Config = serde_json::from_str(
+        r#"{
+            "commands": {},
+            "render-versus-self": {},
+            "render-versus-other": {},
+            "sort-raw-rows": "delta-desc",
+            "sort-raw-rows-for-group": { "decompress": "config-order" },
+            "sort-raw-rows-counter": "cycles"
+        }"#,
+    )
+    .unwrap();
+
+    let rows = |group_name| {
+        let mut md = String::new();
+        after.render_markdown_raw_group(
+            &mut md,
+            group_name,
+            Some(&before),
+            config.raw_table_options(group_name),
+        );
+        md.lines()
+            .skip(2)
+            .map(|line| line.split('`').nth(1).unwrap().to_owned())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(rows("compress"), ["./c 2", "./c 1", "./c 3"]);
+    assert_eq!(rows("decompress"), ["./d 1", "./d 2"]);
+
+    // The results stay in the order of the config.
+    assert_eq!(after.bench_groups["compress"][0].cmd, ["./c", "1"]);
+}
+
+#[test]
+fn identical_binaries_on_top() {
+    let config: Config = serde_json::from_str(
+        r#"{
+            "commands": { "compress": ["./c 1"] },
+            "fingerprint": { "files": ["./c", "./ref"], "control": ["./ref"] },
+            "gate": { "max-regression-percent": 5.0 },
+            "render-versus-self": {},
+            "render-versus-other": {}
+        }"#,
+    )
+    .unwrap();
+    let data = testkit::BenchDataBuilder::new("2222222222222222222222222222222222222222")
+        .binary_hash("./c", "aa")
+        .binary_hash("./ref", "bb")
+        .group("compress", |g| {
+            g.bench(["./c", "1"], |b| b.counter("cycles", 800.0, 100.0, 20, ""))
+        });
+    let prev = data
+        .clone()
+        .commit_hash("1111111111111111111111111111111111111111")
+        .build();
+    let data = data.build();
+
+    let comparisons = Comparisons::collect(&config, &data, Some(&prev));
+    assert!(comparisons.identical_binaries);
+    let md = render_step_summary(
+        &config,
+        "owner/repo",
+        &data,
+        Some(&prev),
+        &comparisons,
+        Verdicts::default(),
+        &[],
+    );
+    assert!(
+        md.starts_with("> [!WARNING]\n> ⚠️ benchmark binaries are byte-identical to the baseline"),
+        "{md}"
+    );
+
+    // Rebuilt, and without hashes in the baseline.
+    let mut rebuilt = data.clone();
+    rebuilt.binary_hashes["./c"] = "cc".to_owned();
+    let comparisons = Comparisons::collect(&config, &rebuilt, Some(&prev));
+    assert!(!comparisons.identical_binaries);
+    let mut unhashed = prev.clone();
+    unhashed.binary_hashes.clear();
+    assert!(!Comparisons::collect(&config, &data, Some(&unhashed)).identical_binaries);
+    assert!(!Comparisons::collect(&config, &data, None).identical_binaries);
+}
+
+#[test]
+fn note_build_differences() {
+    let config = r#"{
+        "commands": {
+            "compress": ["./target/release/compress 1", "./target/release/compress 9"],
+            "reference": ["./zlib-ng/minigzip -1"]
+        },
+        "build-info": { "binaries": ["target/release/compress", "zlib-ng/minigzip"] },
+        "render-versus-self": {
+            "rs vs ng": {
+                "level 1": { "measure": "cycles", "before": { "command": "reference", "index": 0 }, "after": { "command": "compress", "index": 0 } }
+            }
+        },
+        "render-versus-other": {
+            "compress": { "measure": "cycles", "command": "compress", "rows": { "level 1": 0, "level 9": 1 } },
+            "reference": { "measure": "cycles", "command": "reference", "rows": { "level 1": 0 } }
+        }
+    }"#;
+    let config: Config = serde_json::from_str(config).unwrap();
+    let fixture = |name: &str| {
+        build_info::read(
+            &Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("testdata/build-info")
+                .join(name),
+        )
+        .unwrap()
+    };
+    let data = testkit::BenchDataBuilder::new("2222222222222222222222222222222222222222")
+        .group("compress", |g| {
+            g.bench(["./target/release/compress", "1"], |b| {
+                b.counter("cycles", 800.0, 100.0, 20, "")
+            })
+            .bench(["./target/release/compress", "9"], |b| {
+                b.counter("cycles", 900.0, 100.0, 20, "")
+            })
+        })
+        .group("reference", |g| {
+            g.bench(["./zlib-ng/minigzip", "-1"], |b| {
+                b.counter("cycles", 700.0, 100.0, 20, "")
+            })
+        });
+    let mut prev = data
+        .clone()
+        .commit_hash("1111111111111111111111111111111111111111")
+        .build();
+    let mut data = data.build();
+    prev.build_info = IndexMap::from([
+        (
+            "target/release/compress".to_owned(),
+            fixture("release-old.o"),
+        ),
+        ("zlib-ng/minigzip".to_owned(), fixture("ref.o")),
+    ]);
+    data.build_info = IndexMap::from([
+        ("target/release/compress".to_owned(), fixture("release.o")),
+        ("zlib-ng/minigzip".to_owned(), fixture("ref.o")),
+    ]);
+
+    let comparisons = Comparisons::collect(&config, &data, Some(&prev));
+    assert_eq!(comparisons.build_differences.len(), 1);
+    let note =
+        "`target/release/compress`: baseline built with rustc 1.93.1, current with rustc 1.95.0";
+    let notes = |tables: &[compare::ComparisonTable]| {
+        tables
+            .iter()
+            .map(|table| (table.name.clone(), table.build_notes.clone()))
+            .collect::<Vec<_>>()
+    };
+    // Only the tables of the commands that run the rebuilt binary, against the baseline.
+    assert_eq!(
+        notes(&comparisons.versus_other),
+        [
+            ("compress".to_owned(), vec![note.to_owned()]),
+            ("reference".to_owned(), vec![])
+        ]
+    );
+    assert_eq!(
+        notes(&comparisons.raw),
+        [
+            ("compress".to_owned(), vec![note.to_owned()]),
+            ("reference".to_owned(), vec![])
+        ]
+    );
+    assert_eq!(
+        notes(&comparisons.versus_self),
+        [("rs vs ng".to_owned(), vec![])]
+    );
+    let md = render_step_summary(
+        &config,
+        "owner/repo",
+        &data,
+        Some(&prev),
+        &comparisons,
+        Verdicts::default(),
+        &[],
+    );
+    assert!(
+        md.contains(&format!(
+            "> [!NOTE]\n> Built differently than the baseline, which may explain changes:\n> - {note}\n\n"
+        )),
+        "{md}"
+    );
+
+    // Without the metadata in the baseline, from before it was read.
+    prev.build_info.clear();
+    let comparisons = Comparisons::collect(&config, &data, Some(&prev));
+    assert!(comparisons.build_differences.is_empty());
+    assert!(comparisons
+        .raw
+        .iter()
+        .all(|table| table.build_notes.is_empty()));
+}
+
+#[test]
+fn annotate_theoretical_limits() {
+    let config = r#"{
+        "commands": {
+            "adler32": ["./adler32 --scalar", "./adler32 --avx2"],
+            "reference": ["./zlib-ng/adler32"]
+        },
+        "render-versus-self": {
+            "rs vs ng": {
+                "avx2": { "measure": "cycles", "before": { "command": "reference", "index": 0 }, "after": { "command": "adler32", "index": 1 } }
+            }
+        },
+        "render-versus-other": {
+            "adler32": { "measure": "cycles", "command": "adler32", "rows": { "scalar": 0, "avx2": 1 } }
+        },
+        "theoretical-limits": {
+            "avx2": { "match": "--avx2", "measure": "cycles", "limits": { "zen4/4": 100 } }
+        }
+    }"#;
+    let config: Config = serde_json::from_str(config).unwrap();
+    config.validate().unwrap();
+    let data = testkit::BenchDataBuilder::new("2222222222222222222222222222222222222222")
+        .machine_class("zen4/4")
+        .group("adler32", |g| {
+            g.bench(["./adler32", "--scalar"], |b| {
+                b.counter("cycles", 400.0, 1.0, 20, "")
+            })
+            .bench(["./adler32", "--avx2"], |b| {
+                b.counter("cycles", 125.0, 1.0, 20, "")
+            })
+        })
+        .group("reference", |g| {
+            g.bench(["./zlib-ng/adler32"], |b| {
+                b.counter("cycles", 150.0, 1.0, 20, "")
+            })
+        });
+    let prev = data
+        .clone()
+        .commit_hash("1111111111111111111111111111111111111111")
+        .build();
+    let data = data.build();
+
+    let comparisons = Comparisons::collect(&config, &data, Some(&prev));
+    assert_eq!(comparisons.efficiencies.len(), 1);
+    let annotation = "_80.0% of the `cycles` limit_";
+    let efficiencies = |tables: &[compare::ComparisonTable]| {
+        tables
+            .iter()
+            .flat_map(|table| &table.rows)
+            .map(|row| (row.name.clone(), row.efficiency.clone()))
+            .collect::<Vec<_>>()
+    };
+    // Only the rows of the command with the limit, for its measure.
+    assert_eq!(
+        efficiencies(&comparisons.versus_other),
+        [
+            ("scalar".to_owned(), None),
+            ("avx2".to_owned(), Some(annotation.to_owned()))
+        ]
+    );
+    assert_eq!(
+        efficiencies(&comparisons.versus_self),
+        [("avx2".to_owned(), Some(annotation.to_owned()))]
+    );
+    assert_eq!(
+        efficiencies(&comparisons.raw[..1]),
+        [
+            ("./adler32 --scalar (cycles)".to_owned(), None),
+            (
+                "./adler32 --avx2 (cycles)".to_owned(),
+                Some(annotation.to_owned())
+            )
+        ]
+    );
+
+    let mut md = String::new();
+    data.render_markdown_raw_group(
+        &mut md,
+        "adler32",
+        None,
+        config.raw_table_options("adler32"),
+    );
+    assert!(
+        md.contains(&format!("|`./adler32 --avx2` {annotation}|")),
+        "{md}"
+    );
+    let md = render_step_summary(
+        &config,
+        "owner/repo",
+        &data,
+        Some(&prev),
+        &comparisons,
+        Verdicts::default(),
+        &[],
+    );
+    assert!(
+        md.contains("### Furthest from the theoretical limits\n"),
+        "{md}"
+    );
+    assert!(md.contains(&format!("| avx2 {annotation} <a id=")), "{md}");
+
+    // The limits of another class of machine don't apply.
+    let mut other = data.clone();
+    other.machine_class = Some("icelake/4".to_owned());
+    let comparisons = Comparisons::collect(&config, &other, Some(&prev));
+    assert!(comparisons.efficiencies.is_empty());
+    assert!(comparisons
+        .versus_other
+        .iter()
+        .flat_map(|table| &table.rows)
+        .all(|row| row.efficiency.is_none()));
+}
+
+#[test]
+fn parse_render() {
+    let input = r#"{ "measure": "cycles", "before": { "command": "blogpost-compress-ng", "index": 0 }, "after": { "command": "blogpost-compress-rs", "index": 0 } }"#;
+    let _compare: Compare = serde_json::from_slice(input.as_bytes()).unwrap();
+
+    let input = r#"
+        {
+            "level 0": { "measure": "cycles", "before": { "command": "blogpost-compress-ng", "index": 0 }, "after": { "command": "blogpost-compress-rs", "index": 0 } },
+            "level 1": { "measure": "cycles", "before": { "command": "blogpost-compress-ng", "index": 1 }, "after": { "command": "blogpost-compress-rs", "index": 1 } },
+            "level 2": { "measure": "cycles", "before": { "command": "blogpost-compress-ng", "index": 2 }, "after": { "command": "blogpost-compress-rs", "index": 2 } },
+            "level 3": { "measure": "cycles", "before": { "command": "blogpost-compress-ng", "index": 3 }, "after": { "command": "blogpost-compress-rs", "index": 3 } },
+            "level 4": { "measure": "cycles", "before": { "command": "blogpost-compress-ng", "index": 4 }, "after": { "command": "blogpost-compress-rs", "index": 4 } },
+            "level 5": { "measure": "cycles", "before": { "command": "blogpost-compress-ng", "index": 5 }, "after": { "command": "blogpost-compress-rs", "index": 5 } },
+            "level 6": { "measure": "cycles", "before": { "command": "blogpost-compress-ng", "index": 6 }, "after": { "command": "blogpost-compress-rs", "index": 6 } },
+            "level 7": { "measure": "cycles", "before": { "command": "blogpost-compress-ng", "index": 7 }, "after": { "command": "blogpost-compress-rs", "index": 7 } },
+            "level 8": { "measure": "cycles", "before": { "command": "blogpost-compress-ng", "index": 8 }, "after": { "command": "blogpost-compress-rs", "index": 8 } },
+            "level 9": { "measure": "cycles", "before": { "command": "blogpost-compress-ng", "index": 9 }, "after": { "command": "blogpost-compress-rs", "index": 9 } }
+        }
+        "#;
+    let _compares: IndexMap<String, Compare> = serde_json::from_slice(input.as_bytes()).unwrap();
+
+    let input = r#"
+        {
+            "compression (ng vs rs)": {
+                "level 0": { "measure": "cycles", "before": { "command": "blogpost-compress-ng", "index": 0 }, "after": { "command": "blogpost-compress-rs", "index": 0 } },
+                "level 1": { "measure": "cycles", "before": { "command": "blogpost-compress-ng", "index": 1 }, "after": { "command": "blogpost-compress-rs", "index": 1 } },
+                "level 2": { "measure": "cycles", "before": { "command": "blogpost-compress-ng", "index": 2 }, "after": { "command": "blogpost-compress-rs", "index": 2 } },
+                "level 3": { "measure": "cycles", "before": { "command": "blogpost-compress-ng", "index": 3 }, "after": { "command": "blogpost-compress-rs", "index": 3 } },
+                "level 4": { "measure": "cycles", "before": { "command": "blogpost-compress-ng", "index": 4 }, "after": { "command": "blogpost-compress-rs", "index": 4 } },
+                "level 5": { "measure": "cycles", "before": { "command": "blogpost-compress-ng", "index": 5 }, "after": { "command": "blogpost-compress-rs", "index": 5 } },
+                "level 6": { "measure": "cycles", "before": { "command": "blogpost-compress-ng", "index": 6 }, "after": { "command": "blogpost-compress-rs", "index": 6 } },
+                "level 7": { "measure": "cycles", "before": { "command": "blogpost-compress-ng", "index": 7 }, "after": { "command": "blogpost-compress-rs", "index": 7 } },
+                "level 8": { "measure": "cycles", "before": { "command": "blogpost-compress-ng", "index": 8 }, "after": { "command": "blogpost-compress-rs", "index": 8 } },
+                "level 9": { "measure": "cycles", "before": { "command": "blogpost-compress-ng", "index": 9 }, "after": { "command": "blogpost-compress-rs", "index": 9 } }
+            },
+            "decompression (ng vs rs)": {
+                "chunk size 4": { "measure": "cycles", "before": { "command": "blogpost-uncompress-ng", "index": 0 }, "after": { "command": "blogpost-uncompress-rs", "index": 0 } },
+                "chunk size 5": { "measure": "cycles", "before": { "command": "blogpost-uncompress-ng", "index": 1 }, "after": { "command": "blogpost-uncompress-rs", "index": 1 } },
+                "chunk size 6": { "measure": "cycles", "before": { "command": "blogpost-uncompress-ng", "index": 2 }, "after": { "command": "blogpost-uncompress-rs", "index": 2 } },
+                "chunk size 7": { "measure": "cycles", "before": { "command": "blogpost-uncompress-ng", "index": 3 }, "after": { "command": "blogpost-uncompress-rs", "index": 3 } },
+                "chunk size 8": { "measure": "cycles", "before": { "command": "blogpost-uncompress-ng", "index": 4 }, "after": { "command": "blogpost-uncompress-rs", "index": 4 } },
+                "chunk size 9": { "measure": "cycles", "before": { "command": "blogpost-uncompress-ng", "index": 5 }, "after": { "command": "blogpost-uncompress-rs", "index": 5 } },
+                "chunk size 10": { "measure": "cycles", "before": { "command": "blogpost-uncompress-ng", "index": 6 }, "after": { "command": "blogpost-uncompress-rs", "index": 6 } },
+                "chunk size 11": { "measure": "cycles", "before": { "command": "blogpost-uncompress-ng", "index": 7 }, "after": { "command": "blogpost-uncompress-rs", "index": 7 } },
+                "chunk size 12": { "measure": "cycles", "before": { "command": "blogpost-uncompress-ng", "index": 8 }, "after": { "command": "blogpost-uncompress-rs", "index": 8 } },
+                "chunk size 13": { "measure": "cycles", "before": { "command": "blogpost-uncompress-ng", "index": 9 }, "after": { "command": "blogpost-uncompress-rs", "index": 9 } },
+                "chunk size 14": { "measure": "cycles", "before": { "command": "blogpost-uncompress-ng", "index": 10 }, "after": { "command": "blogpost-uncompress-rs", "index": 10 } },
+                "chunk size 15": { "measure": "cycles", "before": { "command": "blogpost-uncompress-ng", "index": 11 }, "after": { "command": "blogpost-uncompress-rs", "index": 11 } },
+                "chunk size 16": { "measure": "cycles", "before": { "command": "blogpost-uncompress-ng", "index": 12 }, "after": { "command": "blogpost-uncompress-rs", "index": 12 } }
+            }
+        }
+    "#;
+
+    let _render: IndexMap<String, IndexMap<String, Compare>> =
+        serde_json::from_slice(input.as_bytes()).unwrap();
+}
+
+#[test]
+fn parse_table_display() {
+    let compare = r#"{ "measure": "cycles", "before": { "command": "ng", "index": 0 }, "after": { "command": "rs", "index": 0 } }"#;
+
+    let table: VersusSelf =
+        serde_json::from_str(&format!(r#"{{ "level 0": {compare} }}"#)).unwrap();
+    assert_eq!(table.rows.len(), 1);
+    assert_eq!(table.display.max_rows, None);
+
+    let table: VersusSelf = serde_json::from_str(&format!(
+        r#"{{ "rows": {{ "level 0": {compare} }}, "max-rows": 10, "show-all-in-details": true }}"#
+    ))
+    .unwrap();
+    assert_eq!(table.rows.len(), 1);
+    assert_eq!(table.display.max_rows, Some(10));
+    assert!(table.display.show_all_in_details);
+
+    let table: VersusOther = serde_json::from_str(
+        r#"{ "measure": "cycles", "command": "compress", "rows": { "level 1": 0 }, "max-rows": 5 }"#,
+    )
+    .unwrap();
+    assert_eq!(table.display.max_rows, Some(5));
+    assert!(!table.display.show_all_in_details);
+    assert_eq!(table.display.order, TableOrder::Config);
+
+    let table: VersusOther = serde_json::from_str(
+        r#"{ "measure": "cycles", "command": "compress", "rows": { "level 1": 0 }, "order": "severity" }"#,
+    )
+    .unwrap();
+    assert_eq!(table.display.order, TableOrder::Severity);
+}
+
+#[test]
+fn parse_commands() {
+    let config: Config = serde_json::from_str(
+        r#"{
+            "commands": {
+                "compress": ["./compress 1", { "command": "./compress 9", "profile": true, "interval-ms": 100, "expected-exit-codes": [0, 1] }]
+            },
+            "profile": { "top-symbols": 5 },
+            "intervals": { "max-points": 16 },
+            "render-versus-self": {},
+            "render-versus-other": {}
+        }"#,
+    )
+    .unwrap();
+
+    let commands = &config.commands["compress"];
+    assert_eq!(commands[0].command, "./compress 1");
+    assert!(!commands[0].profile);
+    assert_eq!(commands[1].command, "./compress 9");
+    assert!(commands[1].profile);
+    assert_eq!(commands[0].expected_exit_codes, [0]);
+    assert_eq!(commands[1].expected_exit_codes, [0, 1]);
+    assert_eq!(config.profile.top_symbols, 5);
+    assert_eq!(config.profile.min_change_points, 1.0);
+    assert_eq!(commands[0].interval, None);
+    assert_eq!(commands[1].interval, Some(Duration::from_millis(100)));
+    assert_eq!(config.intervals.max_points, 16);
+    assert_eq!(config.intervals.counter, "cycles");
+}
+
+#[test]
+fn flush_only_interleaved_groups() {
+    let config: Config = serde_json::from_str(
+        r#"{
+            "commands": {
+                "compress": ["./c ng", "./c rs"],
+                "decompress": ["./d ng", "./d rs"],
+                "pipeline": [{ "composite": "round trip", "steps": ["./c ng", "./d ng"] }]
+            },
+            "interleave-for-group": { "compress": true, "pipeline": true },
+            "flush-between-for-group": {
+                "compress": { "buffer-mb": "1KiB" },
+                "decompress": { "buffer-mb": "1KiB" },
+                "pipeline": { "buffer-mb": "1KiB" }
+            },
+            "render-versus-self": {
+                "ng vs rs": {
+                    "compress": { "measure": "cycles", "before": { "command": "compress", "index": 0 }, "after": { "command": "compress", "index": 1 } },
+                    "decompress": { "measure": "cycles", "before": { "command": "decompress", "index": 0 }, "after": { "command": "decompress", "index": 1 } }
+                }
+            },
+            "render-versus-other": {}
+        }"#,
+    )
+    .unwrap();
+    config.validate().unwrap();
+    let schedule = |group_name: &str| {
+        let mut pairs = if config.interleave(group_name) {
+            interleave::pairs(&config, group_name)
+        } else {
+            vec![]
+        };
+        pairs.extend(composite::pairs(&config.commands[group_name]));
+        interleave::schedule(config.commands[group_name].len(), &pairs)
+    };
+
+    assert!(config.flusher("compress", &schedule("compress")).is_some());
+    // Not interleaved, so not flushed.
+    assert!(config
+        .flusher("decompress", &schedule("decompress"))
+        .is_none());
+    // The steps of a composite run as a pipeline.
+    let pipeline = schedule("pipeline");
+    assert_eq!(pipeline[0], interleave::Step::Interleaved(vec![0, 1]));
+    assert!(!config.compares_step("pipeline", &pipeline[0]));
+    assert!(config.flusher("pipeline", &pipeline).is_none());
+}
+
+#[test]
+fn measure_child_only_with_perf() {
+    let config = |backends: &str, options: &str| {
+        serde_json::from_str::<Config>(&format!(
+            r#"{{
+                "commands": {{ "build": [{{ "command": "./build.sh", {options} }}] }},
+                "backends-for-group": {{ "build": {backends} }},
+                "measure-child-timeout-ms": "2s",
+                "render-versus-self": {{}},
+                "render-versus-other": {{}}
+            }}"#
+        ))
+        .unwrap()
+    };
+
+    let valid = config(r#"["perf"]"#, r#""measure-child": "/^cc1(plus)?$/""#);
+    valid.validate().unwrap();
+    assert_eq!(
+        valid.commands["build"][0].measure_child.as_deref(),
+        Some("/^cc1(plus)?$/")
+    );
+    assert_eq!(valid.measure_child_timeout, Duration::from_secs(2));
+
+    assert_eq!(
+        config(r#"["perf", "getrusage"]"#, r#""measure-child": "cc1""#)
+            .validate()
+            .unwrap_err(),
+        "`./build.sh` has `measure-child`, which only the perf backend measures, but the `build` group also uses the `getrusage` backend"
+    );
+    assert_eq!(
+        config(r#"["perf"]"#, r#""measure-child": "cc1", "sync-start": true"#)
+            .validate()
+            .unwrap_err(),
+        "`./build.sh` has both `sync-start` and `measure-child`, perf either runs the command or attaches to its process"
+    );
+    assert!(config(r#"["perf"]"#, r#""measure-child": "bin/cc1""#)
+        .validate()
+        .is_err());
+}
+
+#[test]
+fn reject_unknown_table_command() {
+    let config: Config = serde_json::from_str(
+        r#"{
+            "commands": { "compress": ["./c 1"] },
+            "render-versus-self": {},
+            "render-versus-other": {
+                "compression": { "measure": "cycles", "command": "compres", "rows": { "level 1": 0 } }
+            }
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(
+        config.validate().unwrap_err(),
+        "the `compression` table compares the `compres` group, which doesn't exist"
+    );
+}
+
+#[test]
+fn parse_outputs() {
+    let config = |produces: &str| -> Config {
+        serde_json::from_str(&format!(
+            r#"{{
+                "commands": {{
+                    "compress": ["./compress 1", {{ "command": "./compress 9", "produces": {produces} }}]
+                }},
+                "outputs": {{ "min-free-space-mb": "10GiB" }},
+                "render-versus-self": {{}},
+                "render-versus-other": {{}}
+            }}"#
+        ))
+        .unwrap()
+    };
+
+    let valid = config(r#"["out/*.zst"]"#);
+    valid.validate().unwrap();
+    let commands = &valid.commands["compress"];
+    assert!(commands[0].produces.is_empty());
+    assert_eq!(commands[1].produces, ["out/*.zst"]);
+    let outputs = valid.outputs.unwrap();
+    assert_eq!(outputs.min_free_space, Some(10 << 30));
+    assert!(!outputs.keep_outputs);
+    assert_eq!(outputs.large_file, 100 << 20);
+
+    assert_eq!(
+        config(r#"["../corpus.zst"]"#).validate().unwrap_err(),
+        "the output `../corpus.zst` must be relative to the working directory, without `..`"
+    );
+}
+
+#[test]
+fn duplicate_ids() {
+    let config: Config = serde_json::from_str(
+        r#"{
+            "commands": {
+                "compress": [{ "command": "./compress 1", "id": "level-1" }, "./compress 2"],
+                "decompress": [{ "command": "./decompress 1", "id": "level-1" }]
+            },
+            "render-versus-self": {},
+            "render-versus-other": {}
+        }"#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        config.commands["compress"][0].id.as_deref(),
+        Some("level-1")
+    );
+    assert_eq!(config.commands["compress"][1].id, None);
+    assert_eq!(
+        config.validate().unwrap_err(),
+        "the id `level-1` is used more than once, in the `compress` and the `decompress` group"
+    );
+}
+
+#[test]
+fn matrix_commands() {
+    let config: Config = serde_json::from_str(
+        r#"{
+            "commands": {
+                "compress": [
+                    "./compress-c 6 enwik8",
+                    {
+                        "template": "target/release/{impl} {level} bench-data/{corpus}",
+                        "matrix": { "impl": ["compress-ng", "compress-rs"], "level": [1, 9], "corpus": ["silesia.tar", "enwik8"] },
+                        "exclude": [{ "impl": "compress-ng", "level": 9 }],
+                        "tags": ["matrix"]
+                    }
+                ]
+            },
+            "render-versus-self": {},
+            "render-versus-other": {}
+        }"#,
+    )
+    .unwrap();
+    config.validate().unwrap();
+    let commands = &config.commands["compress"];
+    assert_eq!(commands.len(), 7);
+    assert_eq!(commands[0].id, None);
+    assert_eq!(
+        commands[1..]
+            .iter()
+            .map(|bench| (bench.id.as_deref().unwrap(), bench.command.as_str()))
+            .collect::<Vec<_>>(),
+        [
+            (
+                "compress-ng/1/silesia.tar",
+                "target/release/compress-ng 1 bench-data/silesia.tar"
+            ),
+            (
+                "compress-ng/1/enwik8",
+                "target/release/compress-ng 1 bench-data/enwik8"
+            ),
+            (
+                "compress-rs/1/silesia.tar",
+                "target/release/compress-rs 1 bench-data/silesia.tar"
+            ),
+            (
+                "compress-rs/1/enwik8",
+                "target/release/compress-rs 1 bench-data/enwik8"
+            ),
+            (
+                "compress-rs/9/silesia.tar",
+                "target/release/compress-rs 9 bench-data/silesia.tar"
+            ),
+            (
+                "compress-rs/9/enwik8",
+                "target/release/compress-rs 9 bench-data/enwik8"
+            ),
+        ]
+    );
+    assert!(commands[1..].iter().all(|bench| bench.tags == ["matrix"]));
+
+    // The generated ids collide with those of the other commands like any other.
+    let config: Config = serde_json::from_str(
+        r#"{
+            "commands": {
+                "compress": [
+                    { "command": "./compress-rs 1 enwik8", "id": "compress-rs/1" },
+                    { "template": "./{impl} {level}", "matrix": { "impl": ["compress-rs"], "level": [1, 9] } }
+                ]
+            },
+            "render-versus-self": {},
+            "render-versus-other": {}
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(
+        config.validate().unwrap_err(),
+        "the id `compress-rs/1` is used more than once in the `compress` group"
+    );
+
+    let err = serde_json::from_str::<Config>(
+        r#"{
+            "commands": { "compress": [{ "template": "./compress {level}", "matrix": { "lvl": [1] } }] },
+            "render-versus-self": {},
+            "render-versus-other": {}
+        }"#,
+    )
+    .unwrap_err();
+    assert!(
+        err.to_string()
+            .starts_with("the matrix parameter `lvl` isn't used in `./compress {level}`"),
+        "{err}"
+    );
+}
+
+#[test]
+fn sample_corpus_commands() {
+    let dir = testkit::test_dir("sample-corpus-commands");
+    for (name, size) in [("a.bin", 30), ("b.bin", 10), ("c.bin", 20)] {
+        std::fs::create_dir_all(dir.join("corpus")).unwrap();
+        std::fs::write(dir.join("corpus").join(name), vec![0; size]).unwrap();
+    }
+    let corpus = format!("{}/corpus", dir.display());
+    let load = || -> Config {
+        serde_json::from_value(serde_json::json!({
+            "commands": {
+                "decompress": [
+                    "./decompress --version",
+                    {
+                        "template": "./decompress {file}",
+                        "files": format!("{corpus}/*.bin"),
+                        "sample": { "count": 2, "strategy": "stratified-by-size" },
+                        "tags": ["corpus"]
+                    }
+                ]
+            },
+            "gate": { "max-regression-percent": 5 },
+            "render-versus-self": {},
+            "render-versus-other": {
+                "decompress": {
+                    "measure": "cycles",
+                    "command": "decompress",
+                    "rows": { "version": 0, "middle": 3, "smallest": 2 }
+                }
+            }
+        }))
+        .unwrap()
+    };
+
+    // The smallest and the largest file, in the order of their paths, with the rows of the
+    // others dropped.
+    let mut config = load();
+    config.validate().unwrap();
+    assert_eq!(config.commands["decompress"].len(), 4);
+    let samples = config.sample_corpora(&mut Rng::new(1));
+    let commands = &config.commands["decompress"];
+    assert_eq!(
+        commands
+            .iter()
+            .map(|bench| (bench.id.as_deref(), bench.command.as_str()))
+            .collect::<Vec<_>>(),
+        [
+            (None, "./decompress --version"),
+            (
+                Some(format!("{corpus}/a.bin").as_str()),
+                format!("./decompress {corpus}/a.bin").as_str()
+            ),
+            (
+                Some(format!("{corpus}/b.bin").as_str()),
+                format!("./decompress {corpus}/b.bin").as_str()
+            ),
+        ]
+    );
+    assert!(commands[1..].iter().all(|bench| bench.tags == ["corpus"]));
+    assert_eq!(
+        config.render_versus_other["decompress"].rows,
+        IndexMap::from([("version".to_owned(), 0), ("smallest".to_owned(), 2)])
+    );
+    assert_eq!(
+        serde_json::to_value(&samples).unwrap(),
+        serde_json::json!({
+            "decompress": [{
+                "files": format!("{corpus}/*.bin"),
+                "strategy": "stratified-by-size",
+                "count": 2,
+                "chosen": [
+                    { "path": format!("{corpus}/a.bin"), "size": 30 },
+                    { "path": format!("{corpus}/b.bin"), "size": 10 }
+                ]
+            }]
+        })
+    );
+
+    // The recorded samples choose the same commands again.
+    let mut recorded = load();
+    recorded.retain_samples(&samples);
+    assert_eq!(
+        recorded.commands["decompress"]
+            .iter()
+            .map(|bench| &bench.command)
+            .collect::<Vec<_>>(),
+        commands
+            .iter()
+            .map(|bench| &bench.command)
+            .collect::<Vec<_>>()
+    );
+
+    // A regression of a group sampled differently than the baseline doesn't fail the gate,
+    // unless with `--strict-sampling`.
+    let before = testkit::BenchDataBuilder::cycles(
+        "1111111111111111111111111111111111111111",
+        &[(
+            "decompress",
+            &[
+                ("./decompress --version", 100.0),
+                ("./decompress a.bin", 100.0),
+                ("./decompress b.bin", 100.0),
+            ],
+        )],
+    )
+    .build();
+    let after = testkit::BenchDataBuilder::cycles(
+        "2222222222222222222222222222222222222222",
+        &[(
+            "decompress",
+            &[
+                ("./decompress --version", 200.0),
+                ("./decompress a.bin", 200.0),
+                ("./decompress b.bin", 200.0),
+            ],
+        )],
+    )
+    .build();
+    let mut comparisons = Comparisons {
+        versus_other: compare::collect_versus_other(
+            &config.render_versus_other,
+            &IndexMap::new(),
+            None,
+            &before,
+            &after,
+        ),
+        ..Comparisons::default()
+    };
+    assert!(!config.evaluate_gate(&comparisons, &[]).unwrap().passed());
+    comparisons.sampling_mismatches = vec![sample::SamplingMismatch {
+        group: "decompress".to_owned(),
+        differences: vec!["`corpus/*.bin` isn't sampled in the baseline".to_owned()],
+    }];
+    let verdict = config.evaluate_gate(&comparisons, &[]).unwrap();
+    assert!(verdict.passed());
+    assert_eq!(verdict.suppressed.len(), 2);
+    config.strict_sampling = true;
+    assert!(!config.evaluate_gate(&comparisons, &[]).unwrap().passed());
+
+    for (entry, expected) in [
+        (
+            serde_json::json!({ "template": "./decompress", "files": format!("{corpus}/*.bin") }),
+            format!("the template `./decompress` of `{corpus}/*.bin` has no `{{file}}`"),
+        ),
+        (
+            serde_json::json!({ "template": "./decompress {file}", "files": format!("{corpus}/*.zst") }),
+            format!("no files match `{corpus}/*.zst`"),
+        ),
+        (
+            serde_json::json!({
+                "template": "./decompress {file}",
+                "files": format!("{corpus}/*.bin"),
+                "sample": { "count": 0, "strategy": "first-n" }
+            }),
+            format!("the sample of `{corpus}/*.bin` must have a count of at least 1"),
+        ),
+    ] {
+        let err = serde_json::from_value::<Config>(serde_json::json!({
+            "commands": { "decompress": [entry] },
+            "render-versus-self": {},
+            "render-versus-other": {}
+        }))
+        .unwrap_err();
+        assert!(err.to_string().starts_with(&expected), "{err}");
+    }
+}
+
+#[test]
+fn filter_by_tags() {
+    let config = || -> Config {
+        serde_json::from_str(
+            r#"{
+                "commands": {
+                    "compress-rs": [{ "command": "./c rs 1", "tags": ["smoke"] }, "./c rs 9"],
+                    "compress-ng": [{ "command": "./c ng 1", "tags": ["smoke", "ng"] }, "./c ng 9"],
+                    "decompress-rs": ["./d rs"]
+                },
+                "tags-for-group": {
+                    "compress-rs": ["compression", "rs"],
+                    "compress-ng": ["compression", "ng"],
+                    "decompress-rs": ["rs"]
+                },
+                "render-versus-self": {
+                    "ng vs rs": {
+                        "level 1": { "measure": "cycles", "before": { "command": "compress-ng", "index": 0 }, "after": { "command": "compress-rs", "index": 0 } },
+                        "level 9": { "measure": "cycles", "before": { "command": "compress-ng", "index": 1 }, "after": { "command": "compress-rs", "index": 1 } }
+                    }
+                },
+                "render-versus-other": {
+                    "compression": { "measure": "cycles", "command": "compress-rs", "rows": { "level 1": 0, "level 9": 1 } },
+                    "decompression": { "measure": "cycles", "command": "decompress-rs", "rows": { "default": 0 } }
+                },
+                "budgets": {
+                    "rs level 9": { "group": "compress-rs", "command": 1, "measure": "cycles", "operator": "<", "limit": 1e9 },
+                    "ng level 9": { "group": "compress-ng", "command": "ng 9", "measure": "cycles", "operator": "<", "limit": 1e9 }
+                }
+            }"#,
+        )
+        .unwrap()
+    };
+    let commands = |config: &Config| {
+        config
+            .commands
+            .values()
+            .flatten()
+            .map(|bench| bench.command.clone())
+            .collect::<Vec<_>>()
+    };
+    let tags = |tags: &[&str]| tags.iter().map(|tag| tag.to_string()).collect::<Vec<_>>();
+
+    // Group tags come first, without duplicates.
+    let unfiltered = config();
+    unfiltered.validate().unwrap();
+    assert_eq!(
+        unfiltered.tags("compress-ng", &unfiltered.commands["compress-ng"][0]),
+        ["compression", "ng", "smoke"]
+    );
+
+    let mut filtered = config();
+    filtered.retain_tagged(&[], &[]);
+    assert_eq!(commands(&filtered).len(), 5);
+
+    // Every `--only-tag` has to match.
+    let mut filtered = config();
+    filtered.retain_tagged(&tags(&["rs", "smoke"]), &[]);
+    assert_eq!(commands(&filtered), ["./c rs 1"]);
+    assert_eq!(
+        filtered.render_versus_other.keys().collect::<Vec<_>>(),
+        ["compression"]
+    );
+    assert_eq!(
+        filtered.render_versus_other["compression"].rows,
+        IndexMap::from([("level 1".to_owned(), 0)])
+    );
+    // The other side of the comparison is gone.
+    assert!(filtered.render_versus_self["ng vs rs"].rows.is_empty());
+
+    // Skipping shifts the indices of the remaining commands.
+    let mut filtered = config();
+    filtered.retain_tagged(&tags(&["compression"]), &tags(&["smoke"]));
+    assert_eq!(commands(&filtered), ["./c rs 9", "./c ng 9"]);
+    assert_eq!(
+        filtered.render_versus_other["compression"].rows,
+        IndexMap::from([("level 9".to_owned(), 0)])
+    );
+    let rows = &filtered.render_versus_self["ng vs rs"].rows;
+    assert_eq!(rows.keys().collect::<Vec<_>>(), ["level 9"]);
+    assert_eq!(rows["level 9"].before.index, 0);
+    assert_eq!(rows["level 9"].after.index, 0);
+    assert!(matches!(
+        filtered.budgets["rs level 9"].command,
+        BudgetCommand::Index(0)
+    ));
+    assert_eq!(filtered.budgets.len(), 2);
+
+    let mut filtered = config();
+    filtered.retain_tagged(&[], &tags(&["rs"]));
+    assert_eq!(commands(&filtered), ["./c ng 1", "./c ng 9"]);
+    assert!(filtered.render_versus_other.is_empty());
+    assert_eq!(filtered.budgets.keys().collect::<Vec<_>>(), ["ng level 9"]);
+
+    let mut invalid = config();
+    invalid
+        .tags_for_group
+        .insert("compress-rs".to_owned(), tags(&["fast path"]));
+    assert_eq!(
+        invalid.validate().unwrap_err(),
+        "the tag `fast path` is empty or contains whitespace or a `|`"
+    );
+    let mut invalid = config();
+    invalid.commands.get_mut("decompress-rs").unwrap()[0].tags = tags(&["a|b"]);
+    assert!(invalid.validate().is_err());
+    let mut invalid = config();
+    invalid.budgets["ng level 9"].command = BudgetCommand::Match("./c ng".to_owned());
+    assert!(invalid.validate().is_err());
+}
+
+#[test]
+fn skip_unchanged_groups() {
+    let mut config: Config = serde_json::from_str(
+        r#"{
+            "commands": {
+                "compress-rs": [{ "command": "./c rs 1", "tags": ["smoke"] }, "./c rs 9"],
+                "compress-ng": ["./c ng 1", "./c ng 9"],
+                "decompress-rs": ["./d rs"]
+            },
+            "paths-for-group": { "compress-ng": ["src/ng/**"] },
+            "render-versus-self": {
+                "ng vs rs": {
+                    "level 1": { "measure": "cycles", "before": { "command": "compress-ng", "index": 0 }, "after": { "command": "compress-rs", "index": 0 } },
+                    "level 9": { "measure": "cycles", "before": { "command": "compress-ng", "index": 1 }, "after": { "command": "compress-rs", "index": 1 } }
+                },
+                "levels": {
+                    "rs": { "measure": "cycles", "before": { "command": "compress-rs", "index": 0 }, "after": { "command": "compress-rs", "index": 1 } }
+                }
+            },
+            "render-versus-other": {
+                "ng": { "measure": "cycles", "command": "compress-ng", "rows": { "level 1": 0, "level 9": 1 } },
+                "decompression": { "measure": "cycles", "command": "decompress-rs", "rows": { "default": 0 } }
+            }
+        }"#,
+    )
+    .unwrap();
+
+    // The tag filters come first, then whole groups are skipped.
+    config.retain_tagged(&[], &["smoke".to_owned()]);
+    let skipped = IndexMap::from([(
+        "compress-ng".to_owned(),
+        "no changed files matched src/ng/**".to_owned(),
+    )]);
+    config.skip_groups(&skipped);
+
+    assert_eq!(
+        config.commands.keys().collect::<Vec<_>>(),
+        ["compress-rs", "decompress-rs"]
+    );
+    assert_eq!(
+        config.render_versus_other.keys().collect::<Vec<_>>(),
+        ["decompression"]
+    );
+    assert!(config.render_versus_self["ng vs rs"].rows.is_empty());
+    // Rows already dropped by the tag filters aren't missing because of the skipped group.
+    assert_eq!(
+        config.unmeasured_rows,
+        IndexMap::from([
+            (
+                "ng".to_owned(),
+                vec!["level 1".to_owned(), "level 9".to_owned()]
+            ),
+            ("ng vs rs".to_owned(), vec!["level 9".to_owned()]),
+        ])
+    );
+
+    let data = testkit::BenchDataBuilder::new("2222222222222222222222222222222222222222")
+        .group("compress-rs", |g| {
+            g.bench(["./c", "rs", "9"], |b| {
+                b.counter("cycles", 800.0, 100.0, 20, "")
+            })
+        })
+        .group("decompress-rs", |g| {
+            g.bench(["./d", "rs"], |b| b.counter("cycles", 900.0, 100.0, 20, ""))
+        })
+        .build();
+    let data = BenchData {
+        skipped_groups: skipped,
+        ..data
+    };
+    let comparisons = Comparisons::collect(&config, &data, Some(&data));
+    let md = render_step_summary(
+        &config,
+        "owner/repo",
+        &data,
+        Some(&data),
+        &comparisons,
+        Verdicts::default(),
+        &[],
+    );
+    assert!(
+        md.contains(
+            "### Not measured in this run\n\n\
+            Skipped with `--changed-only`:\n\n\
+            - `compress-ng`: skipped: no changed files matched src/ng/**\n\n\
+            Missing from the comparisons:\n\n\
+            - ng: `level 1`, `level 9`\n\
+            - ng vs rs: `level 9`\n"
+        ),
+        "{md}"
+    );
+}
+
+#[test]
+fn parse_args() {
+    let args = |args: &[&str]| Args::parse(args.iter().map(|arg| arg.to_string()));
+
+    assert_eq!(
+        args(&["abc", "bench.json", "results.json"]).unwrap(),
+        Args {
+            commit_hash: "abc".to_owned(),
+            config_paths: vec![PathBuf::from("bench.json")],
+            previous_results_path: "results.json".to_owned(),
+            remap_ids: vec![],
+            stream: false,
+            require_isolation: false,
+            require_quiet: false,
+            only_tags: vec![],
+            skip_tags: vec![],
+            changed_only: false,
+            run_report: None,
+            keep_scratch: false,
+            results_file: None,
+            csv: None,
+            allow_dirty: false,
+            fail_fast: false,
+            seed: None,
+            replay_seed_from: None,
+            backfill_baseline_counters: false,
+            persist_backfill: false,
+            results_index: false,
+            strict_sampling: false,
+        }
+    );
+
+    let parsed = args(&[
+        "--remap-id",
+        "./compress --level=6=compress-6",
+        "abc",
+        "bench.json",
+        "--remap-id=./compress 1=compress-1",
+        "results.json",
+    ])
+    .unwrap();
+    assert_eq!(parsed.previous_results_path, "results.json");
+    assert_eq!(
+        parsed.remap_ids,
+        [
+            ("./compress --level=6".to_owned(), "compress-6".to_owned()),
+            ("./compress 1".to_owned(), "compress-1".to_owned()),
+        ]
+    );
+
+    assert!(args(&["abc", "bench.json"]).is_err());
+    assert_eq!(
+        args(&["abc", "a.json", "configs", "results.json"])
+            .unwrap()
+            .config_paths,
+        [PathBuf::from("a.json"), PathBuf::from("configs")]
+    );
+    assert!(args(&["abc", "bench.json", "results.json", "--remap-id"]).is_err());
+    assert!(args(&["abc", "bench.json", "results.json", "--remap-id", "x"]).is_err());
+    assert!(args(&["abc", "bench.json", "results.json", "--verbose=1"]).is_err());
+
+    assert!(
+        args(&["--stream", "abc", "bench.json", "results.json"])
+            .unwrap()
+            .stream
+    );
+    assert!(args(&["abc", "bench.json", "results.json", "--stream=yes"]).is_err());
+    assert!(
+        args(&["abc", "bench.json", "results.json", "--require-quiet"])
+            .unwrap()
+            .require_quiet
+    );
+    assert!(
+        args(&["abc", "bench.json", "results.json", "--keep-scratch"])
+            .unwrap()
+            .keep_scratch
+    );
+    assert_eq!(
+        args(&[
+            "abc",
+            "bench.json",
+            "results.json",
+            "--results-file=out.json"
+        ])
+        .unwrap()
+        .results_file,
+        Some(PathBuf::from("out.json"))
+    );
+    assert_eq!(
+        args(&["abc", "bench.json", "results.json", "--csv", "out.csv"])
+            .unwrap()
+            .csv,
+        Some(PathBuf::from("out.csv"))
+    );
+    assert!(
+        args(&["abc", "bench.json", "results.json", "--allow-dirty"])
+            .unwrap()
+            .allow_dirty
+    );
+    assert!(
+        args(&["abc", "bench.json", "results.json", "--fail-fast"])
+            .unwrap()
+            .fail_fast
+    );
+
+    let tagged = args(&[
+        "abc",
+        "bench.json",
+        "results.json",
+        "--only-tag",
+        "compression",
+        "--skip-tag=slow",
+        "--only-tag=rs",
+    ])
+    .unwrap();
+    assert_eq!(tagged.only_tags, ["compression", "rs"]);
+    assert_eq!(tagged.skip_tags, ["slow"]);
+
+    let changed = args(&["--changed-only", "abc", "bench.json", "results.json"]).unwrap();
+    assert!(changed.changed_only);
+    assert!(args(&["--changed-only=yes", "abc", "bench.json", "results.json"]).is_err());
+
+    assert_eq!(
+        args(&[
+            "abc",
+            "bench.json",
+            "results.json",
+            "--run-report=report.json"
+        ])
+        .unwrap()
+        .run_report,
+        Some(PathBuf::from("report.json"))
+    );
+
+    assert_eq!(
+        args(&["abc", "bench.json", "results.json", "--seed=42"])
+            .unwrap()
+            .seed,
+        Some(42)
+    );
+    assert_eq!(
+        args(&["abc", "bench.json", "results.json", "--seed", "-1"]).unwrap_err(),
+        "`--seed -1` is not a non-negative integer"
+    );
+    assert_eq!(
+        args(&[
+            "abc",
+            "bench.json",
+            "results.json",
+            "--replay-seed-from",
+            "old.json"
+        ])
+        .unwrap()
+        .replay_seed_from,
+        Some(PathBuf::from("old.json"))
+    );
+    assert!(args(&[
+        "abc",
+        "bench.json",
+        "results.json",
+        "--seed=1",
+        "--replay-seed-from=old.json"
+    ])
+    .is_err());
+
+    let backfill = args(&[
+        "abc",
+        "bench.json",
+        "results.json",
+        "--backfill-baseline-counters",
+        "--persist-backfill",
+    ])
+    .unwrap();
+    assert!(backfill.backfill_baseline_counters && backfill.persist_backfill);
+    assert_eq!(
+        args(&["abc", "bench.json", "results.json", "--persist-backfill"]).unwrap_err(),
+        "`--persist-backfill` requires `--backfill-baseline-counters`"
+    );
+}
+
+#[test]
+fn canonical_measures() {
+    let mut config: Config = serde_json::from_str(
+        r#"{
+            "commands": {},
+            "counter-renames": { "cycles:k": "kernel-cycles" },
+            "measure-kinds": { "cpu_core/task-clock/": "time" },
+            "render-versus-self": {
+                "ng vs rs": {
+                    "level 1": { "measure": "cycles:k", "before": { "command": "ng", "index": 0 }, "after": { "command": "rs", "index": 0 } }
+                }
+            },
+            "render-versus-other": {
+                "compression": { "measure": "cpu_core/cycles/", "command": "compress", "rows": { "level 1": 0 } }
+            }
+        }"#,
+    )
+    .unwrap();
+    config.canonicalize_measures();
+
+    assert_eq!(config.render_versus_other["compression"].measure, "cycles");
+    assert_eq!(
+        config.render_versus_self["ng vs rs"].rows["level 1"].measure,
+        "kernel-cycles"
+    );
+    assert_eq!(
+        config.measure_kinds.keys().collect::<Vec<_>>(),
+        ["task-clock"]
+    );
+}
+
+#[test]
+fn remap_ids() {
+    let mut prev = testkit::BenchDataBuilder::cycles(
+        "1111111111111111111111111111111111111111",
+        &[("compress", &[("./c 1", 1000.0), ("./c 2", 1000.0)])],
+    )
+    .build();
+    prev.bench_groups["compress"][1].id = Some("level-2".to_owned());
+
+    prev.remap_ids(&[
+        ("./c 1".to_owned(), "level-1".to_owned()),
+        ("./c 2".to_owned(), "other".to_owned()),
+    ]);
+    assert_eq!(
+        prev.bench_groups["compress"][0].id.as_deref(),
+        Some("level-1")
+    );
+    // Results that already have an id keep it.
+    assert_eq!(
+        prev.bench_groups["compress"][1].id.as_deref(),
+        Some("level-2")
+    );
+}
@@ -0,0 +1,372 @@
+//! The theoretical limits of the measures of commands, like the minimum cycles per byte of a
+//! SIMD kernel on a class of machine, so a change can be read in the context of how close the
+//! command already is to what is possible:
+//!
+//! ```json
+//! "theoretical-limits": {
+//!   "adler32 avx2": {
+//!     "match": "adler32 --avx2",
+//!     "measure": "cycles",
+//!     "limits": { "amd-epyc-7763-64-core/4": 125000, "intel-xeon-platinum-8370c/4": 140000 }
+//!   }
+//! }
+//! ```
+//!
+//! A limit is for the command with the `id`, or for the commands whose command line contains
+//! `match`, in any group. Only the limit of the class of machine of the run counts, see
+//! [`crate::machine`]; without one for it, or when the class is unknown, the limit is ignored.
+//! The measure can be any counter of the results, including the derived ones like the
+//! normalized time or the hit rates, by its name after the `counter-renames`.
+//!
+//! The efficiency of a command is the limit divided by its value, or the value divided by the
+//! limit for the hit rates, of which higher is better, so 100% is at the limit. The rows of the
+//! raw and the comparison tables of the command show it, and the summary lists the commands
+//! furthest from their limits.
+
+use std::fmt::Write;
+
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+use crate::bench::SingleBench;
+use crate::cache_stats;
+use crate::command_display::CommandDisplay;
+use crate::BenchData;
+
+/// How many of the commands furthest from their limits the summary lists.
+const FURTHEST: usize = 5;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct LimitConfig {
+    #[serde(default)]
+    pub id: Option<String>,
+    /// A part of the command lines of the commands the limit is for.
+    #[serde(default, rename = "match")]
+    pub command_match: Option<String>,
+    pub measure: String,
+    /// The limit by class of machine.
+    pub limits: IndexMap<String, f64>,
+}
+
+/// How close the measure of a command is to its limit.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Efficiency {
+    /// The name of the limit.
+    pub name: String,
+    pub group: String,
+    /// The index of the command in its group.
+    pub index: usize,
+    pub command: String,
+    pub measure: String,
+    pub limit: f64,
+    pub value: f64,
+    pub percent: f64,
+}
+
+impl LimitConfig {
+    pub fn validate(&self, name: &str) -> Result<(), String> {
+        if self.id.is_some() == self.command_match.is_some() {
+            return Err(format!(
+                "the theoretical limit `{name}` needs either an `id` or a `match`"
+            ));
+        }
+        if let Some((class, limit)) = self
+            .limits
+            .iter()
+            .find(|(_, limit)| !(limit.is_finite() && **limit > 0.0))
+        {
+            return Err(format!(
+                "the theoretical limit `{name}` for `{class}` must be a positive number, got {limit}"
+            ));
+        }
+        Ok(())
+    }
+
+    fn applies_to(&self, bench: &SingleBench) -> bool {
+        match (&self.id, &self.command_match) {
+            (Some(id), _) => bench.id.as_ref() == Some(id),
+            (None, Some(part)) => bench.cmd.join(" ").contains(part.as_str()),
+            (None, None) => false,
+        }
+    }
+}
+
+/// The efficiency of `value` of `measure` versus `limit`, in percent. `None` for a value that
+/// isn't positive.
+pub fn efficiency_percent(measure: &str, limit: f64, value: f64) -> Option<f64> {
+    if value.is_nan() || value <= 0.0 {
+        return None;
+    }
+    Some(if cache_stats::is_hit_rate(measure) {
+        value / limit * 100.0
+    } else {
+        limit / value * 100.0
+    })
+}
+
+/// The efficiency of every command of `data` with a limit for its class of machine, by
+/// group and command in the order of the results.
+pub fn collect(limits: &IndexMap<String, LimitConfig>, data: &BenchData) -> Vec<Efficiency> {
+    let Some(class) = &data.machine_class else {
+        return vec![];
+    };
+    let mut efficiencies = vec![];
+    for (group_name, benches) in &data.bench_groups {
+        for (index, bench) in benches.iter().enumerate() {
+            for (name, config) in limits {
+                let Some(&limit) = config.limits.get(class) else {
+                    continue;
+                };
+                if !config.applies_to(bench) {
+                    continue;
+                }
+                let Some(counter) = bench.counters.get(&config.measure) else {
+                    continue;
+                };
+                let Some(percent) = efficiency_percent(&config.measure, limit, counter.value)
+                else {
+                    continue;
+                };
+                efficiencies.push(Efficiency {
+                    name: name.clone(),
+                    group: group_name.clone(),
+                    index,
+                    command: bench.cmd.join(" "),
+                    measure: config.measure.clone(),
+                    limit,
+                    value: counter.value,
+                    percent,
+                });
+            }
+        }
+    }
+    efficiencies
+}
+
+/// The `count` efficiencies furthest from their limits, furthest first.
+pub fn furthest(efficiencies: &[Efficiency], count: usize) -> Vec<&Efficiency> {
+    let mut furthest = efficiencies.iter().collect::<Vec<_>>();
+    furthest.sort_by(|a, b| {
+        a.percent
+            .total_cmp(&b.percent)
+            .then_with(|| a.group.cmp(&b.group))
+            .then_with(|| a.index.cmp(&b.index))
+    });
+    furthest.truncate(count);
+    furthest
+}
+
+/// The annotation of a row, like "_87.5% of the `cycles` limit_".
+pub fn annotation(efficiency: &Efficiency) -> String {
+    format!(
+        "_{:.1}% of the `{}` limit_",
+        efficiency.percent, efficiency.measure
+    )
+}
+
+/// The annotations of the rows of the command at `index` of the group, for the `measure` or
+/// for any measure.
+pub fn annotations<'a>(
+    efficiencies: &'a [Efficiency],
+    group_name: &'a str,
+    index: usize,
+    measure: Option<&'a str>,
+) -> impl Iterator<Item = String> + 'a {
+    efficiencies
+        .iter()
+        .filter(move |efficiency| {
+            efficiency.group == group_name
+                && efficiency.index == index
+                && measure.is_none_or(|measure| efficiency.measure == measure)
+        })
+        .map(annotation)
+}
+
+/// The table of the commands furthest from their limits.
+pub fn render_markdown(md: &mut String, efficiencies: &[Efficiency], commands: CommandDisplay) {
+    if efficiencies.is_empty() {
+        return;
+    }
+
+    writeln!(md, "### Furthest from the theoretical limits\n").unwrap();
+    writeln!(
+        md,
+        "| limit | command | measure | value | limit | efficiency |"
+    )
+    .unwrap();
+    writeln!(md, "| --- | --- | --- | --- | --- | --- |").unwrap();
+    let mut cells = commands.cells();
+    for efficiency in furthest(efficiencies, FURTHEST) {
+        writeln!(
+            md,
+            "| {} | {} | {} | `{}` | `{}` | {:.1}% |",
+            efficiency.name,
+            cells.cell(&efficiency.command),
+            efficiency.measure,
+            efficiency.value,
+            efficiency.limit,
+            efficiency.percent,
+        )
+        .unwrap();
+    }
+    cells.render_footnotes(md);
+    writeln!(md).unwrap();
+}
+
+#[cfg(test)]
+fn limits_for_test() -> IndexMap<String, LimitConfig> {
+    serde_json::from_str(
+        r#"{
+            "adler32 avx2": {
+                "match": "--avx2",
+                "measure": "cycles",
+                "limits": { "zen4/4": 100, "icelake/4": 120 }
+            },
+            "crc32 pclmul": {
+                "id": "crc32-pclmul",
+                "measure": "cycles-per-byte",
+                "limits": { "zen4/4": 0.25 }
+            },
+            "hot loop": {
+                "match": "./loop",
+                "measure": "L1-dcache-hit-rate",
+                "limits": { "zen4/4": 99.0 }
+            }
+        }"#,
+    )
+    .unwrap()
+}
+
+#[cfg(test)]
+fn data_for_test(machine_class: &str) -> BenchData {
+    crate::testkit::BenchDataBuilder::new("abc")
+        .machine_class(machine_class)
+        .group("checksum", |group| {
+            group
+                .bench(["./adler32", "--scalar"], |b| {
+                    b.counter("cycles", 400.0, 1.0, 20, "")
+                })
+                .bench(["./adler32", "--avx2"], |b| {
+                    b.counter("cycles", 125.0, 1.0, 20, "")
+                })
+                .bench(["./crc32", "--pclmul"], |b| {
+                    b.id("crc32-pclmul")
+                        .counter("cycles", 500.0, 1.0, 20, "")
+                        .counter("cycles-per-byte", 0.5, 0.0, 20, "")
+                })
+        })
+        .group("loops", |group| {
+            group
+                .bench(["./loop"], |b| {
+                    b.counter("L1-dcache-hit-rate", 94.05, 0.0, 20, "%")
+                })
+                .bench(["./adler32", "--avx2", "--unrolled"], |b| b)
+        })
+        .build()
+}
+
+#[test]
+fn resolve_limits() {
+    let limits = limits_for_test();
+    for (name, limit) in &limits {
+        limit.validate(name).unwrap();
+    }
+
+    // The limits of the class of the run, by id or by a part of the command line. The
+    // unrolled one has no cycles.
+    let efficiencies = collect(&limits, &data_for_test("zen4/4"));
+    assert_eq!(
+        efficiencies
+            .iter()
+            .map(|efficiency| (
+                efficiency.name.as_str(),
+                efficiency.group.as_str(),
+                efficiency.index,
+                efficiency.limit
+            ))
+            .collect::<Vec<_>>(),
+        [
+            ("adler32 avx2", "checksum", 1, 100.0),
+            ("crc32 pclmul", "checksum", 2, 0.25),
+            ("hot loop", "loops", 0, 99.0),
+        ]
+    );
+
+    // Another class has its own limits, and the others are ignored.
+    let efficiencies = collect(&limits, &data_for_test("icelake/4"));
+    assert_eq!(efficiencies.len(), 1);
+    assert_eq!(efficiencies[0].limit, 120.0);
+    assert!(collect(&limits, &data_for_test("graviton3/4")).is_empty());
+    let mut unknown = data_for_test("zen4/4");
+    unknown.machine_class = None;
+    assert!(collect(&limits, &unknown).is_empty());
+
+    let invalid = serde_json::from_str::<IndexMap<String, LimitConfig>>(
+        r#"{
+            "both": { "id": "a", "match": "b", "measure": "cycles", "limits": {} },
+            "zero": { "id": "a", "measure": "cycles", "limits": { "zen4/4": 0 } }
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(
+        invalid["both"].validate("both").unwrap_err(),
+        "the theoretical limit `both` needs either an `id` or a `match`"
+    );
+    assert_eq!(
+        invalid["zero"].validate("zero").unwrap_err(),
+        "the theoretical limit `zero` for `zen4/4` must be a positive number, got 0"
+    );
+}
+
+#[test]
+fn efficiency_by_direction() {
+    // Lower is better: the limit over the value.
+    assert_eq!(efficiency_percent("cycles", 100.0, 125.0), Some(80.0));
+    assert_eq!(efficiency_percent("cycles-per-byte", 0.25, 0.5), Some(50.0));
+    // Beating the limit shows above 100%, which hints at a wrong limit.
+    assert_eq!(efficiency_percent("cycles", 100.0, 80.0), Some(125.0));
+    // Higher is better for the hit rates: the value over the limit.
+    let hit_rate = efficiency_percent("L1-dcache-hit-rate", 99.0, 94.05).unwrap();
+    assert!((hit_rate - 95.0).abs() < 1e-9, "{hit_rate}");
+    assert_eq!(efficiency_percent("cycles", 100.0, 0.0), None);
+
+    let efficiencies = collect(&limits_for_test(), &data_for_test("zen4/4"));
+    assert_eq!(
+        annotations(&efficiencies, "checksum", 1, Some("cycles")).collect::<Vec<_>>(),
+        ["_80.0% of the `cycles` limit_"]
+    );
+    assert_eq!(
+        annotations(&efficiencies, "checksum", 2, None).collect::<Vec<_>>(),
+        ["_50.0% of the `cycles-per-byte` limit_"]
+    );
+    assert_eq!(
+        annotations(&efficiencies, "checksum", 2, Some("cycles")).count(),
+        0
+    );
+}
+
+#[test]
+fn rank_furthest_from_limits() {
+    let efficiencies = collect(&limits_for_test(), &data_for_test("zen4/4"));
+    let names = |count| {
+        furthest(&efficiencies, count)
+            .iter()
+            .map(|efficiency| efficiency.name.as_str())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(names(5), ["crc32 pclmul", "adler32 avx2", "hot loop"]);
+    assert_eq!(names(1), ["crc32 pclmul"]);
+
+    let mut md = String::new();
+    render_markdown(&mut md, &efficiencies, CommandDisplay::default());
+    assert_eq!(
+        md,
+        "### Furthest from the theoretical limits\n\n\
+         | limit | command | measure | value | limit | efficiency |\n\
+         | --- | --- | --- | --- | --- | --- |\n\
+         | crc32 pclmul | `./crc32 --pclmul` | cycles-per-byte | `0.5` | `0.25` | 50.0% |\n\
+         | adler32 avx2 | `./adler32 --avx2` | cycles | `125` | `100` | 80.0% |\n\
+         | hot loop | `./loop` | L1-dcache-hit-rate | `94.05` | `99` | 95.0% |\n\n"
+    );
+}
@@ -0,0 +1,442 @@
+//! The class of machine the benchmarks ran on. Hosted runner pools mix CPU generations, and
+//! cycles and times measured on one generation aren't comparable to those measured on another.
+//! Instruction counts mostly are.
+//!
+//! Every run also records a stable identifier of its machine, the hash of the architecture,
+//! the normalized CPU model and the number of physical cores, see [`machine_id`]. Kernel and
+//! microcode updates change the model string now and then, like its spacing, which doesn't
+//! make it another machine. Where the normalization isn't enough, like for a renamed runner,
+//! or for two identical machines that should count as one, `machine-aliases` maps the
+//! identifier or the raw CPU model of a machine to the identifier it should have:
+//!
+//! ```json
+//! "machine-aliases": { "Intel(R) Xeon(R) CPU E5-2690 v4 (rev 2)": "5c1b9e5a3d0f7f12" }
+//! ```
+
+use std::fmt::Write;
+use std::process::Command;
+
+use indexmap::IndexMap;
+use serde::Serialize;
+
+use crate::frequency::lscpu_fields;
+use crate::sha256::Sha256;
+
+pub fn default_machine_stable_counters() -> Vec<String> {
+    vec!["instructions".to_owned()]
+}
+
+/// The CPU model without what changes cosmetically: the frequency and the spacing, e.g.
+/// `Intel(R) Xeon(R) CPU E5-2690 v4` for `Intel(R) Xeon(R)  CPU E5-2690 v4 @ 2.60GHz`.
+pub fn normalize_model(cpu_model: &str) -> String {
+    let mut words = cpu_model
+        .split('@')
+        .next()
+        .unwrap()
+        .split_whitespace()
+        .collect::<Vec<_>>();
+    // Some models have the frequency without an `@`.
+    while words.last().is_some_and(|word| {
+        let word = word.to_ascii_lowercase();
+        word.strip_suffix("ghz")
+            .or_else(|| word.strip_suffix("mhz"))
+            .is_some_and(|number| number.parse::<f64>().is_ok())
+    }) {
+        words.pop();
+    }
+    words.join(" ")
+}
+
+/// The stable identifier of a machine: the start of the SHA-256 of the architecture, the
+/// normalized CPU model and the number of physical cores, when known.
+pub fn machine_id(arch: &str, cpu_model: &str, physical_cores: Option<usize>) -> String {
+    let mut sha256 = Sha256::default();
+    sha256.update(
+        format!(
+            "{arch}\n{}\n{}",
+            normalize_model(cpu_model),
+            physical_cores
+                .map(|cores| cores.to_string())
+                .unwrap_or_default()
+        )
+        .as_bytes(),
+    );
+    sha256.finish_hex()[..16].to_owned()
+}
+
+/// The identifier of the current machine with the `aliases` applied, see [`machine_id`].
+pub fn detect_id(aliases: &IndexMap<String, String>, arch: &str, cpu_model: &str) -> String {
+    let physical_cores = if cfg!(target_os = "linux") {
+        let lscpu = Command::new("lscpu").env("LANG", "C").arg("-J").output();
+        lscpu
+            .ok()
+            .and_then(|output| physical_cores_from_lscpu(&output.stdout))
+    } else {
+        None
+    };
+    resolve_alias(
+        aliases,
+        machine_id(arch, cpu_model, physical_cores),
+        cpu_model,
+    )
+}
+
+/// The identifier the `machine-aliases` give the machine with the `id` and the raw
+/// `cpu_model`, or else its own.
+pub fn resolve_alias(aliases: &IndexMap<String, String>, id: String, cpu_model: &str) -> String {
+    aliases
+        .get(&id)
+        .or_else(|| aliases.get(cpu_model))
+        .cloned()
+        .unwrap_or(id)
+}
+
+/// The number of physical cores from the output of `lscpu -J`, by socket or by cluster.
+pub fn physical_cores_from_lscpu(json: &[u8]) -> Option<usize> {
+    let fields = lscpu_fields(json);
+    let count = |field: &str| fields.get(field)?.parse::<usize>().ok();
+    count("Core(s) per socket")
+        .zip(count("Socket(s)"))
+        .or_else(|| count("Core(s) per cluster").zip(count("Cluster(s)")))
+        .map(|(cores, units)| cores * units)
+}
+
+/// What tells apart the machines results were measured on.
+#[derive(Debug, Clone, Copy)]
+pub struct MachineKey<'a> {
+    pub arch: &'a str,
+    pub os: &'a str,
+    pub cpu_model: &'a str,
+    pub machine_class: Option<&'a str>,
+    pub machine_id: Option<&'a str>,
+}
+
+/// Whether results were measured on the same kind of machine: the same identifier when both
+/// record theirs, otherwise the same class when both know theirs, otherwise the same
+/// normalized CPU model, for the results recorded before either.
+pub fn same_machine(a: MachineKey, b: MachineKey) -> bool {
+    if a.arch != b.arch || a.os != b.os {
+        return false;
+    }
+    match (a.machine_id, b.machine_id, a.machine_class, b.machine_class) {
+        (Some(a), Some(b), _, _) => a == b,
+        (_, _, Some(a), Some(b)) => a == b,
+        _ => normalize_model(a.cpu_model) == normalize_model(b.cpu_model),
+    }
+}
+
+/// The CPU model without decorations like the frequency, and the number of CPUs, e.g.
+/// `intel-xeon-platinum-8370c/4`. `None` when the model is unknown.
+pub fn machine_class(cpu_model: &str, cpus: usize) -> Option<String> {
+    let model = normalize_model(cpu_model)
+        .to_ascii_lowercase()
+        .replace("(r)", " ")
+        .replace("(tm)", " ");
+    let model = model
+        .split_whitespace()
+        .filter(|word| !matches!(*word, "cpu" | "processor") && !word.ends_with("-core"))
+        .map(|word| word.replace(|c: char| !c.is_ascii_alphanumeric() && c != '-', ""))
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+
+    if model.is_empty() || model == "unknown" {
+        return None;
+    }
+    Some(format!("{model}/{cpus}"))
+}
+
+/// The class from the output of `lscpu -J`.
+pub fn from_lscpu(json: &[u8]) -> Option<String> {
+    let fields = lscpu_fields(json);
+    machine_class(
+        fields.get("Model name")?,
+        fields.get("CPU(s)")?.parse().ok()?,
+    )
+}
+
+/// The class of the current machine, from `lscpu` or otherwise the CPU model the results
+/// record and the available parallelism.
+pub fn detect(cpu_model: &str) -> Option<String> {
+    if cfg!(target_os = "linux") {
+        let lscpu = Command::new("lscpu").env("LANG", "C").arg("-J").output();
+        if let Some(class) = lscpu.ok().and_then(|output| from_lscpu(&output.stdout)) {
+            return Some(class);
+        }
+    }
+
+    machine_class(cpu_model, std::thread::available_parallelism().ok()?.get())
+}
+
+/// The results being compared were measured on different classes of machines.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CrossClass {
+    pub before: String,
+    pub after: String,
+}
+
+/// `None` when both results are from the same class, or when the class of either is unknown,
+/// like for results recorded before the class was.
+pub fn cross_class(before: Option<&str>, after: Option<&str>) -> Option<CrossClass> {
+    match (before, after) {
+        (Some(before), Some(after)) if before != after => Some(CrossClass {
+            before: before.to_owned(),
+            after: after.to_owned(),
+        }),
+        _ => None,
+    }
+}
+
+/// Whether `counter` is one of the `machine-stable-counters`, also under the name perf uses
+/// on hybrid CPUs.
+pub fn is_machine_stable(stable_counters: &[String], counter: &str) -> bool {
+    let counter = counter
+        .strip_prefix("cpu_core/")
+        .and_then(|counter| counter.strip_suffix('/'))
+        .unwrap_or(counter);
+    stable_counters.iter().any(|stable| stable == counter)
+}
+
+/// Explain why only some counters are compared.
+pub fn render_markdown_note(
+    md: &mut String,
+    cross_class: Option<&CrossClass>,
+    stable_counters: &[String],
+) {
+    let Some(cross_class) = cross_class else {
+        return;
+    };
+
+    let counters = stable_counters
+        .iter()
+        .map(|counter| format!("`{counter}`"))
+        .collect::<Vec<_>>();
+    let compared = if counters.is_empty() {
+        "nothing is compared against it".to_owned()
+    } else {
+        format!("only {} are compared", counters.join(", "))
+    };
+    writeln!(
+        md,
+        "> [!NOTE]\n> The parent ran on a different class of machine, `{}` rather than `{}`. \
+         Cycles and times depend on the machine, so {compared}.\n",
+        cross_class.before, cross_class.after,
+    )
+    .unwrap();
+}
+
+#[test]
+fn machine_class_of_azure_skus() {
+    use crate::frequency::lscpu_fixture;
+
+    let class = |name| from_lscpu(&lscpu_fixture(name));
+    assert_eq!(
+        class("azure-dsv5.json").as_deref(),
+        Some("intel-xeon-platinum-8370c/4")
+    );
+    assert_eq!(
+        class("azure-dsv4.json").as_deref(),
+        Some("intel-xeon-platinum-8272cl/2")
+    );
+    assert_eq!(
+        class("azure-dsv2.json").as_deref(),
+        Some("intel-xeon-e5-2673-v4/2")
+    );
+    assert_eq!(
+        class("azure-dasv5.json").as_deref(),
+        Some("amd-epyc-7763/4")
+    );
+
+    // Older lscpu output, and other vendors.
+    assert_eq!(
+        class("intel-coffee-lake.json").as_deref(),
+        Some("intel-core-i7-8700/12")
+    );
+    assert_eq!(
+        class("amd-zen3.json").as_deref(),
+        Some("amd-ryzen-9-5950x/32")
+    );
+    assert_eq!(
+        class("arm-neoverse-n1.json").as_deref(),
+        Some("neoverse-n1/4")
+    );
+
+    assert_eq!(machine_class("unknown", 4), None);
+    assert_eq!(machine_class("", 4), None);
+    assert_eq!(from_lscpu(b"not json"), None);
+}
+
+#[test]
+fn normalize_model_strings() {
+    let variants = std::fs::read_to_string(
+        std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/cpu-models/variants.txt"),
+    )
+    .unwrap();
+    let paragraphs = variants
+        .split("\n\n")
+        .map(|paragraph| {
+            paragraph
+                .lines()
+                .filter(|line| !line.starts_with('#'))
+                .collect::<Vec<_>>()
+        })
+        .filter(|lines| !lines.is_empty())
+        .collect::<Vec<_>>();
+    assert_eq!(paragraphs.len(), 12);
+
+    let mut ids = vec![];
+    for lines in &paragraphs {
+        let (normalized, variants) = lines.split_first().unwrap();
+        assert!(!variants.is_empty(), "{normalized}");
+        for variant in variants {
+            assert_eq!(normalize_model(variant), *normalized, "{variant:?}");
+            assert_eq!(
+                machine_id("X64", variant, Some(4)),
+                machine_id("X64", normalized, Some(4))
+            );
+        }
+        ids.push(machine_id("X64", normalized, Some(4)));
+    }
+    // Every CPU keeps an identifier of its own.
+    let mut distinct = ids.clone();
+    distinct.dedup();
+    assert_eq!(distinct.len(), ids.len());
+
+    // The architecture and the cores tell machines with the same CPU apart.
+    let id = machine_id("X64", "Apple M1", Some(8));
+    assert_eq!(id.len(), 16);
+    assert_ne!(machine_id("ARM64", "Apple M1", Some(8)), id);
+    assert_ne!(machine_id("X64", "Apple M1", Some(4)), id);
+    assert_ne!(machine_id("X64", "Apple M1", None), id);
+
+    // The class of machine is derived from the normalized model too.
+    assert_eq!(
+        machine_class("Intel(R) Xeon(R)  CPU E5-2690 v4 @ 2.60GHz", 4),
+        machine_class("Intel(R) Xeon(R) CPU E5-2690 v4", 4)
+    );
+    assert_eq!(
+        machine_class("Intel(R) Pentium(R) 4 CPU 3.00GHz", 1).as_deref(),
+        Some("intel-pentium-4/1")
+    );
+}
+
+#[test]
+fn physical_cores_and_aliases() {
+    use crate::frequency::lscpu_fixture;
+
+    let cores = |name| physical_cores_from_lscpu(&lscpu_fixture(name));
+    assert_eq!(cores("azure-dsv5.json"), Some(2));
+    assert_eq!(cores("amd-zen3.json"), Some(16));
+    assert_eq!(cores("arm-neoverse-n1.json"), Some(4));
+    assert_eq!(cores("intel-xeon-vm.json"), None);
+
+    let id = machine_id(
+        "X64",
+        "Intel(R) Xeon(R) Platinum 8370C CPU @ 2.80GHz",
+        Some(2),
+    );
+    let aliases = IndexMap::from([
+        (id.clone(), "pool".to_owned()),
+        (
+            "AMD EPYC 7763 64-Core Processor".to_owned(),
+            "pool".to_owned(),
+        ),
+    ]);
+    assert_eq!(resolve_alias(&aliases, id, "anything"), "pool");
+    assert_eq!(
+        resolve_alias(
+            &aliases,
+            "0123".to_owned(),
+            "AMD EPYC 7763 64-Core Processor"
+        ),
+        "pool"
+    );
+    assert_eq!(
+        resolve_alias(&aliases, "0123".to_owned(), "AMD EPYC 7763"),
+        "0123"
+    );
+}
+
+#[test]
+fn match_machines() {
+    let key = |cpu_model, machine_class, machine_id| MachineKey {
+        arch: "X64",
+        os: "Linux",
+        cpu_model,
+        machine_class,
+        machine_id,
+    };
+    let old = "Intel(R) Xeon(R) CPU E5-2690 v4 @ 2.60GHz";
+    let new = "Intel(R) Xeon(R)  CPU E5-2690 v4 @ 2.60GHz";
+
+    // The identifiers when both have them, whatever the model strings.
+    assert!(same_machine(
+        key(old, None, Some("a")),
+        key(new, None, Some("a"))
+    ));
+    assert!(!same_machine(
+        key(old, None, Some("a")),
+        key(old, None, Some("b"))
+    ));
+    // The results from before the identifiers by their classes, or their normalized models.
+    let class = Some("intel-xeon-e5-2690-v4/4");
+    assert!(same_machine(
+        key(old, class, None),
+        key(new, class, Some("a"))
+    ));
+    assert!(!same_machine(
+        key(old, class, None),
+        key(new, Some("intel-xeon-e5-2690-v4/8"), Some("a"))
+    ));
+    assert!(same_machine(
+        key(old, None, None),
+        key(new, class, Some("a"))
+    ));
+    assert!(!same_machine(
+        key(old, None, None),
+        key(
+            "Intel(R) Xeon(R) CPU E5-2673 v4 @ 2.30GHz",
+            class,
+            Some("a")
+        )
+    ));
+    assert!(!same_machine(
+        MachineKey {
+            arch: "ARM64",
+            ..key(old, None, Some("a"))
+        },
+        key(old, None, Some("a"))
+    ));
+}
+
+#[test]
+fn cross_class_comparisons() {
+    let dsv5 = Some("intel-xeon-platinum-8370c/4");
+    let dasv5 = Some("amd-epyc-7763/4");
+
+    assert_eq!(cross_class(dsv5, dsv5), None);
+    assert_eq!(
+        cross_class(dsv5, dasv5),
+        Some(CrossClass {
+            before: "intel-xeon-platinum-8370c/4".to_owned(),
+            after: "amd-epyc-7763/4".to_owned(),
+        })
+    );
+    assert_eq!(cross_class(None, dasv5), None);
+    assert_eq!(cross_class(dsv5, None), None);
+
+    let stable = default_machine_stable_counters();
+    assert!(is_machine_stable(&stable, "instructions"));
+    assert!(is_machine_stable(&stable, "cpu_core/instructions/"));
+    assert!(!is_machine_stable(&stable, "cycles"));
+    assert!(!is_machine_stable(&stable, "task-clock"));
+
+    let mut md = String::new();
+    render_markdown_note(&mut md, cross_class(dsv5, dasv5).as_ref(), &stable);
+    assert_eq!(
+        md,
+        "> [!NOTE]\n> The parent ran on a different class of machine, `intel-xeon-platinum-8370c/4` \
+         rather than `amd-epyc-7763/4`. Cycles and times depend on the machine, so only \
+         `instructions` are compared.\n\n"
+    );
+}
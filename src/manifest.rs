@@ -0,0 +1,207 @@
+//! Reading the package version from a `Cargo.toml`.
+//!
+//! This is not a TOML parser. It understands just enough of the format to find the `version`
+//! key of the `[package]` and `[workspace.package]` tables, which keeps it cheap to run
+//! before every benchmark.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The version of the package defined by the manifest at `manifest_path`, following
+/// `version.workspace = true` to the workspace root. `None` when the manifest is missing or
+/// doesn't define a version.
+pub fn package_version(manifest_path: &Path) -> Option<String> {
+    let contents = fs::read_to_string(manifest_path).ok()?;
+    let package = parse_table(&contents, "package")?;
+
+    match &package.iter().find(|(key, _)| key == "version")?.1 {
+        Value::String(version) => Some(version.clone()),
+        Value::Workspace => {
+            let explicit_root = package.iter().find_map(|(key, value)| match value {
+                Value::String(path) if key == "workspace" => Some(path.clone()),
+                _ => None,
+            });
+
+            let workspace_manifest = match explicit_root {
+                Some(path) => manifest_path.parent()?.join(path).join("Cargo.toml"),
+                None => find_workspace_root(manifest_path)?,
+            };
+
+            let contents = fs::read_to_string(workspace_manifest).ok()?;
+            match parse_table(&contents, "workspace.package")?
+                .into_iter()
+                .find(|(key, _)| key == "version")?
+                .1
+            {
+                Value::String(version) => Some(version),
+                _ => None,
+            }
+        }
+        Value::Other => None,
+    }
+}
+
+/// Cargo looks for the workspace root in the parent directories of a package.
+fn find_workspace_root(manifest_path: &Path) -> Option<PathBuf> {
+    let package_dir = manifest_path.parent()?;
+    for dir in package_dir.ancestors().skip(1) {
+        let candidate = dir.join("Cargo.toml");
+        if let Ok(contents) = fs::read_to_string(&candidate) {
+            if parse_table(&contents, "workspace").is_some() {
+                return Some(candidate);
+            }
+        }
+    }
+
+    None
+}
+
+#[derive(Debug, PartialEq)]
+enum Value {
+    String(String),
+    /// `key.workspace = true` or `key = { workspace = true }`
+    Workspace,
+    /// Anything we don't need to understand.
+    Other,
+}
+
+/// The keys of the given table, or `None` when the table doesn't exist or the manifest is
+/// malformed.
+fn parse_table(contents: &str, table: &str) -> Option<Vec<(String, Value)>> {
+    let mut current_table = None;
+    let mut found = false;
+    let mut keys = vec![];
+
+    for line in contents.lines() {
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(header) = line.strip_prefix('[') {
+            // `[[bin]]` and friends are never the table we are looking for.
+            let header = header.strip_suffix(']')?.trim();
+            current_table = Some(header.to_owned());
+            found |= header == table;
+            continue;
+        }
+
+        if current_table.as_deref() != Some(table) {
+            continue;
+        }
+
+        // Continuation lines of multi-line arrays have no key.
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let key = key.trim();
+        let value = value.trim();
+
+        if let Some(key) = key.strip_suffix(".workspace") {
+            let value = if value == "true" {
+                Value::Workspace
+            } else {
+                Value::Other
+            };
+            keys.push((key.trim().to_owned(), value));
+        } else {
+            keys.push((key.to_owned(), parse_value(value)));
+        }
+    }
+
+    found.then_some(keys)
+}
+
+fn parse_value(value: &str) -> Value {
+    for quote in ['"', '\''] {
+        if let Some(string) = value
+            .strip_prefix(quote)
+            .and_then(|value| value.strip_suffix(quote))
+        {
+            return Value::String(string.to_owned());
+        }
+    }
+
+    if let Some(inline_table) = value
+        .strip_prefix('{')
+        .and_then(|value| value.strip_suffix('}'))
+    {
+        let is_workspace = inline_table.split(',').any(|entry| {
+            entry
+                .split_once('=')
+                .is_some_and(|(key, value)| key.trim() == "workspace" && value.trim() == "true")
+        });
+        if is_workspace {
+            return Value::Workspace;
+        }
+    }
+
+    Value::Other
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    for (i, c) in line.char_indices() {
+        match (c, quote) {
+            ('"' | '\'', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            ('#', None) => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+#[cfg(test)]
+fn fixture(path: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("testdata/manifests")
+        .join(path)
+}
+
+#[test]
+fn version_of_simple_package() {
+    assert_eq!(
+        package_version(&fixture("simple/Cargo.toml")).as_deref(),
+        Some("0.4.1")
+    );
+}
+
+#[test]
+fn version_inherited_from_workspace() {
+    assert_eq!(
+        package_version(&fixture("workspace/crates/member/Cargo.toml")).as_deref(),
+        Some("0.4.0")
+    );
+    assert_eq!(
+        package_version(&fixture("explicit/member/Cargo.toml")).as_deref(),
+        Some("1.2.3-beta.1")
+    );
+}
+
+#[test]
+fn version_missing() {
+    // The workspace doesn't define a version to inherit.
+    assert_eq!(
+        package_version(&fixture("virtual/crates/a/Cargo.toml")),
+        None
+    );
+    // A virtual manifest has no package.
+    assert_eq!(package_version(&fixture("virtual/Cargo.toml")), None);
+    assert_eq!(package_version(&fixture("broken/Cargo.toml")), None);
+    assert_eq!(package_version(&fixture("does-not-exist/Cargo.toml")), None);
+}
+
+#[test]
+fn parse_manifest_values() {
+    assert_eq!(parse_value(r#""1.0.0""#), Value::String("1.0.0".to_owned()));
+    assert_eq!(parse_value("'1.0.0'"), Value::String("1.0.0".to_owned()));
+    assert_eq!(parse_value("{ workspace = true }"), Value::Workspace);
+    assert_eq!(parse_value("{ workspace = false }"), Value::Other);
+    assert_eq!(parse_value("3"), Value::Other);
+
+    assert_eq!(
+        strip_comment(r#"version = "1#2" # comment"#),
+        r#"version = "1#2" "#
+    );
+}
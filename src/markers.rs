@@ -0,0 +1,221 @@
+//! The markers of the changes in the tables, e.g. for tooling that strips emoji:
+//!
+//! ```json
+//! "markers": { "improvement": "✅", "regression": "❌" }
+//! ```
+//!
+//! A table that marks a significant change, or an unreliable measurement, is followed by a
+//! legend of the markers it uses.
+
+use std::collections::BTreeSet;
+
+use serde::Deserialize;
+
+/// The longest marker, in characters.
+const MAX_LEN: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Markers {
+    #[serde(default = "default_improvement")]
+    pub improvement: String,
+    #[serde(default = "default_regression")]
+    pub regression: String,
+    /// Changes that aren't significant, or below the `minimum-effect-percent`.
+    #[serde(default)]
+    pub neutral: String,
+    /// Groups whose measurements vary too much, see [`crate::quality`].
+    #[serde(default = "default_unreliable")]
+    pub unreliable: String,
+}
+
+fn default_improvement() -> String {
+    "🚀".to_owned()
+}
+
+fn default_regression() -> String {
+    "💩".to_owned()
+}
+
+fn default_unreliable() -> String {
+    "⚠️".to_owned()
+}
+
+impl Default for Markers {
+    fn default() -> Self {
+        Markers {
+            improvement: default_improvement(),
+            regression: default_regression(),
+            neutral: String::new(),
+            unreliable: default_unreliable(),
+        }
+    }
+}
+
+/// What a marker means.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Marker {
+    Improvement,
+    Regression,
+    Neutral,
+    Unreliable,
+    /// The output of a compared command differed between its repetitions, shown with the
+    /// `unreliable` marker.
+    NondeterministicOutput,
+    /// Too many repetitions of a compared command were disturbed by other processes, shown
+    /// with the `unreliable` marker.
+    Preempted,
+}
+
+impl Marker {
+    /// The marker of a change: whether it is actionable, see
+    /// [`crate::compare::ComparisonRow::is_actionable`], and whether it is for the worse.
+    pub fn of_change(actionable: bool, worse: bool) -> Self {
+        match (actionable, worse) {
+            (true, true) => Marker::Regression,
+            (true, false) => Marker::Improvement,
+            (false, _) => Marker::Neutral,
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            Marker::Improvement => "significant improvement",
+            Marker::Regression => "significant regression",
+            Marker::Neutral => "no significant change",
+            Marker::Unreliable => "unreliable measurements",
+            Marker::NondeterministicOutput => "output differed between repetitions",
+            Marker::Preempted => "too many repetitions preempted",
+        }
+    }
+}
+
+impl Markers {
+    /// Check what the types of the config can't express.
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, marker) in [
+            ("improvement", &self.improvement),
+            ("regression", &self.regression),
+            ("neutral", &self.neutral),
+            ("unreliable", &self.unreliable),
+        ] {
+            if marker.chars().count() > MAX_LEN || marker.contains(['|', '`', '\n']) {
+                return Err(format!(
+                    "the `{name}` marker `{marker}` must be at most {MAX_LEN} characters, without `|`, backticks or newlines"
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, marker: Marker) -> &str {
+        match marker {
+            Marker::Improvement => &self.improvement,
+            Marker::Regression => &self.regression,
+            Marker::Neutral => &self.neutral,
+            Marker::Unreliable | Marker::NondeterministicOutput | Marker::Preempted => {
+                &self.unreliable
+            }
+        }
+    }
+
+    /// The marker of a change, padded to the width of the widest marker of a change, so the
+    /// deltas after it line up.
+    pub fn padded(&self, marker: Marker) -> String {
+        let widest = [Marker::Improvement, Marker::Regression, Marker::Neutral]
+            .map(|marker| width(self.get(marker)))
+            .into_iter()
+            .max()
+            .unwrap_or_default();
+        let marker = self.get(marker);
+        format!("{marker}{}", " ".repeat(widest - width(marker)))
+    }
+
+    /// The legend of the `used` markers, one line, or `None` when they don't mark anything:
+    /// only neutral changes, or empty markers.
+    pub fn legend(&self, used: impl IntoIterator<Item = Marker>) -> Option<String> {
+        let used = used.into_iter().collect::<BTreeSet<_>>();
+        if used.iter().all(|&marker| marker == Marker::Neutral) {
+            return None;
+        }
+        let entries = used
+            .into_iter()
+            .filter(|&marker| !self.get(marker).is_empty())
+            .map(|marker| format!("{} {}", self.get(marker), marker.description()))
+            .collect::<Vec<_>>();
+        (!entries.is_empty()).then(|| entries.join(" · "))
+    }
+}
+
+/// The width of `marker` in a monospace font, roughly: emoji take two columns, variation
+/// selectors none.
+fn width(marker: &str) -> usize {
+    marker
+        .chars()
+        .map(|c| match c {
+            '\u{fe00}'..='\u{fe0f}' | '\u{200d}' => 0,
+            '\u{2600}'..='\u{27bf}' | '\u{1f000}'.. => 2,
+            _ => 1,
+        })
+        .sum()
+}
+
+#[test]
+fn default_markers() {
+    let markers = Markers::default();
+    assert_eq!(serde_json::from_str::<Markers>("{}").unwrap(), markers);
+    markers.validate().unwrap();
+
+    assert_eq!(markers.get(Marker::of_change(true, true)), "💩");
+    assert_eq!(markers.get(Marker::of_change(true, false)), "🚀");
+    assert_eq!(markers.get(Marker::of_change(false, true)), "");
+    // The neutral marker is as wide as the emoji.
+    assert_eq!(markers.padded(Marker::Improvement), "🚀");
+    assert_eq!(markers.padded(Marker::Neutral), "  ");
+
+    assert_eq!(
+        markers.legend([Marker::Neutral, Marker::Regression, Marker::Improvement]),
+        Some("🚀 significant improvement · 💩 significant regression".to_owned())
+    );
+    assert_eq!(
+        markers.legend([Marker::Unreliable]),
+        Some("⚠️ unreliable measurements".to_owned())
+    );
+    // Only neutral changes, or nothing at all.
+    assert_eq!(markers.legend([Marker::Neutral, Marker::Neutral]), None);
+    assert_eq!(markers.legend([]), None);
+}
+
+#[test]
+fn custom_markers() {
+    let markers: Markers = serde_json::from_str(
+        r#"{ "improvement": "[+]", "regression": "[-]", "neutral": "-", "unreliable": "(!)" }"#,
+    )
+    .unwrap();
+    markers.validate().unwrap();
+    assert_eq!(markers.padded(Marker::Regression), "[-]");
+    assert_eq!(markers.padded(Marker::Neutral), "-  ");
+    assert_eq!(
+        markers.legend([Marker::Regression, Marker::Neutral]),
+        Some("[-] significant regression · - no significant change".to_owned())
+    );
+    assert_eq!(markers.legend([Marker::Neutral]), None);
+
+    // Empty markers have nothing to explain.
+    let markers: Markers =
+        serde_json::from_str(r#"{ "improvement": "", "regression": "" }"#).unwrap();
+    assert_eq!(markers.padded(Marker::Regression), "");
+    assert_eq!(markers.legend([Marker::Regression]), None);
+
+    for marker in ["|", "a`b", "too long!"] {
+        let markers = Markers {
+            regression: marker.to_owned(),
+            ..Markers::default()
+        };
+        assert_eq!(
+            markers.validate().unwrap_err(),
+            format!("the `regression` marker `{marker}` must be at most 8 characters, without `|`, backticks or newlines")
+        );
+    }
+    assert!(serde_json::from_str::<Markers>(r#"{ "other": "x" }"#).is_err());
+}
@@ -0,0 +1,310 @@
+//! Matrix commands, for the commands that only differ in a few parameters, like the level and
+//! the corpus of a compressor:
+//!
+//! ```json
+//! {
+//!   "template": "target/release/{impl} {level} bench-data/{corpus}",
+//!   "matrix": { "impl": ["compress-ng", "compress-rs"], "level": [1, 6, 9], "corpus": ["silesia.tar", "enwik8"] },
+//!   "exclude": [{ "impl": "compress-ng", "level": 9 }]
+//! }
+//! ```
+//!
+//! The entry takes the place of a command for every combination of the parameters, when the
+//! config is loaded, so everything that checks the commands sees them. The first parameter
+//! varies the slowest, like nested loops in the order of the parameters, and the values in
+//! their order: `compress-ng 1 silesia.tar`, `compress-ng 1 enwik8`, `compress-ng 6
+//! silesia.tar`, and so on. Every `{<parameter>}` of the template is replaced by its value,
+//! other braces are left alone.
+//!
+//! Every command gets the id of its values joined by `/`, like `compress-rs/6/enwik8`, so it
+//! keeps matching its previous results when the template changes, and the ids of the other
+//! commands must differ from them. An `exclude` entry drops the combinations with all of its
+//! values, so `{ "impl": "compress-ng", "level": 9 }` drops one command per corpus.
+
+use indexmap::IndexMap;
+use serde_json::Value;
+
+/// An entry of a group that expands into a command for every combination of its parameters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Matrix {
+    pub template: String,
+    pub parameters: IndexMap<String, Vec<Value>>,
+    pub exclude: Vec<IndexMap<String, Value>>,
+}
+
+/// A command of a matrix.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cell {
+    pub id: String,
+    pub command: String,
+}
+
+/// The value of a parameter, as it is put in the template and the id.
+fn value_text(parameter: &str, value: &Value) -> Result<String, String> {
+    match value {
+        Value::String(value) if !value.is_empty() => Ok(value.clone()),
+        Value::Number(value) => Ok(value.to_string()),
+        Value::Bool(value) => Ok(value.to_string()),
+        _ => Err(format!(
+            "the values of the matrix parameter `{parameter}` must be non-empty strings, numbers or booleans, got `{value}`"
+        )),
+    }
+}
+
+impl Matrix {
+    /// The commands, in the order of the combinations, see [the module docs](self).
+    pub fn expand(&self) -> Result<Vec<Cell>, String> {
+        let template = &self.template;
+        if self.parameters.is_empty() {
+            return Err(format!("the matrix of `{template}` has no parameters"));
+        }
+        let mut parameters = vec![];
+        for (name, values) in &self.parameters {
+            if !template.contains(&format!("{{{name}}}")) {
+                return Err(format!(
+                    "the matrix parameter `{name}` isn't used in `{template}`"
+                ));
+            }
+            if values.is_empty() {
+                return Err(format!(
+                    "the matrix parameter `{name}` of `{template}` has no values"
+                ));
+            }
+            let mut texts = Vec::<String>::new();
+            for value in values {
+                let text = value_text(name, value)?;
+                if texts.contains(&text) {
+                    return Err(format!(
+                        "the matrix parameter `{name}` of `{template}` has the value `{text}` more than once"
+                    ));
+                }
+                texts.push(text);
+            }
+            parameters.push((name, texts));
+        }
+
+        let mut exclude = vec![];
+        for combination in &self.exclude {
+            if combination.is_empty() {
+                return Err(format!(
+                    "an `exclude` entry of the matrix of `{template}` is empty, which would exclude everything"
+                ));
+            }
+            let mut excluded = vec![];
+            for (name, value) in combination {
+                let Some(position) = parameters.iter().position(|(other, _)| *other == name) else {
+                    return Err(format!(
+                        "`exclude` refers to the matrix parameter `{name}`, which `{template}` doesn't have"
+                    ));
+                };
+                let text = value_text(name, value)?;
+                if !parameters[position].1.contains(&text) {
+                    return Err(format!(
+                        "`exclude` refers to the value `{text}` of the matrix parameter `{name}`, which it doesn't have"
+                    ));
+                }
+                excluded.push((position, text));
+            }
+            exclude.push(excluded);
+        }
+
+        let mut cells = vec![];
+        // The index of the value of every parameter, the last one counting up the fastest.
+        let mut indices = vec![0; parameters.len()];
+        loop {
+            let values = indices
+                .iter()
+                .zip(&parameters)
+                .map(|(&index, (_, texts))| texts[index].as_str())
+                .collect::<Vec<_>>();
+            let excluded = exclude.iter().any(|excluded| {
+                excluded
+                    .iter()
+                    .all(|(position, text)| values[*position] == text)
+            });
+            if !excluded {
+                let mut command = template.clone();
+                for ((name, _), value) in parameters.iter().zip(&values) {
+                    command = command.replace(&format!("{{{name}}}"), value);
+                }
+                cells.push(Cell {
+                    id: values.join("/"),
+                    command,
+                });
+            }
+
+            let Some(position) = (0..indices.len())
+                .rev()
+                .find(|&position| indices[position] + 1 < parameters[position].1.len())
+            else {
+                break;
+            };
+            indices[position] += 1;
+            indices[position + 1..].fill(0);
+        }
+        if cells.is_empty() {
+            return Err(format!("`exclude` excludes every command of `{template}`"));
+        }
+        Ok(cells)
+    }
+}
+
+#[cfg(test)]
+fn matrix(json: Value) -> Matrix {
+    Matrix {
+        template: json["template"].as_str().unwrap().to_owned(),
+        parameters: serde_json::from_value(json["matrix"].clone()).unwrap(),
+        exclude: serde_json::from_value(json.get("exclude").cloned().unwrap_or_default())
+            .unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+fn ids(cells: &[Cell]) -> Vec<&str> {
+    cells.iter().map(|cell| cell.id.as_str()).collect()
+}
+
+#[test]
+fn expand_matrix() {
+    let cells = matrix(serde_json::json!({
+        "template": "target/release/{impl} {level} bench-data/{corpus}",
+        "matrix": {
+            "impl": ["compress-ng", "compress-rs"],
+            "level": [1, 6, 9],
+            "corpus": ["silesia.tar", "enwik8"]
+        }
+    }))
+    .expand()
+    .unwrap();
+    assert_eq!(cells.len(), 12);
+    assert_eq!(
+        cells[..3],
+        [
+            Cell {
+                id: "compress-ng/1/silesia.tar".to_owned(),
+                command: "target/release/compress-ng 1 bench-data/silesia.tar".to_owned(),
+            },
+            Cell {
+                id: "compress-ng/1/enwik8".to_owned(),
+                command: "target/release/compress-ng 1 bench-data/enwik8".to_owned(),
+            },
+            Cell {
+                id: "compress-ng/6/silesia.tar".to_owned(),
+                command: "target/release/compress-ng 6 bench-data/silesia.tar".to_owned(),
+            },
+        ]
+    );
+    assert_eq!(
+        ids(&cells[6..]),
+        [
+            "compress-rs/1/silesia.tar",
+            "compress-rs/1/enwik8",
+            "compress-rs/6/silesia.tar",
+            "compress-rs/6/enwik8",
+            "compress-rs/9/silesia.tar",
+            "compress-rs/9/enwik8",
+        ]
+    );
+
+    // A single parameter, used more than once, next to braces that aren't parameters.
+    let cells = matrix(serde_json::json!({
+        "template": "sh -c 'f() { ./bench {n} {n}; }; f'",
+        "matrix": { "n": [1.5, "x"] }
+    }))
+    .expand()
+    .unwrap();
+    assert_eq!(
+        cells,
+        [
+            Cell {
+                id: "1.5".to_owned(),
+                command: "sh -c 'f() { ./bench 1.5 1.5; }; f'".to_owned(),
+            },
+            Cell {
+                id: "x".to_owned(),
+                command: "sh -c 'f() { ./bench x x; }; f'".to_owned(),
+            },
+        ]
+    );
+}
+
+#[test]
+fn exclude_from_matrix() {
+    let cells = matrix(serde_json::json!({
+        "template": "./{impl} {level} {corpus}",
+        "matrix": {
+            "impl": ["ng", "rs"],
+            "level": [1, 6, 9],
+            "corpus": ["silesia.tar", "enwik8"]
+        },
+        "exclude": [
+            { "impl": "ng", "level": 9 },
+            { "impl": "rs", "level": "1", "corpus": "enwik8" }
+        ]
+    }))
+    .expand()
+    .unwrap();
+    assert_eq!(
+        ids(&cells),
+        [
+            "ng/1/silesia.tar",
+            "ng/1/enwik8",
+            "ng/6/silesia.tar",
+            "ng/6/enwik8",
+            "rs/1/silesia.tar",
+            "rs/6/silesia.tar",
+            "rs/6/enwik8",
+            "rs/9/silesia.tar",
+            "rs/9/enwik8",
+        ]
+    );
+
+    for (json, err) in [
+        (
+            serde_json::json!({ "template": "./a {x}", "matrix": { "x": [1] }, "exclude": [{ "y": 1 }] }),
+            "`exclude` refers to the matrix parameter `y`, which `./a {x}` doesn't have",
+        ),
+        (
+            serde_json::json!({ "template": "./a {x}", "matrix": { "x": [1] }, "exclude": [{ "x": 2 }] }),
+            "`exclude` refers to the value `2` of the matrix parameter `x`, which it doesn't have",
+        ),
+        (
+            serde_json::json!({ "template": "./a {x}", "matrix": { "x": [1] }, "exclude": [{}] }),
+            "an `exclude` entry of the matrix of `./a {x}` is empty, which would exclude everything",
+        ),
+        (
+            serde_json::json!({ "template": "./a {x}", "matrix": { "x": [1] }, "exclude": [{ "x": 1 }] }),
+            "`exclude` excludes every command of `./a {x}`",
+        ),
+    ] {
+        assert_eq!(matrix(json).expand().unwrap_err(), err);
+    }
+}
+
+#[test]
+fn invalid_matrix() {
+    for (json, err) in [
+        (
+            serde_json::json!({ "template": "./a", "matrix": {} }),
+            "the matrix of `./a` has no parameters",
+        ),
+        (
+            serde_json::json!({ "template": "./a {x}", "matrix": { "x": [1], "y": [2] } }),
+            "the matrix parameter `y` isn't used in `./a {x}`",
+        ),
+        (
+            serde_json::json!({ "template": "./a {x}", "matrix": { "x": [] } }),
+            "the matrix parameter `x` of `./a {x}` has no values",
+        ),
+        (
+            serde_json::json!({ "template": "./a {x}", "matrix": { "x": [6, "6"] } }),
+            "the matrix parameter `x` of `./a {x}` has the value `6` more than once",
+        ),
+        (
+            serde_json::json!({ "template": "./a {x}", "matrix": { "x": [[6]] } }),
+            "the values of the matrix parameter `x` must be non-empty strings, numbers or booleans, got `[6]`",
+        ),
+    ] {
+        assert_eq!(matrix(json).expand().unwrap_err(), err);
+    }
+}
@@ -0,0 +1,117 @@
+//! How the change of a measure is computed and shown, depending on what the measure is.
+
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+use crate::bench::BenchCounter;
+use crate::{cache_stats, mix};
+
+/// Configured per measure with `measure-kinds`. Measures that aren't configured are counts,
+/// except for the derived `branch-miss-rate` and hit rates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MeasureKind {
+    /// Something that is counted, like cycles or instructions.
+    #[default]
+    Count,
+    Time,
+    /// A ratio of two counts, like instructions per cycle. The change is shown as the absolute
+    /// difference next to the relative change.
+    Ratio,
+    /// A ratio expressed in percent, like a cache hit rate. The change is in percentage points,
+    /// as a relative change of a percentage is hard to read.
+    Percentage,
+}
+
+impl MeasureKind {
+    pub fn of(kinds: &IndexMap<String, MeasureKind>, measure: &str) -> Self {
+        kinds.get(measure).copied().unwrap_or(match measure {
+            mix::BRANCH_MISS_RATE => MeasureKind::Percentage,
+            _ if cache_stats::is_hit_rate(measure) => MeasureKind::Percentage,
+            _ => MeasureKind::default(),
+        })
+    }
+
+    /// The change in the unit it is shown in: percentage points for percentages, percent of
+    /// the new value for everything else. The gate compares this against its threshold.
+    pub fn delta(self, before: &BenchCounter, after: &BenchCounter) -> f64 {
+        match self {
+            MeasureKind::Percentage => after.value - before.value,
+            MeasureKind::Count | MeasureKind::Time | MeasureKind::Ratio => {
+                BenchCounter::improvement_percentage(before, after)
+            }
+        }
+    }
+
+    pub fn format_delta(self, before: &BenchCounter, after: &BenchCounter) -> String {
+        let delta = self.delta(before, after);
+        match self {
+            MeasureKind::Count | MeasureKind::Time => format!("{delta:+.2}%"),
+            MeasureKind::Ratio => format!("{:+.3} ({delta:+.2}%)", after.value - before.value),
+            MeasureKind::Percentage => format!("{delta:+.1} pp"),
+        }
+    }
+}
+
+#[cfg(test)]
+fn counters_for_test(before: f64, after: f64) -> (BenchCounter, BenchCounter) {
+    let counter = |value| BenchCounter {
+        value,
+        variance: 0.0,
+        repetitions: 20,
+        unit: String::new(),
+    };
+    (counter(before), counter(after))
+}
+
+#[test]
+fn count_and_time_delta() {
+    let (before, after) = counters_for_test(1000.0, 1200.0);
+    for kind in [MeasureKind::Count, MeasureKind::Time] {
+        assert_eq!(kind.delta(&before, &after), 16.666666666666664);
+        assert_eq!(kind.format_delta(&before, &after), "+16.67%");
+        assert_eq!(kind.format_delta(&after, &before), "-20.00%");
+    }
+}
+
+#[test]
+fn ratio_delta() {
+    let (before, after) = counters_for_test(2.5, 2.0);
+    assert_eq!(MeasureKind::Ratio.delta(&before, &after), -25.0);
+    assert_eq!(
+        MeasureKind::Ratio.format_delta(&before, &after),
+        "-0.500 (-25.00%)"
+    );
+    assert_eq!(
+        MeasureKind::Ratio.format_delta(&after, &before),
+        "+0.500 (+20.00%)"
+    );
+}
+
+#[test]
+fn percentage_delta() {
+    let (before, after) = counters_for_test(92.0, 93.4);
+    let delta = MeasureKind::Percentage.delta(&before, &after);
+    assert!((delta - 1.4).abs() < 1e-9, "{delta}");
+    assert_eq!(
+        MeasureKind::Percentage.format_delta(&before, &after),
+        "+1.4 pp"
+    );
+    assert_eq!(
+        MeasureKind::Percentage.format_delta(&after, &before),
+        "-1.4 pp"
+    );
+}
+
+#[test]
+fn unknown_measures_are_counts() {
+    let kinds: IndexMap<String, MeasureKind> =
+        serde_json::from_str(r#"{ "hit-rate": "percentage", "ipc": "ratio" }"#).unwrap();
+    assert_eq!(MeasureKind::of(&kinds, "hit-rate"), MeasureKind::Percentage);
+    assert_eq!(MeasureKind::of(&kinds, "ipc"), MeasureKind::Ratio);
+    assert_eq!(MeasureKind::of(&kinds, "cycles"), MeasureKind::Count);
+    assert_eq!(
+        MeasureKind::of(&kinds, "branch-miss-rate"),
+        MeasureKind::Percentage
+    );
+}
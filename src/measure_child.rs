@@ -0,0 +1,524 @@
+//! Measuring only a process started by the benchmarked command, with `measure-child`, for
+//! drivers like test harnesses or build tools that set up a worker and wait for it:
+//!
+//! ```json
+//! "commands": { "render": [{ "command": "./render.sh scene.json", "measure-child": "renderer" }] }
+//! ```
+//!
+//! The command, the driver, runs as usual, but in a session of its own. Meanwhile, the
+//! processes of the session are polled in `/proc` for the one named `renderer`: its name as
+//! `ps` shows it, or the basename of its first argument. `perf stat -p` attaches to it as soon
+//! as it shows up and counts until it exits, and those are the counters of the command. A name
+//! between slashes, like `"/^render(er)?$/"`, is a regular expression instead, see
+//! [`crate::pattern`].
+//!
+//! Every repetition runs the driver again, which must start exactly one matching process in
+//! every run, not counting those started by the matching process itself, within `measure-child-timeout-ms`, 10 seconds by default. Otherwise, or when the
+//! process exits before perf could attach to it, the command fails with why rather than with
+//! the counters of nothing. What the process does before perf attaches, a few milliseconds,
+//! isn't counted. Only the perf backend measures a child, and processes that start a session of
+//! their own aren't found.
+
+use std::fs;
+use std::io::{self, Read};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::Path;
+use std::process::{Child, Command, Output, Stdio};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::pattern::Pattern;
+
+const PROC: &str = "/proc";
+
+/// How often `/proc` is polled for the process, and once perf is attached to it. Attached, the
+/// polling only competes with the measured process.
+const SEARCH_INTERVAL: Duration = Duration::from_millis(1);
+const ATTACHED_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The process of a command to measure.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeasureChild {
+    /// The name of the process, or a regular expression between slashes.
+    pub process: String,
+    /// How long the driver has to start the process.
+    pub timeout: Duration,
+}
+
+enum Matcher {
+    Name(String),
+    Pattern(Pattern),
+}
+
+impl Matcher {
+    fn new(process: &str) -> Result<Self, String> {
+        match process.strip_prefix('/').and_then(|rest| rest.strip_suffix('/')) {
+            Some("") => Err("`measure-child` has an empty regular expression".to_owned()),
+            Some(pattern) => Pattern::new(pattern)
+                .map(Matcher::Pattern)
+                .map_err(|e| format!("invalid `measure-child` regular expression `{process}`: {e}")),
+            None if process.is_empty() || process.contains('/') => Err(format!(
+                "`measure-child` must be the name of a process or a regular expression between slashes, got `{process}`"
+            )),
+            None => Ok(Matcher::Name(process.to_owned())),
+        }
+    }
+
+    fn matches(&self, process: &Process) -> bool {
+        process.names().any(|name| match self {
+            Matcher::Name(expected) => name == expected,
+            Matcher::Pattern(pattern) => pattern.is_match(name),
+        })
+    }
+}
+
+pub fn validate(process: &str) -> Result<(), String> {
+    Matcher::new(process).map(|_| ())
+}
+
+/// A process of the session of the driver.
+#[derive(Debug)]
+struct Process {
+    pid: i32,
+    ppid: i32,
+    /// The name of the executable, or of the script, cut to 15 bytes by the kernel.
+    comm: String,
+    /// The basename of the first argument.
+    arg0: String,
+    zombie: bool,
+}
+
+impl Process {
+    fn names(&self) -> impl Iterator<Item = &str> {
+        [self.comm.as_str(), self.arg0.as_str()]
+            .into_iter()
+            .filter(|name| !name.is_empty())
+    }
+
+    fn describe(&self) -> String {
+        format!("`{}` (pid {})", self.comm, self.pid)
+    }
+}
+
+/// The fields of a `/proc/<pid>/stat`.
+#[derive(Debug, PartialEq)]
+struct Stat<'a> {
+    comm: &'a str,
+    state: char,
+    ppid: i32,
+    session: i32,
+}
+
+/// The name is in parentheses, and may itself contain spaces and parentheses.
+fn parse_stat(stat: &str) -> Option<Stat<'_>> {
+    let (_, rest) = stat.split_once(" (")?;
+    let (comm, fields) = rest.rsplit_once(") ")?;
+    // state, ppid, pgrp, session
+    let fields = fields.split(' ').take(4).collect::<Vec<_>>();
+    Some(Stat {
+        comm,
+        state: fields.first()?.chars().next()?,
+        ppid: fields.get(1)?.parse().ok()?,
+        session: fields.get(3)?.parse().ok()?,
+    })
+}
+
+/// The processes in `session`, but its leader, in the `/proc` at `proc`.
+fn session_processes(proc: &Path, session: i32) -> Vec<Process> {
+    let Ok(entries) = fs::read_dir(proc) else {
+        return vec![];
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let pid = entry.file_name().to_str()?.parse::<i32>().ok()?;
+            // Processes that exited in the meantime are skipped.
+            let stat = fs::read_to_string(entry.path().join("stat")).ok()?;
+            let stat = parse_stat(&stat)?;
+            if stat.session != session || pid == session {
+                return None;
+            }
+            let cmdline = fs::read(entry.path().join("cmdline")).unwrap_or_default();
+            let arg0 = cmdline.split(|&b| b == 0).next().unwrap_or_default();
+            let arg0 = String::from_utf8_lossy(arg0);
+            Some(Process {
+                pid,
+                ppid: stat.ppid,
+                comm: stat.comm.to_owned(),
+                arg0: arg0.rsplit('/').next().unwrap_or_default().to_owned(),
+                zombie: stat.state == 'Z',
+            })
+        })
+        .collect()
+}
+
+/// The running processes of `processes` that match, but those started by one that matches too.
+/// Those are part of it, like the copy of it a process forks before running another program.
+fn outermost_matches(processes: Vec<Process>, matcher: &Matcher) -> Vec<Process> {
+    let matching = processes
+        .iter()
+        .filter(|process| !process.zombie && matcher.matches(process))
+        .map(|process| process.pid)
+        .collect::<Vec<_>>();
+    let parents = processes
+        .iter()
+        .map(|process| (process.pid, process.ppid))
+        .collect::<std::collections::HashMap<_, _>>();
+    let nested = |process: &Process| {
+        let mut ancestor = process.ppid;
+        // Within the session, which the processes can't have a loop in.
+        while let Some(&ppid) = parents.get(&ancestor) {
+            if matching.contains(&ancestor) {
+                return true;
+            }
+            ancestor = ppid;
+        }
+        false
+    };
+    processes
+        .into_iter()
+        .filter(|process| matching.contains(&process.pid) && !nested(process))
+        .collect()
+}
+
+/// Whether `pid` is running, rather than gone or a zombie.
+fn alive(pid: i32) -> bool {
+    fs::read_to_string(Path::new(PROC).join(pid.to_string()).join("stat"))
+        .ok()
+        .and_then(|stat| parse_stat(&stat).map(|stat| stat.state != 'Z'))
+        .unwrap_or(false)
+}
+
+fn read_in_background(mut pipe: impl Read + Send + 'static) -> JoinHandle<io::Result<Vec<u8>>> {
+    thread::spawn(move || {
+        let mut output = vec![];
+        pipe.read_to_end(&mut output).map(|_| output)
+    })
+}
+
+fn join(output: JoinHandle<io::Result<Vec<u8>>>) -> Result<Vec<u8>, String> {
+    output
+        .join()
+        .unwrap()
+        .map_err(|e| format!("failed to read the output: {e}"))
+}
+
+/// perf attached to the measured process.
+struct Attached {
+    process: Process,
+    perf: Child,
+    stderr: JoinHandle<io::Result<Vec<u8>>>,
+    /// Whether perf was stopped with `SIGINT`, once the process was gone.
+    interrupted: bool,
+    /// Whether the process was still running when perf exited.
+    alive_at_exit: Option<bool>,
+}
+
+impl Attached {
+    /// Stop perf, if it still runs, and wait for it.
+    fn stop(&mut self) -> Result<std::process::ExitStatus, String> {
+        if self.alive_at_exit.is_none() {
+            // SAFETY: perf hasn't been waited for, so the pid is still ours.
+            unsafe { libc::kill(self.perf.id() as i32, libc::SIGINT) };
+            self.interrupted = true;
+        }
+        self.perf
+            .wait()
+            .map_err(|e| format!("failed to wait for perf: {e}"))
+    }
+}
+
+/// How a run of the driver went.
+pub enum Outcome {
+    /// perf counted the process until it exited.
+    Attached {
+        driver: Output,
+        perf_stderr: Vec<u8>,
+    },
+    /// Why no single process was measured, with the driver stopped.
+    Missed(String),
+}
+
+/// Run `driver`, and `perf_stat`, the `perf stat` command with the events to count, attached
+/// to the process of the driver named by `child`, writing the counters to `perf_output`.
+pub fn run(
+    driver: &mut Command,
+    perf_stat: &mut Command,
+    perf_output: &Path,
+    child: &MeasureChild,
+) -> Result<Outcome, String> {
+    let matcher = Matcher::new(&child.process)?;
+    // SAFETY: `setsid` is async-signal-safe.
+    unsafe {
+        driver.pre_exec(|| {
+            if libc::setsid() == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    let mut driver_process = driver
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("failed to run {}: {e}", driver.get_program().display()))?;
+    // The driver leads its session, and its process group.
+    let session = driver_process.id() as i32;
+    let stdout = read_in_background(driver_process.stdout.take().unwrap());
+    let stderr = read_in_background(driver_process.stderr.take().unwrap());
+
+    let start = Instant::now();
+    let mut attached: Option<Attached> = None;
+    let mut driver_status = None;
+    let missed = loop {
+        let mut matching = outermost_matches(session_processes(Path::new(PROC), session), &matcher);
+        if driver_status.is_none() {
+            driver_status = driver_process
+                .try_wait()
+                .map_err(|e| format!("failed to wait for the driver: {e}"))?;
+        }
+
+        let Some(attached) = &mut attached else {
+            if matching.len() > 1 {
+                break Some(multiple(&child.process, matching.iter()));
+            }
+            if let Some(process) = matching.pop() {
+                perf_stat
+                    .arg("-o")
+                    .arg(perf_output)
+                    .arg("-p")
+                    .arg(process.pid.to_string())
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::piped());
+                match perf_stat.spawn() {
+                    Ok(mut perf) => {
+                        let stderr = read_in_background(perf.stderr.take().unwrap());
+                        attached = Some(Attached {
+                            process,
+                            perf,
+                            stderr,
+                            interrupted: false,
+                            alive_at_exit: None,
+                        });
+                        continue;
+                    }
+                    Err(e) => {
+                        // SAFETY: kills the processes of the session of the driver only.
+                        unsafe { libc::kill(-session, libc::SIGKILL) };
+                        let _ = driver_process.wait();
+                        return Err(format!(
+                            "failed to run {}: {e}",
+                            perf_stat.get_program().display()
+                        ));
+                    }
+                }
+            }
+            if driver_status.is_some() {
+                break Some(format!(
+                    "the command exited without starting a process matching `{}`",
+                    child.process
+                ));
+            }
+            if start.elapsed() > child.timeout {
+                break Some(format!(
+                    "the command didn't start a process matching `{}` within {} ms",
+                    child.process,
+                    child.timeout.as_millis()
+                ));
+            }
+            thread::sleep(SEARCH_INTERVAL);
+            continue;
+        };
+
+        if matching
+            .iter()
+            .any(|process| process.pid != attached.process.pid)
+        {
+            matching.retain(|process| process.pid != attached.process.pid);
+            break Some(multiple(
+                &child.process,
+                std::iter::once(&attached.process).chain(&matching),
+            ));
+        }
+        if attached.alive_at_exit.is_none() {
+            let exited = attached
+                .perf
+                .try_wait()
+                .map_err(|e| format!("failed to wait for perf: {e}"))?;
+            if exited.is_some() {
+                attached.alive_at_exit = Some(alive(attached.process.pid));
+            } else if !alive(attached.process.pid) {
+                // perf notices by itself only about a second later.
+                attached.stop()?;
+                attached.alive_at_exit = Some(false);
+            }
+        }
+        if attached.alive_at_exit.is_some() && driver_status.is_some() {
+            break None;
+        }
+        thread::sleep(ATTACHED_INTERVAL);
+    };
+
+    if missed.is_some() {
+        // SAFETY: kills the processes of the session of the driver only.
+        unsafe { libc::kill(-session, libc::SIGKILL) };
+    }
+    let perf = match attached {
+        Some(mut attached) => {
+            let status = attached.stop()?;
+            let stderr = join(attached.stderr)?;
+            Some((
+                status,
+                stderr,
+                attached.process,
+                attached.interrupted,
+                attached.alive_at_exit,
+            ))
+        }
+        None => None,
+    };
+    let status = match driver_status {
+        Some(status) => status,
+        None => driver_process
+            .wait()
+            .map_err(|e| format!("failed to wait for the driver: {e}"))?,
+    };
+    let driver_output = Output {
+        status,
+        stdout: join(stdout)?,
+        stderr: join(stderr)?,
+    };
+
+    if let Some(reason) = missed {
+        return Ok(Outcome::Missed(reason));
+    }
+    let (perf_status, perf_stderr, process, interrupted, alive_at_exit) =
+        perf.expect("attached when nothing was missed");
+    // perf ends itself with the signal it was stopped by, after writing the counters. Stopped
+    // before it could attach, it has none.
+    let stopped = interrupted && perf_status.signal() == Some(libc::SIGINT);
+    if !(perf_status.success() || stopped) || !perf_output.exists() {
+        let reason = if alive_at_exit == Some(false) {
+            format!(
+                "{} exited before perf could attach to it",
+                process.describe()
+            )
+        } else {
+            format!(
+                "perf failed to attach to {} with {perf_status}:\n{}",
+                process.describe(),
+                String::from_utf8_lossy(&perf_stderr).trim_end()
+            )
+        };
+        return Ok(Outcome::Missed(reason));
+    }
+    Ok(Outcome::Attached {
+        driver: driver_output,
+        perf_stderr,
+    })
+}
+
+fn multiple<'a>(process: &str, matching: impl Iterator<Item = &'a Process>) -> String {
+    let matching = matching.map(Process::describe).collect::<Vec<_>>();
+    format!(
+        "the command started {} processes matching `{process}`, {}, rather than one",
+        matching.len(),
+        matching.join(", ")
+    )
+}
+
+#[test]
+fn parse_proc_stat() {
+    assert_eq!(
+        parse_stat("4242 (worker) S 4201 4200 4200 0 -1 4194560 96 0 0 0"),
+        Some(Stat {
+            comm: "worker",
+            state: 'S',
+            ppid: 4201,
+            session: 4200
+        })
+    );
+    // The name may contain anything, even `) `.
+    assert_eq!(
+        parse_stat("4243 (a) b (c)) Z 1 4200 4201 0 -1"),
+        Some(Stat {
+            comm: "a) b (c)",
+            state: 'Z',
+            ppid: 1,
+            session: 4201
+        })
+    );
+    assert_eq!(parse_stat("4244 (worker)"), None);
+}
+
+#[test]
+fn match_process_names() {
+    let process = |comm: &str, arg0: &str| Process {
+        pid: 1,
+        ppid: 0,
+        comm: comm.to_owned(),
+        arg0: arg0.to_owned(),
+        zombie: false,
+    };
+    let matcher = Matcher::new("worker").unwrap();
+    assert!(matcher.matches(&process("worker", "sh")));
+    assert!(matcher.matches(&process("worker-with-a-l", "worker")));
+    assert!(!matcher.matches(&process("worker2", "worker2")));
+
+    let matcher = Matcher::new("/^render(er)?-\\d+$/").unwrap();
+    assert!(matcher.matches(&process("renderer-12", "")));
+    assert!(!matcher.matches(&process("render", "render")));
+
+    assert_eq!(
+        validate("./worker").unwrap_err(),
+        "`measure-child` must be the name of a process or a regular expression between slashes, got `./worker`"
+    );
+    assert_eq!(
+        validate("//").unwrap_err(),
+        "`measure-child` has an empty regular expression"
+    );
+    assert!(validate("/(/").is_err());
+}
+
+#[test]
+fn nested_matches() {
+    let process = |pid: i32, ppid: i32, comm: &str| Process {
+        pid,
+        ppid,
+        comm: comm.to_owned(),
+        arg0: String::new(),
+        zombie: false,
+    };
+    let matcher = Matcher::new("worker").unwrap();
+    let pids = |processes| {
+        outermost_matches(processes, &matcher)
+            .iter()
+            .map(|process| process.pid)
+            .collect::<Vec<_>>()
+    };
+    // The worker, a copy of it about to run `sleep`, and a worker it started through a shell.
+    assert_eq!(
+        pids(vec![
+            process(10, 1, "driver"),
+            process(11, 10, "worker"),
+            process(12, 11, "worker"),
+            process(13, 11, "sh"),
+            process(14, 13, "worker"),
+        ]),
+        [11]
+    );
+    // Two workers of the driver.
+    assert_eq!(
+        pids(vec![
+            process(11, 10, "worker"),
+            process(12, 10, "worker"),
+            Process {
+                zombie: true,
+                ..process(13, 10, "worker")
+            },
+        ]),
+        [11, 12]
+    );
+}
@@ -0,0 +1,723 @@
+//! `benchmarker migrate <results> (<output> | --in-place) [--cpus <n>]`: upgrade stored results
+//! to the current format, so the results of years past work with everything recent results
+//! do, like comparisons by machine class, instead of being skipped or half understood when read.
+//!
+//! `<results>` is a results file, with one entry per line, or a directory of them. The upgraded
+//! entries go to `<output>`, a file or a directory like `<results>`, which must not exist yet.
+//! With `--in-place` the results are replaced, after copying every file to `<file>.bak`.
+//!
+//! Every entry records the [`SCHEMA_VERSION`] it was written with, 0 for results from before
+//! the version was recorded. An entry gets the [`MIGRATIONS`] of the versions after its own,
+//! in order:
+//!
+//! - `timestamps`: the time of the run as seconds since the epoch, or an RFC 3339 date like
+//!   `2023-04-05T06:07:08Z`, becomes the structure the results have now.
+//! - `units`: duration units spelled differently from perf, like `ms`, are spelled like perf
+//!   does, like `msec`.
+//! - `counter-names`: counters get their canonical names, see [`crate::counter_names`].
+//! - `machine-class`: the class is derived from the CPU model, and the number of CPUs of the
+//!   runners given with `--cpus`, see [`crate::machine`].
+//! - `schema-version`: the entry gets the current version.
+//!
+//! An entry that can't be migrated, like one whose timestamp isn't a date, or that doesn't
+//! read as results afterwards, is kept as it was, and listed with the reason. Running the
+//! migration again leaves migrated entries alone.
+
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use indexmap::IndexMap;
+use serde_json::{json, Value};
+
+use crate::bench::SingleBench;
+use crate::counter_names::CounterRenames;
+use crate::{machine, units, BenchData};
+
+/// The version of the format of the results this benchmarker writes. A change of the format
+/// increments it, and adds the migrations of older entries to [`MIGRATIONS`].
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// What the migrations can't tell from the entries themselves.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// The number of CPUs of the runners, to derive the machine class with.
+    pub cpus: Option<usize>,
+}
+
+/// An upgrade of the entries from before `version`. Returns whether it changed the entry.
+struct Migration {
+    name: &'static str,
+    version: u32,
+    migrate: fn(&mut Value, &Options) -> Result<bool, String>,
+}
+
+/// The migrations, in the order they apply. Bumping the version stays last.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        name: "timestamps",
+        version: 1,
+        migrate: timestamps,
+    },
+    Migration {
+        name: "units",
+        version: 1,
+        migrate: duration_units,
+    },
+    Migration {
+        name: "counter-names",
+        version: 1,
+        migrate: counter_names,
+    },
+    Migration {
+        name: "machine-class",
+        version: 1,
+        migrate: machine_class,
+    },
+    Migration {
+        name: "schema-version",
+        version: SCHEMA_VERSION,
+        migrate: schema_version,
+    },
+];
+
+/// The benchmarks of an entry, by group.
+fn groups_mut(entry: &mut Value) -> impl Iterator<Item = (&String, &mut Vec<Value>)> {
+    entry
+        .get_mut("bench_groups")
+        .and_then(Value::as_object_mut)
+        .into_iter()
+        .flat_map(|groups| groups.iter_mut())
+        .filter_map(|(group_name, benches)| Some((group_name, benches.as_array_mut()?)))
+}
+
+fn timestamps(entry: &mut Value, _: &Options) -> Result<bool, String> {
+    let (secs, nanos) = match &entry["timestamp"] {
+        Value::Object(_) => return Ok(false),
+        Value::Number(secs) => match (secs.as_u64(), secs.as_f64()) {
+            (Some(secs), _) => (secs, 0),
+            (None, Some(secs)) if secs >= 0.0 => {
+                (secs.trunc() as u64, (secs.fract() * 1e9).round() as u32)
+            }
+            _ => return Err(format!("`{secs}` isn't a timestamp")),
+        },
+        Value::String(date) => {
+            parse_rfc3339(date).ok_or_else(|| format!("`{date}` isn't an RFC 3339 date"))?
+        }
+        other => return Err(format!("`{other}` isn't a timestamp")),
+    };
+    entry["timestamp"] = json!({ "secs_since_epoch": secs, "nanos_since_epoch": nanos });
+    Ok(true)
+}
+
+/// A date like `2023-04-05T06:07:08.5+02:00`, as seconds and nanoseconds since the epoch.
+fn parse_rfc3339(date: &str) -> Option<(u64, u32)> {
+    let (day, time) = date.split_once(['T', 't', ' '])?;
+    let mut day = day.splitn(3, '-').map(|part| part.parse::<i64>().ok());
+    let (year, month, day) = (day.next()??, day.next()??, day.next()??);
+
+    let (time, offset) = match time.strip_suffix(['Z', 'z']) {
+        Some(time) => (time, 0),
+        None => {
+            let (time, offset) = time.split_at(time.rfind(['+', '-'])?);
+            let (sign, offset) = offset.split_at(1);
+            let (hours, minutes) = offset.split_once(':')?;
+            let offset = hours.parse::<i64>().ok()? * 3600 + minutes.parse::<i64>().ok()? * 60;
+            (time, if sign == "-" { -offset } else { offset })
+        }
+    };
+    let (time, fraction) = time.split_once('.').unwrap_or((time, ""));
+    let mut time = time.splitn(3, ':').map(|part| part.parse::<i64>().ok());
+    let (hour, minute, second) = (time.next()??, time.next()??, time.next()??);
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 60
+        || !fraction.chars().all(|c| c.is_ascii_digit())
+    {
+        return None;
+    }
+    let nanos = format!("{fraction:0<9}")[..9].parse().ok()?;
+
+    let secs =
+        days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second - offset;
+    Some((u64::try_from(secs).ok()?, nanos))
+}
+
+/// The number of days from 1970-01-01 to a date of the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn duration_units(entry: &mut Value, _: &Options) -> Result<bool, String> {
+    let mut changed = false;
+    for (_, benches) in groups_mut(entry) {
+        let counters = benches
+            .iter_mut()
+            .filter_map(|bench| bench.get_mut("counters")?.as_object_mut())
+            .flat_map(|counters| counters.values_mut());
+        for counter in counters {
+            let Some(unit) = counter.get("unit").and_then(Value::as_str) else {
+                continue;
+            };
+            if let Some(perf_unit) = units::perf_duration_unit(unit).filter(|&perf| perf != unit) {
+                counter["unit"] = perf_unit.into();
+                changed = true;
+            }
+        }
+    }
+    Ok(changed)
+}
+
+fn counter_names(entry: &mut Value, _: &Options) -> Result<bool, String> {
+    let renames = CounterRenames::default();
+    let mut changed = false;
+    for (group_name, benches) in groups_mut(entry) {
+        for bench in benches {
+            let mut parsed = serde_json::from_value::<SingleBench>(bench.clone())
+                .map_err(|err| format!("a benchmark of the `{group_name}` group: {err}"))?;
+            let names = parsed.counters.keys().cloned().collect::<Vec<_>>();
+            // Like when reading the results, the counter with the most repetitions is kept
+            // when two have the same canonical name.
+            renames.canonicalize_bench(group_name, &mut parsed);
+            if !parsed.counters.keys().eq(&names) {
+                bench["counters"] = serde_json::to_value(&parsed.counters).unwrap();
+                changed = true;
+            }
+        }
+    }
+    Ok(changed)
+}
+
+fn machine_class(entry: &mut Value, options: &Options) -> Result<bool, String> {
+    if entry
+        .get("machine_class")
+        .is_some_and(|class| !class.is_null())
+    {
+        return Ok(false);
+    }
+    let cpu_model = entry["cpu_model"].as_str().ok_or("no `cpu_model`")?;
+    // Unknown models have no class, like in fresh results.
+    if machine::machine_class(cpu_model, 1).is_none() {
+        return Ok(false);
+    }
+    let cpus = options
+        .cpus
+        .ok_or("no machine class, and no `--cpus` to derive it with")?;
+    entry["machine_class"] = machine::machine_class(cpu_model, cpus).into();
+    Ok(true)
+}
+
+fn schema_version(entry: &mut Value, _: &Options) -> Result<bool, String> {
+    entry["schema_version"] = SCHEMA_VERSION.into();
+    Ok(true)
+}
+
+/// Apply the migrations `entry` lacks, returning the names of those that changed it.
+fn migrate_entry(entry: &mut Value, options: &Options) -> Result<Vec<&'static str>, String> {
+    if !entry.is_object() {
+        return Err("not an object".to_owned());
+    }
+    let version = match entry.get("schema_version") {
+        Some(version) => version
+            .as_u64()
+            .ok_or_else(|| format!("`{version}` isn't a schema version"))?,
+        None => 0,
+    };
+    if version > u64::from(SCHEMA_VERSION) {
+        return Err(format!(
+            "schema version {version} is newer than {SCHEMA_VERSION}, of this benchmarker"
+        ));
+    }
+
+    let mut migrated = entry.clone();
+    let mut applied = vec![];
+    for migration in MIGRATIONS
+        .iter()
+        .filter(|migration| version < u64::from(migration.version))
+    {
+        if (migration.migrate)(&mut migrated, options)
+            .map_err(|err| format!("{}: {err}", migration.name))?
+        {
+            applied.push(migration.name);
+        }
+    }
+    serde_json::from_value::<BenchData>(migrated.clone())
+        .map_err(|err| format!("not valid results after the migrations: {err}"))?;
+    *entry = migrated;
+    Ok(applied)
+}
+
+/// The outcome of migrating the results.
+#[derive(Debug, Default)]
+struct Summary {
+    entries: usize,
+    /// The number of entries every migration changed, by name.
+    applied: IndexMap<&'static str, usize>,
+    /// The entries kept as they were, by file and line, with the reason.
+    failed: Vec<(String, String)>,
+}
+
+/// Migrate the entries of the results file `contents`, one per line.
+fn migrate_results(
+    contents: &str,
+    source: &str,
+    options: &Options,
+    summary: &mut Summary,
+) -> String {
+    let mut output = String::new();
+    for (line_number, line) in (1..).zip(contents.lines()) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        summary.entries += 1;
+        let migrated = serde_json::from_str::<Value>(line)
+            .map_err(|err| format!("not JSON: {err}"))
+            .and_then(|mut entry| {
+                let applied = migrate_entry(&mut entry, options)?;
+                Ok((entry, applied))
+            });
+        match migrated {
+            Ok((entry, applied)) => {
+                for name in applied {
+                    *summary.applied.entry(name).or_default() += 1;
+                }
+                output.push_str(&entry.to_string());
+            }
+            Err(err) => {
+                summary
+                    .failed
+                    .push((format!("{source}:{line_number}"), err));
+                output.push_str(line);
+            }
+        }
+        output.push('\n');
+    }
+    output
+}
+
+/// The results files to migrate, with where their migrated versions go.
+fn files(results: &Path, output: Option<&Path>) -> Result<Vec<(PathBuf, PathBuf)>, String> {
+    if !results.is_dir() {
+        return Ok(vec![(
+            results.to_owned(),
+            output.unwrap_or(results).to_owned(),
+        )]);
+    }
+    let mut files = fs::read_dir(results)
+        .map_err(|e| format!("failed to read {}: {e}", results.display()))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("failed to read {}: {e}", results.display()))?;
+    files.retain(|path| path.is_file() && path.extension().is_none_or(|ext| ext != "bak"));
+    files.sort();
+    Ok(files
+        .into_iter()
+        .map(|path| {
+            let target = match output {
+                Some(output) => output.join(path.file_name().unwrap()),
+                None => path.clone(),
+            };
+            (path, target)
+        })
+        .collect())
+}
+
+/// Migrate the results at `results` to `output`, or in place after a backup when `None`.
+fn migrate(results: &Path, output: Option<&Path>, options: &Options) -> Result<Summary, String> {
+    if let Some(output) = output {
+        if output.exists() {
+            return Err(format!(
+                "{} already exists, migrate to a new file or use --in-place",
+                output.display()
+            ));
+        }
+        if results.is_dir() {
+            fs::create_dir_all(output)
+                .map_err(|e| format!("failed to create {}: {e}", output.display()))?;
+        }
+    }
+
+    let mut summary = Summary::default();
+    for (source, target) in files(results, output)? {
+        let contents = fs::read_to_string(&source)
+            .map_err(|e| format!("failed to read {}: {e}", source.display()))?;
+        let migrated = migrate_results(
+            &contents,
+            &source.display().to_string(),
+            options,
+            &mut summary,
+        );
+        if output.is_none() {
+            let mut backup = source.clone().into_os_string();
+            backup.push(".bak");
+            let backup = PathBuf::from(backup);
+            if backup.exists() {
+                return Err(format!(
+                    "the backup {} already exists, move it away first",
+                    backup.display()
+                ));
+            }
+            fs::copy(&source, &backup)
+                .map_err(|e| format!("failed to back up {}: {e}", source.display()))?;
+        }
+        fs::write(&target, migrated)
+            .map_err(|e| format!("failed to write {}: {e}", target.display()))?;
+    }
+    Ok(summary)
+}
+
+/// What the migration did, for the terminal.
+fn render_summary(summary: &Summary) -> String {
+    let migrated = summary.entries - summary.failed.len();
+    let mut out = format!("migrated {migrated} of {} entries\n", summary.entries);
+    for migration in MIGRATIONS {
+        let count = summary.applied.get(migration.name).copied().unwrap_or(0);
+        writeln!(out, "  {}: {count}", migration.name).unwrap();
+    }
+    if !summary.failed.is_empty() {
+        writeln!(
+            out,
+            "could not migrate {} entries, kept as they were:",
+            summary.failed.len()
+        )
+        .unwrap();
+        for (entry, err) in &summary.failed {
+            writeln!(out, "  {entry}: {err}").unwrap();
+        }
+    }
+    out
+}
+
+pub fn run(args: impl IntoIterator<Item = String>) -> Result<String, String> {
+    const USAGE: &str =
+        "expected the arguments migrate <results> (<output> | --in-place) [--cpus <n>]";
+
+    let mut positional = vec![];
+    let mut in_place = false;
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--in-place" => in_place = true,
+            "--cpus" => {
+                let cpus = args.next().ok_or("expected a number after --cpus")?;
+                match cpus.parse() {
+                    Ok(cpus) if cpus > 0 => options.cpus = Some(cpus),
+                    _ => {
+                        return Err(format!(
+                            "expected a positive number after --cpus, got {cpus}"
+                        ))
+                    }
+                }
+            }
+            _ => positional.push(PathBuf::from(arg)),
+        }
+    }
+    let (results, output) = match (positional.as_slice(), in_place) {
+        ([results], true) => (results, None),
+        ([results, output], false) => (results, Some(output.as_path())),
+        _ => return Err(USAGE.to_owned()),
+    };
+    let summary = migrate(results, output, &options)?;
+    Ok(render_summary(&summary))
+}
+
+#[cfg(test)]
+fn testdata(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("testdata/migrate")
+        .join(name)
+}
+
+#[test]
+fn migrations_in_order() {
+    assert_eq!(
+        MIGRATIONS
+            .iter()
+            .map(|migration| migration.name)
+            .collect::<Vec<_>>(),
+        [
+            "timestamps",
+            "units",
+            "counter-names",
+            "machine-class",
+            "schema-version"
+        ]
+    );
+    assert!(MIGRATIONS
+        .iter()
+        .all(|migration| (1..=SCHEMA_VERSION).contains(&migration.version)));
+    assert!(MIGRATIONS.is_sorted_by_key(|migration| migration.version));
+}
+
+#[test]
+fn migrate_timestamps() {
+    let migrate = |timestamp: Value| {
+        let mut entry = json!({ "timestamp": timestamp });
+        timestamps(&mut entry, &Options::default())
+            .map(|changed| (changed, entry["timestamp"].clone()))
+    };
+    let time =
+        |secs: u64, nanos: u32| json!({ "secs_since_epoch": secs, "nanos_since_epoch": nanos });
+
+    assert_eq!(migrate(time(1, 2)), Ok((false, time(1, 2))));
+    assert_eq!(migrate(json!(1680674828)), Ok((true, time(1680674828, 0))));
+    assert_eq!(
+        migrate(json!(1680674828.25)),
+        Ok((true, time(1680674828, 250_000_000)))
+    );
+    assert_eq!(
+        migrate(json!("2023-04-05T06:07:08Z")),
+        Ok((true, time(1680674828, 0)))
+    );
+    assert_eq!(
+        migrate(json!("2023-04-05T08:07:08.5+02:00")),
+        Ok((true, time(1680674828, 500_000_000)))
+    );
+    assert_eq!(
+        migrate(json!("1970-01-01 00:00:00-01:30")),
+        Ok((true, time(5400, 0)))
+    );
+    assert_eq!(
+        migrate(json!("2000-02-29T00:00:00Z")),
+        Ok((true, time(951782400, 0)))
+    );
+
+    for (timestamp, err) in [
+        (json!("yesterday"), "`yesterday` isn't an RFC 3339 date"),
+        (
+            json!("2023-13-05T06:07:08Z"),
+            "`2023-13-05T06:07:08Z` isn't an RFC 3339 date",
+        ),
+        (
+            json!("2023-04-05T06:07:08"),
+            "`2023-04-05T06:07:08` isn't an RFC 3339 date",
+        ),
+        (
+            json!("1969-12-31T23:59:59Z"),
+            "`1969-12-31T23:59:59Z` isn't an RFC 3339 date",
+        ),
+        (json!(-1), "`-1` isn't a timestamp"),
+        (Value::Null, "`null` isn't a timestamp"),
+    ] {
+        assert_eq!(migrate(timestamp), Err(err.to_owned()));
+    }
+}
+
+#[test]
+fn migrate_counters() {
+    let mut entry = json!({
+        "bench_groups": {
+            "compress": [{
+                "cmd": ["./compress"],
+                "counters": {
+                    "task-clock": { "value": 250.0, "variance": 1.0, "repetitions": 10, "unit": "ms" },
+                    "wall-time": { "value": 0.3, "variance": 0.01, "repetitions": 10, "unit": "sec" },
+                    "cpu-cycles": { "value": 1e9, "variance": 1e6, "repetitions": 10, "unit": "" },
+                    "cpu_core/cycles/": { "value": 2e9, "variance": 1e6, "repetitions": 20, "unit": "" },
+                    "cpu_core/instructions/": { "value": 3e9, "variance": 1e6, "repetitions": 10, "unit": "" },
+                    "gpu": { "value": 40.0, "variance": 1.0, "repetitions": 10, "unit": "%" }
+                }
+            }]
+        }
+    });
+    let options = Options::default();
+
+    assert_eq!(duration_units(&mut entry, &options), Ok(true));
+    let counters = &entry["bench_groups"]["compress"][0]["counters"];
+    assert_eq!(counters["task-clock"]["unit"], "msec");
+    assert_eq!(counters["task-clock"]["value"], 250.0);
+    assert_eq!(counters["wall-time"]["unit"], "sec");
+    assert_eq!(counters["gpu"]["unit"], "%");
+    assert_eq!(duration_units(&mut entry, &options), Ok(false));
+
+    // The counter with the most repetitions wins, like when reading the results.
+    assert_eq!(counter_names(&mut entry, &options), Ok(true));
+    let counters = entry["bench_groups"]["compress"][0]["counters"]
+        .as_object()
+        .unwrap();
+    assert_eq!(
+        counters.keys().collect::<Vec<_>>(),
+        ["cycles", "gpu", "instructions", "task-clock", "wall-time"]
+    );
+    assert_eq!(counters["cycles"]["value"], 2e9);
+    assert_eq!(counter_names(&mut entry, &options), Ok(false));
+
+    let mut entry = json!({ "bench_groups": { "compress": [{ "cmd": "./compress" }] } });
+    assert!(counter_names(&mut entry, &options)
+        .unwrap_err()
+        .starts_with("a benchmark of the `compress` group: "));
+}
+
+#[test]
+fn migrate_machine_class() {
+    let entry = |class: Value| json!({ "cpu_model": "AMD EPYC 7763 64-Core Processor", "machine_class": class });
+    let with_cpus = Options { cpus: Some(4) };
+
+    let mut classless = entry(Value::Null);
+    assert_eq!(machine_class(&mut classless, &with_cpus), Ok(true));
+    assert_eq!(classless["machine_class"], "amd-epyc-7763/4");
+
+    let mut classed = entry(json!("neoverse-n1/4"));
+    assert_eq!(machine_class(&mut classed, &with_cpus), Ok(false));
+    assert_eq!(classed["machine_class"], "neoverse-n1/4");
+
+    assert_eq!(
+        machine_class(&mut entry(Value::Null), &Options::default()),
+        Err("no machine class, and no `--cpus` to derive it with".to_owned())
+    );
+    let mut unknown = json!({ "cpu_model": "unknown" });
+    assert_eq!(machine_class(&mut unknown, &Options::default()), Ok(false));
+    assert!(unknown.get("machine_class").is_none());
+}
+
+#[test]
+fn migrate_entries() {
+    let options = Options { cpus: Some(4) };
+    let current = crate::testkit::BenchDataBuilder::new("1111111111111111111111111111111111111111")
+        .machine_class("cpu/4")
+        .group("compress", |g| {
+            g.bench(["./compress"], |b| b.counter("cycles", 1e9, 1e6, 20, ""))
+        })
+        .build();
+    let mut entry = serde_json::to_value(&current).unwrap();
+    let unchanged = entry.clone();
+    assert_eq!(migrate_entry(&mut entry, &options), Ok(vec![]));
+    assert_eq!(entry, unchanged);
+
+    // Unversioned, missing a field the migrations don't add.
+    let mut old = unchanged.clone();
+    old.as_object_mut().unwrap().remove("schema_version");
+    old["timestamp"] = json!(1680674828);
+    assert_eq!(
+        migrate_entry(&mut old, &options),
+        Ok(vec!["timestamps", "schema-version"])
+    );
+    assert_eq!(old["schema_version"], SCHEMA_VERSION);
+
+    let mut broken = unchanged.clone();
+    broken.as_object_mut().unwrap().remove("schema_version");
+    broken.as_object_mut().unwrap().remove("commit_hash");
+    let err = migrate_entry(&mut broken, &options).unwrap_err();
+    assert!(
+        err.starts_with("not valid results after the migrations: missing field `commit_hash`"),
+        "{err}"
+    );
+    // Left alone.
+    assert!(broken.get("schema_version").is_none());
+
+    let mut newer = unchanged.clone();
+    newer["schema_version"] = json!(SCHEMA_VERSION + 1);
+    assert_eq!(
+        migrate_entry(&mut newer, &options),
+        Err(format!(
+            "schema version {} is newer than {SCHEMA_VERSION}, of this benchmarker",
+            SCHEMA_VERSION + 1
+        ))
+    );
+}
+
+#[test]
+fn migrate_fixture() {
+    let dir = crate::testkit::test_dir("migrate-fixture");
+    let output = dir.join("history.json");
+    let options = Options { cpus: Some(4) };
+    let summary = migrate(&testdata("history.json"), Some(&output), &options).unwrap();
+
+    // Three historical formats, and an entry from none of them.
+    assert_eq!(summary.entries, 4);
+    assert_eq!(
+        summary.applied,
+        IndexMap::from([
+            ("timestamps", 2),
+            ("units", 1),
+            ("counter-names", 2),
+            ("machine-class", 3),
+            ("schema-version", 3),
+        ])
+    );
+    assert_eq!(summary.failed.len(), 1);
+    let (entry, err) = &summary.failed[0];
+    assert!(entry.ends_with("history.json:4"), "{entry}");
+    assert_eq!(err, "timestamps: `last tuesday` isn't an RFC 3339 date");
+
+    let migrated = fs::read_to_string(&output).unwrap();
+    let lines = migrated.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 4);
+    for line in &lines[..3] {
+        let data = serde_json::from_str::<BenchData>(line).unwrap();
+        assert_eq!(data.schema_version, SCHEMA_VERSION);
+        assert_eq!(
+            data.machine_class.as_deref(),
+            Some("intel-xeon-platinum-8370c/4")
+        );
+        let counters = &data.bench_groups["compress"][0].counters;
+        assert_eq!(
+            counters.keys().collect::<Vec<_>>(),
+            ["cycles", "instructions", "task-clock"]
+        );
+        assert_eq!(counters["task-clock"].unit, "msec");
+    }
+    // The timestamps of the first and the last format, both 2023-04-05T06:07:08Z.
+    let timestamp = |line: &str| serde_json::from_str::<BenchData>(line).unwrap().timestamp;
+    assert_eq!(timestamp(lines[0]), timestamp(lines[2]));
+    // The entry that can't be migrated is kept as it was.
+    let fixture = fs::read_to_string(testdata("history.json")).unwrap();
+    assert_eq!(lines[3], fixture.lines().nth(3).unwrap());
+
+    // Migrating again changes nothing, but the entry that still can't be migrated.
+    let again = dir.join("again.json");
+    let summary = migrate(&output, Some(&again), &options).unwrap();
+    assert!(summary.applied.is_empty(), "{summary:?}");
+    assert_eq!(summary.failed.len(), 1);
+    assert_eq!(fs::read_to_string(&again).unwrap(), migrated);
+
+    // The output is never overwritten.
+    let err = migrate(&testdata("history.json"), Some(&output), &options).unwrap_err();
+    assert!(err.ends_with("history.json already exists, migrate to a new file or use --in-place"));
+}
+
+#[test]
+fn migrate_directory_in_place() {
+    let dir = crate::testkit::test_dir("migrate-in-place");
+    let fixture = fs::read_to_string(testdata("history.json")).unwrap();
+    fs::write(dir.join("linux.json"), &fixture).unwrap();
+    fs::write(dir.join("macos.json"), fixture.lines().next().unwrap()).unwrap();
+    let options = Options { cpus: Some(4) };
+
+    let summary = migrate(&dir, None, &options).unwrap();
+    assert_eq!(summary.entries, 5);
+    assert_eq!(summary.failed.len(), 1);
+    assert!(summary.failed[0].0.ends_with("linux.json:4"));
+    for name in ["linux.json", "macos.json"] {
+        let backup = fs::read_to_string(dir.join(format!("{name}.bak"))).unwrap();
+        assert!(fixture.starts_with(&backup));
+        let migrated = fs::read_to_string(dir.join(name)).unwrap();
+        assert!(migrated.contains(&format!("\"schema_version\":{SCHEMA_VERSION}")));
+    }
+
+    // The backups aren't migrated, nor overwritten.
+    let err = migrate(&dir, None, &options).unwrap_err();
+    assert!(err.contains("linux.json.bak already exists"), "{err}");
+}
+
+#[test]
+fn migrate_args() {
+    for args in [&[][..], &["a.json"], &["a.json", "b.json", "--in-place"]] {
+        assert_eq!(
+            run(args.iter().map(|arg| arg.to_string())).unwrap_err(),
+            "expected the arguments migrate <results> (<output> | --in-place) [--cpus <n>]"
+        );
+    }
+    assert_eq!(
+        run(["a.json", "b.json", "--cpus", "0"].map(str::to_owned)).unwrap_err(),
+        "expected a positive number after --cpus, got 0"
+    );
+}
@@ -0,0 +1,294 @@
+//! The instruction mix of a command: how many of its instructions are branches, loads and
+//! stores. A change can shift the mix without changing the total, e.g. when a loop is
+//! vectorized.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::bench::BenchCounter;
+use crate::command_display::CommandDisplay;
+
+/// The events perf counts in addition to the default ones for groups with `instruction-mix`.
+/// Perf reports the events a CPU doesn't support as `<not supported>`, the counters are then
+/// just missing.
+pub const PERF_EVENTS: &str = "branches,branch-misses,L1-dcache-loads,L1-dcache-stores";
+
+/// The derived counter for the share of branches that were mispredicted, in percent.
+pub const BRANCH_MISS_RATE: &str = "branch-miss-rate";
+
+/// A counter by its generic name, or the name perf uses for it on hybrid CPUs.
+pub fn counter<'a>(
+    counters: &'a BTreeMap<String, BenchCounter>,
+    name: &str,
+) -> Option<&'a BenchCounter> {
+    counters
+        .get(name)
+        .or_else(|| counters.get(&format!("cpu_core/{name}/")))
+}
+
+/// The first of the counters that was measured, e.g. the precise `mem-loads` of an external
+/// backend or otherwise the generic `L1-dcache-loads` of perf.
+fn first_counter<'a>(
+    counters: &'a BTreeMap<String, BenchCounter>,
+    names: &[&str],
+) -> Option<&'a BenchCounter> {
+    names.iter().find_map(|name| counter(counters, name))
+}
+
+/// The branch misses per branch, in percent. The variance is propagated from both counters
+/// to first order, assuming they vary independently.
+pub fn branch_miss_rate(counters: &BTreeMap<String, BenchCounter>) -> Option<BenchCounter> {
+    let branches = counter(counters, "branches").filter(|branches| branches.value > 0.0)?;
+    let misses = counter(counters, "branch-misses")?;
+
+    let rate = misses.value / branches.value;
+    let relative_variance = if misses.value > 0.0 {
+        misses.variance / misses.value.powi(2)
+    } else {
+        0.0
+    } + branches.variance / branches.value.powi(2);
+
+    Some(BenchCounter {
+        value: rate * 100.0,
+        variance: (rate * 100.0).powi(2) * relative_variance,
+        repetitions: misses.repetitions.min(branches.repetitions),
+        unit: "%".to_owned(),
+    })
+}
+
+/// The shares of the instructions, in percent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstructionMix {
+    pub branches: f64,
+    pub loads: Option<f64>,
+    pub stores: Option<f64>,
+}
+
+impl InstructionMix {
+    /// `None` unless at least the instructions and the branches were counted.
+    pub fn of(counters: &BTreeMap<String, BenchCounter>) -> Option<Self> {
+        let instructions =
+            counter(counters, "instructions").filter(|instructions| instructions.value > 0.0)?;
+        let share = |counter: &BenchCounter| counter.value * 100.0 / instructions.value;
+
+        Some(InstructionMix {
+            branches: share(counter(counters, "branches")?),
+            loads: first_counter(counters, &["mem-loads", "L1-dcache-loads"]).map(share),
+            stores: first_counter(counters, &["mem-stores", "L1-dcache-stores"]).map(share),
+        })
+    }
+
+    /// The mix as e.g. `branches 18% ▓▓▓▓ | loads 27% ▓▓▓▓▓▓ | other 55%`, with a block per
+    /// started 5%.
+    ///
+    /// Events are attributed to instructions with some skid, so the shares can add up to more
+    /// than 100%. They are then scaled down to add up to exactly 100%, and the second value is
+    /// true to have that footnoted.
+    pub fn render_bar(&self) -> (String, bool) {
+        let parts = [
+            ("branches", Some(self.branches)),
+            ("loads", self.loads),
+            ("stores", self.stores),
+        ];
+        let total = parts.iter().filter_map(|(_, share)| *share).sum::<f64>();
+        let clamped = total > 100.0;
+        let scaled = |share: f64| {
+            if clamped {
+                share * 100.0 / total
+            } else {
+                share
+            }
+        };
+
+        let mut bar = String::new();
+        for (name, share) in parts {
+            let Some(share) = share else {
+                continue;
+            };
+            let share = scaled(share);
+            let blocks = (share / 5.0).ceil() as usize;
+            write!(bar, "{name} {share:.0}% {} | ", "▓".repeat(blocks)).unwrap();
+        }
+        write!(bar, "other {:.0}%", (100.0 - scaled(total)).max(0.0)).unwrap();
+
+        (bar, clamped)
+    }
+}
+
+/// A line with the mix of every command that has one, to go under the raw table of a group.
+pub fn render_markdown_lines<'a>(
+    md: &mut String,
+    benches: impl IntoIterator<Item = (&'a [String], &'a BTreeMap<String, BenchCounter>)>,
+    commands: CommandDisplay,
+) {
+    let mut cells = commands.cells();
+    let mut any_clamped = false;
+    let mut lines = String::new();
+    for (cmd, counters) in benches {
+        let Some(mix) = InstructionMix::of(counters) else {
+            continue;
+        };
+        let (bar, clamped) = mix.render_bar();
+        any_clamped |= clamped;
+        writeln!(
+            lines,
+            "- {}: {bar}{}",
+            cells.code(&cmd.join(" ")),
+            if clamped { " \\*" } else { "" }
+        )
+        .unwrap();
+    }
+
+    if lines.is_empty() {
+        return;
+    }
+    write!(md, "\n{lines}").unwrap();
+    if any_clamped {
+        writeln!(
+            md,
+            "\n\\* The counted events add up to more than the instructions because of counter skid, \
+             the shares are scaled down to 100%."
+        )
+        .unwrap();
+    }
+    cells.render_footnotes(md);
+}
+
+#[cfg(test)]
+fn counters_for_test(counters: &[(&str, f64, f64)]) -> BTreeMap<String, BenchCounter> {
+    counters
+        .iter()
+        .map(|&(name, value, variance)| {
+            (
+                name.to_owned(),
+                BenchCounter {
+                    value,
+                    variance,
+                    repetitions: 20,
+                    unit: String::new(),
+                },
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn derived_branch_miss_rate() {
+    let counters =
+        counters_for_test(&[("branches", 1.0e6, 1.0e8), ("branch-misses", 2.0e4, 4.0e4)]);
+    let rate = branch_miss_rate(&counters).unwrap();
+    assert_eq!(rate.value, 2.0);
+    // 1% relative deviation of both counters is about 1.41% of the rate.
+    assert!(
+        (rate.variance.sqrt() - 0.02 * 2f64.sqrt()).abs() < 1e-12,
+        "{rate:?}"
+    );
+    assert_eq!(rate.unit, "%");
+    assert_eq!(rate.repetitions, 20);
+
+    // The names on hybrid CPUs, and no misses at all.
+    let counters = counters_for_test(&[
+        ("cpu_core/branches/", 1.0e6, 0.0),
+        ("cpu_core/branch-misses/", 0.0, 0.0),
+    ]);
+    assert_eq!(branch_miss_rate(&counters).unwrap().value, 0.0);
+    assert_eq!(branch_miss_rate(&counters).unwrap().variance, 0.0);
+
+    // Unsupported events are missing from the counters.
+    assert_eq!(
+        branch_miss_rate(&counters_for_test(&[("branches", 1.0e6, 0.0)])),
+        None
+    );
+    assert_eq!(
+        branch_miss_rate(&counters_for_test(&[
+            ("branches", 0.0, 0.0),
+            ("branch-misses", 0.0, 0.0)
+        ])),
+        None
+    );
+}
+
+#[test]
+fn instruction_mix_bar() {
+    let counters = counters_for_test(&[
+        ("instructions", 1.0e9, 0.0),
+        ("branches", 1.8e8, 0.0),
+        ("L1-dcache-loads", 2.7e8, 0.0),
+    ]);
+    let mix = InstructionMix::of(&counters).unwrap();
+    assert_eq!(
+        mix.render_bar(),
+        (
+            "branches 18% ▓▓▓▓ | loads 27% ▓▓▓▓▓▓ | other 55%".to_owned(),
+            false
+        )
+    );
+
+    // The precise events take precedence over the generic ones.
+    let counters = counters_for_test(&[
+        ("instructions", 1.0e9, 0.0),
+        ("branches", 1.0e8, 0.0),
+        ("mem-loads", 3.0e8, 0.0),
+        ("L1-dcache-loads", 9.0e8, 0.0),
+        ("mem-stores", 1.0e8, 0.0),
+    ]);
+    assert_eq!(
+        InstructionMix::of(&counters).unwrap().render_bar().0,
+        "branches 10% ▓▓ | loads 30% ▓▓▓▓▓▓ | stores 10% ▓▓ | other 50%"
+    );
+
+    // Skid: 60% + 60% + 40% is scaled down to 100%.
+    let mix = InstructionMix {
+        branches: 60.0,
+        loads: Some(60.0),
+        stores: Some(40.0),
+    };
+    assert_eq!(
+        mix.render_bar(),
+        (
+            "branches 38% ▓▓▓▓▓▓▓▓ | loads 38% ▓▓▓▓▓▓▓▓ | stores 25% ▓▓▓▓▓ | other 0%".to_owned(),
+            true
+        )
+    );
+
+    assert_eq!(
+        InstructionMix::of(&counters_for_test(&[("instructions", 1.0e9, 0.0)])),
+        None
+    );
+    assert_eq!(
+        InstructionMix::of(&counters_for_test(&[("branches", 1.0e9, 0.0)])),
+        None
+    );
+}
+
+#[test]
+fn instruction_mix_lines() {
+    let cmd = ["./c".to_owned(), "6".to_owned()];
+    let skid = ["./skid".to_owned()];
+    let plain = ["./plain".to_owned()];
+    let mix = counters_for_test(&[("instructions", 100.0, 0.0), ("branches", 20.0, 0.0)]);
+    let skidded = counters_for_test(&[
+        ("instructions", 100.0, 0.0),
+        ("branches", 50.0, 0.0),
+        ("L1-dcache-loads", 75.0, 0.0),
+    ]);
+    let none = counters_for_test(&[("cycles", 100.0, 0.0)]);
+
+    let mut md = String::new();
+    render_markdown_lines(
+        &mut md,
+        [(&cmd[..], &mix), (&skid[..], &skidded), (&plain[..], &none)],
+        CommandDisplay::default(),
+    );
+    assert_eq!(
+        md,
+        "\n- `./c 6`: branches 20% ▓▓▓▓ | other 80%\n\
+         - `./skid`: branches 40% ▓▓▓▓▓▓▓▓ | loads 60% ▓▓▓▓▓▓▓▓▓▓▓▓ | other 0% \\*\n\
+         \n\\* The counted events add up to more than the instructions because of counter skid, \
+         the shares are scaled down to 100%.\n"
+    );
+
+    let mut md = String::new();
+    render_markdown_lines(&mut md, [(&plain[..], &none)], CommandDisplay::default());
+    assert_eq!(md, "");
+}
@@ -0,0 +1,304 @@
+//! Notifications about significant changes, posted to a webhook (e.g. Slack or Matrix).
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::compare::{ComparisonRow, Comparisons};
+use crate::gate::GateVerdict;
+use crate::units;
+use crate::BenchData;
+
+/// The environment variable holding the webhook url. It's a secret, so it can't be part of
+/// the config.
+pub const WEBHOOK_URL_ENV: &str = "BENCH_NOTIFY_WEBHOOK_URL";
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct NotifyConfig {
+    /// The events that trigger a notification.
+    pub events: Vec<NotifyEvent>,
+    #[serde(
+        rename = "timeout-secs",
+        default = "default_timeout",
+        deserialize_with = "units::secs"
+    )]
+    pub timeout: Duration,
+}
+
+fn default_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NotifyEvent {
+    /// Any significant regression versus the parent commit.
+    Regression,
+    /// The gate failed.
+    GateFailure,
+    /// A significant change in one of the control groups.
+    ControlDrift,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Payload {
+    /// A one line summary, shown by chat webhooks that only look at `text`.
+    pub text: String,
+    pub repository: String,
+    pub commit: String,
+    pub commit_url: String,
+    pub events: Vec<NotifyEvent>,
+    pub rows: Vec<PayloadRow>,
+    pub arch: String,
+    pub os: String,
+    pub runner: String,
+    pub cpu_model: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PayloadRow {
+    pub event: NotifyEvent,
+    pub table: String,
+    pub row: String,
+    /// See [`crate::comparison_key`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    pub measure: String,
+    pub before: f64,
+    pub after: f64,
+    pub delta_percent: f64,
+}
+
+impl PayloadRow {
+    fn new(event: NotifyEvent, table: &str, row: &ComparisonRow) -> Self {
+        PayloadRow {
+            event,
+            table: table.to_owned(),
+            row: row.name.clone(),
+            key: row.key.clone(),
+            measure: row.measure.clone(),
+            before: row.before.value,
+            after: row.after.value,
+            delta_percent: row.delta_percent,
+        }
+    }
+}
+
+/// Build the notification payload, or `None` when none of the configured events happened.
+pub fn build_payload(
+    config: &NotifyConfig,
+    repository: &str,
+    data: &BenchData,
+    comparisons: &Comparisons,
+    gate: Option<&GateVerdict>,
+) -> Option<Payload> {
+    let mut events = vec![];
+    let mut rows = vec![];
+
+    for &event in &config.events {
+        let len = rows.len();
+        match event {
+            NotifyEvent::Regression => rows.extend(
+                comparisons
+                    .regressions()
+                    .map(|(table, row)| PayloadRow::new(event, &table.name, row)),
+            ),
+            NotifyEvent::GateFailure => rows.extend(gate.into_iter().flat_map(|gate| {
+                gate.failures
+                    .iter()
+                    .chain(&gate.variance_failures)
+                    .map(move |failure| PayloadRow::new(event, &failure.table, &failure.row))
+            })),
+            NotifyEvent::ControlDrift => rows.extend(
+                comparisons
+                    .control_drift()
+                    .map(|(table, row)| PayloadRow::new(event, &table.name, row)),
+            ),
+        }
+        if rows.len() != len && !events.contains(&event) {
+            events.push(event);
+        }
+    }
+
+    if events.is_empty() {
+        return None;
+    }
+
+    let commit_short = data.short_commit_id();
+    let text = format!(
+        "{repository}@{commit_short} on {}: {}",
+        data.cpu_model,
+        events
+            .iter()
+            .map(|event| match event {
+                NotifyEvent::Regression => "significant regression",
+                NotifyEvent::GateFailure => "gate failed",
+                NotifyEvent::ControlDrift => "control benchmarks drifted",
+            })
+            .collect::<Vec<_>>()
+            .join(", "),
+    );
+
+    Some(Payload {
+        text,
+        repository: repository.to_owned(),
+        commit: data.commit_hash.clone(),
+        commit_url: format!(
+            "https://github.com/{repository}/commit/{}",
+            data.commit_hash
+        ),
+        events,
+        rows,
+        arch: data.arch.clone(),
+        os: data.os.clone(),
+        runner: data.runner.clone(),
+        cpu_model: data.cpu_model.clone(),
+    })
+}
+
+/// Deliver the payload, the sanitized JSON of a [`Payload`], retrying once. Failing to notify
+/// is not a reason to fail the run, so this only warns.
+pub fn send(url: &str, config: &NotifyConfig, body: &str) {
+    for attempt in 1..=2 {
+        match crate::http::post_json(url, body, config.timeout) {
+            Ok(_) => return,
+            Err(err) => eprintln!("warning: webhook notification attempt {attempt} failed: {err}"),
+        }
+    }
+}
+
+#[cfg(test)]
+fn notify_test_data() -> (BenchData, Comparisons) {
+    let before = crate::testkit::BenchDataBuilder::cycles(
+        "1111111111111111111111111111111111111111",
+        &[
+            ("compress", &[("./c 1", 1000.0), ("./c 2", 1000.0)]),
+            ("reference", &[("./ref", 1000.0)]),
+        ],
+    )
+    .build();
+    let after = crate::testkit::BenchDataBuilder::cycles(
+        "2222222222222222222222222222222222222222",
+        &[
+            ("compress", &[("./c 1", 1200.0), ("./c 2", 1000.0)]),
+            ("reference", &[("./ref", 1000.0)]),
+        ],
+    )
+    .build();
+    let config: crate::Config = serde_json::from_str(
+        r#"{
+            "commands": {},
+            "control-groups": ["reference"],
+            "render-versus-self": {},
+            "render-versus-other": {
+                "compression": { "measure": "cycles", "command": "compress", "rows": { "level 1": 0, "level 2": 1 } }
+            }
+        }"#,
+    )
+    .unwrap();
+
+    let comparisons = Comparisons::collect(&config, &after, Some(&before));
+    (after, comparisons)
+}
+
+#[test]
+fn payload() {
+    let (data, comparisons) = notify_test_data();
+    let config: NotifyConfig =
+        serde_json::from_str(r#"{ "events": ["control-drift", "regression"] }"#).unwrap();
+    assert_eq!(config.timeout, Duration::from_secs(10));
+
+    let payload = build_payload(&config, "owner/repo", &data, &comparisons, None).unwrap();
+    assert_eq!(
+        serde_json::to_value(&payload).unwrap(),
+        serde_json::json!({
+            "text": "owner/repo@2222222 on cpu: significant regression",
+            "repository": "owner/repo",
+            "commit": "2222222222222222222222222222222222222222",
+            "commit_url": "https://github.com/owner/repo/commit/2222222222222222222222222222222222222222",
+            "events": ["regression"],
+            "rows": [{
+                "event": "regression",
+                "table": "compression",
+                "row": "level 1",
+                "measure": "cycles",
+                "key": "659734703b347b7d",
+                "before": 1000.0,
+                "after": 1200.0,
+                "delta_percent": 16.666666666666664,
+            }],
+            "arch": "X64",
+            "os": "Linux",
+            "runner": "runner",
+            "cpu_model": "cpu",
+        })
+    );
+
+    // The control group didn't change, so there is nothing to notify about.
+    let config: NotifyConfig = serde_json::from_str(r#"{ "events": ["control-drift"] }"#).unwrap();
+    assert!(build_payload(&config, "owner/repo", &data, &comparisons, None).is_none());
+}
+
+#[test]
+fn payload_gate_failure() {
+    let (data, comparisons) = notify_test_data();
+    let gate = crate::gate::GateConfig {
+        max_regression_percent: 5.0,
+        annotations: false,
+        max_variance_increase: None,
+        baseline: Default::default(),
+        exempt_label: None,
+    }
+    .evaluate(&comparisons);
+
+    let config: NotifyConfig = serde_json::from_str(r#"{ "events": ["gate-failure"] }"#).unwrap();
+    let payload = build_payload(&config, "owner/repo", &data, &comparisons, Some(&gate)).unwrap();
+    assert_eq!(payload.events, [NotifyEvent::GateFailure]);
+    assert_eq!(payload.rows.len(), 1);
+    assert_eq!(payload.rows[0].table, "compression");
+    assert_eq!(payload.rows[0].row, "level 1");
+}
+
+#[test]
+fn send_to_test_server() {
+    let (data, comparisons) = notify_test_data();
+    let config: NotifyConfig = serde_json::from_str(r#"{ "events": ["regression"] }"#).unwrap();
+    let payload = build_payload(&config, "owner/repo", &data, &comparisons, None).unwrap();
+    let payload = crate::sanitize::Sanitizer::default().to_json(&payload);
+
+    // The first attempt fails, the retry succeeds.
+    let (url, server) = crate::http::test_server(vec![500, 200]);
+    send(&url, &config, &payload);
+    let requests = server.join().unwrap();
+    assert_eq!(requests.len(), 2);
+    let body: serde_json::Value = serde_json::from_slice(&requests[1].body).unwrap();
+    assert_eq!(body["commit"], "2222222222222222222222222222222222222222");
+    assert_eq!(body["rows"][0]["row"], "level 1");
+
+    // Both attempts fail; this only warns.
+    let (url, server) = crate::http::test_server(vec![500, 500]);
+    send(&url, &config, &payload);
+    assert_eq!(server.join().unwrap().len(), 2);
+}
+
+#[test]
+fn payload_without_informational_rows() {
+    let (data, mut comparisons) = notify_test_data();
+    for row in &mut comparisons.versus_other[0].rows {
+        row.informational = true;
+    }
+
+    let config: NotifyConfig =
+        serde_json::from_str(r#"{ "events": ["regression", "gate-failure"] }"#).unwrap();
+    let gate = crate::gate::GateConfig {
+        max_regression_percent: 5.0,
+        annotations: false,
+        max_variance_increase: None,
+        baseline: Default::default(),
+        exempt_label: None,
+    }
+    .evaluate(&comparisons);
+    assert!(gate.passed());
+    assert!(build_payload(&config, "owner/repo", &data, &comparisons, Some(&gate)).is_none());
+}
@@ -0,0 +1,317 @@
+//! The files the benchmarks write. Commands that compress a corpus leave gigabytes in the
+//! working directory, and a runner whose disk fills up gets slower, which looks like a
+//! regression. Commands declare what they write with `produces`, globs relative to the working
+//! directory, which are deleted after the repetitions of the command:
+//!
+//! ```json
+//! { "command": "./compress 9 corpus", "produces": ["corpus.zst", "out/**"] }
+//! ```
+//!
+//! With the `outputs` config, the run also fails before running anything when the disk is
+//! too full, and warns about large files a group left behind without declaring them.
+
+use std::collections::BTreeMap;
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, Path};
+
+use serde::Deserialize;
+
+use crate::changed::glob_match;
+use crate::units;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct OutputsConfig {
+    /// Fail before running any benchmark when the working directory has less free space, in
+    /// bytes.
+    #[serde(
+        default,
+        rename = "min-free-space-mb",
+        deserialize_with = "units::option_mebibytes"
+    )]
+    pub min_free_space: Option<u64>,
+    /// Keep the `produces` outputs of the commands, rather than deleting them after their
+    /// repetitions.
+    #[serde(default)]
+    pub keep_outputs: bool,
+    /// Warn about undeclared files of at least this size that appeared during a group, in
+    /// bytes.
+    #[serde(
+        rename = "large-file-mb",
+        default = "default_large_file",
+        deserialize_with = "units::mebibytes"
+    )]
+    pub large_file: u64,
+}
+
+fn default_large_file() -> u64 {
+    100 << 20
+}
+
+/// Check what the types of the config can't express about a `produces` glob.
+pub fn validate_pattern(pattern: &str) -> Result<(), String> {
+    let path = Path::new(pattern);
+    if pattern.is_empty()
+        || path.is_absolute()
+        || path
+            .components()
+            .any(|component| matches!(component, Component::ParentDir))
+    {
+        return Err(format!(
+            "the output `{pattern}` must be relative to the working directory, without `..`"
+        ));
+    }
+    Ok(())
+}
+
+/// The free space of the file system of `dir`, in bytes, for unprivileged users.
+pub fn free_space(dir: &Path) -> Result<u64, String> {
+    let path = CString::new(dir.as_os_str().as_bytes())
+        .map_err(|_| format!("invalid path {}", dir.display()))?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return Err(format!(
+            "failed to get the free space of {}: {}",
+            dir.display(),
+            io::Error::last_os_error()
+        ));
+    }
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+/// Fail when `dir` has less than `min_free_space` bytes free.
+pub fn check_free_space(dir: &Path, min_free_space: u64) -> Result<(), String> {
+    let free = free_space(dir)?;
+    if free < min_free_space {
+        return Err(format!(
+            "only {} of disk space is free in {}, but `min-free-space-mb` requires {}; clean up the runner before benchmarking",
+            format_size(free),
+            dir.display(),
+            format_size(min_free_space)
+        ));
+    }
+    Ok(())
+}
+
+pub fn format_size(bytes: u64) -> String {
+    format!("{:.1} MiB", bytes as f64 / 1048576.0)
+}
+
+/// The paths in `dir` matching one of `patterns`, relative to `dir`. A matching directory
+/// is returned as a whole, without the paths in it.
+pub fn expand(dir: &Path, patterns: &[String]) -> Vec<String> {
+    let mut matches = vec![];
+    for pattern in patterns {
+        // Only the directory of the leading components without wildcards can match.
+        let literal = pattern
+            .split('/')
+            .take_while(|component| !component.contains(['*', '?']))
+            .collect::<Vec<_>>()
+            .join("/");
+        if literal == *pattern {
+            if dir.join(pattern).symlink_metadata().is_ok() {
+                matches.push(pattern.clone());
+            }
+            continue;
+        }
+        walk(dir, &literal, &mut |path, _| {
+            let matched = glob_match(pattern, path);
+            if matched {
+                matches.push(path.to_owned());
+            }
+            !matched
+        });
+    }
+    matches.sort();
+    matches.dedup();
+    matches
+}
+
+/// Visit everything below `start`, a path relative to `dir`, with its path relative to `dir`
+/// and its metadata. Directories are only entered when `visit` returns true for them.
+fn walk(dir: &Path, start: &str, visit: &mut dyn FnMut(&str, &fs::Metadata) -> bool) {
+    let Ok(entries) = fs::read_dir(dir.join(start)) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let path = match start {
+            "" => name.to_string_lossy().into_owned(),
+            start => format!("{start}/{}", name.to_string_lossy()),
+        };
+        let Ok(metadata) = entry.path().symlink_metadata() else {
+            continue;
+        };
+        if visit(&path, &metadata) && metadata.is_dir() {
+            walk(dir, &path, visit);
+        }
+    }
+}
+
+/// The size of the file or directory at `path`, in bytes. Symlinks aren't followed.
+fn size(path: &Path) -> u64 {
+    let Ok(metadata) = path.symlink_metadata() else {
+        return 0;
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    fs::read_dir(path)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| size(&entry.path()))
+        .sum()
+}
+
+/// The total size of the outputs of a command in `dir`, deleting them unless `keep`.
+pub fn collect(dir: &Path, patterns: &[String], keep: bool) -> Result<u64, String> {
+    let mut total = 0;
+    for path in expand(dir, patterns) {
+        let path = dir.join(path);
+        total += size(&path);
+        if keep {
+            continue;
+        }
+        let removed = if path
+            .symlink_metadata()
+            .is_ok_and(|metadata| metadata.is_dir())
+        {
+            fs::remove_dir_all(&path)
+        } else {
+            fs::remove_file(&path)
+        };
+        removed.map_err(|e| format!("failed to delete the output {}: {e}", path.display()))?;
+    }
+    Ok(total)
+}
+
+/// The files in `dir` by their path relative to it, with their size. The `.git` directory
+/// is left out.
+pub fn scan(dir: &Path) -> BTreeMap<String, u64> {
+    let mut files = BTreeMap::new();
+    walk(dir, "", &mut |path, metadata| {
+        if metadata.is_file() {
+            files.insert(path.to_owned(), metadata.len());
+        }
+        path != ".git"
+    });
+    files
+}
+
+/// The files of at least `threshold` bytes in `after` that aren't in `before`, and aren't
+/// declared by one of `patterns`, directly or by a directory they are in.
+pub fn undeclared_large_files(
+    before: &BTreeMap<String, u64>,
+    after: &BTreeMap<String, u64>,
+    patterns: &[String],
+    threshold: u64,
+) -> Vec<(String, u64)> {
+    let declared = |path: &str| {
+        path.match_indices('/')
+            .map(|(end, _)| &path[..end])
+            .chain([path])
+            .any(|path| patterns.iter().any(|pattern| glob_match(pattern, path)))
+    };
+    after
+        .iter()
+        .filter(|&(path, &size)| size >= threshold && !before.contains_key(path) && !declared(path))
+        .map(|(path, &size)| (path.clone(), size))
+        .collect()
+}
+
+/// The warning about an undeclared file left by a group.
+pub fn undeclared_warning(group_name: &str, path: &str, size: u64) -> String {
+    format!(
+        "the `{group_name}` group left the undeclared file `{path}` ({}) in the working directory; declare it in `produces` to delete it after the command",
+        format_size(size)
+    )
+}
+
+#[test]
+fn validate_patterns() {
+    assert!(validate_pattern("out/*.zst").is_ok());
+    assert!(validate_pattern("corpus.zst").is_ok());
+    for pattern in ["", "/tmp/out", "../out", "out/../../x"] {
+        assert_eq!(
+            validate_pattern(pattern).unwrap_err(),
+            format!(
+                "the output `{pattern}` must be relative to the working directory, without `..`"
+            )
+        );
+    }
+}
+
+#[test]
+fn check_disk_space() {
+    let dir = crate::testkit::test_dir("outputs-disk-space");
+    assert!(free_space(&dir).unwrap() > 0);
+    assert!(check_free_space(&dir, 0).is_ok());
+    let err = check_free_space(&dir, u64::MAX).unwrap_err();
+    assert!(err.starts_with("only "), "{err}");
+    assert!(err.contains("but `min-free-space-mb` requires"), "{err}");
+    assert!(free_space(&dir.join("missing")).is_err());
+}
+
+#[test]
+fn clean_up_outputs() {
+    let dir = crate::testkit::test_dir("outputs-clean-up");
+    fs::create_dir_all(dir.join("out/nested")).unwrap();
+    fs::write(dir.join("corpus"), [0; 10]).unwrap();
+    fs::write(dir.join("corpus.zst"), [0; 100]).unwrap();
+    fs::write(dir.join("out/a.zst"), [0; 200]).unwrap();
+    fs::write(dir.join("out/b.txt"), [0; 300]).unwrap();
+    fs::write(dir.join("out/nested/c.zst"), [0; 400]).unwrap();
+    fs::create_dir_all(dir.join("tmp/1")).unwrap();
+    fs::write(dir.join("tmp/1/x"), [0; 500]).unwrap();
+
+    let patterns = ["*.zst", "out/*.zst", "tmp", "missing/*"].map(str::to_owned);
+    assert_eq!(expand(&dir, &patterns), ["corpus.zst", "out/a.zst", "tmp"]);
+    assert_eq!(
+        expand(&dir, &["out/**/*.zst".to_owned()]),
+        ["out/a.zst", "out/nested/c.zst"]
+    );
+
+    // Kept, the size is still counted.
+    assert_eq!(collect(&dir, &patterns, true).unwrap(), 800);
+    assert!(dir.join("tmp/1/x").exists());
+
+    assert_eq!(collect(&dir, &patterns, false).unwrap(), 800);
+    assert!(!dir.join("corpus.zst").exists());
+    assert!(!dir.join("out/a.zst").exists());
+    assert!(!dir.join("tmp").exists());
+    assert!(dir.join("corpus").exists());
+    assert!(dir.join("out/b.txt").exists());
+    assert!(dir.join("out/nested/c.zst").exists());
+
+    // Nothing left to delete.
+    assert_eq!(collect(&dir, &patterns, false).unwrap(), 0);
+}
+
+#[test]
+fn detect_undeclared_files() {
+    let dir = crate::testkit::test_dir("outputs-undeclared");
+    fs::create_dir_all(dir.join(".git")).unwrap();
+    fs::write(dir.join("corpus"), [0; 1000]).unwrap();
+    let before = scan(&dir);
+
+    fs::write(dir.join(".git/index"), [0; 1000]).unwrap();
+    fs::create_dir_all(dir.join("out")).unwrap();
+    fs::write(dir.join("out/declared"), [0; 1000]).unwrap();
+    fs::write(dir.join("leftover"), [0; 1000]).unwrap();
+    fs::write(dir.join("small"), [0; 10]).unwrap();
+    // Grown rather than new.
+    fs::write(dir.join("corpus"), [0; 2000]).unwrap();
+    let after = scan(&dir);
+    assert!(!after.contains_key(".git/index"));
+
+    let undeclared = undeclared_large_files(&before, &after, &["out".to_owned()], 100);
+    assert_eq!(undeclared, [("leftover".to_owned(), 1000)]);
+    assert_eq!(
+        undeclared_warning("compress", "leftover", 1 << 20),
+        "the `compress` group left the undeclared file `leftover` (1.0 MiB) in the working directory; declare it in `produces` to delete it after the command"
+    );
+}
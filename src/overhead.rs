@@ -0,0 +1,372 @@
+//! The overhead of the measurement chain: what measuring a program costs that does nothing.
+//! The wrapper of the isolation and the backends exec programs of their own, and perf's timing
+//! has its granularity, which all ends up in the counters of every benchmark.
+//!
+//! With `harness-overhead`, an empty program is measured at the start of a run, through the
+//! same wrapper and backends as the benchmarks, once for every combination of them in the
+//! config. The overhead is reported with the raw results, and with `subtract`, the time-like
+//! counters get a `-net` counter without it.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bench::{bench_single_cmd, Backend, BenchCounter, CommandSpec, SingleBench};
+use crate::{rusage, units};
+
+/// The program measured for the overhead.
+pub const EMPTY_PROGRAM: &str = "true";
+
+/// The suffix of the counters without the overhead.
+pub const NET_SUFFIX: &str = "-net";
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct OverheadConfig {
+    /// How often to run the empty program for every calibration.
+    #[serde(default = "default_repetitions")]
+    pub repetitions: u32,
+    /// Derive a `-net` counter without the overhead for every time-like counter.
+    #[serde(default)]
+    pub subtract: bool,
+}
+
+fn default_repetitions() -> u32 {
+    20
+}
+
+/// What an overhead was measured with: the wrapper and the backends of a group.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CalibrationKey {
+    pub wrapper: Vec<String>,
+    /// The [`Backend::label`] of every backend.
+    pub backends: Vec<String>,
+}
+
+impl CalibrationKey {
+    pub fn new(wrapper: &[String], backends: &[Box<dyn Backend>]) -> Self {
+        CalibrationKey {
+            wrapper: wrapper.to_vec(),
+            backends: backends.iter().map(|backend| backend.label()).collect(),
+        }
+    }
+}
+
+/// The overhead per invocation of the groups with the same wrapper and backends.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HarnessOverhead {
+    #[serde(flatten)]
+    pub key: CalibrationKey,
+    pub groups: Vec<String>,
+    pub counters: BTreeMap<String, BenchCounter>,
+}
+
+/// The overhead for `key` in `overheads`, measured with `measure` unless it already was for
+/// another group. Either way, `group_name` is added to the groups of the overhead.
+pub fn calibrate<'a>(
+    overheads: &'a mut Vec<HarnessOverhead>,
+    key: CalibrationKey,
+    group_name: &str,
+    measure: impl FnOnce() -> Result<BTreeMap<String, BenchCounter>, String>,
+) -> Result<&'a HarnessOverhead, String> {
+    let index = match overheads.iter().position(|overhead| overhead.key == key) {
+        Some(index) => index,
+        None => {
+            overheads.push(HarnessOverhead {
+                counters: measure()?,
+                key,
+                groups: vec![],
+            });
+            overheads.len() - 1
+        }
+    };
+    let overhead = &mut overheads[index];
+    overhead.groups.push(group_name.to_owned());
+    Ok(overhead)
+}
+
+/// The overhead measured for `key`, if any.
+pub fn find<'a>(
+    overheads: &'a [HarnessOverhead],
+    key: &CalibrationKey,
+) -> Option<&'a HarnessOverhead> {
+    overheads.iter().find(|overhead| overhead.key == *key)
+}
+
+/// Measure the empty program the way the benchmarks are measured.
+pub fn measure(
+    wrapper: &[String],
+    backends: &[Box<dyn Backend>],
+    repetitions: u32,
+) -> Result<SingleBench, String> {
+    let cmd = CommandSpec {
+        argv: vec![EMPTY_PROGRAM.to_owned()],
+        expected_exit_codes: vec![0],
+        wrapper: wrapper.to_vec(),
+        current_dir: None,
+        sync_start: None,
+        measure_child: None,
+        verify_output: None,
+        watchdog: None,
+        preemption: None,
+    };
+    bench_single_cmd(cmd, repetitions, backends, None)
+}
+
+/// Whether the overhead of `counter` can be subtracted: a duration that isn't net already.
+/// The `-cold` and `-warm` counters of the `getrusage` backend are left alone, as they are
+/// hidden in the raw tables by default.
+fn is_time_like(name: &str, counter: &BenchCounter) -> bool {
+    units::is_duration_unit(&counter.unit)
+        && !name.ends_with(NET_SUFFIX)
+        && !rusage::is_cold_or_warm(name)
+}
+
+/// `measured` without `overhead`. They are measured independently, so their variances add
+/// up. The value never drops below zero, as a program can't take less than nothing.
+pub fn net_counter(measured: &BenchCounter, overhead: &BenchCounter) -> BenchCounter {
+    BenchCounter {
+        value: (measured.value - overhead.value).max(0.0),
+        variance: measured.variance + overhead.variance,
+        repetitions: measured.repetitions,
+        unit: measured.unit.clone(),
+    }
+}
+
+/// Add a `-net` counter for every time-like counter with an overhead in the same unit.
+pub fn add_net_counters(
+    counters: &mut BTreeMap<String, BenchCounter>,
+    overhead: &BTreeMap<String, BenchCounter>,
+) {
+    let net = counters
+        .iter()
+        .filter(|(name, counter)| is_time_like(name, counter))
+        .filter_map(|(name, counter)| {
+            let overhead = overhead
+                .get(name)
+                .filter(|overhead| overhead.unit == counter.unit)?;
+            Some((
+                format!("{name}{NET_SUFFIX}"),
+                net_counter(counter, overhead),
+            ))
+        })
+        .collect::<Vec<_>>();
+    counters.extend(net);
+}
+
+/// The `harness-overhead` section of the raw results: the time-like counters of every
+/// calibration.
+pub fn render_markdown(md: &mut String, overheads: &[HarnessOverhead]) {
+    if overheads.is_empty() {
+        return;
+    }
+
+    writeln!(md, "### harness-overhead").unwrap();
+    writeln!(md).unwrap();
+    writeln!(
+        md,
+        "The cost of measuring an empty program (`{EMPTY_PROGRAM}`), per invocation."
+    )
+    .unwrap();
+    writeln!(md).unwrap();
+    writeln!(md, "|wrapper|backends|groups|counter|overhead|").unwrap();
+    writeln!(md, "|---|---|---|---|---|").unwrap();
+    for overhead in overheads {
+        let wrapper = if overhead.key.wrapper.is_empty() {
+            "none".to_owned()
+        } else {
+            format!("`{}`", overhead.key.wrapper.join(" "))
+        };
+        for (name, counter) in &overhead.counters {
+            if !is_time_like(name, counter) {
+                continue;
+            }
+            writeln!(
+                md,
+                "|{wrapper}|{}|{}|{name}|`{:.3}±{:.3}` {}|",
+                overhead.key.backends.join(", "),
+                overhead.groups.join(", "),
+                counter.value,
+                counter.variance.sqrt(),
+                counter.unit,
+            )
+            .unwrap();
+        }
+    }
+    writeln!(md).unwrap();
+}
+
+#[cfg(test)]
+fn counter_for_test(value: f64, variance: f64, unit: &str) -> BenchCounter {
+    BenchCounter {
+        value,
+        variance,
+        repetitions: 20,
+        unit: unit.to_owned(),
+    }
+}
+
+#[cfg(test)]
+fn overhead_for_test() -> BTreeMap<String, BenchCounter> {
+    BTreeMap::from([
+        ("task-clock".to_owned(), counter_for_test(0.5, 0.01, "msec")),
+        ("cycles".to_owned(), counter_for_test(1e6, 100.0, "")),
+    ])
+}
+
+#[test]
+fn calibration_keys() {
+    let perf = || -> Box<dyn Backend> { Box::new(crate::bench::Perf::new("/tmp".as_ref())) };
+    let mix = || -> Box<dyn Backend> {
+        Box::new(crate::bench::Perf {
+            instruction_mix: true,
+            ..crate::bench::Perf::new("/tmp".as_ref())
+        })
+    };
+    let external = |template: &str| -> Box<dyn Backend> {
+        Box::new(crate::bench::External {
+            template: template.to_owned(),
+        })
+    };
+    let systemd_run = ["systemd-run".to_owned(), "--scope".to_owned()];
+
+    let key = CalibrationKey::new(&[], &[perf(), Box::new(crate::bench::Getrusage)]);
+    assert_eq!(key.backends, ["perf", "getrusage"]);
+    assert_eq!(
+        key,
+        CalibrationKey::new(&[], &[perf(), Box::new(crate::bench::Getrusage)])
+    );
+    // The wrapper, the backends, their order and their settings all matter.
+    assert_ne!(
+        key,
+        CalibrationKey::new(&systemd_run, &[perf(), Box::new(crate::bench::Getrusage)])
+    );
+    assert_ne!(
+        key,
+        CalibrationKey::new(&[], &[Box::new(crate::bench::Getrusage), perf()])
+    );
+    assert_ne!(
+        key,
+        CalibrationKey::new(&[], &[mix(), Box::new(crate::bench::Getrusage)])
+    );
+    assert_ne!(
+        CalibrationKey::new(&[], &[external("./a")]),
+        CalibrationKey::new(&[], &[external("./b")])
+    );
+}
+
+#[test]
+fn calibrations_are_cached() {
+    let key = |wrapper: &[&str]| CalibrationKey {
+        wrapper: wrapper.iter().map(|arg| arg.to_string()).collect(),
+        backends: vec!["perf".to_owned()],
+    };
+    let mut overheads = vec![];
+    let mut measured = 0;
+    let mut measure = || {
+        measured += 1;
+        Ok(overhead_for_test())
+    };
+
+    calibrate(&mut overheads, key(&[]), "compress", &mut measure).unwrap();
+    calibrate(&mut overheads, key(&["nice"]), "decompress", &mut measure).unwrap();
+    let overhead = calibrate(&mut overheads, key(&[]), "inflate", &mut measure).unwrap();
+    assert_eq!(overhead.groups, ["compress", "inflate"]);
+    assert_eq!(measured, 2);
+    assert_eq!(overheads.len(), 2);
+    assert_eq!(
+        find(&overheads, &key(&["nice"])).unwrap().groups,
+        ["decompress"]
+    );
+    assert!(find(&overheads, &key(&["taskset"])).is_none());
+
+    // A failed calibration isn't cached.
+    let err = calibrate(&mut overheads, key(&["taskset"]), "deflate", || {
+        Err("failed".to_owned())
+    });
+    assert_eq!(err.unwrap_err(), "failed");
+    assert_eq!(overheads.len(), 2);
+}
+
+#[test]
+fn subtract_overhead() {
+    let overhead = counter_for_test(0.5, 0.01, "msec");
+    assert_eq!(
+        net_counter(&counter_for_test(10.0, 0.04, "msec"), &overhead),
+        counter_for_test(9.5, 0.05, "msec")
+    );
+    // Never negative, even when the noise makes a tiny program look faster than nothing.
+    assert_eq!(
+        net_counter(&counter_for_test(0.4, 0.04, "msec"), &overhead).value,
+        0.0
+    );
+
+    let mut counters = BTreeMap::from([
+        (
+            "task-clock".to_owned(),
+            counter_for_test(10.0, 0.04, "msec"),
+        ),
+        ("cycles".to_owned(), counter_for_test(3e7, 100.0, "")),
+        ("wall-time".to_owned(), counter_for_test(12.0, 0.04, "msec")),
+    ]);
+    add_net_counters(&mut counters, &overhead_for_test());
+    // Only the time-like counters that have an overhead.
+    assert_eq!(
+        counters.keys().collect::<Vec<_>>(),
+        ["cycles", "task-clock", "task-clock-net", "wall-time"]
+    );
+    assert_eq!(counters["task-clock-net"].value, 9.5);
+
+    // A unit that doesn't match isn't subtracted, and neither are the `-cold` counters or the
+    // `-net` counters.
+    let mut counters = BTreeMap::from([
+        ("task-clock".to_owned(), counter_for_test(10.0, 0.04, "s")),
+        (
+            "task-clock-cold".to_owned(),
+            counter_for_test(10.0, 0.04, "msec"),
+        ),
+        (
+            "task-clock-net".to_owned(),
+            counter_for_test(10.0, 0.04, "msec"),
+        ),
+    ]);
+    let mut overhead = overhead_for_test();
+    overhead.insert(
+        "task-clock-cold".to_owned(),
+        counter_for_test(0.5, 0.01, "msec"),
+    );
+    overhead.insert(
+        "task-clock-net".to_owned(),
+        counter_for_test(0.5, 0.01, "msec"),
+    );
+    add_net_counters(&mut counters, &overhead);
+    assert_eq!(counters.len(), 3);
+}
+
+#[test]
+fn render_overhead() {
+    let overheads = vec![HarnessOverhead {
+        key: CalibrationKey {
+            wrapper: vec![],
+            backends: vec!["perf".to_owned()],
+        },
+        groups: vec!["compress".to_owned(), "decompress".to_owned()],
+        counters: overhead_for_test(),
+    }];
+
+    let mut md = String::new();
+    render_markdown(&mut md, &overheads);
+    assert_eq!(
+        md,
+        "### harness-overhead\n\n\
+         The cost of measuring an empty program (`true`), per invocation.\n\n\
+         |wrapper|backends|groups|counter|overhead|\n\
+         |---|---|---|---|---|\n\
+         |none|perf|compress, decompress|task-clock|`0.500±0.100` msec|\n\n"
+    );
+
+    let mut md = String::new();
+    render_markdown(&mut md, &[]);
+    assert_eq!(md, "");
+}
@@ -0,0 +1,218 @@
+//! The regular expressions of the `sanitize` rules and `measure-child`, in the syntax of the
+//! `regex` crate. Matching takes linear time in the length of the text, so a rule can run over a
+//! whole step summary.
+//!
+//! The replacement of a rule has its own, smaller syntax, see [`Replacement`].
+
+use std::borrow::Cow;
+
+use regex::{Captures, Regex};
+
+/// A compiled regular expression.
+#[derive(Debug)]
+pub struct Pattern {
+    regex: Regex,
+}
+
+impl Pattern {
+    pub fn new(source: &str) -> Result<Self, String> {
+        let regex = Regex::new(source).map_err(|err| match err {
+            // The last line of a syntax error says what's wrong, the ones before point at it.
+            regex::Error::Syntax(err) => err
+                .lines()
+                .last()
+                .map(|line| line.trim_start_matches("error: ").to_owned())
+                .unwrap_or(err),
+            err => err.to_string(),
+        })?;
+        Ok(Pattern { regex })
+    }
+
+    /// The pattern matching `literal` and nothing else.
+    pub fn literal(literal: &str) -> Self {
+        Pattern {
+            regex: Regex::new(&regex::escape(literal)).expect("an escaped literal"),
+        }
+    }
+
+    /// The number of capturing groups.
+    fn groups(&self) -> usize {
+        self.regex.captures_len() - 1
+    }
+
+    /// Whether the pattern matches anywhere in `text`.
+    pub fn is_match(&self, text: &str) -> bool {
+        self.regex.is_match(text)
+    }
+
+    /// Every non-overlapping match in `text` replaced, borrowing `text` when nothing matches.
+    pub fn replace_all<'a>(&self, text: &'a str, replacement: &Replacement) -> Cow<'a, str> {
+        self.regex
+            .replace_all(text, |captures: &Captures| replacement.expand(captures))
+    }
+}
+
+/// The replacement of a match, where `$1` to `$9` are the text of a capturing group, `$0` the
+/// whole match and `$$` a `$`.
+#[derive(Debug)]
+pub struct Replacement(Vec<Piece>);
+
+#[derive(Debug)]
+enum Piece {
+    Literal(String),
+    Group(usize),
+}
+
+impl Replacement {
+    /// The replacement for matches of `pattern`.
+    pub fn new(replacement: &str, pattern: &Pattern) -> Result<Self, String> {
+        let mut pieces = vec![];
+        let mut literal = String::new();
+        let mut chars = replacement.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c != '$' {
+                literal.push(c);
+                continue;
+            }
+            match chars.peek().copied() {
+                Some('$') => {
+                    chars.next();
+                    literal.push('$');
+                }
+                Some(digit @ '0'..='9') => {
+                    chars.next();
+                    let group = digit as usize - '0' as usize;
+                    if group > pattern.groups() {
+                        return Err(format!(
+                            "`${group}` refers to a group the pattern doesn't have"
+                        ));
+                    }
+                    pieces.push(Piece::Literal(std::mem::take(&mut literal)));
+                    pieces.push(Piece::Group(group));
+                }
+                _ => literal.push('$'),
+            }
+        }
+        pieces.push(Piece::Literal(literal));
+        Ok(Replacement(pieces))
+    }
+
+    fn expand(&self, captures: &Captures) -> String {
+        let mut out = String::new();
+        for piece in &self.0 {
+            match piece {
+                Piece::Literal(literal) => out.push_str(literal),
+                Piece::Group(group) => {
+                    if let Some(group) = captures.get(*group) {
+                        out.push_str(group.as_str());
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+fn replace(pattern: &str, replacement: &str, text: &str) -> String {
+    let pattern = Pattern::new(pattern).unwrap();
+    let replacement = Replacement::new(replacement, &pattern).unwrap();
+    pattern.replace_all(text, &replacement).into_owned()
+}
+
+#[test]
+fn match_patterns() {
+    let matches = |pattern: &str, text: &str| Pattern::new(pattern).unwrap().is_match(text);
+    assert!(matches("/home/[^/]+", "cd /home/alice/corpus"));
+    assert!(!matches("/home/[^/]+", "/home/"));
+    assert!(matches(r"^\d{3}-\d+$", "123-4"));
+    assert!(!matches(r"^\d{3}-\d+$", "12-4"));
+    assert!(!matches(r"^\d{3}-\d+$", "123-4 "));
+    assert!(matches("ci-(linux|mac)os", "ci-macos"));
+    assert!(!matches("ci-(linux|mac)os", "ci-windows"));
+    assert!(matches("a.c", "abc"));
+    assert!(!matches("a.c", "a\nc"));
+    assert!(matches(r"[\w-]+\.tar", "corpus-1.tar"));
+    assert!(matches("[]a]", "]"));
+    assert!(matches("[a-]", "-"));
+    assert!(matches(r"\S\s\S", "a b"));
+    assert!(matches("^(a|ab)c$", "abc"));
+    assert!(matches("^(a*)*b$", "aaab"));
+    assert!(matches("^x{2,}$", "xxx"));
+    assert!(!matches("^x{2,3}$", "xxxx"));
+    assert!(matches("^$", ""));
+}
+
+#[test]
+fn replace_matches() {
+    assert_eq!(
+        replace("/home/[^/ ]+", "/home/<user>", "/home/alice/a /home/bob"),
+        "/home/<user>/a /home/<user>"
+    );
+    // Captures, and `$$` for a dollar.
+    assert_eq!(
+        replace(r"(\w+)@(\w+)", "$2 at $1 ($$0)", "alice@host"),
+        "host at alice ($0)"
+    );
+    assert_eq!(replace("a+?", "b", "aaa"), "bbb");
+    assert_eq!(replace("a+", "b", "aaa"), "b");
+    // Empty matches don't loop.
+    assert_eq!(replace("x*", "-", "ab"), "-a-b-");
+    assert_eq!(replace("é+", "e", "café, éé"), "cafe, e");
+
+    let pattern = Pattern::new("zzz").unwrap();
+    let replacement = Replacement::new("", &pattern).unwrap();
+    assert!(matches!(
+        pattern.replace_all("abc", &replacement),
+        Cow::Borrowed("abc")
+    ));
+    assert_eq!(
+        Pattern::literal("a.b")
+            .replace_all("a.b axb", &Replacement::new("c", &pattern).unwrap())
+            .into_owned(),
+        "c axb"
+    );
+}
+
+#[test]
+fn match_long_text() {
+    // A whole step summary, without a line break.
+    let text = "a".repeat(100_000);
+    assert!(!Pattern::new("[^/]+x").unwrap().is_match(&text));
+    assert_eq!(replace("a+", "b", &text), "b");
+}
+
+#[test]
+fn invalid_patterns() {
+    for (pattern, err) in [
+        ("(a", "unclosed group"),
+        ("a)", "unopened group"),
+        ("[a", "unclosed character class"),
+        ("*a", "repetition operator missing expression"),
+        (
+            "a{2,1}",
+            "invalid repetition count range, the start must be <= the end",
+        ),
+        ("a{x}", "repetition quantifier expects a valid decimal"),
+        (r"\q", "unrecognized escape sequence"),
+        (
+            "[z-a]",
+            "invalid character class range, the start must be <= the end",
+        ),
+        (
+            "(?=a)",
+            "look-around, including look-ahead and look-behind, is not supported",
+        ),
+        (
+            "a\\",
+            "incomplete escape sequence, reached end of pattern prematurely",
+        ),
+    ] {
+        assert_eq!(Pattern::new(pattern).unwrap_err(), err, "{pattern}");
+    }
+    let pattern = Pattern::new("(a)").unwrap();
+    assert_eq!(
+        Replacement::new("$2", &pattern).unwrap_err(),
+        "`$2` refers to a group the pattern doesn't have"
+    );
+}
@@ -0,0 +1,255 @@
+//! The events perf counts, with their modifiers. `cycles:u` only counts user space, so the
+//! page faults of the kernel don't add noise to the benchmark of a library. A modified event
+//! counts something else than the plain one, so its counter keeps the modifier in its name.
+//!
+//! Perf adds `:u` to every event itself when `perf_event_paranoid` doesn't allow counting the
+//! kernel, and wraps the events in `cpu_core/<event>/` on hybrid CPUs. The counters are named
+//! after the event that was asked for instead, see [`PerfEvent::counter_names`].
+
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+/// The events perf counts when a group doesn't configure them.
+pub const DEFAULT_EVENTS: &str = "task-clock,cycles,instructions";
+
+/// An event of the `perf-events-for-group` config, e.g. `"cycles:u"` or
+/// `{ "event": "cycles", "modifier": "u" }`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "PerfEventRepr")]
+pub struct PerfEvent {
+    pub event: String,
+    pub modifier: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PerfEventRepr {
+    Shorthand(String),
+    Options {
+        event: String,
+        #[serde(default)]
+        modifier: Option<String>,
+    },
+}
+
+impl TryFrom<PerfEventRepr> for PerfEvent {
+    type Error = String;
+
+    fn try_from(repr: PerfEventRepr) -> Result<Self, String> {
+        match repr {
+            PerfEventRepr::Shorthand(name) => PerfEvent::parse(&name),
+            PerfEventRepr::Options { event, modifier } => {
+                if event.contains(':') {
+                    return Err(format!(
+                        "the perf event `{event}` has a modifier, which goes in `modifier`"
+                    ));
+                }
+                PerfEvent::new(event, modifier)
+            }
+        }
+    }
+}
+
+impl PerfEvent {
+    fn new(event: String, modifier: Option<String>) -> Result<Self, String> {
+        if event.is_empty() || event.contains([',', '/', ' ']) {
+            return Err(format!("`{event}` isn't a perf event"));
+        }
+        let modifier = modifier.filter(|modifier| !modifier.is_empty());
+        if let Some(modifier) = &modifier {
+            if !modifier.chars().all(|c| c.is_ascii_alphabetic()) {
+                return Err(format!(
+                    "`{modifier}` of the perf event `{event}` isn't a modifier"
+                ));
+            }
+        }
+        Ok(PerfEvent { event, modifier })
+    }
+
+    /// An event like perf takes it, e.g. `cycles` or `cycles:u`.
+    pub fn parse(name: &str) -> Result<Self, String> {
+        let (event, modifier) = match name.split_once(':') {
+            Some((event, modifier)) => (event, Some(modifier.to_owned())),
+            None => (name, None),
+        };
+        PerfEvent::new(event.to_owned(), modifier)
+    }
+
+    /// The events of a comma separated list like [`DEFAULT_EVENTS`].
+    pub fn parse_list(names: &str) -> Vec<Self> {
+        names
+            .split(',')
+            .map(|name| PerfEvent::parse(name).expect("a valid built-in event"))
+            .collect()
+    }
+
+    /// The name of the event for perf and of its counter, e.g. `cycles:u`.
+    pub fn name(&self) -> String {
+        match &self.modifier {
+            Some(modifier) => format!("{}:{modifier}", self.event),
+            None => self.event.clone(),
+        }
+    }
+
+    /// The names of the counters for the events perf reported, in the order of its output,
+    /// which is the order of `events`. An event reported for a requested event is named after
+    /// it, e.g. `cycles` when perf added `:u` itself. The `n`th report of an event on a PMU is
+    /// for the `n`th requested event with the same name, so `cycles` and `cycles:u` can be
+    /// counted side by side. Others keep the name perf gave them.
+    pub fn counter_names(events: &[PerfEvent], reported: &[&str]) -> Vec<String> {
+        let mut seen = HashMap::new();
+        reported
+            .iter()
+            .map(|&reported| {
+                let decoded = decode(reported);
+                let nth = seen.entry((decoded.pmu, decoded.event)).or_insert(0);
+                let requested = events
+                    .iter()
+                    .filter(|requested| requested.event == decoded.event)
+                    .nth(*nth);
+                *nth += 1;
+                match (requested, decoded.pmu) {
+                    (Some(requested), Some(pmu)) => format!("{pmu}/{}/", requested.name()),
+                    (Some(requested), None) => requested.name(),
+                    (None, _) => reported.to_owned(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// An event as perf reports it, e.g. `cpu_core/cycles/u`.
+#[derive(Debug, PartialEq)]
+pub struct Decoded<'a> {
+    /// The PMU of hybrid CPUs, e.g. `cpu_core`.
+    pub pmu: Option<&'a str>,
+    pub event: &'a str,
+    pub modifier: Option<&'a str>,
+}
+
+/// The parts of an event like perf reports it: `cycles`, `cycles:u`, and on hybrid CPUs
+/// `cpu_core/cycles/`, `cpu_core/cycles/u` or `cpu_core/cycles:u/`.
+pub fn decode(name: &str) -> Decoded<'_> {
+    fn split(event: &str) -> (&str, Option<&str>) {
+        match event.split_once(':') {
+            Some((event, modifier)) => (event, Some(modifier)),
+            None => (event, None),
+        }
+    }
+    let pmu_event = name
+        .split_once('/')
+        .and_then(|(pmu, rest)| Some((pmu, rest.split_once('/')?)));
+    match pmu_event {
+        Some((pmu, (event, suffix))) => {
+            let (event, modifier) = split(event);
+            let modifier = modifier.or(Some(suffix).filter(|suffix| !suffix.is_empty()));
+            Decoded {
+                pmu: Some(pmu),
+                event,
+                modifier,
+            }
+        }
+        None => {
+            let (event, modifier) = split(name);
+            Decoded {
+                pmu: None,
+                event,
+                modifier,
+            }
+        }
+    }
+}
+
+#[test]
+fn parse_perf_events() {
+    let events: Vec<PerfEvent> = serde_json::from_str(
+        r#"["cycles:u", { "event": "instructions", "modifier": "u" }, { "event": "task-clock" }, "branches"]"#,
+    )
+    .unwrap();
+    assert_eq!(
+        events.iter().map(PerfEvent::name).collect::<Vec<_>>(),
+        ["cycles:u", "instructions:u", "task-clock", "branches"]
+    );
+    assert_eq!(events[0], PerfEvent::parse("cycles:u").unwrap());
+    assert_eq!(events[1].modifier.as_deref(), Some("u"));
+    assert_eq!(PerfEvent::parse_list(DEFAULT_EVENTS).len(), 3);
+
+    for (invalid, err) in [
+        (r#""cycles,instructions""#, "`cycles,instructions` isn't"),
+        (r#""cycles:u k""#, "`u k` of the perf event `cycles` isn't"),
+        (
+            r#"{ "event": "cycles:u" }"#,
+            "the perf event `cycles:u` has a modifier",
+        ),
+        (r#""""#, "`` isn't a perf event"),
+    ] {
+        let result = serde_json::from_str::<PerfEvent>(invalid).unwrap_err();
+        assert!(result.to_string().starts_with(err), "{invalid}: {result}");
+    }
+}
+
+#[test]
+fn decode_reported_events() {
+    let decoded = |pmu, event, modifier| Decoded {
+        pmu,
+        event,
+        modifier,
+    };
+    assert_eq!(decode("cycles"), decoded(None, "cycles", None));
+    assert_eq!(decode("cycles:u"), decoded(None, "cycles", Some("u")));
+    assert_eq!(decode("cycles:uk"), decoded(None, "cycles", Some("uk")));
+    assert_eq!(
+        decode("cpu_core/cycles/"),
+        decoded(Some("cpu_core"), "cycles", None)
+    );
+    assert_eq!(
+        decode("cpu_core/cycles/u"),
+        decoded(Some("cpu_core"), "cycles", Some("u"))
+    );
+    assert_eq!(
+        decode("cpu_atom/cycles:u/"),
+        decoded(Some("cpu_atom"), "cycles", Some("u"))
+    );
+}
+
+#[test]
+fn name_reported_counters() {
+    let events = PerfEvent::parse_list("task-clock,cycles,cycles:u,instructions:u");
+
+    // Perf added `:u` to everything, as `perf_event_paranoid` doesn't allow the kernel.
+    assert_eq!(
+        PerfEvent::counter_names(
+            &events,
+            &["task-clock", "cycles:u", "cycles:u", "instructions:u"]
+        ),
+        ["task-clock", "cycles", "cycles:u", "instructions:u"]
+    );
+    assert_eq!(
+        PerfEvent::counter_names(
+            &events,
+            &["task-clock", "cycles", "cycles:u", "instructions:u"]
+        ),
+        ["task-clock", "cycles", "cycles:u", "instructions:u"]
+    );
+    // Both PMUs of a hybrid CPU, and an event that wasn't asked for.
+    assert_eq!(
+        PerfEvent::counter_names(
+            &events,
+            &[
+                "cpu_atom/cycles:u/",
+                "cpu_core/cycles:u/",
+                "cpu_atom/cycles:u/",
+                "cpu_core/cycles/u",
+                "branches",
+            ]
+        ),
+        [
+            "cpu_atom/cycles/",
+            "cpu_core/cycles/",
+            "cpu_atom/cycles:u/",
+            "cpu_core/cycles:u/",
+            "branches"
+        ]
+    );
+}
@@ -0,0 +1,290 @@
+//! Leave out the repetitions another process took the CPU from, for the backends that measure
+//! every run on its own, like `getrusage`. With
+//!
+//! ```json
+//! "preemption": { "max-ratio-factor": 2.0, "max-excluded-fraction": 0.2 }
+//! ```
+//!
+//! the ratio of the wall time of every repetition to its CPU time, user and system, is
+//! compared to the median ratio of the repetitions of the command. Waiting for the CPU makes a
+//! run take longer without using more CPU time, so a repetition whose ratio is more than
+//! `max-ratio-factor` times the median, or less than the median divided by it, was disturbed.
+//! It is left out of the counters, and the rows of the command say how many were.
+//!
+//! When more than `max-excluded-fraction` of the repetitions were disturbed, what is left
+//! isn't worth much either: the command is flagged as unreliable, its rows are marked and the
+//! gate ignores them. With fewer than three repetitions there is no telling which one is off,
+//! and runs without CPU time have no ratio, so these are always kept.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::ops::Not;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bench::BenchCounter;
+use crate::rusage::{self, RunUsage};
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct PreemptionConfig {
+    /// How far the ratio of wall time to CPU time of a repetition may be from the median
+    /// ratio, as a factor.
+    #[serde(default = "default_max_ratio_factor")]
+    pub max_ratio_factor: f64,
+    /// The fraction of the repetitions above which a command is unreliable.
+    #[serde(default = "default_max_excluded_fraction")]
+    pub max_excluded_fraction: f64,
+}
+
+fn default_max_ratio_factor() -> f64 {
+    2.0
+}
+
+fn default_max_excluded_fraction() -> f64 {
+    0.2
+}
+
+impl PreemptionConfig {
+    /// Check what the types of the config can't express.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_ratio_factor.is_nan() || self.max_ratio_factor <= 1.0 {
+            return Err(format!(
+                "the `max-ratio-factor` must be more than 1, not {}",
+                self.max_ratio_factor
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.max_excluded_fraction) {
+            return Err(format!(
+                "the `max-excluded-fraction` must be between 0 and 1, not {}",
+                self.max_excluded_fraction
+            ));
+        }
+        Ok(())
+    }
+
+    /// Whether each of the `runs` was disturbed.
+    pub fn disturbed(&self, runs: &[RunUsage]) -> Vec<bool> {
+        let ratios = runs.iter().map(ratio).collect::<Vec<_>>();
+        let mut known = ratios.iter().flatten().copied().collect::<Vec<_>>();
+        if known.len() < 3 {
+            return vec![false; runs.len()];
+        }
+        known.sort_by(f64::total_cmp);
+        let median = match known.len() % 2 {
+            0 => (known[known.len() / 2 - 1] + known[known.len() / 2]) / 2.0,
+            _ => known[known.len() / 2],
+        };
+        ratios
+            .iter()
+            .map(|ratio| {
+                ratio.is_some_and(|ratio| {
+                    ratio > median * self.max_ratio_factor || ratio < median / self.max_ratio_factor
+                })
+            })
+            .collect()
+    }
+
+    /// The counters of the `runs` without the disturbed ones, and how many were left out, or
+    /// `None` when none were.
+    pub fn exclude(
+        &self,
+        runs: &[RunUsage],
+    ) -> Option<(BTreeMap<String, BenchCounter>, Preempted)> {
+        let disturbed = self.disturbed(runs);
+        let kept = runs
+            .iter()
+            .zip(&disturbed)
+            .filter(|(_, &disturbed)| !disturbed)
+            .map(|(run, _)| run.clone())
+            .collect::<Vec<_>>();
+        let excluded = runs.len() - kept.len();
+        if excluded == 0 {
+            return None;
+        }
+        let preempted = Preempted {
+            excluded: excluded as u32,
+            repetitions: runs.len() as u32,
+            unreliable: excluded as f64 > self.max_excluded_fraction * runs.len() as f64,
+        };
+        Some((rusage::counters(&kept), preempted))
+    }
+}
+
+/// The ratio of the wall time of a run to its CPU time, the task-clock of perf.
+fn ratio(run: &RunUsage) -> Option<f64> {
+    let cpu_time = (run.user_time + run.system_time).as_secs_f64();
+    (cpu_time > 0.0).then(|| run.wall_time.as_secs_f64() / cpu_time)
+}
+
+/// The repetitions of a command left out of its counters.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Preempted {
+    pub excluded: u32,
+    /// All repetitions, including the excluded ones.
+    pub repetitions: u32,
+    /// More than `max-excluded-fraction` of the repetitions were excluded.
+    #[serde(default, skip_serializing_if = "Not::not")]
+    pub unreliable: bool,
+}
+
+impl Preempted {
+    /// The footnote of the rows of the command.
+    pub fn note(&self) -> String {
+        format!(
+            "{} of {} repetitions excluded due to external preemption",
+            self.excluded, self.repetitions
+        )
+    }
+}
+
+/// The footnotes of the rows marked with a dagger, below a table.
+pub fn render_markdown_notes<'a>(
+    md: &mut String,
+    rows: impl IntoIterator<Item = (&'a str, &'a Preempted)>,
+) {
+    let mut rows = rows.into_iter().peekable();
+    if rows.peek().is_none() {
+        return;
+    }
+    md.push('\n');
+    for (name, preempted) in rows {
+        writeln!(md, "† {name}: {}  ", preempted.note()).unwrap();
+    }
+}
+
+/// Whether too many repetitions of the command were excluded.
+pub fn is_unreliable(preempted: Option<&Preempted>) -> bool {
+    preempted.is_some_and(|preempted| preempted.unreliable)
+}
+
+#[cfg(test)]
+fn runs(pairs: &[(u64, u64)]) -> Vec<RunUsage> {
+    use std::time::Duration;
+
+    pairs
+        .iter()
+        .map(|&(wall_ms, cpu_ms)| RunUsage {
+            user_time: Duration::from_millis(cpu_ms),
+            wall_time: Duration::from_millis(wall_ms),
+            ..RunUsage::default()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+fn config() -> PreemptionConfig {
+    serde_json::from_str("{}").unwrap()
+}
+
+#[test]
+fn disturbed_repetitions() {
+    let config = config();
+    // The third run waited for the CPU, the last one ran faster than it used CPU time.
+    let measured = runs(&[
+        (100, 100),
+        (110, 100),
+        (350, 100),
+        (95, 100),
+        (105, 100),
+        (200, 500),
+    ]);
+    assert_eq!(
+        config.disturbed(&measured),
+        [false, false, true, false, false, true]
+    );
+
+    // A slower run that also used more CPU time did more work, it wasn't preempted.
+    assert_eq!(
+        config.disturbed(&runs(&[(100, 100), (300, 300), (100, 100)])),
+        [false; 3]
+    );
+    // No median with fewer than three runs, and no ratio without CPU time.
+    assert_eq!(
+        config.disturbed(&runs(&[(100, 100), (900, 100)])),
+        [false; 2]
+    );
+    assert_eq!(
+        config.disturbed(&runs(&[(100, 0), (100, 100), (900, 0), (100, 100)])),
+        [false; 4]
+    );
+}
+
+#[test]
+fn exclude_disturbed_repetitions() {
+    let config = config();
+    let mut pairs = vec![(100, 100); 18];
+    pairs.extend([(400, 100), (500, 100)]);
+    let (counters, preempted) = config.exclude(&runs(&pairs)).unwrap();
+    assert_eq!(
+        preempted,
+        Preempted {
+            excluded: 2,
+            repetitions: 20,
+            unreliable: false,
+        }
+    );
+    assert_eq!(
+        preempted.note(),
+        "2 of 20 repetitions excluded due to external preemption"
+    );
+    assert_eq!(counters["wall-time"].value, 100.0);
+    assert_eq!(counters["wall-time"].repetitions, 18);
+    assert_eq!(counters["user-time-warm"].repetitions, 17);
+
+    assert_eq!(config.exclude(&runs(&[(100, 100); 5])), None);
+}
+
+#[test]
+fn too_many_disturbed_repetitions() {
+    let config = config();
+    // One in five is still fine, two in five are too many.
+    let (_, preempted) = config
+        .exclude(&runs(&[
+            (100, 100),
+            (100, 100),
+            (100, 100),
+            (100, 100),
+            (900, 100),
+        ]))
+        .unwrap();
+    assert!(!preempted.unreliable);
+    let (_, preempted) = config
+        .exclude(&runs(&[
+            (100, 100),
+            (100, 100),
+            (100, 100),
+            (900, 100),
+            (900, 100),
+        ]))
+        .unwrap();
+    assert!(preempted.unreliable);
+    assert!(is_unreliable(Some(&preempted)));
+    assert!(!is_unreliable(None));
+
+    let strict = PreemptionConfig {
+        max_excluded_fraction: 0.0,
+        ..config
+    };
+    assert!(
+        strict
+            .exclude(&runs(&[(100, 100), (100, 100), (100, 100), (900, 100)]))
+            .unwrap()
+            .1
+            .unreliable
+    );
+}
+
+#[test]
+fn validate_config() {
+    assert_eq!(config().validate(), Ok(()));
+    let config = |json: &str| serde_json::from_str::<PreemptionConfig>(json).unwrap();
+    assert_eq!(
+        config(r#"{ "max-ratio-factor": 1.0 }"#).validate(),
+        Err("the `max-ratio-factor` must be more than 1, not 1".to_owned())
+    );
+    assert_eq!(
+        config(r#"{ "max-excluded-fraction": 1.5 }"#).validate(),
+        Err("the `max-excluded-fraction` must be between 0 and 1, not 1.5".to_owned())
+    );
+}
@@ -0,0 +1,346 @@
+//! Checking that the machine is quiet before measuring anything. A runner that is still busy
+//! with the cleanup of a previous job produces garbage results.
+
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::units;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct PreflightConfig {
+    /// The highest 1 minute load average that counts as quiet.
+    #[serde(default = "default_max_load_average")]
+    pub max_load_average: f64,
+    /// The highest CPU utilization over the sample interval that counts as quiet, in percent
+    /// of all CPUs.
+    #[serde(default = "default_max_cpu_percent")]
+    pub max_cpu_percent: f64,
+    /// The least available memory that counts as quiet, in bytes.
+    #[serde(
+        default,
+        rename = "min-available-memory-mb",
+        deserialize_with = "units::option_mebibytes"
+    )]
+    pub min_available_memory: Option<u64>,
+    /// How often to check again when the system is busy.
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+    #[serde(
+        rename = "retry-delay-secs",
+        default = "default_retry_delay",
+        deserialize_with = "units::secs"
+    )]
+    pub retry_delay: Duration,
+    /// The interval the CPU utilization is measured over.
+    #[serde(
+        rename = "sample-secs",
+        default = "default_sample",
+        deserialize_with = "units::secs"
+    )]
+    pub sample: Duration,
+}
+
+fn default_max_load_average() -> f64 {
+    1.0
+}
+
+fn default_max_cpu_percent() -> f64 {
+    10.0
+}
+
+fn default_retries() -> u32 {
+    3
+}
+
+fn default_retry_delay() -> Duration {
+    Duration::from_secs(30)
+}
+
+fn default_sample() -> Duration {
+    Duration::from_secs(2)
+}
+
+/// The state of the system before the benchmarks ran.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SystemReading {
+    pub load_average: f64,
+    pub cpu_percent: f64,
+    pub available_memory_mb: f64,
+}
+
+/// The outcome of the check, recorded with the results.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Preflight {
+    /// The last reading, taken right before the benchmarks started.
+    pub reading: SystemReading,
+    pub attempts: u32,
+    /// The thresholds the last reading exceeded. Empty when the system was quiet.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub violations: Vec<String>,
+}
+
+impl Preflight {
+    pub fn is_noisy(&self) -> bool {
+        !self.violations.is_empty()
+    }
+}
+
+impl PreflightConfig {
+    /// The thresholds the reading exceeds.
+    pub fn violations(&self, reading: &SystemReading) -> Vec<String> {
+        let mut violations = vec![];
+        if reading.load_average > self.max_load_average {
+            violations.push(format!(
+                "load average {:.2} > {}",
+                reading.load_average, self.max_load_average
+            ));
+        }
+        if reading.cpu_percent > self.max_cpu_percent {
+            violations.push(format!(
+                "CPU utilization {:.1}% > {}%",
+                reading.cpu_percent, self.max_cpu_percent
+            ));
+        }
+        if let Some(min) = self.min_available_memory {
+            let min = min as f64 / (1024.0 * 1024.0);
+            if reading.available_memory_mb < min {
+                violations.push(format!(
+                    "available memory {:.0} MB < {min} MB",
+                    reading.available_memory_mb
+                ));
+            }
+        }
+        violations
+    }
+
+    /// Take readings until the system is quiet or the retries run out.
+    pub fn check(
+        &self,
+        mut sample: impl FnMut() -> Result<SystemReading, String>,
+        mut sleep: impl FnMut(Duration),
+    ) -> Result<Preflight, String> {
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let reading = sample()?;
+            let violations = self.violations(&reading);
+            if violations.is_empty() || attempts > self.retries {
+                return Ok(Preflight {
+                    reading,
+                    attempts,
+                    violations,
+                });
+            }
+
+            eprintln!(
+                "preflight: the system is busy ({}), checking again in {:?}",
+                violations.join(", "),
+                self.retry_delay
+            );
+            sleep(self.retry_delay);
+        }
+    }
+}
+
+/// Read the system state from procfs, measuring the CPU utilization over `interval`.
+pub fn sample(proc: &Path, interval: Duration) -> Result<SystemReading, String> {
+    let read = |name: &str| {
+        fs::read_to_string(proc.join(name))
+            .map_err(|e| format!("failed to read {}: {e}", proc.join(name).display()))
+    };
+
+    let before = CpuTimes::parse(&read("stat")?)?;
+    std::thread::sleep(interval);
+    let after = CpuTimes::parse(&read("stat")?)?;
+
+    Ok(SystemReading {
+        load_average: parse_load_average(&read("loadavg")?)?,
+        cpu_percent: after.utilization_since(&before),
+        available_memory_mb: parse_available_memory_mb(&read("meminfo")?)?,
+    })
+}
+
+/// The aggregate `cpu` line of `/proc/stat`, in clock ticks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct CpuTimes {
+    busy: u64,
+    total: u64,
+}
+
+impl CpuTimes {
+    fn parse(stat: &str) -> Result<Self, String> {
+        let line = stat
+            .lines()
+            .find(|line| line.starts_with("cpu "))
+            .ok_or("no cpu line in /proc/stat")?;
+        let fields = line
+            .split_whitespace()
+            .skip(1)
+            .map(|field| field.parse::<u64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("invalid cpu line in /proc/stat: {e}"))?;
+        if fields.len() < 4 {
+            return Err("invalid cpu line in /proc/stat".to_owned());
+        }
+
+        // user nice system idle iowait irq softirq steal. The guest times are already
+        // included in user and nice.
+        let total = fields.iter().take(8).sum::<u64>();
+        let idle = fields[3] + fields.get(4).copied().unwrap_or(0);
+        Ok(CpuTimes {
+            busy: total - idle,
+            total,
+        })
+    }
+
+    fn utilization_since(&self, before: &Self) -> f64 {
+        let total = self.total.saturating_sub(before.total);
+        if total == 0 {
+            return 0.0;
+        }
+        self.busy.saturating_sub(before.busy) as f64 / total as f64 * 100.0
+    }
+}
+
+fn parse_load_average(loadavg: &str) -> Result<f64, String> {
+    loadavg
+        .split_whitespace()
+        .next()
+        .and_then(|load| load.parse().ok())
+        .ok_or_else(|| format!("invalid /proc/loadavg: {loadavg:?}"))
+}
+
+fn parse_available_memory_mb(meminfo: &str) -> Result<f64, String> {
+    meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix("MemAvailable:"))
+        .and_then(|value| value.trim().strip_suffix("kB"))
+        .and_then(|kb| kb.trim().parse::<f64>().ok())
+        .map(|kb| kb / 1024.0)
+        .ok_or_else(|| "no MemAvailable in /proc/meminfo".to_owned())
+}
+
+/// Warn that the results were measured on a busy system.
+pub fn render_markdown_warning(md: &mut String, preflight: Option<&Preflight>) {
+    let Some(preflight) = preflight.filter(|preflight| preflight.is_noisy()) else {
+        return;
+    };
+
+    writeln!(
+        md,
+        "> [!WARNING]\n> Noisy environment: the system was still busy after {} checks ({}). The results may be unreliable.\n",
+        preflight.attempts,
+        preflight.violations.join(", "),
+    )
+    .unwrap();
+}
+
+#[cfg(test)]
+fn config_for_test() -> PreflightConfig {
+    serde_json::from_str(r#"{ "min-available-memory-mb": 1024, "retries": 2 }"#).unwrap()
+}
+
+#[cfg(test)]
+fn reading_for_test(load_average: f64, cpu_percent: f64) -> SystemReading {
+    SystemReading {
+        load_average,
+        cpu_percent,
+        available_memory_mb: 4096.0,
+    }
+}
+
+#[test]
+fn preflight_thresholds() {
+    let config = config_for_test();
+    assert_eq!(config.max_load_average, 1.0);
+    assert_eq!(config.max_cpu_percent, 10.0);
+
+    assert!(config.violations(&reading_for_test(0.5, 3.0)).is_empty());
+    assert_eq!(
+        config.violations(&SystemReading {
+            load_average: 2.5,
+            cpu_percent: 37.25,
+            available_memory_mb: 512.0,
+        }),
+        [
+            "load average 2.50 > 1",
+            "CPU utilization 37.2% > 10%",
+            "available memory 512 MB < 1024 MB"
+        ]
+    );
+}
+
+#[test]
+fn preflight_retries_until_quiet() {
+    let config = config_for_test();
+
+    let mut readings = vec![reading_for_test(0.2, 2.0), reading_for_test(3.0, 80.0)];
+    let mut sleeps = vec![];
+    let preflight = config
+        .check(|| Ok(readings.pop().unwrap()), |delay| sleeps.push(delay))
+        .unwrap();
+    assert_eq!(preflight.attempts, 2);
+    assert!(!preflight.is_noisy());
+    assert_eq!(preflight.reading, reading_for_test(0.2, 2.0));
+    assert_eq!(sleeps, [Duration::from_secs(30)]);
+
+    // Never settles: the first check and two retries.
+    let mut sleeps = vec![];
+    let preflight = config
+        .check(
+            || Ok(reading_for_test(3.0, 2.0)),
+            |delay| sleeps.push(delay),
+        )
+        .unwrap();
+    assert_eq!(preflight.attempts, 3);
+    assert_eq!(sleeps.len(), 2);
+    assert_eq!(preflight.violations, ["load average 3.00 > 1"]);
+
+    let mut md = String::new();
+    render_markdown_warning(&mut md, Some(&preflight));
+    assert_eq!(
+        md,
+        "> [!WARNING]\n> Noisy environment: the system was still busy after 3 checks (load average 3.00 > 1). The results may be unreliable.\n\n"
+    );
+
+    assert!(config
+        .check(|| Err("no procfs".to_owned()), |_| unreachable!())
+        .is_err());
+}
+
+#[test]
+fn sample_procfs() {
+    let dir = crate::testkit::test_dir("preflight-procfs");
+    fs::write(dir.join("loadavg"), "0.42 0.30 0.25 1/345 6789\n").unwrap();
+    fs::write(
+        dir.join("meminfo"),
+        "MemTotal:       16384000 kB\nMemFree:         1024000 kB\nMemAvailable:    8192000 kB\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("stat"),
+        "cpu  100 0 50 800 50 0 0 0 0 0\ncpu0 100 0 50 800 50 0 0 0 0 0\n",
+    )
+    .unwrap();
+
+    let reading = sample(&dir, Duration::ZERO).unwrap();
+    assert_eq!(reading.load_average, 0.42);
+    assert_eq!(reading.available_memory_mb, 8000.0);
+    // Nothing changed in between.
+    assert_eq!(reading.cpu_percent, 0.0);
+
+    let before = CpuTimes::parse("cpu  100 0 50 800 50 0 0 0 0 0\n").unwrap();
+    let after = CpuTimes::parse("cpu  130 0 60 850 60 0 0 0 0 0\n").unwrap();
+    assert_eq!(before.busy, 150);
+    assert_eq!(before.total, 1000);
+    assert_eq!(after.utilization_since(&before), 40.0);
+
+    assert!(CpuTimes::parse("intr 1 2 3\n").is_err());
+    assert!(parse_load_average("").is_err());
+    assert!(parse_available_memory_mb("MemTotal: 1 kB\n").is_err());
+}
@@ -0,0 +1,217 @@
+//! The order in which the groups run, with `priority-for-group`: lower priorities run earlier,
+//! 100 by default, and groups of the same priority in the order of the config. A run cut short,
+//! like by `--fail-fast`, then still has the groups that matter most:
+//!
+//! ```json
+//! "priority-for-group": { "decompress": 10, "experimental": 200 }
+//! ```
+//!
+//! With `"prioritize-rendered-groups": true`, the groups the `render-versus-self` and
+//! `render-versus-other` tables refer to run with priority 50 unless they have one of their
+//! own, before those that only show up in the raw tables.
+//!
+//! Only the order of the measurements changes, the results and the summary keep the groups in
+//! the order of the config. The measurements of the `sentinel-group` still run first and last.
+
+use std::collections::HashSet;
+
+use crate::{sentinel, Config};
+
+pub const DEFAULT_PRIORITY: i64 = 100;
+
+/// The priority of the groups of the comparison tables with `prioritize-rendered-groups`.
+pub const RENDERED_PRIORITY: i64 = 50;
+
+/// The groups the `render-versus-self` and `render-versus-other` tables refer to.
+pub fn rendered_groups(config: &Config) -> HashSet<&str> {
+    let versus_self = config
+        .render_versus_self
+        .values()
+        .flat_map(|table| table.rows.values())
+        .flat_map(|row| [row.before.command.as_str(), row.after.command.as_str()]);
+    let versus_other = config
+        .render_versus_other
+        .values()
+        .map(|table| table.command.as_str());
+    versus_self.chain(versus_other).collect()
+}
+
+/// The priority of `group_name`, given the groups of the comparison tables.
+fn priority(config: &Config, rendered: &HashSet<&str>, group_name: &str) -> i64 {
+    match config.priority_for_group.get(group_name) {
+        Some(&priority) => priority,
+        None if config.prioritize_rendered_groups && rendered.contains(group_name) => {
+            RENDERED_PRIORITY
+        }
+        None => DEFAULT_PRIORITY,
+    }
+}
+
+/// The groups of `config` in the order they run, with their priorities.
+pub fn execution_order(config: &Config) -> Vec<(String, i64)> {
+    let rendered = rendered_groups(config);
+    let sentinel = config.sentinel_group.as_deref().map(|group_name| {
+        (
+            sentinel::start_key(group_name),
+            sentinel::end_key(group_name),
+        )
+    });
+    let mut order = config
+        .commands
+        .keys()
+        .map(|group_name| {
+            // Both measurements of the sentinel group have the priority of the group.
+            let referenced = sentinel::referenced_group(config, group_name);
+            (group_name.clone(), priority(config, &rendered, &referenced))
+        })
+        .collect::<Vec<_>>();
+    // Stable, so groups of the same priority keep the order of the config.
+    order.sort_by_key(|(group_name, priority)| match &sentinel {
+        Some((start, _)) if group_name == start => (0, 0),
+        Some((_, end)) if group_name == end => (2, 0),
+        _ => (1, *priority),
+    });
+    order
+}
+
+/// The execution order to log, when it differs from the order of the config.
+pub fn describe(config: &Config, order: &[(String, i64)]) -> Option<String> {
+    if order
+        .iter()
+        .map(|(group_name, _)| group_name)
+        .eq(config.commands.keys())
+    {
+        return None;
+    }
+    Some(
+        order
+            .iter()
+            .map(|(group_name, priority)| format!("`{group_name}` ({priority})"))
+            .collect::<Vec<_>>()
+            .join(", "),
+    )
+}
+
+#[cfg(test)]
+fn config_for_test(settings: &str) -> Config {
+    serde_json::from_str(&format!(
+        r#"{{
+            "commands": {{
+                "compress": ["./c 1", "./c 2"],
+                "decompress": ["./d 1", "./d 2"],
+                "checksum": ["./s"],
+                "startup": ["./u"]
+            }},
+            {settings}
+            "render-versus-self": {{
+                "levels": {{
+                    "1 vs 2": {{ "measure": "cycles", "before": {{ "command": "decompress", "index": 0 }}, "after": {{ "command": "decompress", "index": 1 }} }}
+                }}
+            }},
+            "render-versus-other": {{
+                "checksums": {{ "measure": "cycles", "command": "checksum", "rows": {{ "crc": 0 }} }}
+            }}
+        }}"#
+    ))
+    .unwrap()
+}
+
+#[cfg(test)]
+fn names(order: &[(String, i64)]) -> Vec<&str> {
+    order
+        .iter()
+        .map(|(group_name, _)| group_name.as_str())
+        .collect()
+}
+
+#[test]
+fn priority_order() {
+    // The order of the config by default.
+    let config = config_for_test("");
+    let order = execution_order(&config);
+    assert_eq!(
+        names(&order),
+        ["compress", "decompress", "checksum", "startup"]
+    );
+    assert!(order.iter().all(|&(_, priority)| priority == 100));
+    assert_eq!(describe(&config, &order), None);
+
+    // Lower first, ties in the order of the config.
+    let config = config_for_test(
+        r#""priority-for-group": { "startup": 10, "checksum": 10, "compress": 200 },"#,
+    );
+    let order = execution_order(&config);
+    assert_eq!(
+        order,
+        [
+            ("checksum".to_owned(), 10),
+            ("startup".to_owned(), 10),
+            ("decompress".to_owned(), 100),
+            ("compress".to_owned(), 200),
+        ]
+    );
+    assert_eq!(
+        describe(&config, &order).unwrap(),
+        "`checksum` (10), `startup` (10), `decompress` (100), `compress` (200)"
+    );
+
+    // Negative priorities run before the default.
+    let config = config_for_test(r#""priority-for-group": { "startup": -1 },"#);
+    assert_eq!(names(&execution_order(&config))[0], "startup");
+}
+
+#[test]
+fn prioritize_rendered_groups() {
+    let config = config_for_test("");
+    let mut rendered = rendered_groups(&config).into_iter().collect::<Vec<_>>();
+    rendered.sort();
+    assert_eq!(rendered, ["checksum", "decompress"]);
+
+    let config = config_for_test(
+        r#""prioritize-rendered-groups": true, "priority-for-group": { "checksum": 150, "startup": 20 },"#,
+    );
+    assert_eq!(
+        execution_order(&config),
+        [
+            ("startup".to_owned(), 20),
+            ("decompress".to_owned(), 50),
+            ("compress".to_owned(), 100),
+            ("checksum".to_owned(), 150),
+        ]
+    );
+}
+
+#[test]
+fn sentinel_runs_first_and_last() {
+    let mut config = config_for_test(
+        r#""sentinel-group": "compress", "prioritize-rendered-groups": true, "priority-for-group": { "compress": 500, "startup": 1 },"#,
+    );
+    sentinel::expand(&mut config).unwrap();
+    assert_eq!(
+        execution_order(&config),
+        [
+            ("compress@start".to_owned(), 500),
+            ("startup".to_owned(), 1),
+            ("decompress".to_owned(), 50),
+            ("checksum".to_owned(), 50),
+            ("compress@end".to_owned(), 500),
+        ]
+    );
+}
+
+#[test]
+fn order_of_changed_groups() {
+    // The groups `--changed-only` skips don't run, the others keep their order.
+    let mut config = config_for_test(
+        r#""prioritize-rendered-groups": true, "priority-for-group": { "startup": 10 },"#,
+    );
+    config.skip_groups(&[("decompress".to_owned(), "no changes".to_owned())].into());
+    assert_eq!(
+        execution_order(&config),
+        [
+            ("startup".to_owned(), 10),
+            ("checksum".to_owned(), 50),
+            ("compress".to_owned(), 100),
+        ]
+    );
+}
@@ -0,0 +1,519 @@
+//! Profiles of single runs, recorded with `perf record`, to see at a glance which functions a
+//! change in the counters comes from.
+
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+use crate::bench::CommandSpec;
+use crate::command_display::CommandDisplay;
+use crate::compare::find_prev_bench;
+use crate::scratch;
+use crate::BenchData;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ProfileConfig {
+    /// How many symbols to keep per profile.
+    #[serde(default = "default_top_symbols")]
+    pub top_symbols: usize,
+    /// Only report symbols whose share of the samples moved by more than this many
+    /// percentage points.
+    #[serde(default = "default_min_change_points")]
+    pub min_change_points: f64,
+}
+
+fn default_top_symbols() -> usize {
+    10
+}
+
+fn default_min_change_points() -> f64 {
+    1.0
+}
+
+impl Default for ProfileConfig {
+    fn default() -> Self {
+        ProfileConfig {
+            top_symbols: default_top_symbols(),
+            min_change_points: default_min_change_points(),
+        }
+    }
+}
+
+/// Record a profile of a single run of the command, returning the share of samples of the
+/// hottest symbols in percent.
+///
+/// The profile is only informational, so this returns `None` rather than an error when perf
+/// is missing or not allowed to record. The recording goes into the scratch directory.
+pub fn record(
+    perf: &Path,
+    scratch: &Path,
+    cmd: &CommandSpec,
+    top_symbols: usize,
+) -> Option<IndexMap<String, f64>> {
+    let perf_data = scratch::file_path(scratch, "record", "data");
+    let report = record_and_report(perf, cmd, &perf_data);
+    let _ = fs::remove_file(&perf_data);
+
+    Some(hottest(parse_report(&report?), top_symbols))
+}
+
+fn record_and_report(perf: &Path, cmd: &CommandSpec, perf_data: &Path) -> Option<Vec<u8>> {
+    let status = cmd
+        .command(perf)
+        .arg("record")
+        .arg("-g")
+        .arg("--call-graph")
+        .arg("dwarf")
+        .arg("-o")
+        .arg(perf_data)
+        .arg("--")
+        .args(&cmd.argv)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .ok()?;
+    cmd.expected_exit_code(status)?;
+
+    let output = Command::new(perf)
+        .env("LANG", "C")
+        .arg("report")
+        .arg("--stdio")
+        .arg("--percent-limit")
+        .arg("2")
+        .arg("-i")
+        .arg(perf_data)
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+
+    output.status.success().then_some(output.stdout)
+}
+
+/// Parse the output of `perf report --stdio` into the share of samples per symbol.
+///
+/// With call graphs perf reports both the children and the self overhead of every symbol, in
+/// which case the self overhead is used. The call graphs below the entries are skipped. A
+/// symbol reported several times, e.g. once per thread, is summed.
+pub fn parse_report(output: &[u8]) -> IndexMap<String, f64> {
+    let mut symbols = IndexMap::new();
+
+    for line in String::from_utf8_lossy(output).lines() {
+        if let Some((symbol, percentage)) = parse_report_line(line) {
+            *symbols.entry(symbol).or_insert(0.0) += percentage;
+        }
+    }
+
+    symbols
+}
+
+fn parse_report_line(line: &str) -> Option<(String, f64)> {
+    let mut percentages = vec![];
+    let mut rest = line.trim_start();
+    while let Some((column, remainder)) = rest.split_once(char::is_whitespace) {
+        let Some(percentage) = column.strip_suffix('%') else {
+            break;
+        };
+        percentages.push(percentage.parse::<f64>().ok()?);
+        rest = remainder.trim_start();
+    }
+    let self_percentage = *percentages.last()?;
+
+    // The symbol follows a marker like `[.]` for user space or `[k]` for the kernel. The
+    // shared object before it may be in brackets too, e.g. `[kernel.kallsyms]`.
+    let (marker, _) = rest
+        .match_indices("] ")
+        .find(|&(i, _)| i >= 2 && rest.as_bytes()[i - 2] == b'[')?;
+    let symbol = rest[marker + 2..].trim();
+
+    Some((demangle(symbol), self_percentage))
+}
+
+/// The `n` symbols with the largest share, ties broken by name.
+fn hottest(symbols: IndexMap<String, f64>, n: usize) -> IndexMap<String, f64> {
+    let mut symbols = symbols
+        .into_iter()
+        .filter(|&(_, percentage)| percentage > 0.0)
+        .collect::<Vec<_>>();
+    symbols.sort_by(|(a_name, a), (b_name, b)| b.total_cmp(a).then_with(|| a_name.cmp(b_name)));
+    symbols.truncate(n);
+    symbols.into_iter().collect()
+}
+
+/// Demangle legacy Rust symbols perf didn't demangle itself, and strip the hash Rust appends
+/// to them. The hash changes with every build, which would make every symbol look new.
+pub fn demangle(symbol: &str) -> String {
+    let demangled = demangle_legacy(symbol).unwrap_or_else(|| symbol.to_owned());
+
+    match demangled.rsplit_once("::h") {
+        Some((path, hash)) if hash.len() == 16 && hash.chars().all(|c| c.is_ascii_hexdigit()) => {
+            path.to_owned()
+        }
+        _ => demangled,
+    }
+}
+
+/// `_ZN7zlib_rs7deflate7deflate17h0123456789abcdefE` to
+/// `zlib_rs::deflate::deflate::h0123456789abcdef`.
+fn demangle_legacy(symbol: &str) -> Option<String> {
+    let mut rest = symbol.strip_prefix("_ZN")?.strip_suffix('E')?;
+    let mut path = vec![];
+
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit())?;
+        let len = rest[..digits].parse::<usize>().ok()?;
+        let ident = rest.get(digits..digits + len)?;
+        path.push(unescape_ident(ident)?);
+        rest = &rest[digits + len..];
+    }
+
+    (!path.is_empty()).then(|| path.join("::"))
+}
+
+fn unescape_ident(ident: &str) -> Option<String> {
+    // Identifiers starting with an escape get an extra `_` in front.
+    let mut rest = ident
+        .strip_prefix('_')
+        .filter(|rest| rest.starts_with('$'))
+        .unwrap_or(ident);
+    let mut unescaped = String::new();
+
+    while !rest.is_empty() {
+        if let Some(escape) = rest.strip_prefix('$') {
+            let (escape, remainder) = escape.split_once('$')?;
+            let c = match escape {
+                "SP" => '@',
+                "BP" => '*',
+                "RF" => '&',
+                "LT" => '<',
+                "GT" => '>',
+                "LP" => '(',
+                "RP" => ')',
+                "C" => ',',
+                _ => char::from_u32(u32::from_str_radix(escape.strip_prefix('u')?, 16).ok()?)?,
+            };
+            unescaped.push(c);
+            rest = remainder;
+        } else if let Some(remainder) = rest.strip_prefix("..") {
+            unescaped.push_str("::");
+            rest = remainder;
+        } else {
+            let c = rest.chars().next()?;
+            unescaped.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+
+    Some(unescaped)
+}
+
+/// Escape text for use in a markdown table. Symbols are full of `<`, `>`, `*` and `_`.
+pub fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(
+            c,
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '|' | '#' | '!' | '~' | '&'
+        ) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// A symbol whose share of the samples of a command moved compared to the previous results.
+#[derive(Debug, Clone, Serialize)]
+pub struct HotFunctionChange {
+    pub group: String,
+    pub cmd: Vec<String>,
+    pub symbol: String,
+    /// The share in percent, `None` when the symbol wasn't among the hottest symbols.
+    pub before: Option<f64>,
+    pub after: Option<f64>,
+}
+
+impl HotFunctionChange {
+    pub fn delta_points(&self) -> f64 {
+        self.after.unwrap_or(0.0) - self.before.unwrap_or(0.0)
+    }
+}
+
+/// Compare the profiles of the commands that have one in both the current and the previous
+/// results.
+pub fn collect_changes(
+    config: &ProfileConfig,
+    data: &BenchData,
+    prev: &BenchData,
+) -> Vec<HotFunctionChange> {
+    let mut changes = vec![];
+
+    for (group_name, group_results) in &data.bench_groups {
+        let Some(prev_group_results) = prev.bench_groups.get(group_name) else {
+            continue;
+        };
+
+        for bench in group_results {
+            let Some(profile) = &bench.profile else {
+                continue;
+            };
+            let Some(prev_profile) = find_prev_bench(prev_group_results, bench)
+                .and_then(|prev_bench| prev_bench.profile.as_ref())
+            else {
+                continue;
+            };
+
+            let symbols = profile.keys().chain(
+                prev_profile
+                    .keys()
+                    .filter(|symbol| !profile.contains_key(*symbol)),
+            );
+            for symbol in symbols {
+                let change = HotFunctionChange {
+                    group: group_name.clone(),
+                    cmd: bench.cmd.clone(),
+                    symbol: symbol.clone(),
+                    before: prev_profile.get(symbol).copied(),
+                    after: profile.get(symbol).copied(),
+                };
+                if change.delta_points().abs() > config.min_change_points {
+                    changes.push(change);
+                }
+            }
+        }
+    }
+
+    changes
+}
+
+pub fn render_markdown(md: &mut String, changes: &[HotFunctionChange], commands: CommandDisplay) {
+    if changes.is_empty() {
+        return;
+    }
+
+    writeln!(md, "### Hot function changes").unwrap();
+    writeln!(md).unwrap();
+    writeln!(md, "| command | symbol | before | after | Δ |").unwrap();
+    writeln!(md, "| --- | --- | --- | --- | --- |").unwrap();
+
+    let share = |percentage: Option<f64>| match percentage {
+        Some(percentage) => format!("`{percentage:.2}%`"),
+        None => "-".to_owned(),
+    };
+    let mut cells = commands.cells();
+    for change in changes {
+        writeln!(
+            md,
+            "| {} | {} | {} | {} | `{:+.2}pp` |",
+            cells.cell(&change.cmd.join(" ")),
+            escape_markdown(&change.symbol),
+            share(change.before),
+            share(change.after),
+            change.delta_points(),
+        )
+        .unwrap();
+    }
+    cells.render_footnotes(md);
+    writeln!(md).unwrap();
+}
+
+#[cfg(test)]
+fn perf_report_fixture(name: &str) -> Vec<u8> {
+    fs::read(
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("testdata/perf")
+            .join(name),
+    )
+    .unwrap()
+}
+
+#[test]
+fn parse_report_with_children() {
+    let symbols = parse_report(&perf_report_fixture("report-children.txt"));
+
+    assert_eq!(
+        symbols.into_iter().collect::<Vec<_>>(),
+        [
+            ("_start".to_owned(), 0.0),
+            ("zlib_rs::deflate::longest_match".to_owned(), 44.17),
+            ("zlib_rs::deflate::algorithm::slow::deflate_slow".to_owned(), 30.42),
+            (
+                "<zlib_rs::deflate::hash_calc::StandardHashCalc as zlib_rs::deflate::hash_calc::HashCalc>::insert_string".to_owned(),
+                12.03
+            ),
+            ("__memmove_avx_unaligned_erms".to_owned(), 5.11),
+            ("asm_exc_page_fault".to_owned(), 1.64),
+            ("core::ptr::drop_in_place<alloc::vec::Vec<u8>>".to_owned(), 0.0),
+        ]
+    );
+}
+
+#[test]
+fn parse_report_without_children() {
+    let symbols = hottest(parse_report(&perf_report_fixture("report-self.txt")), 2);
+
+    assert_eq!(
+        symbols.into_iter().collect::<Vec<_>>(),
+        [
+            ("zlib_rs::inflate::inflate_fast_help".to_owned(), 61.5),
+            ("zlib_rs::adler32::avx2::adler32_avx2".to_owned(), 20.25),
+        ]
+    );
+
+    // Reported once per thread.
+    let symbols = parse_report(&perf_report_fixture("report-self.txt"));
+    assert_eq!(symbols["__memmove_avx_unaligned_erms"], 12.75);
+}
+
+#[test]
+fn demangle_symbols() {
+    assert_eq!(
+        demangle("_ZN7zlib_rs7deflate7deflate17h0123456789abcdefE"),
+        "zlib_rs::deflate::deflate"
+    );
+    assert_eq!(
+        demangle("_ZN61_$LT$zlib_rs..inflate..Window$u20$as$u20$core..fmt..Debug$GT$3fmt17h0123456789abcdefE"),
+        "<zlib_rs::inflate::Window as core::fmt::Debug>::fmt"
+    );
+    assert_eq!(
+        demangle("zlib_rs::crc32::crc32::h0123456789abcdef"),
+        "zlib_rs::crc32::crc32"
+    );
+    // Not Rust, or not a hash.
+    assert_eq!(
+        demangle("__memmove_avx_unaligned_erms"),
+        "__memmove_avx_unaligned_erms"
+    );
+    assert_eq!(demangle("crate::hash::hash"), "crate::hash::hash");
+    assert_eq!(demangle("_ZN3fooE9"), "_ZN3fooE9");
+    assert_eq!(demangle("_ZN99fooE"), "_ZN99fooE");
+}
+
+#[test]
+fn escape_symbols() {
+    assert_eq!(
+        escape_markdown("<alloc::vec::Vec<u8> as core::ops::Drop>::drop"),
+        r"\<alloc::vec::Vec\<u8\> as core::ops::Drop\>::drop"
+    );
+    assert_eq!(escape_markdown("*mut T | &[u8]"), r"\*mut T \| \&\[u8\]");
+    assert_eq!(escape_markdown("__memcpy"), r"\_\_memcpy");
+}
+
+/// A fake `perf` that runs the command for `record` and prints the given report for `report`.
+#[cfg(test)]
+fn fake_perf(dir: &Path, report: &str) -> std::path::PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+
+    let script = dir.join("perf");
+    fs::write(
+        &script,
+        format!(
+            r#"#!/bin/sh
+if [ "$1" = "report" ]; then
+    cat "{}"
+    exit 0
+fi
+while [ "$1" != "--" ]; do
+    if [ "$1" = "-o" ]; then out="$2"; fi
+    shift
+done
+shift
+"$@" || exit $?
+touch "$out"
+"#,
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("testdata/perf")
+                .join(report)
+                .display()
+        ),
+    )
+    .unwrap();
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+
+    script
+}
+
+#[test]
+fn record_profile() {
+    let dir = crate::testkit::test_dir("profile-record");
+    let cmd = CommandSpec::new(vec!["true".to_owned()]);
+
+    let profile = record(&fake_perf(&dir, "report-children.txt"), &dir, &cmd, 3).unwrap();
+    assert_eq!(
+        profile.keys().collect::<Vec<_>>(),
+        [
+            "zlib_rs::deflate::longest_match",
+            "zlib_rs::deflate::algorithm::slow::deflate_slow",
+            "<zlib_rs::deflate::hash_calc::StandardHashCalc as zlib_rs::deflate::hash_calc::HashCalc>::insert_string",
+        ]
+    );
+
+    // Without perf, or when recording fails, there is no profile.
+    assert!(record(&dir.join("does-not-exist"), &dir, &cmd, 3).is_none());
+    let cmd = CommandSpec::new(vec!["false".to_owned()]);
+    assert!(record(&fake_perf(&dir, "report-children.txt"), &dir, &cmd, 3).is_none());
+}
+
+#[test]
+fn hot_function_changes() {
+    let profile = |symbols: &[(&str, f64)]| {
+        Some(
+            symbols
+                .iter()
+                .map(|&(symbol, percentage)| (symbol.to_owned(), percentage))
+                .collect(),
+        )
+    };
+
+    let mut before = crate::testkit::BenchDataBuilder::cycles(
+        "1111111111111111111111111111111111111111",
+        &[("compress", &[("./c 1", 1000.0), ("./c 2", 1000.0)])],
+    )
+    .build();
+    let mut after = crate::testkit::BenchDataBuilder::cycles(
+        "2222222222222222222222222222222222222222",
+        &[("compress", &[("./c 1", 1100.0), ("./c 2", 1000.0)])],
+    )
+    .build();
+    before.bench_groups["compress"][0].profile = profile(&[
+        ("longest_match", 40.0),
+        ("<Vec<u8> as Drop>::drop", 3.0),
+        ("memcpy", 10.0),
+    ]);
+    after.bench_groups["compress"][0].profile =
+        profile(&[("longest_match", 48.5), ("memcpy", 10.5), ("adler32", 2.5)]);
+    // Only the current results have a profile.
+    after.bench_groups["compress"][1].profile = profile(&[("longest_match", 50.0)]);
+
+    let changes = collect_changes(&ProfileConfig::default(), &after, &before);
+    assert_eq!(
+        changes
+            .iter()
+            .map(|change| (change.symbol.as_str(), change.delta_points()))
+            .collect::<Vec<_>>(),
+        [
+            ("longest_match", 8.5),
+            ("adler32", 2.5),
+            ("<Vec<u8> as Drop>::drop", -3.0)
+        ]
+    );
+
+    let mut md = String::new();
+    render_markdown(&mut md, &changes, CommandDisplay::default());
+    assert_eq!(
+        md,
+        "### Hot function changes
+
+| command | symbol | before | after | Δ |
+| --- | --- | --- | --- | --- |
+| `./c 1` | longest\\_match | `40.00%` | `48.50%` | `+8.50pp` |
+| `./c 1` | adler32 | - | `2.50%` | `+2.50pp` |
+| `./c 1` | \\<Vec\\<u8\\> as Drop\\>::drop | `3.00%` | - | `-3.00pp` |
+
+"
+    );
+}
@@ -0,0 +1,456 @@
+//! The quality of the measurements, group by group. A runner whose measurements get noisier
+//! over a long suite shows up as groups with a high median coefficient of variation of their
+//! time counter, and as a rising trend against the baseline run.
+
+use std::fmt::Write;
+
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+use crate::baseline;
+use crate::command_display::CommandDisplay;
+use crate::gate::GateVerdict;
+use crate::markers::{Marker, Markers};
+use crate::{BenchData, VersusOther};
+
+/// The time counters a group is judged by when no `counter` is configured, in order of
+/// preference: perf's, then getrusage's.
+const TIME_COUNTERS: &[&str] = &["task-clock", "wall-time"];
+
+/// A change of the median coefficient of variation by less than this fraction of the baseline
+/// one counts as no change.
+const TREND_TOLERANCE: f64 = 0.1;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct QualityConfig {
+    /// The counter to judge every group by, rather than its time counter.
+    #[serde(default)]
+    pub counter: Option<String>,
+    /// The median coefficient of variation, in percent, above which the comparisons of a
+    /// group are unreliable.
+    #[serde(default = "default_max_median_cov_percent")]
+    pub max_median_cov_percent: f64,
+    /// Don't fail the gate on the comparisons of unreliable groups.
+    #[serde(default = "default_suppress_gate")]
+    pub suppress_gate: bool,
+}
+
+pub fn default_max_median_cov_percent() -> f64 {
+    5.0
+}
+
+fn default_suppress_gate() -> bool {
+    true
+}
+
+/// How much the measurements of a group vary.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GroupQuality {
+    pub group: String,
+    pub counter: String,
+    /// The median of the coefficients of variation of the commands, in percent.
+    pub median_cov_percent: f64,
+    /// The command with the highest coefficient of variation, and that coefficient.
+    pub worst_command: String,
+    pub worst_cov_percent: f64,
+    /// The same median in the baseline run, when it has the group and the counter.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub baseline_median_cov_percent: Option<f64>,
+    /// The median is above `max-median-cov-percent`.
+    pub unreliable: bool,
+}
+
+impl QualityConfig {
+    /// The quality of every group of `data` that has the counter, compared with the same
+    /// group of `prev_results`.
+    pub fn collect(&self, data: &BenchData, prev_results: Option<&BenchData>) -> Vec<GroupQuality> {
+        data.bench_groups
+            .keys()
+            .filter_map(|group_name| {
+                let counter = self.counter(data, group_name)?;
+                let current = covs(data, group_name, &counter);
+                let (worst_command, worst_cov_percent) = current
+                    .iter()
+                    .max_by(|(_, a), (_, b)| a.total_cmp(b))
+                    .cloned()?;
+                let median_cov_percent =
+                    baseline::median(current.iter().map(|(_, cov)| *cov).collect());
+                let baseline_median_cov_percent = prev_results
+                    .map(|prev_results| covs(prev_results, group_name, &counter))
+                    .filter(|covs| !covs.is_empty())
+                    .map(|covs| baseline::median(covs.into_iter().map(|(_, cov)| cov).collect()));
+                Some(GroupQuality {
+                    group: group_name.clone(),
+                    counter,
+                    median_cov_percent,
+                    worst_command,
+                    worst_cov_percent,
+                    baseline_median_cov_percent,
+                    unreliable: median_cov_percent > self.max_median_cov_percent,
+                })
+            })
+            .collect()
+    }
+
+    fn counter(&self, data: &BenchData, group_name: &str) -> Option<String> {
+        if let Some(counter) = &self.counter {
+            return Some(counter.clone());
+        }
+        let benches = &data.bench_groups[group_name];
+        TIME_COUNTERS
+            .iter()
+            .find(|counter| {
+                benches
+                    .iter()
+                    .any(|bench| bench.counters.contains_key(**counter))
+            })
+            .map(|counter| counter.to_string())
+    }
+
+    /// Move the gate failures of the `render-versus-other` tables of unreliable groups to the
+    /// suppressed ones, with `suppress-gate`.
+    pub fn suppress_gate(
+        &self,
+        verdict: &mut GateVerdict,
+        render_versus_other: &IndexMap<String, VersusOther>,
+        quality: &[GroupQuality],
+    ) {
+        if !self.suppress_gate {
+            return;
+        }
+
+        let unreliable = |table_name: &str| {
+            render_versus_other.get(table_name).is_some_and(|table| {
+                quality
+                    .iter()
+                    .any(|group| group.unreliable && group.group == table.command)
+            })
+        };
+        for failures in [&mut verdict.failures, &mut verdict.variance_failures] {
+            let (suppressed, kept) = std::mem::take(failures)
+                .into_iter()
+                .partition(|failure| unreliable(&failure.table));
+            *failures = kept;
+            verdict.suppressed.extend::<Vec<_>>(suppressed);
+        }
+    }
+}
+
+/// The coefficient of variation of `counter` of every command of the group, in percent.
+fn covs(data: &BenchData, group_name: &str, counter: &str) -> Vec<(String, f64)> {
+    data.bench_groups
+        .get(group_name)
+        .into_iter()
+        .flatten()
+        .filter_map(|bench| {
+            let cov = bench.counters.get(counter)?.coefficient_of_variation()?;
+            Some((bench.cmd.join(" "), cov * 100.0))
+        })
+        .collect()
+}
+
+impl GroupQuality {
+    /// Whether the median went up, down or stayed the same since the baseline run.
+    pub fn trend(&self) -> Option<&'static str> {
+        let baseline = self.baseline_median_cov_percent?;
+        let tolerance = baseline * TREND_TOLERANCE;
+        Some(if self.median_cov_percent > baseline + tolerance {
+            "↑"
+        } else if self.median_cov_percent < baseline - tolerance {
+            "↓"
+        } else {
+            "→"
+        })
+    }
+}
+
+/// Warn about the groups whose comparisons may be unreliable.
+pub fn render_markdown_warning(md: &mut String, config: &QualityConfig, quality: &[GroupQuality]) {
+    let unreliable = quality
+        .iter()
+        .filter(|group| group.unreliable)
+        .collect::<Vec<_>>();
+    if unreliable.is_empty() {
+        return;
+    }
+
+    writeln!(
+        md,
+        "> [!WARNING]\n> The measurements of these groups vary by more than {}%, their comparisons may be unreliable:",
+        config.max_median_cov_percent
+    )
+    .unwrap();
+    for group in unreliable {
+        writeln!(
+            md,
+            "> - `{}`: median coefficient of variation of {:.2}% in {}",
+            group.group, group.median_cov_percent, group.counter
+        )
+        .unwrap();
+    }
+    if config.suppress_gate {
+        writeln!(md, ">\n> Their regressions don't fail the gate.").unwrap();
+    }
+    writeln!(md).unwrap();
+}
+
+/// The table of the quality of every group, for the bottom of the summary.
+pub fn render_markdown(
+    md: &mut String,
+    quality: &[GroupQuality],
+    markers: &Markers,
+    commands: CommandDisplay,
+) {
+    if quality.is_empty() {
+        return;
+    }
+
+    writeln!(md, "### Measurement quality").unwrap();
+    writeln!(md).unwrap();
+    writeln!(md, "|group|counter|median CoV|worst command|trend|").unwrap();
+    writeln!(md, "|---|---|---|---|---|").unwrap();
+    let mut cells = commands.cells();
+    for group in quality {
+        let marker = if group.unreliable && !markers.unreliable.is_empty() {
+            format!(" {}", markers.unreliable)
+        } else {
+            String::new()
+        };
+        let trend = match (group.trend(), group.baseline_median_cov_percent) {
+            (Some(trend), Some(baseline)) => format!("{trend} from `{baseline:.2}%`"),
+            _ => "n.a.".to_owned(),
+        };
+        writeln!(
+            md,
+            "|{}|{}|`{:.2}%`{marker}|{} (`{:.2}%`)|{trend}|",
+            group.group,
+            group.counter,
+            group.median_cov_percent,
+            cells.cell(&group.worst_command),
+            group.worst_cov_percent,
+        )
+        .unwrap();
+    }
+    cells.render_footnotes(md);
+
+    let used = quality
+        .iter()
+        .filter(|group| group.unreliable)
+        .map(|_| Marker::Unreliable);
+    if let Some(legend) = markers.legend(used) {
+        writeln!(md, "\n{legend}").unwrap();
+    }
+    writeln!(md).unwrap();
+}
+
+#[cfg(test)]
+fn config_for_test() -> QualityConfig {
+    serde_json::from_str(r#"{ "max-median-cov-percent": 5 }"#).unwrap()
+}
+
+/// Commands whose `task-clock` of 100 ms varies by the given percentages.
+#[cfg(test)]
+fn data_for_test(commit_hash: &str, groups: &[(&str, &[f64])]) -> BenchData {
+    groups
+        .iter()
+        .fold(
+            crate::testkit::BenchDataBuilder::new(commit_hash),
+            |data, &(group_name, covs)| {
+                data.group(group_name, |group| {
+                    covs.iter().enumerate().fold(group, |group, (index, cov)| {
+                        let stddev = 100.0 * cov / 100.0;
+                        group.bench(["./c".to_owned(), index.to_string()], |b| {
+                            b.counter("task-clock", 100.0, stddev * stddev, 20, "msec")
+                                .counter("cycles", 1e9, 0.0, 20, "")
+                        })
+                    })
+                })
+            },
+        )
+        .build()
+}
+
+#[test]
+fn median_cov_per_group() {
+    let before = data_for_test(
+        "1111111111111111111111111111111111111111",
+        &[("compress", &[1.0, 1.0, 1.0]), ("decompress", &[2.0, 2.0])],
+    );
+    let after = data_for_test(
+        "2222222222222222222222222222222222222222",
+        &[
+            ("compress", &[1.0, 3.0, 2.0]),
+            ("decompress", &[2.0, 2.1]),
+            ("new", &[8.0, 6.0, 9.0, 1.0]),
+        ],
+    );
+
+    let quality = config_for_test().collect(&after, Some(&before));
+    let summary = quality
+        .iter()
+        .map(|group| {
+            (
+                group.group.as_str(),
+                group.counter.as_str(),
+                (group.median_cov_percent * 100.0).round() / 100.0,
+                group.worst_command.as_str(),
+                group.unreliable,
+                group.trend(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        summary,
+        [
+            ("compress", "task-clock", 2.0, "./c 1", false, Some("↑")),
+            ("decompress", "task-clock", 2.05, "./c 1", false, Some("→")),
+            // An even number of commands, and no baseline.
+            ("new", "task-clock", 7.0, "./c 2", true, None),
+        ]
+    );
+
+    // Another counter, without any variance.
+    let config: QualityConfig = serde_json::from_str(r#"{ "counter": "cycles" }"#).unwrap();
+    let quality = config.collect(&after, None);
+    assert_eq!(quality.len(), 3);
+    assert!(quality.iter().all(|group| group.median_cov_percent == 0.0));
+
+    // Groups without the counter are left out.
+    let quality = QualityConfig {
+        counter: Some("instructions".to_owned()),
+        ..config_for_test()
+    }
+    .collect(&after, None);
+    assert!(quality.is_empty());
+}
+
+#[test]
+fn render_quality() {
+    let before = data_for_test(
+        "1111111111111111111111111111111111111111",
+        &[("compress", &[4.0, 4.0])],
+    );
+    let after = data_for_test(
+        "2222222222222222222222222222222222222222",
+        &[("compress", &[1.0, 2.0]), ("decompress", &[6.0])],
+    );
+    let config = config_for_test();
+    let quality = config.collect(&after, Some(&before));
+
+    let mut md = String::new();
+    render_markdown_warning(&mut md, &config, &quality);
+    assert_eq!(
+        md,
+        "> [!WARNING]\n> The measurements of these groups vary by more than 5%, their comparisons may be unreliable:\n\
+         > - `decompress`: median coefficient of variation of 6.00% in task-clock\n\
+         >\n> Their regressions don't fail the gate.\n\n"
+    );
+
+    let mut md = String::new();
+    render_markdown(
+        &mut md,
+        &quality,
+        &Markers::default(),
+        CommandDisplay::default(),
+    );
+    assert_eq!(
+        md,
+        "### Measurement quality\n\n\
+         |group|counter|median CoV|worst command|trend|\n\
+         |---|---|---|---|---|\n\
+         |compress|task-clock|`1.50%`|`./c 1` (`2.00%`)|↓ from `4.00%`|\n\
+         |decompress|task-clock|`6.00%` ⚠️|`./c 0` (`6.00%`)|n.a.|\n\n\
+         ⚠\u{fe0f} unreliable measurements\n\n"
+    );
+
+    let mut md = String::new();
+    render_markdown_warning(&mut md, &config, &quality[..1]);
+    render_markdown(&mut md, &[], &Markers::default(), CommandDisplay::default());
+    assert!(md.is_empty());
+}
+
+#[test]
+fn suppress_gate_failures() {
+    use crate::compare::Comparisons;
+    use crate::gate::GateConfig;
+
+    let before = data_for_test(
+        "1111111111111111111111111111111111111111",
+        &[("compress", &[1.0, 1.0]), ("noisy", &[1.0])],
+    );
+    let mut after = data_for_test(
+        "2222222222222222222222222222222222222222",
+        &[("compress", &[1.0, 1.0]), ("noisy", &[12.0])],
+    );
+    // Both take twice as long, the noisy one still significantly so over its 20 repetitions.
+    for bench in after.bench_groups.values_mut().flatten() {
+        bench.counters.get_mut("task-clock").unwrap().value = 200.0;
+    }
+
+    let render: IndexMap<String, VersusOther> = serde_json::from_str(
+        r#"{
+            "compression": { "measure": "task-clock", "command": "compress", "rows": { "level 1": 0, "level 9": 1 } },
+            "noise": { "measure": "task-clock", "command": "noisy", "rows": { "default": 0 } }
+        }"#,
+    )
+    .unwrap();
+    let comparisons = Comparisons {
+        versus_other: crate::compare::collect_versus_other(
+            &render,
+            &IndexMap::new(),
+            None,
+            &before,
+            &after,
+        ),
+        ..Comparisons::default()
+    };
+    let gate = GateConfig {
+        max_regression_percent: 5.0,
+        annotations: false,
+        max_variance_increase: None,
+        baseline: Default::default(),
+        exempt_label: None,
+    };
+
+    let config = config_for_test();
+    let quality = config.collect(&after, Some(&before));
+    assert_eq!(
+        quality
+            .iter()
+            .map(|group| group.unreliable)
+            .collect::<Vec<_>>(),
+        [false, true]
+    );
+
+    // Only the failure of the noisy group is suppressed.
+    let mut verdict = gate.evaluate(&comparisons);
+    assert_eq!(verdict.failures.len(), 3);
+    config.suppress_gate(&mut verdict, &render, &quality);
+    let tables = |failures: &[crate::gate::GateFailure]| {
+        failures
+            .iter()
+            .map(|failure| format!("{} / {}", failure.table, failure.row.name))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        tables(&verdict.failures),
+        ["compression / level 1", "compression / level 9"]
+    );
+    assert_eq!(tables(&verdict.suppressed), ["noise / default"]);
+    assert!(!verdict.passed());
+
+    // With only the noisy group regressing, the gate passes.
+    verdict.failures.clear();
+    assert!(verdict.passed());
+
+    // Unless suppressing is turned off.
+    let mut verdict = gate.evaluate(&comparisons);
+    QualityConfig {
+        suppress_gate: false,
+        ..config_for_test()
+    }
+    .suppress_gate(&mut verdict, &render, &quality);
+    assert_eq!(verdict.failures.len(), 3);
+    assert!(verdict.suppressed.is_empty());
+}
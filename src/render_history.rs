@@ -0,0 +1,492 @@
+//! `benchmarker render-history <results> <output-dir> <config>... [--last <n> | --range
+//! <from>..<to>]`: render the report of every stored result versus the result before it, to
+//! look back at how a range of commits went, without running any command or asking git.
+//!
+//! The results are ordered by commit timestamp. Every result is compared against the previous
+//! one like against its parent, when they are from the same machine: commits without results
+//! in between are skipped over, and at a change of machine there's nothing to compare
+//! against. The report of every commit goes to `<commit>.md` in the output directory, and an
+//! `index.md` links them with the verdict of each, with a marker row where the version of the
+//! package changes between two results.
+
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::baseline;
+use crate::budget;
+use crate::comment;
+use crate::compare::Comparisons;
+use crate::gate::Verdicts;
+use crate::repro;
+use crate::sanitize::Sanitizer;
+use crate::trigger::{self, GitHubContext};
+use crate::{render_step_summary, BenchData, Config};
+
+/// The name of the index in the output directory.
+pub const INDEX: &str = "index.md";
+
+/// Which of the results to render.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Selection {
+    All,
+    /// The last `n` results.
+    Last(usize),
+    /// The results after `from`, up to and including `to`, like `git log <from>..<to>`.
+    Range(String, String),
+}
+
+/// A result of the history, and what it is compared against.
+#[derive(Debug, Clone, Copy)]
+struct Step<'a> {
+    results: &'a BenchData,
+    previous: Previous<'a>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Previous<'a> {
+    /// The first result of the history.
+    None,
+    /// The previous result, from the same machine.
+    SameMachine(&'a BenchData),
+    /// The previous result, from another machine.
+    OtherMachine(&'a BenchData),
+}
+
+impl<'a> Step<'a> {
+    /// The versions before and after this result, when both are known and differ.
+    fn version_change(&self) -> Option<(&'a str, &'a str)> {
+        let previous = match self.previous {
+            Previous::None => return None,
+            Previous::SameMachine(previous) | Previous::OtherMachine(previous) => previous,
+        };
+        match (previous.version.as_deref(), self.results.version.as_deref()) {
+            (Some(old), Some(new)) if old != new => Some((old, new)),
+            _ => None,
+        }
+    }
+}
+
+/// Run the `render-history` subcommand with the arguments after `render-history`, returning
+/// what to print.
+pub fn run(args: impl IntoIterator<Item = String>) -> Result<String, String> {
+    const USAGE: &str = "expected the arguments render-history <results> <output-dir> <config>... [--last <n> | --range <from>..<to>]";
+
+    let mut positional = vec![];
+    let mut selection = Selection::All;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--last" => {
+                let n = args.next().ok_or("expected a number after --last")?;
+                match n.parse() {
+                    Ok(n) if n > 0 => selection = Selection::Last(n),
+                    _ => return Err(format!("expected a positive number after --last, got {n}")),
+                }
+            }
+            "--range" => {
+                let range = args.next().ok_or("expected a range after --range")?;
+                let Some((from, to)) = range.split_once("..") else {
+                    return Err(format!("expected a range like <from>..<to>, got {range}"));
+                };
+                selection = Selection::Range(from.to_owned(), to.to_owned());
+            }
+            _ => positional.push(arg),
+        }
+    }
+    if positional.len() < 3 {
+        return Err(USAGE.to_owned());
+    }
+    let results_path = PathBuf::from(positional.remove(0));
+    let out_dir = PathBuf::from(positional.remove(0));
+    let config_paths = positional
+        .into_iter()
+        .map(PathBuf::from)
+        .collect::<Vec<_>>();
+    let repository = std::env::var("GITHUB_REPOSITORY").map_err(|_| {
+        "GITHUB_REPOSITORY must be set to `<owner>/<repo>` for the links to the commits".to_owned()
+    })?;
+
+    let rendered = render_history(
+        &results_path,
+        &out_dir,
+        &config_paths,
+        &repository,
+        &selection,
+    )?;
+    Ok(format!(
+        "rendered {rendered} reports to {}\n",
+        out_dir.join(INDEX).display()
+    ))
+}
+
+/// Render the reports of the `selection` of the results file at `results_path` to `out_dir`,
+/// returning how many there are.
+fn render_history(
+    results_path: &Path,
+    out_dir: &Path,
+    config_paths: &[PathBuf],
+    repository: &str,
+    selection: &Selection,
+) -> Result<usize, String> {
+    let config = load_config(config_paths)?;
+    let history = read_history(results_path, &config)?;
+    let steps = select(&steps(&history), selection)?;
+
+    fs::create_dir_all(out_dir)
+        .map_err(|e| format!("failed to create {}: {e}", out_dir.display()))?;
+    let mut index = String::new();
+    writeln!(index, "# Benchmark history\n").unwrap();
+    writeln!(index, "| commit | compared with | verdict |").unwrap();
+    writeln!(index, "| --- | --- | --- |").unwrap();
+    let mut rendered = 0;
+    for step in steps {
+        let results = step.results;
+        if let Some((old, new)) = step.version_change() {
+            writeln!(index, "| **v{old} → v{new}** | | |").unwrap();
+        }
+        let previous = match step.previous {
+            Previous::None => {
+                writeln!(
+                    index,
+                    "| `{}` | | No previous results to compare against. |",
+                    results.short_commit_id()
+                )
+                .unwrap();
+                continue;
+            }
+            Previous::OtherMachine(previous) => {
+                writeln!(
+                    index,
+                    "| `{}` | | Not compared, the previous results of `{}` are from another machine. |",
+                    results.short_commit_id(),
+                    previous.short_commit_id()
+                )
+                .unwrap();
+                continue;
+            }
+            Previous::SameMachine(previous) => previous,
+        };
+
+        // Loaded again for every report, as the groups a result skipped are dropped from it.
+        let (report, verdict) =
+            render_report(load_config(config_paths)?, repository, results, previous)?;
+        let path = out_dir.join(report_name(results));
+        fs::write(&path, report).map_err(|e| format!("failed to write {}: {e}", path.display()))?;
+        rendered += 1;
+        writeln!(
+            index,
+            "| [`{}`]({}) | `{}` | {verdict} |",
+            results.short_commit_id(),
+            report_name(results),
+            previous.short_commit_id()
+        )
+        .unwrap();
+    }
+
+    let path = out_dir.join(INDEX);
+    fs::write(&path, index).map_err(|e| format!("failed to write {}: {e}", path.display()))?;
+    Ok(rendered)
+}
+
+fn load_config(config_paths: &[PathBuf]) -> Result<Config, String> {
+    let config = Config::load(config_paths)?;
+    config
+        .validate()
+        .map_err(|err| format!("invalid config: {err}"))?;
+    Ok(config)
+}
+
+/// The file name of the report of `results`.
+fn report_name(results: &BenchData) -> String {
+    format!("{}.md", results.commit_id())
+}
+
+/// The results that could be a baseline, in the order of their commit timestamps, with only
+/// the last results of every commit.
+fn read_history(path: &Path, config: &Config) -> Result<Vec<BenchData>, String> {
+    let contents = fs::read(path).map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+    let persistent_branches =
+        GitHubContext::default().persistent_branches(config.persistent_branches.as_deref());
+    let mut history = Vec::<BenchData>::new();
+    for line in contents.split(|&b| b == b'\n') {
+        let Ok(data) = serde_json::from_slice::<BenchData>(line) else {
+            continue; // Data format likely changed
+        };
+        if data.partial || !trigger::is_persistent(data.trigger.as_ref(), &persistent_branches) {
+            continue;
+        }
+        match history
+            .iter_mut()
+            .find(|other| other.commit_id() == data.commit_id())
+        {
+            Some(other) if other.timestamp <= data.timestamp => *other = data,
+            Some(_) => {}
+            None => history.push(data),
+        }
+    }
+    history.sort_by_key(|data| (data.commit_timestamp, data.timestamp));
+    Ok(history)
+}
+
+/// Every result of the `history`, with the one before it.
+fn steps(history: &[BenchData]) -> Vec<Step<'_>> {
+    history
+        .iter()
+        .enumerate()
+        .map(|(index, results)| {
+            let previous = match index.checked_sub(1).map(|index| &history[index]) {
+                None => Previous::None,
+                Some(previous) if baseline::same_machine(previous, results) => {
+                    Previous::SameMachine(previous)
+                }
+                Some(previous) => Previous::OtherMachine(previous),
+            };
+            Step { results, previous }
+        })
+        .collect()
+}
+
+fn select<'a>(steps: &[Step<'a>], selection: &Selection) -> Result<Vec<Step<'a>>, String> {
+    let range = match selection {
+        Selection::All => 0..steps.len(),
+        Selection::Last(n) => steps.len().saturating_sub(*n)..steps.len(),
+        Selection::Range(from, to) => {
+            let from = find(steps, from)?;
+            let to = find(steps, to)?;
+            if to < from {
+                return Err(format!(
+                    "the results of {} are older than those of {}",
+                    steps[to].results.short_commit_id(),
+                    steps[from].results.short_commit_id()
+                ));
+            }
+            from + 1..to + 1
+        }
+    };
+    Ok(steps[range].to_vec())
+}
+
+/// The index of the results of `commit`, which may be abbreviated.
+fn find(steps: &[Step], commit: &str) -> Result<usize, String> {
+    let mut found = steps
+        .iter()
+        .enumerate()
+        .filter(|(_, step)| !commit.is_empty() && step.results.commit_hash.starts_with(commit));
+    match (found.next(), found.next()) {
+        (Some((index, _)), None) => Ok(index),
+        (None, _) => Err(format!("no results for {commit}")),
+        (Some(_), Some(_)) => Err(format!("several results for {commit}")),
+    }
+}
+
+/// The report of `results` versus `previous`, and its one-line verdict.
+fn render_report(
+    mut config: Config,
+    repository: &str,
+    results: &BenchData,
+    previous: &BenchData,
+) -> Result<(String, String), String> {
+    let sanitizer = match config.sanitize.take() {
+        Some(sanitize) => Sanitizer::new(sanitize, Some(&results.runner)),
+        None => Sanitizer::default(),
+    };
+    let mut results = results.clone();
+    let mut previous = previous.clone();
+    for data in [&mut results, &mut previous] {
+        sanitizer.restore(data, &config.commands);
+        // Only the warnings of a run are of interest, not those of its history.
+        let _ = config.counter_renames.canonicalize(data);
+    }
+    config.skip_groups(&results.skipped_groups);
+
+    let mut comparisons = Comparisons::collect(&config, &results, Some(&previous));
+    repro::attach(&mut comparisons, &config, &results, &sanitizer);
+    let gate = config.evaluate_gate(&comparisons, &results.exemptions);
+    let shadow_gate = config.evaluate_shadow_gate(&comparisons, &results.exemptions);
+    let budgets = budget::evaluate(&config.budgets, &results)?;
+    let summary = render_step_summary(
+        &config,
+        repository,
+        &results,
+        Some(&previous),
+        &comparisons,
+        Verdicts {
+            gate: gate.as_ref(),
+            shadow: shadow_gate.as_ref(),
+        },
+        &budgets,
+    );
+    Ok((
+        sanitizer.sanitize(&summary).into_owned(),
+        comment::verdict(&comparisons, gate.as_ref()),
+    ))
+}
+
+#[cfg(test)]
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("testdata/render-history")
+        .join(name)
+}
+
+#[cfg(test)]
+fn step_for_test(step: &Step) -> (String, String) {
+    let previous = match step.previous {
+        Previous::None => "none".to_owned(),
+        Previous::SameMachine(previous) => previous.short_commit_id(),
+        Previous::OtherMachine(previous) => format!("other machine {}", previous.short_commit_id()),
+    };
+    (step.results.short_commit_id(), previous)
+}
+
+#[test]
+fn pair_results() {
+    let config = load_config(&[fixture("config.json")]).unwrap();
+    let history = read_history(&fixture("history.json"), &config).unwrap();
+    let steps = steps(&history);
+    let pairs = |steps: &[Step]| steps.iter().map(step_for_test).collect::<Vec<_>>();
+    let pair = |commit: &str, previous: &str| (commit.to_owned(), previous.to_owned());
+
+    // In the order of the commits, across the gap of the commit without results, but not
+    // across the change of machine.
+    assert_eq!(
+        pairs(&steps),
+        [
+            pair("1111111", "none"),
+            pair("2222222", "1111111"),
+            pair("4444444", "2222222"),
+            pair("5555555", "other machine 4444444"),
+            pair("6666666", "5555555"),
+        ]
+    );
+
+    let last = select(&steps, &Selection::Last(2)).unwrap();
+    assert_eq!(
+        pairs(&last),
+        [
+            pair("5555555", "other machine 4444444"),
+            pair("6666666", "5555555"),
+        ]
+    );
+    assert_eq!(select(&steps, &Selection::Last(10)).unwrap().len(), 5);
+
+    let range = Selection::Range("1111".to_owned(), "4444444444".to_owned());
+    assert_eq!(
+        pairs(&select(&steps, &range).unwrap()),
+        [pair("2222222", "1111111"), pair("4444444", "2222222")]
+    );
+    let range = Selection::Range("4444".to_owned(), "2222".to_owned());
+    assert_eq!(
+        select(&steps, &range).unwrap_err(),
+        "the results of 2222222 are older than those of 4444444"
+    );
+    let range = Selection::Range("3333".to_owned(), "4444".to_owned());
+    assert_eq!(select(&steps, &range).unwrap_err(), "no results for 3333");
+}
+
+#[test]
+fn render_fixture_history() {
+    let dir = crate::testkit::test_dir("render-history");
+    let rendered = render_history(
+        &fixture("history.json"),
+        &dir,
+        &[fixture("config.json")],
+        "owner/repo",
+        &Selection::All,
+    )
+    .unwrap();
+    assert_eq!(rendered, 3);
+
+    let index = fs::read_to_string(dir.join(INDEX)).unwrap();
+    assert_eq!(
+        index,
+        "# Benchmark history\n\n\
+         | commit | compared with | verdict |\n\
+         | --- | --- | --- |\n\
+         | `1111111` | | No previous results to compare against. |\n\
+         | [`2222222`](2222222222222222222222222222222222222222.md) | `1111111` | 0 significant regressions, 0 significant improvements. The gate passed. |\n\
+         | [`4444444`](4444444444444444444444444444444444444444.md) | `2222222` | 1 significant regressions, 0 significant improvements. The gate failed with 1 failures. |\n\
+         | `5555555` | | Not compared, the previous results of `4444444` are from another machine. |\n\
+         | [`6666666`](6666666666666666666666666666666666666666.md) | `5555555` | 0 significant regressions, 2 significant improvements. The gate passed. |\n"
+    );
+
+    // The reports are the step summaries of the runs, as if they had been compared against
+    // the previous results.
+    let config = load_config(&[fixture("config.json")]).unwrap();
+    let history = read_history(&fixture("history.json"), &config).unwrap();
+    let (expected, _) = render_report(config, "owner/repo", &history[2], &history[1]).unwrap();
+    let report =
+        fs::read_to_string(dir.join("4444444444444444444444444444444444444444.md")).unwrap();
+    assert_eq!(report, expected);
+    assert!(
+        report.contains("with parent [`2222222`](https://github.com/owner/repo/commit/2222222222222222222222222222222222222222)"),
+        "{report}"
+    );
+    assert!(!dir
+        .join("1111111111111111111111111111111111111111.md")
+        .exists());
+    assert!(!dir
+        .join("5555555555555555555555555555555555555555.md")
+        .exists());
+}
+
+#[test]
+fn render_version_changes() {
+    let dir = crate::testkit::test_dir("render-history-versions");
+    // The fixture history, with the version bumped at 4444444 and unknown at 6666666.
+    let history = fs::read_to_string(fixture("history.json")).unwrap();
+    let mut versioned = String::new();
+    for line in history.lines() {
+        let mut data = serde_json::from_str::<serde_json::Value>(line).unwrap();
+        match data["commit_timestamp"].as_u64().unwrap() {
+            ..=300 => data["version"] = "0.4.0".into(),
+            301..=500 => data["version"] = "0.4.1".into(),
+            _ => {}
+        }
+        versioned.push_str(&format!("{data}\n"));
+    }
+    let results_path = dir.join("history.json");
+    fs::write(&results_path, versioned).unwrap();
+
+    render_history(
+        &results_path,
+        &dir.join("out"),
+        &[fixture("config.json")],
+        "owner/repo",
+        &Selection::All,
+    )
+    .unwrap();
+    let index = fs::read_to_string(dir.join("out").join(INDEX)).unwrap();
+    let rows = index
+        .lines()
+        .skip(4)
+        .map(|row| row.split(" | ").next().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        rows,
+        [
+            "| `1111111`",
+            "| [`2222222`](2222222222222222222222222222222222222222.md)",
+            "| **v0.4.0 → v0.4.1**",
+            "| [`4444444`](4444444444444444444444444444444444444444.md)",
+            "| `5555555`",
+            "| [`6666666`](6666666666666666666666666666666666666666.md)",
+        ],
+        "{index}"
+    );
+}
+
+#[test]
+fn render_history_args() {
+    let usage = "expected the arguments render-history <results> <output-dir> <config>... [--last <n> | --range <from>..<to>]";
+    assert_eq!(run(["results.json".to_owned()]).unwrap_err(), usage);
+    assert_eq!(
+        run(["--last".to_owned(), "0".to_owned()]).unwrap_err(),
+        "expected a positive number after --last, got 0"
+    );
+    assert_eq!(
+        run(["--range".to_owned(), "1111".to_owned()]).unwrap_err(),
+        "expected a range like <from>..<to>, got 1111"
+    );
+}
@@ -0,0 +1,278 @@
+//! `benchmarker replay <dir> <config>...`: render the report of a run again from the output
+//! of perf it kept with `keep-perf-output`, without running any command. The counters go
+//! through the same parsing as when they were measured, so a change to the rendering or the
+//! statistics can be checked against the reports of real runs.
+//!
+//! The directory has the raw output of perf at `<group>/<index>.txt`, and a `replay.json`
+//! with the results of the run without their counters, and the baseline they were compared
+//! against.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::baseline::BaselineAnomaly;
+use crate::bench::parse_perf_stat_output;
+use crate::budget;
+use crate::compare::Comparisons;
+use crate::counter_bounds;
+use crate::gate::Verdicts;
+use crate::repro;
+use crate::sanitize::Sanitizer;
+use crate::{render_step_summary, BackendConfig, BenchData, Config};
+
+/// The name of the manifest in the directory.
+pub const MANIFEST: &str = "replay.json";
+
+/// What a run knew besides the output of perf.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Manifest {
+    /// For the links to the commits.
+    pub repository: String,
+    /// The results, with the counters left out.
+    pub results: BenchData,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub baseline: Option<BenchData>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub baseline_anomaly: Option<BaselineAnomaly>,
+}
+
+/// Where to keep the output of perf for the `index`th command of a group, creating the
+/// directory of the group.
+pub fn perf_output_path(dir: &Path, group_name: &str, index: usize) -> Result<PathBuf, String> {
+    if Path::new(group_name).file_name() != Some(group_name.as_ref()) {
+        return Err(format!(
+            "can't keep the perf output of the `{group_name}` group, its name is not a valid directory name"
+        ));
+    }
+
+    let group_dir = dir.join(group_name);
+    fs::create_dir_all(&group_dir)
+        .map_err(|e| format!("failed to create {}: {e}", group_dir.display()))?;
+    Ok(group_dir.join(format!("{index}.txt")))
+}
+
+/// Write the manifest next to the kept output of perf, returning the directory.
+pub fn write_manifest(
+    dir: &Path,
+    repository: &str,
+    results: &BenchData,
+    baseline: Option<&BenchData>,
+    baseline_anomaly: Option<&BaselineAnomaly>,
+) -> Result<PathBuf, String> {
+    let mut results = results.clone();
+    for bench in results.bench_groups.values_mut().flatten() {
+        bench.counters.clear();
+    }
+    let manifest = Manifest {
+        repository: repository.to_owned(),
+        results,
+        baseline: baseline.cloned(),
+        baseline_anomaly: baseline_anomaly.cloned(),
+    };
+
+    let path = dir.join(MANIFEST);
+    fs::create_dir_all(dir).map_err(|e| format!("failed to create {}: {e}", dir.display()))?;
+    fs::write(&path, serde_json::to_string_pretty(&manifest).unwrap())
+        .map_err(|e| format!("failed to write {}: {e}", path.display()))?;
+    Ok(dir.to_owned())
+}
+
+pub fn run(args: impl IntoIterator<Item = String>) -> Result<String, String> {
+    let mut args = args.into_iter();
+    let dir = args.next();
+    let config_paths = args.map(PathBuf::from).collect::<Vec<_>>();
+    let (Some(dir), false) = (dir, config_paths.is_empty()) else {
+        return Err("expected the arguments replay <dir> <config>...".to_owned());
+    };
+
+    replay(Path::new(&dir), &config_paths)
+}
+
+/// The step summary of the run whose perf output was kept in `dir`.
+pub fn replay(dir: &Path, config_paths: &[PathBuf]) -> Result<String, String> {
+    let mut config = Config::load(config_paths)?;
+    config
+        .validate()
+        .map_err(|err| format!("invalid config: {err}"))?;
+
+    let path = dir.join(MANIFEST);
+    let manifest =
+        fs::read(&path).map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+    let Manifest {
+        repository,
+        mut results,
+        mut baseline,
+        baseline_anomaly,
+    } = serde_json::from_slice(&manifest)
+        .map_err(|e| format!("failed to parse {}: {e}", path.display()))?;
+
+    // The groups skipped by `--changed-only` have no perf output.
+    config.skip_groups(&results.skipped_groups);
+    // Only the sampled files of the corpora were measured.
+    config.retain_samples(&results.samples);
+
+    // The baseline may be from before the renames were configured.
+    if let Some(baseline) = &mut baseline {
+        for warning in config.counter_renames.canonicalize(baseline) {
+            eprintln!("warning: {warning}");
+        }
+        for dropped in counter_bounds::drop_from(&config.counter_bounds, baseline) {
+            eprintln!("warning: {}", dropped.warning());
+        }
+    }
+
+    let commit = results.short_commit_id();
+    for (group_name, benches) in &mut results.bench_groups {
+        let other_backends = config
+            .backends_for_group
+            .get(group_name)
+            .is_some_and(|backends| {
+                backends
+                    .iter()
+                    .any(|backend| !matches!(backend, BackendConfig::Perf))
+            });
+        if other_backends {
+            eprintln!(
+                "warning: only the counters of perf are replayed for the `{group_name}` group"
+            );
+        }
+
+        let events = config.perf(group_name, dir).events();
+        for (index, bench) in benches.iter_mut().enumerate() {
+            let path = dir.join(group_name).join(format!("{index}.txt"));
+            let output = fs::read(&path).map_err(|e| {
+                format!(
+                    "no perf output for `{}` of the `{group_name}` group: failed to read {}: {e}",
+                    bench.cmd.join(" "),
+                    path.display()
+                )
+            })?;
+            bench.counters =
+                parse_perf_stat_output(&output, config.repetitions(group_name), &events)
+                    .map_err(|err| format!("{}: {err}", path.display()))?;
+            for warning in config.counter_renames.canonicalize_bench(group_name, bench) {
+                eprintln!("warning: {warning}");
+            }
+            for dropped in counter_bounds::drop_out_of_bounds(
+                &config.counter_bounds,
+                &commit,
+                group_name,
+                bench,
+            ) {
+                eprintln!("warning: {}", dropped.warning());
+            }
+            config.derive_counters(
+                group_name,
+                results.cpu_frequency.as_ref(),
+                &mut bench.counters,
+            );
+        }
+    }
+
+    let mut comparisons = Comparisons::collect(&config, &results, baseline.as_ref());
+    comparisons.baseline_anomaly = baseline_anomaly;
+    let gate = config.evaluate_gate(&comparisons, &results.exemptions);
+    let shadow_gate = config.evaluate_shadow_gate(&comparisons, &results.exemptions);
+    let budgets = budget::evaluate(&config.budgets, &results)?;
+
+    let sanitizer = match config.sanitize.take() {
+        Some(sanitize) => Sanitizer::new(sanitize, Some(&results.runner)),
+        None => Sanitizer::default(),
+    };
+    repro::attach(&mut comparisons, &config, &results, &sanitizer);
+
+    let summary = render_step_summary(
+        &config,
+        &repository,
+        &results,
+        baseline.as_ref(),
+        &comparisons,
+        Verdicts {
+            gate: gate.as_ref(),
+            shadow: shadow_gate.as_ref(),
+        },
+        &budgets,
+    );
+    Ok(sanitizer.sanitize(&summary).into_owned())
+}
+
+#[cfg(test)]
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("testdata/replay")
+        .join(name)
+}
+
+#[test]
+fn replay_golden_report() {
+    let report = replay(&fixture("run"), &[fixture("config.json")]).unwrap();
+    let expected = fs::read_to_string(fixture("expected.md")).unwrap();
+    assert_eq!(report, expected);
+}
+
+#[test]
+fn keep_and_replay() {
+    let dir = crate::testkit::test_dir("keep-and-replay");
+    let original = fixture("run");
+    let manifest: Manifest =
+        serde_json::from_slice(&fs::read(original.join(MANIFEST)).unwrap()).unwrap();
+
+    // Keep the output of the fixture as a run would, with the counters still in the results.
+    let mut results = manifest.results.clone();
+    let events = crate::bench::Perf::new(&dir).events();
+    for (group_name, benches) in &mut results.bench_groups {
+        for (index, bench) in benches.iter_mut().enumerate() {
+            let output = fs::read(original.join(group_name).join(format!("{index}.txt"))).unwrap();
+            bench.counters = parse_perf_stat_output(&output, 20, &events).unwrap();
+            fs::write(perf_output_path(&dir, group_name, index).unwrap(), output).unwrap();
+        }
+    }
+    write_manifest(
+        &dir,
+        &manifest.repository,
+        &results,
+        manifest.baseline.as_ref(),
+        manifest.baseline_anomaly.as_ref(),
+    )
+    .unwrap();
+
+    let kept: Manifest = serde_json::from_slice(&fs::read(dir.join(MANIFEST)).unwrap()).unwrap();
+    assert!(kept
+        .results
+        .bench_groups
+        .values()
+        .flatten()
+        .all(|bench| bench.counters.is_empty()));
+    assert_eq!(
+        replay(&dir, &[fixture("config.json")]).unwrap(),
+        fs::read_to_string(fixture("expected.md")).unwrap()
+    );
+
+    assert_eq!(
+        perf_output_path(&dir, "a/b", 0).unwrap_err(),
+        "can't keep the perf output of the `a/b` group, its name is not a valid directory name"
+    );
+    assert!(perf_output_path(&dir, "..", 0).is_err());
+
+    fs::remove_file(dir.join("compress/1.txt")).unwrap();
+    let err = replay(&dir, &[fixture("config.json")]).unwrap_err();
+    assert!(
+        err.starts_with("no perf output for `./compress 6` of the `compress` group"),
+        "{err}"
+    );
+}
+
+#[test]
+fn replay_args() {
+    assert_eq!(
+        run(["dir".to_owned()]).unwrap_err(),
+        "expected the arguments replay <dir> <config>..."
+    );
+    assert_eq!(
+        run([]).unwrap_err(),
+        "expected the arguments replay <dir> <config>..."
+    );
+}
@@ -0,0 +1,152 @@
+//! A machine-readable summary of what a run did, for the workflow steps that run after the
+//! benchmarks.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use indexmap::IndexMap;
+use serde::Serialize;
+
+use crate::baseline::BaselineAnomaly;
+use crate::budget::BudgetResult;
+use crate::build_info::BuildDifference;
+use crate::counter_bounds::DroppedCounter;
+use crate::drift::Drift;
+use crate::flush::FlushTime;
+use crate::gate::{GateVerdict, RowVerdicts};
+use crate::sample::SamplingMismatch;
+use crate::sanitize::Sanitizer;
+use crate::staleness::Staleness;
+use crate::stamp::Stamp;
+use crate::totals::SuiteTotal;
+
+/// Filled in as the run progresses, and written when it ends, whether it succeeded or not.
+#[derive(Debug, Default, Serialize)]
+pub struct RunReport {
+    /// The exit code of the process.
+    pub exit_code: i32,
+    pub baseline: Baseline,
+    /// Every configured group, in config order. Groups that didn't get to run are `skipped`.
+    pub groups: IndexMap<String, GroupReport>,
+    /// The benchmarked binaries are byte-identical to those of the baseline, see
+    /// [`crate::fingerprint`].
+    pub identical_binaries: bool,
+    /// The binaries built differently than in the baseline, see [`crate::build_info`].
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub build_differences: Vec<BuildDifference>,
+    /// The groups that sampled other files than the baseline, see [`crate::sample`].
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sampling_mismatches: Vec<SamplingMismatch>,
+    /// The working tree had uncommitted changes, see [`crate::worktree`].
+    pub dirty: bool,
+    /// Only present when a gate is configured and the comparisons got evaluated.
+    pub gate: Option<GateVerdict>,
+    /// The verdict of the `shadow-gate`, which never fails the run. Only present when one is
+    /// configured and the comparisons got evaluated.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shadow_gate: Option<GateVerdict>,
+    /// The outcome of every row under both gates, when a `shadow-gate` is configured.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub gate_rows: Vec<RowVerdicts>,
+    /// The outcome of every budget that is left after the tag filters.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub budgets: Vec<BudgetResult>,
+    /// The counters dropped for being out of bounds, from the results of the run and from the
+    /// baseline, see [`crate::counter_bounds`].
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub dropped_counters: Vec<DroppedCounter>,
+    /// The totals of the suite, with `suite-totals`, see [`crate::totals`].
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub suite_totals: Vec<SuiteTotal>,
+    /// Set when a measure rose slowly over the history, with `drift-alarm`, see
+    /// [`crate::drift`].
+    pub drift_detected: bool,
+    /// The measures that drifted.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub drift: Vec<Drift>,
+    /// The files written by the run, by kind.
+    pub artifacts: IndexMap<String, PathBuf>,
+    /// What produced the verdict, see [`crate::stamp`].
+    pub stamp: Stamp,
+}
+
+/// The previous results the run compared against.
+#[derive(Debug, Default, Serialize)]
+pub struct Baseline {
+    pub commit: Option<String>,
+    /// How many commits before the merge base the baseline is, when the merge base has no
+    /// results.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ancestor_distance: Option<usize>,
+    /// Why there is no baseline.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Set when the baseline deviates from the results of the commits before it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub anomaly: Option<BaselineAnomaly>,
+    /// How old the baseline is, with `baseline-staleness`. Its `stale` flag is set when it is
+    /// older than the thresholds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub staleness: Option<Staleness>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct GroupReport {
+    pub status: GroupStatus,
+    pub commands: usize,
+    pub completed: usize,
+    pub failed: usize,
+    /// Why the commands failed, for the failures that don't stop the run, see
+    /// [`crate::required_counters`].
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<CommandError>,
+    /// The measurement backends, in the order they ran.
+    pub backends: Vec<String>,
+    /// The config file that defined the group, when there are several.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config: Option<PathBuf>,
+    /// Why `--changed-only` skipped the group.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skip_reason: Option<String>,
+    /// The time spent flushing the caches between the measured runs, see [`crate::flush`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flush: Option<FlushTime>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct CommandError {
+    pub command: String,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GroupStatus {
+    Skipped,
+    Completed,
+    Failed,
+}
+
+impl GroupReport {
+    pub fn skipped(commands: usize) -> Self {
+        GroupReport {
+            status: GroupStatus::Skipped,
+            commands,
+            completed: 0,
+            failed: 0,
+            errors: vec![],
+            backends: vec![],
+            config: None,
+            skip_reason: None,
+            flush: None,
+        }
+    }
+}
+
+impl RunReport {
+    pub fn write(&self, path: &Path, sanitizer: &Sanitizer) -> Result<(), String> {
+        let json = sanitizer.to_json_pretty(self);
+        fs::write(path, json)
+            .map_err(|e| format!("failed to write the run report to {}: {e}", path.display()))
+    }
+}
@@ -0,0 +1,456 @@
+//! Command lines to reproduce the rows of the step summary by hand. With
+//!
+//! ```json
+//! "repro": true
+//! ```
+//!
+//! every table is followed by a collapsed section with, for every row, the command lines that
+//! measure its commands like the run did, with the backends of the group and its number of
+//! repetitions, and a `benchmarker stat` call with the numbers of the row to recheck whether
+//! the change is significant. They add bulk, so they are off by default.
+//!
+//! The commands run in the directory the benchmarker ran in, the root of the checkout, with
+//! its environment; an isolation wrapper is left out. Every argument is sanitized before it is
+//! quoted for the shell, so that a replacement like `<corpus>` can't turn into a redirection.
+//! Rules that span several arguments still apply, as the summary as a whole is sanitized
+//! afterwards like everything else the run publishes.
+
+use std::borrow::Cow;
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::bench::{Backend, CommandSpec, SingleBench};
+use crate::compare::{ComparisonRow, Comparisons};
+use crate::sanitize::Sanitizer;
+use crate::{BenchData, Config, Reference};
+
+/// How the commands of a group are measured.
+pub struct Entry {
+    pub backends: Vec<Box<dyn Backend>>,
+    pub repetitions: u32,
+}
+
+impl Entry {
+    /// The entry of the command of `bench` in the group, or `None` for a composite, which
+    /// isn't run itself, or an imported command, which was run elsewhere.
+    pub fn of(config: &Config, group_name: &str, bench: &SingleBench) -> Option<Self> {
+        let command = bench.cmd.join(" ");
+        let composite = config.commands.get(group_name).is_some_and(|benches| {
+            benches
+                .iter()
+                .any(|bench| bench.command == command && bench.is_composite())
+        });
+        if composite || bench.imported.is_some() {
+            return None;
+        }
+        Some(Entry {
+            // The scratch directory is only where perf writes its output.
+            backends: config.backends(group_name, Path::new(".")),
+            repetitions: config.repetitions(group_name),
+        })
+    }
+}
+
+/// `arg` quoted for a POSIX shell, if it needs to be.
+pub fn quote(arg: &str) -> Cow<'_, str> {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "_@%+=:,./-".contains(c);
+    if !arg.is_empty() && arg.chars().all(plain) {
+        Cow::Borrowed(arg)
+    } else {
+        Cow::Owned(format!("'{}'", arg.replace('\'', r"'\''")))
+    }
+}
+
+/// The command line with every argument sanitized and quoted.
+fn shell_line(argv: &[String], sanitizer: &Sanitizer) -> String {
+    argv.iter()
+        .map(|arg| quote(&sanitizer.sanitize(arg)).into_owned())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The lines that measure the command of `bench` like `entry` did.
+fn measurement(md: &mut String, entry: &Entry, bench: &SingleBench, sanitizer: &Sanitizer) {
+    let cmd = CommandSpec {
+        argv: bench.cmd.clone(),
+        expected_exit_codes: vec![0],
+        wrapper: vec![],
+        current_dir: None,
+        sync_start: None,
+        measure_child: None,
+        verify_output: None,
+        watchdog: None,
+        preemption: None,
+    };
+    let repetitions = entry.repetitions;
+    for backend in &entry.backends {
+        match backend.repro_command_line(&cmd, repetitions) {
+            Some(argv) => {
+                writeln!(md, "# {}, {repetitions} runs", backend.label()).unwrap();
+                writeln!(md, "{}", shell_line(&argv, sanitizer)).unwrap();
+            }
+            None => {
+                let command = shell_line(&cmd.argv, sanitizer);
+                let warmup_runs = backend.warmup_runs();
+                if warmup_runs > 0 {
+                    writeln!(
+                        md,
+                        "# {}, {warmup_runs} warm-up and {repetitions} measured runs",
+                        backend.label()
+                    )
+                    .unwrap();
+                    writeln!(md, "for _ in $(seq {warmup_runs}); do {command}; done").unwrap();
+                } else {
+                    writeln!(md, "# {}, {repetitions} runs", backend.label()).unwrap();
+                }
+                writeln!(
+                    md,
+                    "time (for _ in $(seq {repetitions}); do {command}; done)"
+                )
+                .unwrap();
+            }
+        }
+    }
+}
+
+/// The `benchmarker stat` call that rechecks the significance of the change of `row`.
+fn stat(md: &mut String, row: &ComparisonRow) {
+    writeln!(md, "# is the change of {} significant?", row.measure).unwrap();
+    writeln!(
+        md,
+        "benchmarker stat --old {} --old-variance {} --old-n {} --new {} --new-variance {} --new-n {}",
+        row.before.value,
+        row.before.variance,
+        row.before.repetitions,
+        row.after.value,
+        row.after.variance,
+        row.after.repetitions,
+    )
+    .unwrap();
+}
+
+/// The shell snippet that measures the `measured` commands, each like its entry did, and
+/// rechecks the significance of the changes of the `rows`.
+pub fn snippet(
+    measured: &[(&Entry, &SingleBench)],
+    rows: &[&ComparisonRow],
+    sanitizer: &Sanitizer,
+) -> String {
+    let mut md = String::new();
+    for (entry, bench) in measured {
+        measurement(&mut md, entry, bench, sanitizer);
+    }
+    for row in rows {
+        stat(&mut md, row);
+    }
+    md
+}
+
+/// Set the snippets of the rows of the `render-versus-other` and `render-versus-self` tables
+/// and of every command of the raw tables, when `repro` is enabled.
+pub fn attach(
+    comparisons: &mut Comparisons,
+    config: &Config,
+    data: &BenchData,
+    sanitizer: &Sanitizer,
+) {
+    if !config.repro {
+        return;
+    }
+
+    let bench = |reference: &Reference| {
+        let bench = data
+            .bench_groups
+            .get(&reference.command)?
+            .get(reference.index)?;
+        Some((Entry::of(config, &reference.command, bench)?, bench))
+    };
+
+    for table in &mut comparisons.versus_other {
+        let Some(table_config) = config.render_versus_other.get(&table.name) else {
+            continue;
+        };
+        for row in &mut table.rows {
+            let Some(&index) = table_config.rows.get(&row.name) else {
+                continue;
+            };
+            let reference = Reference {
+                command: table_config.command.clone(),
+                index,
+            };
+            if let Some((entry, bench)) = bench(&reference) {
+                row.repro = Some(snippet(&[(&entry, bench)], &[row], sanitizer));
+            }
+        }
+    }
+
+    for table in &mut comparisons.versus_self {
+        let Some(table_config) = config.render_versus_self.get(&table.name) else {
+            continue;
+        };
+        for row in &mut table.rows {
+            let Some(row_config) = table_config.rows.get(&row.name) else {
+                continue;
+            };
+            if let (Some((before_entry, before)), Some((after_entry, after))) =
+                (bench(&row_config.before), bench(&row_config.after))
+            {
+                row.repro = Some(snippet(
+                    &[(&before_entry, before), (&after_entry, after)],
+                    &[row],
+                    sanitizer,
+                ));
+            }
+        }
+    }
+
+    for (group_name, benches) in &data.bench_groups {
+        let raw = comparisons
+            .raw
+            .iter()
+            .find(|table| &table.name == group_name);
+        let mut snippets = vec![];
+        for bench in benches {
+            let Some(entry) = Entry::of(config, group_name, bench) else {
+                continue;
+            };
+            let command = bench.cmd.join(" ");
+            // The rows of the command are named `<command> (<counter>)`.
+            let rows = raw
+                .iter()
+                .flat_map(|table| &table.rows)
+                .filter(|row| {
+                    row.is_actionable() && row.name == format!("{command} ({})", row.measure)
+                })
+                .collect::<Vec<_>>();
+            snippets.push((
+                format!("`{command}`"),
+                snippet(&[(&entry, bench)], &rows, sanitizer),
+            ));
+        }
+        comparisons.raw_repro.insert(group_name.clone(), snippets);
+    }
+}
+
+/// A collapsed section with the snippets, by the name of their row, if there are any.
+pub fn render_markdown<'a>(
+    md: &mut String,
+    snippets: impl IntoIterator<Item = (&'a str, &'a str)>,
+) {
+    let mut snippets = snippets.into_iter().peekable();
+    if snippets.peek().is_none() {
+        return;
+    }
+
+    // GitHub only renders markdown inside <details> when surrounded by blank lines.
+    writeln!(md, "\n<details>\n<summary>Reproduce</summary>\n").unwrap();
+    for (name, snippet) in snippets {
+        writeln!(md, "{name}\n\n```sh\n{snippet}```\n").unwrap();
+    }
+    writeln!(md, "</details>\n").unwrap();
+}
+
+#[cfg(test)]
+fn entry_for_test(backends: &str, repetitions: u32) -> Entry {
+    let config: Config = serde_json::from_str(&format!(
+        r#"{{
+            "commands": {{ "compress": ["./c 1"] }},
+            "repetitions-for-group": {{ "compress": {repetitions} }},
+            "backends-for-group": {{ "compress": {backends} }},
+            "render-versus-self": {{}},
+            "render-versus-other": {{}}
+        }}"#
+    ))
+    .unwrap();
+    Entry::of(&config, "compress", &bench_for_test(&["./c", "1"], 20)).unwrap()
+}
+
+#[cfg(test)]
+fn bench_for_test(cmd: &[&str], repetitions: u32) -> SingleBench {
+    crate::testkit::BenchDataBuilder::new("1111111111111111111111111111111111111111")
+        .group("compress", |g| {
+            g.bench(cmd.iter().copied(), |b| {
+                b.counter("cycles", 900.0, 100.0, repetitions, "")
+            })
+        })
+        .build()
+        .bench_groups["compress"][0]
+        .clone()
+}
+
+#[test]
+fn quote_arguments() {
+    assert_eq!(quote("./target/release/bench"), "./target/release/bench");
+    assert_eq!(quote("--level=6"), "--level=6");
+    assert_eq!(quote(""), "''");
+    assert_eq!(quote("a b"), "'a b'");
+    assert_eq!(quote("it's"), r"'it'\''s'");
+    assert_eq!(quote(r#""quoted""#), r#"'"quoted"'"#);
+    assert_eq!(quote("$HOME"), "'$HOME'");
+    assert_eq!(quote("<corpus>"), "'<corpus>'");
+    assert_eq!(quote("*.gz"), "'*.gz'");
+}
+
+#[test]
+fn snippet_per_backend() {
+    let bench = bench_for_test(&["./c", "it's", "-o", "/home/alice/out file"], 20);
+    let row = ComparisonRow::new(
+        "level 1".to_owned(),
+        "cycles".to_owned(),
+        crate::measure::MeasureKind::Count,
+        &crate::bench::BenchCounter {
+            value: 1000.0,
+            variance: 100.0,
+            repetitions: 20,
+            unit: String::new(),
+        },
+        &bench.counters["cycles"],
+    );
+    let sanitizer = Sanitizer::new(serde_json::from_str("{}").unwrap(), None);
+
+    let perf = entry_for_test(r#"["perf"]"#, 20);
+    assert_eq!(
+        snippet(&[(&perf, &bench)], &[&row], &sanitizer),
+        r"# perf, 20 runs
+LANG=C perf stat -e task-clock,cycles,instructions --repeat 20 -- ./c 'it'\''s' -o '/home/<user>/out file'
+# is the change of cycles significant?
+benchmarker stat --old 1000 --old-variance 100 --old-n 20 --new 900 --new-variance 100 --new-n 20
+"
+    );
+
+    let others = entry_for_test(
+        r#"["getrusage", { "external": "./backend --runs {repetitions} {cmd}" }]"#,
+        5,
+    );
+    assert_eq!(
+        snippet(&[(&others, &bench)], &[], &sanitizer),
+        r"# getrusage, 1 warm-up and 5 measured runs
+for _ in $(seq 1); do ./c 'it'\''s' -o '/home/<user>/out file'; done
+time (for _ in $(seq 5); do ./c 'it'\''s' -o '/home/<user>/out file'; done)
+# external `./backend --runs {repetitions} {cmd}`, 5 runs
+./backend --runs 5 ./c 'it'\''s' -o '/home/<user>/out file'
+"
+    );
+}
+
+#[test]
+fn sanitize_before_quoting() {
+    let entry = entry_for_test(r#"["perf"]"#, 3);
+    let bench = bench_for_test(&["./c", "--corpus", "/srv/corpora/silesia", "-9"], 3);
+
+    // The replacement is quoted, cutting it at the spaces would make two arguments of it.
+    let sanitizer = Sanitizer::new(
+        serde_json::from_str(
+            r#"{ "rules": [{ "pattern": "/srv/corpora/\\w+", "replacement": "<the corpus>" }] }"#,
+        )
+        .unwrap(),
+        None,
+    );
+    let snippet = snippet(&[(&entry, &bench)], &[], &sanitizer);
+    assert_eq!(
+        snippet,
+        "# perf, 3 runs\nLANG=C perf stat -e task-clock,cycles,instructions --repeat 3 -- ./c --corpus '<the corpus>' -9\n"
+    );
+    // Sanitizing the summary again leaves it as is.
+    assert_eq!(sanitizer.sanitize(&snippet), snippet);
+}
+
+#[test]
+fn render_snippets() {
+    let mut md = String::new();
+    render_markdown(&mut md, []);
+    assert_eq!(md, "");
+
+    render_markdown(&mut md, [("level 1", "./c 1\n"), ("`./c 2`", "./c 2\n")]);
+    assert_eq!(
+        md,
+        "
+<details>
+<summary>Reproduce</summary>
+
+level 1
+
+```sh
+./c 1
+```
+
+`./c 2`
+
+```sh
+./c 2
+```
+
+</details>
+
+"
+    );
+}
+
+#[test]
+fn attach_snippets() {
+    let config = |repro: bool| -> Config {
+        serde_json::from_str(&format!(
+            r#"{{
+                "commands": {{ "compress-ng": ["./ng 1"], "compress-rs": ["./rs 1"] }},
+                "backends-for-group": {{ "compress-ng": ["getrusage"], "compress-rs": ["getrusage"] }},
+                "render-versus-self": {{
+                    "ng vs rs": {{
+                        "level 1": {{ "measure": "cycles", "before": {{ "command": "compress-ng", "index": 0 }}, "after": {{ "command": "compress-rs", "index": 0 }} }}
+                    }}
+                }},
+                "render-versus-other": {{}},
+                "repro": {repro}
+            }}"#
+        ))
+        .unwrap()
+    };
+    let data = crate::testkit::BenchDataBuilder::cycles(
+        "2222222222222222222222222222222222222222",
+        &[
+            ("compress-ng", &[("./ng 1", 1000.0)]),
+            ("compress-rs", &[("./rs 1", 900.0)]),
+        ],
+    )
+    .build();
+    let sanitizer = Sanitizer::default();
+
+    let mut comparisons = Comparisons::collect(&config(false), &data, None);
+    attach(&mut comparisons, &config(false), &data, &sanitizer);
+    assert_eq!(comparisons.versus_self[0].rows[0].repro, None);
+    assert!(comparisons.raw_repro.is_empty());
+
+    attach(&mut comparisons, &config(true), &data, &sanitizer);
+    assert_eq!(
+        comparisons.versus_self[0].rows[0].repro.as_deref(),
+        Some(
+            "# getrusage, 1 warm-up and 20 measured runs
+for _ in $(seq 1); do ./ng 1; done
+time (for _ in $(seq 20); do ./ng 1; done)
+# getrusage, 1 warm-up and 20 measured runs
+for _ in $(seq 1); do ./rs 1; done
+time (for _ in $(seq 20); do ./rs 1; done)
+# is the change of cycles significant?
+benchmarker stat --old 1000 --old-variance 100 --old-n 20 --new 900 --new-variance 100 --new-n 20
+"
+        )
+    );
+    // Without previous results, the raw commands are only measured again.
+    assert_eq!(
+        comparisons.raw_repro["compress-rs"],
+        [(
+            "`./rs 1`".to_owned(),
+            "# getrusage, 1 warm-up and 20 measured runs
+for _ in $(seq 1); do ./rs 1; done
+time (for _ in $(seq 20); do ./rs 1; done)
+"
+            .to_owned()
+        )]
+    );
+
+    let mut md = String::new();
+    comparisons.versus_self[0].render_markdown(&mut md, "", &crate::markers::Markers::default());
+    assert!(
+        md.contains("<summary>Reproduce</summary>\n\nlevel 1\n\n```sh\n# getrusage"),
+        "{md}"
+    );
+}
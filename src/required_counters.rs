@@ -0,0 +1,209 @@
+//! A command that perf measured without the counters of the events it was asked for, e.g.
+//! because of a typo in an event name, fails rather than succeeding with an empty table. By
+//! default, every event of the group is required, see [`crate::perf_events`]. A group can
+//! require other counters instead, or none:
+//!
+//! ```json
+//! "required-counters-for-group": { "compress": ["cycles", "instructions"] }
+//! ```
+//!
+//! Perf reporting no counters at all is always a failure. The other commands still run; the
+//! failures are listed in the step summary and the run report, and the run exits with
+//! [`crate::EXIT_MISSING_COUNTERS`].
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::bench::BenchCounter;
+use crate::perf_events;
+use crate::BenchData;
+
+/// The most lines of what perf wrote to stderr kept in an error.
+const MAX_STDERR_LINES: usize = 20;
+
+/// The error of a command that perf measured without some of the `required` counters, or
+/// without any, with the lines perf wrote to `stderr`, like its `event syntax error`. `None`
+/// when nothing is missing.
+pub fn check(
+    required: &[String],
+    counters: &BTreeMap<String, BenchCounter>,
+    stderr: &[u8],
+) -> Option<String> {
+    let missing = required
+        .iter()
+        .filter(|name| !counters.keys().any(|counter| is_counter_of(counter, name)))
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+    let mut error = if !missing.is_empty() {
+        format!(
+            "perf reported no data for events: {} — check event names",
+            missing.join(", ")
+        )
+    } else if counters.is_empty() {
+        "perf reported no data for any event — check event names".to_owned()
+    } else {
+        return None;
+    };
+
+    // The counters are in the output file, anything else on stderr is a message of perf, or
+    // the output of the command.
+    let stderr = String::from_utf8_lossy(stderr);
+    let lines = stderr
+        .lines()
+        .map(str::trim_end)
+        .filter(|line| !line.trim_start().is_empty() && !line.trim_start().starts_with('{'))
+        .collect::<Vec<_>>();
+    if !lines.is_empty() {
+        let lines = &lines[lines.len().saturating_sub(MAX_STDERR_LINES)..];
+        write!(error, "\n=== perf stderr ===\n{}", lines.join("\n")).unwrap();
+    }
+    Some(error)
+}
+
+/// Whether `counter` counts the event `name`, also on a PMU of a hybrid CPU, like
+/// `cpu_core/cycles/` for `cycles`.
+pub fn is_counter_of(counter: &str, name: &str) -> bool {
+    counter == name
+        || perf_events::decode(counter)
+            .pmu
+            .is_some_and(|pmu| counter == format!("{pmu}/{name}/"))
+}
+
+/// The commands of `data` that failed, by group, with their errors.
+pub fn failures(data: &BenchData) -> Vec<(&str, String, &str)> {
+    data.bench_groups
+        .iter()
+        .flat_map(|(group_name, benches)| {
+            benches.iter().filter_map(move |bench| {
+                let error = bench.error.as_deref()?;
+                Some((group_name.as_str(), bench.cmd.join(" "), error))
+            })
+        })
+        .collect()
+}
+
+/// A caution listing the commands that failed, with the first line of their error and what
+/// perf wrote to stderr in a code block.
+pub fn render_markdown_warning(md: &mut String, data: &BenchData) {
+    let failures = failures(data);
+    if failures.is_empty() {
+        return;
+    }
+
+    writeln!(
+        md,
+        "> [!CAUTION]\n> {} commands failed, their results are incomplete:",
+        failures.len()
+    )
+    .unwrap();
+    for (group_name, command, error) in failures {
+        let (summary, details) = error.split_once('\n').unwrap_or((error, ""));
+        writeln!(md, "> - {group_name} / `{command}`: {summary}").unwrap();
+        let details = details
+            .lines()
+            .filter(|line| !line.starts_with("=== "))
+            .collect::<Vec<_>>();
+        if !details.is_empty() {
+            writeln!(md, ">\n>   ```").unwrap();
+            for line in details {
+                writeln!(md, ">   {}", line.replace("```", "` ` `")).unwrap();
+            }
+            writeln!(md, ">   ```").unwrap();
+        }
+    }
+    writeln!(md).unwrap();
+}
+
+#[cfg(test)]
+fn counters_for_test(names: &[&str]) -> BTreeMap<String, BenchCounter> {
+    names
+        .iter()
+        .map(|&name| {
+            let counter = BenchCounter {
+                value: 1000.0,
+                variance: 100.0,
+                repetitions: 20,
+                unit: String::new(),
+            };
+            (name.to_owned(), counter)
+        })
+        .collect()
+}
+
+#[test]
+fn check_required_counters() {
+    let required = ["cycles", "instructions"].map(str::to_owned);
+    let counters = counters_for_test(&["cycles", "instructions", "task-clock"]);
+    assert_eq!(check(&required, &counters, b""), None);
+    // The counters of a hybrid CPU, before they are merged.
+    let counters = counters_for_test(&["cpu_core/cycles/", "cpu_core/instructions/"]);
+    assert_eq!(check(&required, &counters, b""), None);
+    // Nothing is required, but something must be reported.
+    assert_eq!(check(&[], &counters, b""), None);
+    assert_eq!(
+        check(&[], &BTreeMap::new(), b"").unwrap(),
+        "perf reported no data for any event — check event names"
+    );
+
+    // A typo in an event name, where perf writes the counters that it did count, and complains
+    // on stderr amid the output of the command.
+    let required = ["instuctions", "cycles", "cycles:u"].map(str::to_owned);
+    let counters = counters_for_test(&["cycles"]);
+    let stderr = b"compressing corpus\n\
+        event syntax error: 'instuctions'\n                     \\___ parser error\n\
+        {\"counter-value\" : \"1\"}\n\n";
+    assert_eq!(
+        check(&required, &counters, stderr).unwrap(),
+        "perf reported no data for events: instuctions, cycles:u — check event names\n\
+         === perf stderr ===\n\
+         compressing corpus\n\
+         event syntax error: 'instuctions'\n                     \\___ parser error"
+    );
+
+    // Only the end of a long stderr is kept.
+    let stderr = (0..100).map(|i| format!("line {i}\n")).collect::<String>();
+    let error = check(&required, &counters, stderr.as_bytes()).unwrap();
+    assert_eq!(error.lines().count(), 2 + MAX_STDERR_LINES);
+    assert!(error.contains("=== perf stderr ===\nline 80\n"));
+    assert!(error.ends_with("\nline 99"));
+}
+
+#[test]
+fn render_failed_commands() {
+    let mut data = crate::testkit::BenchDataBuilder::cycles(
+        "1111111111111111111111111111111111111111",
+        &[("compress", &[("./c 1", 1000.0), ("./c 2", 1000.0)])],
+    )
+    .build();
+    let mut md = String::new();
+    render_markdown_warning(&mut md, &data);
+    assert_eq!(md, "");
+
+    data.bench_groups["compress"][1].error = Some(
+        "perf reported no data for events: instuctions — check event names\n\
+         === perf stderr ===\n\
+         event syntax error: 'instuctions'\n\
+         \x20                    \\___ parser error"
+            .to_owned(),
+    );
+    assert_eq!(
+        failures(&data),
+        [(
+            "compress",
+            "./c 2".to_owned(),
+            data.bench_groups["compress"][1].error.as_deref().unwrap()
+        )]
+    );
+    render_markdown_warning(&mut md, &data);
+    assert_eq!(
+        md,
+        "> [!CAUTION]\n\
+         > 1 commands failed, their results are incomplete:\n\
+         > - compress / `./c 2`: perf reported no data for events: instuctions — check event names\n\
+         >\n\
+         >   ```\n\
+         >   event syntax error: 'instuctions'\n\
+         >                        \\___ parser error\n\
+         >   ```\n\n"
+    );
+}
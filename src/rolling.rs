@@ -0,0 +1,336 @@
+//! Comparing against a rolling baseline: the pooled results of the last entries of the main
+//! branch from the same machine, rather than the single run of the merge base, whose noise
+//! otherwise decides every comparison:
+//!
+//! ```json
+//! "rolling-baseline": { "entries": 5 }
+//! ```
+//!
+//! The `render-versus-other` tables get a column with the change versus the pooled counter,
+//! next to the change versus the parent commit. The gate can use either, or require both, see
+//! [`crate::gate::GateBaseline`].
+
+use serde::{Deserialize, Serialize};
+
+use crate::bench::BenchCounter;
+use crate::compare::{find_prev_bench_at, Comparisons};
+use crate::measure::MeasureKind;
+use crate::{BenchData, Config};
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct RollingBaselineConfig {
+    /// How many results of the main branch to pool, the one of the merge base included.
+    #[serde(default = "default_entries")]
+    pub entries: usize,
+    /// How many commits before the merge base to look for them.
+    #[serde(default = "default_search_commits")]
+    pub search_commits: usize,
+}
+
+fn default_entries() -> usize {
+    5
+}
+
+fn default_search_commits() -> usize {
+    50
+}
+
+/// The change of a row versus the rolling baseline.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RollingChange {
+    /// How many of the entries had the counter. Commands and counters that are newer than
+    /// some of the entries are pooled from the others.
+    pub entries: usize,
+    /// The pooled counter, see [`pool`].
+    pub baseline: BenchCounter,
+    pub delta_percent: f64,
+    /// The two-tailed p-value of the t-test against the pooled counter.
+    pub p_value: f64,
+    /// Whether the change is significant, with the same correction for multiple comparisons
+    /// as the change versus the parent commit.
+    pub significant: bool,
+}
+
+impl RollingChange {
+    pub fn new(entries: usize, baseline: BenchCounter, after: &BenchCounter) -> Self {
+        RollingChange {
+            entries,
+            delta_percent: BenchCounter::improvement_percentage(&baseline, after),
+            p_value: BenchCounter::p_value(&baseline, after),
+            significant: BenchCounter::is_significant(&baseline, after),
+            baseline,
+        }
+    }
+
+    /// The change in the unit it is shown in, see [`MeasureKind::delta`].
+    pub fn delta(&self, kind: MeasureKind, after: &BenchCounter) -> f64 {
+        kind.delta(&self.baseline, after)
+    }
+
+    pub fn format_delta(&self, kind: MeasureKind, after: &BenchCounter) -> String {
+        kind.format_delta(&self.baseline, after)
+    }
+}
+
+/// Pool `counters` as if all their repetitions were a single sample: the mean weighted by
+/// the repetitions, and the variance of the combined sample. The variance includes how much
+/// the means of the runs differ, so the noise between runs counts too. `None` without any
+/// counters.
+pub fn pool(counters: &[&BenchCounter]) -> Option<BenchCounter> {
+    let first = counters.first()?;
+    let repetitions = counters
+        .iter()
+        .map(|counter| counter.repetitions)
+        .sum::<u32>();
+    let n = repetitions as f64;
+    let mean = counters
+        .iter()
+        .map(|counter| counter.value * counter.repetitions as f64)
+        .sum::<f64>()
+        / n;
+
+    let within = counters
+        .iter()
+        .map(|counter| (counter.repetitions as f64 - 1.0).max(0.0) * counter.variance)
+        .sum::<f64>();
+    let between = counters
+        .iter()
+        .map(|counter| counter.repetitions as f64 * (counter.value - mean).powi(2))
+        .sum::<f64>();
+    let variance = if repetitions > 1 {
+        (within + between) / (n - 1.0)
+    } else {
+        0.0
+    };
+
+    Some(BenchCounter {
+        value: mean,
+        variance,
+        repetitions,
+        unit: first.unit.clone(),
+    })
+}
+
+impl RollingBaselineConfig {
+    /// Compare the rows of the `render-versus-other` tables against the pooled counters of
+    /// the `entries`, the results of the main branch from the machine of `data`, nearest
+    /// first. At most [`Self::entries`] of them are pooled. The changes are significant with a
+    /// p-value of at most the `cutoff` of the correction, if any, see [`Comparisons::cutoff`].
+    pub fn apply(
+        &self,
+        comparisons: &mut Comparisons,
+        config: &Config,
+        data: &BenchData,
+        entries: &[&BenchData],
+        cutoff: Option<f64>,
+    ) {
+        let entries = &entries[..entries.len().min(self.entries)];
+
+        for table in &mut comparisons.versus_other {
+            let Some(table_config) = config.render_versus_other.get(&table.name) else {
+                continue;
+            };
+            table.rolling_window = Some(self.entries);
+
+            for row in &mut table.rows {
+                let index = table_config.rows[&row.name];
+                let Some(bench) = data
+                    .bench_groups
+                    .get(&table_config.command)
+                    .and_then(|group| group.get(index))
+                else {
+                    continue;
+                };
+                let counters = entries
+                    .iter()
+                    .filter_map(|entry| {
+                        let group = entry.bench_groups.get(&table_config.command)?;
+                        find_prev_bench_at(group, bench, index)?
+                            .counters
+                            .get(&row.measure)
+                    })
+                    .collect::<Vec<_>>();
+                row.rolling = pool(&counters).map(|baseline| {
+                    let mut change = RollingChange::new(counters.len(), baseline, &row.after);
+                    if let Some(cutoff) = cutoff {
+                        change.significant = change.p_value <= cutoff;
+                    }
+                    change
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn history_for_test() -> Vec<BenchData> {
+    let history = std::fs::read(
+        std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/rolling/history.json"),
+    )
+    .unwrap();
+    history
+        .split(|&b| b == b'\n')
+        .filter_map(|line| serde_json::from_slice(line).ok())
+        .collect()
+}
+
+#[test]
+fn pool_counters() {
+    let counter = |value, variance, repetitions| BenchCounter {
+        value,
+        variance,
+        repetitions,
+        unit: "msec".to_owned(),
+    };
+    assert_eq!(pool(&[]), None);
+
+    // A single counter stays as it is.
+    let single = counter(1000.0, 100.0, 10);
+    assert_eq!(pool(&[&single]).unwrap(), single);
+
+    // Runs with the same mean and variance pool into the same distribution, with more
+    // repetitions.
+    let pooled = pool(&[&single, &single]).unwrap();
+    assert_eq!(pooled.value, 1000.0);
+    assert_eq!(pooled.repetitions, 20);
+    assert!((pooled.variance - 100.0 * 18.0 / 19.0).abs() < 1e-9);
+
+    // The difference between the runs counts: (2700 + 200000) / 29.
+    let pooled = pool(&[
+        &counter(1000.0, 100.0, 10),
+        &counter(1100.0, 100.0, 10),
+        &counter(1200.0, 100.0, 10),
+    ])
+    .unwrap();
+    assert_eq!(pooled.value, 1100.0);
+    assert_eq!(pooled.repetitions, 30);
+    assert!((pooled.variance - 202700.0 / 29.0).abs() < 1e-9);
+    assert_eq!(pooled.unit, "msec");
+
+    // The mean is weighted by the repetitions.
+    let pooled = pool(&[&counter(1000.0, 0.0, 30), &counter(2000.0, 0.0, 10)]).unwrap();
+    assert_eq!(pooled.value, 1250.0);
+}
+
+#[test]
+fn compare_against_rolling_baseline() {
+    let history = history_for_test();
+    let data = crate::testkit::BenchDataBuilder::cycles(
+        "1111111111111111111111111111111111111111",
+        &[("compress", &[("./c 1", 1120.0), ("./c 2", 515.0)])],
+    )
+    .build();
+    let commits = ["2", "3", "4", "5", "6"]
+        .iter()
+        .map(|digit| digit.repeat(40))
+        .collect::<Vec<_>>();
+    // The results of 3333333 are from another machine.
+    let entries = crate::baseline::neighbors(&data, &commits, &history, 3);
+    assert_eq!(
+        entries
+            .iter()
+            .map(|entry| &entry.commit_hash[..1])
+            .collect::<Vec<_>>(),
+        ["2", "4", "5"]
+    );
+
+    let config: Config = serde_json::from_str(
+        r#"{
+            "commands": {},
+            "rolling-baseline": { "entries": 3 },
+            "render-versus-self": {},
+            "render-versus-other": {
+                "compression": { "measure": "cycles", "command": "compress", "rows": { "level 1": 0, "level 2": 1 } }
+            }
+        }"#,
+    )
+    .unwrap();
+    let mut comparisons = Comparisons::collect(&config, &data, Some(entries[0]));
+    let rolling_config = config.rolling_baseline.as_ref().unwrap();
+    let cutoff = comparisons.cutoff(config.correction);
+    rolling_config.apply(&mut comparisons, &config, &data, &entries, cutoff);
+
+    let table = &comparisons.versus_other[0];
+    assert_eq!(table.rolling_window, Some(3));
+    // level 1 is in all three entries: 1000, 1100 and 1200.
+    let level_1 = table.rows[0].rolling.as_ref().unwrap();
+    assert_eq!(level_1.entries, 3);
+    assert_eq!(level_1.baseline.value, 1100.0);
+    assert_eq!(level_1.baseline.repetitions, 30);
+    assert!((level_1.baseline.variance - 202700.0 / 29.0).abs() < 1e-6);
+    // +12% versus the parent, but within the spread of the rolling baseline.
+    assert!(table.rows[0].significant);
+    assert!(!level_1.significant);
+
+    // level 2 is missing in 4444444, so it is pooled from the other two: 500 and 520.
+    let level_2 = table.rows[1].rolling.as_ref().unwrap();
+    assert_eq!(level_2.entries, 2);
+    assert_eq!(level_2.baseline.value, 510.0);
+    assert_eq!(level_2.baseline.repetitions, 20);
+    assert!((level_2.baseline.variance - 2450.0 / 19.0).abs() < 1e-6);
+    assert!(table.rows[1].significant);
+    assert!(!level_2.significant);
+
+    let mut md = String::new();
+    table.render_markdown(
+        &mut md,
+        "| name | before | after | Δ |\n| --- | --- | --- | --- |\n",
+        &crate::markers::Markers::default(),
+    );
+    assert_eq!(
+        md,
+        "| name | before | after | Δ | vs rolling(3) |\n\
+         | --- | --- | --- | --- | --- |\n\
+         | level 1 <a id=\"cmp-659734703b347b7d\"></a> | `  1.00K ±      10` | `  1.12K ±      10` | `💩 +10.71%` | `    +1.79%` |\n\
+         | level 2 <a id=\"cmp-0003c1e193e96e7a\"></a> | `    500 ±       5` | `    515 ±      10` | `💩  +2.91%` | `    +0.97%` (2 of 3) |\n\n\
+         💩 significant regression\n"
+    );
+}
+
+#[test]
+fn gate_against_rolling_baseline() {
+    let history = history_for_test();
+    let data = crate::testkit::BenchDataBuilder::cycles(
+        "1111111111111111111111111111111111111111",
+        &[("compress", &[("./c 1", 1120.0), ("./c 2", 515.0)])],
+    )
+    .build();
+    let config: Config = serde_json::from_str(
+        r#"{
+            "commands": {},
+            "render-versus-self": {},
+            "render-versus-other": {
+                "compression": { "measure": "cycles", "command": "compress", "rows": { "level 1": 0, "level 2": 1 } }
+            }
+        }"#,
+    )
+    .unwrap();
+    let entries = [&history[0], &history[2], &history[3]];
+    let mut comparisons = Comparisons::collect(&config, &data, Some(entries[0]));
+    let gate = |baseline: &str, comparisons: &Comparisons| {
+        let gate: crate::gate::GateConfig = serde_json::from_str(&format!(
+            r#"{{ "max-regression-percent": 5, "baseline": "{baseline}" }}"#
+        ))
+        .unwrap();
+        gate.evaluate(comparisons)
+            .failures
+            .into_iter()
+            .map(|failure| failure.row.name)
+            .collect::<Vec<_>>()
+    };
+
+    // Without a rolling baseline, every mode falls back to the parent commit.
+    for baseline in ["parent", "rolling", "both"] {
+        assert_eq!(gate(baseline, &comparisons), ["level 1"], "{baseline}");
+    }
+
+    RollingBaselineConfig {
+        entries: 3,
+        search_commits: 0,
+    }
+    .apply(&mut comparisons, &config, &data, &entries, None);
+    assert_eq!(gate("parent", &comparisons), ["level 1"]);
+    assert!(gate("rolling", &comparisons).is_empty());
+    assert!(gate("both", &comparisons).is_empty());
+}
@@ -0,0 +1,353 @@
+//! Measuring the whole suite in passes with `round-robin`. Running all repetitions of one
+//! command before the next lets slow drift of the machine, like the CPU heating up or an
+//! indexer starting in the background, affect the commands unequally, by where they are in
+//! the suite. Instead, every pass runs a single repetition of every command of the suite, so
+//! the runs of every command are spread over the whole run:
+//!
+//! ```json
+//! "round-robin": true
+//! ```
+//!
+//! The commands run in the order of their groups, see [`crate::priority`], and their order in
+//! the group. A group with fewer `repetitions-for-group` than others drops out of the later
+//! passes. Backends that warm up get unmeasured passes first, like [`crate::interleave`]. The
+//! runs of every command are aggregated as if it was measured on its own.
+//!
+//! A group starts before the first pass, which runs all groups, and is done after the last
+//! pass that runs it: its status, the outputs it left and `--fail-fast` are only checked then,
+//! when the groups are done in their order. The thermal samples see every group running in
+//! every pass it is in, see [`crate::thermal`].
+//!
+//! Not measured round-robin are the composites, which are measured from their steps, the
+//! imported commands, which aren't measured at all, the commands with `produces`, whose outputs
+//! are collected after every measurement, the commands measured once for several groups, see
+//! [`crate::dedupe`], and those of the `sentinel-group`, which measure the start and the end of
+//! the run, see [`crate::sentinel`]. Their groups still run them as usual.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use indexmap::IndexMap;
+
+use crate::bench::{Backend, CommandSpec, SingleBench};
+use crate::interleave;
+use crate::sentinel;
+use crate::thermal::GroupWindow;
+use crate::verify_output::Hashes;
+use crate::Config;
+
+/// A command measured round-robin.
+pub struct RoundRobinCommand {
+    pub group: String,
+    /// The index of the command in its group.
+    pub index: usize,
+    pub spec: CommandSpec,
+    pub repetitions: u32,
+}
+
+/// The results of the commands measured round-robin.
+#[derive(Debug, Default)]
+pub struct Measured {
+    results: HashMap<(String, usize), SingleBench>,
+    /// When every group ran, a window for every pass it was in.
+    pub windows: Vec<GroupWindow>,
+}
+
+impl Measured {
+    /// Whether all `indices` of the group were measured round-robin.
+    pub fn covers(&self, group_name: &str, indices: &[usize]) -> bool {
+        indices
+            .iter()
+            .all(|&index| self.results.contains_key(&(group_name.to_owned(), index)))
+    }
+
+    /// The results of the `indices` of the group, see [`Self::covers`].
+    pub fn take(&mut self, group_name: &str, indices: &[usize]) -> Vec<SingleBench> {
+        indices
+            .iter()
+            .map(|&index| {
+                self.results
+                    .remove(&(group_name.to_owned(), index))
+                    .expect("only covered commands are taken")
+            })
+            .collect()
+    }
+}
+
+/// The commands of the groups measured round-robin, by group in the `order` the groups run
+/// and by index, see the module docs. `shared` tells the commands measured once for several
+/// groups.
+pub fn plan<'a>(
+    config: &Config,
+    order: impl IntoIterator<Item = &'a str>,
+    mut shared: impl FnMut(&str, usize) -> bool,
+) -> Vec<(String, usize)> {
+    let sentinel = config.sentinel_group.as_deref().map(|group_name| {
+        [
+            sentinel::start_key(group_name),
+            sentinel::end_key(group_name),
+        ]
+    });
+    let mut commands = vec![];
+    for group_name in order {
+        if sentinel
+            .as_ref()
+            .is_some_and(|keys| keys.iter().any(|key| key == group_name))
+        {
+            continue;
+        }
+        for (index, bench) in config.commands[group_name].iter().enumerate() {
+            if bench.is_composite()
+                || bench.import.is_some()
+                || !bench.produces.is_empty()
+                || shared(group_name, index)
+            {
+                continue;
+            }
+            commands.push((group_name.to_owned(), index));
+        }
+    }
+    commands
+}
+
+/// The commands every pass runs, by their index in `repetitions`: every command runs in the
+/// `warmup_runs` passes and then in as many passes as it has repetitions.
+pub fn passes(repetitions: &[u32], warmup_runs: u32) -> Vec<Vec<usize>> {
+    let last = repetitions.iter().max().map_or(0, |max| warmup_runs + max);
+    (0..last)
+        .map(|pass| {
+            (0..repetitions.len())
+                .filter(|&index| pass < warmup_runs + repetitions[index])
+                .collect()
+        })
+        .collect()
+}
+
+/// Measure the `cmds` round-robin, each with the backends of its group. With a `clock`, the
+/// time since the thermal sampler started, the windows in which every group ran are recorded.
+pub fn bench(
+    cmds: &[RoundRobinCommand],
+    backends: &IndexMap<String, Vec<Box<dyn Backend>>>,
+    clock: Option<&dyn Fn() -> Duration>,
+) -> Result<Measured, String> {
+    eprintln!(
+        "Benchmarking {} commands of {} groups round-robin",
+        cmds.len(),
+        backends.len()
+    );
+
+    let warmup_runs = backends
+        .values()
+        .flatten()
+        .map(|backend| backend.warmup_runs())
+        .max()
+        .unwrap_or(0);
+    let repetitions = cmds.iter().map(|cmd| cmd.repetitions).collect::<Vec<_>>();
+    // The runs of every command, by backend.
+    let mut runs = cmds
+        .iter()
+        .map(|cmd| backends[&cmd.group].iter().map(|_| vec![]).collect())
+        .collect::<Vec<Vec<_>>>();
+    let mut hashes = cmds.iter().map(|_| Hashes::default()).collect::<Vec<_>>();
+    // Why the commands the watchdog killed hung, they sit out the rest of the passes.
+    let mut hung = cmds.iter().map(|_| None).collect::<Vec<_>>();
+    let mut windows = vec![];
+    for (pass, indices) in passes(&repetitions, warmup_runs).iter().enumerate() {
+        let pass = pass as u32;
+        let mut window: Option<GroupWindow> = None;
+        for &index in indices {
+            let cmd = &cmds[index];
+            let group_backends = &backends[&cmd.group];
+            let warm = |backend: &dyn Backend| pass + backend.warmup_runs() >= warmup_runs;
+            if hung[index].is_some() || !group_backends.iter().any(|backend| warm(backend.as_ref()))
+            {
+                continue;
+            }
+            if let Some(clock) = clock {
+                if window
+                    .as_ref()
+                    .is_none_or(|window| window.group != cmd.group)
+                {
+                    windows.extend(window.take().map(|window| GroupWindow {
+                        end: clock(),
+                        ..window
+                    }));
+                    let start = clock();
+                    window = Some(GroupWindow {
+                        group: cmd.group.clone(),
+                        start,
+                        end: start,
+                    });
+                }
+            }
+            for (backend, runs) in group_backends.iter().zip(&mut runs[index]) {
+                if !warm(backend.as_ref()) || hung[index].is_some() {
+                    continue;
+                }
+                hung[index] = interleave::run_once(
+                    &cmd.spec,
+                    backend.as_ref(),
+                    pass < warmup_runs,
+                    runs,
+                    &mut hashes[index],
+                )?;
+            }
+        }
+        if let (Some(clock), Some(window)) = (clock, window) {
+            windows.push(GroupWindow {
+                end: clock(),
+                ..window
+            });
+        }
+    }
+
+    let mut results = HashMap::new();
+    for (((cmd, runs), hashes), hung) in cmds.iter().zip(runs).zip(hashes).zip(hung) {
+        let result = interleave::aggregate(&cmd.spec, &backends[&cmd.group], runs, hashes, hung)?;
+        results.insert((cmd.group.clone(), cmd.index), result);
+    }
+    Ok(Measured { results, windows })
+}
+
+#[test]
+fn passes_with_unequal_repetitions() {
+    // Every pass runs every command with repetitions left, in order.
+    assert_eq!(passes(&[3, 1, 2], 0), [vec![0, 1, 2], vec![0, 2], vec![0]]);
+    // The warmup passes run every command.
+    assert_eq!(
+        passes(&[2, 1, 1], 1),
+        [vec![0, 1, 2], vec![0, 1, 2], vec![0]]
+    );
+    assert!(passes(&[], 1).is_empty());
+}
+
+#[test]
+fn plan_the_suite() {
+    let mut config: Config = serde_json::from_str(
+        r#"{
+            "commands": {
+                "compress": [
+                    "./c 1",
+                    "./c 9",
+                    { "composite": "./c 1 | ./d", "steps": ["./c 1 -", "./d -"] },
+                    { "command": "./c out", "produces": ["out.gz"] }
+                ],
+                "decompress": ["./d", "./c 1"],
+                "noise": ["./noop"]
+            },
+            "sentinel-group": "noise",
+            "render-versus-other": {},
+            "render-versus-self": {}
+        }"#,
+    )
+    .unwrap();
+    sentinel::expand(&mut config).unwrap();
+
+    // In the order the groups run, with the steps of the composite but not the composite
+    // itself, the command with outputs, the shared one and those of the sentinel group.
+    let commands = plan(&config, ["decompress", "compress"], |group_name, index| {
+        group_name == "decompress" && index == 1
+    });
+    assert_eq!(
+        commands,
+        [
+            ("decompress".to_owned(), 0),
+            ("compress".to_owned(), 0),
+            ("compress".to_owned(), 1),
+            ("compress".to_owned(), 2),
+            ("compress".to_owned(), 3),
+        ]
+    );
+    let groups = config.commands.keys().cloned().collect::<Vec<_>>();
+    assert_eq!(
+        plan(&config, groups.iter().map(String::as_str), |_, _| false).len(),
+        6
+    );
+}
+
+#[test]
+fn round_robin_runs() {
+    use crate::interleave::RecordingBackend;
+
+    let log = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+    let recording = |name, warmup_runs| -> Box<dyn Backend> {
+        Box::new(RecordingBackend {
+            name,
+            warmup_runs,
+            log: log.clone(),
+        })
+    };
+    let backends = IndexMap::from([
+        ("compress".to_owned(), vec![recording("a", 0)]),
+        (
+            "decompress".to_owned(),
+            vec![recording("a", 0), recording("b", 1)],
+        ),
+    ]);
+    let cmd = |group: &str, index, argv: &str, repetitions| RoundRobinCommand {
+        group: group.to_owned(),
+        index,
+        spec: CommandSpec::new(vec![argv.to_owned()]),
+        repetitions,
+    };
+    let cmds = [
+        cmd("compress", 0, "./c1", 3),
+        cmd("compress", 1, "./c9", 3),
+        cmd("decompress", 0, "./d", 1),
+    ];
+
+    let ticks = std::cell::Cell::new(0);
+    let clock = || {
+        ticks.set(ticks.get() + 1);
+        Duration::from_secs(ticks.get())
+    };
+    let mut measured = bench(&cmds, &backends, Some(&clock)).unwrap();
+    // One warmup pass for `b`, then the decompression drops out after its single repetition.
+    assert_eq!(
+        *log.borrow(),
+        [
+            "b ./d", //
+            "a ./c1", "a ./c9", "a ./d", "b ./d", //
+            "a ./c1", "a ./c9", //
+            "a ./c1", "a ./c9",
+        ]
+    );
+
+    assert!(measured.covers("compress", &[0, 1]));
+    assert!(!measured.covers("compress", &[0, 2]));
+    let compress = measured.take("compress", &[0, 1]);
+    // The mean and the sample variance of the runs of every command, over the whole run.
+    let runs = &compress[0].counters["a-runs"];
+    assert_eq!((runs.value, runs.repetitions), (16.0 / 3.0, 3));
+    let runs = &compress[1].counters["a-runs"];
+    assert_eq!((runs.value, runs.repetitions), (19.0 / 3.0, 3));
+    assert_eq!(runs.variance, compress[0].counters["a-runs"].variance);
+    let decompress = measured.take("decompress", &[0]);
+    assert_eq!(decompress[0].cmd, ["./d"]);
+    assert_eq!(decompress[0].counters["a-runs"].value, 4.0);
+    assert_eq!(decompress[0].counters["b-runs"].repetitions, 1);
+    assert!(!measured.covers("decompress", &[0]));
+
+    // Every group runs in every pass it is in, also the warmup pass.
+    let windows = measured
+        .windows
+        .iter()
+        .map(|window| {
+            (
+                window.group.as_str(),
+                window.start.as_secs(),
+                window.end.as_secs(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        windows,
+        [
+            ("decompress", 1, 2),
+            ("compress", 3, 4),
+            ("decompress", 5, 6),
+            ("compress", 7, 8),
+            ("compress", 9, 10),
+        ]
+    );
+}
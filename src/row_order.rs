@@ -0,0 +1,251 @@
+//! The order of the rows of the raw tables. A large group buries its biggest regressions at
+//! whatever position the config put them, so the rows can be sorted by the change or the value
+//! of a counter instead. Only the rendering is sorted: the results themselves stay in the order
+//! of the config, as the `render-versus-other` tables refer to commands by index.
+//!
+//! The comparison tables have an `order` of their own, see [`TableOrder`].
+
+use std::cmp::Ordering;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bench::SingleBench;
+use crate::compare::{find_prev_bench, ComparisonRow};
+
+/// The counter to sort by when the config doesn't name one, or a group doesn't have it.
+pub const DEFAULT_COUNTER: &str = "task-clock";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RowOrder {
+    /// The order of the commands in the config.
+    #[default]
+    ConfigOrder,
+    /// The largest change relative to the baseline first, the rows without a baseline last.
+    DeltaDesc,
+    /// The largest current value first.
+    ValueDesc,
+}
+
+/// How to sort the rows of the raw table of a group.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RowSort<'a> {
+    pub order: RowOrder,
+    /// The counter to sort by, see [`RowSort::counter`].
+    pub counter: Option<&'a str>,
+}
+
+impl<'a> RowSort<'a> {
+    /// The counter to sort the rows of a group by: the configured one, task-clock, or the first
+    /// counter of the group by name, whichever the group has.
+    pub fn counter<'b>(&self, group_results: &'b [SingleBench]) -> Option<&'b str>
+    where
+        'a: 'b,
+    {
+        let has = |counter: &str| {
+            group_results
+                .iter()
+                .any(|bench| bench.counters.contains_key(counter))
+        };
+        self.counter
+            .into_iter()
+            .chain([DEFAULT_COUNTER])
+            .find(|counter| has(counter))
+            .or_else(|| {
+                group_results
+                    .iter()
+                    .flat_map(|bench| bench.counters.keys())
+                    .min()
+                    .map(String::as_str)
+            })
+    }
+
+    /// The indices of `group_results` in the order to render them. The changes are relative
+    /// to `prev_group_results`, which is `None` when they aren't shown. Rows that tie, or that
+    /// lack the counter or a baseline, are sorted by their command.
+    pub fn permutation(
+        &self,
+        group_results: &[SingleBench],
+        prev_group_results: Option<&[SingleBench]>,
+    ) -> Vec<usize> {
+        let mut order = (0..group_results.len()).collect::<Vec<_>>();
+        let counter = match self.order {
+            RowOrder::ConfigOrder => return order,
+            RowOrder::DeltaDesc | RowOrder::ValueDesc => self.counter(group_results),
+        };
+        let Some(counter) = counter else {
+            return order;
+        };
+
+        let keys = group_results
+            .iter()
+            .map(|bench| {
+                let value = bench.counters.get(counter)?.value;
+                match self.order {
+                    RowOrder::ConfigOrder | RowOrder::ValueDesc => Some(value),
+                    RowOrder::DeltaDesc => {
+                        let prev_bench = find_prev_bench(prev_group_results?, bench)?;
+                        let prev_value = prev_bench.counters.get(counter)?.value;
+                        let delta = ((value - prev_value) / prev_value).abs();
+                        // 0 before and after.
+                        Some(if delta.is_nan() { 0.0 } else { delta })
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+        let commands = group_results
+            .iter()
+            .map(|bench| bench.cmd.join(" "))
+            .collect::<Vec<_>>();
+
+        order.sort_by(|&a, &b| {
+            let by_key = match (keys[a], keys[b]) {
+                (Some(a), Some(b)) => b.total_cmp(&a),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            };
+            by_key.then_with(|| commands[a].cmp(&commands[b]))
+        });
+        order
+    }
+}
+
+/// The order of the rows of a comparison table. Like [`RowOrder`], it only applies to the
+/// rendering, after the rows to show are selected with `max-rows`: the rows keep their order
+/// and keys everywhere else.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TableOrder {
+    /// The order of the rows in the config.
+    #[default]
+    Config,
+    /// The significant regressions first, then the significant improvements, both by the size
+    /// of their change, then the other rows in config order.
+    Severity,
+    /// By row name.
+    Alphabetical,
+}
+
+impl TableOrder {
+    /// Sort the `rows`, which are in config order. Ties are broken by row name.
+    pub fn sort(self, rows: &mut [&ComparisonRow]) {
+        match self {
+            TableOrder::Config => {}
+            TableOrder::Severity => rows.sort_by(|a, b| {
+                let rank = |row: &ComparisonRow| match () {
+                    () if row.is_regression() => 0,
+                    () if row.is_improvement() => 1,
+                    () => 2,
+                };
+                rank(a).cmp(&rank(b)).then_with(|| match rank(a) {
+                    // The others keep their config order, the sort is stable.
+                    2 => Ordering::Equal,
+                    _ => b
+                        .delta_percent
+                        .abs()
+                        .total_cmp(&a.delta_percent.abs())
+                        .then_with(|| a.name.cmp(&b.name)),
+                })
+            }),
+            TableOrder::Alphabetical => rows.sort_by(|a, b| a.name.cmp(&b.name)),
+        }
+    }
+
+    /// Whether the rendered order needs explaining: only when severity puts a regression on top.
+    pub fn caption(self, rows: &[&ComparisonRow]) -> Option<&'static str> {
+        (self == TableOrder::Severity && rows.iter().any(|row| row.is_regression()))
+            .then_some("_Rows sorted by severity: significant regressions first, then significant improvements._")
+    }
+}
+
+#[cfg(test)]
+fn group_for_test(hash: &str, values: &[(&str, Option<f64>)]) -> crate::BenchData {
+    crate::testkit::BenchDataBuilder::new(hash)
+        .group("compress", |mut g| {
+            for &(cmd, value) in values {
+                g = g.bench(cmd.split(' '), |b| match value {
+                    Some(value) => b
+                        .counter("task-clock", value, 1.0, 20, "msec")
+                        .counter("cycles", 1000.0, 1.0, 20, ""),
+                    None => b.counter("cycles", 1000.0, 1.0, 20, ""),
+                });
+            }
+            g
+        })
+        .build()
+}
+
+#[test]
+fn sort_rows() {
+    let before = group_for_test(
+        "1111111111111111111111111111111111111111",
+        &[
+            ("./c 1", Some(100.0)),
+            ("./c 2", Some(100.0)),
+            ("./c 3", Some(100.0)),
+            ("./c 5", Some(100.0)),
+        ],
+    );
+    // Changes of +10%, -30%, none, +10%, no baseline and no counter.
+    let after = group_for_test(
+        "2222222222222222222222222222222222222222",
+        &[
+            ("./c 5", Some(110.0)),
+            ("./c 2", Some(70.0)),
+            ("./c 3", Some(100.0)),
+            ("./c 1", Some(110.0)),
+            ("./c 4", Some(500.0)),
+            ("./c 6", None),
+        ],
+    );
+    let group = &after.bench_groups["compress"][..];
+    let prev = Some(&before.bench_groups["compress"][..]);
+    let sort = |order, counter| RowSort { order, counter }.permutation(group, prev);
+
+    assert_eq!(sort(RowOrder::ConfigOrder, None), [0, 1, 2, 3, 4, 5]);
+    // Ties by command, so `./c 1` before `./c 5`.
+    assert_eq!(sort(RowOrder::DeltaDesc, None), [1, 3, 0, 2, 4, 5]);
+    assert_eq!(sort(RowOrder::ValueDesc, None), [4, 3, 0, 2, 1, 5]);
+
+    // Without a baseline, only the command remains.
+    let unsorted = RowSort {
+        order: RowOrder::DeltaDesc,
+        counter: None,
+    };
+    assert_eq!(unsorted.permutation(group, None), [3, 1, 2, 4, 0, 5]);
+
+    // The cycles are the same everywhere.
+    assert_eq!(
+        sort(RowOrder::ValueDesc, Some("cycles")),
+        [3, 1, 2, 4, 0, 5]
+    );
+}
+
+#[test]
+fn sort_counter() {
+    let data = group_for_test(
+        "2222222222222222222222222222222222222222",
+        &[("./c 1", None)],
+    );
+    let group = &data.bench_groups["compress"][..];
+    let sort = |counter| RowSort {
+        order: RowOrder::DeltaDesc,
+        counter,
+    };
+    assert_eq!(sort(Some("cycles")).counter(group), Some("cycles"));
+    // Neither the configured counter nor task-clock, then the first by name.
+    assert_eq!(sort(Some("instructions")).counter(group), Some("cycles"));
+    assert_eq!(sort(None).counter(&[]), None);
+
+    let data = group_for_test(
+        "2222222222222222222222222222222222222222",
+        &[("./c 1", None), ("./c 2", Some(1.0))],
+    );
+    let group = &data.bench_groups["compress"][..];
+    assert_eq!(sort(None).counter(group), Some("task-clock"));
+    assert_eq!(
+        sort(Some("instructions")).counter(group),
+        Some("task-clock")
+    );
+}
@@ -0,0 +1,369 @@
+//! The resources used by a single run of a command, for the `getrusage` backend. The times
+//! and the peak memory come from `wait4`, which works on every unix. On macOS, the cycles and
+//! instructions of the command are read with `proc_pid_rusage` before it is reaped; Apple
+//! Silicon counts those, Intel Macs report zeros.
+//!
+//! As every run is measured on its own, every counter is also split into the first of the
+//! repetitions and the rest of them, the derived `<counter>-cold` and `<counter>-warm`
+//! counters, to see whether a change affects startup specifically.
+
+use std::collections::BTreeMap;
+use std::io::{self, Read};
+use std::process::{Command, ExitStatus, Output, Stdio};
+use std::time::{Duration, Instant};
+
+use crate::bench::{mean_and_variance, BenchCounter};
+
+/// What a single run of a command used.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RunUsage {
+    pub user_time: Duration,
+    pub system_time: Duration,
+    pub wall_time: Duration,
+    /// The peak resident set size, in KiB.
+    pub max_rss: u64,
+    /// Only counted for the process itself, not for the processes it started.
+    pub cycles: Option<u64>,
+    pub instructions: Option<u64>,
+}
+
+/// Run `command` to completion, capturing its output like [`Command::output`].
+///
+/// The times and the peak memory include the children the command waited for, so a wrapper or
+/// a shell script counts too.
+pub fn run(command: &mut Command) -> io::Result<(Output, RunUsage)> {
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    let start = Instant::now();
+    let mut child = command.spawn()?;
+    let pid = child.id() as libc::pid_t;
+
+    // Read both pipes while the command runs, so it never blocks on a full pipe.
+    let mut stdout_pipe = child.stdout.take().unwrap();
+    let mut stderr_pipe = child.stderr.take().unwrap();
+    let stderr = std::thread::spawn(move || {
+        let mut stderr = vec![];
+        stderr_pipe.read_to_end(&mut stderr).map(|_| stderr)
+    });
+    let mut stdout = vec![];
+    let read_stdout = stdout_pipe.read_to_end(&mut stdout);
+    let stderr = stderr.join().unwrap();
+
+    #[cfg(target_os = "macos")]
+    let counts = apple::wait_and_count(pid)?;
+    #[cfg(not(target_os = "macos"))]
+    let counts = None;
+
+    let (status, usage) = wait4(pid)?;
+    let wall_time = start.elapsed();
+    read_stdout?;
+
+    let output = Output {
+        status,
+        stdout,
+        stderr: stderr?,
+    };
+    let usage = RunUsage {
+        user_time: timeval_duration(usage.ru_utime),
+        system_time: timeval_duration(usage.ru_stime),
+        wall_time,
+        max_rss: max_rss_kib(usage.ru_maxrss as u64),
+        cycles: counts.map(|(cycles, _)| cycles),
+        instructions: counts.map(|(_, instructions)| instructions),
+    };
+    Ok((output, usage))
+}
+
+/// Wait for `pid` to exit without reaping it, so what the system knows about the process can
+/// still be read while it is a zombie.
+pub fn wait_exited(pid: libc::pid_t) -> io::Result<()> {
+    // SAFETY: all-zero is a valid `siginfo_t`.
+    let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+    loop {
+        // SAFETY: the pointer is valid for writes.
+        let ret = unsafe {
+            libc::waitid(
+                libc::P_PID,
+                pid as libc::id_t,
+                &mut info,
+                libc::WEXITED | libc::WNOWAIT,
+            )
+        };
+        if ret == 0 {
+            return Ok(());
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+/// Reap `pid`, returning how it exited and what it used.
+pub fn wait4(pid: libc::pid_t) -> io::Result<(ExitStatus, libc::rusage)> {
+    use std::os::unix::process::ExitStatusExt;
+
+    let mut status = 0;
+    // SAFETY: all-zero is a valid `rusage`.
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    loop {
+        // SAFETY: both pointers are valid for writes.
+        if unsafe { libc::wait4(pid, &mut status, 0, &mut usage) } == pid {
+            return Ok((ExitStatus::from_raw(status), usage));
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+fn timeval_duration(time: libc::timeval) -> Duration {
+    Duration::new(time.tv_sec as u64, time.tv_usec as u32 * 1000)
+}
+
+/// `ru_maxrss` is in bytes on macOS, and in KiB everywhere else.
+fn max_rss_kib(max_rss: u64) -> u64 {
+    if cfg!(target_os = "macos") {
+        max_rss / 1024
+    } else {
+        max_rss
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod apple {
+    use std::io;
+
+    /// Wait for `pid` to exit without reaping it, and read its cycles and instructions. `None`
+    /// when the system doesn't count them.
+    pub fn wait_and_count(pid: libc::pid_t) -> io::Result<Option<(u64, u64)>> {
+        super::wait_exited(pid)?;
+
+        // SAFETY: all-zero is a valid `rusage_info_v4`.
+        let mut usage: libc::rusage_info_v4 = unsafe { std::mem::zeroed() };
+        // SAFETY: the buffer is a `rusage_info_v4`, as the flavor says. The process is a
+        // zombie until it is reaped, so the pid still refers to it.
+        let ret = unsafe {
+            libc::proc_pid_rusage(
+                pid,
+                libc::RUSAGE_INFO_V4,
+                (&mut usage as *mut libc::rusage_info_v4).cast(),
+            )
+        };
+        // Older versions of macOS don't have the v4 flavor.
+        if ret != 0 || usage.ri_cycles == 0 {
+            return Ok(None);
+        }
+        Ok(Some((usage.ri_cycles, usage.ri_instructions)))
+    }
+}
+
+/// The suffix of the derived counter of the first repetition.
+pub const COLD_SUFFIX: &str = "-cold";
+/// The suffix of the derived counter of the repetitions after the first one.
+pub const WARM_SUFFIX: &str = "-warm";
+
+/// Whether `counter` is a derived `-cold` or `-warm` counter. These are only shown in the raw
+/// tables with `show-cold-warm`, but can be compared like any other counter.
+pub fn is_cold_or_warm(counter: &str) -> bool {
+    counter.ends_with(COLD_SUFFIX) || counter.ends_with(WARM_SUFFIX)
+}
+
+/// The counters of the runs, named like those of perf where there is an equivalent. Cycles
+/// and instructions are only included when every run counted them.
+pub fn counters(runs: &[RunUsage]) -> BTreeMap<String, BenchCounter> {
+    let msec = |time: fn(&RunUsage) -> Duration| {
+        let samples = runs.iter().map(|run| time(run).as_secs_f64() * 1000.0);
+        (samples.collect(), "msec")
+    };
+
+    let mut measured: Vec<(&str, (Vec<f64>, &str))> = vec![
+        ("user-time", msec(|run| run.user_time)),
+        ("system-time", msec(|run| run.system_time)),
+        ("wall-time", msec(|run| run.wall_time)),
+        (
+            "max-rss",
+            (runs.iter().map(|run| run.max_rss as f64).collect(), "KiB"),
+        ),
+    ];
+
+    let counts = |count: fn(&RunUsage) -> Option<u64>| {
+        runs.iter()
+            .map(|run| count(run).map(|count| count as f64))
+            .collect::<Option<Vec<_>>>()
+    };
+    if let Some(cycles) = counts(|run| run.cycles) {
+        measured.push(("cycles", (cycles, "")));
+    }
+    if let Some(instructions) = counts(|run| run.instructions) {
+        measured.push(("instructions", (instructions, "")));
+    }
+
+    let mut counters = BTreeMap::new();
+    for (name, (samples, unit)) in measured {
+        let (cold, warm) = split_cold_warm(&samples, unit);
+        if let Some(cold) = cold {
+            counters.insert(format!("{name}{COLD_SUFFIX}"), cold);
+        }
+        if let Some(warm) = warm {
+            counters.insert(format!("{name}{WARM_SUFFIX}"), warm);
+        }
+        counters.insert(name.to_owned(), counter(&samples, unit));
+    }
+    counters
+}
+
+fn counter(samples: &[f64], unit: &str) -> BenchCounter {
+    let (value, variance) = mean_and_variance(samples);
+    BenchCounter {
+        value,
+        variance,
+        repetitions: samples.len() as u32,
+        unit: unit.to_owned(),
+    }
+}
+
+/// The counter of the first sample, and the one of the samples after it. Either is `None`
+/// when there are no such samples, so with a single repetition there is no warm counter.
+fn split_cold_warm(samples: &[f64], unit: &str) -> (Option<BenchCounter>, Option<BenchCounter>) {
+    match samples {
+        [] => (None, None),
+        [cold] => (Some(counter(&[*cold], unit)), None),
+        [cold, warm @ ..] => (Some(counter(&[*cold], unit)), Some(counter(warm, unit))),
+    }
+}
+
+#[test]
+fn counters_of_runs() {
+    let run = |user_ms, max_rss, cycles| RunUsage {
+        user_time: Duration::from_millis(user_ms),
+        system_time: Duration::from_millis(1),
+        wall_time: Duration::from_millis(user_ms + 1),
+        max_rss,
+        cycles,
+        instructions: cycles.map(|cycles| cycles * 2),
+    };
+
+    let measured = counters(&[run(10, 2048, None), run(12, 4096, None)]);
+    assert_eq!(
+        measured
+            .keys()
+            .filter(|counter| !is_cold_or_warm(counter))
+            .collect::<Vec<_>>(),
+        ["max-rss", "system-time", "user-time", "wall-time"]
+    );
+    assert_eq!(
+        measured["user-time"],
+        BenchCounter {
+            value: 11.0,
+            variance: 2.0,
+            repetitions: 2,
+            unit: "msec".to_owned(),
+        }
+    );
+    assert_eq!(measured["system-time"].variance, 0.0);
+    assert_eq!(measured["wall-time"].value, 12.0);
+    assert_eq!(measured["max-rss"].value, 3072.0);
+    assert_eq!(measured["max-rss"].unit, "KiB");
+
+    // Named like the counters of perf, so configs work the same on both.
+    let measured = counters(&[run(10, 2048, Some(100)), run(12, 2048, Some(300))]);
+    assert_eq!(measured["cycles"].value, 200.0);
+    assert_eq!(measured["cycles"].unit, "");
+    assert_eq!(measured["instructions"].value, 400.0);
+
+    // Not when some run wasn't counted.
+    let measured = counters(&[run(10, 2048, Some(100)), run(12, 2048, None)]);
+    assert!(!measured.contains_key("cycles"));
+    assert!(!measured.contains_key("instructions"));
+    assert!(!measured.contains_key("cycles-cold"));
+}
+
+#[test]
+fn cold_and_warm_runs() {
+    let counter = |value: f64, variance: f64, repetitions: u32| BenchCounter {
+        value,
+        variance,
+        repetitions,
+        unit: "msec".to_owned(),
+    };
+
+    // The first run stands apart, the rest are aggregated like all of them are.
+    let (cold, warm) = split_cold_warm(&[30.0, 10.0, 12.0, 14.0], "msec");
+    assert_eq!(cold, Some(counter(30.0, 0.0, 1)));
+    assert_eq!(warm, Some(counter(12.0, 4.0, 3)));
+
+    // Two runs: a single warm one, without variance.
+    let (cold, warm) = split_cold_warm(&[30.0, 10.0], "msec");
+    assert_eq!(cold, Some(counter(30.0, 0.0, 1)));
+    assert_eq!(warm, Some(counter(10.0, 0.0, 1)));
+
+    // A single run is only cold.
+    assert_eq!(
+        split_cold_warm(&[30.0], "msec"),
+        (Some(counter(30.0, 0.0, 1)), None)
+    );
+    assert_eq!(split_cold_warm(&[], "msec"), (None, None));
+
+    let run = |user_ms| RunUsage {
+        user_time: Duration::from_millis(user_ms),
+        ..RunUsage::default()
+    };
+    let measured = counters(&[run(30), run(10), run(12), run(14)]);
+    assert_eq!(measured["user-time"], counter(16.5, 251.0 / 3.0, 4));
+    assert_eq!(measured["user-time-cold"], counter(30.0, 0.0, 1));
+    assert_eq!(measured["user-time-warm"], counter(12.0, 4.0, 3));
+    assert_eq!(measured["max-rss-warm"].unit, "KiB");
+
+    let measured = counters(&[run(30)]);
+    assert!(measured.contains_key("user-time-cold"));
+    assert!(!measured.contains_key("user-time-warm"));
+
+    assert!(is_cold_or_warm("task-clock-cold"));
+    assert!(is_cold_or_warm("wall-time-warm"));
+    assert!(!is_cold_or_warm("wall-time"));
+}
+
+#[test]
+fn run_usage() {
+    let mut command = Command::new("sh");
+    command.args(["-c", "echo out; echo err >&2; sleep 0.05; exit 3"]);
+    let (output, usage) = run(&mut command).unwrap();
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(output.stdout, b"out\n");
+    assert_eq!(output.stderr, b"err\n");
+    assert!(usage.wall_time >= Duration::from_millis(50), "{usage:?}");
+    assert!(usage.max_rss > 0, "{usage:?}");
+
+    // More output than fits in a pipe.
+    let mut command = Command::new("sh");
+    command.args([
+        "-c",
+        "head -c 1000000 /dev/zero; head -c 1000000 /dev/zero >&2",
+    ]);
+    let (output, _) = run(&mut command).unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        (output.stdout.len(), output.stderr.len()),
+        (1000000, 1000000)
+    );
+
+    assert!(run(&mut Command::new("./does-not-exist")).is_err());
+}
+
+#[cfg(target_os = "macos")]
+#[test]
+fn run_usage_counts() {
+    let mut command = Command::new("sh");
+    command.args(["-c", "i=0; while [ $i -lt 1000 ]; do i=$((i + 1)); done"]);
+    let (_, usage) = run(&mut command).unwrap();
+    // Only Apple Silicon counts them.
+    if cfg!(target_arch = "aarch64") {
+        assert!(usage.cycles.unwrap() > 0, "{usage:?}");
+        assert!(usage.instructions.unwrap() > 0, "{usage:?}");
+    }
+}
@@ -0,0 +1,501 @@
+//! Commands over the files of a corpus, for a growing corpus of inputs, and samples of them to
+//! keep the duration of a suite bounded as the corpus grows:
+//!
+//! ```json
+//! {
+//!   "template": "target/release/decompress {file}",
+//!   "files": "corpus/*.bin",
+//!   "sample": { "count": 10, "strategy": "stratified-by-size" }
+//! }
+//! ```
+//!
+//! The entry takes the place of a command for every file the glob matches, relative to the
+//! working directory and in the order of their paths, when the config is loaded. Every `{file}`
+//! of the template is replaced by the path, which is also the id of the command. The glob is
+//! matched like the `paths-for-group` patterns, see [`crate::changed`].
+//!
+//! With `sample`, only `count` of the files are benchmarked, chosen by the `strategy`:
+//!
+//! - `first-n`: the first ones, in the order of their paths.
+//! - `random`: random ones, from the seed of the run, see [`crate::seed`].
+//! - `stratified-by-size`: spread evenly over the files sorted by size, from the smallest to
+//!   the largest.
+//!
+//! The chosen files keep the order of their paths. Their paths and sizes are recorded with the
+//! results, by group, so two runs can be checked for comparable samples. When the baseline
+//! recorded another sample for a group, its `render-versus-other` tables get a warning and the
+//! gate ignores their failures, unless `--strict-sampling` is given.
+
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+use crate::changed::glob_match;
+use crate::gate::GateVerdict;
+use crate::seed::Rng;
+use crate::VersusOther;
+
+/// How many of the files of a corpus to benchmark, and how to choose them.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Sample {
+    pub count: usize,
+    pub strategy: Strategy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Strategy {
+    FirstN,
+    Random,
+    StratifiedBySize,
+}
+
+/// A file of a corpus, with its size in bytes when the config was loaded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CorpusFile {
+    pub path: String,
+    pub size: u64,
+}
+
+/// The command of a file of a corpus.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorpusCommand {
+    /// The index of the first command of the entry in its group, to tell the commands of two
+    /// entries with the same glob apart.
+    pub entry: usize,
+    pub files: String,
+    pub sample: Option<Sample>,
+    pub file: CorpusFile,
+}
+
+/// The files chosen from a corpus, as recorded with the results.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CorpusSample {
+    /// The glob of the corpus.
+    pub files: String,
+    pub strategy: Strategy,
+    pub count: usize,
+    pub chosen: Vec<CorpusFile>,
+}
+
+/// A group whose sample differs from that of the baseline.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SamplingMismatch {
+    pub group: String,
+    /// What differs, like "`corpus/b.bin` and `corpus/c.bin` only in the baseline".
+    pub differences: Vec<String>,
+}
+
+/// The files under `root` that `pattern` matches, in the order of their paths.
+pub fn expand_glob(root: &Path, pattern: &str) -> Result<Vec<CorpusFile>, String> {
+    // Only the directory before the first component with a wildcard can hold matches.
+    let components = pattern.split('/').collect::<Vec<_>>();
+    let literal = components[..components.len() - 1]
+        .iter()
+        .take_while(|component| !component.contains(['*', '?']))
+        .copied()
+        .collect::<Vec<_>>();
+
+    let mut files = vec![];
+    let mut dirs = vec![literal.join("/")];
+    while let Some(dir) = dirs.pop() {
+        let path = if dir.is_empty() {
+            root.to_owned()
+        } else {
+            root.join(&dir)
+        };
+        let entries = match fs::read_dir(&path) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(format!("failed to read {}: {e}", path.display())),
+        };
+        for entry in entries {
+            let entry = entry.map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let relative = if dir.is_empty() {
+                name
+            } else {
+                format!("{dir}/{name}")
+            };
+            let metadata = fs::metadata(entry.path())
+                .map_err(|e| format!("failed to read {}: {e}", entry.path().display()))?;
+            if metadata.is_dir() {
+                dirs.push(relative);
+            } else if glob_match(pattern, &relative) {
+                files.push(CorpusFile {
+                    path: relative,
+                    size: metadata.len(),
+                });
+            }
+        }
+    }
+    if files.is_empty() {
+        return Err(format!("no files match `{pattern}`"));
+    }
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(files)
+}
+
+impl Sample {
+    /// The indices of the chosen `files`, which are in the order of their paths, in that order.
+    pub fn choose(&self, files: &[CorpusFile], rng: &mut Rng) -> Vec<usize> {
+        let count = self.count.min(files.len());
+        let mut chosen = match self.strategy {
+            _ if count == files.len() => (0..count).collect(),
+            Strategy::FirstN => (0..count).collect(),
+            Strategy::Random => {
+                let mut indices = (0..files.len()).collect::<Vec<_>>();
+                rng.shuffle(&mut indices);
+                indices.truncate(count);
+                indices
+            }
+            Strategy::StratifiedBySize => {
+                let mut by_size = (0..files.len()).collect::<Vec<_>>();
+                by_size.sort_by_key(|&index| files[index].size);
+                if count == 1 {
+                    vec![by_size[(files.len() - 1) / 2]]
+                } else {
+                    // Evenly spaced ranks, from the smallest to the largest. The spacing is at
+                    // least one, so no rank is taken twice.
+                    (0..count)
+                        .map(|i| by_size[i * (files.len() - 1) / (count - 1)])
+                        .collect()
+                }
+            }
+        };
+        chosen.sort_unstable();
+        chosen
+    }
+}
+
+/// The groups whose samples differ between the baseline and the run. A group only counts when
+/// both have results of it.
+pub fn collect_mismatches(
+    prev: &crate::BenchData,
+    data: &crate::BenchData,
+) -> Vec<SamplingMismatch> {
+    let mut groups = data.samples.keys().collect::<Vec<_>>();
+    groups.extend(
+        prev.samples
+            .keys()
+            .filter(|group| !data.samples.contains_key(*group)),
+    );
+
+    let none = vec![];
+    groups
+        .into_iter()
+        .filter(|&group| {
+            prev.bench_groups.contains_key(group) && data.bench_groups.contains_key(group)
+        })
+        .filter_map(|group| {
+            let differences = differences(
+                prev.samples.get(group).unwrap_or(&none),
+                data.samples.get(group).unwrap_or(&none),
+            );
+            (!differences.is_empty()).then(|| SamplingMismatch {
+                group: group.clone(),
+                differences,
+            })
+        })
+        .collect()
+}
+
+/// What differs between the samples of the corpora of a group.
+fn differences(prev: &[CorpusSample], current: &[CorpusSample]) -> Vec<String> {
+    let paths = |files: &[&CorpusFile]| {
+        files
+            .iter()
+            .map(|file| format!("`{}`", file.path))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    let mut differences = vec![];
+    let mut corpora = current
+        .iter()
+        .map(|sample| &sample.files)
+        .collect::<Vec<_>>();
+    for sample in prev {
+        if !corpora.contains(&&sample.files) {
+            corpora.push(&sample.files);
+        }
+    }
+    for files in corpora {
+        let prev = prev.iter().find(|sample| sample.files == *files);
+        let current = current.iter().find(|sample| sample.files == *files);
+        let (prev, current) = match (prev, current) {
+            (Some(prev), Some(current)) => (prev, current),
+            (Some(_), None) => {
+                differences.push(format!("`{files}` is only sampled in the baseline"));
+                continue;
+            }
+            (None, Some(_)) => {
+                differences.push(format!("`{files}` isn't sampled in the baseline"));
+                continue;
+            }
+            (None, None) => unreachable!(),
+        };
+        let find = |sample: &'_ CorpusSample, path: &str| {
+            sample
+                .chosen
+                .iter()
+                .find(|file| file.path == path)
+                .map(|file| file.size)
+        };
+
+        let only_prev = prev
+            .chosen
+            .iter()
+            .filter(|file| find(current, &file.path).is_none())
+            .collect::<Vec<_>>();
+        let only_current = current
+            .chosen
+            .iter()
+            .filter(|file| find(prev, &file.path).is_none())
+            .collect::<Vec<_>>();
+        let resized = current
+            .chosen
+            .iter()
+            .filter(|file| find(prev, &file.path).is_some_and(|size| size != file.size))
+            .collect::<Vec<_>>();
+        let mut difference = String::new();
+        for (files, what) in [
+            (only_prev, "only in the baseline"),
+            (only_current, "not in the baseline"),
+            (resized, "of another size in the baseline"),
+        ] {
+            if !files.is_empty() {
+                let separator = if difference.is_empty() { "" } else { ", " };
+                write!(difference, "{separator}{} {what}", paths(&files)).unwrap();
+            }
+        }
+        if !difference.is_empty() {
+            differences.push(format!("of `{files}`: {difference}"));
+        }
+    }
+    differences
+}
+
+/// The note above a table of a group whose sample differs from that of the baseline.
+pub fn note(mismatch: &SamplingMismatch) -> String {
+    format!(
+        "The baseline sampled other files, so the rows may not be comparable and the gate ignores them unless `--strict-sampling` is given: {}",
+        mismatch.differences.join("; ")
+    )
+}
+
+/// The warning above a table whose commands were sampled differently than in the baseline.
+pub fn render_markdown_note(md: &mut String, note: Option<&str>) {
+    if let Some(note) = note {
+        writeln!(md, "> [!WARNING]\n> {note}\n").unwrap();
+    }
+}
+
+/// Move the gate failures of the `render-versus-other` tables of the groups with another
+/// sample than the baseline to the suppressed ones.
+pub fn suppress_gate(
+    verdict: &mut GateVerdict,
+    render_versus_other: &IndexMap<String, VersusOther>,
+    mismatches: &[SamplingMismatch],
+) {
+    let mismatched = |table_name: &str| {
+        render_versus_other.get(table_name).is_some_and(|table| {
+            mismatches
+                .iter()
+                .any(|mismatch| mismatch.group == table.command)
+        })
+    };
+    for failures in [&mut verdict.failures, &mut verdict.variance_failures] {
+        let (suppressed, kept) = std::mem::take(failures)
+            .into_iter()
+            .partition(|failure| mismatched(&failure.table));
+        *failures = kept;
+        verdict.suppressed.extend::<Vec<_>>(suppressed);
+    }
+}
+
+#[cfg(test)]
+fn corpus_for_test(name: &str, sizes: &[u64]) -> std::path::PathBuf {
+    let dir = crate::testkit::test_dir(name);
+    for (i, &size) in sizes.iter().enumerate() {
+        let path = dir.join(format!("corpus/{i:02}.bin"));
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, vec![0; size as usize]).unwrap();
+    }
+    dir
+}
+
+#[cfg(test)]
+fn chosen_paths(files: &[CorpusFile], chosen: &[usize]) -> Vec<String> {
+    chosen
+        .iter()
+        .map(|&index| files[index].path.clone())
+        .collect()
+}
+
+#[test]
+fn expand_corpus_glob() {
+    let dir = corpus_for_test("sample-glob", &[3, 1, 2]);
+    fs::write(dir.join("corpus/notes.txt"), "").unwrap();
+    fs::create_dir_all(dir.join("corpus/nested")).unwrap();
+    fs::write(dir.join("corpus/nested/03.bin"), "abcd").unwrap();
+
+    let files = expand_glob(&dir, "corpus/*.bin").unwrap();
+    assert_eq!(
+        files,
+        [
+            CorpusFile {
+                path: "corpus/00.bin".to_owned(),
+                size: 3,
+            },
+            CorpusFile {
+                path: "corpus/01.bin".to_owned(),
+                size: 1,
+            },
+            CorpusFile {
+                path: "corpus/02.bin".to_owned(),
+                size: 2,
+            },
+        ]
+    );
+    let files = expand_glob(&dir, "**/*.bin").unwrap();
+    assert_eq!(files.len(), 4);
+    assert_eq!(files[3].path, "corpus/nested/03.bin");
+    assert_eq!(
+        expand_glob(&dir, "corpus/*.zst").unwrap_err(),
+        "no files match `corpus/*.zst`"
+    );
+    assert_eq!(
+        expand_glob(&dir, "missing/*.bin").unwrap_err(),
+        "no files match `missing/*.bin`"
+    );
+}
+
+#[test]
+fn sample_strategies() {
+    // Sizes out of the order of the paths: 00.bin is the largest, 09.bin the smallest.
+    let dir = corpus_for_test(
+        "sample-strategies",
+        &[90, 10, 50, 20, 70, 30, 80, 40, 60, 0],
+    );
+    let files = expand_glob(&dir, "corpus/*.bin").unwrap();
+    let choose = |count, strategy, seed| {
+        let chosen = Sample { count, strategy }.choose(&files, &mut Rng::new(seed));
+        chosen_paths(&files, &chosen)
+    };
+
+    assert_eq!(
+        choose(3, Strategy::FirstN, 1),
+        ["corpus/00.bin", "corpus/01.bin", "corpus/02.bin"]
+    );
+
+    // The smallest, the largest and evenly between them, in the order of the paths.
+    assert_eq!(
+        choose(3, Strategy::StratifiedBySize, 1),
+        ["corpus/00.bin", "corpus/07.bin", "corpus/09.bin"]
+    );
+    assert_eq!(
+        choose(4, Strategy::StratifiedBySize, 1),
+        [
+            "corpus/00.bin",
+            "corpus/05.bin",
+            "corpus/08.bin",
+            "corpus/09.bin"
+        ]
+    );
+    assert_eq!(choose(1, Strategy::StratifiedBySize, 1), ["corpus/07.bin"]);
+
+    // The same seed chooses the same files, another seed others.
+    let random = choose(4, Strategy::Random, 7);
+    assert_eq!(random.len(), 4);
+    assert!(
+        random.windows(2).all(|pair| pair[0] < pair[1]),
+        "{random:?}"
+    );
+    assert_eq!(choose(4, Strategy::Random, 7), random);
+    assert!((8..16).any(|seed| choose(4, Strategy::Random, seed) != random));
+
+    // Every file when the count is at least the size of the corpus.
+    for strategy in [
+        Strategy::FirstN,
+        Strategy::Random,
+        Strategy::StratifiedBySize,
+    ] {
+        assert_eq!(choose(12, strategy, 1).len(), 10);
+    }
+}
+
+#[cfg(test)]
+fn sampled_for_test(files: &str, chosen: &[(&str, u64)]) -> CorpusSample {
+    CorpusSample {
+        files: files.to_owned(),
+        strategy: Strategy::Random,
+        count: chosen.len(),
+        chosen: chosen
+            .iter()
+            .map(|&(path, size)| CorpusFile {
+                path: path.to_owned(),
+                size,
+            })
+            .collect(),
+    }
+}
+
+#[test]
+fn detect_sampling_mismatches() {
+    let data_for_test = |samples: Vec<CorpusSample>| {
+        let mut data = crate::testkit::BenchDataBuilder::cycles(
+            "abc",
+            &[
+                ("decompress", &[("./decompress", 1.0)]),
+                ("other", &[("./other", 1.0)]),
+            ],
+        )
+        .build();
+        if !samples.is_empty() {
+            data.samples.insert("decompress".to_owned(), samples);
+        }
+        data
+    };
+    let sample = sampled_for_test("corpus/*.bin", &[("a.bin", 1), ("b.bin", 2)]);
+    let prev = data_for_test(vec![sample.clone()]);
+
+    // The same files, whichever strategy chose them.
+    let same = CorpusSample {
+        strategy: Strategy::FirstN,
+        ..sample.clone()
+    };
+    assert_eq!(collect_mismatches(&prev, &data_for_test(vec![same])), []);
+
+    let other = sampled_for_test("corpus/*.bin", &[("b.bin", 3), ("c.bin", 1)]);
+    let mismatches = collect_mismatches(&prev, &data_for_test(vec![other]));
+    assert_eq!(
+        mismatches,
+        [SamplingMismatch {
+            group: "decompress".to_owned(),
+            differences: vec![
+                "of `corpus/*.bin`: `a.bin` only in the baseline, `c.bin` not in the baseline, `b.bin` of another size in the baseline".to_owned()
+            ],
+        }]
+    );
+    assert!(note(&mismatches[0]).ends_with("given: of `corpus/*.bin`: `a.bin` only in the baseline, `c.bin` not in the baseline, `b.bin` of another size in the baseline"));
+
+    // A corpus sampled on one side only.
+    assert_eq!(
+        collect_mismatches(&prev, &data_for_test(vec![]))[0].differences,
+        ["`corpus/*.bin` is only sampled in the baseline"]
+    );
+    assert_eq!(
+        collect_mismatches(&data_for_test(vec![]), &prev)[0].differences,
+        ["`corpus/*.bin` isn't sampled in the baseline"]
+    );
+
+    // A group the baseline has no results of isn't compared.
+    let mut new_group = data_for_test(vec![]);
+    new_group.bench_groups.shift_remove("decompress");
+    assert_eq!(collect_mismatches(&new_group, &prev), []);
+}
@@ -0,0 +1,304 @@
+//! Sanitizing what the run publishes. Command lines with absolute paths, like a corpus in the
+//! home directory, and the name of the runner end up in the step summary, the commit comment
+//! and the results, which may be public. The `sanitize` rules replace them when the run writes
+//! its output, so the run itself, like finding the baseline of a command, uses the real
+//! values:
+//!
+//! ```json
+//! "sanitize": { "rules": [{ "pattern": "/srv/corpora/[\\w-]+", "replacement": "<corpus>" }] }
+//! ```
+//!
+//! The rules apply in order, each to the result of the previous, followed by the built-in ones
+//! for the home directory and the name of the runner unless `defaults` is false.
+//!
+//! As the stored results are sanitized too, the baselines loaded from them get the real names
+//! of the configured groups and commands back, see [`Sanitizer::restore`].
+
+use std::borrow::Cow;
+
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+use crate::pattern::{Pattern, Replacement};
+use crate::{BenchData, CommandConfig};
+
+/// The built-in rules, after the configured ones.
+const DEFAULT_RULES: &[(&str, &str)] = &[
+    (r"/home/[^/\s]+", "/home/<user>"),
+    (r"/Users/[^/\s]+", "/Users/<user>"),
+];
+
+/// What the name of the runner is replaced with.
+const RUNNER_REPLACEMENT: &str = "<runner>";
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct SanitizeConfig {
+    #[serde(default)]
+    rules: Vec<Rule>,
+    /// Also apply the built-in rules.
+    #[serde(default = "default_defaults")]
+    defaults: bool,
+}
+
+fn default_defaults() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(try_from = "RuleConfig")]
+struct Rule {
+    pattern: Pattern,
+    replacement: Replacement,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleConfig {
+    pattern: String,
+    replacement: String,
+}
+
+impl TryFrom<RuleConfig> for Rule {
+    type Error = String;
+
+    fn try_from(config: RuleConfig) -> Result<Self, String> {
+        Rule::new(&config.pattern, &config.replacement)
+    }
+}
+
+impl Rule {
+    fn new(pattern: &str, replacement: &str) -> Result<Self, String> {
+        let invalid = |err| format!("invalid sanitize pattern `{pattern}`: {err}");
+        let pattern = Pattern::new(pattern).map_err(invalid)?;
+        let replacement = Replacement::new(replacement, &pattern).map_err(invalid)?;
+        Ok(Rule {
+            pattern,
+            replacement,
+        })
+    }
+}
+
+/// Applies the rules to the output of the run. Without a `sanitize` config, it leaves
+/// everything as is.
+#[derive(Debug, Default)]
+pub struct Sanitizer {
+    rules: Vec<Rule>,
+}
+
+impl Sanitizer {
+    /// The rules of `config`, with the built-in ones for the `runner` it runs on, if known.
+    pub fn new(config: SanitizeConfig, runner: Option<&str>) -> Self {
+        let mut rules = config.rules;
+        if config.defaults {
+            rules.extend(DEFAULT_RULES.iter().map(|(pattern, replacement)| {
+                Rule::new(pattern, replacement).expect("a valid built-in rule")
+            }));
+            if let Some(runner) = runner.filter(|runner| !runner.is_empty()) {
+                let pattern = Pattern::literal(runner);
+                let replacement = Replacement::new(RUNNER_REPLACEMENT, &pattern)
+                    .expect("a valid built-in replacement");
+                rules.push(Rule {
+                    pattern,
+                    replacement,
+                });
+            }
+        }
+        Sanitizer { rules }
+    }
+
+    pub fn sanitize<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+        for rule in &self.rules {
+            if let Cow::Owned(replaced) = rule.pattern.replace_all(&text, &rule.replacement) {
+                text = Cow::Owned(replaced);
+            }
+        }
+        text
+    }
+
+    /// Every string of `value`, including the keys of objects, like the names of groups.
+    pub fn sanitize_json(&self, value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::String(text) => {
+                if let Cow::Owned(sanitized) = self.sanitize(text) {
+                    *text = sanitized;
+                }
+            }
+            serde_json::Value::Array(values) => {
+                for value in values {
+                    self.sanitize_json(value);
+                }
+            }
+            serde_json::Value::Object(object) => {
+                *object = std::mem::take(object)
+                    .into_iter()
+                    .map(|(key, mut value)| {
+                        self.sanitize_json(&mut value);
+                        (self.sanitize(&key).into_owned(), value)
+                    })
+                    .collect();
+            }
+            serde_json::Value::Null | serde_json::Value::Bool(_) | serde_json::Value::Number(_) => {
+            }
+        }
+    }
+
+    /// `value` as sanitized JSON on a single line.
+    pub fn to_json<T: Serialize>(&self, value: &T) -> String {
+        serde_json::to_string(&self.to_value(value)).unwrap()
+    }
+
+    /// `value` as sanitized, pretty-printed JSON.
+    pub fn to_json_pretty<T: Serialize>(&self, value: &T) -> Vec<u8> {
+        serde_json::to_vec_pretty(&self.to_value(value)).unwrap()
+    }
+
+    /// `value` as sanitized JSON.
+    pub fn to_value<T: Serialize>(&self, value: &T) -> serde_json::Value {
+        let mut value = serde_json::to_value(value).unwrap();
+        self.sanitize_json(&mut value);
+        value
+    }
+
+    /// Give the groups and the commands of `data`, as loaded from sanitized results, their
+    /// real names from the config where sanitizing them gives the stored ones, so the
+    /// commands are matched with their previous results by their command lines as usual.
+    pub fn restore(&self, data: &mut BenchData, commands: &IndexMap<String, Vec<CommandConfig>>) {
+        if self.rules.is_empty() {
+            return;
+        }
+        for (group_name, benches) in commands {
+            let sanitized_name = self.sanitize(group_name);
+            if !data.bench_groups.contains_key(group_name) {
+                if let Some(index) = data.bench_groups.get_index_of(&*sanitized_name) {
+                    let (_, results) = data.bench_groups.swap_remove_index(index).unwrap();
+                    let (last, _) = data.bench_groups.insert_full(group_name.clone(), results);
+                    data.bench_groups.swap_indices(index, last);
+                }
+            }
+            let Some(results) = data.bench_groups.get_mut(group_name) else {
+                continue;
+            };
+            for result in results.iter_mut().filter(|result| result.id.is_none()) {
+                let stored = result.cmd.join(" ");
+                if benches.iter().any(|bench| bench.command == stored) {
+                    continue;
+                }
+                if let Some(bench) = benches
+                    .iter()
+                    .find(|bench| self.sanitize(&bench.command) == stored)
+                {
+                    result.cmd = bench.command.split(' ').map(str::to_owned).collect();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn sanitizer_for_test(config: &str, runner: Option<&str>) -> Sanitizer {
+    Sanitizer::new(serde_json::from_str(config).unwrap(), runner)
+}
+
+#[test]
+fn sanitize_text() {
+    // No rules, no changes.
+    let none = Sanitizer::default();
+    assert!(matches!(
+        none.sanitize("/home/alice/corpus"),
+        Cow::Borrowed(_)
+    ));
+    let no_defaults = sanitizer_for_test(r#"{ "defaults": false }"#, Some("ci-runner-7"));
+    assert_eq!(
+        no_defaults.sanitize("/home/alice on ci-runner-7"),
+        "/home/alice on ci-runner-7"
+    );
+
+    let defaults = sanitizer_for_test("{}", Some("ci-runner-7"));
+    assert_eq!(
+        defaults.sanitize("./c /home/alice/corpus/a.tar on ci-runner-7, /Users/bob"),
+        "./c /home/<user>/corpus/a.tar on <runner>, /Users/<user>"
+    );
+    assert!(matches!(defaults.sanitize("./c 1"), Cow::Borrowed(_)));
+
+    // Overlapping rules apply in order, each to the result of the previous one, and before
+    // the built-in ones.
+    let overlapping = sanitizer_for_test(
+        r#"{
+            "rules": [
+                { "pattern": "/home/alice/corpus", "replacement": "<corpus>" },
+                { "pattern": "<corpus>/(\\w+)\\.tar", "replacement": "<corpus>/$1" },
+                { "pattern": "/home/alice", "replacement": "~" }
+            ]
+        }"#,
+        None,
+    );
+    assert_eq!(
+        overlapping.sanitize("/home/alice/corpus/a.tar /home/alice/b /home/bob/c"),
+        "<corpus>/a ~/b /home/<user>/c"
+    );
+}
+
+#[test]
+fn invalid_rules() {
+    let err = serde_json::from_str::<SanitizeConfig>(
+        r#"{ "rules": [{ "pattern": "/home/(", "replacement": "" }] }"#,
+    )
+    .unwrap_err();
+    assert!(
+        err.to_string()
+            .starts_with("invalid sanitize pattern `/home/(`: unclosed group"),
+        "{err}"
+    );
+    let err = serde_json::from_str::<SanitizeConfig>(
+        r#"{ "rules": [{ "pattern": "/home", "replacement": "$1" }] }"#,
+    )
+    .unwrap_err();
+    assert!(
+        err.to_string().starts_with(
+            "invalid sanitize pattern `/home`: `$1` refers to a group the pattern doesn't have"
+        ),
+        "{err}"
+    );
+}
+
+#[test]
+fn sanitize_results() {
+    let sanitizer = sanitizer_for_test("{}", Some("ci-runner-7"));
+    let data = crate::testkit::BenchDataBuilder::new("1111111111111111111111111111111111111111")
+        .group("/home/alice/corpus", |g| {
+            g.bench(["./c", "/home/alice/corpus/a.tar"], |b| {
+                b.counter("cycles", 1000.0, 10.0, 20, "")
+            })
+        })
+        .build();
+
+    let json = sanitizer.to_json(&data);
+    assert!(!json.contains("alice"), "{json}");
+    assert!(json.contains(r#""/home/<user>/corpus":[{"cmd":["./c","/home/<user>/corpus/a.tar"]"#));
+    // The results themselves keep the real values.
+    assert!(data.bench_groups.contains_key("/home/alice/corpus"));
+
+    // Loaded back, the real names are restored from the config, so the baseline is found.
+    let config: crate::Config = serde_json::from_str(
+        r#"{
+            "commands": {
+                "/home/alice/corpus": ["./c /home/alice/corpus/a.tar", "./c 2"]
+            },
+            "render-versus-self": {},
+            "render-versus-other": {}
+        }"#,
+    )
+    .unwrap();
+    let mut stored: BenchData = serde_json::from_str(&json).unwrap();
+    sanitizer.restore(&mut stored, &config.commands);
+    let prev_group = &stored.bench_groups["/home/alice/corpus"];
+    let bench = &data.bench_groups["/home/alice/corpus"][0];
+    assert!(crate::compare::find_prev_bench(prev_group, bench).is_some());
+
+    // Nothing to restore without rules.
+    let mut stored: BenchData = serde_json::from_str(&json).unwrap();
+    Sanitizer::default().restore(&mut stored, &config.commands);
+    assert!(stored.bench_groups.contains_key("/home/<user>/corpus"));
+}
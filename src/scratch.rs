@@ -0,0 +1,173 @@
+//! The directory for the intermediate files of a run, like the output of perf. Runners fill
+//! up when failed runs leave their files behind, so everything goes into one directory that
+//! is removed when the run ends, however it ends.
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::SystemTime;
+
+/// Removes its directory on drop, unless it is kept.
+#[derive(Debug)]
+pub struct RunScratch {
+    dir: PathBuf,
+    /// `--keep-scratch`
+    keep: bool,
+    /// `keep-scratch-on-failure`
+    keep_on_failure: bool,
+}
+
+impl RunScratch {
+    /// Create a fresh directory under `root`, named after the commit and the current time.
+    pub fn create(
+        root: &Path,
+        commit_hash: &str,
+        keep: bool,
+        keep_on_failure: bool,
+    ) -> Result<Self, String> {
+        fs::create_dir_all(root)
+            .map_err(|e| format!("failed to create {}: {e}", root.display()))?;
+
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let name = format!(
+            "benchmarker-{}-{timestamp}",
+            commit_hash.get(..12).unwrap_or(commit_hash)
+        );
+
+        // Creating the directory fails when it exists, so concurrent runs of the same commit
+        // never share one.
+        for attempt in 0.. {
+            let dir = match attempt {
+                0 => root.join(&name),
+                _ => root.join(format!("{name}-{attempt}")),
+            };
+            match fs::create_dir(&dir) {
+                Ok(()) => {
+                    return Ok(RunScratch {
+                        dir,
+                        keep,
+                        keep_on_failure,
+                    })
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(format!("failed to create {}: {e}", dir.display())),
+            }
+        }
+        unreachable!()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.dir
+    }
+
+    /// End the run, removing the directory unless it is kept. Returns the path of a kept
+    /// directory.
+    pub fn finish(mut self, failed: bool) -> Option<PathBuf> {
+        if !(self.keep || failed && self.keep_on_failure) {
+            return None;
+        }
+
+        eprintln!("scratch directory kept at {}", self.dir.display());
+        // Dropping doesn't remove anything with `keep` set.
+        self.keep = true;
+        Some(self.dir.clone())
+    }
+}
+
+impl Drop for RunScratch {
+    fn drop(&mut self) {
+        if self.keep {
+            return;
+        }
+        if let Err(err) = fs::remove_dir_all(&self.dir) {
+            eprintln!("warning: failed to remove {}: {err}", self.dir.display());
+        }
+    }
+}
+
+/// A fresh path in the scratch directory `dir` for e.g. perf to write its output to.
+pub fn file_path(dir: &Path, name: &str, extension: &str) -> PathBuf {
+    static FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    dir.join(format!(
+        "{name}-{}.{extension}",
+        FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+#[test]
+fn remove_scratch_on_success() {
+    let root = crate::testkit::test_dir("scratch-success");
+
+    let scratch = RunScratch::create(&root, "0123456789abcdef", false, true).unwrap();
+    let dir = scratch.path().to_owned();
+    assert!(dir
+        .file_name()
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .starts_with("benchmarker-0123456789ab-"));
+    fs::write(file_path(&dir, "perf", "json"), "{}").unwrap();
+
+    assert_eq!(scratch.finish(false), None);
+    assert!(!dir.exists());
+
+    // Also when the run panics.
+    let dir = std::panic::catch_unwind(|| {
+        let scratch = RunScratch::create(&root, "0123456789abcdef", false, false).unwrap();
+        std::panic::panic_any(scratch.path().to_owned());
+    })
+    .unwrap_err()
+    .downcast::<PathBuf>()
+    .unwrap();
+    assert!(!dir.exists());
+}
+
+#[test]
+fn keep_scratch() {
+    let root = crate::testkit::test_dir("scratch-keep");
+
+    // A failed run keeps its files with `keep-scratch-on-failure`.
+    let scratch = RunScratch::create(&root, "abc", false, true).unwrap();
+    let dir = scratch.path().to_owned();
+    assert_eq!(scratch.finish(true), Some(dir.clone()));
+    assert!(dir.exists());
+
+    let scratch = RunScratch::create(&root, "abc", false, false).unwrap();
+    let dir = scratch.path().to_owned();
+    assert_eq!(scratch.finish(true), None);
+    assert!(!dir.exists());
+
+    // And every run with `--keep-scratch`.
+    let scratch = RunScratch::create(&root, "abc", true, false).unwrap();
+    let dir = scratch.path().to_owned();
+    assert_eq!(scratch.finish(false), Some(dir.clone()));
+    assert!(dir.exists());
+}
+
+#[test]
+fn concurrent_runs_get_distinct_scratch() {
+    let root = crate::testkit::test_dir("scratch-concurrent");
+
+    let scratches = std::thread::scope(|s| {
+        let threads = (0..8)
+            .map(|_| s.spawn(|| RunScratch::create(&root, "abc", false, false).unwrap()))
+            .collect::<Vec<_>>();
+        threads
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+
+    let mut dirs = scratches
+        .iter()
+        .map(|scratch| scratch.path())
+        .collect::<Vec<_>>();
+    dirs.sort();
+    dirs.dedup();
+    assert_eq!(dirs.len(), 8);
+}
@@ -0,0 +1,338 @@
+//! Writing to files that several invocations of the benchmarker in one job share, like the
+//! step summary when a job benchmarks more than one suite. What an invocation writes is
+//! appended, unless the file already has what the same invocation wrote before, e.g. in a
+//! retried step, which is then replaced.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+
+use crate::history;
+
+/// The start of the marker of every section.
+const MARKER_START: &str = "<!-- benchmarker ";
+/// The line after every section.
+const SECTION_END: &str = "<!-- /benchmarker -->";
+
+/// The hidden marker at the start of the section of a commit benchmarked with the given
+/// configs.
+pub fn marker(commit: &str, config_paths: &[PathBuf]) -> String {
+    let configs = config_paths
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+    // `--` would end the comment early.
+    let key = format!("commit={commit} config={configs}").replace("--", "-%2D");
+    format!("{MARKER_START}{key} -->")
+}
+
+/// Append `content` to the markdown file at `path` as the section with `marker`, or replace
+/// the section with that marker if the file already has one.
+pub fn write_section(path: &Path, marker: &str, content: &str) -> Result<(), String> {
+    let mut section = format!("{marker}\n{content}");
+    if !section.ends_with('\n') {
+        section.push('\n');
+    }
+    section.push_str(SECTION_END);
+    section.push('\n');
+
+    let existing = read_existing(path)?;
+    let Some((start, _, end)) = find_section(&existing, marker) else {
+        return append(path, ends_unfinished(existing.as_bytes()), &section);
+    };
+    replace(
+        path,
+        &[&existing[..start], &section, &existing[end..]].concat(),
+    )
+}
+
+/// The content of the section with `marker` in the markdown file at `path`, if it has one.
+pub fn read_section(path: &Path, marker: &str) -> Result<Option<String>, String> {
+    let existing = read_existing(path)?;
+    Ok(find_section(&existing, marker).map(|(_, content, end)| {
+        let section = &existing[content..end];
+        section
+            .trim_end_matches(['\n', '\r'])
+            .strip_suffix(SECTION_END)
+            .unwrap_or(section)
+            .to_owned()
+    }))
+}
+
+/// The offsets of the start of the section with `marker`, of its content, and of its end.
+fn find_section(existing: &str, marker: &str) -> Option<(usize, usize, usize)> {
+    let lines = lines(existing);
+    let start = lines.iter().position(|&(_, _, line)| line == marker)?;
+
+    // The section ends with its end line, or, if that got lost, where the next one starts.
+    let end = lines[start + 1..]
+        .iter()
+        .find_map(|&(line_start, line_end, line)| {
+            if line == SECTION_END {
+                Some(line_end)
+            } else if line.starts_with(MARKER_START) {
+                Some(line_start)
+            } else {
+                None
+            }
+        })
+        .unwrap_or(existing.len());
+    Some((lines[start].0, lines[start].1, end))
+}
+
+/// Append `line` to the file at `path`, or replace the first line for which `is_same` holds
+/// if there is one. The file, like the growing results, is streamed rather than read whole.
+pub fn write_line(
+    path: &Path,
+    line: &str,
+    mut is_same: impl FnMut(&[u8]) -> bool,
+) -> Result<(), String> {
+    if !path.exists() {
+        return append(path, false, &format!("{line}\n"));
+    }
+    // The line to replace, from its start to the start of the next one.
+    let mut found = None;
+    let mut next = None;
+    history::for_each_line(path, |offset, existing| {
+        if found.is_some() {
+            next = Some(offset);
+            ControlFlow::Break(())
+        } else {
+            if is_same(existing) {
+                found = Some(offset);
+            }
+            ControlFlow::Continue(())
+        }
+    })?;
+    let Some(start) = found else {
+        return append(
+            path,
+            ends_unfinished(&last_byte(path)?),
+            &format!("{line}\n"),
+        );
+    };
+
+    let read_error = |e: std::io::Error| format!("failed to read {}: {e}", path.display());
+    let mut file = File::open(path).map_err(read_error)?;
+    replace_with(path, |tmp| {
+        io::copy(&mut (&mut file).take(start), tmp)?;
+        writeln!(tmp, "{line}")?;
+        if let Some(next) = next {
+            file.seek(SeekFrom::Start(next))?;
+            io::copy(&mut file, tmp)?;
+        }
+        Ok(())
+    })
+}
+
+/// The last byte of the file at `path`, if it isn't empty.
+fn last_byte(path: &Path) -> Result<Vec<u8>, String> {
+    let read_error = |e: std::io::Error| format!("failed to read {}: {e}", path.display());
+    let mut file = File::open(path).map_err(read_error)?;
+    let len = file.metadata().map_err(read_error)?.len();
+    let mut last = vec![];
+    if len > 0 {
+        file.seek(SeekFrom::Start(len - 1)).map_err(read_error)?;
+        file.read_to_end(&mut last).map_err(read_error)?;
+    }
+    Ok(last)
+}
+
+/// Whether the `existing` text ends with an unfinished line, like whatever wrote the file
+/// before left it.
+fn ends_unfinished(existing: &[u8]) -> bool {
+    existing.last().is_some_and(|&last| last != b'\n')
+}
+
+fn read_existing(path: &Path) -> Result<String, String> {
+    match fs::read_to_string(path) {
+        Ok(existing) => Ok(existing),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(String::new()),
+        Err(e) => Err(format!("failed to read {}: {e}", path.display())),
+    }
+}
+
+/// Every line with the offsets it starts at and the next one starts at.
+fn lines(text: &str) -> Vec<(usize, usize, &str)> {
+    let mut offset = 0;
+    text.split_inclusive('\n')
+        .map(|line| {
+            let start = offset;
+            offset += line.len();
+            (start, offset, line.trim_end_matches(['\n', '\r']))
+        })
+        .collect()
+}
+
+fn append(path: &Path, unfinished: bool, text: &str) -> Result<(), String> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("failed to open {}: {e}", path.display()))?;
+    // Don't glue the text onto an unfinished last line of whatever wrote the file before.
+    let separator = if unfinished { "\n" } else { "" };
+    write!(file, "{separator}{text}")
+        .map_err(|e| format!("failed to write {}: {e}", path.display()))
+}
+
+/// Replace the file at `path` with `contents` by renaming a new file over it, so it is never
+/// left half written.
+fn replace(path: &Path, contents: &str) -> Result<(), String> {
+    replace_with(path, |tmp| tmp.write_all(contents.as_bytes()))
+}
+
+/// Replace the file at `path` with what `write` writes, by renaming a new file over it.
+fn replace_with(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+) -> Result<(), String> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(format!(".{}.tmp", std::process::id()));
+    let tmp = PathBuf::from(tmp);
+
+    let written = File::create(&tmp).and_then(|file| {
+        let mut writer = BufWriter::new(file);
+        write(&mut writer)?;
+        writer
+            .into_inner()
+            .map_err(io::IntoInnerError::into_error)?;
+        Ok(())
+    });
+    if let Err(e) = written {
+        let _ = fs::remove_file(&tmp);
+        return Err(format!("failed to write {}: {e}", tmp.display()));
+    }
+    fs::rename(&tmp, path).map_err(|e| {
+        let _ = fs::remove_file(&tmp);
+        format!("failed to replace {}: {e}", path.display())
+    })
+}
+
+#[test]
+fn append_and_replace_sections() {
+    let dir = crate::testkit::test_dir("sections");
+    let path = dir.join("summary.md");
+    let compression = marker("abc", &[PathBuf::from("benches/compression.json")]);
+    let parsing = marker("abc", &[PathBuf::from("benches/parsing.json")]);
+    assert_eq!(
+        compression,
+        "<!-- benchmarker commit=abc config=benches/compression.json -->"
+    );
+
+    // First write.
+    write_section(&path, &compression, "## compression\n").unwrap();
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "<!-- benchmarker commit=abc config=benches/compression.json -->\n## compression\n<!-- /benchmarker -->\n"
+    );
+
+    // Another suite, after something else wrote to the summary too.
+    fs::write(
+        &path,
+        fs::read_to_string(&path).unwrap() + "written by another step",
+    )
+    .unwrap();
+    write_section(&path, &parsing, "## parsing\n").unwrap();
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "<!-- benchmarker commit=abc config=benches/compression.json -->\n## compression\n<!-- /benchmarker -->\n\
+         written by another step\n\
+         <!-- benchmarker commit=abc config=benches/parsing.json -->\n## parsing\n<!-- /benchmarker -->\n"
+    );
+
+    // A re-run replaces its own section only.
+    write_section(&path, &compression, "## compression, again\n\nretried").unwrap();
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "<!-- benchmarker commit=abc config=benches/compression.json -->\n## compression, again\n\nretried\n<!-- /benchmarker -->\n\
+         written by another step\n\
+         <!-- benchmarker commit=abc config=benches/parsing.json -->\n## parsing\n<!-- /benchmarker -->\n"
+    );
+    assert_eq!(
+        read_section(&path, &compression).unwrap().as_deref(),
+        Some("## compression, again\n\nretried\n")
+    );
+    assert_eq!(read_section(&path, &marker("def", &[])).unwrap(), None);
+    write_section(&path, &parsing, "## parsing, again\n").unwrap();
+    assert!(fs::read_to_string(&path)
+        .unwrap()
+        .ends_with("config=benches/parsing.json -->\n## parsing, again\n<!-- /benchmarker -->\n"));
+
+    // Without its end, a section runs up to the next one.
+    fs::write(
+        &path,
+        format!("{compression}\n## cut short\n{parsing}\n## parsing\n{SECTION_END}\n"),
+    )
+    .unwrap();
+    assert_eq!(
+        read_section(&path, &compression).unwrap().as_deref(),
+        Some("## cut short\n")
+    );
+    write_section(&path, &compression, "## compression\n").unwrap();
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        format!(
+            "{compression}\n## compression\n{SECTION_END}\n{parsing}\n## parsing\n{SECTION_END}\n"
+        )
+    );
+
+    // A new commit is a new section.
+    write_section(&path, &marker("def", &[]), "## next\n").unwrap();
+    assert_eq!(
+        fs::read_to_string(&path)
+            .unwrap()
+            .matches(MARKER_START)
+            .count(),
+        3
+    );
+
+    assert_eq!(
+        marker(
+            "abc",
+            &[PathBuf::from("a--b.json"), PathBuf::from("c.json")]
+        ),
+        "<!-- benchmarker commit=abc config=a-%2Db.json,c.json -->"
+    );
+}
+
+#[test]
+fn append_and_replace_lines() {
+    let dir = crate::testkit::test_dir("sections-lines");
+    let path = dir.join("results.json");
+    let same_suite = |suite: &'static str| move |line: &[u8]| line.starts_with(suite.as_bytes());
+
+    write_line(&path, "compression 1", same_suite("compression")).unwrap();
+    write_line(&path, "parsing 1", same_suite("parsing")).unwrap();
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "compression 1\nparsing 1\n"
+    );
+
+    write_line(&path, "compression 2", same_suite("compression")).unwrap();
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "compression 2\nparsing 1\n"
+    );
+    write_line(&path, "parsing 2", same_suite("parsing")).unwrap();
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "compression 2\nparsing 2\n"
+    );
+
+    // After an unfinished line, which is replaced as a whole.
+    fs::write(&path, "compression 2\r\nparsing 2").unwrap();
+    write_line(&path, "search 1", same_suite("search")).unwrap();
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "compression 2\r\nparsing 2\nsearch 1\n"
+    );
+    fs::write(&path, "compression 2\r\nparsing 2").unwrap();
+    write_line(&path, "parsing 3", same_suite("parsing")).unwrap();
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "compression 2\r\nparsing 3\n"
+    );
+}
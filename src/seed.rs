@@ -0,0 +1,119 @@
+//! The random choices of a run, like the order of the commands of the groups with
+//! `shuffle-for-group`, all come from a single [`Rng`]. It is seeded with `--seed <seed>`, with
+//! the seed of stored results with `--replay-seed-from <results>`, or with a seed generated at
+//! the start of the run. The seed is recorded with the results and shown in the header of the
+//! summary, so a run in CI can be reproduced locally with the same choices.
+//!
+//! The generator is passed to everything that needs it, in the order of the run, so the same
+//! seed and config make the same choices.
+
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::BenchData;
+
+/// A SplitMix64 generator. It is not cryptographically secure, it only has to be fast and
+/// reproducible.
+#[derive(Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number below `bound`, which must not be 0.
+    pub fn below(&mut self, bound: usize) -> usize {
+        ((u128::from(self.next_u64()) * bound as u128) >> 64) as usize
+    }
+
+    /// Put `items` in a random order, with a Fisher-Yates shuffle.
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, self.below(i + 1));
+        }
+    }
+}
+
+/// A seed for a run without `--seed`, from the time and the process id.
+pub fn generate() -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64;
+    Rng::new(nanos ^ u64::from(std::process::id()).rotate_left(32)).next_u64()
+}
+
+/// The seed of the last results with one in the NDJSON at `path`, for `--replay-seed-from`.
+pub fn from_results(path: &Path) -> Result<u64, String> {
+    let results =
+        std::fs::read(path).map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+    results
+        .split(|&b| b == b'\n')
+        .rev()
+        .filter_map(|line| serde_json::from_slice::<BenchData>(line).ok())
+        .find_map(|data| data.seed)
+        .ok_or_else(|| format!("no results with a seed in {}", path.display()))
+}
+
+#[test]
+fn shuffle_with_seed() {
+    let shuffled = |seed| {
+        let mut items = (0..20).collect::<Vec<_>>();
+        Rng::new(seed).shuffle(&mut items);
+        items
+    };
+
+    // The same seed makes the same choices, and a different one other choices.
+    assert_eq!(shuffled(42), shuffled(42));
+    assert_ne!(shuffled(42), shuffled(43));
+    let mut sorted = shuffled(42);
+    assert_ne!(sorted, (0..20).collect::<Vec<_>>());
+    sorted.sort();
+    assert_eq!(sorted, (0..20).collect::<Vec<_>>());
+
+    let mut rng = Rng::new(7);
+    assert!((0..1000).all(|_| rng.below(3) < 3));
+    assert_eq!(Rng::new(7).below(1), 0);
+}
+
+#[test]
+fn seed_from_results() {
+    let dir = crate::testkit::test_dir("seed");
+    let results = dir.join("results.json");
+    let entry = |commit_hash: &str, seed| {
+        let mut data = crate::testkit::BenchDataBuilder::new(commit_hash).build();
+        data.seed = seed;
+        serde_json::to_string(&data).unwrap()
+    };
+
+    // The last entry with a seed, skipping other lines.
+    std::fs::write(
+        &results,
+        [
+            entry("aaa", Some(1)),
+            "{\"type\":\"bench\"}".to_owned(),
+            entry("bbb", Some(2)),
+            entry("ccc", None),
+        ]
+        .join("\n"),
+    )
+    .unwrap();
+    assert_eq!(from_results(&results), Ok(2));
+
+    std::fs::write(&results, entry("ccc", None)).unwrap();
+    assert_eq!(
+        from_results(&results),
+        Err(format!("no results with a seed in {}", results.display()))
+    );
+}
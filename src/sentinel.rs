@@ -0,0 +1,382 @@
+//! A group measured at the very start and at the very end of the run, with `sentinel-group`.
+//! Whatever group runs first may pay for cold file system caches or a CPU that hasn't warmed
+//! up, and a run can drift as it goes. Measuring the same group twice tells that apart from
+//! changes of the benchmarks:
+//!
+//! ```json
+//! "sentinel-group": "compress"
+//! ```
+//!
+//! The group is replaced by `<group>@start`, which runs first, and `<group>@end`, which runs
+//! last, both with the settings of the group. The tables, budgets and control groups that
+//! refer to the group refer to one of them instead, the `sentinel-measurement`, `end` by
+//! default, so their rows resolve by index as before. A run stability table compares the two,
+//! with a warning when they differ significantly.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use indexmap::IndexMap;
+use serde::Deserialize;
+
+use crate::compare::{geomean_delta_percent, ComparisonKind, ComparisonRow, ComparisonTable};
+use crate::markers::Markers;
+use crate::measure::MeasureKind;
+use crate::{rusage, BenchData, Config, TableDisplay};
+
+/// Which measurement of the sentinel group the tables, budgets and control groups refer to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SentinelMeasurement {
+    Start,
+    #[default]
+    End,
+}
+
+pub fn start_key(group_name: &str) -> String {
+    format!("{group_name}@start")
+}
+
+pub fn end_key(group_name: &str) -> String {
+    format!("{group_name}@end")
+}
+
+impl SentinelMeasurement {
+    pub fn key(self, group_name: &str) -> String {
+        match self {
+            SentinelMeasurement::Start => start_key(group_name),
+            SentinelMeasurement::End => end_key(group_name),
+        }
+    }
+}
+
+/// Copy the setting of `group_name` to `keys`.
+fn duplicate<V: Clone>(settings: &mut HashMap<String, V>, group_name: &str, keys: &[String; 2]) {
+    if let Some(setting) = settings.remove(group_name) {
+        for key in keys {
+            settings.insert(key.clone(), setting.clone());
+        }
+    }
+}
+
+/// Replace the `sentinel-group` of `config` with its start and end measurements.
+pub fn expand(config: &mut Config) -> Result<(), String> {
+    let Some(group_name) = config.sentinel_group.clone() else {
+        return Ok(());
+    };
+    let Some(benches) = config.commands.shift_remove(&group_name) else {
+        return Err(format!("the `sentinel-group` `{group_name}` doesn't exist"));
+    };
+    if benches.iter().any(|bench| bench.is_composite()) {
+        return Err(format!(
+            "the `sentinel-group` `{group_name}` has composites, which can't be measured twice"
+        ));
+    }
+    let keys = [start_key(&group_name), end_key(&group_name)];
+    let referenced = config.sentinel_measurement.key(&group_name);
+
+    let mut commands = IndexMap::new();
+    commands.insert(keys[0].clone(), benches.clone());
+    commands.extend(std::mem::take(&mut config.commands));
+    commands.insert(keys[1].clone(), benches);
+    config.commands = commands;
+
+    if let Some(repetitions) = config.repetitions_for_group.shift_remove(&group_name) {
+        for key in &keys {
+            config
+                .repetitions_for_group
+                .insert(key.clone(), repetitions);
+        }
+    }
+    duplicate(&mut config.backends_for_group, &group_name, &keys);
+    duplicate(&mut config.instruction_mix_for_group, &group_name, &keys);
+    duplicate(&mut config.perf_events_for_group, &group_name, &keys);
+    duplicate(&mut config.required_counters_for_group, &group_name, &keys);
+    duplicate(&mut config.interleave_for_group, &group_name, &keys);
+    duplicate(&mut config.shuffle_for_group, &group_name, &keys);
+    duplicate(&mut config.priority_for_group, &group_name, &keys);
+    duplicate(&mut config.flush_between_for_group, &group_name, &keys);
+    duplicate(&mut config.tags_for_group, &group_name, &keys);
+    duplicate(&mut config.paths_for_group, &group_name, &keys);
+    duplicate(&mut config.sort_raw_rows_for_group, &group_name, &keys);
+    if let Some(source) = config.group_sources.shift_remove(&group_name) {
+        for key in &keys {
+            config.group_sources.insert(key.clone(), source.clone());
+        }
+    }
+
+    let refer = |command: &mut String| {
+        if *command == group_name {
+            command.clone_from(&referenced);
+        }
+    };
+    config.control_groups.iter_mut().for_each(refer);
+    for table in config.render_versus_other.values_mut() {
+        refer(&mut table.command);
+    }
+    for row in config
+        .render_versus_self
+        .values_mut()
+        .flat_map(|table| table.rows.values_mut())
+    {
+        refer(&mut row.before.command);
+        refer(&mut row.after.command);
+    }
+    for budget in config.budgets.values_mut() {
+        refer(&mut budget.group);
+    }
+    for table in config
+        .render_cross_machine
+        .iter_mut()
+        .flat_map(|cross_machine| cross_machine.tables.values_mut())
+    {
+        refer(&mut table.command);
+    }
+    Ok(())
+}
+
+/// The group whose tables apply to `group_name`: the referenced measurement for both
+/// measurements of the sentinel group, so they are interleaved alike.
+pub fn referenced_group<'a>(config: &'a Config, group_name: &'a str) -> Cow<'a, str> {
+    match &config.sentinel_group {
+        Some(sentinel) if group_name == start_key(sentinel) || group_name == end_key(sentinel) => {
+            config.sentinel_measurement.key(sentinel).into()
+        }
+        _ => group_name.into(),
+    }
+}
+
+/// Compare every counter of every command of the sentinel group at the end of the run with
+/// the start. `None` without a sentinel group, or when a measurement is missing.
+pub fn collect(
+    config: &Config,
+    kinds: &IndexMap<String, MeasureKind>,
+    data: &BenchData,
+) -> Option<ComparisonTable> {
+    let group_name = config.sentinel_group.as_ref()?;
+    let start = data.bench_groups.get(&start_key(group_name))?;
+    let end = data.bench_groups.get(&end_key(group_name))?;
+
+    let mut rows = vec![];
+    for (start, end) in start.iter().zip(end) {
+        for (counter, before) in &start.counters {
+            if !config.show_cold_warm && rusage::is_cold_or_warm(counter) {
+                continue;
+            }
+            let Some(after) = end.counters.get(counter) else {
+                continue;
+            };
+            rows.push(ComparisonRow {
+                tags: end.tags.clone(),
+                ..ComparisonRow::new(
+                    format!("{} ({counter})", end.cmd.join(" ")),
+                    counter.clone(),
+                    MeasureKind::of(kinds, counter),
+                    before,
+                    after,
+                )
+            });
+        }
+    }
+
+    Some(ComparisonTable {
+        name: group_name.clone(),
+        kind: ComparisonKind::VersusSelf,
+        rows,
+        display: TableDisplay::default(),
+        rolling_window: None,
+        build_notes: vec![],
+        sampling_note: None,
+    })
+}
+
+/// Warn when the sentinel group measured differently at the end of the run than at the start.
+pub fn render_markdown_warning(md: &mut String, stability: Option<&ComparisonTable>) {
+    let Some(table) = stability else {
+        return;
+    };
+    let drifted = table
+        .rows
+        .iter()
+        .filter(|row| row.is_actionable())
+        .collect::<Vec<_>>();
+    if drifted.is_empty() {
+        return;
+    }
+    writeln!(
+        md,
+        "> [!WARNING]\n> Within-run drift detected: the `{}` sentinel group measured differently at the end of the run than at the start, in {} of {} comparisons, `geomean {:>+6.2}%`:",
+        table.name,
+        drifted.len(),
+        table.rows.len(),
+        geomean_delta_percent(drifted.iter().copied()),
+    )
+    .unwrap();
+    for row in drifted {
+        writeln!(md, "> - {}: `{}`", row.name, row.format_delta()).unwrap();
+    }
+    writeln!(md).unwrap();
+}
+
+/// The run stability table: the sentinel group at the end of the run versus the start.
+pub fn render_markdown(md: &mut String, stability: Option<&ComparisonTable>, markers: &Markers) {
+    let Some(table) = stability.filter(|table| !table.rows.is_empty()) else {
+        return;
+    };
+    writeln!(md, "### Run stability: {}\n", table.name).unwrap();
+    table.render_markdown(
+        md,
+        "| name | start | end | Δ |\n| --- | --- | --- | --- |\n",
+        markers,
+    );
+    writeln!(md).unwrap();
+}
+
+#[cfg(test)]
+fn config_for_test(sentinel: &str) -> Config {
+    serde_json::from_str(&format!(
+        r#"{{
+            "commands": {{
+                "first": ["./first"],
+                "compress": ["./c 1", "./c 9"],
+                "last": ["./last"]
+            }},
+            "repetitions-for-group": {{ "compress": 5, "last": 3 }},
+            "tags-for-group": {{ "compress": ["slow"] }},
+            "control-groups": ["compress"],
+            "budgets": {{
+                "fast": {{ "group": "compress", "command": 0, "measure": "cycles", "operator": "<", "limit": 1000 }}
+            }},
+            "render-versus-self": {{
+                "levels": {{
+                    "9 vs 1": {{ "measure": "cycles", "before": {{ "command": "compress", "index": 0 }}, "after": {{ "command": "compress", "index": 1 }} }}
+                }}
+            }},
+            "render-versus-other": {{
+                "compression": {{ "measure": "cycles", "command": "compress", "rows": {{ "level 9": 1 }} }}
+            }},
+            {sentinel}
+        }}"#
+    ))
+    .unwrap()
+}
+
+#[test]
+fn sentinel_runs_first_and_last() {
+    let mut config = config_for_test(r#""sentinel-group": "compress""#);
+    expand(&mut config).unwrap();
+    config.validate().unwrap();
+
+    // Measured before and after every other group, with the settings of the group.
+    assert_eq!(
+        config.commands.keys().collect::<Vec<_>>(),
+        ["compress@start", "first", "last", "compress@end"]
+    );
+    assert_eq!(config.commands["compress@start"].len(), 2);
+    assert_eq!(config.repetitions("compress@start"), 5);
+    assert_eq!(config.repetitions("compress@end"), 5);
+    assert_eq!(config.repetitions("last"), 3);
+    assert_eq!(config.tags_for_group["compress@start"], ["slow"]);
+    assert!(!config.tags_for_group.contains_key("compress"));
+
+    // Everything that referred to the group refers to the end, at the same indices.
+    assert_eq!(
+        config.render_versus_other["compression"].command,
+        "compress@end"
+    );
+    assert_eq!(config.render_versus_other["compression"].rows["level 9"], 1);
+    let row = &config.render_versus_self["levels"].rows["9 vs 1"];
+    assert_eq!(
+        (row.before.command.as_str(), row.after.command.as_str()),
+        ("compress@end", "compress@end")
+    );
+    assert_eq!(config.control_groups, ["compress@end"]);
+    assert_eq!(config.budgets["fast"].group, "compress@end");
+
+    // Both measurements are interleaved like the one the tables refer to.
+    assert_eq!(referenced_group(&config, "compress@start"), "compress@end");
+    assert_eq!(referenced_group(&config, "first"), "first");
+    assert_eq!(
+        crate::interleave::pairs(&config, "compress@start"),
+        crate::interleave::pairs(&config, "compress@end")
+    );
+
+    // Or to the start, when asked.
+    let mut config =
+        config_for_test(r#""sentinel-group": "compress", "sentinel-measurement": "start""#);
+    expand(&mut config).unwrap();
+    assert_eq!(
+        config.render_versus_other["compression"].command,
+        "compress@start"
+    );
+
+    let mut config = config_for_test(r#""sentinel-group": "compres""#);
+    assert_eq!(
+        expand(&mut config).unwrap_err(),
+        "the `sentinel-group` `compres` doesn't exist"
+    );
+    // Without a sentinel, nothing changes.
+    let mut config = config_for_test(r#""sentinel-measurement": "end""#);
+    expand(&mut config).unwrap();
+    assert_eq!(
+        config.commands.keys().collect::<Vec<_>>(),
+        ["first", "compress", "last"]
+    );
+}
+
+#[test]
+fn run_stability_table() {
+    use crate::testkit::BenchDataBuilder;
+
+    let mut config = config_for_test(r#""sentinel-group": "compress""#);
+    expand(&mut config).unwrap();
+
+    let group = |cycles: [f64; 2]| {
+        move |g: crate::testkit::GroupBuilder| {
+            g.bench(["./c", "1"], |b| {
+                b.counter("cycles", cycles[0], 100.0, 20, "")
+            })
+            .bench(["./c", "9"], |b| {
+                b.counter("cycles", cycles[1], 100.0, 20, "")
+            })
+        }
+    };
+    let data = BenchDataBuilder::new("2222222222222222222222222222222222222222")
+        .group("compress@start", group([1000.0, 2000.0]))
+        .group("first", |g| g.bench(["./first"], |b| b))
+        .group("compress@end", group([1000.0, 2200.0]))
+        .build();
+
+    let stability = collect(&config, &IndexMap::new(), &data).unwrap();
+    assert_eq!(stability.rows.len(), 2);
+    assert_eq!(stability.rows[1].name, "./c 9 (cycles)");
+    assert!(!stability.rows[0].is_actionable());
+    assert!(stability.rows[1].is_regression());
+
+    let mut md = String::new();
+    render_markdown_warning(&mut md, Some(&stability));
+    render_markdown(&mut md, Some(&stability), &Markers::default());
+    assert_eq!(
+        md,
+        "> [!WARNING]\n> Within-run drift detected: the `compress` sentinel group measured differently at the end of the run than at the start, in 1 of 2 comparisons, `geomean  +9.09%`:\n\
+         > - ./c 9 (cycles): `+9.09%`\n\n\
+         ### Run stability: compress\n\n\
+         | name | start | end | Δ |\n\
+         | --- | --- | --- | --- |\n\
+         | ./c 1 (cycles) | `  1.00K ±      10` | `  1.00K ±      10` | `    +0.00%` |\n\
+         | ./c 9 (cycles) | `  2.00K ±      10` | `  2.20K ±      10` | `💩  +9.09%` |\n\n\
+         💩 significant regression\n\n"
+    );
+
+    // Nothing to warn about without drift.
+    let mut md = String::new();
+    let mut stable = stability.clone();
+    stable.rows.truncate(1);
+    render_markdown_warning(&mut md, Some(&stable));
+    assert_eq!(md, "");
+    let without_end = BenchDataBuilder::new("2222222222222222222222222222222222222222")
+        .group("compress@start", group([1000.0, 2000.0]))
+        .build();
+    assert!(collect(&config, &IndexMap::new(), &without_end).is_none());
+}
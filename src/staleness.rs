@@ -0,0 +1,278 @@
+//! How stale the baseline is. When main hasn't been benchmarked in a while, a comparison
+//! against the last results of main includes everything that landed since, not just the
+//! changes of the benchmarked commit. With `baseline-staleness`, the age of the baseline and
+//! the number of commits since are shown in the headers of the comparisons, with a warning
+//! when either is over its threshold.
+
+use std::fmt::Write;
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use crate::units;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct StalenessConfig {
+    /// The age from which the baseline is stale, in days when it is a plain number.
+    #[serde(
+        rename = "max-age-days",
+        default = "default_max_age",
+        deserialize_with = "units::days"
+    )]
+    pub max_age: Duration,
+    /// The number of commits since the baseline from which it is stale.
+    #[serde(default = "default_max_commits")]
+    pub max_commits: usize,
+}
+
+fn default_max_age() -> Duration {
+    Duration::from_secs(14 * 24 * 3600)
+}
+
+fn default_max_commits() -> usize {
+    50
+}
+
+/// How old the baseline is.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Staleness {
+    /// The time between the baseline and this run, in seconds.
+    pub age_secs: u64,
+    /// The commits since the baseline, up to the parent of the benchmarked commit.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commits: Option<usize>,
+    /// Why the commits are unknown, e.g. in a shallow clone.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// Whether the age or the commits are over the thresholds.
+    pub stale: bool,
+}
+
+impl StalenessConfig {
+    /// The staleness of a baseline measured `age` before this run, with `commits` since.
+    pub fn evaluate(&self, age: Duration, commits: Result<usize, String>) -> Staleness {
+        let (commits, note) = match commits {
+            Ok(commits) => (Some(commits), None),
+            Err(note) => (None, Some(note)),
+        };
+        Staleness {
+            age_secs: age.as_secs(),
+            stale: age > self.max_age || commits.is_some_and(|commits| commits > self.max_commits),
+            commits,
+            note,
+        }
+    }
+}
+
+impl Staleness {
+    /// E.g. `baseline is 19 days / 42 commits old`.
+    pub fn label(&self) -> String {
+        let age = format_age(self.age_secs);
+        match (self.commits, &self.note) {
+            (Some(1), _) => format!("baseline is {age} / 1 commit old"),
+            (Some(commits), _) => format!("baseline is {age} / {commits} commits old"),
+            (None, Some(note)) => format!("baseline is {age} old, commits unknown: {note}"),
+            (None, None) => format!("baseline is {age} old"),
+        }
+    }
+}
+
+/// The age in the largest whole unit of days, hours and minutes.
+fn format_age(secs: u64) -> String {
+    let (count, unit) = match secs {
+        86400.. => (secs / 86400, "day"),
+        3600.. => (secs / 3600, "hour"),
+        _ => (secs / 60, "minute"),
+    };
+    if count == 1 {
+        format!("1 {unit}")
+    } else {
+        format!("{count} {unit}s")
+    }
+}
+
+/// The time between measuring the baseline and this run, zero when the clocks disagree.
+pub fn age(baseline: SystemTime, now: SystemTime) -> Duration {
+    now.duration_since(baseline).unwrap_or_default()
+}
+
+/// The commits after `baseline_commit` up to the parent of HEAD in the repository at `dir`,
+/// like the merge base is taken from the parent. A shallow clone may not have the baseline,
+/// which is then named as the reason.
+pub fn commit_distance(dir: &Path, baseline_commit: &str) -> Result<usize, String> {
+    let output = Command::new("git")
+        .args(["rev-list", "--count"])
+        .arg(format!("{baseline_commit}..HEAD~"))
+        .current_dir(dir)
+        .output()
+        .map_err(|e| format!("failed to run git rev-list: {e}"))?;
+    if !output.status.success() {
+        if is_shallow(dir) {
+            return Err("the clone is shallow".to_owned());
+        }
+        return Err(format!(
+            "git rev-list failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse()
+        .map_err(|e| format!("unexpected output of git rev-list: {e}"))
+}
+
+fn is_shallow(dir: &Path) -> bool {
+    Command::new("git")
+        .args(["rev-parse", "--is-shallow-repository"])
+        .current_dir(dir)
+        .output()
+        .is_ok_and(|output| output.stdout.starts_with(b"true"))
+}
+
+/// Warn above the comparisons when the baseline is stale.
+pub fn render_markdown_warning(
+    md: &mut String,
+    config: &StalenessConfig,
+    staleness: Option<&Staleness>,
+) {
+    let Some(staleness) = staleness.filter(|staleness| staleness.stale) else {
+        return;
+    };
+    writeln!(
+        md,
+        "> [!WARNING]\n> The {}, more than the {} or {} commits of `baseline-staleness`. The \
+         comparisons below include every change since, not just those of this commit.\n",
+        staleness.label(),
+        format_age(config.max_age.as_secs()),
+        config.max_commits,
+    )
+    .unwrap();
+}
+
+#[cfg(test)]
+fn config_for_test() -> StalenessConfig {
+    serde_json::from_str(r#"{ "max-age-days": 14, "max-commits": 50 }"#).unwrap()
+}
+
+#[test]
+fn evaluate_staleness() {
+    let config = config_for_test();
+    let days = |days: u64| Duration::from_secs(days * 86400);
+
+    let fresh = config.evaluate(Duration::from_secs(5400), Ok(3));
+    assert!(!fresh.stale);
+    assert_eq!(fresh.label(), "baseline is 1 hour / 3 commits old");
+
+    let by_age = config.evaluate(days(19), Ok(42));
+    assert!(by_age.stale);
+    assert_eq!(by_age.label(), "baseline is 19 days / 42 commits old");
+
+    let by_distance = config.evaluate(days(2), Ok(51));
+    assert!(by_distance.stale);
+    assert_eq!(by_distance.label(), "baseline is 2 days / 51 commits old");
+    // Right at the thresholds is still fresh.
+    assert!(!config.evaluate(days(14), Ok(50)).stale);
+
+    // Only the age in a shallow clone.
+    let shallow = config.evaluate(days(1), Err("the clone is shallow".to_owned()));
+    assert!(!shallow.stale);
+    assert_eq!(shallow.commits, None);
+    assert_eq!(
+        shallow.label(),
+        "baseline is 1 day old, commits unknown: the clone is shallow"
+    );
+    assert!(
+        config
+            .evaluate(days(15), Err("the clone is shallow".to_owned()))
+            .stale
+    );
+
+    assert_eq!(format_age(59), "0 minutes");
+    assert_eq!(format_age(7200), "2 hours");
+    let now = SystemTime::now();
+    assert_eq!(age(now + days(1), now), Duration::ZERO);
+    assert_eq!(age(now - days(1), now), days(1));
+
+    let config: StalenessConfig = serde_json::from_str(r#"{ "max-age-days": "36h" }"#).unwrap();
+    assert_eq!(config.max_age, Duration::from_secs(36 * 3600));
+    assert_eq!(config.max_commits, 50);
+}
+
+#[test]
+fn distance_in_shallow_clone() {
+    let dir = crate::testkit::test_dir("staleness");
+    let git = |dir: &Path, args: &[&str]| {
+        let output = Command::new("git")
+            .args([
+                "-c",
+                "user.name=Bench",
+                "-c",
+                "user.email=bench@example.com",
+            ])
+            .args(["-c", "commit.gpgsign=false"])
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{output:?}");
+        String::from_utf8(output.stdout).unwrap().trim().to_owned()
+    };
+    let repo = dir.join("repo");
+    std::fs::create_dir_all(&repo).unwrap();
+    git(&repo, &["init", "--quiet"]);
+    for message in ["baseline", "a", "b", "c"] {
+        git(
+            &repo,
+            &["commit", "--quiet", "--allow-empty", "-m", message],
+        );
+    }
+    let baseline = git(&repo, &["rev-parse", "HEAD~3"]);
+
+    // `a` and `b`, not the benchmarked commit.
+    assert_eq!(commit_distance(&repo, &baseline), Ok(2));
+
+    let clone = dir.join("clone");
+    git(
+        &dir,
+        &[
+            "clone",
+            "--quiet",
+            "--depth=1",
+            &format!("file://{}", repo.display()),
+            "clone",
+        ],
+    );
+    assert_eq!(
+        commit_distance(&clone, &baseline),
+        Err("the clone is shallow".to_owned())
+    );
+}
+
+#[test]
+fn stale_baseline_warning() {
+    let config = config_for_test();
+    let mut md = String::new();
+    render_markdown_warning(
+        &mut md,
+        &config,
+        Some(&config.evaluate(Duration::from_secs(19 * 86400), Ok(42))),
+    );
+    assert_eq!(
+        md,
+        "> [!WARNING]\n> The baseline is 19 days / 42 commits old, more than the 14 days or 50 \
+         commits of `baseline-staleness`. The comparisons below include every change since, not \
+         just those of this commit.\n\n"
+    );
+
+    let mut md = String::new();
+    render_markdown_warning(
+        &mut md,
+        &config,
+        Some(&config.evaluate(Duration::from_secs(86400), Ok(1))),
+    );
+    render_markdown_warning(&mut md, &config, None);
+    assert_eq!(md, "");
+}
@@ -0,0 +1,356 @@
+//! The stamp of a run: which attempt of which workflow run it was, and the hashes of what went
+//! into its verdict. A re-run job writes its step summary and run report again, and the check
+//! of the branch protection only reflects the latest attempt, so the stamp ties every output
+//! to the measurement that produced it. It is in the run report, at the end of the step
+//! summary, in `GITHUB_OUTPUT`, and in a line at the end of the log.
+//!
+//! The hashes are SHA-256 hashes of the canonical serialization of:
+//!
+//! - the merged config, see [`crate::config_files`];
+//! - the entry of the baseline, as stored in the previous results;
+//! - the results of the run, as printed and stored.
+//!
+//! The canonical serialization is the compact JSON with the keys of every object sorted, so
+//! the hash of an entry doesn't depend on its formatting, like after a tool reformatted the
+//! results. `benchmarker verify-report` checks a run report against the config and the results,
+//! see [`crate::verify_report`].
+
+use std::fmt::Write as _;
+use std::fs::OpenOptions;
+use std::ops::ControlFlow;
+use std::path::Path;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::history;
+use crate::sha256::Sha256;
+
+/// The start of the hidden line with the stamp in the step summary.
+const MARKDOWN_START: &str = "<!-- benchmarker-stamp ";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Stamp {
+    /// `GITHUB_RUN_ID`, the same for every attempt of a workflow run.
+    pub run_id: Option<String>,
+    /// `GITHUB_RUN_ATTEMPT`, which counts the re-runs.
+    pub run_attempt: Option<String>,
+    /// The commit of the run, with `-dirty` for a dirty working tree.
+    pub commit: Option<String>,
+    pub config_sha256: Option<String>,
+    /// `None` without a baseline.
+    pub baseline_sha256: Option<String>,
+    pub results_sha256: Option<String>,
+    /// The tag filters of the run, which change what the config compares.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub only_tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skip_tags: Vec<String>,
+    /// `--strict-sampling`, which changes what the gate ignores.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub strict_sampling: bool,
+    /// The run compared against something else than the stored baseline: a substitute for an
+    /// anomaly, or the baseline with backfilled counters that weren't persisted.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub baseline_changed: bool,
+}
+
+impl Stamp {
+    pub fn from_env(var: impl Fn(&str) -> Option<String>) -> Self {
+        Stamp {
+            run_id: var("GITHUB_RUN_ID").filter(|id| !id.is_empty()),
+            run_attempt: var("GITHUB_RUN_ATTEMPT").filter(|attempt| !attempt.is_empty()),
+            ..Stamp::default()
+        }
+    }
+
+    /// The attempt, like `1234567890/2`, or `local` outside of a workflow run.
+    pub fn attempt(&self) -> String {
+        match (&self.run_id, &self.run_attempt) {
+            (Some(id), Some(attempt)) => format!("{id}/{attempt}"),
+            (Some(id), None) => id.clone(),
+            (None, _) => "local".to_owned(),
+        }
+    }
+
+    /// The line at the end of the log.
+    pub fn one_line(&self) -> String {
+        let hash = |hash: &Option<String>| hash.as_deref().unwrap_or("none").to_owned();
+        format!(
+            "stamp: run {}, config {}, baseline {}, results {}",
+            self.attempt(),
+            hash(&self.config_sha256),
+            hash(&self.baseline_sha256),
+            hash(&self.results_sha256)
+        )
+    }
+
+    /// A small line with the attempt and the start of the hashes, followed by a hidden one with
+    /// the whole stamp, for [`Stamp::from_markdown`].
+    pub fn render_markdown(&self, md: &mut String) {
+        let short = |hash: &Option<String>| match hash {
+            Some(hash) => format!("`{}`", &hash[..hash.len().min(12)]),
+            None => "none".to_owned(),
+        };
+        writeln!(
+            md,
+            "\n<sub>run {} · config {} · baseline {} · results {}</sub>",
+            self.attempt(),
+            short(&self.config_sha256),
+            short(&self.baseline_sha256),
+            short(&self.results_sha256)
+        )
+        .unwrap();
+        // `--` would end the comment early, and can only be in the tags.
+        let json = serde_json::to_string(self)
+            .unwrap()
+            .replace("--", "-\\u002d");
+        writeln!(md, "{MARKDOWN_START}{json} -->").unwrap();
+    }
+
+    /// The stamp [`Stamp::render_markdown`] rendered into `md`, if any.
+    pub fn from_markdown(md: &str) -> Option<Self> {
+        md.lines().find_map(|line| {
+            let json = line.strip_prefix(MARKDOWN_START)?.strip_suffix(" -->")?;
+            serde_json::from_str(json).ok()
+        })
+    }
+
+    /// Append the attempt and the hashes to the `GITHUB_OUTPUT` file of the step.
+    pub fn write_github_output(&self, path: &Path) -> Result<(), String> {
+        use std::io::Write;
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("failed to open {}: {e}", path.display()))?;
+        let hash = |hash: &Option<String>| hash.clone().unwrap_or_default();
+        writeln!(
+            file,
+            "run-attempt={}\nconfig-sha256={}\nbaseline-sha256={}\nresults-sha256={}",
+            self.attempt(),
+            hash(&self.config_sha256),
+            hash(&self.baseline_sha256),
+            hash(&self.results_sha256)
+        )
+        .map_err(|e| format!("failed to write {}: {e}", path.display()))
+    }
+}
+
+/// `value` with the keys of every object sorted.
+pub fn canonicalize(value: &Value) -> Value {
+    match value {
+        Value::Object(object) => {
+            let mut keys = object.keys().collect::<Vec<_>>();
+            keys.sort();
+            Value::Object(
+                keys.into_iter()
+                    .map(|key| (key.clone(), canonicalize(&object[key])))
+                    .collect::<Map<_, _>>(),
+            )
+        }
+        Value::Array(values) => Value::Array(values.iter().map(canonicalize).collect()),
+        _ => value.clone(),
+    }
+}
+
+/// The hash of the canonical serialization of `value`.
+pub fn sha256(value: &Value) -> String {
+    let mut sha256 = Sha256::default();
+    sha256.update(canonicalize(value).to_string().as_bytes());
+    sha256.finish_hex()
+}
+
+/// The hash of the canonical serialization of the JSON `line`.
+pub fn line_sha256(line: &[u8]) -> Result<String, String> {
+    let value = serde_json::from_slice::<Value>(line)
+        .map_err(|e| format!("failed to parse the entry: {e}"))?;
+    Ok(sha256(&value))
+}
+
+/// Just enough of an entry of the results to find it.
+#[derive(Deserialize)]
+pub struct EntryKey {
+    pub commit_hash: String,
+    #[serde(default)]
+    pub dirty: bool,
+    pub timestamp: SystemTime,
+}
+
+impl EntryKey {
+    pub fn commit_id(&self) -> String {
+        if self.dirty {
+            format!("{}-dirty", self.commit_hash)
+        } else {
+            self.commit_hash.clone()
+        }
+    }
+}
+
+/// The hash of the entry of `commit_id` measured at `timestamp` in the results at `path`, as
+/// stored there.
+pub fn stored_entry_sha256(
+    path: &Path,
+    commit_id: &str,
+    timestamp: SystemTime,
+) -> Result<Option<String>, String> {
+    let mut found = None;
+    history::for_each_line(path, |_, line| {
+        match serde_json::from_slice::<EntryKey>(line) {
+            Ok(key) if key.commit_id() == commit_id && key.timestamp == timestamp => {
+                found = Some(line_sha256(line));
+                ControlFlow::Break(())
+            }
+            _ => ControlFlow::Continue(()),
+        }
+    })?;
+    found.transpose()
+}
+
+#[cfg(test)]
+fn stamp_for_test() -> Stamp {
+    Stamp {
+        run_id: Some("1234567890".to_owned()),
+        run_attempt: Some("2".to_owned()),
+        commit: Some("2222222222222222222222222222222222222222".to_owned()),
+        config_sha256: Some("c".repeat(64)),
+        baseline_sha256: None,
+        results_sha256: Some("r".repeat(64)),
+        only_tags: vec!["--fast".to_owned()],
+        skip_tags: vec![],
+        strict_sampling: false,
+        baseline_changed: false,
+    }
+}
+
+#[test]
+fn canonical_hash() {
+    let a = serde_json::json!({ "b": [1, { "y": 2.5, "x": null }], "a": "text" });
+    let b = serde_json::from_str::<Value>(
+        r#"{
+            "a": "text",
+            "b": [1, { "x": null, "y": 2.5 }]
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(sha256(&a), sha256(&b));
+    assert_eq!(
+        canonicalize(&a).to_string(),
+        r#"{"a":"text","b":[1,{"x":null,"y":2.5}]}"#
+    );
+    assert_eq!(
+        sha256(&serde_json::json!({})),
+        "44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a"
+    );
+
+    // Any change of a value, and the order of the values of an array, count.
+    let changed = serde_json::json!({ "b": [1, { "y": 2.6, "x": null }], "a": "text" });
+    assert_ne!(sha256(&a), sha256(&changed));
+    let reordered = serde_json::json!({ "b": [{ "y": 2.5, "x": null }, 1], "a": "text" });
+    assert_ne!(sha256(&a), sha256(&reordered));
+    assert_eq!(
+        line_sha256(a.to_string().as_bytes()).unwrap(),
+        line_sha256(b.to_string().as_bytes()).unwrap()
+    );
+    assert!(line_sha256(b"{").is_err());
+}
+
+#[test]
+fn find_stored_entry() {
+    use crate::testkit::BenchDataBuilder;
+
+    let dir = crate::testkit::test_dir("stamp-stored-entry");
+    let path = dir.join("results.json");
+    let entry = |commit: &str, secs: u64| {
+        let mut data = BenchDataBuilder::new(commit)
+            .group("work", |g| {
+                g.bench(["./work"], |b| b.counter("cycles", 1e6, 1e2, 2, ""))
+            })
+            .build();
+        data.timestamp = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(secs);
+        data
+    };
+    let first = entry(&"1".repeat(40), 10);
+    let retried = entry(&"1".repeat(40), 20);
+    let lines = [
+        serde_json::to_string(&first).unwrap(),
+        "not results".to_owned(),
+        // Formatted differently.
+        serde_json::to_string_pretty(&serde_json::to_value(&retried).unwrap())
+            .unwrap()
+            .replace('\n', " "),
+    ];
+    std::fs::write(&path, lines.join("\n") + "\n").unwrap();
+
+    let hash = stored_entry_sha256(&path, &retried.commit_id(), retried.timestamp).unwrap();
+    assert_eq!(hash, Some(sha256(&serde_json::to_value(&retried).unwrap())));
+    assert_ne!(
+        stored_entry_sha256(&path, &first.commit_id(), first.timestamp).unwrap(),
+        hash
+    );
+    assert_eq!(
+        stored_entry_sha256(&path, &"2".repeat(40), first.timestamp).unwrap(),
+        None
+    );
+    assert!(stored_entry_sha256(&dir.join("missing.json"), "x", first.timestamp).is_err());
+}
+
+#[test]
+fn render_stamp() {
+    let stamp = stamp_for_test();
+    assert_eq!(stamp.attempt(), "1234567890/2");
+    assert_eq!(Stamp::default().attempt(), "local");
+    assert_eq!(
+        Stamp::from_env(|name| match name {
+            "GITHUB_RUN_ID" => Some("1234567890".to_owned()),
+            "GITHUB_RUN_ATTEMPT" => Some("2".to_owned()),
+            _ => None,
+        }),
+        Stamp {
+            run_id: Some("1234567890".to_owned()),
+            run_attempt: Some("2".to_owned()),
+            ..Stamp::default()
+        }
+    );
+
+    let mut md = String::new();
+    stamp.render_markdown(&mut md);
+    let (visible, hidden) = md.trim_start().split_once('\n').unwrap();
+    assert_eq!(
+        visible,
+        "<sub>run 1234567890/2 · config `cccccccccccc` · baseline none · results `rrrrrrrrrrrr`</sub>"
+    );
+    let json = hidden
+        .strip_prefix(MARKDOWN_START)
+        .unwrap()
+        .strip_suffix(" -->\n")
+        .unwrap();
+    assert!(json.contains(r#""only_tags":["-\u002dfast"]"#), "{json}");
+    assert_eq!(
+        Stamp::from_markdown(&format!("# Results\n{md}more\n")),
+        Some(stamp.clone())
+    );
+    assert_eq!(Stamp::from_markdown("# Results\n"), None);
+
+    assert_eq!(
+        stamp.one_line(),
+        format!(
+            "stamp: run 1234567890/2, config {}, baseline none, results {}",
+            "c".repeat(64),
+            "r".repeat(64)
+        )
+    );
+
+    let path = crate::testkit::test_dir("stamp-output").join("output");
+    stamp.write_github_output(&path).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        format!(
+            "run-attempt=1234567890/2\nconfig-sha256={}\nbaseline-sha256=\nresults-sha256={}\n",
+            "c".repeat(64),
+            "r".repeat(64)
+        )
+    );
+}
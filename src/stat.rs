@@ -0,0 +1,354 @@
+//! `benchmarker stat`: whether the difference between two measurements is significant, by the
+//! same t-test as the reports. Either from the summaries:
+//!
+//! ```text
+//! benchmarker stat --old 1823 --old-variance 140 --old-n 20 --new 1791 --new-variance 160 --new-n 20
+//! ```
+//!
+//! or with `--samples` from the samples themselves, the old ones on the first line of stdin
+//! and the new ones on the second. Add `--json` for a machine-readable verdict.
+//!
+//! Needs no config, git or environment variables.
+
+use std::fmt::Write;
+use std::io::Read;
+
+use serde::Serialize;
+
+use crate::bench::{mean_and_variance, BenchCounter};
+
+#[derive(Debug, Serialize)]
+pub struct StatReport {
+    pub old: Summary,
+    pub new: Summary,
+    /// The change relative to the new value, as in the reports.
+    pub delta_percent: f64,
+    /// `new - old`.
+    pub difference: f64,
+    /// The 95% confidence interval of `difference`.
+    pub confidence_interval: [f64; 2],
+    pub p_value: f64,
+    /// Whether the difference is significant at a 95% confidence level.
+    pub significant: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    pub mean: f64,
+    pub variance: f64,
+    pub n: u32,
+}
+
+impl Summary {
+    fn counter(&self) -> BenchCounter {
+        BenchCounter {
+            value: self.mean,
+            variance: self.variance,
+            repetitions: self.n,
+            unit: String::new(),
+        }
+    }
+}
+
+impl StatReport {
+    pub fn new(old: Summary, new: Summary) -> Self {
+        let (before, after) = (old.counter(), new.counter());
+        let (low, high) = BenchCounter::difference_confidence_interval(&before, &after);
+        StatReport {
+            delta_percent: BenchCounter::improvement_percentage(&before, &after),
+            difference: after.value - before.value,
+            confidence_interval: [low, high],
+            p_value: BenchCounter::p_value(&before, &after),
+            significant: BenchCounter::is_significant(&before, &after),
+            old,
+            new,
+        }
+    }
+
+    pub fn render_text(&self) -> String {
+        let mut text = String::new();
+        for (name, summary) in [("old", &self.old), ("new", &self.new)] {
+            writeln!(
+                text,
+                "{name}: {} ± {} (n = {})",
+                number(summary.mean),
+                number(summary.variance.sqrt()),
+                summary.n
+            )
+            .unwrap();
+        }
+        writeln!(
+            text,
+            "difference: {} (95% CI {} to {}), {:+.2}%",
+            number(self.difference),
+            number(self.confidence_interval[0]),
+            number(self.confidence_interval[1]),
+            self.delta_percent,
+        )
+        .unwrap();
+
+        let verdict = match (self.significant, self.difference > 0.0) {
+            (false, _) => "not significant",
+            (true, true) => "significant: new is higher",
+            (true, false) => "significant: new is lower",
+        };
+        writeln!(text, "{verdict} (p = {:.4})", self.p_value).unwrap();
+        text
+    }
+}
+
+/// A number with at most three decimals, and without trailing zeros.
+fn number(value: f64) -> String {
+    let formatted = format!("{value:.3}");
+    let trimmed = formatted.trim_end_matches('0').trim_end_matches('.');
+    match trimmed {
+        "-0" => "0".to_owned(),
+        trimmed => trimmed.to_owned(),
+    }
+}
+
+pub fn run(args: impl IntoIterator<Item = String>, stdin: impl Read) -> Result<String, String> {
+    let mut old = [None; 3];
+    let mut new = [None; 3];
+    let mut samples = false;
+    let mut json = false;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let Some(flag) = arg.strip_prefix("--") else {
+            return Err(format!("unexpected argument `{arg}`"));
+        };
+        let (flag, inline_value) = match flag.split_once('=') {
+            Some((flag, value)) => (flag, Some(value.to_owned())),
+            None => (flag, None),
+        };
+        let mut value = || {
+            let value = inline_value
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("`--{flag}` requires a value"))?;
+            parse_number(&value).map_err(|err| format!("`--{flag}`: {err}"))
+        };
+
+        match flag {
+            "samples" if inline_value.is_none() => samples = true,
+            "json" if inline_value.is_none() => json = true,
+            "old" => old[0] = Some(value()?),
+            "old-variance" => old[1] = Some(value()?),
+            "old-n" => old[2] = Some(value()?),
+            "new" => new[0] = Some(value()?),
+            "new-variance" => new[1] = Some(value()?),
+            "new-n" => new[2] = Some(value()?),
+            _ => return Err(format!("unknown flag `--{flag}`")),
+        }
+    }
+
+    let (old, new) = if samples {
+        if old.iter().chain(&new).any(Option::is_some) {
+            return Err(
+                "`--samples` reads both sides from stdin, without `--old` or `--new`".to_owned(),
+            );
+        }
+        let input = read_input(stdin)?;
+        let mut lists = input.lines().filter(|line| !line.trim().is_empty());
+        let (Some(old), Some(new), None) = (lists.next(), lists.next(), lists.next()) else {
+            return Err(
+                "expected two lines of samples on stdin, the old ones and the new ones".to_owned(),
+            );
+        };
+        (
+            summarize(
+                &parse_samples(old).map_err(|err| format!("old samples: {err}"))?,
+                "old",
+            )?,
+            summarize(
+                &parse_samples(new).map_err(|err| format!("new samples: {err}"))?,
+                "new",
+            )?,
+        )
+    } else {
+        (summary(old, "old")?, summary(new, "new")?)
+    };
+
+    let report = StatReport::new(old, new);
+    if json {
+        Ok(serde_json::to_string_pretty(&report).unwrap() + "\n")
+    } else {
+        Ok(report.render_text())
+    }
+}
+
+fn read_input(mut stdin: impl Read) -> Result<String, String> {
+    let mut input = String::new();
+    stdin
+        .read_to_string(&mut input)
+        .map_err(|e| format!("failed to read stdin: {e}"))?;
+    Ok(input)
+}
+
+fn parse_number(value: &str) -> Result<f64, String> {
+    match value.trim().parse::<f64>() {
+        Ok(number) if number.is_finite() => Ok(number),
+        _ => Err(format!("`{value}` is not a number")),
+    }
+}
+
+/// Samples separated by commas, whitespace or both.
+pub fn parse_samples(list: &str) -> Result<Vec<f64>, String> {
+    list.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|sample| !sample.is_empty())
+        .map(parse_number)
+        .collect()
+}
+
+fn summarize(samples: &[f64], side: &str) -> Result<Summary, String> {
+    if samples.len() < 2 {
+        return Err(format!("need at least 2 {side} samples"));
+    }
+    let (mean, variance) = mean_and_variance(samples);
+    Ok(Summary {
+        mean,
+        variance,
+        n: samples.len() as u32,
+    })
+}
+
+fn summary([mean, variance, n]: [Option<f64>; 3], side: &str) -> Result<Summary, String> {
+    let (Some(mean), Some(variance), Some(n)) = (mean, variance, n) else {
+        return Err(format!(
+            "expected `--{side}`, `--{side}-variance` and `--{side}-n`, or `--samples`"
+        ));
+    };
+    if variance < 0.0 {
+        return Err(format!("`--{side}-variance` can't be negative"));
+    }
+    if n.fract() != 0.0 || n < 2.0 || n > u32::MAX as f64 {
+        return Err(format!("`--{side}-n` must be a whole number of at least 2"));
+    }
+    Ok(Summary {
+        mean,
+        variance,
+        n: n as u32,
+    })
+}
+
+#[cfg(test)]
+fn stat(args: &str, stdin: &str) -> Result<String, String> {
+    run(args.split_whitespace().map(str::to_owned), stdin.as_bytes())
+}
+
+#[test]
+fn sample_lists() {
+    assert_eq!(
+        parse_samples("1.5, 2 3,4\t5e2  ,-1E-3").unwrap(),
+        [1.5, 2.0, 3.0, 4.0, 500.0, -0.001]
+    );
+    assert!(parse_samples(" , ").unwrap().is_empty());
+    assert_eq!(
+        parse_samples("1, two").unwrap_err(),
+        "`two` is not a number"
+    );
+    assert!(parse_samples("1 inf").is_err());
+    assert!(parse_samples("NaN").is_err());
+}
+
+#[test]
+fn verdict_from_summaries() {
+    let output = stat(
+        "--old 1823 --old-variance 140 --old-n 20 --new 1791 --new-variance 160 --new-n=20",
+        "",
+    )
+    .unwrap();
+    assert_eq!(
+        output,
+        "old: 1823 ± 11.832 (n = 20)\n\
+         new: 1791 ± 12.649 (n = 20)\n\
+         difference: -32 (95% CI -39.84 to -24.16), -1.79%\n\
+         significant: new is lower (p = 0.0000)\n"
+    );
+
+    let output = stat(
+        "--old 1.823e3 --old-variance 14000 --old-n 20 --new 1791 --new-variance 16000 --new-n 20",
+        "",
+    )
+    .unwrap();
+    assert!(
+        output.ends_with("not significant (p = 0.4138)\n"),
+        "{output}"
+    );
+
+    let json: serde_json::Value = serde_json::from_str(
+        &stat(
+            "--json --old 10 --old-variance 1 --old-n 5 --new 12 --new-variance 1 --new-n 5",
+            "",
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        json["old"],
+        serde_json::json!({ "mean": 10.0, "variance": 1.0, "n": 5 })
+    );
+    assert_eq!(json["difference"], 2.0);
+    assert_eq!(json["significant"], true);
+}
+
+#[test]
+fn verdict_from_samples() {
+    let output = stat("--samples", "1.0, 2.0, 3.0\n\n2 3 4 5\n").unwrap();
+    assert_eq!(
+        output,
+        "old: 2 ± 1 (n = 3)\n\
+         new: 3.5 ± 1.291 (n = 4)\n\
+         difference: 1.5 (95% CI -0.823 to 3.823), +42.86%\n\
+         not significant (p = 0.1578)\n"
+    );
+
+    assert_eq!(
+        stat("--samples", "1 2 3\n").unwrap_err(),
+        "expected two lines of samples on stdin, the old ones and the new ones"
+    );
+    assert_eq!(
+        stat("--samples", "1\n2 3\n").unwrap_err(),
+        "need at least 2 old samples"
+    );
+    assert_eq!(
+        stat("--samples", "1 2\n2 x\n").unwrap_err(),
+        "new samples: `x` is not a number"
+    );
+    assert!(stat("--samples --old 1", "1 2\n3 4\n").is_err());
+}
+
+#[test]
+fn stat_args() {
+    assert_eq!(
+        stat("--old 1 --old-variance 1 --old-n 20", "").unwrap_err(),
+        "expected `--new`, `--new-variance` and `--new-n`, or `--samples`"
+    );
+    assert_eq!(
+        stat(
+            "--old 1 --old-variance 1 --old-n 1.5 --new 1 --new-variance 1 --new-n 2",
+            ""
+        )
+        .unwrap_err(),
+        "`--old-n` must be a whole number of at least 2"
+    );
+    assert_eq!(
+        stat(
+            "--old 1 --old-variance -1 --old-n 2 --new 1 --new-variance 1 --new-n 2",
+            ""
+        )
+        .unwrap_err(),
+        "`--old-variance` can't be negative"
+    );
+    assert_eq!(stat("--old", "").unwrap_err(), "`--old` requires a value");
+    assert_eq!(
+        stat("--old x", "").unwrap_err(),
+        "`--old`: `x` is not a number"
+    );
+    assert_eq!(
+        stat("--verbose", "").unwrap_err(),
+        "unknown flag `--verbose`"
+    );
+    assert_eq!(stat("1823", "").unwrap_err(), "unexpected argument `1823`");
+}
@@ -0,0 +1,263 @@
+//! The `sync-start` protocol, for commands whose interesting part is too short to drown out
+//! starting the process and perf setting up its counters. perf then starts with its counters
+//! disabled, and the benchmarked program tells when to count.
+//!
+//! The program finds one end of a Unix socket in the file descriptor given by the
+//! `BENCHMARKER_SYNC_FD` environment variable. In every run, it
+//!
+//! 1. writes `r` when it is ready to start the measured part, and waits for a byte back,
+//! 2. does the measured part,
+//! 3. writes `d` and again waits for a byte back before it exits.
+//!
+//! When the variable isn't set, e.g. with a backend other than perf, or the socket is closed,
+//! the program just runs. `examples/sync_start.rs` shows how.
+//!
+//! In between, the counters are enabled and disabled through the `--control` interface of
+//! perf. A program that doesn't send `r` within the timeout, or breaks off the protocol, is
+//! measured as usual instead.
+
+use std::io::{self, BufRead, BufReader, PipeReader, PipeWriter, Read, Write};
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Output, Stdio};
+use std::time::Duration;
+
+/// The variable with the file descriptor of the socket.
+pub const SYNC_FD_ENV: &str = "BENCHMARKER_SYNC_FD";
+
+/// The socket to the benchmarked program, and the pipes of the `--control` interface of perf.
+pub struct SyncStart {
+    socket: UnixStream,
+    program_socket: UnixStream,
+    control: PipeWriter,
+    perf_control: PipeReader,
+    ack: BufReader<PipeReader>,
+    perf_ack: PipeWriter,
+}
+
+/// How a synchronized run of perf went.
+pub enum Outcome {
+    /// Every repetition was synchronized.
+    Finished(Output),
+    /// Why the program didn't take part. The counters of the run are useless.
+    Unsynchronized(String),
+}
+
+impl SyncStart {
+    pub fn new() -> io::Result<Self> {
+        let (socket, program_socket) = UnixStream::pair()?;
+        let (perf_control, control) = io::pipe()?;
+        let (ack, perf_ack) = io::pipe()?;
+        Ok(SyncStart {
+            socket,
+            program_socket,
+            control,
+            perf_control,
+            ack: BufReader::new(ack),
+            perf_ack,
+        })
+    }
+
+    /// The options of `perf stat` to start with its counters disabled, and to take commands.
+    pub fn perf_args(&self) -> Vec<String> {
+        vec![
+            "--delay".to_owned(),
+            "-1".to_owned(),
+            "--control".to_owned(),
+            format!(
+                "fd:{},{}",
+                self.perf_control.as_raw_fd(),
+                self.perf_ack.as_raw_fd()
+            ),
+        ]
+    }
+
+    /// Run `perf_stat`, the command from [`Self::perf_args`], enabling its counters for the
+    /// part of every repetition between the signals of the program.
+    pub fn run(
+        self,
+        perf_stat: &mut Command,
+        repetitions: u32,
+        timeout: Duration,
+    ) -> Result<Outcome, String> {
+        let inherited: [RawFd; 3] = [
+            self.program_socket.as_raw_fd(),
+            self.perf_control.as_raw_fd(),
+            self.perf_ack.as_raw_fd(),
+        ];
+        perf_stat.env(SYNC_FD_ENV, inherited[0].to_string());
+        // SAFETY: `fcntl` is async-signal-safe, and only touches the descriptors.
+        unsafe {
+            perf_stat.pre_exec(move || {
+                // The descriptors are created close-on-exec, perf and the program need them.
+                for fd in inherited {
+                    if libc::fcntl(fd, libc::F_SETFD, 0) == -1 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
+        }
+
+        let mut perf = perf_stat
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("failed to run {}: {e}", perf_stat.get_program().display()))?;
+        let SyncStart {
+            socket,
+            program_socket,
+            mut control,
+            perf_control,
+            mut ack,
+            perf_ack,
+        } = self;
+        // Only perf and the program keep their ends, so the reads see when they are gone.
+        drop((program_socket, perf_control, perf_ack));
+
+        let mut stdout_pipe = perf.stdout.take().unwrap();
+        let mut stderr_pipe = perf.stderr.take().unwrap();
+        let stdout = std::thread::spawn(move || {
+            let mut stdout = vec![];
+            stdout_pipe.read_to_end(&mut stdout).map(|_| stdout)
+        });
+        let stderr = std::thread::spawn(move || {
+            let mut stderr = vec![];
+            stderr_pipe.read_to_end(&mut stderr).map(|_| stderr)
+        });
+
+        let synchronized = synchronize(&socket, &mut control, &mut ack, repetitions, timeout);
+        // Without the socket and the control pipe, the program and perf carry on by themselves.
+        drop((socket, control));
+
+        let status = perf
+            .wait()
+            .map_err(|e| format!("failed to wait for perf: {e}"))?;
+        let read = |output: std::thread::JoinHandle<io::Result<Vec<u8>>>| {
+            output
+                .join()
+                .unwrap()
+                .map_err(|e| format!("failed to read the output of perf: {e}"))
+        };
+        let output = Output {
+            status,
+            stdout: read(stdout)?,
+            stderr: read(stderr)?,
+        };
+
+        Ok(match synchronized {
+            Ok(()) => Outcome::Finished(output),
+            Err(reason) => Outcome::Unsynchronized(reason),
+        })
+    }
+}
+
+/// Enable the counters of perf between the signals of every repetition.
+fn synchronize(
+    mut socket: &UnixStream,
+    control: &mut PipeWriter,
+    ack: &mut BufReader<PipeReader>,
+    repetitions: u32,
+    timeout: Duration,
+) -> Result<(), String> {
+    for repetition in 1..=repetitions {
+        socket.set_read_timeout(Some(timeout)).unwrap();
+        expect(socket, b'r')
+            .map_err(|err| format!("no start signal in repetition {repetition}: {err}"))?;
+        perf_command(control, ack, "enable")?;
+        socket
+            .write_all(b"g")
+            .map_err(|e| format!("failed to start repetition {repetition}: {e}"))?;
+
+        // The measured part takes as long as it takes.
+        socket.set_read_timeout(None).unwrap();
+        expect(socket, b'd')
+            .map_err(|err| format!("no end signal in repetition {repetition}: {err}"))?;
+        perf_command(control, ack, "disable")?;
+        socket
+            .write_all(b"g")
+            .map_err(|e| format!("failed to end repetition {repetition}: {e}"))?;
+    }
+    Ok(())
+}
+
+fn expect(mut socket: &UnixStream, signal: u8) -> Result<(), String> {
+    let mut byte = [0];
+    match socket.read(&mut byte) {
+        Ok(0) => Err("the program exited".to_owned()),
+        Ok(_) if byte[0] == signal => Ok(()),
+        Ok(_) => Err(format!("unexpected signal {:?}", char::from(byte[0]))),
+        Err(e)
+            if matches!(
+                e.kind(),
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+            ) =>
+        {
+            Err("timed out".to_owned())
+        }
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Send a command to perf and wait for it to acknowledge it. perf terminates its `ack` with a
+/// NUL byte.
+fn perf_command(
+    control: &mut PipeWriter,
+    ack: &mut BufReader<PipeReader>,
+    command: &str,
+) -> Result<(), String> {
+    writeln!(control, "{command}").map_err(|e| format!("failed to {command} perf: {e}"))?;
+    let mut line = vec![];
+    ack.read_until(b'\n', &mut line)
+        .map_err(|e| format!("failed to {command} perf: {e}"))?;
+    match String::from_utf8_lossy(&line).trim_matches(['\0', '\n']) {
+        "ack" => Ok(()),
+        "" => Err(format!(
+            "perf exited before it could {command} its counters"
+        )),
+        other => Err(format!("unexpected answer of perf to {command}: {other:?}")),
+    }
+}
+
+#[test]
+fn perf_commands() {
+    let (perf_control, mut control) = io::pipe().unwrap();
+    let (ack, mut perf_ack) = io::pipe().unwrap();
+    let mut ack = BufReader::new(ack);
+
+    // Like perf, NUL-terminated.
+    let perf = std::thread::spawn(move || {
+        let mut commands = BufReader::new(perf_control).lines();
+        for _ in 0..2 {
+            let command = commands.next().unwrap().unwrap();
+            perf_ack.write_all(b"ack\n\0").unwrap();
+            assert!(["enable", "disable"].contains(&command.as_str()));
+        }
+    });
+    perf_command(&mut control, &mut ack, "enable").unwrap();
+    perf_command(&mut control, &mut ack, "disable").unwrap();
+    perf.join().unwrap();
+
+    assert_eq!(
+        perf_command(&mut control, &mut ack, "enable").unwrap_err(),
+        "failed to enable perf: Broken pipe (os error 32)"
+    );
+}
+
+#[test]
+fn program_signals() {
+    let (socket, mut program) = UnixStream::pair().unwrap();
+    socket
+        .set_read_timeout(Some(Duration::from_millis(50)))
+        .unwrap();
+
+    assert_eq!(expect(&socket, b'r').unwrap_err(), "timed out");
+    program.write_all(b"d").unwrap();
+    assert_eq!(expect(&socket, b'r').unwrap_err(), "unexpected signal 'd'");
+    program.write_all(b"r").unwrap();
+    expect(&socket, b'r').unwrap();
+    drop(program);
+    assert_eq!(expect(&socket, b'r').unwrap_err(), "the program exited");
+}
@@ -0,0 +1,293 @@
+//! Builders for the results the renderers and comparisons take, so tests don't have to spell
+//! out every field of every benchmark. The integration tests, and other crates, get them with
+//! the `testkit` feature.
+//!
+//! ```ignore
+//! let after = BenchDataBuilder::new("2222222222222222222222222222222222222222")
+//!     .machine("X64", "Linux", "AMD EPYC 7763")
+//!     .group("compress", |g| {
+//!         g.bench(["./c", "6"], |b| b.counter("cycles", 1.0e9, 2.5e4, 20, ""))
+//!     });
+//! let before = after
+//!     .with_scaled_counters(0.95)
+//!     .commit_hash("1111111111111111111111111111111111111111");
+//! ```
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use indexmap::IndexMap;
+
+use crate::bench::{BenchCounter, SingleBench};
+use crate::BenchData;
+
+/// Results for one commit, on a machine called `cpu` unless [`Self::machine`] says otherwise.
+#[derive(Debug, Clone)]
+pub struct BenchDataBuilder {
+    data: BenchData,
+}
+
+impl BenchDataBuilder {
+    pub fn new(commit_hash: &str) -> Self {
+        BenchDataBuilder {
+            data: BenchData {
+                schema_version: crate::migrate::SCHEMA_VERSION,
+                commit_hash: commit_hash.to_owned(),
+                commit_timestamp: 0,
+                timestamp: SystemTime::UNIX_EPOCH,
+                arch: "X64".to_owned(),
+                os: "Linux".to_owned(),
+                runner: "runner".to_owned(),
+                cpu_model: "cpu".to_owned(),
+                cpu_frequency: None,
+                machine_class: None,
+                machine_id: None,
+                isolation: None,
+                preflight: None,
+                thermal: None,
+                environment: None,
+                version: None,
+                fixtures: IndexMap::new(),
+                binary_hashes: IndexMap::new(),
+                build_info: IndexMap::new(),
+                trigger: None,
+                exemptions: vec![],
+                dirty: false,
+                diff_sha256: None,
+                skipped_groups: IndexMap::new(),
+                partial: false,
+                ancestor_distance: None,
+                staleness: None,
+                harness_overhead: vec![],
+                seed: None,
+                samples: IndexMap::new(),
+                bench_groups: IndexMap::new(),
+            },
+        }
+    }
+
+    pub fn commit_hash(mut self, commit_hash: &str) -> Self {
+        self.data.commit_hash = commit_hash.to_owned();
+        self
+    }
+
+    pub fn commit_timestamp(mut self, commit_timestamp: u64) -> Self {
+        self.data.commit_timestamp = commit_timestamp;
+        self
+    }
+
+    pub fn machine(mut self, arch: &str, os: &str, cpu_model: &str) -> Self {
+        self.data.arch = arch.to_owned();
+        self.data.os = os.to_owned();
+        self.data.cpu_model = cpu_model.to_owned();
+        self
+    }
+
+    pub fn machine_class(mut self, machine_class: &str) -> Self {
+        self.data.machine_class = Some(machine_class.to_owned());
+        self
+    }
+
+    pub fn machine_id(mut self, machine_id: &str) -> Self {
+        self.data.machine_id = Some(machine_id.to_owned());
+        self
+    }
+
+    pub fn version(mut self, version: &str) -> Self {
+        self.data.version = Some(version.to_owned());
+        self
+    }
+
+    pub fn binary_hash(mut self, path: &str, sha256: &str) -> Self {
+        self.data
+            .binary_hashes
+            .insert(path.to_owned(), sha256.to_owned());
+        self
+    }
+
+    /// Results of a working tree with uncommitted changes.
+    pub fn dirty(mut self, diff_sha256: &str) -> Self {
+        self.data.dirty = true;
+        self.data.diff_sha256 = Some(diff_sha256.to_owned());
+        self
+    }
+
+    /// Results with only `cycles`, one group per `(name, [(command, cycles)])`, where the
+    /// command is split at spaces.
+    pub fn cycles(commit_hash: &str, groups: &[(&str, &[(&str, f64)])]) -> Self {
+        groups
+            .iter()
+            .fold(Self::new(commit_hash), |data, &(group_name, benches)| {
+                data.group(group_name, |group| {
+                    benches.iter().fold(group, |group, &(cmd, cycles)| {
+                        group.bench(cmd.split(' '), |b| {
+                            b.counter("cycles", cycles, 100.0, 20, "")
+                        })
+                    })
+                })
+            })
+    }
+
+    /// Add the benchmarks `build` adds to the group, creating it if needed.
+    pub fn group(mut self, name: &str, build: impl FnOnce(GroupBuilder) -> GroupBuilder) -> Self {
+        let benches = build(GroupBuilder { benches: vec![] }).benches;
+        self.data
+            .bench_groups
+            .entry(name.to_owned())
+            .or_default()
+            .extend(benches);
+        self
+    }
+
+    /// The same results with every counter scaled by `factor`, e.g. as the baseline of a
+    /// change that made everything 5% faster. The standard deviation scales along.
+    pub fn with_scaled_counters(&self, factor: f64) -> Self {
+        let mut scaled = self.clone();
+        for bench in scaled.data.bench_groups.values_mut().flatten() {
+            for counter in bench.counters.values_mut() {
+                counter.value *= factor;
+                counter.variance *= factor * factor;
+            }
+        }
+        scaled
+    }
+
+    pub fn build(self) -> BenchData {
+        self.data
+    }
+}
+
+/// A fresh, empty directory for a test to write files to.
+pub fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("benchmarker-test-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+pub struct GroupBuilder {
+    benches: Vec<SingleBench>,
+}
+
+impl GroupBuilder {
+    pub fn bench<S: Into<String>>(
+        mut self,
+        cmd: impl IntoIterator<Item = S>,
+        build: impl FnOnce(BenchBuilder) -> BenchBuilder,
+    ) -> Self {
+        let bench = SingleBench {
+            cmd: cmd.into_iter().map(Into::into).collect(),
+            id: None,
+            tags: vec![],
+            counters: BTreeMap::new(),
+            profile: None,
+            intervals: None,
+            exit_code: None,
+            output_bytes: None,
+            error: None,
+            nondeterministic_output: false,
+            preempted: None,
+            backfilled: vec![],
+            hung: false,
+            imported: None,
+        };
+        self.benches.push(build(BenchBuilder { bench }).bench);
+        self
+    }
+}
+
+pub struct BenchBuilder {
+    bench: SingleBench,
+}
+
+impl BenchBuilder {
+    pub fn counter(
+        mut self,
+        name: &str,
+        value: f64,
+        variance: f64,
+        repetitions: u32,
+        unit: &str,
+    ) -> Self {
+        self.bench.counters.insert(
+            name.to_owned(),
+            BenchCounter {
+                value,
+                variance,
+                repetitions,
+                unit: unit.to_owned(),
+            },
+        );
+        self
+    }
+
+    pub fn id(mut self, id: &str) -> Self {
+        self.bench.id = Some(id.to_owned());
+        self
+    }
+
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.bench.tags.push(tag.to_owned());
+        self
+    }
+
+    pub fn exit_code(mut self, exit_code: i32) -> Self {
+        self.bench.exit_code = Some(exit_code);
+        self
+    }
+}
+
+#[test]
+fn build_bench_data() {
+    let after = BenchDataBuilder::new("2222222222222222222222222222222222222222")
+        .machine("Arm64", "macOS", "Apple M1")
+        .group("compress", |g| {
+            g.bench(["./c", "6"], |b| {
+                b.counter("cycles", 1.0e9, 2.5e4, 20, "")
+                    .counter("task-clock", 250.0, 4.0, 20, "msec")
+                    .id("c6")
+                    .tag("slow")
+            })
+        })
+        .group("decompress", |g| g.bench(["./d"], |b| b.exit_code(1)))
+        .group("compress", |g| g.bench(["./c", "1"], |b| b));
+    let before = after
+        .with_scaled_counters(0.5)
+        .commit_hash("1111111111111111111111111111111111111111")
+        .build();
+    let after = after.build();
+
+    assert_eq!(
+        after.commit_hash,
+        "2222222222222222222222222222222222222222"
+    );
+    assert_eq!((&*after.arch, &*after.os), ("Arm64", "macOS"));
+    assert_eq!(after.cpu_model, "Apple M1");
+    assert_eq!(
+        after.bench_groups.keys().collect::<Vec<_>>(),
+        ["compress", "decompress"]
+    );
+
+    let compress = &after.bench_groups["compress"];
+    assert_eq!(compress.len(), 2);
+    assert_eq!(compress[0].cmd, ["./c", "6"]);
+    assert_eq!(compress[0].id.as_deref(), Some("c6"));
+    assert_eq!(compress[0].tags, ["slow"]);
+    assert_eq!(compress[0].counters["task-clock"].unit, "msec");
+    assert!(compress[1].counters.is_empty());
+    assert_eq!(after.bench_groups["decompress"][0].exit_code, Some(1));
+
+    assert_eq!(
+        before.commit_hash,
+        "1111111111111111111111111111111111111111"
+    );
+    let cycles = &before.bench_groups["compress"][0].counters["cycles"];
+    assert_eq!(cycles.value, 5.0e8);
+    assert_eq!(cycles.variance, 6.25e3);
+    assert_eq!(cycles.repetitions, 20);
+    assert_eq!(
+        after.bench_groups["compress"][0].counters["cycles"].value,
+        1.0e9
+    );
+}
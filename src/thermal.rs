@@ -0,0 +1,410 @@
+//! Monitoring the temperature and the clock frequency of the CPU while the benchmarks run
+//! (Linux only). A machine without a fan that heats up over the day throttles its CPU, which
+//! slows down whatever runs at the time.
+
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::units;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ThermalConfig {
+    /// How often to sample the temperature and the frequency.
+    #[serde(
+        rename = "interval-ms",
+        default = "default_interval",
+        deserialize_with = "units::millis"
+    )]
+    pub interval: Duration,
+    /// A sample counts as throttled when the fastest CPU runs below this percentage of its
+    /// maximum frequency.
+    #[serde(default = "default_throttle_percent")]
+    pub throttle_percent: f64,
+}
+
+fn default_interval() -> Duration {
+    Duration::from_secs(1)
+}
+
+fn default_throttle_percent() -> f64 {
+    90.0
+}
+
+/// A single sample, taken `at` after the sampler started.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    pub at: Duration,
+    /// The temperature of the hottest thermal zone.
+    pub celsius: Option<f64>,
+    /// The current frequency of the fastest CPU, in percent of its maximum frequency.
+    pub frequency_percent: Option<f64>,
+}
+
+/// When a group ran, relative to the start of the sampler.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupWindow {
+    pub group: String,
+    pub start: Duration,
+    pub end: Duration,
+}
+
+/// What the samples of a run amount to, recorded with the results.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Thermal {
+    pub samples: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_celsius: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_celsius: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mean_celsius: Option<f64>,
+    pub throttle_percent: f64,
+    /// The share of the samples with a frequency that were throttled, in percent. `None` when
+    /// the frequency is unknown.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub throttled_percent: Option<f64>,
+    /// The groups that were running while the CPU was throttled, in the order they ran.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub throttled_groups: Vec<String>,
+}
+
+impl Thermal {
+    /// Summarize the `samples`, taken every `interval`, of a run in which the groups ran in
+    /// the `windows`. `None` without samples.
+    pub fn summarize(
+        samples: &[Sample],
+        windows: &[GroupWindow],
+        interval: Duration,
+        throttle_percent: f64,
+    ) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+
+        let temperatures = samples
+            .iter()
+            .filter_map(|sample| sample.celsius)
+            .collect::<Vec<_>>();
+        let frequencies = samples
+            .iter()
+            .filter_map(|sample| sample.frequency_percent)
+            .collect::<Vec<_>>();
+        let throttled = samples
+            .iter()
+            .filter(|sample| {
+                sample
+                    .frequency_percent
+                    .is_some_and(|percent| percent < throttle_percent)
+            })
+            .collect::<Vec<_>>();
+
+        // A sample covers the interval before it, so a group ran while the CPU was throttled
+        // when its window overlaps that of a throttled sample.
+        let throttled_groups = windows
+            .iter()
+            .filter(|window| {
+                throttled.iter().any(|sample| {
+                    window.start <= sample.at && window.end >= sample.at.saturating_sub(interval)
+                })
+            })
+            .map(|window| window.group.clone())
+            .fold(vec![], |mut groups, group| {
+                if !groups.contains(&group) {
+                    groups.push(group);
+                }
+                groups
+            });
+
+        Some(Thermal {
+            samples: samples.len(),
+            min_celsius: temperatures.iter().copied().reduce(f64::min),
+            max_celsius: temperatures.iter().copied().reduce(f64::max),
+            mean_celsius: (!temperatures.is_empty())
+                .then(|| temperatures.iter().sum::<f64>() / temperatures.len() as f64),
+            throttle_percent,
+            throttled_percent: (!frequencies.is_empty())
+                .then(|| throttled.len() as f64 / frequencies.len() as f64 * 100.0),
+            throttled_groups,
+        })
+    }
+
+    pub fn is_throttled(&self) -> bool {
+        self.throttled_percent.is_some_and(|percent| percent > 0.0)
+    }
+}
+
+/// Samples sysfs in a background thread until it is stopped. The thread sleeps between the
+/// samples and only reads a few small files, so it hardly competes with the benchmarks.
+pub struct Sampler {
+    start: Instant,
+    stop: Sender<()>,
+    thread: JoinHandle<Vec<Sample>>,
+}
+
+impl Sampler {
+    /// Start sampling the sysfs mounted at `sys` every `interval`, starting right away.
+    pub fn start(sys: &Path, interval: Duration) -> Self {
+        let sys = sys.to_owned();
+        let start = Instant::now();
+        let (stop, stopped) = mpsc::channel();
+        let thread = std::thread::spawn(move || {
+            let mut samples = vec![];
+            loop {
+                samples.push(sample(&sys, start.elapsed()));
+                // Also stops when the sampler is dropped without being stopped, e.g. when a
+                // benchmark panics.
+                match stopped.recv_timeout(interval) {
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Ok(()) | Err(RecvTimeoutError::Disconnected) => return samples,
+                }
+            }
+        });
+        Sampler {
+            start,
+            stop,
+            thread,
+        }
+    }
+
+    /// The time since the sampler started, for the [`GroupWindow`]s.
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// Stop the thread, without waiting for the next sample, and return all samples.
+    pub fn stop(self) -> Vec<Sample> {
+        let _ = self.stop.send(());
+        self.thread.join().unwrap()
+    }
+}
+
+/// Read the temperature of every thermal zone and the frequency of every CPU from the sysfs
+/// mounted at `sys`.
+pub fn sample(sys: &Path, at: Duration) -> Sample {
+    let read = |path: &Path| -> Option<f64> { fs::read_to_string(path).ok()?.trim().parse().ok() };
+    let entries = |dir: &Path, prefix: &str| {
+        let mut paths = fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with(prefix))
+            .map(|entry| entry.path())
+            .collect::<Vec<_>>();
+        paths.sort();
+        paths
+    };
+
+    // In millidegrees Celsius. Some zones, like those of disconnected sensors, report nonsense
+    // below freezing.
+    let celsius = entries(&sys.join("class/thermal"), "thermal_zone")
+        .iter()
+        .filter_map(|zone| read(&zone.join("temp")))
+        .map(|millidegrees| millidegrees / 1000.0)
+        .filter(|&celsius| celsius > 0.0)
+        .reduce(f64::max);
+
+    // Idle CPUs clock down, so only the fastest one tells whether the CPU is throttled.
+    let frequency_percent = entries(&sys.join("devices/system/cpu"), "cpu")
+        .iter()
+        .filter_map(|cpu| {
+            let current = read(&cpu.join("cpufreq/scaling_cur_freq"))?;
+            let max = read(&cpu.join("cpufreq/cpuinfo_max_freq")).filter(|&max| max > 0.0)?;
+            Some(current / max * 100.0)
+        })
+        .reduce(f64::max);
+
+    Sample {
+        at,
+        celsius,
+        frequency_percent,
+    }
+}
+
+/// Warn that the CPU was throttled while the benchmarks ran.
+pub fn render_markdown_warning(md: &mut String, thermal: Option<&Thermal>) {
+    let Some(thermal) = thermal.filter(|thermal| thermal.is_throttled()) else {
+        return;
+    };
+
+    write!(
+        md,
+        "> [!WARNING]\n> Thermal throttling: the CPU ran below {}% of its maximum frequency in {:.0}% of {} samples",
+        thermal.throttle_percent,
+        thermal.throttled_percent.unwrap_or_default(),
+        thermal.samples,
+    )
+    .unwrap();
+    if let (Some(min), Some(max), Some(mean)) = (
+        thermal.min_celsius,
+        thermal.max_celsius,
+        thermal.mean_celsius,
+    ) {
+        write!(md, " ({min:.1}–{max:.1} °C, mean {mean:.1} °C)").unwrap();
+    }
+    writeln!(md, ". The results may be unreliable.").unwrap();
+    if !thermal.throttled_groups.is_empty() {
+        let groups = thermal
+            .throttled_groups
+            .iter()
+            .map(|group| format!("`{group}`"))
+            .collect::<Vec<_>>();
+        writeln!(md, ">\n> Throttled while running: {}.", groups.join(", ")).unwrap();
+    }
+    writeln!(md).unwrap();
+}
+
+#[cfg(test)]
+fn sysfs_for_test(name: &str, temps: &[&str], frequencies: &[(&str, &str)]) -> std::path::PathBuf {
+    let dir = crate::testkit::test_dir(name);
+    for (index, temp) in temps.iter().enumerate() {
+        let zone = dir.join(format!("class/thermal/thermal_zone{index}"));
+        fs::create_dir_all(&zone).unwrap();
+        fs::write(zone.join("temp"), temp).unwrap();
+    }
+    for (index, (current, max)) in frequencies.iter().enumerate() {
+        let cpufreq = dir.join(format!("devices/system/cpu/cpu{index}/cpufreq"));
+        fs::create_dir_all(&cpufreq).unwrap();
+        fs::write(cpufreq.join("scaling_cur_freq"), current).unwrap();
+        fs::write(cpufreq.join("cpuinfo_max_freq"), max).unwrap();
+    }
+    // Not a CPU.
+    fs::create_dir_all(dir.join("devices/system/cpu/cpufreq")).unwrap();
+    dir
+}
+
+#[test]
+fn sample_sysfs() {
+    let sys = sysfs_for_test(
+        "thermal-sysfs",
+        &["45000\n", "71500\n", "-273000\n", "garbage\n"],
+        &[("1200000\n", "2400000\n"), ("2160000\n", "2400000\n")],
+    );
+    let reading = sample(&sys, Duration::from_secs(3));
+    assert_eq!(
+        reading,
+        Sample {
+            at: Duration::from_secs(3),
+            celsius: Some(71.5),
+            frequency_percent: Some(90.0),
+        }
+    );
+
+    // Neither thermal zones nor cpufreq, like in many VMs.
+    let empty = crate::testkit::test_dir("thermal-sysfs-empty");
+    assert_eq!(
+        sample(&empty, Duration::ZERO),
+        Sample {
+            at: Duration::ZERO,
+            celsius: None,
+            frequency_percent: None,
+        }
+    );
+}
+
+#[test]
+fn sampler_lifecycle() {
+    let sys = sysfs_for_test("thermal-sampler", &["50000\n"], &[("2400000", "2400000")]);
+
+    let sampler = Sampler::start(&sys, Duration::from_millis(5));
+    std::thread::sleep(Duration::from_millis(50));
+    let elapsed = sampler.elapsed();
+    let samples = sampler.stop();
+    assert!(samples.len() >= 2, "{samples:?}");
+    assert!(samples.windows(2).all(|pair| pair[0].at < pair[1].at));
+    assert!(samples
+        .iter()
+        .all(|sample| sample.at <= elapsed + Duration::from_millis(50)));
+    assert_eq!(samples[0].celsius, Some(50.0));
+
+    // Stopping doesn't wait for the next sample.
+    let sampler = Sampler::start(&sys, Duration::from_secs(3600));
+    let stopping = Instant::now();
+    assert_eq!(sampler.stop().len(), 1);
+    assert!(stopping.elapsed() < Duration::from_secs(10));
+
+    // Dropping it stops the thread too.
+    drop(Sampler::start(&sys, Duration::from_millis(1)));
+}
+
+#[test]
+fn throttled_groups_from_timeline() {
+    let secs = Duration::from_secs;
+    let sample = |at: u64, celsius: f64, frequency_percent: f64| Sample {
+        at: secs(at),
+        celsius: Some(celsius),
+        frequency_percent: Some(frequency_percent),
+    };
+    let window = |group: &str, start: u64, end: u64| GroupWindow {
+        group: group.to_owned(),
+        start: secs(start),
+        end: secs(end),
+    };
+
+    let samples = [
+        sample(0, 40.0, 100.0),
+        sample(10, 60.0, 100.0),
+        sample(20, 80.0, 70.0),
+        sample(30, 80.0, 75.0),
+        sample(40, 60.0, 100.0),
+    ];
+    let windows = [
+        window("compress", 1, 9),
+        // Throttled in the interval before the sample at 20.
+        window("decompress", 9, 12),
+        window("checksum", 12, 28),
+        window("compress", 28, 32),
+        // Ends before the interval of the sample at 40.
+        window("inflate", 32, 34),
+    ];
+    let thermal = Thermal::summarize(&samples, &windows, secs(10), 90.0).unwrap();
+    assert_eq!(
+        thermal,
+        Thermal {
+            samples: 5,
+            min_celsius: Some(40.0),
+            max_celsius: Some(80.0),
+            mean_celsius: Some(64.0),
+            throttle_percent: 90.0,
+            throttled_percent: Some(40.0),
+            throttled_groups: vec![
+                "decompress".to_owned(),
+                "checksum".to_owned(),
+                "compress".to_owned()
+            ],
+        }
+    );
+    assert!(thermal.is_throttled());
+
+    let mut md = String::new();
+    render_markdown_warning(&mut md, Some(&thermal));
+    assert_eq!(
+        md,
+        "> [!WARNING]\n> Thermal throttling: the CPU ran below 90% of its maximum frequency in 40% of 5 samples (40.0–80.0 °C, mean 64.0 °C). The results may be unreliable.\n\
+         >\n> Throttled while running: `decompress`, `checksum`, `compress`.\n\n"
+    );
+
+    // Not throttled, or the frequency is unknown.
+    let cool = Thermal::summarize(&samples[..2], &windows, secs(10), 90.0).unwrap();
+    assert_eq!(cool.throttled_percent, Some(0.0));
+    assert!(cool.throttled_groups.is_empty());
+    let unknown = [Sample {
+        frequency_percent: None,
+        ..sample(0, 40.0, 0.0)
+    }];
+    let unknown = Thermal::summarize(&unknown, &windows, secs(10), 90.0).unwrap();
+    assert_eq!(unknown.throttled_percent, None);
+    for thermal in [None, Some(&cool), Some(&unknown)] {
+        let mut md = String::new();
+        render_markdown_warning(&mut md, thermal);
+        assert_eq!(md, "");
+    }
+
+    assert_eq!(Thermal::summarize(&[], &windows, secs(10), 90.0), None);
+}
@@ -0,0 +1,387 @@
+//! The totals of the whole suite, for "did the suite get faster" at a glance: the sum of a
+//! counter over every command of every group, and its change since the baseline.
+//!
+//! ```json
+//! "suite-totals": { "counters": ["task-clock", "cycles", "instructions"] }
+//! ```
+//!
+//! Only counters whose values add up are summed, counts and durations, see
+//! [`composite::is_summable`]. Durations in other units are converted to the unit of the first
+//! command that has the counter; a command with the counter in a unit that doesn't convert is
+//! left out. The change only covers the commands that have the counter both in the run and in
+//! the baseline, so a command that was added or lost its counter doesn't show up as a change;
+//! how many were left out is reported with the totals.
+//!
+//! The totals are never marked significant. A t-test on the sum of the means would need the
+//! commands to be independent and their variances to add up, which they don't on a shared
+//! runner, where one noisy neighbour slows every command down at once. The comparisons of the
+//! single commands are what tests.
+
+use std::fmt::Write;
+use std::fs::OpenOptions;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bench::{BenchCounter, SingleBench};
+use crate::compare::find_prev_bench;
+use crate::{composite, format_number, units, BenchData};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct SuiteTotalsConfig {
+    /// The counters to sum, in the order of the table.
+    #[serde(default = "default_counters")]
+    pub counters: Vec<String>,
+}
+
+fn default_counters() -> Vec<String> {
+    ["task-clock", "cycles", "instructions"]
+        .map(String::from)
+        .to_vec()
+}
+
+/// The total of a counter over the suite.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SuiteTotal {
+    pub counter: String,
+    pub unit: String,
+    /// The sum over every command that has the counter.
+    pub total: f64,
+    /// The commands summed in `total`.
+    pub commands: usize,
+    /// The commands left out of `total`, as their counter is in a unit that doesn't convert.
+    #[serde(skip_serializing_if = "is_zero")]
+    pub mismatched_units: usize,
+    /// The change since the baseline, when there is one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub baseline: Option<BaselineTotal>,
+}
+
+/// The totals of the commands that have the counter both in the run and in the baseline.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BaselineTotal {
+    pub baseline: f64,
+    pub current: f64,
+    pub delta_percent: f64,
+    /// The commands of `total` left out, as the baseline doesn't have their counter.
+    pub excluded: usize,
+}
+
+fn is_zero(count: &usize) -> bool {
+    *count == 0
+}
+
+/// The value of `counter` in `unit`, when it converts.
+fn value_in(counter: &BenchCounter, unit: &str) -> Option<f64> {
+    if counter.unit == unit {
+        return Some(counter.value);
+    }
+    let from = units::duration_unit_secs(&counter.unit)?;
+    let to = units::duration_unit_secs(unit)?;
+    Some(counter.value * from / to)
+}
+
+impl SuiteTotalsConfig {
+    /// The totals of the configured counters that `data` has in a summable unit, over the
+    /// commands `counted` accepts, by group.
+    pub fn collect(
+        &self,
+        data: &BenchData,
+        prev_results: Option<&BenchData>,
+        counted: impl Fn(&str, &SingleBench) -> bool,
+    ) -> Vec<SuiteTotal> {
+        let benches = data
+            .bench_groups
+            .iter()
+            .flat_map(|(group_name, benches)| benches.iter().map(move |bench| (group_name, bench)))
+            .filter(|(group_name, bench)| bench.error.is_none() && counted(group_name, bench))
+            .collect::<Vec<_>>();
+
+        let mut totals = vec![];
+        for counter_name in &self.counters {
+            let Some(unit) = benches
+                .iter()
+                .find_map(|(_, bench)| bench.counters.get(counter_name))
+                .map(|counter| counter.unit.clone())
+                .filter(|unit| composite::is_summable(unit))
+            else {
+                continue;
+            };
+
+            let mut total = SuiteTotal {
+                counter: counter_name.clone(),
+                unit,
+                total: 0.0,
+                commands: 0,
+                mismatched_units: 0,
+                baseline: prev_results.map(|_| BaselineTotal {
+                    baseline: 0.0,
+                    current: 0.0,
+                    delta_percent: 0.0,
+                    excluded: 0,
+                }),
+            };
+            for (group_name, bench) in &benches {
+                let Some(counter) = bench.counters.get(counter_name) else {
+                    continue;
+                };
+                let Some(value) = value_in(counter, &total.unit) else {
+                    total.mismatched_units += 1;
+                    continue;
+                };
+                total.total += value;
+                total.commands += 1;
+
+                let Some(baseline) = &mut total.baseline else {
+                    continue;
+                };
+                let prev_value = prev_results
+                    .and_then(|prev_results| prev_results.bench_groups.get(*group_name))
+                    .and_then(|prev_group_results| find_prev_bench(prev_group_results, bench))
+                    .filter(|prev_bench| prev_bench.error.is_none())
+                    .and_then(|prev_bench| prev_bench.counters.get(counter_name))
+                    .and_then(|prev_counter| value_in(prev_counter, &total.unit));
+                match prev_value {
+                    Some(prev_value) => {
+                        baseline.baseline += prev_value;
+                        baseline.current += value;
+                    }
+                    None => baseline.excluded += 1,
+                }
+            }
+            if let Some(baseline) = &mut total.baseline {
+                baseline.delta_percent = if baseline.baseline == 0.0 {
+                    0.0
+                } else {
+                    (baseline.current - baseline.baseline) / baseline.baseline * 100.0
+                };
+            }
+            totals.push(total);
+        }
+        totals
+    }
+}
+
+fn with_unit(value: f64, unit: &str) -> String {
+    if unit.is_empty() {
+        format_number(value, 3)
+    } else {
+        format!("{} {unit}", format_number(value, 3))
+    }
+}
+
+/// The table of the totals, with a note on the commands they leave out.
+pub fn render_markdown(md: &mut String, totals: &[SuiteTotal]) {
+    if totals.is_empty() {
+        return;
+    }
+
+    writeln!(md, "### Suite totals\n").unwrap();
+    writeln!(md, "| counter | total | commands | baseline | Δ |").unwrap();
+    writeln!(md, "| --- | --- | --- | --- | --- |").unwrap();
+    for total in totals {
+        let (baseline, delta) = match &total.baseline {
+            Some(baseline) if baseline.excluded < total.commands => (
+                format!("`{}`", with_unit(baseline.baseline, &total.unit)),
+                format!("`{:+.2}%`", baseline.delta_percent),
+            ),
+            _ => ("n.a.".to_owned(), "n.a.".to_owned()),
+        };
+        writeln!(
+            md,
+            "| {} | `{}` | {} | {baseline} | {delta} |",
+            total.counter,
+            with_unit(total.total, &total.unit),
+            total.commands,
+        )
+        .unwrap();
+    }
+    writeln!(md).unwrap();
+
+    let mut notes = vec![
+        "The totals are sums of means, they are not tested for significance. The baseline and Δ only cover the commands measured in both runs.".to_owned(),
+    ];
+    for total in totals {
+        if let Some(baseline) = total
+            .baseline
+            .as_ref()
+            .filter(|baseline| baseline.excluded > 0)
+        {
+            notes.push(format!(
+                "{} of {} commands are left out of the baseline of `{}`, it has no data for them.",
+                baseline.excluded, total.commands, total.counter
+            ));
+        }
+        if total.mismatched_units > 0 {
+            notes.push(format!(
+                "{} commands are left out of the total of `{}`, they measure it in another unit than `{}`.",
+                total.mismatched_units, total.counter, total.unit
+            ));
+        }
+    }
+    for note in notes {
+        writeln!(md, "> {note}").unwrap();
+    }
+    writeln!(md).unwrap();
+}
+
+/// The name of the output of a counter in `GITHUB_OUTPUT`, e.g. `suite-total-task-clock`.
+fn output_name(counter: &str) -> String {
+    let name = counter
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect::<String>();
+    format!("suite-total-{}", name.trim_matches('-'))
+}
+
+/// Append the totals, and their changes, to the `GITHUB_OUTPUT` file of the step.
+pub fn write_github_output(path: &Path, totals: &[SuiteTotal]) -> Result<(), String> {
+    use std::io::Write;
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("failed to open {}: {e}", path.display()))?;
+    let mut outputs = String::new();
+    for total in totals {
+        let name = output_name(&total.counter);
+        writeln!(outputs, "{name}={}", format_number(total.total, 3)).unwrap();
+        if let Some(baseline) = &total.baseline {
+            writeln!(
+                outputs,
+                "{name}-delta-percent={}",
+                format_number(baseline.delta_percent, 3)
+            )
+            .unwrap();
+        }
+    }
+    file.write_all(outputs.as_bytes())
+        .map_err(|e| format!("failed to write {}: {e}", path.display()))
+}
+
+#[cfg(test)]
+fn data_for_test(commit_hash: &str, scale: f64) -> BenchData {
+    crate::testkit::BenchDataBuilder::new(commit_hash)
+        .group("compress", |g| {
+            g.bench(["./compress", "6"], |b| {
+                b.counter("task-clock", 100.0 * scale, 1.0, 20, "msec")
+                    .counter("cycles", 1.0e9 * scale, 1.0e6, 20, "")
+                    .counter("max-rss", 4096.0, 0.0, 20, "KiB")
+            })
+            .bench(["./compress", "9"], |b| {
+                b.counter("task-clock", 0.2 * scale, 1.0, 20, "sec")
+                    .counter("cycles", 2.0e9 * scale, 1.0e6, 20, "")
+            })
+        })
+        .group("decompress", |g| {
+            g.bench(["./decompress"], |b| {
+                b.counter("task-clock", 50.0 * scale, 1.0, 20, "msec")
+                    .counter("cycles", 5.0e8 * scale, 1.0e6, 20, "")
+                    .counter("max-rss", 2048.0, 0.0, 20, "KiB")
+            })
+        })
+        .build()
+}
+
+#[cfg(test)]
+fn config_for_test(counters: &[&str]) -> SuiteTotalsConfig {
+    SuiteTotalsConfig {
+        counters: counters.iter().map(|&counter| counter.to_owned()).collect(),
+    }
+}
+
+#[test]
+fn summable_counters() {
+    let data = data_for_test("2222222222222222222222222222222222222222", 1.0);
+    let totals = config_for_test(&["task-clock", "instructions", "max-rss", "cycles"]).collect(
+        &data,
+        None,
+        |_, _| true,
+    );
+
+    // Sizes don't add up, and the run has no instructions.
+    assert_eq!(
+        totals
+            .iter()
+            .map(|total| &total.counter)
+            .collect::<Vec<_>>(),
+        ["task-clock", "cycles"]
+    );
+    // The seconds are converted to the unit of the first command.
+    assert_eq!(totals[0].unit, "msec");
+    assert!((totals[0].total - 350.0).abs() < 1e-9, "{totals:?}");
+    assert_eq!(totals[0].commands, 3);
+    assert_eq!(totals[1].total, 3.5e9);
+    assert!(totals.iter().all(|total| total.baseline.is_none()));
+
+    // A unit that doesn't convert leaves the command out, and so does `counted`.
+    let mut data = data;
+    data.bench_groups["decompress"][0]
+        .counters
+        .get_mut("task-clock")
+        .unwrap()
+        .unit = "KiB".to_owned();
+    let totals = config_for_test(&["task-clock"]).collect(&data, None, |group_name, bench| {
+        group_name != "compress" || bench.cmd[1] != "9"
+    });
+    assert_eq!(totals[0].total, 100.0);
+    assert_eq!(totals[0].commands, 1);
+    assert_eq!(totals[0].mismatched_units, 1);
+}
+
+#[test]
+fn common_commands() {
+    let prev = data_for_test("1111111111111111111111111111111111111111", 1.0);
+    let mut data = data_for_test("2222222222222222222222222222222222222222", 1.1);
+    // A command the baseline doesn't have, and one the run doesn't have.
+    data.bench_groups["decompress"][0].cmd = vec!["./decompress".to_owned(), "-k".to_owned()];
+    data.bench_groups["compress"].remove(1);
+
+    let totals = config_for_test(&["cycles"]).collect(&data, Some(&prev), |_, _| true);
+    assert_eq!(totals[0].total, 1.1e9 + 5.5e8);
+    assert_eq!(totals[0].commands, 2);
+    let baseline = totals[0].baseline.as_ref().unwrap();
+    assert_eq!(baseline.baseline, 1.0e9);
+    assert_eq!(baseline.current, 1.1e9);
+    assert!((baseline.delta_percent - 10.0).abs() < 1e-9);
+    assert_eq!(baseline.excluded, 1);
+}
+
+#[test]
+fn excluded_commands() {
+    let mut prev = data_for_test("1111111111111111111111111111111111111111", 1.0);
+    let data = data_for_test("2222222222222222222222222222222222222222", 0.9);
+    prev.bench_groups["compress"][1].counters.remove("cycles");
+    prev.bench_groups["decompress"][0].error = Some("no data for `cycles`".to_owned());
+
+    let totals =
+        config_for_test(&["task-clock", "cycles"]).collect(&data, Some(&prev), |_, _| true);
+    assert_eq!(totals[0].baseline.as_ref().unwrap().excluded, 1);
+    assert_eq!(totals[1].baseline.as_ref().unwrap().excluded, 2);
+
+    let mut md = String::new();
+    render_markdown(&mut md, &totals);
+    assert_eq!(
+        md,
+        "### Suite totals\n\n\
+         | counter | total | commands | baseline | Δ |\n\
+         | --- | --- | --- | --- | --- |\n\
+         | task-clock | `315 msec` | 3 | `300 msec` | `-10.00%` |\n\
+         | cycles | `3150000000` | 3 | `1000000000` | `-10.00%` |\n\n\
+         > The totals are sums of means, they are not tested for significance. The baseline and Δ only cover the commands measured in both runs.\n\
+         > 1 of 3 commands are left out of the baseline of `task-clock`, it has no data for them.\n\
+         > 2 of 3 commands are left out of the baseline of `cycles`, it has no data for them.\n\n"
+    );
+
+    let path = crate::testkit::test_dir("suite-totals").join("output");
+    write_github_output(&path, &totals).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "suite-total-task-clock=315\n\
+         suite-total-task-clock-delta-percent=-10\n\
+         suite-total-cycles=3150000000\n\
+         suite-total-cycles-delta-percent=-10\n"
+    );
+}
@@ -0,0 +1,314 @@
+//! Where a run was triggered from: the git ref, the branch and the pull request, from the
+//! environment of GitHub Actions. Only the results of the persistent branches are appended to
+//! the results file, and only those are baselines, so the runs of a pull request or a feature
+//! branch never end up compared against:
+//!
+//! ```json
+//! "persistent-branches": ["main", "release"]
+//! ```
+//!
+//! By default, the only persistent branch is the default branch of the repository. Results
+//! without a trigger, from before it was recorded or from a run outside of GitHub Actions,
+//! are kept and used like before.
+
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+/// The branch when the event doesn't tell the default branch of the repository.
+const DEFAULT_BRANCH: &str = "main";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Trigger {
+    /// `GITHUB_REF`, e.g. `refs/heads/main` or `refs/pull/42/merge`.
+    #[serde(rename = "ref")]
+    pub git_ref: String,
+    /// The branch of a push, or the head branch of a pull request.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pull_request: Option<u64>,
+}
+
+/// What the run knows about the event that triggered it.
+#[derive(Debug, Default, PartialEq)]
+pub struct GitHubContext {
+    /// `None` outside of GitHub Actions.
+    pub trigger: Option<Trigger>,
+    /// The default branch of the repository, from the event payload.
+    pub default_branch: Option<String>,
+    /// The head commit of the pull request, from the event payload.
+    pub pull_request_head: Option<String>,
+    /// The labels of the pull request, from the event payload.
+    pub labels: Vec<Label>,
+}
+
+/// The parts of the event payload at `GITHUB_EVENT_PATH` that matter, for `push` and
+/// `pull_request` events alike.
+#[derive(Debug, Default, Deserialize)]
+struct EventPayload {
+    #[serde(default)]
+    pull_request: Option<PullRequest>,
+    #[serde(default)]
+    repository: Option<Repository>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PullRequest {
+    number: u64,
+    head: Head,
+    #[serde(default)]
+    labels: Vec<Label>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Head {
+    #[serde(rename = "ref")]
+    git_ref: String,
+    #[serde(default)]
+    sha: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Label {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Repository {
+    default_branch: String,
+}
+
+impl GitHubContext {
+    /// From the environment variables of GitHub Actions, as given by `var`: `GITHUB_REF`,
+    /// `GITHUB_REF_NAME`, `GITHUB_HEAD_REF` and the payload at `GITHUB_EVENT_PATH`. A payload
+    /// that can't be read only loses what the variables don't tell.
+    pub fn from_env(var: impl Fn(&str) -> Option<String>) -> Self {
+        let payload = match var("GITHUB_EVENT_PATH") {
+            Some(path) => read_event(Path::new(&path)).unwrap_or_else(|err| {
+                eprintln!("warning: {err}");
+                EventPayload::default()
+            }),
+            None => EventPayload::default(),
+        };
+        let default_branch = payload
+            .repository
+            .map(|repository| repository.default_branch);
+        let pull_request_head = payload
+            .pull_request
+            .as_ref()
+            .and_then(|pull_request| pull_request.head.sha.clone());
+        let labels = payload
+            .pull_request
+            .as_ref()
+            .map(|pull_request| pull_request.labels.clone())
+            .unwrap_or_default();
+
+        let Some(git_ref) = var("GITHUB_REF").filter(|git_ref| !git_ref.is_empty()) else {
+            return GitHubContext {
+                trigger: None,
+                default_branch,
+                pull_request_head,
+                labels,
+            };
+        };
+        let pull_request = payload
+            .pull_request
+            .as_ref()
+            .map(|pull_request| pull_request.number)
+            .or_else(|| pull_request_number(&git_ref));
+        let branch = if pull_request.is_some() {
+            var("GITHUB_HEAD_REF")
+                .filter(|branch| !branch.is_empty())
+                .or(payload
+                    .pull_request
+                    .map(|pull_request| pull_request.head.git_ref))
+        } else if git_ref.starts_with("refs/heads/") {
+            var("GITHUB_REF_NAME")
+                .or_else(|| git_ref.strip_prefix("refs/heads/").map(str::to_owned))
+        } else {
+            None
+        };
+
+        GitHubContext {
+            trigger: Some(Trigger {
+                git_ref,
+                branch,
+                pull_request,
+            }),
+            default_branch,
+            pull_request_head,
+            labels,
+        }
+    }
+
+    /// The branches whose results are stored and compared against: the `configured` ones, or
+    /// the default branch.
+    pub fn persistent_branches(&self, configured: Option<&[String]>) -> Vec<String> {
+        match configured {
+            Some(branches) => branches.to_vec(),
+            None => vec![self
+                .default_branch
+                .clone()
+                .unwrap_or_else(|| DEFAULT_BRANCH.to_owned())],
+        }
+    }
+}
+
+fn read_event(path: &Path) -> Result<EventPayload, String> {
+    let json = fs::read(path)
+        .map_err(|e| format!("failed to read the event payload {}: {e}", path.display()))?;
+    serde_json::from_slice(&json)
+        .map_err(|e| format!("failed to parse the event payload {}: {e}", path.display()))
+}
+
+/// The number of the pull request of a ref like `refs/pull/42/merge`.
+fn pull_request_number(git_ref: &str) -> Option<u64> {
+    let (number, _) = git_ref.strip_prefix("refs/pull/")?.split_once('/')?;
+    number.parse().ok()
+}
+
+impl Trigger {
+    /// What the run is of, e.g. ``pull request #42 (`faster-inflate`)``.
+    pub fn describe(&self) -> String {
+        match (self.pull_request, &self.branch) {
+            (Some(number), Some(branch)) => format!("pull request #{number} (`{branch}`)"),
+            (Some(number), None) => format!("pull request #{number}"),
+            (None, Some(branch)) => format!("the `{branch}` branch"),
+            (None, None) => format!("`{}`", self.git_ref),
+        }
+    }
+
+    /// Whether the run is of one of the persistent `branches`, and not of a pull request.
+    pub fn is_persistent(&self, branches: &[String]) -> bool {
+        self.pull_request.is_none()
+            && self
+                .branch
+                .as_ref()
+                .is_some_and(|branch| branches.contains(branch))
+    }
+}
+
+/// Whether results with `trigger` are stored, and can be a baseline. Results without one are.
+pub fn is_persistent(trigger: Option<&Trigger>, branches: &[String]) -> bool {
+    trigger.is_none_or(|trigger| trigger.is_persistent(branches))
+}
+
+#[cfg(test)]
+pub fn context_for_test(vars: &[(&str, &str)]) -> GitHubContext {
+    GitHubContext::from_env(|name| {
+        let value = vars.iter().find(|(var, _)| *var == name)?.1;
+        let value = match name {
+            "GITHUB_EVENT_PATH" => Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("testdata/trigger")
+                .join(value)
+                .display()
+                .to_string(),
+            _ => value.to_owned(),
+        };
+        Some(value)
+    })
+}
+
+#[test]
+fn pull_request_event() {
+    let context = context_for_test(&[
+        ("GITHUB_REF", "refs/pull/42/merge"),
+        ("GITHUB_REF_NAME", "42/merge"),
+        ("GITHUB_HEAD_REF", "faster-inflate"),
+        ("GITHUB_EVENT_PATH", "pull_request.json"),
+    ]);
+    let trigger = Trigger {
+        git_ref: "refs/pull/42/merge".to_owned(),
+        branch: Some("faster-inflate".to_owned()),
+        pull_request: Some(42),
+    };
+    assert_eq!(
+        context,
+        GitHubContext {
+            trigger: Some(trigger.clone()),
+            default_branch: Some("trunk".to_owned()),
+            pull_request_head: Some("2222222222222222222222222222222222222222".to_owned()),
+            labels: vec![],
+        }
+    );
+    assert_eq!(context.persistent_branches(None), ["trunk"]);
+
+    // Without the payload, or the variables of a pull request event.
+    assert_eq!(
+        context_for_test(&[("GITHUB_REF", "refs/pull/42/merge")]).trigger,
+        Some(Trigger {
+            branch: None,
+            ..trigger.clone()
+        })
+    );
+    assert_eq!(
+        context_for_test(&[
+            ("GITHUB_REF", "refs/pull/42/merge"),
+            ("GITHUB_EVENT_PATH", "pull_request.json"),
+        ])
+        .trigger,
+        Some(trigger)
+    );
+
+    // A payload that can't be read only loses what it would add.
+    let context = context_for_test(&[
+        ("GITHUB_REF", "refs/pull/42/merge"),
+        ("GITHUB_EVENT_PATH", "does-not-exist.json"),
+    ]);
+    assert_eq!(context.trigger.unwrap().pull_request, Some(42));
+    assert_eq!(context.default_branch, None);
+}
+
+#[test]
+fn push_event() {
+    let context = context_for_test(&[
+        ("GITHUB_REF", "refs/heads/trunk"),
+        ("GITHUB_REF_NAME", "trunk"),
+        ("GITHUB_EVENT_PATH", "push.json"),
+    ]);
+    let trigger = context.trigger.as_ref().unwrap();
+    assert_eq!(trigger.branch.as_deref(), Some("trunk"));
+    assert_eq!(trigger.pull_request, None);
+    assert!(trigger.is_persistent(&context.persistent_branches(None)));
+
+    // Tags aren't branches.
+    let context = context_for_test(&[("GITHUB_REF", "refs/tags/v1.0")]);
+    let trigger = context.trigger.unwrap();
+    assert_eq!(trigger.branch, None);
+    assert_eq!(context.default_branch, None);
+    assert!(!trigger.is_persistent(&["main".to_owned()]));
+
+    // Outside of GitHub Actions.
+    assert_eq!(context_for_test(&[]), GitHubContext::default());
+    assert_eq!(GitHubContext::default().persistent_branches(None), ["main"]);
+}
+
+#[test]
+fn persistent_results() {
+    let branches = ["main".to_owned(), "release".to_owned()];
+    let trigger = |branch: &str, pull_request| Trigger {
+        git_ref: format!("refs/heads/{branch}"),
+        branch: Some(branch.to_owned()),
+        pull_request,
+    };
+    assert!(is_persistent(Some(&trigger("main", None)), &branches));
+    assert!(is_persistent(Some(&trigger("release", None)), &branches));
+    assert!(!is_persistent(Some(&trigger("feature", None)), &branches));
+    // A pull request from a persistent branch is still a pull request.
+    assert!(!is_persistent(
+        Some(&trigger("release", Some(7))),
+        &branches
+    ));
+    // Results from before the trigger was recorded.
+    assert!(is_persistent(None, &branches));
+
+    let json = serde_json::to_value(trigger("feature", Some(7))).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({ "ref": "refs/heads/feature", "branch": "feature", "pull_request": 7 })
+    );
+}
@@ -0,0 +1,476 @@
+//! Durations and sizes in the config, with their unit, e.g. `"90s"`, `"1.5min"`, `"250ms"`,
+//! `"100MiB"` or `"2GB"`. A plain number is in the unit the field always had, e.g.
+//! milliseconds for `sync-start-timeout-ms`, so existing configs keep working.
+//!
+//! Units are case-insensitive, and may be separated from the number by whitespace. `m` and
+//! the bare size prefixes like `M` are rejected as ambiguous, rather than guessing between
+//! minutes and milliseconds, or decimal and binary multiples.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+
+/// The duration units, in seconds. The perf spellings like `msec` are accepted too.
+const DURATION_UNITS: &[(&str, f64)] = &[
+    ("ns", 1e-9),
+    ("us", 1e-6),
+    ("µs", 1e-6),
+    ("ms", 1e-3),
+    ("s", 1.0),
+    ("min", 60.0),
+    ("h", 3600.0),
+    ("d", 86400.0),
+    ("nsec", 1e-9),
+    ("usec", 1e-6),
+    ("msec", 1e-3),
+    ("sec", 1.0),
+];
+
+/// The size units, in bytes.
+const SIZE_UNITS: &[(&str, f64)] = &[
+    ("B", 1.0),
+    ("kB", 1e3),
+    ("MB", 1e6),
+    ("GB", 1e9),
+    ("TB", 1e12),
+    ("KiB", 1024.0),
+    ("MiB", 1048576.0),
+    ("GiB", 1073741824.0),
+    ("TiB", 1099511627776.0),
+];
+
+/// Units that could mean more than one of the above, with what to write instead.
+const AMBIGUOUS_DURATION_UNITS: &[(&str, &str)] = &[("m", "min or ms")];
+const AMBIGUOUS_SIZE_UNITS: &[(&str, &str)] = &[
+    ("k", "kB or KiB"),
+    ("m", "MB or MiB"),
+    ("g", "GB or GiB"),
+    ("t", "TB or TiB"),
+];
+
+/// The units listed in the errors, without the perf spellings.
+fn listed(units: &[(&str, f64)]) -> String {
+    units
+        .iter()
+        .map(|(unit, _)| *unit)
+        .filter(|unit| !unit.ends_with("sec"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Split `text` into its number and its unit, and look the unit up in `units`.
+fn parse(text: &str, units: &[(&str, f64)], ambiguous: &[(&str, &str)]) -> Result<f64, String> {
+    let text = text.trim();
+    let number_len = text
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(number_len);
+    let unit = unit.trim_start();
+
+    let number = number
+        .parse::<f64>()
+        .map_err(|_| "expected a number followed by a unit".to_owned())?;
+    if unit.is_empty() {
+        return Err(format!("missing unit, expected one of {}", listed(units)));
+    }
+    if let Some((_, instead)) = ambiguous
+        .iter()
+        .find(|(ambiguous, _)| ambiguous.eq_ignore_ascii_case(unit))
+    {
+        return Err(format!("ambiguous unit `{unit}`, use {instead}"));
+    }
+    let (_, scale) = units
+        .iter()
+        .find(|(known, _)| known.to_lowercase() == unit.to_lowercase())
+        .ok_or_else(|| format!("unknown unit `{unit}`, expected one of {}", listed(units)))?;
+    Ok(number * scale)
+}
+
+/// Whether `unit` is a duration unit, like the `msec` of perf's task-clock.
+pub fn is_duration_unit(unit: &str) -> bool {
+    duration_unit_secs(unit).is_some()
+}
+
+/// The length of the duration unit `unit` in seconds, `None` for any other unit.
+pub fn duration_unit_secs(unit: &str) -> Option<f64> {
+    DURATION_UNITS
+        .iter()
+        .find(|(known, _)| *known == unit)
+        .map(|&(_, scale)| scale)
+}
+
+/// The perf spelling of the duration unit `unit`, like `msec` for `ms`, `None` for units perf
+/// doesn't spell differently.
+pub fn perf_duration_unit(unit: &str) -> Option<&'static str> {
+    let scale = duration_unit_secs(unit)?;
+    DURATION_UNITS
+        .iter()
+        .find(|(known, known_scale)| known.ends_with("sec") && *known_scale == scale)
+        .map(|&(known, _)| known)
+}
+
+/// Parse a duration like `"1.5min"`.
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let secs = parse(text, DURATION_UNITS, AMBIGUOUS_DURATION_UNITS)?;
+    Duration::try_from_secs_f64(secs).map_err(|_| "the duration is too long".to_owned())
+}
+
+/// Parse a size like `"100MiB"`, in bytes.
+pub fn parse_size(text: &str) -> Result<u64, String> {
+    let bytes = parse(text, SIZE_UNITS, AMBIGUOUS_SIZE_UNITS)?;
+    if bytes > u64::MAX as f64 {
+        return Err("the size is too large".to_owned());
+    }
+    Ok(bytes.round() as u64)
+}
+
+/// A plain number in the default unit of a field, `scale` in seconds or bytes.
+fn from_number(value: &Value, scale: f64) -> Result<f64, String> {
+    value
+        .as_f64()
+        .filter(|number| number.is_finite() && *number >= 0.0)
+        .map(|number| number * scale)
+        .ok_or_else(|| "expected a non-negative number".to_owned())
+}
+
+/// Deserialize a duration, in `default_unit` when it is a plain number. The error starts with
+/// `invalid duration` and the value, for [`locate_error`].
+fn deserialize_duration<'de, D: Deserializer<'de>>(
+    deserializer: D,
+    default_unit: f64,
+) -> Result<Duration, D::Error> {
+    let value = Value::deserialize(deserializer)?;
+    match &value {
+        Value::String(text) => parse_duration(text),
+        _ => from_number(&value, default_unit).and_then(|secs| {
+            Duration::try_from_secs_f64(secs).map_err(|_| "the duration is too long".to_owned())
+        }),
+    }
+    .map_err(|err| serde::de::Error::custom(format!("invalid duration {value}: {err}")))
+}
+
+/// Deserialize a size in bytes, in `default_unit` when it is a plain number.
+fn deserialize_size<'de, D: Deserializer<'de>>(
+    deserializer: D,
+    default_unit: f64,
+) -> Result<u64, D::Error> {
+    let value = Value::deserialize(deserializer)?;
+    match &value {
+        Value::String(text) => parse_size(text),
+        _ => from_number(&value, default_unit).map(|bytes| bytes.round() as u64),
+    }
+    .map_err(|err| serde::de::Error::custom(format!("invalid size {value}: {err}")))
+}
+
+/// A duration in milliseconds when it is a plain number.
+pub fn millis<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    deserialize_duration(deserializer, 1e-3)
+}
+
+pub fn option_millis<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error> {
+    millis(deserializer).map(Some)
+}
+
+/// A duration in seconds when it is a plain number.
+pub fn secs<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    deserialize_duration(deserializer, 1.0)
+}
+
+/// A duration in days when it is a plain number.
+pub fn days<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    deserialize_duration(deserializer, 86400.0)
+}
+
+/// A size in MiB when it is a plain number.
+pub fn mebibytes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    deserialize_size(deserializer, 1048576.0)
+}
+
+pub fn option_mebibytes<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<u64>, D::Error> {
+    mebibytes(deserializer).map(Some)
+}
+
+/// A limit that is either a plain number in some other unit, or a duration or size.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Quantity {
+    Number(f64),
+    Duration(Duration),
+    Bytes(u64),
+}
+
+impl<'de> Deserialize<'de> for Quantity {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        match &value {
+            Value::Number(number) => Ok(Quantity::Number(number.as_f64().unwrap())),
+            Value::String(text) => parse_duration(text)
+                .map(Quantity::Duration)
+                .or_else(|_| parse_size(text).map(Quantity::Bytes))
+                .map_err(|_| {
+                    format!(
+                        "expected a duration in one of {}, or a size in one of {}",
+                        listed(DURATION_UNITS),
+                        listed(SIZE_UNITS)
+                    )
+                }),
+            _ => Err("expected a number, or a string with a unit".to_owned()),
+        }
+        .map_err(|err| serde::de::Error::custom(format!("invalid quantity {value}: {err}")))
+    }
+}
+
+impl Quantity {
+    /// What the quantity is, for errors.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Quantity::Number(_) => "a number",
+            Quantity::Duration(_) => "a duration",
+            Quantity::Bytes(_) => "a size",
+        }
+    }
+
+    /// The quantity in `unit`, e.g. the unit of a counter, when it is of the same kind.
+    pub fn in_unit(&self, unit: &str) -> Option<f64> {
+        let find = |units: &[(&str, f64)]| {
+            units
+                .iter()
+                .find(|(known, _)| *known == unit)
+                .map(|(_, scale)| *scale)
+        };
+        match self {
+            Quantity::Number(_) => None,
+            Quantity::Duration(duration) => Some(duration.as_secs_f64() / find(DURATION_UNITS)?),
+            Quantity::Bytes(bytes) => Some(*bytes as f64 / find(SIZE_UNITS)?),
+        }
+    }
+}
+
+/// Point an error of deserializing the merged `config` at the duration or size it is about,
+/// by its JSON path. Other errors are returned as they are.
+///
+/// The path is that of the first value the error can be about, which is the right one unless
+/// the same invalid value appears in another field too.
+pub fn locate_error(config: &Value, err: String) -> String {
+    fn find(value: &Value, path: &mut Vec<String>, err: &str) -> bool {
+        match value {
+            Value::Object(map) => map.iter().any(|(key, value)| {
+                path.push(format!(".{key}"));
+                let found = find(value, path, err);
+                if !found {
+                    path.pop();
+                }
+                found
+            }),
+            Value::Array(values) => values.iter().enumerate().any(|(index, value)| {
+                path.push(format!("[{index}]"));
+                let found = find(value, path, err);
+                if !found {
+                    path.pop();
+                }
+                found
+            }),
+            leaf => ["duration", "size", "quantity"]
+                .iter()
+                .any(|kind| err.starts_with(&format!("invalid {kind} {leaf}: "))),
+        }
+    }
+
+    let mut path = vec![];
+    if find(config, &mut path, &err) {
+        format!("{}: {err}", path.concat().trim_start_matches('.'))
+    } else {
+        err
+    }
+}
+
+#[test]
+fn durations() {
+    for (text, expected) in [
+        ("90s", Duration::from_secs(90)),
+        ("1.5min", Duration::from_secs(90)),
+        ("250ms", Duration::from_millis(250)),
+        ("2h", Duration::from_secs(7200)),
+        ("10us", Duration::from_micros(10)),
+        ("10µs", Duration::from_micros(10)),
+        ("100ns", Duration::from_nanos(100)),
+        ("0.5s", Duration::from_millis(500)),
+        (".5s", Duration::from_millis(500)),
+        ("0ms", Duration::ZERO),
+        // Whitespace and case.
+        (" 250 ms ", Duration::from_millis(250)),
+        ("250MS", Duration::from_millis(250)),
+        ("1.5 Min", Duration::from_secs(90)),
+        ("3H", Duration::from_secs(3 * 3600)),
+        ("2d", Duration::from_secs(2 * 86400)),
+        // The perf spellings.
+        ("250 msec", Duration::from_millis(250)),
+        ("2sec", Duration::from_secs(2)),
+    ] {
+        assert_eq!(parse_duration(text), Ok(expected), "{text:?}");
+    }
+
+    let expected = "expected one of ns, us, µs, ms, s, min, h, d";
+    for (text, err) in [
+        ("300", format!("missing unit, {expected}")),
+        ("300x", format!("unknown unit `x`, {expected}")),
+        ("300 days", format!("unknown unit `days`, {expected}")),
+        ("5MiB", format!("unknown unit `MiB`, {expected}")),
+        ("5m", "ambiguous unit `m`, use min or ms".to_owned()),
+        ("5M", "ambiguous unit `M`, use min or ms".to_owned()),
+        ("ms", "expected a number followed by a unit".to_owned()),
+        ("", "expected a number followed by a unit".to_owned()),
+        ("-5s", "expected a number followed by a unit".to_owned()),
+        ("1.2.3s", "expected a number followed by a unit".to_owned()),
+        ("1e3s", format!("unknown unit `e3s`, {expected}")),
+        (
+            "1000000000000000000000h",
+            "the duration is too long".to_owned(),
+        ),
+    ] {
+        assert_eq!(parse_duration(text), Err(err), "{text:?}");
+    }
+}
+
+#[test]
+fn sizes() {
+    for (text, expected) in [
+        ("100MiB", 100 << 20),
+        ("2GB", 2_000_000_000),
+        ("1.5KiB", 1536),
+        ("4 GiB", 4 << 30),
+        ("1TiB", 1 << 40),
+        ("3TB", 3_000_000_000_000),
+        ("512B", 512),
+        ("1kB", 1000),
+        // Whitespace and case.
+        ("  1 kb\t", 1000),
+        ("1KB", 1000),
+        ("100mib", 100 << 20),
+        ("0.5gb", 500_000_000),
+        ("2 b", 2),
+    ] {
+        assert_eq!(parse_size(text), Ok(expected), "{text:?}");
+    }
+
+    let expected = "expected one of B, kB, MB, GB, TB, KiB, MiB, GiB, TiB";
+    for (text, err) in [
+        ("100", format!("missing unit, {expected}")),
+        ("100 bytes", format!("unknown unit `bytes`, {expected}")),
+        ("100ms", format!("unknown unit `ms`, {expected}")),
+        ("100M", "ambiguous unit `M`, use MB or MiB".to_owned()),
+        ("100k", "ambiguous unit `k`, use kB or KiB".to_owned()),
+        ("1g", "ambiguous unit `g`, use GB or GiB".to_owned()),
+        ("2T", "ambiguous unit `T`, use TB or TiB".to_owned()),
+        ("MiB", "expected a number followed by a unit".to_owned()),
+        ("100000000000TB", "the size is too large".to_owned()),
+    ] {
+        assert_eq!(parse_size(text), Err(err), "{text:?}");
+    }
+}
+
+#[cfg(test)]
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct FieldsForTest {
+    #[serde(default, deserialize_with = "millis")]
+    timeout_ms: Duration,
+    #[serde(default, deserialize_with = "secs")]
+    delay_secs: Duration,
+    #[serde(default, deserialize_with = "option_mebibytes")]
+    memory_mb: Option<u64>,
+    #[serde(default)]
+    limits: Vec<Quantity>,
+}
+
+#[test]
+fn deserialize_fields() {
+    let fields: FieldsForTest = serde_json::from_str(
+        r#"{ "timeout-ms": 300, "delay-secs": 1.5, "memory-mb": 1024, "limits": [400, "250ms", "1GiB"] }"#,
+    )
+    .unwrap();
+    assert_eq!(fields.timeout_ms, Duration::from_millis(300));
+    assert_eq!(fields.delay_secs, Duration::from_millis(1500));
+    assert_eq!(fields.memory_mb, Some(1 << 30));
+    assert_eq!(
+        fields.limits,
+        [
+            Quantity::Number(400.0),
+            Quantity::Duration(Duration::from_millis(250)),
+            Quantity::Bytes(1 << 30),
+        ]
+    );
+
+    let fields: FieldsForTest = serde_json::from_str(
+        r#"{ "timeout-ms": "5min", "delay-secs": "250ms", "memory-mb": "2GB" }"#,
+    )
+    .unwrap();
+    assert_eq!(fields.timeout_ms, Duration::from_secs(300));
+    assert_eq!(fields.delay_secs, Duration::from_millis(250));
+    assert_eq!(fields.memory_mb, Some(2_000_000_000));
+
+    let err = |json: &str| {
+        serde_json::from_value::<FieldsForTest>(serde_json::from_str(json).unwrap())
+            .unwrap_err()
+            .to_string()
+    };
+    assert_eq!(
+        err(r#"{ "timeout-ms": "300x" }"#),
+        r#"invalid duration "300x": unknown unit `x`, expected one of ns, us, µs, ms, s, min, h, d"#
+    );
+    assert_eq!(
+        err(r#"{ "delay-secs": -1 }"#),
+        "invalid duration -1: expected a non-negative number"
+    );
+    assert_eq!(
+        err(r#"{ "memory-mb": true }"#),
+        "invalid size true: expected a non-negative number"
+    );
+    assert_eq!(
+        err(r#"{ "limits": ["5 parsecs"] }"#),
+        r#"invalid quantity "5 parsecs": expected a duration in one of ns, us, µs, ms, s, min, h, d, or a size in one of B, kB, MB, GB, TB, KiB, MiB, GiB, TiB"#
+    );
+
+    assert_eq!(
+        Quantity::Duration(Duration::from_secs(2)).in_unit("msec"),
+        Some(2000.0)
+    );
+    assert_eq!(Quantity::Bytes(1 << 20).in_unit("KiB"), Some(1024.0));
+    assert_eq!(Quantity::Bytes(1 << 20).in_unit("msec"), None);
+    assert_eq!(Quantity::Number(1.0).in_unit(""), None);
+}
+
+#[test]
+fn locate_errors() {
+    let config: Value = serde_json::from_str(
+        r#"{
+            "commands": { "compress": { "command": "./c", "interval-ms": "5m" } },
+            "fixtures": [{ "url": "x" }, { "size": -1 }],
+            "sync-start-timeout-ms": "10s"
+        }"#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        locate_error(
+            &config,
+            r#"invalid duration "5m": ambiguous unit `m`, use min or ms"#.to_owned()
+        ),
+        r#"commands.compress.interval-ms: invalid duration "5m": ambiguous unit `m`, use min or ms"#
+    );
+    assert_eq!(
+        locate_error(
+            &config,
+            "invalid size -1: expected a non-negative number".to_owned()
+        ),
+        "fixtures[1].size: invalid size -1: expected a non-negative number"
+    );
+    assert_eq!(
+        locate_error(&config, "missing field `commands`".to_owned()),
+        "missing field `commands`"
+    );
+}
@@ -0,0 +1,226 @@
+//! Checks that a command produces the same output in every repetition, for commands with
+//! `verify-output`. A command whose input changes under it, e.g. because another command of
+//! the suite overwrites it, only shows up as absurd variance otherwise.
+//!
+//! ```json
+//! { "command": "./compress corpus out.zst", "verify-output": { "file": "out.zst" } }
+//! ```
+//!
+//! The output is a `file`, hashed with SHA-256, or what a checksum `command` prints: the first
+//! word of its output, like the hash printed by `sha256sum out.zst`. It is hashed after the
+//! first and the last repetition, outside of the measured runs. perf repeats the command
+//! itself, so the first hash is of an extra, unmeasured run before the repetitions. Interleaved
+//! commands run one repetition at a time, and are hashed after every one.
+//!
+//! When the hashes differ, the command is flagged with `nondeterministic_output`, its rows are
+//! marked and the gate ignores them. With an `expected-hash`, the command also fails when any
+//! hash is a different one, so a speedup that produces wrong output is never celebrated.
+
+use std::path::PathBuf;
+use std::process::Command;
+
+use serde::Deserialize;
+
+use crate::bench::SingleBench;
+use crate::sha256;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct VerifyOutput {
+    /// A file the command writes.
+    #[serde(default)]
+    pub file: Option<PathBuf>,
+    /// A command printing a checksum of what the command wrote.
+    #[serde(default)]
+    pub command: Option<String>,
+    /// The hash the output must have, compared without regard to case.
+    #[serde(default)]
+    pub expected_hash: Option<String>,
+}
+
+impl VerifyOutput {
+    /// Check what the types of the config can't express.
+    pub fn validate(&self) -> Result<(), String> {
+        match (&self.file, &self.command) {
+            (Some(_), Some(_)) | (None, None) => {
+                return Err("exactly one of `file` and `command` must be given".to_owned())
+            }
+            (None, Some(command)) if command.trim().is_empty() => {
+                return Err("the `command` is empty".to_owned())
+            }
+            _ => {}
+        }
+        if self
+            .expected_hash
+            .as_ref()
+            .is_some_and(|hash| hash.trim().is_empty())
+        {
+            return Err("the `expected-hash` is empty".to_owned());
+        }
+        Ok(())
+    }
+
+    /// The hash of the output as it is now.
+    pub fn hash(&self) -> Result<String, String> {
+        if let Some(file) = &self.file {
+            return sha256::file_hex(file);
+        }
+        let command = self.command.as_deref().unwrap_or_default();
+        let mut args = command.split_whitespace();
+        let program = args.next().unwrap_or_default();
+        let output = Command::new(program)
+            .args(args)
+            .output()
+            .map_err(|e| format!("failed to run `{command}`: {e}"))?;
+        if !output.status.success() {
+            return Err(format!(
+                "`{command}` failed with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        String::from_utf8_lossy(&output.stdout)
+            .split_whitespace()
+            .next()
+            .map(str::to_owned)
+            .ok_or_else(|| format!("`{command}` printed no checksum"))
+    }
+}
+
+/// The hashes of the output of a command over its repetitions.
+#[derive(Debug, Default)]
+pub struct Hashes {
+    first: Option<String>,
+    differ: bool,
+    /// Why the output can't be verified or is wrong, the first time.
+    error: Option<String>,
+}
+
+impl Hashes {
+    /// Hash the output of the last repetition.
+    pub fn record(&mut self, verify: &VerifyOutput) {
+        match verify.hash() {
+            Ok(hash) => self.add(hash, verify.expected_hash.as_deref()),
+            Err(err) => {
+                self.error
+                    .get_or_insert(format!("failed to hash the output: {err}"));
+            }
+        }
+    }
+
+    fn add(&mut self, hash: String, expected: Option<&str>) {
+        if let Some(expected) = expected.map(str::trim) {
+            if !hash.eq_ignore_ascii_case(expected) {
+                self.error.get_or_insert(format!(
+                    "the output has the hash {hash} rather than the `expected-hash` {expected}"
+                ));
+            }
+        }
+        match &self.first {
+            Some(first) => self.differ |= *first != hash,
+            None => self.first = Some(hash),
+        }
+    }
+
+    /// Flag `bench` when the hashes differ, and fail it when the output is wrong, unless it
+    /// failed already.
+    pub fn apply(self, bench: &mut SingleBench) {
+        bench.nondeterministic_output = self.differ;
+        bench.error = bench.error.take().or(self.error);
+    }
+}
+
+#[test]
+fn compare_hashes() {
+    let mut hashes = Hashes::default();
+    hashes.add("abc".to_owned(), None);
+    hashes.add("abc".to_owned(), None);
+    assert!(!hashes.differ);
+    hashes.add("def".to_owned(), None);
+    hashes.add("abc".to_owned(), None);
+    assert!(hashes.differ);
+    assert_eq!(hashes.error, None);
+
+    // Any hash other than the expected one fails, and only the first is reported.
+    let mut hashes = Hashes::default();
+    hashes.add("abc".to_owned(), Some(" ABC "));
+    assert_eq!(hashes.error, None);
+    hashes.add("def".to_owned(), Some("abc"));
+    hashes.add("ghi".to_owned(), Some("abc"));
+    assert_eq!(
+        hashes.error.as_deref(),
+        Some("the output has the hash def rather than the `expected-hash` abc")
+    );
+}
+
+#[test]
+fn hash_outputs() {
+    let dir = crate::testkit::test_dir("verify-output");
+    let file = dir.join("out");
+    std::fs::write(&file, "hello\n").unwrap();
+
+    let by_file = VerifyOutput {
+        file: Some(file.clone()),
+        command: None,
+        expected_hash: None,
+    };
+    by_file.validate().unwrap();
+    assert_eq!(
+        by_file.hash().unwrap(),
+        "5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03"
+    );
+
+    // The first word of what the checksum command prints.
+    let by_command = VerifyOutput {
+        file: None,
+        command: Some(format!("echo  feed  {}", file.display())),
+        expected_hash: None,
+    };
+    by_command.validate().unwrap();
+    assert_eq!(by_command.hash().unwrap(), "feed");
+    let failing = VerifyOutput {
+        command: Some("false".to_owned()),
+        ..by_command.clone()
+    };
+    assert!(failing
+        .hash()
+        .unwrap_err()
+        .starts_with("`false` failed with"));
+
+    let mut hashes = Hashes::default();
+    hashes.record(&VerifyOutput {
+        file: Some(dir.join("missing")),
+        ..by_file.clone()
+    });
+    assert!(hashes
+        .error
+        .unwrap()
+        .starts_with("failed to hash the output: failed to open "));
+
+    for (verify, error) in [
+        (
+            VerifyOutput {
+                command: Some("sha256sum out".to_owned()),
+                ..by_file.clone()
+            },
+            "exactly one of `file` and `command` must be given",
+        ),
+        (
+            VerifyOutput {
+                command: Some(" ".to_owned()),
+                ..by_command.clone()
+            },
+            "the `command` is empty",
+        ),
+        (
+            VerifyOutput {
+                expected_hash: Some(String::new()),
+                ..by_file.clone()
+            },
+            "the `expected-hash` is empty",
+        ),
+    ] {
+        assert_eq!(verify.validate().unwrap_err(), error);
+    }
+    assert!(serde_json::from_str::<VerifyOutput>(r#"{ "path": "out" }"#).is_err());
+}
@@ -0,0 +1,480 @@
+//! `benchmarker verify-report <run-report> <config>... <results>`: check that a run report was
+//! produced by the config and the results its stamp names, see [`crate::stamp`], so downstream
+//! automation can tell which measurement a verdict belongs to.
+//!
+//! The hash of the config files is compared with the one of the report, and the entries of the
+//! run and of its baseline are looked up in `<results>` by their commits and hashes, e.g. the
+//! results file after a run of the main branch, or the previous results with the printed
+//! results of the run appended. The gate is then evaluated again on those entries and compared
+//! with the verdict of the report, by the rows that failed, had their failures suppressed or
+//! accepted.
+//!
+//! The gate is only evaluated versus the parent commit: it isn't evaluated again when it
+//! compares against the rolling baseline, or when the run changed its baseline, by
+//! substituting it for an anomaly or by backfilling counters without persisting them. The
+//! hashes are still checked. Exits with 1 when anything doesn't match.
+
+use std::fmt::Write as _;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+use crate::compare::Comparisons;
+use crate::gate::GateBaseline;
+use crate::sanitize::Sanitizer;
+use crate::stamp::{self, EntryKey, Stamp};
+use crate::{counter_bounds, history, BenchData, Config};
+
+/// The entry of `commit_id` with the hash `sha256` in the results at `path`, or the hashes of
+/// the entries of that commit there are.
+fn find_entry(
+    path: &Path,
+    commit_id: &str,
+    sha256: &str,
+) -> Result<Result<Vec<u8>, Vec<String>>, String> {
+    let mut found = None;
+    let mut others = vec![];
+    let mut error = None;
+    history::for_each_line(path, |_, line| {
+        match serde_json::from_slice::<EntryKey>(line) {
+            Ok(key) if key.commit_id() == commit_id => match stamp::line_sha256(line) {
+                Ok(hash) if hash == sha256 => {
+                    found = Some(line.to_vec());
+                    return ControlFlow::Break(());
+                }
+                Ok(hash) => others.push(hash),
+                Err(err) => error = Some(err),
+            },
+            _ => {}
+        }
+        ControlFlow::Continue(())
+    })?;
+    if let Some(err) = error.filter(|_| found.is_none()) {
+        return Err(err);
+    }
+    Ok(found.ok_or(others))
+}
+
+/// The rows of the verdict, by list, as `<table> / <row>`, from its JSON.
+fn verdict_rows(gate: &Value) -> Vec<(&'static str, Vec<String>)> {
+    ["failures", "variance_failures", "suppressed", "accepted"]
+        .into_iter()
+        .map(|list| {
+            let rows = gate[list]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|failure| {
+                    format!(
+                        "{} / {}",
+                        failure["table"].as_str().unwrap_or_default(),
+                        failure["row"]["name"].as_str().unwrap_or_default()
+                    )
+                })
+                .collect();
+            (list, rows)
+        })
+        .collect()
+}
+
+/// What the report says, and whether it all matched.
+#[derive(Debug, Default)]
+struct Verification {
+    out: String,
+    matched: bool,
+}
+
+impl Verification {
+    fn check(&mut self, what: &str, result: Result<String, String>) {
+        match result {
+            Ok(line) => writeln!(self.out, "{what}: {line}").unwrap(),
+            Err(line) => {
+                writeln!(self.out, "{what}: {line}").unwrap();
+                self.matched = false;
+            }
+        }
+    }
+}
+
+/// The entry stamped with `sha256`, and the line about it.
+fn check_entry(results: &Path, commit_id: &str, sha256: &str) -> Result<(Vec<u8>, String), String> {
+    match find_entry(results, commit_id, sha256)? {
+        Ok(line) => Ok((line, format!("matches {sha256}"))),
+        Err(others) if others.is_empty() => {
+            Err(format!("no entry of {commit_id} in {}", results.display()))
+        }
+        Err(others) => Err(format!(
+            "doesn't match, the report has {sha256}, the entries of {commit_id} have {}",
+            others.join(", ")
+        )),
+    }
+}
+
+pub fn verify(
+    report_path: &Path,
+    config_paths: &[PathBuf],
+    results: &Path,
+) -> Result<(String, bool), String> {
+    let report = std::fs::read(report_path)
+        .map_err(|e| format!("failed to read {}: {e}", report_path.display()))?;
+    let report = serde_json::from_slice::<Value>(&report)
+        .map_err(|e| format!("failed to parse {}: {e}", report_path.display()))?;
+    let stamp = serde_json::from_value::<Stamp>(report["stamp"].clone())
+        .map_err(|e| format!("{} has no valid stamp: {e}", report_path.display()))?;
+    let mut verification = Verification {
+        out: format!("run {}\n", stamp.attempt()),
+        matched: true,
+    };
+
+    let mut config = Config::load(config_paths)?;
+    config
+        .validate()
+        .map_err(|err| format!("invalid config: {err}"))?;
+    verification.check(
+        "config",
+        match &stamp.config_sha256 {
+            Some(hash) if *hash == config.sha256 => Ok(format!("matches {hash}")),
+            Some(hash) => Err(format!(
+                "doesn't match, the report has {hash}, the config has {}",
+                config.sha256
+            )),
+            None => Err("not in the report".to_owned()),
+        },
+    );
+
+    let (Some(commit_id), Some(results_sha256)) = (&stamp.commit, &stamp.results_sha256) else {
+        verification.check("results", Err("not in the report".to_owned()));
+        return Ok((verification.out, false));
+    };
+    let current = check_entry(results, commit_id, results_sha256);
+    let current = match current {
+        Ok((line, message)) => {
+            verification.check("results", Ok(message));
+            Some(line)
+        }
+        Err(message) => {
+            verification.check("results", Err(message));
+            None
+        }
+    };
+    let baseline_commit = report["baseline"]["commit"].as_str();
+    let baseline = match (baseline_commit, &stamp.baseline_sha256) {
+        (None, None) => {
+            verification.check("baseline", Ok("none".to_owned()));
+            Some(None)
+        }
+        (Some(commit), Some(hash)) => match check_entry(results, commit, hash) {
+            Ok((line, message)) => {
+                verification.check("baseline", Ok(message));
+                Some(Some(line))
+            }
+            Err(message) => {
+                verification.check("baseline", Err(message));
+                None
+            }
+        },
+        _ => {
+            verification.check(
+                "baseline",
+                Err("the commit and the hash of the report don't go together".to_owned()),
+            );
+            None
+        }
+    };
+
+    let (Some(current), Some(baseline)) = (current, baseline) else {
+        verification.check(
+            "gate",
+            Err("not evaluated again, without the entries".to_owned()),
+        );
+        return Ok((verification.out, false));
+    };
+    if config.gate.is_none() {
+        let gate = if report["gate"].is_null() {
+            Ok("not configured".to_owned())
+        } else {
+            Err("the report has a verdict, but the config has no gate".to_owned())
+        };
+        verification.check("gate", gate);
+        return Ok((verification.out, verification.matched));
+    }
+    let rolling = config
+        .gate
+        .as_ref()
+        .is_some_and(|gate| gate.baseline != GateBaseline::Parent)
+        && config.rolling_baseline.is_some();
+    if rolling || stamp.baseline_changed {
+        let reason = if rolling {
+            "it compares against the rolling baseline"
+        } else {
+            "the run changed its baseline"
+        };
+        verification.check("gate", Ok(format!("not evaluated again, {reason}")));
+        return Ok((verification.out, verification.matched));
+    }
+
+    config.retain_tagged(&stamp.only_tags, &stamp.skip_tags);
+    let sanitizer = match config.sanitize.take() {
+        Some(sanitize) => Sanitizer::new(sanitize, None),
+        None => Sanitizer::default(),
+    };
+    let parse = |line: &[u8]| {
+        let mut data = serde_json::from_slice::<BenchData>(line)
+            .map_err(|e| format!("failed to parse the entry: {e}"))?;
+        sanitizer.restore(&mut data, &config.commands);
+        Ok::<_, String>(data)
+    };
+    let current = parse(&current)?;
+    let baseline = match baseline {
+        Some(line) => {
+            let mut data = parse(&line)?;
+            config.counter_renames.canonicalize(&mut data);
+            counter_bounds::drop_from(&config.counter_bounds, &mut data);
+            Some(data)
+        }
+        None => None,
+    };
+    config.skip_groups(&current.skipped_groups);
+    config.retain_samples(&current.samples);
+    config.strict_sampling = stamp.strict_sampling;
+    let comparisons = Comparisons::collect(&config, &current, baseline.as_ref());
+    let verdict = config
+        .evaluate_gate(&comparisons, &current.exemptions)
+        .unwrap();
+
+    let reported = verdict_rows(&report["gate"]);
+    let recomputed = verdict_rows(&sanitizer.to_value(&verdict));
+    let describe = |rows: &[(&str, Vec<String>)]| {
+        let failures = rows[0].1.len() + rows[1].1.len();
+        if failures == 0 {
+            "passed".to_owned()
+        } else {
+            format!("failed with {failures} failures")
+        }
+    };
+    let gate = if reported == recomputed {
+        Ok(format!("{}, as reported", describe(&recomputed)))
+    } else {
+        let mut message = format!(
+            "doesn't match, the report {}, evaluated again it {}",
+            describe(&reported),
+            describe(&recomputed)
+        );
+        for ((list, reported), (_, recomputed)) in reported.iter().zip(&recomputed) {
+            if reported != recomputed {
+                write!(
+                    message,
+                    "\n  {list}: reported [{}], evaluated again [{}]",
+                    reported.join(", "),
+                    recomputed.join(", ")
+                )
+                .unwrap();
+            }
+        }
+        Err(message)
+    };
+    verification.check("gate", gate);
+    Ok((verification.out, verification.matched))
+}
+
+pub fn run(args: impl IntoIterator<Item = String>) -> Result<(String, bool), String> {
+    const USAGE: &str = "expected the arguments verify-report <run-report> <config>... <results>";
+
+    let args = args.into_iter().map(PathBuf::from).collect::<Vec<_>>();
+    let [report, config_paths @ .., results] = args.as_slice() else {
+        return Err(USAGE.to_owned());
+    };
+    if config_paths.is_empty() {
+        return Err(USAGE.to_owned());
+    }
+    verify(report, config_paths, results)
+}
+
+#[cfg(test)]
+const CONFIG_FOR_TEST: &str = r#"{
+    "commands": { "work": ["./work a", "./work b"] },
+    "gate": { "max-regression-percent": 5 },
+    "render-versus-self": {},
+    "render-versus-other": {
+        "work": { "measure": "cycles", "command": "work", "rows": { "a": 0, "b": 1 } }
+    }
+}"#;
+
+/// The results of a baseline and of a run after it, where `./work a` regressed by 20%, with
+/// the report of the run as the benchmarker writes it. Returns the paths of the config, the
+/// results and the report.
+#[cfg(test)]
+fn run_for_test(name: &str) -> (PathBuf, PathBuf, PathBuf) {
+    use crate::report::{Baseline, RunReport};
+    use crate::testkit::BenchDataBuilder;
+
+    let dir = crate::testkit::test_dir(name);
+    let config_path = dir.join("bench.json");
+    std::fs::write(&config_path, CONFIG_FOR_TEST).unwrap();
+    let config = Config::load(std::slice::from_ref(&config_path)).unwrap();
+
+    let data = |commit: &str, a: f64| {
+        BenchDataBuilder::new(commit)
+            .group("work", |g| {
+                g.bench(["./work", "a"], |b| b.counter("cycles", a, 1.0, 20, ""))
+                    .bench(["./work", "b"], |b| {
+                        b.counter("cycles", 1000.0, 1.0, 20, "")
+                    })
+            })
+            .build()
+    };
+    let baseline = data(&"1".repeat(40), 1000.0);
+    let current = data(&"2".repeat(40), 1200.0);
+    let sanitizer = Sanitizer::default();
+    let lines = [sanitizer.to_json(&baseline), sanitizer.to_json(&current)];
+    let results = dir.join("results.json");
+    std::fs::write(&results, lines.join("\n") + "\n").unwrap();
+
+    let comparisons = Comparisons::collect(&config, &current, Some(&baseline));
+    let report = RunReport {
+        baseline: Baseline {
+            commit: Some(baseline.commit_hash.clone()),
+            ..Baseline::default()
+        },
+        gate: config.evaluate_gate(&comparisons, &current.exemptions),
+        stamp: Stamp {
+            run_id: Some("1234567890".to_owned()),
+            run_attempt: Some("2".to_owned()),
+            commit: Some(current.commit_id()),
+            config_sha256: Some(config.sha256.clone()),
+            baseline_sha256: Some(stamp::line_sha256(lines[0].as_bytes()).unwrap()),
+            results_sha256: Some(stamp::line_sha256(lines[1].as_bytes()).unwrap()),
+            ..Stamp::default()
+        },
+        ..RunReport::default()
+    };
+    assert_eq!(report.gate.as_ref().unwrap().failures.len(), 1);
+    let report_path = dir.join("run-report.json");
+    report.write(&report_path, &sanitizer).unwrap();
+    (config_path, results, report_path)
+}
+
+#[cfg(test)]
+fn edit_json(path: &Path, line: usize, edit: impl FnOnce(&mut Value)) {
+    let text = std::fs::read_to_string(path).unwrap();
+    let mut lines = text.lines().map(str::to_owned).collect::<Vec<_>>();
+    let mut value = serde_json::from_str::<Value>(&lines[line]).unwrap();
+    edit(&mut value);
+    lines[line] = value.to_string();
+    std::fs::write(path, lines.join("\n") + "\n").unwrap();
+}
+
+#[test]
+fn verify_matching_report() {
+    let (config, results, report) = run_for_test("verify-report-matching");
+    let (out, matched) = verify(&report, std::slice::from_ref(&config), &results).unwrap();
+    let stamp =
+        serde_json::from_slice::<Value>(&std::fs::read(&report).unwrap()).unwrap()["stamp"].clone();
+    assert_eq!(
+        out,
+        format!(
+            "run 1234567890/2\n\
+             config: matches {}\n\
+             results: matches {}\n\
+             baseline: matches {}\n\
+             gate: failed with 1 failures, as reported\n",
+            stamp["config_sha256"].as_str().unwrap(),
+            stamp["results_sha256"].as_str().unwrap(),
+            stamp["baseline_sha256"].as_str().unwrap()
+        )
+    );
+    assert!(matched);
+
+    // Reformatting the results, like with the keys sorted, doesn't matter.
+    let text = std::fs::read_to_string(&results).unwrap();
+    let reformatted = text
+        .lines()
+        .map(|line| {
+            let value = serde_json::from_str::<Value>(line).unwrap();
+            stamp::canonicalize(&value).to_string() + "\n"
+        })
+        .collect::<String>();
+    assert_ne!(reformatted, text);
+    std::fs::write(&results, reformatted).unwrap();
+    let (_, matched) = verify(&report, std::slice::from_ref(&config), &results).unwrap();
+    assert!(matched);
+
+    let err = run(["run-report.json".to_owned(), "results.json".to_owned()]).unwrap_err();
+    assert_eq!(
+        err,
+        "expected the arguments verify-report <run-report> <config>... <results>"
+    );
+}
+
+#[test]
+fn verify_tampered_report() {
+    // Other results.
+    let (config, results, report) = run_for_test("verify-report-tampered-results");
+    edit_json(&results, 1, |entry| {
+        entry["bench_groups"]["work"][0]["counters"]["cycles"]["value"] = 1000.0.into();
+    });
+    let (out, matched) = verify(&report, std::slice::from_ref(&config), &results).unwrap();
+    assert!(!matched);
+    let lines = out.lines().collect::<Vec<_>>();
+    assert!(
+        lines[2].starts_with("results: doesn't match, the report has "),
+        "{out}"
+    );
+    assert!(lines[3].starts_with("baseline: matches "), "{out}");
+    assert_eq!(lines[4], "gate: not evaluated again, without the entries");
+
+    // Another baseline.
+    let (config, results, report) = run_for_test("verify-report-tampered-baseline");
+    let text = std::fs::read_to_string(&results).unwrap();
+    std::fs::write(&results, text.lines().nth(1).unwrap()).unwrap();
+    let (out, matched) = verify(&report, std::slice::from_ref(&config), &results).unwrap();
+    assert!(!matched);
+    assert!(
+        out.contains(&format!(
+            "baseline: no entry of {} in {}\n",
+            "1".repeat(40),
+            results.display()
+        )),
+        "{out}"
+    );
+
+    // Another config, which doesn't fail the gate either.
+    let (config, results, report) = run_for_test("verify-report-tampered-config");
+    std::fs::write(&config, CONFIG_FOR_TEST.replace("5 }", "50 }")).unwrap();
+    let (out, matched) = verify(&report, std::slice::from_ref(&config), &results).unwrap();
+    assert!(!matched);
+    let lines = out.lines().collect::<Vec<_>>();
+    assert!(
+        lines[1].starts_with("config: doesn't match, the report has "),
+        "{out}"
+    );
+    assert_eq!(
+        lines[4..],
+        [
+            "gate: doesn't match, the report failed with 1 failures, evaluated again it passed",
+            "  failures: reported [work / a], evaluated again []"
+        ]
+    );
+
+    // Another verdict.
+    let (config, results, report) = run_for_test("verify-report-tampered-verdict");
+    let mut value = serde_json::from_slice::<Value>(&std::fs::read(&report).unwrap()).unwrap();
+    value["gate"]["failures"] = Value::Array(vec![]);
+    std::fs::write(&report, value.to_string()).unwrap();
+    let (out, matched) = verify(&report, std::slice::from_ref(&config), &results).unwrap();
+    assert!(!matched);
+    assert!(
+        out.ends_with(
+            "gate: doesn't match, the report passed, evaluated again it failed with 1 failures\n  \
+             failures: reported [], evaluated again [work / a]\n"
+        ),
+        "{out}"
+    );
+
+    // A report without a stamp.
+    value.as_object_mut().unwrap().remove("stamp");
+    std::fs::write(&report, value.to_string()).unwrap();
+    let err = verify(&report, std::slice::from_ref(&config), &results).unwrap_err();
+    assert!(err.contains("has no valid stamp"), "{err}");
+}
@@ -0,0 +1,510 @@
+//! A watchdog for commands that hang without failing, like perf stuck in uninterruptible sleep
+//! on a dying NFS mount (Linux only). With
+//!
+//! ```json
+//! "watchdog": { "window-secs": 60, "cpu-epsilon-ms": 10 }
+//! ```
+//!
+//! a thread samples the processes started by the benchmarker while a command is measured,
+//! every `interval-ms`, 1 second by default. When their CPU time, from `/proc/<pid>/stat`,
+//! hasn't advanced by more than `cpu-epsilon-ms` and they haven't read or written anything,
+//! from `/proc/<pid>/io`, for `window-secs`, the watchdog logs the process tree with the state
+//! and the wait channel of every process, and kills the tree. The command then fails as hung,
+//! with the tree attached, and the suite goes on with the next one.
+//!
+//! Benchmarks that legitimately sleep, like those of timers, opt out with `"sleeps": true` in
+//! their options. A process in uninterruptible sleep only dies once the sleep ends, so the
+//! watchdog can only recover from a hang that ends eventually, but it still tells why.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+use crate::bench::Measurement;
+use crate::units;
+
+const PROC: &str = "/proc";
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct WatchdogConfig {
+    /// How long the processes of a command may make no progress.
+    #[serde(
+        rename = "window-secs",
+        default = "default_window",
+        deserialize_with = "units::secs"
+    )]
+    pub window: Duration,
+    /// The CPU time that counts as progress, to ignore the odd wakeup of a hung process.
+    #[serde(
+        rename = "cpu-epsilon-ms",
+        default = "default_cpu_epsilon",
+        deserialize_with = "units::millis"
+    )]
+    pub cpu_epsilon: Duration,
+    /// How often to sample the processes.
+    #[serde(
+        rename = "interval-ms",
+        default = "default_interval",
+        deserialize_with = "units::millis"
+    )]
+    pub interval: Duration,
+}
+
+fn default_window() -> Duration {
+    Duration::from_secs(60)
+}
+
+fn default_cpu_epsilon() -> Duration {
+    Duration::from_millis(10)
+}
+
+fn default_interval() -> Duration {
+    Duration::from_secs(1)
+}
+
+/// A process of the tree, as sampled from `/proc`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Process {
+    pub pid: i32,
+    pub ppid: i32,
+    pub comm: String,
+    pub state: char,
+    /// The CPU time of the process and of its children it waited for.
+    pub cpu: Duration,
+    /// The bytes read and written, when `/proc/<pid>/io` is readable.
+    pub io_bytes: Option<u64>,
+    /// What the process sleeps on, if anything.
+    pub wchan: Option<String>,
+}
+
+/// The fields of a `/proc/<pid>/stat` line the watchdog uses, the CPU times in clock ticks.
+#[derive(Debug, PartialEq)]
+struct Stat<'a> {
+    comm: &'a str,
+    state: char,
+    ppid: i32,
+    ticks: u64,
+}
+
+/// The name is in parentheses, and may itself contain spaces and parentheses.
+fn parse_stat(stat: &str) -> Option<Stat<'_>> {
+    let (_, rest) = stat.split_once(" (")?;
+    let (comm, fields) = rest.rsplit_once(") ")?;
+    let fields = fields.split(' ').collect::<Vec<_>>();
+    // utime, stime, cutime and cstime, after the state and 10 other fields.
+    let ticks = fields
+        .get(11..15)?
+        .iter()
+        .map(|field| field.parse::<u64>().ok())
+        .sum::<Option<u64>>()?;
+    Some(Stat {
+        comm,
+        state: fields.first()?.chars().next()?,
+        ppid: fields.get(1)?.parse().ok()?,
+        ticks,
+    })
+}
+
+/// `read_bytes` plus `write_bytes` of a `/proc/<pid>/io`.
+fn parse_io(io: &str) -> Option<u64> {
+    io.lines()
+        .filter_map(|line| line.split_once(": "))
+        .filter(|(name, _)| *name == "read_bytes" || *name == "write_bytes")
+        .map(|(_, bytes)| bytes.trim().parse::<u64>().ok())
+        .sum()
+}
+
+fn clock_ticks_per_second() -> u64 {
+    // SAFETY: sysconf has no preconditions.
+    match unsafe { libc::sysconf(libc::_SC_CLK_TCK) } {
+        ticks if ticks > 0 => ticks as u64,
+        _ => 100,
+    }
+}
+
+/// The descendants of `root` in the `/proc` at `proc`, every process followed by its own.
+pub fn tree(proc: &Path, root: i32) -> Vec<Process> {
+    let ticks_per_second = clock_ticks_per_second();
+    let mut children = BTreeMap::<i32, Vec<Process>>::new();
+    for entry in fs::read_dir(proc).into_iter().flatten().flatten() {
+        let Some(pid) = entry.file_name().to_str().and_then(|pid| pid.parse().ok()) else {
+            continue;
+        };
+        let dir = entry.path();
+        let Ok(stat) = fs::read_to_string(dir.join("stat")) else {
+            continue;
+        };
+        let Some(stat) = parse_stat(&stat) else {
+            continue;
+        };
+        let wchan = fs::read_to_string(dir.join("wchan"))
+            .ok()
+            .map(|wchan| wchan.trim().to_owned())
+            .filter(|wchan| !wchan.is_empty() && wchan != "0");
+        children.entry(stat.ppid).or_default().push(Process {
+            pid,
+            ppid: stat.ppid,
+            comm: stat.comm.to_owned(),
+            state: stat.state,
+            cpu: Duration::from_secs_f64(stat.ticks as f64 / ticks_per_second as f64),
+            io_bytes: fs::read_to_string(dir.join("io"))
+                .ok()
+                .and_then(|io| parse_io(&io)),
+            wchan,
+        });
+    }
+
+    fn add(children: &mut BTreeMap<i32, Vec<Process>>, pid: i32, tree: &mut Vec<Process>) {
+        for process in children.remove(&pid).unwrap_or_default() {
+            let pid = process.pid;
+            tree.push(process);
+            add(children, pid, tree);
+        }
+    }
+    let mut tree = vec![];
+    add(&mut children, root, &mut tree);
+    tree
+}
+
+/// The CPU time and the bytes read and written by the tree between two samples: the progress
+/// of the processes in both, and all of those that started in between.
+fn progress(before: &[Process], after: &[Process]) -> (Duration, u64) {
+    let before = before
+        .iter()
+        .map(|process| (process.pid, process))
+        .collect::<BTreeMap<_, _>>();
+    after
+        .iter()
+        .fold((Duration::ZERO, 0), |(cpu, io), process| {
+            let previous = before.get(&process.pid);
+            let previous_io = previous.and_then(|previous| previous.io_bytes);
+            (
+                cpu + process
+                    .cpu
+                    .saturating_sub(previous.map_or(Duration::ZERO, |previous| previous.cpu)),
+                io + process
+                    .io_bytes
+                    .unwrap_or(0)
+                    .saturating_sub(previous_io.unwrap_or(0)),
+            )
+        })
+}
+
+/// Decides from the samples of the tree whether it hangs.
+pub struct Detector {
+    window: Duration,
+    cpu_epsilon: Duration,
+    previous: Vec<Process>,
+    /// The progress since `since`, the last sample with enough of it.
+    cpu: Duration,
+    io: u64,
+    since: Duration,
+}
+
+impl Detector {
+    pub fn new(config: &WatchdogConfig) -> Self {
+        Detector {
+            window: config.window,
+            cpu_epsilon: config.cpu_epsilon,
+            previous: vec![],
+            cpu: Duration::ZERO,
+            io: 0,
+            since: Duration::ZERO,
+        }
+    }
+
+    /// Take the sample of the tree at `at`, returning whether it made no progress for the
+    /// window. A new process is progress, and without any process there is nothing to hang.
+    pub fn observe(&mut self, at: Duration, tree: &[Process]) -> bool {
+        let (cpu, io) = progress(&self.previous, tree);
+        let started = tree.iter().any(|process| {
+            !self
+                .previous
+                .iter()
+                .any(|previous| previous.pid == process.pid)
+        });
+        self.previous = tree.to_vec();
+        self.cpu += cpu;
+        self.io += io;
+        if tree.is_empty() || started || self.cpu > self.cpu_epsilon || self.io > 0 {
+            self.cpu = Duration::ZERO;
+            self.io = 0;
+            self.since = at;
+            return false;
+        }
+        at.saturating_sub(self.since) >= self.window
+    }
+}
+
+/// The tree, a process per line, indented below its parent, with its state and wait channel.
+pub fn diagnose(tree: &[Process]) -> String {
+    let mut depths = BTreeMap::new();
+    let mut diagnosis = String::new();
+    for process in tree {
+        let depth = depths.get(&process.ppid).map_or(0, |depth| depth + 1);
+        depths.insert(process.pid, depth);
+        write!(
+            diagnosis,
+            "{:indent$}{} `{}` ({}",
+            "",
+            process.pid,
+            process.comm,
+            process.state,
+            indent = depth * 2,
+        )
+        .unwrap();
+        if let Some(wchan) = &process.wchan {
+            write!(diagnosis, ", in {wchan}").unwrap();
+        }
+        writeln!(diagnosis, ", {:.2}s CPU)", process.cpu.as_secs_f64()).unwrap();
+    }
+    diagnosis
+}
+
+fn kill(tree: &[Process]) {
+    for process in tree {
+        // SAFETY: kill has no preconditions. A pid reused since the sample is not a descendant
+        // any more, but the window for that is tiny.
+        unsafe { libc::kill(process.pid, libc::SIGKILL) };
+    }
+}
+
+/// Samples the descendants of a process in a background thread until it is stopped, and kills
+/// them when they hang.
+pub struct Watchdog {
+    stop: Sender<()>,
+    thread: JoinHandle<Option<String>>,
+}
+
+impl Watchdog {
+    /// Start watching the descendants of `root` in the `/proc` at `proc`, which run `command`.
+    pub fn start(config: &WatchdogConfig, proc: &Path, root: i32, command: String) -> Self {
+        let config = config.clone();
+        let proc = proc.to_owned();
+        let (stop, stopped) = mpsc::channel();
+        let thread = std::thread::spawn(move || {
+            let start = Instant::now();
+            let mut detector = Detector::new(&config);
+            loop {
+                let tree = tree(&proc, root);
+                if detector.observe(start.elapsed(), &tree) {
+                    let diagnosis = diagnose(&tree);
+                    eprintln!(
+                        "warning: `{command}` made no progress in {:?}, killing it:\n{diagnosis}",
+                        config.window
+                    );
+                    kill_all(&proc, root, &tree, config.interval);
+                    return Some(diagnosis);
+                }
+                // Also stops when the watchdog is dropped without being stopped.
+                match stopped.recv_timeout(config.interval) {
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Ok(()) | Err(RecvTimeoutError::Disconnected) => return None,
+                }
+            }
+        });
+        Watchdog { stop, thread }
+    }
+
+    /// Stop watching, returning the diagnosis when the command hung and was killed.
+    pub fn stop(self) -> Option<String> {
+        let _ = self.stop.send(());
+        self.thread.join().unwrap()
+    }
+}
+
+/// Kill the tree, and whatever it started since the sample, a few times until it's gone.
+fn kill_all(proc: &Path, root: i32, tree: &[Process], interval: Duration) {
+    kill(tree);
+    for _ in 0..10 {
+        let rest = self::tree(proc, root);
+        if rest.is_empty() {
+            return;
+        }
+        kill(&rest);
+        std::thread::sleep(interval.min(Duration::from_millis(100)));
+    }
+}
+
+/// Whether a measurement finished, or hung and was killed, with why.
+pub enum Watched {
+    Finished(Measurement),
+    Hung(String),
+}
+
+/// Run `measure` of `command` under the watchdog, if it has one. What a hung measurement
+/// returns, like the signal that killed it, doesn't matter.
+pub fn watch(
+    config: Option<&WatchdogConfig>,
+    command: &[String],
+    measure: impl FnOnce() -> Result<Measurement, String>,
+) -> Result<Watched, String> {
+    let Some(config) = config else {
+        return measure().map(Watched::Finished);
+    };
+    let watchdog = Watchdog::start(
+        config,
+        Path::new(PROC),
+        std::process::id() as i32,
+        command.join(" "),
+    );
+    let measured = measure();
+    match watchdog.stop() {
+        Some(diagnosis) => Ok(Watched::Hung(format!(
+            "hung: no CPU or I/O progress in {:?}, killed\n=== process tree ===\n{}",
+            config.window,
+            diagnosis.trim_end()
+        ))),
+        None => measured.map(Watched::Finished),
+    }
+}
+
+#[cfg(test)]
+fn config(window: Duration) -> WatchdogConfig {
+    WatchdogConfig {
+        window,
+        cpu_epsilon: Duration::from_millis(10),
+        interval: Duration::from_millis(20),
+    }
+}
+
+#[cfg(test)]
+fn process(pid: i32, ppid: i32, cpu_ms: u64, io_bytes: u64) -> Process {
+    Process {
+        pid,
+        ppid,
+        comm: format!("p{pid}"),
+        state: 'S',
+        cpu: Duration::from_millis(cpu_ms),
+        io_bytes: Some(io_bytes),
+        wchan: None,
+    }
+}
+
+#[test]
+fn parse_proc_files() {
+    let stat =
+        "4242 (perf (x) y) D 4241 4242 4242 0 -1 4194560 100 0 0 0 12 3 4 5 20 0 1 0 100 0 0";
+    assert_eq!(
+        parse_stat(stat),
+        Some(Stat {
+            comm: "perf (x) y",
+            state: 'D',
+            ppid: 4241,
+            ticks: 24,
+        })
+    );
+    assert_eq!(parse_stat("4242 (perf) D 4241"), None);
+
+    let io = "rchar: 100\nwchar: 50\nsyscr: 3\nsyscw: 2\nread_bytes: 4096\nwrite_bytes: 8192\ncancelled_write_bytes: 0\n";
+    assert_eq!(parse_io(io), Some(12288));
+    assert_eq!(parse_io("rchar: 100\n"), Some(0));
+}
+
+#[test]
+fn process_tree() {
+    let proc = crate::testkit::test_dir("watchdog-proc");
+    let add = |pid: i32, ppid: i32, state: char, wchan: &str| {
+        let dir = proc.join(pid.to_string());
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("stat"),
+            format!("{pid} (p{pid}) {state} {ppid} 0 0 0 -1 0 0 0 0 0 100 0 0 0 20 0 1 0 0 0 0"),
+        )
+        .unwrap();
+        fs::write(dir.join("io"), "read_bytes: 10\nwrite_bytes: 0\n").unwrap();
+        fs::write(dir.join("wchan"), wchan).unwrap();
+    };
+    add(1, 0, 'S', "0");
+    add(10, 1, 'S', "do_wait");
+    add(11, 10, 'D', "nfs_wait_bit_killable");
+    add(12, 11, 'Z', "");
+    add(13, 10, 'S', "0");
+    add(20, 1, 'R', "");
+    fs::create_dir_all(proc.join("self")).unwrap();
+
+    let tree = tree(&proc, 10);
+    assert_eq!(
+        tree.iter().map(|process| process.pid).collect::<Vec<_>>(),
+        [11, 12, 13]
+    );
+    assert_eq!(tree[0].io_bytes, Some(10));
+    assert_eq!(
+        tree[0].cpu,
+        Duration::from_secs_f64(100.0 / clock_ticks_per_second() as f64)
+    );
+    assert_eq!(
+        diagnose(&tree),
+        format!(
+            "11 `p11` (D, in nfs_wait_bit_killable, {cpu:.2}s CPU)\n  12 `p12` (Z, {cpu:.2}s CPU)\n13 `p13` (S, {cpu:.2}s CPU)\n",
+            cpu = tree[0].cpu.as_secs_f64()
+        )
+    );
+}
+
+#[test]
+fn detect_hangs() {
+    let window = Duration::from_secs(60);
+    let at = Duration::from_secs;
+    let mut detector = Detector::new(&config(window));
+
+    // Busy: CPU time advances in every sample.
+    for second in 0..120 {
+        let tree = [process(1, 0, second * 100, 0)];
+        assert!(!detector.observe(at(second), &tree));
+    }
+
+    // Idle, but for the odd wakeup.
+    let mut detector = Detector::new(&config(window));
+    assert!(!detector.observe(at(0), &[process(1, 0, 500, 0)]));
+    for second in 1..60 {
+        let tree = [process(1, 0, 500 + second / 20, 0)];
+        assert!(!detector.observe(at(second), &tree), "{second}");
+    }
+    assert!(detector.observe(at(60), &[process(1, 0, 503, 0)]));
+
+    // I/O, a new process and no process at all are progress.
+    let mut detector = Detector::new(&config(window));
+    assert!(!detector.observe(at(0), &[process(1, 0, 0, 0)]));
+    assert!(!detector.observe(at(59), &[process(1, 0, 0, 1)]));
+    assert!(!detector.observe(at(118), &[process(1, 0, 0, 1)]));
+    assert!(!detector.observe(at(119), &[process(1, 0, 0, 1), process(2, 1, 0, 0)]));
+    assert!(!detector.observe(at(178), &[process(1, 0, 0, 1)]));
+    assert!(!detector.observe(at(179), &[]));
+    assert!(!detector.observe(at(200), &[process(1, 0, 0, 1)]));
+    assert!(detector.observe(at(260), &[process(1, 0, 0, 1)]));
+
+    // A process that exits and is waited for moves its CPU time to its parent.
+    assert_eq!(
+        progress(
+            &[process(1, 0, 100, 0), process(2, 1, 50, 0)],
+            &[process(1, 0, 150, 0)]
+        ),
+        (Duration::from_millis(50), 0)
+    );
+}
+
+#[test]
+fn watchdog_config() {
+    let config = serde_json::from_str::<WatchdogConfig>("{}").unwrap();
+    assert_eq!(config.window, Duration::from_secs(60));
+    assert_eq!(config.cpu_epsilon, Duration::from_millis(10));
+    assert_eq!(config.interval, Duration::from_secs(1));
+
+    let config = serde_json::from_str::<WatchdogConfig>(
+        r#"{ "window-secs": "2min", "cpu-epsilon-ms": 50, "interval-ms": "5s" }"#,
+    )
+    .unwrap();
+    assert_eq!(config.window, Duration::from_secs(120));
+    assert_eq!(config.cpu_epsilon, Duration::from_millis(50));
+    assert_eq!(config.interval, Duration::from_secs(5));
+
+    assert!(serde_json::from_str::<WatchdogConfig>(r#"{ "window": 60 }"#).is_err());
+}
@@ -0,0 +1,81 @@
+//! Whether the working tree has uncommitted changes. Results of a dirty tree are attributed to
+//! a commit that doesn't contain what was measured, so they are marked and never used as a
+//! baseline.
+
+use std::path::Path;
+use std::process::Command;
+
+use crate::sha256::Sha256;
+
+/// The SHA-256 of the uncommitted changes to tracked files of the repository at `dir`, or
+/// `None` when there are none. Untracked files don't count, as the workflow puts its own files
+/// like the clone of the bench repo into the working tree.
+pub fn diff_sha256(dir: &Path) -> Result<Option<String>, String> {
+    let status = git(dir, &["status", "--porcelain", "--untracked-files=no"])?;
+    if status.trim_ascii().is_empty() {
+        return Ok(None);
+    }
+
+    let diff = git(dir, &["diff", "--binary", "HEAD"])?;
+    let mut sha256 = Sha256::default();
+    sha256.update(&diff);
+    Ok(Some(sha256.finish_hex()))
+}
+
+pub fn git(dir: &Path, args: &[&str]) -> Result<Vec<u8>, String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .map_err(|e| format!("failed to run git: {e}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "`git {}` failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(output.stdout)
+}
+
+#[test]
+fn dirty_working_tree() {
+    let dir = crate::testkit::test_dir("worktree");
+    let git = |args: &[&str]| {
+        let output = Command::new("git")
+            .args([
+                "-c",
+                "user.name=Bench",
+                "-c",
+                "user.email=bench@example.com",
+            ])
+            .args(["-c", "commit.gpgsign=false"])
+            .args(args)
+            .current_dir(&dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{output:?}");
+    };
+
+    git(&["init", "--quiet"]);
+    std::fs::write(dir.join("lib.rs"), "fn main() {}\n").unwrap();
+    git(&["add", "lib.rs"]);
+    git(&["commit", "--quiet", "-m", "init"]);
+    assert_eq!(diff_sha256(&dir).unwrap(), None);
+
+    // Untracked files, like the results of the run, don't make it dirty.
+    std::fs::write(dir.join("bench_results.json"), "{}\n").unwrap();
+    assert_eq!(diff_sha256(&dir).unwrap(), None);
+
+    std::fs::write(dir.join("lib.rs"), "fn main() { fast() }\n").unwrap();
+    let unstaged = diff_sha256(&dir).unwrap().unwrap();
+    assert_eq!(unstaged.len(), 64);
+    // Staging doesn't change the diff against HEAD.
+    git(&["add", "lib.rs"]);
+    assert_eq!(diff_sha256(&dir).unwrap(), Some(unstaged.clone()));
+
+    std::fs::write(dir.join("lib.rs"), "fn main() { faster() }\n").unwrap();
+    assert_ne!(diff_sha256(&dir).unwrap(), Some(unstaged));
+
+    assert!(diff_sha256(&dir.join("does-not-exist")).is_err());
+}
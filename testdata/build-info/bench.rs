@@ -0,0 +1,13 @@
+pub fn add(a: u32, b: u32) -> u32 {
+    a + b
+}
+
+/// # Safety
+/// `i` must be in bounds.
+pub unsafe fn get(s: &[u8], i: usize) -> u8 {
+    unsafe { *s.get_unchecked(i) }
+}
+
+pub fn sum_every(s: &[u32], step: usize) -> u32 {
+    s.iter().step_by(step).sum()
+}
@@ -0,0 +1,11 @@
+#!/bin/sh
+# Rebuild the object files of the build metadata tests. `release-old.o` is `release.o` as if
+# built by rustc 1.93.1, patched as that toolchain isn't necessarily at hand.
+set -e
+cd "$(dirname "$0")"
+rustc --edition 2021 --crate-type lib --emit obj -C opt-level=3 -C debug-assertions=off -C overflow-checks=off -C panic=abort bench.rs -o release.o
+rustc --edition 2021 --crate-type lib --emit obj -C opt-level=0 -C debug-assertions=on -C overflow-checks=on -C panic=abort bench.rs -o debug.o
+gcc -O2 -c ref.c -o ref.o
+version=$(rustc --version | sed 's/^rustc //')
+commit=$(rustc -vV | sed -n 's/^commit-hash: //p')
+perl -0777 -pe "s/\Q$version\E/1.93.1 (01f6ddf75 2026-02-11)/g; s/$commit/01f6ddf7588f42ae2d7eb0a2f21d44e8e96674cf/g" release.o > release-old.o
@@ -0,0 +1,190 @@
+//! Run the benchmarker with `--backfill-baseline-counters` in a scratch repository, with a fake
+//! perf that logs what it counts where, after adding a perf event since the baseline.
+
+mod common;
+
+use std::path::Path;
+use std::process::Output;
+
+use serde_json::{json, Value};
+
+use benchmarker::testkit::BenchDataBuilder;
+use common::{
+    benchmarker, fake_perf, git, perf_log, test_dir, write_results, write_script, EVERY_EVENT,
+};
+
+const WORK: &str = "#!/bin/sh\ntrue\n";
+
+/// A repository with the `work` script at its base commit and a change, returning both.
+fn repository(repo: &Path) -> (String, String) {
+    write_script(repo, "work", WORK);
+    git(repo, &["init", "--quiet"]);
+    git(repo, &["add", "work"]);
+    git(repo, &["commit", "--quiet", "-m", "base"]);
+    git(
+        repo,
+        &["commit", "--quiet", "--allow-empty", "-m", "change"],
+    );
+    git(repo, &["update-ref", "refs/remotes/origin/main", "HEAD~"]);
+    (
+        git(repo, &["rev-parse", "HEAD~"]),
+        git(repo, &["rev-parse", "HEAD"]),
+    )
+}
+
+fn run_benchmarker(
+    dir: &Path,
+    commit: &str,
+    commands: &[&str],
+    events: &[&str],
+    args: &[&str],
+) -> Output {
+    let repo = dir.join("repo");
+    let config = json!({
+        "commands": { "work": commands },
+        "repetitions-for-group": { "work": 2 },
+        "backends-for-group": { "work": ["perf"] },
+        "perf-events-for-group": { "work": events },
+        "backfill": { "build": "echo build >> \"$PERF_LOG\"" },
+        "render-versus-self": {},
+        "render-versus-other": {
+            "work": { "measure": "instructions", "command": "work", "rows": { "a": 0 } }
+        }
+    });
+    std::fs::write(dir.join("bench.json"), config.to_string()).unwrap();
+    for file in ["perf.log", "summary.md"] {
+        let _ = std::fs::remove_file(dir.join(file));
+    }
+    benchmarker(dir)
+        .arg(commit)
+        .arg(dir.join("bench.json"))
+        .arg(dir.join("previous.json"))
+        .args(args)
+        .current_dir(&repo)
+        .output()
+        .unwrap()
+}
+
+/// Store the results of the base commit, measured without `instructions`.
+fn store_baseline(dir: &Path, base: &str) -> Value {
+    let output = run_benchmarker(dir, base, &["./work a", "./work b"], &["cycles"], &[]);
+    assert!(output.status.success(), "{output:?}");
+    std::fs::write(dir.join("previous.json"), &output.stdout).unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    serde_json::from_str(stdout.lines().last().unwrap()).unwrap()
+}
+
+fn stored_baseline(dir: &Path) -> Value {
+    let stored = std::fs::read_to_string(dir.join("previous.json")).unwrap();
+    serde_json::from_str(stored.lines().last().unwrap()).unwrap()
+}
+
+#[test]
+fn backfill_missing_event() {
+    let dir = test_dir("missing-event");
+    fake_perf(&dir, EVERY_EVENT);
+    let repo = dir.join("repo");
+    let (base, head) = repository(&repo);
+    store_baseline(&dir, &base);
+
+    let commands = ["./work a", "./work b", "./work c"];
+    let events = ["cycles", "instructions"];
+    let args = ["--backfill-baseline-counters"];
+    let output = run_benchmarker(&dir, &head, &commands, &events, &args);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{stderr}");
+    assert!(
+        stderr.contains("backfilled 2 counters of the baseline"),
+        "{stderr}"
+    );
+
+    // After measuring this commit, the baseline is built and only its commands are measured
+    // again, with only the missing event, in a worktree that is removed afterwards.
+    let log = perf_log(&dir);
+    assert_eq!(log.len(), 6, "{log:?}");
+    assert!(log[..3]
+        .iter()
+        .all(|line| line.starts_with("cycles,instructions ")));
+    assert_eq!(log[3], "build");
+    for (line, command) in log[4..].iter().zip(["./work a", "./work b"]) {
+        let (events, rest) = line.split_once(' ').unwrap();
+        assert_eq!(events, "instructions");
+        assert!(rest.ends_with(&format!("/backfill {command}")), "{line}");
+    }
+    assert_eq!(git(&repo, &["worktree", "list"]).lines().count(), 1);
+
+    // The baseline of the comparisons has the counter, but the stored one doesn't.
+    let summary = std::fs::read_to_string(dir.join("summary.md")).unwrap();
+    assert!(
+        summary.contains(
+            "> [!NOTE]\n> Counters the baseline lacked were measured later, at its commit, with `--backfill-baseline-counters`:\n\
+             > - work / `./work a`: `instructions`\n\
+             > - work / `./work b`: `instructions`\n"
+        ),
+        "{summary}"
+    );
+    assert!(
+        summary.contains("|`./work a`|`1000±1`  | `-0.0%` |`1000±1`  | `-0.0%` |"),
+        "{summary}"
+    );
+    assert!(stored_baseline(&dir)["bench_groups"]["work"][0]["counters"]
+        .get("instructions")
+        .is_none());
+
+    // Persisted, and not needed again.
+    let args = ["--backfill-baseline-counters", "--persist-backfill"];
+    let output = run_benchmarker(&dir, &head, &commands, &events, &args);
+    assert!(output.status.success(), "{output:?}");
+    let stored = stored_baseline(&dir);
+    let bench = &stored["bench_groups"]["work"][1];
+    assert_eq!(
+        bench["counters"]["instructions"]["value"], 1000.0,
+        "{bench}"
+    );
+    assert_eq!(bench["backfilled"], json!(["instructions"]));
+    assert_eq!(stored["commit_hash"], base);
+
+    let output = run_benchmarker(&dir, &head, &commands, &events, &args);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("the baseline has all counters of the run"),
+        "{stderr}"
+    );
+    assert_eq!(perf_log(&dir).len(), 3);
+}
+
+#[test]
+fn no_backfill_from_other_machine() {
+    let dir = test_dir("other-machine");
+    fake_perf(&dir, EVERY_EVENT);
+    let (base, head) = repository(&dir.join("repo"));
+    let baseline = BenchDataBuilder::new(&base)
+        .machine("X64", "Linux", "Other CPU")
+        .machine_class("Other CPU, 1 CPUs")
+        .machine_id("0123456789abcdef")
+        .group("work", |g| {
+            g.bench(["./work", "a"], |b| {
+                b.counter("cycles", 1000.0, 10.0, 2, "")
+            })
+            .bench(["./work", "b"], |b| {
+                b.counter("cycles", 1000.0, 10.0, 2, "")
+            })
+        })
+        .build();
+    write_results(&dir.join("previous.json"), &[baseline]);
+
+    let output = run_benchmarker(
+        &dir,
+        &head,
+        &["./work a"],
+        &["cycles", "instructions"],
+        &["--backfill-baseline-counters"],
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{stderr}");
+    assert!(
+        stderr.contains("warning: not backfilling the counters of the baseline: the baseline was measured on another kind of machine, `Other CPU, 1 CPUs` rather than `"),
+        "{stderr}"
+    );
+    assert_eq!(perf_log(&dir).len(), 1);
+}
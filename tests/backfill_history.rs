@@ -0,0 +1,147 @@
+//! Run `benchmarker backfill` over the history of a scratch repository, with a fake perf and a
+//! build that fails at one of the commits.
+
+mod common;
+
+use std::path::Path;
+use std::process::Output;
+
+use serde_json::{json, Value};
+
+use common::{benchmarker, fake_perf, git, test_dir, write_script, EVERY_EVENT};
+
+const WORK: &str = "#!/bin/sh\ntrue\n";
+
+/// A repository of four commits, the third of which doesn't build, returning their hashes.
+fn repository(repo: &Path) -> Vec<String> {
+    write_script(repo, "work", WORK);
+    git(repo, &["init", "--quiet"]);
+    git(repo, &["add", "work"]);
+    git(repo, &["commit", "--quiet", "-m", "0"]);
+    git(repo, &["commit", "--quiet", "--allow-empty", "-m", "1"]);
+    std::fs::write(repo.join("broken"), "").unwrap();
+    git(repo, &["add", "broken"]);
+    git(repo, &["commit", "--quiet", "-m", "2"]);
+    git(repo, &["rm", "--quiet", "broken"]);
+    git(repo, &["commit", "--quiet", "-m", "3"]);
+    git(repo, &["rev-list", "--reverse", "HEAD"])
+        .lines()
+        .map(str::to_owned)
+        .collect()
+}
+
+fn run_backfill(dir: &Path, args: &[&str]) -> Output {
+    let config = json!({
+        "commands": { "work": ["./work"] },
+        "repetitions-for-group": { "work": 2 },
+        "backends-for-group": { "work": ["perf"] },
+        "perf-events-for-group": { "work": ["cycles"] },
+        "backfill": { "build": "echo build >> \"$PERF_LOG\" && test ! -e broken" },
+        "scratch-root": dir.join("scratch"),
+        "render-versus-self": {},
+        "render-versus-other": {}
+    });
+    std::fs::write(dir.join("bench.json"), config.to_string()).unwrap();
+    benchmarker(dir)
+        .arg("backfill")
+        .arg(dir.join("bench.json"))
+        .arg(dir.join("results.json"))
+        .args(args)
+        .current_dir(dir.join("repo"))
+        .env("GITHUB_REF", "refs/pull/1/merge")
+        .output()
+        .unwrap()
+}
+
+fn stored_commits(dir: &Path) -> Vec<String> {
+    let results = std::fs::read_to_string(dir.join("results.json")).unwrap();
+    results
+        .lines()
+        .map(|line| {
+            let entry = serde_json::from_str::<Value>(line).unwrap();
+            entry["commit_hash"].as_str().unwrap().to_owned()
+        })
+        .collect()
+}
+
+#[test]
+fn backfill_history() {
+    let dir = test_dir("history");
+    fake_perf(&dir, EVERY_EVENT);
+    let repo = dir.join("repo");
+    let commits = repository(&repo);
+
+    // The budget runs out after the first commit.
+    let output = run_backfill(&dir, &["--last", "3", "--max-duration", "1ns"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        stdout,
+        format!(
+            "measured 1 of 3 commits\n  skipped: 0\n  failed to build: 0\n  failed: 0\n  left for the next backfill: 2\n\
+             {} measured\n\
+             {} left for the next backfill\n\
+             {} left for the next backfill\n",
+            commits[1], commits[2], commits[3]
+        )
+    );
+    assert_eq!(stored_commits(&dir), [commits[1].clone()]);
+    assert!(dir.join("results.json.backfill").exists());
+
+    // Resumed, and measured in worktrees in the scratch root, which are removed afterwards.
+    // The results are stored whatever triggered the backfill, and the runs write no step
+    // summary.
+    let output = run_backfill(&dir, &["--last", "3"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{output:?}");
+    assert!(
+        stdout.starts_with(
+            "measured 2 of 3 commits\n  skipped: 0\n  failed to build: 1\n  failed: 0\n"
+        ),
+        "{stdout}"
+    );
+    assert!(
+        stdout.contains(&format!(
+            "{} failed to build: `echo build >> \"$PERF_LOG\" && test ! -e broken` failed with exit status: 1\n",
+            commits[2]
+        )),
+        "{stdout}"
+    );
+    assert_eq!(
+        stored_commits(&dir),
+        [commits[1].clone(), commits[3].clone()]
+    );
+    assert!(!dir.join("results.json.backfill").exists());
+    assert!(!dir.join("summary.md").exists());
+    assert_eq!(git(&repo, &["worktree", "list"]).lines().count(), 1);
+    assert_eq!(std::fs::read_dir(dir.join("scratch")).unwrap().count(), 0);
+    let log = std::fs::read_to_string(dir.join("perf.log")).unwrap();
+    let builds = log.lines().filter(|line| *line == "build").count();
+    assert_eq!(builds, 3, "{log}");
+    assert!(
+        log.lines().filter(|line| *line != "build").all(|line| {
+            let scratch = format!("{}/benchmarker-backfill-", dir.join("scratch").display());
+            line.contains(&scratch) && line.ends_with(" ./work")
+        }),
+        "{log}"
+    );
+
+    // Measured ones are skipped, including the explicitly given ones.
+    let output = run_backfill(&dir, &[&commits[3], &commits[0][..10], &commits[1]]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        stdout,
+        format!(
+            "measured 1 of 3 commits\n  skipped: 2\n  failed to build: 0\n  failed: 0\n\
+             {} measured\n\
+             {} skipped, already measured on this kind of machine\n\
+             {} skipped, already measured on this kind of machine\n",
+            commits[0], commits[1], commits[3]
+        )
+    );
+    assert_eq!(
+        stored_commits(&dir),
+        [commits[1].clone(), commits[3].clone(), commits[0].clone()]
+    );
+}
@@ -0,0 +1,113 @@
+//! Run the benchmarker with `--changed-only` in a scratch repository, where the commits since
+//! the merge base with `origin/main` only touch one of the implementations.
+
+mod common;
+
+use std::path::Path;
+
+use serde_json::Value;
+
+use common::{benchmarker, git, test_dir};
+
+fn commit(dir: &Path, file: &str, contents: &str) {
+    let path = dir.join(file);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, contents).unwrap();
+    git(dir, &["add", file]);
+    git(dir, &["commit", "--quiet", "-m", file]);
+}
+
+#[test]
+fn changed_groups_only() {
+    let dir = test_dir("groups");
+    git(&dir, &["init", "--quiet"]);
+    commit(&dir, "src/deflate/mod.rs", "fn deflate() {}\n");
+    commit(&dir, "src/inflate/mod.rs", "fn inflate() {}\n");
+    git(&dir, &["update-ref", "refs/remotes/origin/main", "HEAD"]);
+    // The merge base is taken with the parent of HEAD, as for a commit on main.
+    commit(&dir, "src/inflate/mod.rs", "fn inflate() { fast() }\n");
+    commit(&dir, "README.md", "faster\n");
+    let head = git(&dir, &["rev-parse", "HEAD"]);
+
+    // Untracked, so the working tree isn't dirty.
+    let config = dir.join("bench.json");
+    std::fs::write(
+        &config,
+        r#"{
+            "commands": {
+                "deflate": ["true"],
+                "inflate": [{ "command": "true", "tags": ["slow"] }, "echo inflate"],
+                "always": ["echo always"]
+            },
+            "paths-for-group": {
+                "deflate": ["src/deflate/**"],
+                "inflate": ["src/inflate/**", "Cargo.toml"]
+            },
+            "repetitions-for-group": { "deflate": 2, "inflate": 2, "always": 2 },
+            "backends-for-group": { "deflate": ["getrusage"], "inflate": ["getrusage"], "always": ["getrusage"] },
+            "render-versus-self": {
+                "deflate vs inflate": {
+                    "default": { "measure": "wall-time", "before": { "command": "deflate", "index": 0 }, "after": { "command": "inflate", "index": 1 } }
+                }
+            },
+            "render-versus-other": {}
+        }"#,
+    )
+    .unwrap();
+
+    let output = benchmarker(&dir)
+        .arg("--stream")
+        .arg("--changed-only")
+        .arg("--skip-tag=slow")
+        .arg("--run-report")
+        .arg(dir.join("report.json"))
+        .arg(&head)
+        .arg(&config)
+        .arg(dir.join("does-not-exist.json"))
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{stderr}");
+    assert!(
+        stderr.contains("skipping `deflate`: no changed files matched src/deflate/**"),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains("running `inflate`: src/inflate/mod.rs matched src/inflate/**"),
+        "{stderr}"
+    );
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let last = serde_json::from_str::<Value>(stdout.lines().last().unwrap()).unwrap();
+    assert_eq!(last["type"], "final");
+    // The tag filter still applies to the groups that run.
+    let groups = last["bench_groups"].as_object().unwrap();
+    assert_eq!(groups.keys().collect::<Vec<_>>(), ["inflate", "always"]);
+    assert_eq!(
+        last["bench_groups"]["inflate"][0]["cmd"],
+        serde_json::json!(["echo", "inflate"])
+    );
+    assert_eq!(
+        last["skipped_groups"],
+        serde_json::json!({ "deflate": "no changed files matched src/deflate/**" })
+    );
+
+    let report = std::fs::read(dir.join("report.json")).unwrap();
+    let report = serde_json::from_slice::<Value>(&report).unwrap();
+    assert_eq!(report["groups"]["deflate"]["status"], "skipped");
+    assert_eq!(
+        report["groups"]["deflate"]["skip_reason"],
+        "no changed files matched src/deflate/**"
+    );
+    assert_eq!(report["groups"]["inflate"]["status"], "completed");
+
+    let summary = std::fs::read_to_string(dir.join("summary.md")).unwrap();
+    assert!(
+        summary.contains("- `deflate`: skipped: no changed files matched src/deflate/**"),
+        "{summary}"
+    );
+    assert!(
+        summary.contains("- deflate vs inflate: `default`"),
+        "{summary}"
+    );
+}
@@ -0,0 +1,147 @@
+//! Post the report as a commit comment to a mock of the GitHub API.
+
+mod common;
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::process::Output;
+use std::thread::JoinHandle;
+
+use serde_json::Value;
+
+use common::{benchmarker, scratch_repo, test_dir};
+
+struct Request {
+    request_line: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+/// Answer one request per status with a GitHub-like body, returning the url of the server.
+fn mock_api(statuses: Vec<u16>) -> (String, JoinHandle<Vec<Request>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let handle = std::thread::spawn(move || {
+        statuses
+            .into_iter()
+            .map(|status| {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut headers = vec![];
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let Some((name, value)) = line.trim_end().split_once(':') else {
+                        break;
+                    };
+                    headers.push((name.trim().to_ascii_lowercase(), value.trim().to_owned()));
+                }
+                let content_length = headers
+                    .iter()
+                    .find(|(name, _)| name == "content-length")
+                    .map_or(0, |(_, value)| value.parse().unwrap());
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+
+                let response = match status {
+                    201 => r#"{"id":1}"#,
+                    _ => r#"{"message":"Resource not accessible by integration"}"#,
+                };
+                write!(
+                    reader.into_inner(),
+                    "HTTP/1.1 {status} Status\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{response}",
+                    response.len()
+                )
+                .unwrap();
+                Request {
+                    request_line: request_line.trim_end().to_owned(),
+                    headers,
+                    body,
+                }
+            })
+            .collect()
+    });
+    (url, handle)
+}
+
+fn run_benchmarker(dir: &Path, commit: &str, previous_results: &str, api_url: &str) -> Output {
+    benchmarker(dir)
+        .args([commit, "bench.json", previous_results])
+        .env("GITHUB_API_URL", api_url)
+        .env("BENCH_GITHUB_TOKEN", "secret-token")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn comment_on_commit() {
+    let dir = test_dir("post");
+    let (base, head) = scratch_repo(&dir);
+
+    // A row too long for a comment, and a short one.
+    let long_row = "x".repeat(70_000);
+    let config = serde_json::json!({
+        "commands": { "trivial": ["true", "true"] },
+        "repetitions-for-group": { "trivial": 2 },
+        "backends-for-group": { "trivial": ["getrusage"] },
+        "comment-target": "commit",
+        "render-versus-self": {},
+        "render-versus-other": {
+            "trivial": { "measure": "wall-time", "command": "trivial", "rows": { "short": 0, long_row.clone(): 1 } }
+        }
+    });
+    std::fs::write(dir.join("bench.json"), config.to_string()).unwrap();
+
+    let (api_url, api) = mock_api(vec![201, 201, 403]);
+    let output = run_benchmarker(&dir, &base, "does-not-exist.json", &api_url);
+    assert!(output.status.success(), "{output:?}");
+    std::fs::write(dir.join("previous.json"), &output.stdout).unwrap();
+    let output = run_benchmarker(&dir, &head, "previous.json", &api_url);
+    assert!(output.status.success(), "{output:?}");
+
+    // The API refusing doesn't fail the run.
+    let output = run_benchmarker(&dir, &head, "previous.json", &api_url);
+    assert_eq!(output.status.code(), Some(0));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(
+            "warning: failed to comment on the commit: the token may not comment on commits"
+        ),
+        "{stderr}"
+    );
+
+    let requests = api.join().unwrap();
+    assert_eq!(
+        requests[0].request_line,
+        format!("POST /repos/owner/repo/commits/{base}/comments HTTP/1.1")
+    );
+    assert_eq!(
+        requests[1].request_line,
+        format!("POST /repos/owner/repo/commits/{head}/comments HTTP/1.1")
+    );
+    for request in &requests {
+        assert!(request
+            .headers
+            .contains(&("authorization".to_owned(), "Bearer secret-token".to_owned())));
+    }
+
+    let body = |request: &Request| {
+        let payload: Value = serde_json::from_slice(&request.body).unwrap();
+        payload["body"].as_str().unwrap().to_owned()
+    };
+    assert!(body(&requests[0]).ends_with("No previous results to compare against.\n"));
+    let comment = body(&requests[1]);
+    assert!(comment.len() <= 65536, "{}", comment.len());
+    assert!(
+        comment.contains("| trivial | short | wall-time |"),
+        "{comment}"
+    );
+    assert!(!comment.contains(&long_row));
+    assert!(
+        comment.ends_with("\n_1 more rows didn't fit in a comment._\n"),
+        "{comment}"
+    );
+}
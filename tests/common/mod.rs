@@ -0,0 +1,149 @@
+//! What the integration tests share: a scratch directory per test, git, the benchmarker with
+//! a hermetic environment, scripts standing in for perf and the benchmarked programs, and
+//! stored results built with [`benchmarker::testkit`].
+//!
+//! Every test crate uses only part of it.
+#![allow(dead_code)]
+
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use benchmarker::BenchData;
+use serde_json::Value;
+
+/// Counts 1000 of every event, with a variance of 0.1%. The output of [`fake_perf`] most tests
+/// need.
+pub const EVERY_EVENT: &str = r#"for event in $(echo "$events" | tr , ' '); do
+    echo "{\"counter-value\" : \"1000\", \"unit\" : \"\", \"event\" : \"$event\", \"variance\" : 0.10}"
+done"#;
+
+/// An empty directory for the test `name`, named after the test crate so that the tests of
+/// different crates, which run in parallel, don't share it.
+pub fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "benchmarker-test-{}-{}-{name}",
+        std::process::id(),
+        env!("CARGO_CRATE_NAME").replace('_', "-")
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Write the executable `script` to `path` in `dir`, creating the directories on the way.
+pub fn write_script(dir: &Path, path: &str, script: &str) {
+    let path = dir.join(path);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(&path, script).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+/// Put a fake `perf` in `dir/bin`, which [`benchmarker`] puts on the `PATH`.
+///
+/// It takes the arguments of `perf stat` apart into `$out`, `$events` and `$repeat`, logs
+/// `<events> <repeat> <directory> <command>` to `$PERF_LOG` and runs the command once. Then it
+/// runs the shell `output`, whose stdout are the JSON lines of the counters perf writes to
+/// `$out`. The command is still in `$@`.
+pub fn fake_perf(dir: &Path, output: &str) {
+    let script = format!(
+        r#"#!/bin/sh
+while [ "$1" != "--" ]; do
+    case "$1" in
+        -o) out="$2"; shift ;;
+        -e) events="$2"; shift ;;
+        --repeat) repeat="$2"; shift ;;
+    esac
+    shift
+done
+shift
+
+echo "$events $repeat $(pwd) $*" >> "$PERF_LOG"
+"$@"
+status=$?
+{{
+{output}
+}} >> "$out"
+exit $status
+"#
+    );
+    write_script(dir, "bin/perf", &script);
+}
+
+/// The runs of the fake perf, with `dir` itself shown as `.`.
+pub fn perf_log(dir: &Path) -> Vec<String> {
+    let log = std::fs::read_to_string(dir.join("perf.log")).unwrap();
+    let dir = dir.display().to_string();
+    log.lines().map(|line| line.replace(&dir, ".")).collect()
+}
+
+pub fn git(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .args([
+            "-c",
+            "user.name=Bench",
+            "-c",
+            "user.email=bench@example.com",
+            "-c",
+            "commit.gpgsign=false",
+        ])
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    String::from_utf8(output.stdout).unwrap().trim().to_owned()
+}
+
+/// A repository with two commits, where `origin/main` points at the first one, like a PR
+/// branch. Returns both commits.
+pub fn scratch_repo(dir: &Path) -> (String, String) {
+    git(dir, &["init", "--quiet"]);
+    git(dir, &["commit", "--quiet", "--allow-empty", "-m", "base"]);
+    git(dir, &["commit", "--quiet", "--allow-empty", "-m", "change"]);
+    git(dir, &["update-ref", "refs/remotes/origin/main", "HEAD~"]);
+    (
+        git(dir, &["rev-parse", "HEAD~"]),
+        git(dir, &["rev-parse", "HEAD"]),
+    )
+}
+
+/// The benchmarker, running in `dir`, with `dir/bin` first on the `PATH`, the fake perf
+/// logging to `dir/perf.log` and the step summary in `dir/summary.md`. It runs on the X64
+/// Linux runner the builders of [`benchmarker::testkit`] default to. Nothing of the
+/// environment of the test run, like a webhook or the event of a CI job, leaks into it.
+pub fn benchmarker(dir: &Path) -> Command {
+    let path = format!(
+        "{}:{}",
+        dir.join("bin").display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+    let mut command = Command::new(env!("CARGO_BIN_EXE_benchmarker"));
+    command
+        .current_dir(dir)
+        .env("PATH", path)
+        .env("PERF_LOG", dir.join("perf.log"))
+        .env("GITHUB_REPOSITORY", "owner/repo")
+        .env("GITHUB_STEP_SUMMARY", dir.join("summary.md"))
+        .env("RUNNER_ARCH", "X64")
+        .env("RUNNER_OS", "Linux")
+        .env_remove("BENCH_NOTIFY_WEBHOOK_URL")
+        .env_remove("GITHUB_REF")
+        .env_remove("GITHUB_EVENT_PATH");
+    command
+}
+
+/// Store `entries` at `path` as previous results, one line each, like `--results-file` does.
+pub fn write_results(path: &Path, entries: &[BenchData]) {
+    let lines = entries
+        .iter()
+        .map(|entry| serde_json::to_string(entry).unwrap() + "\n")
+        .collect::<String>();
+    std::fs::write(path, lines).unwrap();
+}
+
+/// The results of the run, the last line of its output.
+pub fn final_line(output: &Output) -> Value {
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    serde_json::from_str(stdout.lines().last().unwrap()).unwrap()
+}
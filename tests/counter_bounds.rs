@@ -0,0 +1,98 @@
+//! Run the benchmarker with a backend that reports a counter that wrapped around, against a
+//! baseline with a broken counter, and check that neither is compared.
+
+mod common;
+
+use std::path::Path;
+use std::process::Output;
+
+use serde_json::{json, Value};
+
+use common::{benchmarker, final_line, scratch_repo, test_dir, write_script};
+
+/// Counts like a kernel with a broken perf backport.
+const FAKE_BACKEND: &str = r#"#!/bin/sh
+echo '{"counters": {"cycles": {"value": 1.8e19, "variance": 100.0}, "instructions": {"value": 1000.0, "variance": 10.0}}}'
+"#;
+
+const CONFIG: &str = r#"{
+    "commands": { "tiny": ["true"] },
+    "repetitions-for-group": { "tiny": 2 },
+    "backends-for-group": { "tiny": [{ "external": "./fake-backend {cmd}" }] },
+    "render-versus-self": {},
+    "render-versus-other": {}
+}"#;
+
+fn run_benchmarker(dir: &Path, commit: &str) -> Output {
+    benchmarker(dir)
+        .arg(commit)
+        .arg("bench.json")
+        .arg("previous.json")
+        .args(["--run-report", "run-report.json"])
+        .env_remove("GITHUB_STEP_SUMMARY")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn drop_counters_out_of_bounds() {
+    let dir = test_dir("drop");
+    let (base, head) = scratch_repo(&dir);
+
+    std::fs::write(dir.join("bench.json"), CONFIG).unwrap();
+    write_script(&dir, "fake-backend", FAKE_BACKEND);
+
+    // The fresh measurement of the cycles is dropped, the instructions are kept.
+    let output = run_benchmarker(&dir, &base);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{stderr}");
+    let warning = format!(
+        "warning: dropped `cycles` of `true` in the `tiny` group of {}: 1.8e19 per run is more than the 1e15 of `max-count`",
+        &base[..7]
+    );
+    assert!(stderr.contains(&warning), "{stderr}");
+    let mut results = final_line(&output);
+    let counters = &results["bench_groups"]["tiny"][0]["counters"];
+    assert!(counters.get("cycles").is_none(), "{counters}");
+    assert_eq!(counters["instructions"]["value"], 1000.0);
+
+    // A baseline stored before the bounds existed, with a broken counter.
+    results["bench_groups"]["tiny"][0]["counters"]["instructions"]["value"] = json!(-3.0);
+    std::fs::write(dir.join("previous.json"), format!("{results}\n")).unwrap();
+
+    let output = run_benchmarker(&dir, &head);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{stderr}");
+    assert!(
+        stderr.contains(&format!(
+            "warning: dropped `instructions` of `true` in the `tiny` group of {}: the value -3 is negative",
+            &base[..7]
+        )),
+        "{stderr}"
+    );
+    // Only the fresh instructions are left, with nothing to compare them with.
+    assert!(stderr.contains("|`true`|`1000±3`  | `n.a.` |"), "{stderr}");
+
+    let report = std::fs::read(dir.join("run-report.json")).unwrap();
+    let report = serde_json::from_slice::<Value>(&report).unwrap();
+    assert_eq!(report["baseline"]["commit"], base.as_str());
+    assert_eq!(
+        report["dropped_counters"],
+        json!([
+            {
+                "commit": &base[..7],
+                "group": "tiny",
+                "command": "true",
+                "counter": "instructions",
+                "reason": "the value -3 is negative"
+            },
+            {
+                "commit": &head[..7],
+                "group": "tiny",
+                "command": "true",
+                "counter": "cycles",
+                "reason": "1.8e19 per run is more than the 1e15 of `max-count`"
+            }
+        ])
+    );
+}
@@ -0,0 +1,94 @@
+//! Run the benchmarker with `dedupe-commands` in a scratch repository, with a fake perf that
+//! logs what it counts, on a command that three groups share.
+
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::path::Path;
+use std::process::Output;
+
+use serde_json::{json, Value};
+
+use common::{benchmarker, fake_perf, git, perf_log, test_dir, EVERY_EVENT};
+
+fn run_benchmarker(dir: &Path, commit: &str, config: &Value) -> Output {
+    std::fs::write(dir.join("bench.json"), config.to_string()).unwrap();
+    benchmarker(dir)
+        .arg(commit)
+        .arg("bench.json")
+        .arg("previous.json")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn measure_shared_command_once() {
+    let dir = test_dir("shared");
+    fake_perf(&dir, EVERY_EVENT);
+    git(&dir, &["init", "--quiet"]);
+    git(&dir, &["commit", "--quiet", "--allow-empty", "-m", "base"]);
+    let commit = git(&dir, &["rev-parse", "HEAD"]);
+
+    let config = json!({
+        "commands": {
+            "headline": ["true shared", "true headline"],
+            "switches": ["true shared"],
+            "cache": ["true shared"]
+        },
+        "dedupe-commands": true,
+        "repetitions-for-group": { "headline": 5, "switches": 3, "cache": 8 },
+        "backends-for-group": {
+            "headline": ["perf"],
+            "switches": ["perf"],
+            "cache": ["perf"]
+        },
+        "perf-events-for-group": {
+            "headline": ["cycles", "instructions"],
+            "switches": ["cycles", "context-switches"],
+            "cache": ["cycles", "cache-misses"]
+        },
+        "render-versus-self": {},
+        "render-versus-other": {}
+    });
+    let output = run_benchmarker(&dir, &commit, &config);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{stderr}");
+    assert!(
+        stderr.contains(
+            "measuring `true shared` once for the `headline`, `switches`, `cache` groups, with 8 repetitions\n"
+        ),
+        "{stderr}"
+    );
+
+    // The shared command runs once, with the events of all groups and the most repetitions.
+    assert_eq!(
+        perf_log(&dir),
+        [
+            "cycles,instructions,context-switches,cache-misses 8 . true shared",
+            "cycles,instructions 5 . true headline",
+        ]
+    );
+
+    // Every group gets the counters of its own events, with the repetitions they were measured
+    // with.
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let results: Value = serde_json::from_str(stdout.lines().last().unwrap()).unwrap();
+    for (group_name, events) in [
+        ("headline", ["cycles", "instructions"]),
+        ("switches", ["context-switches", "cycles"]),
+        ("cache", ["cache-misses", "cycles"]),
+    ] {
+        let counters = results["bench_groups"][group_name][0]["counters"]
+            .as_object()
+            .unwrap();
+        let names = counters.keys().collect::<Vec<_>>();
+        assert_eq!(names, events, "{group_name}");
+        assert!(
+            counters.values().all(|counter| counter["repetitions"] == 8),
+            "{group_name}: {counters:?}"
+        );
+    }
+    let headline = &results["bench_groups"]["headline"][1]["counters"];
+    assert_eq!(headline["cycles"]["repetitions"], 5);
+}
@@ -0,0 +1,124 @@
+//! Run the benchmarker with `--fail-fast` in a scratch repository, where the first group of
+//! the suite regressed.
+
+mod common;
+
+use std::path::Path;
+use std::process::Output;
+
+use serde_json::{json, Value};
+
+use common::{benchmarker, final_line, scratch_repo, test_dir};
+
+/// The suite, with the command of the `early` group, which is compared by its id.
+fn config(early_command: &str) -> String {
+    json!({
+        "commands": {
+            "early": [{ "command": early_command, "id": "work" }],
+            "late": ["true"]
+        },
+        "repetitions-for-group": { "early": 3, "late": 2 },
+        "backends-for-group": { "early": ["getrusage"], "late": ["getrusage"] },
+        "gate": { "max-regression-percent": 50 },
+        "render-versus-self": {},
+        "render-versus-other": {
+            "early": { "measure": "wall-time", "command": "early", "rows": { "work": 0 } }
+        }
+    })
+    .to_string()
+}
+
+fn run_benchmarker(dir: &Path, commit: &str, config: &str, args: &[&str]) -> Output {
+    std::fs::write(dir.join("bench.json"), config).unwrap();
+    benchmarker(dir)
+        .arg(commit)
+        .arg("bench.json")
+        .arg("previous.json")
+        .args(["--run-report", "run-report.json"])
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn stop_after_regressed_group() {
+    let dir = test_dir("stop");
+    let (base, head) = scratch_repo(&dir);
+
+    // Nothing to compare against, so the whole suite runs.
+    let output = run_benchmarker(&dir, &base, &config("true"), &["--fail-fast"]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(final_line(&output).get("partial").is_none());
+    std::fs::write(dir.join("previous.json"), &output.stdout).unwrap();
+
+    let output = run_benchmarker(
+        &dir,
+        &head,
+        &config("sleep 0.05"),
+        &["--fail-fast", "--results-file", "results.json"],
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(1), "{stderr}");
+    assert!(
+        stderr.contains(
+            "the gate failed after the `early` group, not running the 1 remaining groups (--fail-fast)"
+        ),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains("gate failure: early / work regressed by"),
+        "{stderr}"
+    );
+    assert!(
+        stderr
+            .contains("warning: not writing the partial results of `--fail-fast` to results.json"),
+        "{stderr}"
+    );
+    assert!(!dir.join("results.json").exists());
+
+    let last = final_line(&output);
+    assert_eq!(last["partial"], true);
+    assert_eq!(
+        last["skipped_groups"],
+        json!({ "late": "not run (fail-fast)" })
+    );
+    let groups = last["bench_groups"].as_object().unwrap();
+    assert_eq!(groups.keys().collect::<Vec<_>>(), ["early"]);
+
+    let report = std::fs::read(dir.join("run-report.json")).unwrap();
+    let report = serde_json::from_slice::<Value>(&report).unwrap();
+    assert_eq!(report["exit_code"], 1);
+    assert_eq!(report["groups"]["early"]["status"], "completed");
+    assert_eq!(report["groups"]["late"]["status"], "skipped");
+    assert_eq!(
+        report["groups"]["late"]["skip_reason"],
+        "not run (fail-fast)"
+    );
+    assert_eq!(report["gate"]["failures"][0]["row"]["name"], "work");
+
+    let summary = std::fs::read_to_string(dir.join("summary.md")).unwrap();
+    assert!(
+        summary
+            .contains("Not run, `--fail-fast` stopped the run when the gate failed:\n\n- `late`\n"),
+        "{summary}"
+    );
+
+    // Without the flag, every group runs and the regular gate fails on the same row.
+    let output = run_benchmarker(
+        &dir,
+        &head,
+        &config("sleep 0.05"),
+        &["--results-file", "results.json"],
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(1), "{stderr}");
+    assert!(
+        stderr.contains("gate failure: early / work regressed by"),
+        "{stderr}"
+    );
+    let last = final_line(&output);
+    assert!(last.get("partial").is_none());
+    let groups = last["bench_groups"].as_object().unwrap();
+    assert_eq!(groups.keys().collect::<Vec<_>>(), ["early", "late"]);
+    assert!(dir.join("results.json").exists());
+}
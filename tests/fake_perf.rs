@@ -0,0 +1,83 @@
+//! Run the benchmarker without any backend configured in a scratch repository, with a fake perf
+//! on the `PATH` that logs what it counts and reports canned counters, to check what perf is
+//! asked to count and how its output ends up in the summary.
+
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::path::Path;
+use std::process::Output;
+
+use serde_json::{json, Value};
+
+use common::{benchmarker, fake_perf, git, perf_log, test_dir};
+
+/// Writes garbage to stderr like a chatty command would, and reports canned counters for the
+/// default events.
+const OUTPUT: &str = r#"printf '\377 garbage\n{"counter-value" : "1", "event" : "cycles"}\n' >&2
+cat <<EOF
+# started on Tue Oct 15 10:00:00 2024
+
+{"counter-value" : "254.210000", "unit" : "msec", "event" : "task-clock", "variance" : 16.00, "event-runtime" : 254210000, "pcnt-running" : 100.00}
+{"counter-value" : "1000000000.000000", "unit" : "", "event" : "cycles", "variance" : 0.10, "event-runtime" : 254210000, "pcnt-running" : 100.00}
+{"counter-value" : "2000000000.000000", "unit" : "", "event" : "instructions", "variance" : 0.10, "event-runtime" : 254210000, "pcnt-running" : 100.00}
+EOF"#;
+
+fn run_benchmarker(dir: &Path, commit: &str, config: &Value) -> Output {
+    std::fs::write(dir.join("bench.json"), config.to_string()).unwrap();
+    for file in ["perf.log", "summary.md"] {
+        let _ = std::fs::remove_file(dir.join(file));
+    }
+    benchmarker(dir)
+        .arg(commit)
+        .arg("bench.json")
+        .arg("previous.json")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn perf_by_default() {
+    let dir = test_dir("default");
+    fake_perf(&dir, OUTPUT);
+    git(&dir, &["init", "--quiet"]);
+    git(&dir, &["commit", "--quiet", "--allow-empty", "-m", "base"]);
+    let commit = git(&dir, &["rev-parse", "HEAD"]);
+
+    let config = json!({
+        "commands": { "work": ["true a", "true b"] },
+        "repetitions-for-group": { "work": 4 },
+        "render-versus-self": {},
+        "render-versus-other": {}
+    });
+    let output = run_benchmarker(&dir, &commit, &config);
+    assert!(output.status.success(), "{output:?}");
+
+    // Without any backend configured, perf counts the default events, repeating every command
+    // as often as the group says, in a single run of perf.
+    assert_eq!(
+        perf_log(&dir),
+        [
+            "task-clock,cycles,instructions 4 . true a",
+            "task-clock,cycles,instructions 4 . true b",
+        ]
+    );
+
+    // The garbage the command writes to stderr doesn't end up in the counters.
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let results: Value = serde_json::from_str(stdout.lines().last().unwrap()).unwrap();
+    let bench = &results["bench_groups"]["work"][0];
+    assert_eq!(bench.get("error"), None, "{bench}");
+    let counters = &bench["counters"];
+    assert_eq!(counters["cycles"]["value"], 1e9);
+    assert_eq!(counters["cycles"]["repetitions"], 4);
+    assert_eq!(counters["task-clock"]["unit"], "msec");
+
+    // task-clock is shown in msec, with the standard deviation from the relative one of perf.
+    let summary = std::fs::read_to_string(dir.join("summary.md")).unwrap();
+    assert!(
+        summary.contains("|`true a`|`1000000000±1000000`  | `n.a.` |`2000000000±2000000`  | `n.a.` |`254.210±41` msec | `n.a.` |"),
+        "{summary}"
+    );
+}
@@ -0,0 +1,143 @@
+//! Run the benchmarker on a suite that imports the results of GPU benchmarks measured by
+//! another job, on another machine, and compare them with a baseline whose GPU benchmarks ran
+//! on yet another class of machine.
+
+mod common;
+
+use std::path::Path;
+use std::process::Output;
+
+use serde_json::{json, Value};
+
+use common::{benchmarker, final_line, scratch_repo, test_dir};
+
+fn run_benchmarker(dir: &Path, commit: &str, config: &Value) -> Output {
+    std::fs::write(dir.join("bench.json"), config.to_string()).unwrap();
+    benchmarker(dir)
+        .arg(commit)
+        .arg("bench.json")
+        .arg("previous.json")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn import_results_of_another_machine() {
+    let dir = test_dir("gpu");
+    std::fs::create_dir_all(dir.join("artifacts")).unwrap();
+    for fixture in ["gpu-results.json", "single-bench.json"] {
+        std::fs::copy(
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("testdata/import")
+                .join(fixture),
+            dir.join("artifacts").join(fixture),
+        )
+        .unwrap();
+    }
+    let (base, head) = scratch_repo(&dir);
+
+    let config = json!({
+        "commands": {
+            "gpu": [
+                { "import-results": "artifacts/gpu-results.json", "select": "gpu-group/2", "tags": ["nightly"] },
+                { "import-results": "artifacts/single-bench.json" }
+            ]
+        },
+        "render-versus-self": {},
+        "render-versus-other": {}
+    });
+    let output = run_benchmarker(&dir, &base, &config);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{stderr}");
+    assert!(
+        stderr.contains("warning: `./gpu-bench --kernel matmul` is imported from the results of 2222222222222222222222222222222222222222, not of the benchmarked commit\n"),
+        "{stderr}"
+    );
+
+    // The imported commands keep their command lines, ids and tags, and where they ran.
+    let mut results = final_line(&output);
+    let matmul = &results["bench_groups"]["gpu"][0];
+    assert_eq!(matmul["cmd"], json!(["./gpu-bench", "--kernel", "matmul"]));
+    assert_eq!(matmul["id"], "matmul");
+    assert_eq!(matmul["tags"], json!(["nightly", "gpu"]));
+    assert_eq!(matmul["counters"]["gpu-time"]["value"], 41.0);
+    assert_eq!(
+        matmul["imported"],
+        json!({
+            "path": "artifacts/gpu-results.json",
+            "commit_hash": "2222222222222222222222222222222222222222",
+            "cpu_model": "AMD EPYC 7V13 64-Core Processor",
+            "machine_class": "amd-epyc-7v13/24"
+        })
+    );
+    let fft = &results["bench_groups"]["gpu"][1];
+    assert_eq!(fft["cmd"], json!(["./gpu-bench", "--kernel", "fft"]));
+    assert_eq!(
+        fft["imported"],
+        json!({ "path": "artifacts/single-bench.json" })
+    );
+
+    // The GPU benchmarks of the baseline ran on another class of machine.
+    results["bench_groups"]["gpu"][0]["imported"]["machine_class"] = json!("amd-epyc-7763/4");
+    std::fs::write(dir.join("previous.json"), format!("{results}\n")).unwrap();
+
+    let output = run_benchmarker(&dir, &head, &config);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{stderr}");
+    // Only the instructions of the matmul are compared, the FFT of an unknown machine fully.
+    assert!(
+        stderr.contains("|`./gpu-bench --kernel matmul`|`90000000±1000`  | `n.a.` |`41.000±0` msec | `n.a.` |`120000000±1000`  | `-0.0%` |"),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains("|`./gpu-bench --kernel fft`||`12.500±0` msec | `-0.0%` ||"),
+        "{stderr}"
+    );
+    let summary = std::fs::read_to_string(dir.join("summary.md")).unwrap();
+    assert!(
+        summary.contains(
+            "> [!NOTE]\n> Imported results were measured on a different class of machine than their baseline. \
+             Cycles and times depend on the machine, so only `instructions` are compared for them:\n\
+             > - `./gpu-bench --kernel matmul` in `gpu`: `amd-epyc-7763/4` rather than `amd-epyc-7v13/24`\n"
+        ),
+        "{summary}"
+    );
+
+    // A missing file fails the command, not the run.
+    let config = json!({
+        "commands": {
+            "gpu": [
+                { "import-results": "artifacts/gpu-results.json", "select": "gpu-group/2" },
+                { "import-results": "artifacts/missing.json", "select": "gpu-group/0" }
+            ]
+        },
+        "render-versus-self": {},
+        "render-versus-other": {}
+    });
+    let output = run_benchmarker(&dir, &head, &config);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(4), "{stderr}");
+    assert!(
+        stderr.contains("error: `import-results artifacts/missing.json gpu-group/0` failed: failed to read the results to import from artifacts/missing.json: "),
+        "{stderr}"
+    );
+    let results = final_line(&output);
+    assert_eq!(
+        results["bench_groups"]["gpu"][0]["counters"]["gpu-time"]["value"],
+        41.0
+    );
+
+    // An invalid selector is an invalid config.
+    let config = json!({
+        "commands": { "gpu": [{ "import-results": "artifacts/gpu-results.json", "select": "gpu-group" }] },
+        "render-versus-self": {},
+        "render-versus-other": {}
+    });
+    let output = run_benchmarker(&dir, &head, &config);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(
+        stderr.contains("invalid `select` `gpu-group`, expected `<group>/<index>`"),
+        "{stderr}"
+    );
+}
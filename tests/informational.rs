@@ -0,0 +1,108 @@
+//! Run the benchmarker with an `informational` table in a scratch repository, with a fake perf
+//! that counts `$CYCLES` cycles for `echo`, the reference, and 1000 for anything else.
+
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::path::Path;
+use std::process::Output;
+
+use serde_json::{json, Value};
+
+use common::{benchmarker, fake_perf, scratch_repo, test_dir};
+
+/// Counts `$CYCLES` cycles for `echo` and 1000 for anything else, with a variance of 0.1%.
+const CYCLES: &str = r#"case "$1" in
+    echo) cycles="$CYCLES" ;;
+    *) cycles=1000 ;;
+esac
+echo "{\"counter-value\" : \"$cycles\", \"unit\" : \"\", \"event\" : \"cycles\", \"variance\" : 0.10}""#;
+
+/// The suite, with the reference in an `informational` table.
+fn config() -> String {
+    json!({
+        "commands": { "work": ["true", "echo"] },
+        "repetitions-for-group": { "work": 3 },
+        "backends-for-group": { "work": ["perf"] },
+        "perf-events-for-group": { "work": ["cycles"] },
+        "tags-for-group": { "work": ["suite"] },
+        "gate": { "max-regression-percent": 5.0 },
+        "shadow-gate": { "max-regression-percent": 2.0 },
+        "render-versus-self": {},
+        "render-versus-other": {
+            "work": { "measure": "cycles", "command": "work", "rows": { "true": 0 } },
+            "reference": {
+                "measure": "cycles",
+                "command": "work",
+                "rows": { "echo": 1 },
+                "informational": true
+            }
+        }
+    })
+    .to_string()
+}
+
+fn run_benchmarker(dir: &Path, commit: &str, cycles: u32) -> Output {
+    std::fs::write(dir.join("bench.json"), config()).unwrap();
+    let _ = std::fs::remove_file(dir.join("github-output"));
+    let _ = std::fs::remove_file(dir.join("summary.md"));
+    benchmarker(dir)
+        .arg(commit)
+        .arg("bench.json")
+        .arg("previous.json")
+        .args(["--run-report", "run-report.json"])
+        .env("CYCLES", cycles.to_string())
+        .env("GITHUB_OUTPUT", dir.join("github-output"))
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn informational_regression_only_renders() {
+    let dir = test_dir("regression");
+    fake_perf(&dir, CYCLES);
+    let (base, head) = scratch_repo(&dir);
+
+    let output = run_benchmarker(&dir, &base, 1000);
+    assert!(output.status.success(), "{output:?}");
+    std::fs::write(dir.join("previous.json"), &output.stdout).unwrap();
+
+    // The reference got twice as slow, which would fail both gates anywhere else.
+    let output = run_benchmarker(&dir, &head, 2000);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(0), "{stderr}");
+    assert!(!stderr.contains("gate failure"), "{stderr}");
+
+    let github_output = std::fs::read_to_string(dir.join("github-output")).unwrap();
+    assert!(
+        github_output.contains("shadow_regressions=0\n"),
+        "{github_output}"
+    );
+
+    let report: Value =
+        serde_json::from_slice(&std::fs::read(dir.join("run-report.json")).unwrap()).unwrap();
+    assert_eq!(report["gate"]["failures"], json!([]));
+    assert_eq!(report["shadow_gate"]["failures"], json!([]));
+    let rows = report["gate_rows"].as_array().unwrap();
+    assert_eq!(rows.len(), 1, "{rows:?}");
+    assert_eq!(rows[0]["table"], "work");
+
+    // The table itself shows the regression.
+    let summary = std::fs::read_to_string(dir.join("summary.md")).unwrap();
+    let table = summary
+        .split_once("### reference (informational)\n")
+        .unwrap_or_else(|| panic!("{summary}"))
+        .1;
+    let row = table
+        .lines()
+        .find(|line| line.starts_with("| echo "))
+        .unwrap_or_else(|| panic!("{table}"));
+    assert!(row.contains("+50.00%"), "{row}");
+    assert!(summary.contains("### work\n"), "{summary}");
+    // The rollup of the tag only counts the row of the other table.
+    assert!(
+        summary.contains("| suite | 1 | 🚀 0 | 💩 0 | `geomean  +0.00%` |"),
+        "{summary}"
+    );
+}
@@ -0,0 +1,82 @@
+//! Run the benchmarker in a scratch repository on a helper that reads its input a given number
+//! of times, with `io-counters-for-group`.
+
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::path::Path;
+use std::process::Output;
+
+use serde_json::json;
+
+use common::{benchmarker, final_line, scratch_repo, test_dir, write_script};
+
+/// `decompress <times>`: reads the 1 MiB `input` the given number of times.
+const DECOMPRESS: &str = r#"#!/bin/sh
+for _ in $(seq "$1"); do
+    cat input > /dev/null
+done
+"#;
+
+/// The suite, with the helper reading its input `times` times.
+fn config(times: u32) -> String {
+    json!({
+        "commands": {
+            "decompress": [{ "command": format!("./decompress {times}"), "id": "decompress" }]
+        },
+        "repetitions-for-group": { "decompress": 2 },
+        "backends-for-group": { "decompress": ["getrusage"] },
+        "io-counters-for-group": { "decompress": true },
+        "render-versus-self": {},
+        "render-versus-other": {
+            "reads": { "measure": "io-read-bytes", "command": "decompress", "rows": { "input": 0 } }
+        }
+    })
+    .to_string()
+}
+
+fn run_benchmarker(dir: &Path, commit: &str, config: &str) -> Output {
+    std::fs::write(dir.join("bench.json"), config).unwrap();
+    benchmarker(dir)
+        .arg(commit)
+        .arg("bench.json")
+        .arg("previous.json")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn flag_double_reading() {
+    let dir = test_dir("double-reading");
+    write_script(&dir, "decompress", DECOMPRESS);
+    std::fs::write(dir.join("input"), vec![0u8; 1 << 20]).unwrap();
+    let (base, head) = scratch_repo(&dir);
+
+    let output = run_benchmarker(&dir, &base, &config(1));
+    assert!(output.status.success(), "{output:?}");
+    let bench = &final_line(&output)["bench_groups"]["decompress"][0];
+    let read = &bench["counters"]["io-read-bytes"];
+    assert_eq!(read["unit"], "bytes", "{bench}");
+    assert_eq!(read["repetitions"], 1, "{bench}");
+    assert_eq!(read["variance"], 0.0, "{bench}");
+    let once = read["value"].as_f64().unwrap();
+    assert!(
+        ((1 << 20) as f64..(2 << 20) as f64).contains(&once),
+        "{bench}"
+    );
+    assert!(bench["counters"]["io-syscalls-read"].is_object(), "{bench}");
+    std::fs::write(dir.join("previous.json"), &output.stdout).unwrap();
+
+    // Reading the input twice doubles the bytes read, in binary units.
+    let output = run_benchmarker(&dir, &head, &config(2));
+    assert!(output.status.success(), "{output:?}");
+    let summary = std::fs::read_to_string(dir.join("summary.md")).unwrap();
+    let row = summary
+        .lines()
+        .rfind(|line| line.starts_with("| input "))
+        .unwrap_or_else(|| panic!("{summary}"));
+    // The shell reads a little more than the input.
+    assert!(row.contains("| `  1.0"), "{row}");
+    assert!(row.contains("Mi ±        0` | `  2.0"), "{row}");
+}
@@ -0,0 +1,190 @@
+//! Run the benchmarker on a shell driver that starts `worker` processes, with a fake perf,
+//! which logs the command line of the process it is attached to, and counts until stopped.
+
+mod common;
+
+use std::process::Output;
+
+use serde_json::Value;
+
+use common::{benchmarker, final_line, git, test_dir, write_script};
+
+const FAKE_PERF: &str = r#"#!/bin/sh
+while [ $# -gt 0 ]; do
+    case "$1" in
+        -o) out="$2"; shift ;;
+        -p) pid="$2"; shift ;;
+    esac
+    shift
+done
+
+sleep "${FAKE_PERF_ATTACH_DELAY:-0}"
+if [ ! -e "/proc/$pid/cmdline" ] || [ "$(cut -d' ' -f3 "/proc/$pid/stat")" = Z ]; then
+    echo "Problems finding threads of monitor" >&2
+    exit 1
+fi
+tr '\000' ' ' < "/proc/$pid/cmdline" >> "$FAKE_PERF_LOG"
+echo >> "$FAKE_PERF_LOG"
+
+trap 'echo "{\"counter-value\" : \"1000\", \"unit\" : \"\", \"event\" : \"cycles\", \"variance\" : 0.00}" > "$out"; exit 0' INT
+while :; do
+    sleep 0.01
+done
+"#;
+
+/// `driver <workers> <seconds of every worker> <seconds after starting them>`
+const DRIVER: &str = r#"#!/bin/sh
+i=0
+while [ $i -lt "$1" ]; do
+    worker "$2" &
+    i=$((i + 1))
+done
+sleep "$3"
+wait
+"#;
+
+/// A script, so it is named `worker` rather than after its interpreter.
+const WORKER: &str = r#"#!/bin/sh
+sleep "$1"
+"#;
+
+/// Run the benchmarker on the driver with `args`, measuring its `measure-child` process,
+/// returning its output, the result of the command and the log of the fake perf.
+fn run(
+    name: &str,
+    args: &str,
+    measure_child: &str,
+    env: &[(&str, &str)],
+) -> (Output, Value, String) {
+    let dir = test_dir(name);
+    for (script, content) in [("perf", FAKE_PERF), ("driver", DRIVER), ("worker", WORKER)] {
+        write_script(&dir, &format!("bin/{script}"), content);
+    }
+    git(&dir, &["init", "--quiet"]);
+    git(&dir, &["commit", "--quiet", "--allow-empty", "-m", "base"]);
+    let commit = git(&dir, &["rev-parse", "HEAD"]);
+
+    let log = dir.join("log");
+    let driver = dir.join("bin/driver");
+    std::fs::write(
+        dir.join("bench.json"),
+        serde_json::json!({
+            "commands": { "child": [{ "command": format!("{} {args}", driver.display()), "measure-child": measure_child }] },
+            "repetitions-for-group": { "child": 2 },
+            "backends-for-group": { "child": ["perf"] },
+            "perf-events-for-group": { "child": ["cycles"] },
+            "measure-child-timeout-ms": 500,
+            "render-versus-self": {},
+            "render-versus-other": {}
+        })
+        .to_string(),
+    )
+    .unwrap();
+
+    let output = benchmarker(&dir)
+        .arg("--stream")
+        .arg(&commit)
+        .arg("bench.json")
+        .arg("does-not-exist.json")
+        .env("FAKE_PERF_LOG", &log)
+        .envs(env.iter().copied())
+        .env_remove("GITHUB_STEP_SUMMARY")
+        .output()
+        .unwrap();
+
+    let bench = final_line(&output)["bench_groups"]["child"][0].clone();
+    (
+        output,
+        bench,
+        std::fs::read_to_string(log).unwrap_or_default(),
+    )
+}
+
+/// The error of a command that failed, after checking that the run did.
+fn error(output: &Output, bench: &Value) -> String {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(4), "{stderr}");
+    assert!(bench["counters"].as_object().unwrap().is_empty(), "{bench}");
+    bench["error"].as_str().unwrap().to_owned()
+}
+
+#[test]
+fn attach_to_worker() {
+    let (output, bench, log) = run("attach", "1 0.3 0", "worker", &[]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    // The counters of the worker, in both runs of the driver.
+    assert_eq!(bench["counters"]["cycles"]["value"], 1000.0, "{bench}");
+    assert_eq!(bench["counters"]["cycles"]["repetitions"], 2, "{bench}");
+    assert!(bench.get("error").is_none(), "{bench}");
+    let attached = log.lines().collect::<Vec<_>>();
+    assert_eq!(attached.len(), 2, "{log}");
+    assert!(
+        attached.iter().all(|line| line.contains("/worker 0.3")),
+        "{log}"
+    );
+}
+
+#[test]
+fn attach_to_worker_by_pattern() {
+    let (output, bench, log) = run("pattern", "1 0.3 0", "/^work(er)?$/", &[]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(bench["counters"]["cycles"]["value"], 1000.0, "{bench}");
+    assert_eq!(log.lines().count(), 2, "{log}");
+}
+
+#[test]
+fn several_workers() {
+    let (output, bench, _) = run("several", "2 1 0", "worker", &[]);
+    let error = error(&output, &bench);
+    assert!(
+        error.starts_with("the command started 2 processes matching `worker`, `worker` (pid "),
+        "{error}"
+    );
+    assert!(error.ends_with(", rather than one"), "{error}");
+}
+
+#[test]
+fn no_worker() {
+    let (output, bench, log) = run("none", "0 0 0", "worker", &[]);
+    assert_eq!(
+        error(&output, &bench),
+        "the command exited without starting a process matching `worker`"
+    );
+    assert_eq!(log, "");
+}
+
+#[test]
+fn no_worker_within_timeout() {
+    // The driver is stopped at the timeout, rather than after its 30 seconds.
+    let (output, bench, _) = run("timeout", "0 0 30", "worker", &[]);
+    assert_eq!(
+        error(&output, &bench),
+        "the command didn't start a process matching `worker` within 500 ms"
+    );
+}
+
+#[test]
+fn worker_exits_before_attach() {
+    let (output, bench, log) = run(
+        "early-exit",
+        "1 0.05 0",
+        "worker",
+        &[("FAKE_PERF_ATTACH_DELAY", "0.5")],
+    );
+    let error = error(&output, &bench);
+    assert!(
+        error.starts_with("`worker` (pid ")
+            && error.ends_with(") exited before perf could attach to it"),
+        "{error}"
+    );
+    assert_eq!(log, "");
+}
@@ -0,0 +1,120 @@
+//! Run the benchmarker in a scratch repository as GitHub Actions would for pushes and pull
+//! requests, where only the results of the default branch are stored and compared against.
+
+mod common;
+
+use std::path::Path;
+use std::process::Output;
+
+use serde_json::{json, Value};
+
+use common::{benchmarker, final_line, scratch_repo, test_dir};
+
+const CONFIG: &str = r#"{
+    "commands": { "tiny": ["true"] },
+    "repetitions-for-group": { "tiny": 2 },
+    "backends-for-group": { "tiny": ["getrusage"] },
+    "render-versus-self": {},
+    "render-versus-other": {}
+}"#;
+
+/// Run on `commit` for the event with the `payload`, with `GITHUB_REF` set to `git_ref`.
+fn run_benchmarker(dir: &Path, commit: &str, git_ref: &str, payload: &Value) -> Output {
+    std::fs::write(dir.join("bench.json"), CONFIG).unwrap();
+    std::fs::write(dir.join("event.json"), payload.to_string()).unwrap();
+    benchmarker(dir)
+        .arg(commit)
+        .arg("bench.json")
+        .arg("previous.json")
+        .args(["--results-file", "previous.json"])
+        .args(["--run-report", "run-report.json"])
+        .env("GITHUB_REF", git_ref)
+        .env("GITHUB_EVENT_PATH", dir.join("event.json"))
+        .env_remove("GITHUB_REF_NAME")
+        .env_remove("GITHUB_HEAD_REF")
+        .env_remove("GITHUB_STEP_SUMMARY")
+        .output()
+        .unwrap()
+}
+
+fn push(branch: &str) -> Value {
+    json!({
+        "ref": format!("refs/heads/{branch}"),
+        "repository": { "full_name": "owner/repo", "default_branch": "main" }
+    })
+}
+
+fn pull_request() -> Value {
+    json!({
+        "number": 7,
+        "pull_request": { "number": 7, "head": { "ref": "feature" }, "base": { "ref": "main" } },
+        "repository": { "full_name": "owner/repo", "default_branch": "main" }
+    })
+}
+
+fn baseline(dir: &Path) -> Value {
+    let report = std::fs::read(dir.join("run-report.json")).unwrap();
+    serde_json::from_slice::<Value>(&report).unwrap()["baseline"].clone()
+}
+
+#[test]
+fn store_and_compare_default_branch_only() {
+    let dir = test_dir("default-branch");
+    let (base, head) = scratch_repo(&dir);
+
+    // A push to a feature branch is printed, but not stored.
+    let output = run_benchmarker(&dir, &base, "refs/heads/feature", &push("feature"));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{stderr}");
+    assert!(
+        stderr.contains(
+            "not writing the results of the `feature` branch to previous.json, only those of `main` are stored"
+        ),
+        "{stderr}"
+    );
+    assert!(!dir.join("previous.json").exists());
+    let feature = final_line(&output);
+    assert_eq!(
+        feature["trigger"],
+        json!({ "ref": "refs/heads/feature", "branch": "feature" })
+    );
+
+    // Results of the feature branch that got stored anyway are never a baseline.
+    std::fs::write(dir.join("previous.json"), format!("{feature}\n")).unwrap();
+    let output = run_benchmarker(&dir, &head, "refs/pull/7/merge", &pull_request());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{stderr}");
+    assert_eq!(baseline(&dir)["commit"], Value::Null);
+    assert_eq!(
+        baseline(&dir)["reason"].as_str().unwrap(),
+        format!("no previous results for {base}"),
+    );
+    assert_eq!(
+        final_line(&output)["trigger"],
+        json!({ "ref": "refs/pull/7/merge", "branch": "feature", "pull_request": 7 })
+    );
+    assert!(
+        stderr.contains(
+            "not writing the results of pull request #7 (`feature`) to previous.json, only those of `main` are stored"
+        ),
+        "{stderr}"
+    );
+
+    // A push to the default branch is stored, replacing the results of the same commit like a
+    // retried step, and is the baseline of the pull request.
+    let output = run_benchmarker(&dir, &base, "refs/heads/main", &push("main"));
+    assert!(output.status.success(), "{output:?}");
+    let stored = std::fs::read_to_string(dir.join("previous.json")).unwrap();
+    assert_eq!(stored.lines().count(), 1);
+    let main = serde_json::from_str::<Value>(&stored).unwrap();
+    assert_eq!(
+        main["trigger"],
+        json!({ "ref": "refs/heads/main", "branch": "main" })
+    );
+
+    let output = run_benchmarker(&dir, &head, "refs/pull/7/merge", &pull_request());
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(baseline(&dir), json!({ "commit": base }));
+    let stored_after = std::fs::read_to_string(dir.join("previous.json")).unwrap();
+    assert_eq!(stored_after, stored);
+}
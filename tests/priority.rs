@@ -0,0 +1,103 @@
+//! Run the benchmarker with `priority-for-group` in a scratch repository, on commands that log
+//! when they run, to compare the order they ran in with the order of the results.
+
+mod common;
+
+use std::path::Path;
+use std::process::Output;
+
+use serde_json::{json, Value};
+
+use common::{benchmarker, git, test_dir, write_script};
+
+/// `log <name>`, appending the name to `log.txt`.
+const LOG: &str = r#"#!/bin/sh
+echo "$1" >> log.txt
+"#;
+
+fn run_benchmarker(dir: &Path, commit: &str) -> Output {
+    let config = json!({
+        "commands": {
+            "raw": ["./log raw"],
+            "headline": ["./log headline-1", "./log headline-2"],
+            "startup": ["./log startup"],
+            "later": ["./log later"]
+        },
+        "repetitions-for-group": { "raw": 1, "headline": 1, "startup": 1, "later": 1 },
+        "backends-for-group": {
+            "raw": ["getrusage"],
+            "headline": ["getrusage"],
+            "startup": ["getrusage"],
+            "later": ["getrusage"]
+        },
+        "priority-for-group": { "startup": 10, "later": 500 },
+        "prioritize-rendered-groups": true,
+        "render-versus-self": {
+            "headline": {
+                "2 vs 1": {
+                    "measure": "wall-time",
+                    "before": { "command": "headline", "index": 0 },
+                    "after": { "command": "headline", "index": 1 }
+                }
+            }
+        },
+        "render-versus-other": {}
+    });
+    std::fs::write(dir.join("bench.json"), config.to_string()).unwrap();
+    benchmarker(dir)
+        .arg(commit)
+        .arg("bench.json")
+        .arg("previous.json")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn run_by_priority() {
+    let dir = test_dir("order");
+    write_script(&dir, "log", LOG);
+    git(&dir, &["init", "--quiet"]);
+    git(&dir, &["commit", "--quiet", "--allow-empty", "-m", "base"]);
+    let commit = git(&dir, &["rev-parse", "HEAD"]);
+
+    let output = run_benchmarker(&dir, &commit);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{stderr}");
+    assert!(
+        stderr.contains(
+            "running the groups by priority: `startup` (10), `headline` (50), `raw` (100), `later` (500)\n"
+        ),
+        "{stderr}"
+    );
+
+    // The groups run by priority, the warmup run of getrusage too.
+    let log = std::fs::read_to_string(dir.join("log.txt")).unwrap();
+    let mut order = log.lines().collect::<Vec<_>>();
+    order.dedup();
+    assert_eq!(
+        order,
+        ["startup", "headline-1", "headline-2", "raw", "later"]
+    );
+
+    // The results and the summary keep the order of the config.
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let results: Value = serde_json::from_str(stdout.lines().last().unwrap()).unwrap();
+    let groups = results["bench_groups"]
+        .as_object()
+        .unwrap()
+        .keys()
+        .collect::<Vec<_>>();
+    assert_eq!(groups, ["raw", "headline", "startup", "later"]);
+    let summary = std::fs::read_to_string(dir.join("summary.md")).unwrap();
+    // The raw tables, after the comparison tables, with those of compared groups collapsed.
+    let (_, raw) = summary.split_once("Seed: ").unwrap();
+    let positions = [
+        "### raw\n",
+        "Raw results: headline ",
+        "### startup\n",
+        "### later\n",
+    ]
+    .map(|heading| raw.find(heading));
+    assert!(positions.iter().all(Option::is_some), "{summary}");
+    assert!(positions.is_sorted(), "{summary}");
+}
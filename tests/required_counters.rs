@@ -0,0 +1,96 @@
+//! Run the benchmarker with a fake perf that, like the real one, only counts the events it
+//! knows, and complains about the others on stderr.
+
+mod common;
+
+use serde_json::{json, Value};
+
+use common::{benchmarker, fake_perf, git, test_dir};
+
+/// Counts only `cycles`, and complains about the other events.
+const CYCLES_ONLY: &str = r#"for event in $(echo "$events" | tr , ' '); do
+    if [ "$event" = cycles ]; then
+        echo '{"counter-value" : "1000", "unit" : "", "event" : "cycles", "variance" : 0.10}'
+    else
+        echo "event syntax error: '$event'" >&2
+    fi
+done"#;
+
+#[test]
+fn fail_on_misspelled_event() {
+    let dir = test_dir("misspelled");
+    fake_perf(&dir, CYCLES_ONLY);
+    git(&dir, &["init", "--quiet"]);
+    git(&dir, &["commit", "--quiet", "--allow-empty", "-m", "base"]);
+    let commit = git(&dir, &["rev-parse", "HEAD"]);
+
+    std::fs::write(
+        dir.join("bench.json"),
+        json!({
+            "commands": { "good": ["true"], "bad": ["true", "true again"] },
+            "repetitions-for-group": { "good": 2, "bad": 2 },
+            "backends-for-group": { "good": ["perf"], "bad": ["perf"] },
+            "perf-events-for-group": { "good": ["cycles"], "bad": ["cycles", "instuctions"] },
+            "render-versus-self": {},
+            "render-versus-other": {}
+        })
+        .to_string(),
+    )
+    .unwrap();
+
+    let output = benchmarker(&dir)
+        .arg(&commit)
+        .arg("bench.json")
+        .arg("does-not-exist.json")
+        .args(["--run-report", "run-report.json"])
+        .output()
+        .unwrap();
+
+    // Every command runs, and the run fails at the end.
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(4), "{stderr}");
+    let error = "perf reported no data for events: instuctions — check event names\n\
+                 === perf stderr ===\n\
+                 event syntax error: 'instuctions'";
+    assert!(
+        stderr.contains(&format!("error: `true` failed: {error}\n")),
+        "{stderr}"
+    );
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let last = serde_json::from_str::<Value>(stdout.lines().last().unwrap()).unwrap();
+    assert!(last["bench_groups"]["good"][0].get("error").is_none());
+    assert_eq!(last["bench_groups"]["bad"][0]["error"], error);
+    assert_eq!(
+        last["bench_groups"]["bad"][0]["counters"]["cycles"]["value"],
+        1000.0
+    );
+    assert_eq!(last["bench_groups"]["bad"][1]["error"], error);
+
+    let report = std::fs::read(dir.join("run-report.json")).unwrap();
+    let report = serde_json::from_slice::<Value>(&report).unwrap();
+    assert_eq!(report["exit_code"], 4);
+    assert_eq!(report["groups"]["good"]["status"], "completed");
+    assert!(report["groups"]["good"].get("errors").is_none());
+    assert_eq!(report["groups"]["bad"]["status"], "failed");
+    assert_eq!(report["groups"]["bad"]["completed"], 0);
+    assert_eq!(report["groups"]["bad"]["failed"], 2);
+    assert_eq!(
+        report["groups"]["bad"]["errors"][0],
+        json!({ "command": "true", "error": error })
+    );
+
+    let summary = std::fs::read_to_string(dir.join("summary.md")).unwrap();
+    assert!(
+        summary.contains(
+            "> [!CAUTION]\n\
+             > 2 commands failed, their results are incomplete:\n\
+             > - bad / `true`: perf reported no data for events: instuctions — check event names\n\
+             >\n\
+             >   ```\n\
+             >   event syntax error: 'instuctions'\n\
+             >   ```\n"
+        ),
+        "{summary}"
+    );
+}
@@ -0,0 +1,84 @@
+//! Run the benchmarker with `round-robin` in a scratch repository, with a fake perf that logs
+//! what it runs, on a suite of three commands in two groups with different repetitions.
+
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::path::Path;
+use std::process::Output;
+
+use serde_json::{json, Value};
+
+use common::{benchmarker, fake_perf, git, perf_log, test_dir, EVERY_EVENT};
+
+fn run_benchmarker(dir: &Path, commit: &str, config: &Value) -> Output {
+    std::fs::write(dir.join("bench.json"), config.to_string()).unwrap();
+    benchmarker(dir)
+        .arg(commit)
+        .arg("bench.json")
+        .arg("previous.json")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn measure_suite_round_robin() {
+    let dir = test_dir("suite");
+    fake_perf(&dir, EVERY_EVENT);
+    git(&dir, &["init", "--quiet"]);
+    git(&dir, &["commit", "--quiet", "--allow-empty", "-m", "base"]);
+    let commit = git(&dir, &["rev-parse", "HEAD"]);
+
+    let config = json!({
+        "commands": {
+            "compress": ["true level-1", "true level-9"],
+            "decompress": ["true decompress"]
+        },
+        "round-robin": true,
+        "repetitions-for-group": { "compress": 3, "decompress": 1 },
+        "backends-for-group": { "compress": ["perf"], "decompress": ["perf"] },
+        "perf-events-for-group": { "compress": ["cycles"], "decompress": ["cycles"] },
+        "render-versus-self": {},
+        "render-versus-other": {}
+    });
+    let output = run_benchmarker(&dir, &commit, &config);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{stderr}");
+    assert!(
+        stderr.contains("Benchmarking 3 commands of 2 groups round-robin\n"),
+        "{stderr}"
+    );
+
+    // A single repetition of every command at a time, the decompression drops out after its
+    // only one.
+    assert_eq!(
+        perf_log(&dir),
+        [
+            "cycles 1 . true level-1",
+            "cycles 1 . true level-9",
+            "cycles 1 . true decompress",
+            "cycles 1 . true level-1",
+            "cycles 1 . true level-9",
+            "cycles 1 . true level-1",
+            "cycles 1 . true level-9",
+        ]
+    );
+
+    // The runs of every command are aggregated in its group as usual.
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let results: Value = serde_json::from_str(stdout.lines().last().unwrap()).unwrap();
+    for (group_name, index, command, repetitions) in [
+        ("compress", 0, ["true", "level-1"], 3),
+        ("compress", 1, ["true", "level-9"], 3),
+        ("decompress", 0, ["true", "decompress"], 1),
+    ] {
+        let bench = &results["bench_groups"][group_name][index];
+        assert_eq!(bench["cmd"], json!(command));
+        assert_eq!(bench["counters"]["cycles"]["value"], 1000.0);
+        assert_eq!(
+            bench["counters"]["cycles"]["repetitions"], repetitions,
+            "{bench}"
+        );
+    }
+}
@@ -0,0 +1,582 @@
+//! Run the benchmarker in a scratch git repository and check the run report it writes.
+
+mod common;
+
+use std::path::{Path, PathBuf};
+use std::process::Output;
+
+use serde_json::{json, Value};
+
+use benchmarker::testkit::BenchDataBuilder;
+use common::{benchmarker, git, scratch_repo, test_dir, write_script};
+
+fn run_benchmarker(dir: &Path, commit: &str, config: &str, previous_results: &Path) -> Output {
+    run_benchmarker_with_args(dir, commit, config, previous_results, &[])
+}
+
+fn run_benchmarker_with_args(
+    dir: &Path,
+    commit: &str,
+    config: &str,
+    previous_results: &Path,
+    args: &[&str],
+) -> Output {
+    std::fs::write(dir.join("bench.json"), config).unwrap();
+    benchmarker(dir)
+        .arg(commit)
+        .arg("bench.json")
+        .arg(previous_results)
+        .args(["--run-report", "run-report.json"])
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn read_report(dir: &Path) -> Value {
+    serde_json::from_slice(&std::fs::read(dir.join("run-report.json")).unwrap()).unwrap()
+}
+
+const CONFIG: &str = r#"{
+    "commands": { "trivial": ["true"] },
+    "repetitions-for-group": { "trivial": 2 },
+    "backends-for-group": { "trivial": ["getrusage"] },
+    "gate": { "max-regression-percent": 1000 },
+    "render-versus-self": {},
+    "render-versus-other": {}
+}"#;
+
+#[test]
+fn report_successful_run() {
+    let dir = test_dir("success");
+    let (base, head) = scratch_repo(&dir);
+
+    // Benchmark the base commit first, so there are previous results to compare against.
+    let output = run_benchmarker(&dir, &base, CONFIG, &dir.join("does-not-exist.json"));
+    assert!(output.status.success());
+    std::fs::write(dir.join("previous.json"), output.stdout).unwrap();
+
+    let output = run_benchmarker(&dir, &head, CONFIG, &dir.join("previous.json"));
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let report = read_report(&dir);
+    assert_eq!(report["exit_code"], 0);
+    assert_eq!(report["baseline"], json!({ "commit": base }));
+    assert_eq!(
+        report["groups"],
+        json!({
+            "trivial": {
+                "status": "completed",
+                "commands": 1,
+                "completed": 1,
+                "failed": 0,
+                "backends": ["getrusage"],
+            }
+        })
+    );
+    assert_eq!(report["gate"], json!({ "failures": [] }));
+    assert_eq!(
+        report["artifacts"],
+        json!({ "step-summary": dir.join("summary.md") })
+    );
+}
+
+#[test]
+fn report_failed_command() {
+    let dir = test_dir("failed");
+    let (_, head) = scratch_repo(&dir);
+
+    let config = r#"{
+        "commands": { "ok": ["true"], "broken": ["true", "false", "true"], "after": ["true"] },
+        "backends-for-group": { "ok": ["getrusage"], "broken": ["getrusage"] },
+        "repetitions-for-group": { "ok": 2, "broken": 2 },
+        "gate": { "max-regression-percent": 5 },
+        "render-versus-self": {},
+        "render-versus-other": {}
+    }"#;
+    let output = run_benchmarker(&dir, &head, config, &dir.join("does-not-exist.json"));
+    assert_eq!(output.status.code(), Some(101));
+    // No results, not even partial ones.
+    assert!(output.stdout.is_empty());
+
+    let report = read_report(&dir);
+    assert_eq!(report["exit_code"], 101);
+    assert_eq!(report["groups"]["ok"]["status"], "completed");
+    assert_eq!(
+        report["groups"]["broken"],
+        json!({
+            "status": "failed",
+            "commands": 3,
+            "completed": 1,
+            "failed": 1,
+            "backends": ["getrusage"],
+        })
+    );
+    assert_eq!(
+        report["groups"]["after"],
+        json!({
+            "status": "skipped",
+            "commands": 1,
+            "completed": 0,
+            "failed": 0,
+            "backends": [],
+        })
+    );
+    // The run never got to the comparisons.
+    assert_eq!(report["gate"], Value::Null);
+    assert_eq!(report["artifacts"], json!({}));
+}
+
+#[test]
+fn report_without_baseline() {
+    let dir = test_dir("no-baseline");
+    let (_, head) = scratch_repo(&dir);
+
+    let output = run_benchmarker(&dir, &head, CONFIG, &dir.join("does-not-exist.json"));
+    assert!(output.status.success());
+    let report = read_report(&dir);
+    assert_eq!(report["baseline"]["commit"], Value::Null);
+    let reason = report["baseline"]["reason"].as_str().unwrap();
+    assert!(reason.contains("does-not-exist.json"), "{reason}");
+
+    // The results of a different commit don't count.
+    std::fs::write(dir.join("previous.json"), output.stdout).unwrap();
+    let output = run_benchmarker(&dir, &head, CONFIG, &dir.join("previous.json"));
+    assert!(output.status.success());
+    let report = read_report(&dir);
+    assert_eq!(report["exit_code"], 0);
+    assert_eq!(report["baseline"]["commit"], Value::Null);
+    let reason = report["baseline"]["reason"].as_str().unwrap();
+    assert!(reason.starts_with("no previous results for "), "{reason}");
+    assert_eq!(report["groups"]["trivial"]["status"], "completed");
+}
+
+#[test]
+fn report_kept_scratch() {
+    let dir = test_dir("scratch");
+    let (_, head) = scratch_repo(&dir);
+
+    let config = |command: &str| {
+        format!(
+            r#"{{
+                "commands": {{ "trivial": ["{command}"] }},
+                "repetitions-for-group": {{ "trivial": 2 }},
+                "backends-for-group": {{ "trivial": ["getrusage"] }},
+                "scratch-root": "scratch",
+                "keep-scratch-on-failure": true,
+                "render-versus-self": {{}},
+                "render-versus-other": {{}}
+            }}"#
+        )
+    };
+    let scratch_dirs = || std::fs::read_dir(dir.join("scratch")).unwrap().count();
+
+    // Removed after a successful run.
+    let output = run_benchmarker(&dir, &head, &config("true"), &dir.join("none.json"));
+    assert!(output.status.success());
+    assert_eq!(scratch_dirs(), 0);
+    assert_eq!(read_report(&dir)["artifacts"].get("scratch"), None);
+
+    // Kept after a failed one, and recorded in the report.
+    let output = run_benchmarker(&dir, &head, &config("false"), &dir.join("none.json"));
+    assert_eq!(output.status.code(), Some(101));
+    assert_eq!(scratch_dirs(), 1);
+    let kept = PathBuf::from(read_report(&dir)["artifacts"]["scratch"].as_str().unwrap());
+    assert!(dir.join(&kept).is_dir());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("scratch directory kept at"));
+}
+
+#[test]
+fn report_config_directory() {
+    let dir = test_dir("config-directory");
+    let (_, head) = scratch_repo(&dir);
+
+    let configs = dir.join("configs");
+    std::fs::create_dir(&configs).unwrap();
+    std::fs::write(configs.join("a.json"), CONFIG).unwrap();
+    std::fs::write(
+        configs.join("b.json"),
+        r#"{
+            "commands": { "broken": ["false"] },
+            "backends-for-group": { "broken": ["getrusage"] },
+            "repetitions-for-group": { "broken": 2 }
+        }"#,
+    )
+    .unwrap();
+
+    let output = benchmarker(&dir)
+        .args([&head, "configs", "does-not-exist.json"])
+        .args(["--run-report", "run-report.json"])
+        .env_remove("GITHUB_STEP_SUMMARY")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(101));
+    // The failure points at the file that defined the group.
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("(in the `broken` group from configs/b.json)"),
+        "{stderr}"
+    );
+
+    let report = read_report(&dir);
+    assert_eq!(report["groups"]["trivial"]["config"], "configs/a.json");
+    assert_eq!(report["groups"]["trivial"]["status"], "completed");
+    assert_eq!(report["groups"]["broken"]["config"], "configs/b.json");
+    assert_eq!(report["groups"]["broken"]["status"], "failed");
+}
+
+#[test]
+fn report_dirty_working_tree() {
+    let dir = test_dir("dirty");
+    let (base, head) = scratch_repo(&dir);
+    let final_line = |output: &Output| -> Value { serde_json::from_slice(&output.stdout).unwrap() };
+
+    // Clean, the files the run writes itself are untracked.
+    let output = run_benchmarker(&dir, &base, CONFIG, &dir.join("does-not-exist.json"));
+    assert!(output.status.success());
+    let clean = output.stdout;
+    assert_eq!(read_report(&dir)["dirty"], false);
+    let results: Value = serde_json::from_slice(&clean).unwrap();
+    assert_eq!(results["dirty"], Value::Null);
+
+    // A staged change makes the tree dirty.
+    std::fs::write(dir.join("patch.rs"), "fn candidate_fix() {}\n").unwrap();
+    git(&dir, &["add", "patch.rs"]);
+
+    // Refused: the results are still printed, but the exit code keeps them from being stored.
+    let output = run_benchmarker(&dir, &base, CONFIG, &dir.join("does-not-exist.json"));
+    assert_eq!(output.status.code(), Some(3));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--allow-dirty"), "{stderr}");
+    assert_eq!(final_line(&output)["dirty"], true);
+    let report = read_report(&dir);
+    assert_eq!(report["exit_code"], 3);
+    assert_eq!(report["dirty"], true);
+
+    // Allowed.
+    let output = run_benchmarker_with_args(
+        &dir,
+        &base,
+        CONFIG,
+        &dir.join("does-not-exist.json"),
+        &["--allow-dirty"],
+    );
+    assert!(output.status.success());
+    let results = final_line(&output);
+    assert_eq!(results["dirty"], true);
+    assert_eq!(results["commit_hash"], base.as_str());
+    assert_eq!(results["diff_sha256"].as_str().unwrap().len(), 64);
+    assert_eq!(read_report(&dir)["dirty"], true);
+    let dirty = output.stdout;
+
+    // A dirty result of the base commit is never the baseline.
+    std::fs::write(dir.join("previous.json"), &dirty).unwrap();
+    let output = run_benchmarker_with_args(
+        &dir,
+        &head,
+        CONFIG,
+        &dir.join("previous.json"),
+        &["--allow-dirty"],
+    );
+    assert!(output.status.success());
+    let report = read_report(&dir);
+    assert_eq!(report["baseline"]["commit"], Value::Null);
+    assert_eq!(
+        report["baseline"]["reason"],
+        format!("no previous results for {base}")
+    );
+    let summary = std::fs::read_to_string(dir.join("summary.md")).unwrap();
+    assert!(summary.contains(&format!("`{head}-dirty`")), "{summary}");
+
+    // The clean one is, even when the dirty one comes first.
+    std::fs::write(dir.join("previous.json"), [dirty, clean].concat()).unwrap();
+    let output = run_benchmarker_with_args(
+        &dir,
+        &head,
+        CONFIG,
+        &dir.join("previous.json"),
+        &["--allow-dirty"],
+    );
+    assert!(output.status.success());
+    assert_eq!(read_report(&dir)["baseline"], json!({ "commit": base }));
+}
+
+#[test]
+fn report_dirty_results_file() {
+    let dir = test_dir("dirty-results-file");
+    let (base, _) = scratch_repo(&dir);
+    let previous_results = dir.join("does-not-exist.json");
+    let args = ["--results-file", "results.json"];
+    let output = run_benchmarker_with_args(&dir, &base, CONFIG, &previous_results, &args);
+    assert!(output.status.success());
+    let stored = std::fs::read_to_string(dir.join("results.json")).unwrap();
+
+    // The results of a dirty tree are refused for --results-file, but still reported.
+    std::fs::write(dir.join("patch.rs"), "fn candidate_fix() {}\n").unwrap();
+    git(&dir, &["add", "patch.rs"]);
+    let output = run_benchmarker_with_args(&dir, &base, CONFIG, &previous_results, &args);
+    assert_eq!(output.status.code(), Some(3));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("warning: not writing the results of a dirty working tree to results.json"),
+        "{stderr}"
+    );
+    assert_eq!(
+        std::fs::read_to_string(dir.join("results.json")).unwrap(),
+        stored
+    );
+    assert!(read_report(&dir)["artifacts"].get("results").is_none());
+
+    // With --allow-dirty they are added to the file, marked as dirty.
+    let args = ["--results-file", "results.json", "--allow-dirty"];
+    let output = run_benchmarker_with_args(&dir, &base, CONFIG, &previous_results, &args);
+    assert!(output.status.success());
+    let results = std::fs::read_to_string(dir.join("results.json")).unwrap();
+    assert!(results.starts_with(&stored), "{results}");
+    let added: Value = serde_json::from_str(results.lines().last().unwrap()).unwrap();
+    assert_eq!(added["dirty"], true);
+}
+
+#[test]
+fn report_two_suites_in_one_job() {
+    let dir = test_dir("suites");
+    let (_, head) = scratch_repo(&dir);
+    let suite = |name: &str| {
+        let config = format!(
+            r#"{{
+                "commands": {{ "{name}": ["true"] }},
+                "repetitions-for-group": {{ "{name}": 2 }},
+                "backends-for-group": {{ "{name}": ["getrusage"] }},
+                "render-versus-self": {{}},
+                "render-versus-other": {{}}
+            }}"#
+        );
+        std::fs::write(dir.join(format!("{name}.json")), config).unwrap();
+        let output = benchmarker(&dir)
+            .arg(&head)
+            .arg(format!("{name}.json"))
+            .arg("does-not-exist.json")
+            .args(["--results-file", "bench_results.json"])
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    };
+    let summary = || std::fs::read_to_string(dir.join("summary.md")).unwrap();
+    let results = || {
+        std::fs::read_to_string(dir.join("bench_results.json"))
+            .unwrap()
+            .lines()
+            .map(|line| {
+                let line: Value = serde_json::from_str(line).unwrap();
+                line["bench_groups"]
+                    .as_object()
+                    .unwrap()
+                    .keys()
+                    .cloned()
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>()
+    };
+
+    suite("compression");
+    let first = summary();
+    assert!(first.starts_with(&format!(
+        "<!-- benchmarker commit={head} config=compression.json -->\n"
+    )));
+    assert_eq!(results(), [["compression"]]);
+
+    // The second suite doesn't clobber the first.
+    suite("parsing");
+    let both = summary();
+    assert!(both.starts_with(&first), "{both}");
+    assert!(both.contains(&format!(
+        "<!-- benchmarker commit={head} config=parsing.json -->\n"
+    )));
+    assert!(both.contains("### compression") && both.contains("### parsing"));
+    assert_eq!(results(), [["compression"], ["parsing"]]);
+
+    // A retried step replaces what it wrote before.
+    suite("compression");
+    let retried = summary();
+    assert_eq!(retried.matches("<!-- benchmarker commit=").count(), 2);
+    assert_eq!(retried.matches("### compression").count(), 1);
+    assert!(retried.find("### compression") < retried.find("### parsing"));
+    assert_eq!(results(), [["compression"], ["parsing"]]);
+}
+
+#[test]
+fn report_ancestor_baseline() {
+    let dir = test_dir("ancestor-baseline");
+    git(&dir, &["init", "--quiet"]);
+    let mut main = vec![];
+    for message in ["one", "two", "three"] {
+        git(&dir, &["commit", "--quiet", "--allow-empty", "-m", message]);
+        main.push(git(&dir, &["rev-parse", "HEAD"]));
+    }
+    git(&dir, &["update-ref", "refs/remotes/origin/main", "HEAD"]);
+    git(
+        &dir,
+        &["commit", "--quiet", "--allow-empty", "-m", "change"],
+    );
+    let head = git(&dir, &["rev-parse", "HEAD"]);
+
+    // Results for the first commit, and for the second one from another machine, leaving a
+    // gap at the merge base.
+    let output = run_benchmarker(&dir, &main[0], CONFIG, &dir.join("does-not-exist.json"));
+    assert!(output.status.success());
+    let other_machine = BenchDataBuilder::new(&main[1])
+        .machine("X64", "Linux", "some other cpu")
+        .machine_class("some-other-cpu/1")
+        .machine_id("0123456789abcdef")
+        .build();
+    let mut previous = output.stdout;
+    previous.extend(serde_json::to_string(&other_machine).unwrap().as_bytes());
+    previous.push(b'\n');
+    std::fs::write(dir.join("previous.json"), previous).unwrap();
+
+    let output = run_benchmarker(&dir, &head, CONFIG, &dir.join("previous.json"));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{stderr}");
+    assert!(
+        stderr.contains(&format!(
+            "warning: no previous results for {}, comparing against {} (2 commits before merge-base)",
+            main[2], main[0]
+        )),
+        "{stderr}"
+    );
+    let report = read_report(&dir);
+    assert_eq!(
+        report["baseline"],
+        json!({ "commit": main[0], "ancestor_distance": 2 })
+    );
+    let summary = std::fs::read_to_string(dir.join("summary.md")).unwrap();
+    assert!(
+        summary.contains(&format!(
+            " versus ancestor [`{}`](https://github.com/owner/repo/commit/{}) (2 commits before merge-base) (on ",
+            main[0], main[0]
+        )),
+        "{summary}"
+    );
+
+    // Not when the first commit is beyond the configured depth.
+    let config = CONFIG.replacen('{', r#"{ "baseline-ancestor-depth": 1,"#, 1);
+    let output = run_benchmarker(&dir, &head, &config, &dir.join("previous.json"));
+    assert!(output.status.success());
+    let report = read_report(&dir);
+    assert_eq!(report["baseline"]["commit"], Value::Null);
+    assert_eq!(
+        report["baseline"]["reason"],
+        format!("no previous results for {}", main[2])
+    );
+}
+
+#[test]
+fn report_sanitized_run() {
+    let dir = test_dir("sanitize");
+    let (base, head) = scratch_repo(&dir);
+    // Canned counters, so that every row has a relative change to the previous results.
+    write_script(
+        &dir,
+        "stats.sh",
+        "#!/bin/sh\necho '{ \"counters\": { \"instructions\": { \"value\": 1000 } } }'\n",
+    );
+    let config = format!(
+        r#"{{
+            "commands": {{ "trivial": ["test -d {}"] }},
+            "repetitions-for-group": {{ "trivial": 2 }},
+            "backends-for-group": {{ "trivial": [{{ "external": "{}" }}] }},
+            "sanitize": {{ "rules": [{{ "pattern": "benchmarker-test-\\d+", "replacement": "<tmp>" }}] }},
+            "render-versus-self": {{}},
+            "render-versus-other": {{}}
+        }}"#,
+        dir.display(),
+        dir.join("stats.sh").display()
+    );
+    let output = run_benchmarker(&dir, &base, &config, &dir.join("does-not-exist.json"));
+    assert!(output.status.success());
+    std::fs::write(dir.join("previous.json"), &output.stdout).unwrap();
+
+    let output = run_benchmarker(&dir, &head, &config, &dir.join("previous.json"));
+    assert!(output.status.success());
+    let real = format!("benchmarker-test-{}", std::process::id());
+    let summary = std::fs::read_to_string(dir.join("summary.md")).unwrap();
+    let report = std::fs::read_to_string(dir.join("run-report.json")).unwrap();
+    for published in [
+        &String::from_utf8(output.stdout).unwrap(),
+        &summary,
+        &report,
+    ] {
+        assert!(!published.contains(&real), "{published}");
+        assert!(
+            published.contains("<tmp>-run-report-sanitize"),
+            "{published}"
+        );
+    }
+    // The sanitized command of the previous results is still found as the baseline.
+    let compared = summary.rsplit("### trivial").next().unwrap();
+    assert!(
+        compared.contains("<tmp>-run-report-sanitize`|`1000±0`"),
+        "{summary}"
+    );
+    assert!(!compared.contains("n.a."), "{summary}");
+    assert!(report.contains(&base), "{report}");
+}
+
+#[test]
+fn report_stamp_verified() {
+    let dir = test_dir("stamp");
+    let (base, head) = scratch_repo(&dir);
+
+    let output = run_benchmarker(&dir, &base, CONFIG, &dir.join("does-not-exist.json"));
+    assert!(output.status.success());
+    std::fs::write(dir.join("results.json"), &output.stdout).unwrap();
+    let output = run_benchmarker(&dir, &head, CONFIG, &dir.join("results.json"));
+    assert!(output.status.success());
+    let mut results = std::fs::read(dir.join("results.json")).unwrap();
+    results.extend_from_slice(&output.stdout);
+    std::fs::write(dir.join("results.json"), results).unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("stamp: run local, config "), "{stderr}");
+    let report = read_report(&dir);
+    assert_eq!(report["stamp"]["commit"], head);
+    for hash in ["config_sha256", "baseline_sha256", "results_sha256"] {
+        assert_eq!(
+            report["stamp"][hash].as_str().unwrap().len(),
+            64,
+            "{report}"
+        );
+    }
+    let summary = std::fs::read_to_string(dir.join("summary.md")).unwrap();
+    assert!(summary.contains("<sub>run local · config "), "{summary}");
+    assert!(summary.contains("<!-- benchmarker-stamp {"), "{summary}");
+
+    let verify = || {
+        benchmarker(&dir)
+            .args([
+                "verify-report",
+                "run-report.json",
+                "bench.json",
+                "results.json",
+            ])
+            .output()
+            .unwrap()
+    };
+    let output = verify();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{stdout}");
+    assert!(stdout.ends_with("gate: passed, as reported\n"), "{stdout}");
+
+    std::fs::write(dir.join("bench.json"), CONFIG.replace("1000", "999")).unwrap();
+    let output = verify();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(1), "{stdout}");
+    assert!(stdout.contains("config: doesn't match"), "{stdout}");
+}
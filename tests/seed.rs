@@ -0,0 +1,86 @@
+//! Run the benchmarker with `shuffle-for-group` in a scratch repository, on commands that log
+//! when they run, to compare the random choices of runs with the same seed.
+
+mod common;
+
+use std::path::Path;
+use std::process::Output;
+
+use serde_json::json;
+
+use common::{benchmarker, final_line, git, test_dir, write_script};
+
+/// `log <name>`, appending the name to `log.txt`.
+const LOG: &str = r#"#!/bin/sh
+echo "$1" >> log.txt
+"#;
+
+/// Run the shuffled suite with `args`, returning the output and the order in which the
+/// commands ran.
+fn run_benchmarker(dir: &Path, commit: &str, args: &[&str]) -> (Output, Vec<String>) {
+    let config = json!({
+        "commands": {
+            "work": ["./log a", "./log b", "./log c", "./log d"]
+        },
+        "repetitions-for-group": { "work": 3 },
+        "backends-for-group": { "work": ["getrusage"] },
+        "interleave-for-group": { "work": true },
+        "shuffle-for-group": { "work": true },
+        "render-versus-self": {
+            "b vs a": {
+                "work": {
+                    "measure": "wall-time",
+                    "before": { "command": "work", "index": 0 },
+                    "after": { "command": "work", "index": 1 }
+                }
+            }
+        },
+        "render-versus-other": {}
+    });
+    std::fs::write(dir.join("bench.json"), config.to_string()).unwrap();
+    let _ = std::fs::remove_file(dir.join("log.txt"));
+    let output = benchmarker(dir)
+        .arg(commit)
+        .arg("bench.json")
+        .arg("previous.json")
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    let log = std::fs::read_to_string(dir.join("log.txt")).unwrap();
+    (output, log.lines().map(str::to_owned).collect())
+}
+
+#[test]
+fn same_seed_same_order() {
+    let dir = test_dir("order");
+    write_script(&dir, "log", LOG);
+    git(&dir, &["init", "--quiet"]);
+    git(&dir, &["commit", "--quiet", "--allow-empty", "-m", "base"]);
+    let commit = git(&dir, &["rev-parse", "HEAD"]);
+
+    // A seed is generated and recorded without `--seed`.
+    let (output, order) = run_benchmarker(&dir, &commit, &[]);
+    let seed = final_line(&output)["seed"].as_u64().unwrap();
+    let summary = std::fs::read_to_string(dir.join("summary.md")).unwrap();
+    assert!(summary.contains(&format!("Seed: `{seed}`\n")), "{summary}");
+    // The interleaved commands run in every round, the warmup one of getrusage too.
+    assert_eq!(order.len(), 16);
+    assert_eq!(order.iter().filter(|name| *name == "a").count(), 4);
+
+    let (output, replayed) = run_benchmarker(&dir, &commit, &["--seed", &seed.to_string()]);
+    assert_eq!(final_line(&output)["seed"], seed);
+    assert_eq!(replayed, order);
+
+    // The seed of stored results.
+    std::fs::write(dir.join("results.json"), &output.stdout).unwrap();
+    let (output, replayed) =
+        run_benchmarker(&dir, &commit, &["--replay-seed-from", "results.json"]);
+    assert_eq!(final_line(&output)["seed"], seed);
+    assert_eq!(replayed, order);
+
+    // Other seeds make other choices.
+    let (_, first) = run_benchmarker(&dir, &commit, &["--seed=1"]);
+    let (_, second) = run_benchmarker(&dir, &commit, &["--seed=2"]);
+    assert_ne!(first, second);
+}
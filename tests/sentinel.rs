@@ -0,0 +1,103 @@
+//! Run the benchmarker with a `sentinel-group` in a scratch repository, on commands that log
+//! when they run.
+
+mod common;
+
+use std::path::Path;
+use std::process::Output;
+
+use serde_json::{json, Value};
+
+use common::{benchmarker, git, test_dir, write_script};
+
+/// `log <name>`, appending the name to `log.txt`.
+const LOG: &str = r#"#!/bin/sh
+echo "$1" >> log.txt
+"#;
+
+fn run_benchmarker(dir: &Path, commit: &str) -> Output {
+    let config = json!({
+        "commands": {
+            "first": ["./log first"],
+            "sentinel": ["./log sentinel-1", "./log sentinel-2"],
+            "last": ["./log last"]
+        },
+        "sentinel-group": "sentinel",
+        "repetitions-for-group": { "first": 1, "sentinel": 2, "last": 1 },
+        "backends-for-group": {
+            "first": ["getrusage"],
+            "sentinel": ["getrusage"],
+            "last": ["getrusage"]
+        },
+        "render-versus-self": {
+            "sentinel": {
+                "2 vs 1": {
+                    "measure": "wall-time",
+                    "before": { "command": "sentinel", "index": 0 },
+                    "after": { "command": "sentinel", "index": 1 }
+                }
+            }
+        },
+        "render-versus-other": {}
+    });
+    std::fs::write(dir.join("bench.json"), config.to_string()).unwrap();
+    benchmarker(dir)
+        .arg(commit)
+        .arg("bench.json")
+        .arg("previous.json")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn sentinel_runs_first_and_last() {
+    let dir = test_dir("order");
+    write_script(&dir, "log", LOG);
+    git(&dir, &["init", "--quiet"]);
+    git(&dir, &["commit", "--quiet", "--allow-empty", "-m", "base"]);
+    let commit = git(&dir, &["rev-parse", "HEAD"]);
+
+    let output = run_benchmarker(&dir, &commit);
+    assert!(output.status.success(), "{output:?}");
+
+    // With the warmup runs of getrusage.
+    let log = std::fs::read_to_string(dir.join("log.txt")).unwrap();
+    assert_eq!(log.lines().filter(|line| *line == "sentinel-1").count(), 6);
+    let mut order = log.lines().collect::<Vec<_>>();
+    order.dedup();
+    assert_eq!(
+        order,
+        [
+            "sentinel-1",
+            "sentinel-2",
+            "first",
+            "last",
+            "sentinel-1",
+            "sentinel-2"
+        ]
+    );
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let last = serde_json::from_str::<Value>(stdout.lines().last().unwrap()).unwrap();
+    let groups = last["bench_groups"].as_object().unwrap();
+    assert_eq!(
+        groups.keys().collect::<Vec<_>>(),
+        ["sentinel@start", "first", "last", "sentinel@end"]
+    );
+    assert_eq!(
+        groups["sentinel@end"][1]["cmd"],
+        json!(["./log", "sentinel-2"])
+    );
+
+    let summary = std::fs::read_to_string(dir.join("summary.md")).unwrap();
+    // The table of the group compares the end measurement.
+    assert!(summary.contains("| 2 vs 1 <a id="), "{summary}");
+    assert!(
+        summary.contains("### Run stability: sentinel\n\n| name | start | end | Δ |\n"),
+        "{summary}"
+    );
+    assert!(
+        summary.contains("| ./log sentinel-2 (wall-time) |"),
+        "{summary}"
+    );
+}
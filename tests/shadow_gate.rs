@@ -0,0 +1,148 @@
+//! Run the benchmarker with a `gate` and a `shadow-gate` in a scratch repository, with a fake
+//! perf that counts as many cycles as `$CYCLES` says, so either gate can fail alone.
+
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::path::Path;
+use std::process::Output;
+
+use serde_json::{json, Value};
+
+use common::{benchmarker, fake_perf, scratch_repo, test_dir};
+
+/// Counts `$CYCLES` cycles, with a variance of 0.1%.
+const CYCLES: &str = r#"echo "{\"counter-value\" : \"$CYCLES\", \"unit\" : \"\", \"event\" : \"cycles\", \"variance\" : 0.10}""#;
+
+/// The suite, with the gate and the shadow gate at the given thresholds.
+fn config(gate_percent: f64, shadow_percent: f64) -> String {
+    json!({
+        "commands": { "work": ["true"] },
+        "repetitions-for-group": { "work": 3 },
+        "backends-for-group": { "work": ["perf"] },
+        "perf-events-for-group": { "work": ["cycles"] },
+        "gate": { "max-regression-percent": gate_percent },
+        "shadow-gate": { "max-regression-percent": shadow_percent },
+        "render-versus-self": {},
+        "render-versus-other": {
+            "work": { "measure": "cycles", "command": "work", "rows": { "true": 0 } }
+        }
+    })
+    .to_string()
+}
+
+fn run_benchmarker(dir: &Path, commit: &str, config: &str, cycles: u32) -> Output {
+    std::fs::write(dir.join("bench.json"), config).unwrap();
+    let _ = std::fs::remove_file(dir.join("github-output"));
+    benchmarker(dir)
+        .arg(commit)
+        .arg("bench.json")
+        .arg("previous.json")
+        .args(["--run-report", "run-report.json"])
+        .env("CYCLES", cycles.to_string())
+        .env("GITHUB_OUTPUT", dir.join("github-output"))
+        .output()
+        .unwrap()
+}
+
+/// A scratch repository with a baseline of 1000 cycles, returning the commit to benchmark.
+fn repository_with_baseline(dir: &Path) -> String {
+    let (base, head) = scratch_repo(dir);
+    let output = run_benchmarker(dir, &base, &config(5.0, 2.0), 1000);
+    assert!(output.status.success(), "{output:?}");
+    std::fs::write(dir.join("previous.json"), &output.stdout).unwrap();
+    head
+}
+
+fn read_report(dir: &Path) -> Value {
+    serde_json::from_slice(&std::fs::read(dir.join("run-report.json")).unwrap()).unwrap()
+}
+
+fn github_output(dir: &Path) -> String {
+    std::fs::read_to_string(dir.join("github-output")).unwrap()
+}
+
+#[test]
+fn shadow_gate_fails_alone() {
+    let dir = test_dir("stricter");
+    fake_perf(&dir, CYCLES);
+    let head = repository_with_baseline(&dir);
+
+    // +3% passes the gate at 5%, but not the shadow gate at 2%.
+    let output = run_benchmarker(&dir, &head, &config(5.0, 2.0), 1030);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(0), "{stderr}");
+    assert!(
+        stderr.contains("shadow gate failure: work / true regressed by +2.91% cycles"),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("\ngate failure"), "{stderr}");
+    assert!(
+        github_output(&dir).contains("shadow_regressions=1\n"),
+        "{}",
+        github_output(&dir)
+    );
+
+    let report = read_report(&dir);
+    assert_eq!(report["gate"]["failures"], json!([]));
+    assert_eq!(report["shadow_gate"]["failures"][0]["row"]["name"], "true");
+    assert_eq!(
+        report["gate_rows"],
+        json!([{
+            "table": "work",
+            "row": "true",
+            "measure": "cycles",
+            "key": report["gate_rows"][0]["key"],
+            "gate": "passed",
+            "shadow_gate": "failed"
+        }])
+    );
+
+    let summary = std::fs::read_to_string(dir.join("summary.md")).unwrap();
+    let section = summary
+        .rsplit_once("<summary>Shadow gate: 1 comparisons would fail</summary>")
+        .unwrap_or_else(|| panic!("{summary}"))
+        .1;
+    assert!(
+        section.contains(
+            "> 1 comparisons regressed by more than 2%:\n> - work / true: `+2.91%` cycles\n"
+        ),
+        "{section}"
+    );
+}
+
+#[test]
+fn gate_fails_alone() {
+    let dir = test_dir("laxer");
+    fake_perf(&dir, CYCLES);
+    let head = repository_with_baseline(&dir);
+
+    // +10% fails the gate at 5%, but not the shadow gate at 20%.
+    let output = run_benchmarker(&dir, &head, &config(5.0, 20.0), 1100);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(1), "{stderr}");
+    assert!(
+        stderr.contains("gate failure: work / true regressed by +9.09% cycles"),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("shadow gate failure"), "{stderr}");
+    assert!(
+        github_output(&dir).contains("shadow_regressions=0\n"),
+        "{}",
+        github_output(&dir)
+    );
+
+    let report = read_report(&dir);
+    assert_eq!(report["gate_rows"][0]["gate"], "failed");
+    assert_eq!(report["gate_rows"][0]["shadow_gate"], "passed");
+    assert_eq!(report["shadow_gate"]["failures"], json!([]));
+
+    let summary = std::fs::read_to_string(dir.join("summary.md")).unwrap();
+    assert!(
+        summary.contains(
+            "<summary>Shadow gate: would pass</summary>\n\nNo comparison regressed by more than 20%.\n\n</details>\n"
+        ),
+        "{summary}"
+    );
+}
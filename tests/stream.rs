@@ -0,0 +1,67 @@
+//! Run the benchmarker on two trivial commands and check what it prints on stdout.
+
+mod common;
+
+use serde_json::Value;
+
+use common::{benchmarker, git, test_dir};
+
+#[test]
+fn stream_results() {
+    let dir = test_dir("trivial");
+    git(&dir, &["init", "--quiet"]);
+    git(&dir, &["commit", "--quiet", "--allow-empty", "-m", "base"]);
+    let commit = git(&dir, &["rev-parse", "HEAD"]);
+
+    std::fs::write(
+        dir.join("bench.json"),
+        r#"{
+            "commands": { "trivial": ["true", "echo not on stdout"] },
+            "repetitions-for-group": { "trivial": 2 },
+            "backends-for-group": { "trivial": ["getrusage"] },
+            "render-versus-self": {},
+            "render-versus-other": {}
+        }"#,
+    )
+    .unwrap();
+
+    let output = benchmarker(&dir)
+        .arg("--stream")
+        .arg(&commit)
+        .arg("bench.json")
+        .arg("does-not-exist.json")
+        .env_remove("GITHUB_STEP_SUMMARY")
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines = stdout
+        .lines()
+        .map(|line| serde_json::from_str::<Value>(line).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(lines.len(), 3, "{stdout}");
+
+    for (sequence, line) in lines[..2].iter().enumerate() {
+        assert_eq!(line["type"], "bench");
+        assert_eq!(line["sequence"], sequence);
+        assert_eq!(line["commit_hash"], commit.as_str());
+        assert_eq!(line["group"], "trivial");
+    }
+    assert_eq!(lines[0]["bench"]["cmd"], serde_json::json!(["true"]));
+    assert_eq!(
+        lines[1]["bench"]["cmd"],
+        serde_json::json!(["echo", "not", "on", "stdout"])
+    );
+
+    // The final line holds the same results, and is what gets stored as previous results.
+    let last = &lines[2];
+    assert_eq!(last["type"], "final");
+    assert_eq!(last["commit_hash"], commit.as_str());
+    assert_eq!(last["bench_groups"]["trivial"][0], lines[0]["bench"]);
+    assert_eq!(last["bench_groups"]["trivial"][1], lines[1]["bench"]);
+}
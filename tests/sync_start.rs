@@ -0,0 +1,136 @@
+//! Run the benchmarker on `examples/sync_start.rs` with a fake perf, which answers the
+//! `--control` commands like perf and counts the enabled regions as its cycles.
+
+mod common;
+
+use std::path::PathBuf;
+use std::process::Output;
+
+use serde_json::Value;
+
+use common::{benchmarker, final_line, git, test_dir, write_script};
+
+const FAKE_PERF: &str = r#"#!/bin/sh
+repeat=1
+while [ "$1" != "--" ]; do
+    case "$1" in
+        -o) out="$2"; shift ;;
+        --repeat) repeat="$2"; shift ;;
+        --control) fds="${2#fd:}"; control="${fds%,*}"; ack="${fds#*,}"; shift ;;
+    esac
+    shift
+done
+shift
+
+enabled=0
+run=0
+while [ $run -lt "$repeat" ]; do
+    "$@" &
+    pid=$!
+    if [ -n "$control" ]; then
+        while read -r command <&"$control"; do
+            echo "$command" >> "$FAKE_PERF_LOG"
+            # Like perf, NUL-terminated.
+            printf 'ack\n\000' >&"$ack"
+            [ "$command" = enable ] && enabled=$((enabled + 1))
+            [ "$command" = disable ] && break
+        done
+    fi
+    wait $pid
+    status=$?
+    run=$((run + 1))
+done
+
+if [ -z "$control" ]; then
+    enabled=1000
+fi
+echo "{\"counter-value\" : \"$enabled\", \"unit\" : \"\", \"event\" : \"cycles\", \"variance\" : 0.00}" > "$out"
+exit $status
+"#;
+
+/// The example, which cargo builds next to the directory of the test.
+fn example() -> PathBuf {
+    let test = std::env::current_exe().unwrap();
+    test.parent()
+        .unwrap()
+        .parent()
+        .unwrap()
+        .join("examples/sync_start")
+}
+
+/// Run the benchmarker on `command` with `sync-start`, returning its output and the counters
+/// of the command.
+fn run(name: &str, command: &str) -> (Output, Value, PathBuf) {
+    let dir = test_dir(name);
+    write_script(&dir, "bin/perf", FAKE_PERF);
+    git(&dir, &["init", "--quiet"]);
+    git(&dir, &["commit", "--quiet", "--allow-empty", "-m", "base"]);
+    let commit = git(&dir, &["rev-parse", "HEAD"]);
+
+    let log = dir.join("log");
+    std::fs::write(
+        dir.join("bench.json"),
+        serde_json::json!({
+            "commands": { "sync": [{ "command": command.replace("{log}", log.to_str().unwrap()), "sync-start": true }] },
+            "repetitions-for-group": { "sync": 2 },
+            "backends-for-group": { "sync": ["perf"] },
+            "perf-events-for-group": { "sync": ["cycles"] },
+            "sync-start-timeout-ms": 200,
+            "render-versus-self": {},
+            "render-versus-other": {}
+        })
+        .to_string(),
+    )
+    .unwrap();
+
+    let output = benchmarker(&dir)
+        .arg("--stream")
+        .arg(&commit)
+        .arg("bench.json")
+        .arg("does-not-exist.json")
+        .env("FAKE_PERF_LOG", &log)
+        .env_remove("GITHUB_STEP_SUMMARY")
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let counters = final_line(&output)["bench_groups"]["sync"][0]["counters"].clone();
+    (output, counters, log)
+}
+
+#[test]
+fn synchronized_runs() {
+    let command = format!("{} {{log}}", example().display());
+    let (output, counters, log) = run("synchronized", &command);
+
+    // Counted only between the signals, in both repetitions.
+    assert_eq!(counters["cycles"]["value"], 2.0, "{counters}");
+    let log = std::fs::read_to_string(log).unwrap();
+    assert_eq!(
+        log.lines().collect::<Vec<_>>(),
+        [
+            "ready", "enable", "done", "disable", "exit", //
+            "ready", "enable", "done", "disable", "exit",
+        ]
+    );
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("didn't take part"));
+}
+
+#[test]
+fn unsynchronized_fallback() {
+    // A command that doesn't know about the protocol is measured as a whole after the timeout.
+    let (output, counters, _) = run("fallback", "true");
+
+    assert_eq!(counters["cycles"]["value"], 1000.0, "{counters}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(
+            "warning: true didn't take part in sync-start (no start signal in repetition 1: timed out), measuring it as a whole"
+        ),
+        "{stderr}"
+    );
+}
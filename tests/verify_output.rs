@@ -0,0 +1,155 @@
+//! Run the benchmarker in a scratch repository on a helper that writes either random or fixed
+//! output, with `verify-output`.
+
+mod common;
+
+use std::path::Path;
+use std::process::Output;
+
+use serde_json::{json, Value};
+
+use common::{benchmarker, final_line, git, test_dir, write_script};
+
+/// `produce <random|fixed> <file> <seconds>`
+const PRODUCE: &str = r#"#!/bin/sh
+case "$1" in
+    random) od -An -N16 -tx1 /dev/urandom > "$2" ;;
+    *) echo fixed > "$2" ;;
+esac
+sleep "$3"
+"#;
+
+/// The SHA-256 of the fixed output.
+const FIXED_HASH: &str = "0c3071418e6356e614898c84ed064ca95e88551bc0811b534bdf1952ecdae534";
+
+/// The suite, with a command writing random output and one writing fixed output, which sleep
+/// `seconds`, both compared by their ids.
+fn config(seconds: &str, fixed_verify: Value, interleave: bool) -> String {
+    json!({
+        "commands": {
+            "work": [
+                {
+                    "command": format!("./produce random random.out {seconds}"),
+                    "id": "random",
+                    "verify-output": { "file": "random.out" }
+                },
+                {
+                    "command": format!("./produce fixed fixed.out {seconds}"),
+                    "id": "fixed",
+                    "verify-output": fixed_verify
+                }
+            ]
+        },
+        "repetitions-for-group": { "work": 3 },
+        "backends-for-group": { "work": ["getrusage"] },
+        "interleave-for-group": { "work": interleave },
+        "gate": { "max-regression-percent": 50 },
+        "render-versus-self": {
+            "random vs fixed": {
+                "work": {
+                    "measure": "wall-time",
+                    "before": { "command": "work", "index": 0 },
+                    "after": { "command": "work", "index": 1 }
+                }
+            }
+        },
+        "render-versus-other": {
+            "work": { "measure": "wall-time", "command": "work", "rows": { "random": 0, "fixed": 1 } }
+        }
+    })
+    .to_string()
+}
+
+fn run_benchmarker(dir: &Path, commit: &str, config: &str) -> Output {
+    std::fs::write(dir.join("bench.json"), config).unwrap();
+    benchmarker(dir)
+        .arg(commit)
+        .arg("bench.json")
+        .arg("previous.json")
+        .args(["--run-report", "run-report.json"])
+        .output()
+        .unwrap()
+}
+
+/// A scratch repository with two commits, returning them.
+fn repository(dir: &Path) -> (String, String) {
+    git(dir, &["init", "--quiet"]);
+    git(dir, &["commit", "--quiet", "--allow-empty", "-m", "base"]);
+    git(dir, &["commit", "--quiet", "--allow-empty", "-m", "change"]);
+    git(dir, &["update-ref", "refs/remotes/origin/main", "HEAD~"]);
+    (
+        git(dir, &["rev-parse", "HEAD~"]),
+        git(dir, &["rev-parse", "HEAD"]),
+    )
+}
+
+fn flag_random_output(name: &str, interleave: bool) {
+    let dir = test_dir(name);
+    write_script(&dir, "produce", PRODUCE);
+    let (base, head) = repository(&dir);
+    let fixed = json!({ "file": "fixed.out", "expected-hash": FIXED_HASH.to_uppercase() });
+
+    let output = run_benchmarker(&dir, &base, &config("0", fixed.clone(), interleave));
+    assert!(output.status.success(), "{output:?}");
+    let benches = &final_line(&output)["bench_groups"]["work"];
+    assert_eq!(benches[0]["nondeterministic_output"], true, "{benches}");
+    assert!(
+        benches[1].get("nondeterministic_output").is_none(),
+        "{benches}"
+    );
+    assert!(benches[1].get("error").is_none(), "{benches}");
+    std::fs::write(dir.join("previous.json"), &output.stdout).unwrap();
+
+    // Both regressed, but only the command with the fixed output fails the gate.
+    let output = run_benchmarker(&dir, &head, &config("0.05", fixed, interleave));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(1), "{stderr}");
+    assert!(
+        stderr.contains("warning: the output of `./produce random random.out 0.05` differed between its repetitions, its comparisons are left out of the gate"),
+        "{stderr}"
+    );
+
+    let report = std::fs::read(dir.join("run-report.json")).unwrap();
+    let report = serde_json::from_slice::<Value>(&report).unwrap();
+    let failures = report["gate"]["failures"].as_array().unwrap();
+    assert_eq!(failures.len(), 1, "{failures:?}");
+    assert_eq!(failures[0]["row"]["name"], "fixed");
+
+    let summary = std::fs::read_to_string(dir.join("summary.md")).unwrap();
+    assert!(summary.contains("| random ⚠️ <a id="), "{summary}");
+    assert!(!summary.contains("| fixed ⚠️"), "{summary}");
+    assert!(summary.contains("| work ⚠️ <a id="), "{summary}");
+    assert!(
+        summary.contains("⚠️ output differed between repetitions"),
+        "{summary}"
+    );
+}
+
+#[test]
+fn flag_random_output_alone() {
+    flag_random_output("alone", false);
+}
+
+#[test]
+fn flag_random_output_interleaved() {
+    flag_random_output("interleaved", true);
+}
+
+#[test]
+fn wrong_expected_hash() {
+    let dir = test_dir("wrong-hash");
+    write_script(&dir, "produce", PRODUCE);
+    let (base, _) = repository(&dir);
+
+    // With a checksum command, and the hash of other output.
+    let fixed = json!({ "command": "sha256sum fixed.out", "expected-hash": "0123abcd" });
+    let output = run_benchmarker(&dir, &base, &config("0", fixed, false));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(4), "{stderr}");
+    let benches = &final_line(&output)["bench_groups"]["work"];
+    assert_eq!(
+        benches[1]["error"],
+        format!("the output has the hash {FIXED_HASH} rather than the `expected-hash` 0123abcd")
+    );
+    assert!(benches[0].get("error").is_none(), "{benches}");
+}
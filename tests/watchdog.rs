@@ -0,0 +1,114 @@
+//! Run the benchmarker with a `watchdog` in a scratch repository, on helper programs that hang
+//! without using any CPU, sleep legitimately, or work, to check that only the hung one is
+//! killed and that the suite goes on.
+
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::path::Path;
+use std::process::Output;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+use common::{benchmarker, git, test_dir, write_script};
+
+/// Hangs for much longer than the test, like perf stuck on a dead mount, in a child process.
+const HANG: &str = "#!/bin/sh\nsleep 300\n";
+
+/// Sleeps for longer than the window, as a benchmark of a timer would.
+const NAP: &str = "#!/bin/sh\nsleep 1\n";
+
+fn run_benchmarker(dir: &Path, commit: &str, config: &Value) -> Output {
+    std::fs::write(dir.join("bench.json"), config.to_string()).unwrap();
+    benchmarker(dir)
+        .arg(commit)
+        .arg("bench.json")
+        .arg("previous.json")
+        .args(["--run-report", "run-report.json"])
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn kill_hung_command() {
+    let dir = test_dir("hung");
+    write_script(&dir, "hang", HANG);
+    write_script(&dir, "nap", NAP);
+    git(&dir, &["init", "--quiet"]);
+    git(&dir, &["commit", "--quiet", "--allow-empty", "-m", "base"]);
+    let commit = git(&dir, &["rev-parse", "HEAD"]);
+
+    let config = json!({
+        "commands": {
+            "work": [
+                "./hang",
+                { "command": "./nap", "sleeps": true },
+                "true",
+            ],
+            "pair": ["./hang again", "true again"]
+        },
+        "repetitions-for-group": { "work": 1, "pair": 2 },
+        "backends-for-group": { "work": ["getrusage"], "pair": ["getrusage"] },
+        "interleave-for-group": { "pair": true },
+        "watchdog": { "window-secs": "500ms", "interval-ms": 50 },
+        "render-versus-self": {
+            "pair": {
+                "pair": {
+                    "measure": "wall-time",
+                    "before": { "command": "pair", "index": 0 },
+                    "after": { "command": "pair", "index": 1 }
+                }
+            }
+        },
+        "render-versus-other": {}
+    });
+    let start = Instant::now();
+    let output = run_benchmarker(&dir, &commit, &config);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(start.elapsed() < Duration::from_secs(60), "{stderr}");
+
+    // The hung commands are killed, and the run fails at the end.
+    assert_eq!(output.status.code(), Some(4), "{stderr}");
+    assert!(
+        stderr.contains("warning: `./hang` made no progress in 500ms, killing it:\n"),
+        "{stderr}"
+    );
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let results: Value = serde_json::from_str(stdout.lines().last().unwrap()).unwrap();
+    let work = &results["bench_groups"]["work"];
+    assert_eq!(work[0]["hung"], true, "{work}");
+    assert_eq!(work[0]["counters"], json!({}));
+    let error = work[0]["error"].as_str().unwrap();
+    assert!(
+        error.starts_with("hung: no CPU or I/O progress in 500ms, killed\n=== process tree ===\n"),
+        "{error}"
+    );
+    // The script, and the sleep it waits for, below it.
+    let tree = error.lines().skip(2).collect::<Vec<_>>();
+    assert_eq!(tree.len(), 2, "{error}");
+    assert!(tree[0].contains(" `hang` (S, "), "{error}");
+    assert!(tree[1].starts_with("  "), "{error}");
+    assert!(tree[1].contains(" `sleep` (S, "), "{error}");
+
+    // Sleeping legitimately, and working, are no hangs.
+    for bench in [&work[1], &work[2]] {
+        assert!(bench.get("hung").is_none(), "{bench}");
+        assert!(bench.get("error").is_none(), "{bench}");
+        assert!(bench["counters"].get("wall-time").is_some(), "{bench}");
+    }
+
+    // An interleaved command sits out the rest of the rounds once it hung.
+    let pair = &results["bench_groups"]["pair"];
+    assert_eq!(pair[0]["hung"], true, "{pair}");
+    assert!(pair[1].get("error").is_none(), "{pair}");
+    assert_eq!(pair[1]["counters"]["wall-time"]["repetitions"], 2);
+
+    let report: Value =
+        serde_json::from_str(&std::fs::read_to_string(dir.join("run-report.json")).unwrap())
+            .unwrap();
+    assert_eq!(report["groups"]["work"]["failed"], 1, "{report}");
+    assert_eq!(report["groups"]["work"]["completed"], 2, "{report}");
+}